@@ -0,0 +1,118 @@
+//! Criterion benches of the workloads of `index_tools::bench`: tokenizing,
+//! writing segments, merging them and answering queries, on the same
+//! synthetic corpus as `index_tools bench`.
+
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, Criterion,
+    Throughput,
+};
+use std::fs;
+
+use index_tools::bench::{corpus, queries, write_segments, BenchOptions};
+use index_tools::prelude::*;
+
+/// A smaller corpus than that of `index_tools bench`, for criterion to run
+/// each workload many times.
+fn options() -> BenchOptions {
+    BenchOptions {
+        docs: 500,
+        words: 300,
+        queries: 200,
+        ..BenchOptions::default()
+    }
+}
+
+fn tokenize(c: &mut Criterion) {
+    let options = options();
+    let texts = corpus(options.docs, options.words, options.seed);
+    let bytes = texts.iter().map(|t| t.len() as u64).sum();
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(bytes));
+    for (name, config) in [
+        ("default", AnalyzerConfig::default()),
+        (
+            "stop_words",
+            AnalyzerConfig {
+                stop_words: vec!["english".to_string()],
+                ..AnalyzerConfig::default()
+            },
+        ),
+    ] {
+        let analyzer = config.build().unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for text in &texts {
+                    black_box(analyzer.analyze(text));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn write(c: &mut Criterion) {
+    let options = options();
+    let texts = corpus(options.docs, options.words, options.seed);
+    let dir = tempfile::tempdir().unwrap();
+    let settings = IndexSettings::default();
+    let mut group = c.benchmark_group("write");
+    group.throughput(Throughput::Elements(texts.len() as u64));
+    group.sample_size(20);
+    group.bench_function("segment", |b| {
+        b.iter(|| {
+            let files = write_segments(&texts, 1, dir.path(), &settings);
+            for file in files.unwrap() {
+                fs::remove_file(file).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn merge(c: &mut Criterion) {
+    let options = options();
+    let texts = corpus(options.docs, options.words, options.seed);
+    let dir = tempfile::tempdir().unwrap();
+    let settings = IndexSettings::default();
+    let segments =
+        write_segments(&texts, options.segments, dir.path(), &settings)
+            .unwrap();
+    let bytes = segments
+        .iter()
+        .map(|f| fs::metadata(f).unwrap().len())
+        .sum();
+    let mut group = c.benchmark_group("merge");
+    group.throughput(Throughput::Bytes(bytes));
+    group.sample_size(20);
+    group.bench_function("segments", |b| {
+        b.iter_batched(
+            || tempfile::tempdir_in(dir.path()).unwrap(),
+            |out| merge_index_files(&segments, out.path()).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn query(c: &mut Criterion) {
+    let options = options();
+    let texts = corpus(options.docs, options.words, options.seed);
+    let dir = tempfile::tempdir().unwrap();
+    let settings = IndexSettings::default();
+    let segments = write_segments(&texts, 1, dir.path(), &settings).unwrap();
+    let searcher = Searcher::open(&segments[0]).unwrap();
+    let queries = queries(options.queries, options.seed);
+    let mut group = c.benchmark_group("query");
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_function("mixed", |b| {
+        b.iter(|| {
+            for query in &queries {
+                black_box(searcher.query(query).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tokenize, write, merge, query);
+criterion_main!(benches);
@@ -0,0 +1,20 @@
+//! Generates the gRPC service of `index_tools grpc` from
+//! proto/index_tools.proto, with the `grpc` feature only.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc();
+}
+
+#[cfg(feature = "grpc")]
+fn grpc() {
+    println!("cargo:rerun-if-changed=proto");
+    // A protoc of our own, so that building doesn't need one installed.
+    let protoc = protoc_bin_vendored::protoc_bin_path().unwrap();
+    std::env::set_var("PROTOC", protoc);
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/index_tools.proto"], &["proto"])
+        .unwrap();
+}
@@ -0,0 +1,29 @@
+//! Reads arbitrary bytes as an index file, both from beginning to end and
+//! through lookups: this must fail with an error, never panic or allocate
+//! more than the file can hold.
+//!
+//! Run with `cargo fuzz run index_file`, seeding the corpus with index files
+//! written by the crate.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::fs;
+use std::process;
+
+use index_tools::prelude::*;
+
+fuzz_target!(|data: &[u8]| {
+    let file = std::env::temp_dir()
+        .join(format!("index_tools-fuzz-{}.dat", process::id()));
+    fs::write(&file, data).unwrap();
+    let terms: Vec<String> = match IndexFileReader::get_index_from_file(&file) {
+        Ok(index) => index.map.into_keys().collect(),
+        Err(_) => vec!["the".to_string(), "cat".to_string()],
+    };
+    if let Ok(mut searcher) = IndexFileSearcher::open(&file) {
+        for term in &terms {
+            let _ = searcher.lookup(term);
+        }
+    }
+});
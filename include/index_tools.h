@@ -0,0 +1,63 @@
+#ifndef INDEX_TOOLS_H
+#define INDEX_TOOLS_H
+
+/* Generated with cbindgen from src/capi.rs. Don't edit. */
+
+#include <stddef.h>
+
+// An index loaded for searching.
+typedef struct IndexToolsIndex IndexToolsIndex;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Index the files under the directory, or the file, `input` into the
+// directory `output`, created if need be, with the default settings.
+// Returns the path of the index file written, or NULL.
+//
+// # Safety
+//
+// `input` and `output` must be NUL-terminated strings.
+char *index_tools_index(const char *input, const char *output);
+
+// Load the index file, index directory or pack (with the `mmap` feature)
+// at `path` for searching. Returns NULL if it can't be loaded.
+//
+// # Safety
+//
+// `path` must be a NUL-terminated string.
+IndexToolsIndex *index_tools_open(const char *path);
+
+// Search `index` for `query`, in the syntax of `Query`. Returns the first
+// `limit` results, in JSON, or NULL if the query is invalid.
+//
+// # Safety
+//
+// `index` must come from `index_tools_open` and not be closed, and `query`
+// must be a NUL-terminated string.
+char *index_tools_search(const IndexToolsIndex *index, const char *query, size_t limit);
+
+// Free `index`. Does nothing if it is NULL.
+//
+// # Safety
+//
+// `index` must come from `index_tools_open`, and not be used afterwards.
+void index_tools_close(IndexToolsIndex *index);
+
+// Free a string returned by another function. Does nothing if it is NULL.
+//
+// # Safety
+//
+// `string` must come from this library, and not be used afterwards.
+void index_tools_free_string(char *string);
+
+// Why the last function that failed on this thread failed, or NULL if none
+// did. The string is valid until the next failure on this thread.
+const char *index_tools_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* INDEX_TOOLS_H */
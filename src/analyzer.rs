@@ -0,0 +1,1000 @@
+//! Text analysis: turning a document into the terms that get indexed.
+//!
+//! An `Analyzer` runs a `Tokenizer`, which splits the text into words and
+//! numbers them, followed by a pipeline of `TokenFilter`s, which can rewrite
+//! or drop tokens (lowercasing, stop words, length limits, stemming...).
+//! Dropped tokens keep their position, so the word offsets stored in the
+//! index still match the original text and phrase queries stay exact.
+//!
+//! `TextAnalyzer::default()` reproduces what the indexer has always done:
+//! split on anything that isn't alphanumeric, then lowercase.
+//!
+//! Searches must analyze query terms exactly like the documents were
+//! analyzed, so the settings used to build an index are described by an
+//! `AnalyzerConfig`, which is saved in the header of the index file and
+//! loaded back by the searcher.
+//!
+//! The language of each document can be detected (see the `language`
+//! module), so that its own stop words and stemmer are used: that is what
+//! the language name `auto` stands for in an `AnalyzerConfig`.
+//!
+//! Lowercasing makes searches case-insensitive, but loses the case of the
+//! text. An index built with `AnalyzerConfig::preserve_case` also stores
+//! every word as it was written, as a term of its own starting with
+//! `EXACT_PREFIX` (`=Rust`), at the same offset as its folded form. The
+//! searcher then chooses which of the two forms queries look up (see
+//! `CaseForms`).
+//!
+//! Word offsets count words, but editors and highlighters need to know
+//! where words are in the text. An index built with
+//! `IndexSettings::byte_offsets` also stores, for every term, a term of its
+//! own starting with `BYTE_OFFSETS_PREFIX` (`@rust`, `title:@rust`), whose
+//! offsets are the byte offsets of the occurrences of the term in the
+//! analyzed text, in the same order as its word offsets. One built with
+//! `IndexSettings::line_numbers` stores another twin of each term of the
+//! body, starting with `LINE_NUMBERS_PREFIX` (`#rust`), whose offsets are
+//! the numbers of the lines the term occurs on, from 1. Twins aren't words:
+//! queries never match them.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::routing::Route;
+use crate::stopwords;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// A single word of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    /// The term text, as it will be stored in the index.
+    pub text: String,
+    /// Word offset of the token from the beginning of the document.
+    pub position: u32,
+    /// Byte offset of the start of the word in the analyzed text. Filters
+    /// may rewrite `text`, but `start..end` always spans the original word.
+    pub start: usize,
+    /// Byte offset of the end of the word in the analyzed text.
+    pub end: usize,
+}
+
+impl Token {
+    /// Build the token for `word`, which must be a slice of `text`.
+    fn from_slice(text: &str, word: &str, position: usize) -> Token {
+        let start = word.as_ptr() as usize - text.as_ptr() as usize;
+        Token {
+            text: word.to_string(),
+            // Past u32::MAX words, indexes leave the words out (see
+            // `index::MAX_DOC_WORDS`).
+            position: u32::try_from(position).unwrap_or(u32::MAX),
+            start,
+            end: start + word.len(),
+        }
+    }
+}
+
+/// The first character of the terms holding a word in its original case.
+/// Tokenizers never produce it, so these terms can't clash with words.
+pub const EXACT_PREFIX: char = '=';
+
+/// True if `word`, an analyzed word without field prefix, is the original
+/// form of a word (see `EXACT_PREFIX`).
+pub fn is_exact(word: &str) -> bool {
+    word.starts_with(EXACT_PREFIX)
+}
+
+/// The first character of the terms holding the byte offsets of a word
+/// (see `IndexSettings::byte_offsets`). Like `EXACT_PREFIX`, tokenizers
+/// never produce it.
+pub const BYTE_OFFSETS_PREFIX: char = '@';
+
+/// True if `word`, an analyzed word without field prefix, holds the byte
+/// offsets of another word (see `BYTE_OFFSETS_PREFIX`).
+pub fn is_byte_offsets(word: &str) -> bool {
+    word.starts_with(BYTE_OFFSETS_PREFIX)
+}
+
+/// The word holding the byte offsets of `word`, an analyzed word without
+/// field prefix.
+pub fn byte_offsets_word(word: &str) -> String {
+    format!("{}{}", BYTE_OFFSETS_PREFIX, word)
+}
+
+/// The first character of the terms holding the numbers of the lines a
+/// word occurs on (see `IndexSettings::line_numbers`). Like `EXACT_PREFIX`,
+/// tokenizers never produce it.
+pub const LINE_NUMBERS_PREFIX: char = '#';
+
+/// True if `word`, an analyzed word without field prefix, holds the line
+/// numbers of another word (see `LINE_NUMBERS_PREFIX`).
+pub fn is_line_numbers(word: &str) -> bool {
+    word.starts_with(LINE_NUMBERS_PREFIX)
+}
+
+/// The word holding the line numbers of `word`, an analyzed word without
+/// field prefix.
+pub fn line_numbers_word(word: &str) -> String {
+    format!("{}{}", LINE_NUMBERS_PREFIX, word)
+}
+
+/// True if `word`, an analyzed word without field prefix, holds the byte
+/// offsets or line numbers of another word rather than being one.
+pub fn is_twin(word: &str) -> bool {
+    is_byte_offsets(word) || is_line_numbers(word)
+}
+
+/// Splits text into tokens.
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<Token>;
+}
+
+/// One step of the analysis pipeline. Returning `None` drops the token.
+pub trait TokenFilter: Send + Sync {
+    fn filter(&self, token: Token) -> Option<Token>;
+}
+
+/// Turns text into the tokens to index or to search for.
+pub trait Analyzer: Send + Sync {
+    fn analyze(&self, text: &str) -> Vec<Token>;
+
+    /// `analyze`, and the number of words of `text` that the positions of
+    /// the tokens count, dropped words included, so that a text analyzed in
+    /// parts can be numbered as a whole. By default, one past the last
+    /// position.
+    fn analyze_counted(&self, text: &str) -> (Vec<Token>, u32) {
+        let tokens = self.analyze(text);
+        let words = tokens.last().map_or(0, |t| t.position.saturating_add(1));
+        (tokens, words)
+    }
+}
+
+/// Break a string into words, splitting on every character that is not
+/// alphanumeric.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimpleTokenizer;
+
+impl Tokenizer for SimpleTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        text.split(|ch: char| !ch.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .enumerate()
+            .map(|(i, word)| Token::from_slice(text, word, i))
+            .collect()
+    }
+}
+
+/// Break a string into words at Unicode word boundaries (UAX #29).
+///
+/// Unlike `SimpleTokenizer`, this keeps contractions ("don't") and numbers
+/// with separators ("3.14") together, and handles scripts whose words contain
+/// combining marks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnicodeTokenizer;
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        text.unicode_words()
+            .enumerate()
+            .map(|(i, word)| Token::from_slice(text, word, i))
+            .collect()
+    }
+}
+
+/// Splits words like `SimpleTokenizer`, except that runs of Chinese,
+/// Japanese or Korean characters, which are written without spaces, are
+/// broken into overlapping character n-grams.
+///
+/// With `n = 2`, "東京都" becomes "東京", "京都". Queries go through the same
+/// tokenizer, so a multi-character query turns into a phrase of n-grams and
+/// only matches the exact character sequence. Runs shorter than `n` are kept
+/// whole.
+#[derive(Debug, Clone, Copy)]
+pub struct NgramTokenizer {
+    pub n: usize,
+}
+
+impl Tokenizer for NgramTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let n = self.n.max(1);
+        let mut words = vec![];
+        for word in text.split(|ch: char| !ch.is_alphanumeric()) {
+            // Split the word into alternating CJK and non-CJK runs.
+            let mut rest = word;
+            while let Some(first) = rest.chars().next() {
+                let cjk = is_cjk(first);
+                let end = rest
+                    .char_indices()
+                    .find(|&(_, c)| is_cjk(c) != cjk)
+                    .map_or(rest.len(), |(i, _)| i);
+                let (run, tail) = rest.split_at(end);
+                rest = tail;
+
+                // Byte boundaries of every character of the run.
+                let bounds: Vec<usize> = run
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain([run.len()])
+                    .collect();
+                let chars = bounds.len() - 1;
+                if !cjk || chars <= n {
+                    words.push(run);
+                } else {
+                    words.extend(
+                        (0..=chars - n).map(|i| &run[bounds[i]..bounds[i + n]]),
+                    );
+                }
+            }
+        }
+        words
+            .into_iter()
+            .enumerate()
+            .map(|(i, word)| Token::from_slice(text, word, i))
+            .collect()
+    }
+}
+
+/// Splits words like `SimpleTokenizer`, then identifiers where their case
+/// changes, so that `parseHttpRequest` and `parse_http_request` both become
+/// "parse", "http", "request". A run of capitals is one word, but for its
+/// last letter when a lowercase one follows: `HTTPServer` becomes "HTTP",
+/// "Server".
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodeTokenizer;
+
+impl Tokenizer for CodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut words = vec![];
+        for word in text.split(|ch: char| !ch.is_alphanumeric()) {
+            let chars: Vec<(usize, char)> = word.char_indices().collect();
+            let mut start = 0;
+            for i in 1..chars.len() {
+                let (prev, (at, c)) = (chars[i - 1].1, chars[i]);
+                let lower_next =
+                    chars.get(i + 1).is_some_and(|&(_, n)| n.is_lowercase());
+                let boundary = c.is_uppercase()
+                    && (prev.is_lowercase()
+                        || prev.is_numeric()
+                        || (prev.is_uppercase() && lower_next));
+                if boundary {
+                    words.push(&word[start..at]);
+                    start = at;
+                }
+            }
+            if start < word.len() {
+                words.push(&word[start..]);
+            }
+        }
+        words
+            .into_iter()
+            .enumerate()
+            .map(|(i, word)| Token::from_slice(text, word, i))
+            .collect()
+    }
+}
+
+/// True for characters of scripts written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'    // Hangul Jamo
+        | '\u{3040}'..='\u{30FF}'  // Hiragana, Katakana
+        | '\u{3130}'..='\u{318F}'  // Hangul Compatibility Jamo
+        | '\u{3400}'..='\u{4DBF}'  // CJK Unified Ideographs Extension A
+        | '\u{4E00}'..='\u{9FFF}'  // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'  // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}'  // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FA1F}' // CJK Extensions B and later
+    )
+}
+
+/// The tokenizers an `AnalyzerConfig` can select.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TokenizerKind {
+    /// `SimpleTokenizer`, the historical behavior.
+    #[default]
+    Simple,
+    /// `UnicodeTokenizer`.
+    Unicode,
+    /// `NgramTokenizer` with the given n-gram size.
+    Ngram(usize),
+    /// `CodeTokenizer`.
+    Code,
+}
+
+impl TokenizerKind {
+    /// The name recorded in index metadata, e.g. "unicode" or "ngram:2".
+    pub fn name(&self) -> String {
+        match self {
+            TokenizerKind::Simple => "simple".to_string(),
+            TokenizerKind::Unicode => "unicode".to_string(),
+            TokenizerKind::Ngram(n) => format!("ngram:{}", n),
+            TokenizerKind::Code => "code".to_string(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TokenizerKind> {
+        match name {
+            "simple" => Some(TokenizerKind::Simple),
+            "unicode" => Some(TokenizerKind::Unicode),
+            "code" => Some(TokenizerKind::Code),
+            _ => {
+                let n = name.strip_prefix("ngram:")?.parse().ok()?;
+                (n > 0).then_some(TokenizerKind::Ngram(n))
+            }
+        }
+    }
+
+    /// The tokenizer of this kind.
+    pub fn tokenizer(self) -> Box<dyn Tokenizer> {
+        match self {
+            TokenizerKind::Simple => Box::new(SimpleTokenizer),
+            TokenizerKind::Unicode => Box::new(UnicodeTokenizer),
+            TokenizerKind::Ngram(n) => Box::new(NgramTokenizer { n }),
+            TokenizerKind::Code => Box::new(CodeTokenizer),
+        }
+    }
+}
+
+/// Lowercases every token.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LowerCaser;
+
+impl TokenFilter for LowerCaser {
+    fn filter(&self, mut token: Token) -> Option<Token> {
+        if token.text.chars().any(char::is_uppercase) {
+            token.text = token.text.to_lowercase();
+        }
+        Some(token)
+    }
+}
+
+/// Drops tokens that appear in a list of stop words.
+///
+/// The comparison is exact, so this filter normally goes after `LowerCaser`.
+#[derive(Debug, Clone, Default)]
+pub struct StopWordFilter {
+    words: HashSet<String>,
+}
+
+impl StopWordFilter {
+    pub fn new<I, S>(words: I) -> StopWordFilter
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        StopWordFilter {
+            words: words.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TokenFilter for StopWordFilter {
+    fn filter(&self, token: Token) -> Option<Token> {
+        if self.words.contains(&token.text) {
+            None
+        } else {
+            Some(token)
+        }
+    }
+}
+
+/// Drops tokens shorter than `min` or longer than `max` characters.
+#[derive(Debug, Clone, Copy)]
+pub struct LengthFilter {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl TokenFilter for LengthFilter {
+    fn filter(&self, token: Token) -> Option<Token> {
+        let len = token.text.chars().count();
+        if len >= self.min && len <= self.max {
+            Some(token)
+        } else {
+            None
+        }
+    }
+}
+
+/// Drops numbers: tokens made of digits only, like "2024", or with the
+/// separators that `UnicodeTokenizer` keeps within them, like "3.14".
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NumberFilter;
+
+impl TokenFilter for NumberFilter {
+    fn filter(&self, token: Token) -> Option<Token> {
+        let mut chars = token.text.chars();
+        let number = chars.next().is_some_and(char::is_numeric)
+            && chars.all(|c| c.is_numeric() || c == '.' || c == ',');
+        if number {
+            None
+        } else {
+            Some(token)
+        }
+    }
+}
+
+/// Reduces words to their stem with a Snowball stemmer, so that "running"
+/// and "run" become the same term.
+///
+/// Only available with the `stemming` feature.
+#[cfg(feature = "stemming")]
+pub struct StemFilter {
+    stemmer: rust_stemmers::Stemmer,
+}
+
+#[cfg(feature = "stemming")]
+impl StemFilter {
+    /// Create a stemmer for `language` (e.g. "english"). Returns `None` for
+    /// unsupported languages.
+    pub fn new(language: &str) -> Option<StemFilter> {
+        use rust_stemmers::Algorithm::*;
+        let algorithm = match language.to_lowercase().as_str() {
+            "arabic" => Arabic,
+            "danish" => Danish,
+            "dutch" => Dutch,
+            "english" => English,
+            "finnish" => Finnish,
+            "french" => French,
+            "german" => German,
+            "greek" => Greek,
+            "hungarian" => Hungarian,
+            "italian" => Italian,
+            "norwegian" => Norwegian,
+            "portuguese" => Portuguese,
+            "romanian" => Romanian,
+            "russian" => Russian,
+            "spanish" => Spanish,
+            "swedish" => Swedish,
+            "tamil" => Tamil,
+            "turkish" => Turkish,
+            _ => return None,
+        };
+        Some(StemFilter {
+            stemmer: rust_stemmers::Stemmer::create(algorithm),
+        })
+    }
+}
+
+#[cfg(feature = "stemming")]
+impl TokenFilter for StemFilter {
+    fn filter(&self, mut token: Token) -> Option<Token> {
+        token.text = self.stemmer.stem(&token.text).into_owned();
+        Some(token)
+    }
+}
+
+/// Which forms of each word an analyzer produces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseForms {
+    /// The tokens of the filter pipeline, usually lowercased.
+    #[default]
+    Folded,
+    /// The original word of each token, with `EXACT_PREFIX`, for
+    /// case-sensitive searches.
+    Exact,
+    /// Both, the folded form first: what a case-preserving index stores.
+    Both,
+}
+
+/// An analyzer made of a tokenizer and a pipeline of filters, applied in the
+/// order they were added, e.g.
+/// `TextAnalyzer::new(SimpleTokenizer).filter(LowerCaser).filter(stop_words)`.
+pub struct TextAnalyzer {
+    tokenizer: Box<dyn Tokenizer>,
+    filters: Vec<Box<dyn TokenFilter>>,
+    case_forms: CaseForms,
+}
+
+impl TextAnalyzer {
+    /// Create an analyzer with no filters.
+    pub fn new<T: Tokenizer + 'static>(tokenizer: T) -> TextAnalyzer {
+        TextAnalyzer {
+            tokenizer: Box::new(tokenizer),
+            filters: vec![],
+            case_forms: CaseForms::Folded,
+        }
+    }
+
+    /// The tokenizer at the head of the pipeline. Its tokens, before any
+    /// filtering, are the words that the offsets stored in an index count.
+    pub fn tokenizer(&self) -> &dyn Tokenizer {
+        self.tokenizer.as_ref()
+    }
+
+    /// Append a filter to the pipeline.
+    pub fn filter<F: TokenFilter + 'static>(mut self, filter: F) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Produce `forms` of each word that gets through the filters. Words
+    /// dropped by a filter are dropped in all forms.
+    pub fn with_case_forms(mut self, forms: CaseForms) -> Self {
+        self.case_forms = forms;
+        self
+    }
+
+    /// The forms of each word this analyzer produces.
+    pub fn case_forms(&self) -> CaseForms {
+        self.case_forms
+    }
+}
+
+impl Default for TextAnalyzer {
+    fn default() -> Self {
+        TextAnalyzer::new(SimpleTokenizer).filter(LowerCaser)
+    }
+}
+
+impl Analyzer for TextAnalyzer {
+    fn analyze(&self, text: &str) -> Vec<Token> {
+        self.analyze_counted(text).0
+    }
+
+    fn analyze_counted(&self, text: &str) -> (Vec<Token>, u32) {
+        let filtered = |token| {
+            self.filters
+                .iter()
+                .try_fold(token, |token, filter| filter.filter(token))
+        };
+        let words = self.tokenizer.tokenize(text);
+        let count = u32::try_from(words.len()).unwrap_or(u32::MAX);
+        let tokens = words.into_iter().filter_map(filtered);
+        let exact = |token: &Token| Token {
+            text: format!("{}{}", EXACT_PREFIX, &text[token.start..token.end]),
+            ..token.clone()
+        };
+        let tokens = match self.case_forms {
+            CaseForms::Folded => tokens.collect(),
+            CaseForms::Exact => tokens.map(|token| exact(&token)).collect(),
+            CaseForms::Both => tokens
+                .flat_map(|token| {
+                    let exact = exact(&token);
+                    [token, exact]
+                })
+                .collect(),
+        };
+        (tokens, count)
+    }
+}
+
+/// The settings an index was built with, from which the matching analyzer
+/// can be rebuilt at search time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalyzerConfig {
+    /// How text is split into words.
+    pub tokenizer: TokenizerKind,
+    /// Languages whose built-in stop words are removed.
+    pub stop_words: Vec<String>,
+    /// Additional stop words, e.g. loaded from a user-supplied file. The
+    /// words themselves are recorded, so the file isn't needed at search
+    /// time.
+    pub custom_stop_words: Vec<String>,
+    /// Language of the Snowball stemmer applied after lowercasing, if any.
+    pub stemmer: Option<String>,
+    /// Detect the language of each document and record it in the document
+    /// table. Implied by `auto` in `stop_words` or `stemmer`.
+    pub detect_language: bool,
+    /// Also index every word in its original case, so that searches can be
+    /// case-sensitive.
+    pub preserve_case: bool,
+    /// Drop numbers (see `NumberFilter`).
+    pub skip_numbers: bool,
+    /// Drop words shorter than this many characters.
+    pub min_length: Option<usize>,
+    /// Drop words longer than this many characters.
+    pub max_length: Option<usize>,
+    /// The files analyzed or extracted differently from the others, by
+    /// glob (see `routing`).
+    pub routes: Vec<Route>,
+}
+
+impl AnalyzerConfig {
+    /// The language name standing for "the language of the document".
+    pub const AUTO: &'static str = "auto";
+
+    /// True if documents are analyzed according to their language, or at
+    /// least have their language detected.
+    pub fn uses_language(&self) -> bool {
+        self.detect_language
+            || self.stop_words.iter().any(|l| l == Self::AUTO)
+            || self.stemmer.as_deref() == Some(Self::AUTO)
+    }
+
+    /// The configuration the document at `path` is analyzed with: that of
+    /// the first of `routes` to match it that has one, or this one.
+    pub fn for_path(&self, path: &Path) -> &AnalyzerConfig {
+        self.routes
+            .iter()
+            .filter(|route| route.matches(path))
+            .find_map(|route| route.analyzer.as_ref())
+            .unwrap_or(self)
+    }
+
+    /// Build the analyzer described by this configuration, for text whose
+    /// language is unknown: `auto` stop words and stemming are left out.
+    pub fn build(&self) -> io::Result<TextAnalyzer> {
+        self.build_for(None)
+    }
+
+    /// Build the analyzer described by this configuration, for text in
+    /// `language` (a name like "english"). `auto` stop words and stemming
+    /// are those of `language`, when there are any.
+    ///
+    /// With `preserve_case`, the analyzer produces both forms of each word,
+    /// as they are indexed.
+    pub fn build_for(
+        &self,
+        language: Option<&str>,
+    ) -> io::Result<TextAnalyzer> {
+        let mut analyzer = TextAnalyzer {
+            tokenizer: self.tokenizer.tokenizer(),
+            filters: vec![],
+            case_forms: CaseForms::Folded,
+        }
+        .filter(LowerCaser);
+        if self.skip_numbers {
+            analyzer = analyzer.filter(NumberFilter);
+        }
+        if self.min_length.is_some() || self.max_length.is_some() {
+            let min = self.min_length.unwrap_or(0);
+            let max = self.max_length.unwrap_or(usize::MAX);
+            if min > max {
+                return Err(invalid_config(format!(
+                    "minimum length {min} is over the maximum {max}"
+                )));
+            }
+            analyzer = analyzer.filter(LengthFilter { min, max });
+        }
+        if !self.stop_words.is_empty() || !self.custom_stop_words.is_empty() {
+            let mut words: Vec<&str> = vec![];
+            for name in &self.stop_words {
+                if name == Self::AUTO {
+                    words.extend(
+                        language
+                            .and_then(stopwords::for_language)
+                            .unwrap_or(&[]),
+                    );
+                    continue;
+                }
+                let list = stopwords::for_language(name).ok_or_else(|| {
+                    invalid_config(format!("no stop words for {name}"))
+                })?;
+                words.extend(list);
+            }
+            words.extend(self.custom_stop_words.iter().map(String::as_str));
+            analyzer = analyzer.filter(StopWordFilter::new(words));
+        }
+        if let Some(name) = &self.stemmer {
+            #[cfg(feature = "stemming")]
+            if name == Self::AUTO {
+                // Languages without a stemmer are left as they are.
+                if let Some(stemmer) = language.and_then(StemFilter::new) {
+                    analyzer = analyzer.filter(stemmer);
+                }
+            } else {
+                let stemmer = StemFilter::new(name).ok_or_else(|| {
+                    invalid_config(format!("unsupported language {name}"))
+                })?;
+                analyzer = analyzer.filter(stemmer);
+            }
+            #[cfg(not(feature = "stemming"))]
+            return Err(invalid_config(format!(
+                "stemming ({name}) requires the `stemming` feature"
+            )));
+        }
+        if self.preserve_case {
+            analyzer = analyzer.with_case_forms(CaseForms::Both);
+        }
+        Ok(analyzer)
+    }
+
+    /// Serialize as `key=value` lines.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        if self.tokenizer != TokenizerKind::default() {
+            out.push_str(&format!("tokenizer={}\n", self.tokenizer.name()));
+        }
+        if !self.stop_words.is_empty() {
+            out.push_str(&format!(
+                "stop_words={}\n",
+                self.stop_words.join(",")
+            ));
+        }
+        if !self.custom_stop_words.is_empty() {
+            out.push_str(&format!(
+                "custom_stop_words={}\n",
+                self.custom_stop_words.join(",")
+            ));
+        }
+        if let Some(language) = &self.stemmer {
+            out.push_str(&format!("stemmer={}\n", language));
+        }
+        if self.detect_language {
+            out.push_str("detect_language=true\n");
+        }
+        if self.preserve_case {
+            out.push_str("preserve_case=true\n");
+        }
+        if self.skip_numbers {
+            out.push_str("skip_numbers=true\n");
+        }
+        if let Some(min) = self.min_length {
+            out.push_str(&format!("min_length={}\n", min));
+        }
+        if let Some(max) = self.max_length {
+            out.push_str(&format!("max_length={}\n", max));
+        }
+        for route in &self.routes {
+            out.push_str(&format!("route:{}={}\n", route.glob, route.encode()));
+        }
+        out
+    }
+
+    /// Parse the output of `encode`.
+    pub fn decode(text: &str) -> io::Result<AnalyzerConfig> {
+        let mut config = AnalyzerConfig::default();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid_config(format!("bad line {line:?}")))?;
+            let list = || {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|w| !w.is_empty())
+                    .map(String::from)
+                    .collect()
+            };
+            if let Some(glob) = key.trim().strip_prefix("route:") {
+                config.routes.push(Route::decode(glob, value)?);
+                continue;
+            }
+            match key.trim() {
+                "tokenizer" => {
+                    config.tokenizer = TokenizerKind::from_name(value.trim())
+                        .ok_or_else(|| {
+                            invalid_config(format!("unknown tokenizer {value}"))
+                        })?
+                }
+                "stop_words" => config.stop_words = list(),
+                "custom_stop_words" => config.custom_stop_words = list(),
+                "stemmer" => config.stemmer = Some(value.trim().to_string()),
+                "detect_language" => {
+                    config.detect_language = value.trim() == "true"
+                }
+                "preserve_case" => {
+                    config.preserve_case = value.trim() == "true"
+                }
+                "skip_numbers" => config.skip_numbers = value.trim() == "true",
+                "min_length" => config.min_length = Some(length(value)?),
+                "max_length" => config.max_length = Some(length(value)?),
+                other => {
+                    return Err(invalid_config(format!("unknown key {other}")))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn length(value: &str) -> io::Result<usize> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid_config(format!("bad length {value:?}")))
+}
+
+pub(crate) fn invalid_config(msg: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("analyzer config: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(config: &AnalyzerConfig, text: &str) -> Vec<String> {
+        let analyzer = config.build().unwrap();
+        analyzer.analyze(text).into_iter().map(|t| t.text).collect()
+    }
+
+    #[test]
+    fn numbers_and_words_out_of_bounds_are_dropped() {
+        let text =
+            "Error 404 at 0x1f: a request_id_0123 of supercalifragilistic";
+        let config = AnalyzerConfig {
+            skip_numbers: true,
+            min_length: Some(2),
+            max_length: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(
+            terms(&config, text),
+            ["error", "at", "0x1f", "request", "id", "of"]
+        );
+        // Offsets still count the words dropped.
+        let analyzer = config.build().unwrap();
+        assert_eq!(analyzer.analyze(text)[1].position, 2);
+        assert_eq!(AnalyzerConfig::decode(&config.encode()).unwrap(), config);
+        assert_eq!(terms(&AnalyzerConfig::default(), "a 404").len(), 2);
+        let unicode = AnalyzerConfig {
+            tokenizer: TokenizerKind::Unicode,
+            skip_numbers: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            terms(&unicode, "pi is 3.14, not 3rd"),
+            ["pi", "is", "not", "3rd"]
+        );
+    }
+
+    #[test]
+    fn tokenizers_split_text_into_words() {
+        let text = "It's 3.14 東京都 fooBar";
+        let cases: &[(TokenizerKind, &[&str])] = &[
+            (
+                TokenizerKind::Simple,
+                &["It", "s", "3", "14", "東京都", "fooBar"],
+            ),
+            (
+                TokenizerKind::Unicode,
+                &["It's", "3.14", "東", "京", "都", "fooBar"],
+            ),
+            (
+                TokenizerKind::Ngram(1),
+                &["It", "s", "3", "14", "東", "京", "都", "fooBar"],
+            ),
+            (
+                TokenizerKind::Ngram(2),
+                &["It", "s", "3", "14", "東京", "京都", "fooBar"],
+            ),
+            (
+                TokenizerKind::Ngram(3),
+                &["It", "s", "3", "14", "東京都", "fooBar"],
+            ),
+            (
+                TokenizerKind::Code,
+                &["It", "s", "3", "14", "東京都", "foo", "Bar"],
+            ),
+        ];
+        for &(kind, expected) in cases {
+            let tokens = kind.tokenizer().tokenize(text);
+            let words: Vec<&str> = tokens.iter().map(|t| &t.text[..]).collect();
+            assert_eq!(words, expected, "{}", kind.name());
+            for (i, token) in tokens.iter().enumerate() {
+                assert_eq!(token.position as usize, i);
+                assert_eq!(&text[token.start..token.end], token.text);
+            }
+            assert_eq!(TokenizerKind::from_name(&kind.name()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn ngrams_break_runs_of_every_cjk_script() {
+        let cases: &[(&str, &[&str])] = &[
+            ("日", &["日"]),
+            ("東京タワー", &["東京", "京タ", "タワ", "ワー"]),
+            ("tokyo東京tower", &["tokyo", "東京", "tower"]),
+            ("한국어 문법", &["한국", "국어", "문법"]),
+            ("ひらがなカナ", &["ひら", "らが", "がな", "なカ", "カナ"]),
+        ];
+        for &(text, expected) in cases {
+            let tokens = NgramTokenizer { n: 2 }.tokenize(text);
+            let words: Vec<&str> = tokens.iter().map(|t| &t.text[..]).collect();
+            assert_eq!(words, expected, "{}", text);
+        }
+        assert_eq!(TokenizerKind::from_name("ngram:0"), None);
+    }
+
+    #[test]
+    fn filters_rewrite_and_drop_words() {
+        let stop = |languages: &[&str], custom: &[&str]| AnalyzerConfig {
+            stop_words: languages.iter().map(|l| l.to_string()).collect(),
+            custom_stop_words: custom.iter().map(|w| w.to_string()).collect(),
+            ..Default::default()
+        };
+        let unicode = AnalyzerConfig {
+            tokenizer: TokenizerKind::Unicode,
+            ..Default::default()
+        };
+        let cases: &[(AnalyzerConfig, &str, &[&str])] = &[
+            // Lowercasing folds every script, final sigma included.
+            (
+                AnalyzerConfig::default(),
+                "ÉCOLE Straße ΟΔΟΣ ДОМ",
+                &["école", "straße", "οδος", "дом"],
+            ),
+            // Combining marks are only kept in words by `UnicodeTokenizer`.
+            (AnalyzerConfig::default(), "E\u{301}TE\u{301}", &["e", "te"]),
+            (unicode, "E\u{301}TE\u{301}", &["e\u{301}te\u{301}"]),
+            (
+                stop(&["english"], &[]),
+                "The cat and THE dog",
+                &["cat", "dog"],
+            ),
+            (
+                stop(&["english", "french"], &["chien"]),
+                "the chat et le chien of Paris",
+                &["chat", "paris"],
+            ),
+            (
+                AnalyzerConfig {
+                    min_length: Some(3),
+                    ..Default::default()
+                },
+                "a an ant été",
+                &["ant", "été"],
+            ),
+            (
+                AnalyzerConfig {
+                    preserve_case: true,
+                    ..stop(&["english"], &[])
+                },
+                "The Rust book",
+                &["rust", "=Rust", "book", "=book"],
+            ),
+        ];
+        for (config, text, expected) in cases {
+            assert_eq!(terms(config, text), *expected, "{}", text);
+        }
+        let unknown = stop(&["klingon"], &[]);
+        assert!(unknown.build().is_err());
+    }
+
+    #[cfg(feature = "stemming")]
+    #[test]
+    fn words_of_every_language_are_stemmed() {
+        let cases = [
+            ("arabic", "المكتبات", "مكتب"),
+            ("danish", "hestene", "hest"),
+            ("dutch", "lichamelijke", "licham"),
+            ("english", "Running", "run"),
+            ("finnish", "kissoille", "kiso"),
+            ("french", "continuellement", "continuel"),
+            ("german", "Häuser", "haus"),
+            ("greek", "γυναίκες", "γυναικ"),
+            ("hungarian", "házakban", "ház"),
+            ("italian", "abbandonata", "abbandon"),
+            ("norwegian", "hestene", "hest"),
+            ("portuguese", "bibliotecas", "bibliotec"),
+            ("romanian", "copiilor", "cop"),
+            ("russian", "книгами", "книг"),
+            ("spanish", "corriendo", "corr"),
+            ("swedish", "flickorna", "flick"),
+            ("tamil", "பள்ளிகளில்", "பள்ளி"),
+            ("turkish", "kitaplardan", "kitap"),
+        ];
+        let languages: Vec<&str> = cases.iter().map(|c| c.0).collect();
+        assert_eq!(languages, crate::language::LANGUAGES);
+        for (language, word, stem) in cases {
+            // The vowel signs of Tamil aren't alphanumeric.
+            let config = AnalyzerConfig {
+                tokenizer: TokenizerKind::Unicode,
+                stemmer: Some(language.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(terms(&config, word), [stem], "{}", language);
+        }
+        let unknown = AnalyzerConfig {
+            stemmer: Some("klingon".to_string()),
+            ..Default::default()
+        };
+        assert!(unknown.build().is_err());
+    }
+
+    #[test]
+    fn identifiers_are_split_where_their_case_changes() {
+        let tokens = CodeTokenizer.tokenize("parseHTTPRequest(utf8Bytes, x)");
+        let words: Vec<_> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(words, ["parse", "HTTP", "Request", "utf8", "Bytes", "x"]);
+        assert_eq!((tokens[2].start, tokens[2].position), (9, 2));
+    }
+}
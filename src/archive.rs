@@ -0,0 +1,330 @@
+//! Documents inside archives.
+//!
+//! Corpora are often distributed as archives. Rather than having to unpack
+//! them first, each file in an archive is indexed as a document of its own,
+//! recorded under the path of the archive followed by `!` and the name of
+//! the member, e.g. `corpus.zip!texts/intro.txt`.
+//!
+//! Supported are `.zip`, `.tar`, `.tar.gz` (or `.tgz`) and plain `.gz`
+//! files, whose only member is named after the file without the `.gz`.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// A file inside an archive.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// `archive!name`, where `archive` is the canonical path of the archive.
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+    /// Modification time, in seconds since the Unix epoch. Members of zip
+    /// archives get the modification time of the archive.
+    pub mtime: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    Gz,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".gz") {
+            Some(Format::Gz)
+        } else {
+            None
+        }
+    }
+}
+
+/// True if the file at `path` is an archive whose members should be indexed
+/// instead of the file itself. Only the name of the file is checked.
+pub fn is_archive(path: &Path) -> bool {
+    Format::of(path).is_some()
+}
+
+/// All the files in the archive at `path`. Fails with `InvalidData` if the
+/// archive is corrupt.
+pub fn members(path: &Path) -> io::Result<Vec<Member>> {
+    let Some(format) = Format::of(path) else {
+        return Err(not_an_archive(path));
+    };
+    let file = BufReader::new(File::open(path)?);
+    let mtime = file
+        .get_ref()
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = &path.canonicalize()?;
+    let mut members = vec![];
+    let mut add = |name: &str, bytes, mtime| {
+        members.push(Member {
+            path: member_path(path, name),
+            bytes,
+            mtime,
+        })
+    };
+    match format {
+        Format::Zip => {
+            let mut zip = ZipArchive::new(file).map_err(corrupt)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).map_err(corrupt)?;
+                if entry.is_file() {
+                    let name = entry.name().to_string();
+                    add(&name, read_all(&mut entry)?, mtime);
+                }
+            }
+        }
+        Format::Tar | Format::TarGz => {
+            let mut tar = tar_archive(file, format);
+            for entry in tar.entries().map_err(corrupt)? {
+                let mut entry = entry.map_err(corrupt)?;
+                if entry.header().entry_type().is_file() {
+                    let name = entry.path()?.to_string_lossy().into_owned();
+                    let mtime = entry.header().mtime().unwrap_or(mtime);
+                    add(&name, read_all(&mut entry)?, mtime);
+                }
+            }
+        }
+        Format::Gz => {
+            let bytes = read_all(&mut GzDecoder::new(file))?;
+            add(&gz_member_name(path), bytes, mtime);
+        }
+    }
+    Ok(members)
+}
+
+/// Read the file at `path`, which may also be the path of an archive member
+/// as returned by `members`.
+pub fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            // Both the archive path and the member name may contain a `!`;
+            // the archive is the first prefix that exists.
+            let full = path.to_string_lossy();
+            for (i, _) in full.match_indices('!') {
+                let archive = Path::new(&full[..i]);
+                if is_archive(archive) && archive.is_file() {
+                    return read_member(archive, &full[i + 1..]);
+                }
+            }
+            Err(e)
+        }
+        result => result,
+    }
+}
+
+/// Read the member called `name` of the archive at `archive`.
+fn read_member(archive: &Path, name: &str) -> io::Result<Vec<u8>> {
+    let file = BufReader::new(File::open(archive)?);
+    match Format::of(archive) {
+        Some(Format::Zip) => {
+            let mut zip = ZipArchive::new(file).map_err(corrupt)?;
+            let mut entry = zip.by_name(name).map_err(|_| no_member(name))?;
+            read_all(&mut entry)
+        }
+        Some(format @ (Format::Tar | Format::TarGz)) => {
+            let mut tar = tar_archive(file, format);
+            for entry in tar.entries().map_err(corrupt)? {
+                let mut entry = entry.map_err(corrupt)?;
+                if entry.path()?.to_string_lossy() == name {
+                    return read_all(&mut entry);
+                }
+            }
+            Err(no_member(name))
+        }
+        Some(Format::Gz) if name == gz_member_name(archive) => {
+            read_all(&mut GzDecoder::new(file))
+        }
+        Some(Format::Gz) => Err(no_member(name)),
+        None => Err(not_an_archive(archive)),
+    }
+}
+
+fn tar_archive(
+    file: BufReader<File>,
+    format: Format,
+) -> tar::Archive<Box<dyn Read>> {
+    let reader: Box<dyn Read> = match format {
+        Format::TarGz => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    tar::Archive::new(reader)
+}
+
+fn member_path(archive: &Path, name: &str) -> PathBuf {
+    let mut path = archive.as_os_str().to_os_string();
+    path.push("!");
+    path.push(name);
+    PathBuf::from(path)
+}
+
+fn gz_member_name(path: &Path) -> String {
+    path.file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+}
+
+fn read_all<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    // Decompressors report corrupt data as invalid input.
+    reader.read_to_end(&mut bytes).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidInput => corrupt(e),
+        _ => e,
+    })?;
+    Ok(bytes)
+}
+
+fn corrupt<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn not_an_archive(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("not an archive: {}", path.display()),
+    )
+}
+
+fn no_member(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no such archive member: {}", name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Indexer;
+    use crate::read::IndexFileReader;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    const FILES: [(&str, &str); 2] = [
+        ("texts/intro.txt", "the cat sat"),
+        ("notes.md", "a dog! barked"),
+    ];
+
+    fn write_zip(path: &Path) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, text) in FILES {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(text.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn write_tar_gz(path: &Path) {
+        let gz =
+            GzEncoder::new(File::create(path).unwrap(), Compression::fast());
+        let mut tar = tar::Builder::new(gz);
+        for (name, text) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_000_000);
+            tar.append_data(&mut header, name, text.as_bytes()).unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn write_gz(path: &Path) {
+        let mut gz =
+            GzEncoder::new(File::create(path).unwrap(), Compression::fast());
+        gz.write_all(b"a single bird").unwrap();
+        gz.finish().unwrap();
+    }
+
+    #[test]
+    fn members_are_read_from_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let (zip, tgz, gz) = (
+            root.join("corpus.zip"),
+            root.join("corpus.tgz"),
+            root.join("words.txt.gz"),
+        );
+        write_zip(&zip);
+        write_tar_gz(&tgz);
+        write_gz(&gz);
+        for archive in [&zip, &tgz] {
+            let found = members(archive).unwrap();
+            let found: Vec<_> = found
+                .iter()
+                .map(|m| (m.path.clone(), String::from_utf8_lossy(&m.bytes)))
+                .collect();
+            let expected: Vec<_> = FILES
+                .iter()
+                .map(|(name, text)| {
+                    (member_path(archive, name), (*text).into())
+                })
+                .collect();
+            assert_eq!(found, expected, "{}", archive.display());
+            for (path, text) in expected {
+                assert_eq!(read_file(&path).unwrap(), text.as_bytes());
+            }
+        }
+        assert_eq!(members(&tgz).unwrap()[0].mtime, 1_000_000);
+        let only = members(&gz).unwrap();
+        assert_eq!(only.len(), 1);
+        assert_eq!(only[0].path, root.join("words.txt.gz!words.txt"));
+        assert_eq!(read_file(&only[0].path).unwrap(), b"a single bird");
+
+        let missing = read_file(&member_path(&zip, "none.txt"));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(!is_archive(&root.join("corpus.txt")));
+        fs::write(&zip, "not a zip file").unwrap();
+        let corrupt = members(&zip).unwrap_err();
+        assert_eq!(corrupt.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn members_are_indexed_as_documents_of_their_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        let out = dir.path().join("out");
+        fs::create_dir(&docs).unwrap();
+        fs::create_dir(&out).unwrap();
+        write_zip(&docs.join("corpus.zip"));
+        write_gz(&docs.join("words.txt.gz"));
+        let file = Indexer::new(&docs).output(&out).run().unwrap();
+        let index = IndexFileReader::get_index_from_file(file).unwrap();
+        let root = docs.canonicalize().unwrap();
+        let mut paths: Vec<_> =
+            index.docs.values().map(|info| info.path.clone()).collect();
+        paths.sort();
+        let expected = [
+            "corpus.zip!notes.md",
+            "corpus.zip!texts/intro.txt",
+            "words.txt.gz!words.txt",
+        ]
+        .map(|name| root.join(name).display().to_string());
+        assert_eq!(paths, expected);
+        for term in ["cat", "dog", "bird"] {
+            assert_eq!(index.map[term].len(), 1, "{}", term);
+        }
+    }
+}
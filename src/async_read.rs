@@ -0,0 +1,267 @@
+//! Looking terms up in an index file from async code.
+//!
+//! `AsyncIndexFileSearcher` answers the lookups of `IndexFileSearcher` in
+//! tokio tasks. The table of contents and the document table are loaded
+//! once, when the file is opened, and shared by all lookups.
+//!
+//! Reads are not async I/O: the hits of the terms looked up are read with
+//! ordinary blocking reads, on tokio's pool of blocking threads
+//! (`spawn_blocking`), with a reader per lookup running. Many lookups can
+//! then wait on the disk at once without holding up the threads that run
+//! tasks, and a task awaiting a lookup costs no thread of its own. Readers
+//! share the file opened first, each reading at its own position, so the
+//! file can be replaced while it is searched, and are kept for the lookups
+//! that follow.
+//!
+//! ```text
+//! let index = AsyncIndexFileSearcher::open("out/index.dat").await?;
+//! let hits = index.lookup("cat").await?;
+//! let docs = index.intersect(&["cat", "dog"]).await?;
+//! ```
+//!
+//! Built with the `async` feature.
+
+use std::io::{self, BufReader};
+use std::panic;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::crypt::IndexFile;
+use crate::error::{Error, Result};
+use crate::index::{Doc, DocEntry};
+use crate::read::IndexFileSearcher;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Most readers kept between lookups.
+const IDLE_READERS: usize = 16;
+
+/// An `IndexFileSearcher` for async code. Clones share the loaded tables
+/// and the open file.
+#[derive(Debug, Clone)]
+pub struct AsyncIndexFileSearcher {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Holds the tables and the file; its own reader isn't used.
+    searcher: IndexFileSearcher,
+    /// Readers of the file not used by any lookup.
+    readers: Mutex<Vec<BufReader<IndexFile>>>,
+}
+
+impl AsyncIndexFileSearcher {
+    /// Open an index file and load its table of contents, like
+    /// `IndexFileSearcher::open`.
+    pub async fn open<P: AsRef<Path>>(
+        filename: P,
+    ) -> Result<AsyncIndexFileSearcher> {
+        let path = filename.as_ref().to_path_buf();
+        blocking(move || {
+            let searcher = IndexFileSearcher::open(&path)?;
+            Ok(AsyncIndexFileSearcher {
+                inner: Arc::new(Inner {
+                    searcher,
+                    readers: Mutex::new(vec![]),
+                }),
+            })
+        })
+        .await
+    }
+
+    /// The searcher whose tables are shared, for what doesn't read the
+    /// file: `entry`, `docs`, `terms` and the like.
+    pub fn searcher(&self) -> &IndexFileSearcher {
+        &self.inner.searcher
+    }
+
+    /// Read and decode the hits for `term`, like
+    /// `IndexFileSearcher::lookup`.
+    pub async fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        let Some(e) = self.inner.searcher.entry(term) else {
+            return Ok(None);
+        };
+        let inner = Arc::clone(&self.inner);
+        blocking(move || {
+            let hits_raw = inner
+                .with_reader(|data| inner.searcher.read_hits_from(data, &e))?;
+            inner.searcher.decode(&e, &hits_raw)
+        })
+        .await
+    }
+
+    /// The documents containing all of `terms`, like
+    /// `IndexFileSearcher::intersect`.
+    pub async fn intersect(&self, terms: &[&str]) -> Result<Vec<Doc>> {
+        let Some(entries) = self.inner.searcher.entries_of(terms) else {
+            return Ok(vec![]);
+        };
+        let inner = Arc::clone(&self.inner);
+        blocking(move || {
+            let hits = inner.with_reader(|data| {
+                entries
+                    .iter()
+                    .map(|e| inner.searcher.read_hits_from(data, e))
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            inner.searcher.intersect_hits(&entries, &hits)
+        })
+        .await
+    }
+}
+
+impl Inner {
+    /// Run `f` with a reader of the file no other lookup is using, a new
+    /// one if none is left. The reader is kept unless `f` fails, as it may
+    /// have been left in the middle of a read.
+    fn with_reader<T>(
+        &self,
+        f: impl FnOnce(&mut BufReader<IndexFile>) -> Result<T>,
+    ) -> Result<T> {
+        let idle = self.readers.lock().unwrap().pop();
+        let mut data = match idle {
+            Some(data) => data,
+            None => self.searcher.reader(),
+        };
+        let result = f(&mut data)?;
+        let mut readers = self.readers.lock().unwrap();
+        if readers.len() < IDLE_READERS {
+            readers.push(data);
+        }
+        Ok(result)
+    }
+}
+
+/// Run `f` on one of tokio's blocking threads, passing on its panics.
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(Error::Io(io::Error::other(e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::IndexSettings;
+    use crate::index::InMemoryIndex;
+    use crate::tmp::TmpDir;
+    use crate::tombstone::Tombstones;
+    use crate::write::write_index_to_tmp_file;
+    use crate::HASH_LENGTH;
+
+    #[test]
+    fn answers_like_index_file_searcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = InMemoryIndex::new();
+        for i in 0..40u8 {
+            let text = format!("doc{} common word{} word{}", i, i % 3, i % 7);
+            index.merge(InMemoryIndex::from_single_document(
+                &[i; HASH_LENGTH],
+                text,
+            ));
+        }
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let mut tombstones = Tombstones::default();
+        for i in (0..40).step_by(5) {
+            tombstones.add(Doc::new(&[i; HASH_LENGTH]));
+        }
+        tombstones.save(&file).unwrap();
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let index = runtime
+            .block_on(AsyncIndexFileSearcher::open(&file))
+            .unwrap();
+        let terms: Vec<String> = searcher.terms().collect();
+        assert_eq!(index.searcher().term_count(), terms.len());
+        // All lookups at once, to share the file handles.
+        let lookups = runtime.block_on(async {
+            let tasks: Vec<_> = terms
+                .iter()
+                .map(|term| {
+                    let index = index.clone();
+                    let term = term.clone();
+                    tokio::spawn(async move { index.lookup(&term).await })
+                })
+                .collect();
+            let mut lookups = vec![];
+            for task in tasks {
+                lookups.push(task.await.unwrap().unwrap());
+            }
+            lookups
+        });
+        for (term, found) in terms.iter().zip(lookups) {
+            assert_eq!(found, searcher.lookup(term).unwrap(), "{}", term);
+        }
+        assert_eq!(runtime.block_on(index.lookup("doc5")).unwrap(), None);
+        assert_eq!(runtime.block_on(index.lookup("missing")).unwrap(), None);
+
+        for terms in [
+            &["common", "word1"][..],
+            &["word2", "word3"],
+            &["word1", "missing"],
+            &[],
+        ] {
+            let found = runtime.block_on(index.intersect(terms)).unwrap();
+            assert_eq!(found, searcher.intersect(terms).unwrap());
+        }
+        assert!(index.inner.readers.lock().unwrap().len() <= IDLE_READERS);
+    }
+
+    #[test]
+    fn lookups_read_the_file_opened_even_once_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = crate::fixtures::write_index(dir.path(), &["cat dog"]);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let index = runtime
+            .block_on(AsyncIndexFileSearcher::open(&file))
+            .unwrap();
+        let cat = runtime.block_on(index.lookup("cat")).unwrap();
+
+        let other = tempfile::tempdir().unwrap();
+        let replacement = crate::fixtures::write_index(
+            other.path(),
+            &["a much longer text about birds, and nothing about cats"],
+        );
+        std::fs::rename(replacement, &file).unwrap();
+        // More lookups at once than readers were made so far.
+        let lookups = runtime.block_on(async {
+            let tasks: Vec<_> = (0..8)
+                .map(|_| {
+                    let index = index.clone();
+                    tokio::spawn(async move { index.lookup("cat").await })
+                })
+                .collect();
+            let mut lookups = vec![];
+            for task in tasks {
+                lookups.push(task.await.unwrap().unwrap());
+            }
+            lookups
+        });
+        assert!(cat.is_some());
+        assert!(lookups.iter().all(|found| *found == cat));
+    }
+
+    #[test]
+    fn missing_files_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let opened = runtime
+            .block_on(AsyncIndexFileSearcher::open(dir.path().join("none")));
+        assert!(matches!(opened, Err(Error::Io(_))));
+    }
+}
@@ -0,0 +1,354 @@
+//! Workloads for measuring how fast the crate is.
+//!
+//! `index_tools bench` and the criterion benches in `benches/` time the same
+//! four things on a synthetic corpus, so that a change to the format or to
+//! the analyzers that slows one of them down shows: splitting text into
+//! terms, writing index files (segments), merging segments into one file,
+//! and answering queries.
+//!
+//! `corpus` makes documents of pseudo-words whose frequencies roughly follow
+//! Zipf's law, like those of the words of a natural language, so that a few
+//! terms have very long postings and most very short ones. The same seed
+//! always gives the same documents, and `queries` the same queries, so that
+//! runs compare. `run` times every workload and returns a `Measurement` of
+//! each.
+
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{Analyzer, TextAnalyzer};
+use crate::error::Result;
+use crate::header::IndexSettings;
+use crate::index::InMemoryIndex;
+use crate::merge::merge_index_files;
+use crate::search::Searcher;
+use crate::tmp::TmpDir;
+use crate::write::write_index_to_tmp_file;
+use crate::HASH_LENGTH;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Number of distinct words of `corpus`.
+pub const VOCABULARY: usize = 20_000;
+
+/// Syllables the words of `corpus` are made of.
+const SYLLABLES: [&str; 16] = [
+    "ka", "to", "ri", "me", "su", "na", "lo", "pe", "zi", "du", "ba", "xo",
+    "fe", "gu", "hi", "ja",
+];
+
+/// The size of the corpus and how many times each workload runs.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Number of documents of the corpus.
+    pub docs: usize,
+    /// Number of words of each document.
+    pub words: usize,
+    /// Number of segments the corpus is written in, then merged from.
+    pub segments: usize,
+    /// Number of times tokenizing, writing and merging are timed.
+    pub iterations: usize,
+    /// Number of queries timed.
+    pub queries: usize,
+    /// Seed of the corpus and the queries.
+    pub seed: u64,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        BenchOptions {
+            docs: 2_000,
+            words: 500,
+            segments: 4,
+            iterations: 5,
+            queries: 1_000,
+            seed: 1,
+        }
+    }
+}
+
+/// How long a workload took each time it ran.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// The workload: "tokenize", "write", "merge" or "query".
+    pub name: &'static str,
+    /// Time of every run, in the order they ran. A run of "query" is one
+    /// query.
+    pub times: Vec<Duration>,
+    /// Bytes each run went through: of text for "tokenize", of index files
+    /// written or merged for "write" and "merge", none for "query".
+    pub bytes: u64,
+}
+
+impl Measurement {
+    /// The time that `p` percent of the runs took at most, e.g. 50 for the
+    /// median.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let mut times = self.times.clone();
+        times.sort_unstable();
+        let last = times.len().saturating_sub(1);
+        let i = ((p / 100.0) * last as f64).round() as usize;
+        times.get(i.min(last)).copied().unwrap_or_default()
+    }
+
+    /// The median time of the runs.
+    pub fn median(&self) -> Duration {
+        self.percentile(50.0)
+    }
+
+    /// Bytes per second at the median time, for workloads that go through
+    /// bytes.
+    pub fn throughput(&self) -> Option<f64> {
+        let seconds = self.median().as_secs_f64();
+        (self.bytes > 0 && seconds > 0.0).then(|| self.bytes as f64 / seconds)
+    }
+}
+
+/// A small, fast random number generator (SplitMix64), so that the corpus
+/// is the same on every platform and with every version of the crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in [0, 1).
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// The rank of a word in the vocabulary, small ranks being the most
+    /// frequent: the probability of rank `r` is about proportional to
+    /// `1 / r`.
+    fn rank(&mut self) -> usize {
+        let rank = (VOCABULARY as f64).powf(self.unit()) as usize;
+        rank.clamp(1, VOCABULARY) - 1
+    }
+}
+
+/// The word of `rank` in the vocabulary, made of one syllable per digit of
+/// `rank + 1` in base 16.
+fn word(rank: usize) -> String {
+    let mut n = rank + 1;
+    let mut word = String::new();
+    while n > 0 {
+        word.push_str(SYLLABLES[n % SYLLABLES.len()]);
+        n /= SYLLABLES.len();
+    }
+    word
+}
+
+/// `docs` documents of `words` words each, picked among `VOCABULARY` words
+/// with frequencies that follow Zipf's law, in lines of 12 words.
+pub fn corpus(docs: usize, words: usize, seed: u64) -> Vec<String> {
+    let mut rng = Rng(seed);
+    (0..docs)
+        .map(|_| {
+            let mut text = String::new();
+            for i in 0..words {
+                if i > 0 {
+                    text.push(if i % 12 == 0 { '\n' } else { ' ' });
+                }
+                text.push_str(&word(rng.rank()));
+            }
+            text
+        })
+        .collect()
+}
+
+/// `count` queries of the words of `corpus`: single words, two words that
+/// must both occur, either of two words, and two-word phrases, in turn.
+pub fn queries(count: usize, seed: u64) -> Vec<String> {
+    let mut rng = Rng(seed.wrapping_add(1));
+    (0..count)
+        .map(|i| {
+            let (a, b) = (word(rng.rank()), word(rng.rank()));
+            match i % 4 {
+                0 => a,
+                1 => format!("{} {}", a, b),
+                2 => format!("{} OR {}", a, b),
+                _ => format!("\"{} {}\"", a, b),
+            }
+        })
+        .collect()
+}
+
+/// An index of `texts`, analyzed with `analyzer`. The hash of each document
+/// is its number in `texts`.
+pub fn index(texts: &[String], analyzer: &dyn Analyzer) -> InMemoryIndex {
+    index_from(texts, 0, analyzer)
+}
+
+/// Like `index`, with documents numbered from `first`.
+fn index_from(
+    texts: &[String],
+    first: usize,
+    analyzer: &dyn Analyzer,
+) -> InMemoryIndex {
+    let mut index = InMemoryIndex::new();
+    for (i, text) in texts.iter().enumerate() {
+        let mut hash = [0; HASH_LENGTH];
+        hash[..8].copy_from_slice(&((first + i) as u64).to_be_bytes());
+        index.add_document_with(&hash, text.clone(), analyzer);
+    }
+    index
+}
+
+/// Write `texts` in `segments` index files in `dir`, with `settings`, and
+/// return their paths.
+pub fn write_segments(
+    texts: &[String],
+    segments: usize,
+    dir: &Path,
+    settings: &IndexSettings,
+) -> Result<Vec<PathBuf>> {
+    let analyzer = settings.analyzer.build()?;
+    let mut tmp_dir = TmpDir::new(dir);
+    let per_segment = texts.len().div_ceil(segments.max(1)).max(1);
+    let mut files = vec![];
+    for (i, chunk) in texts.chunks(per_segment).enumerate() {
+        let segment = index_from(chunk, i * per_segment, &analyzer);
+        files.push(write_index_to_tmp_file(segment, &mut tmp_dir, settings)?);
+    }
+    Ok(files)
+}
+
+/// Time every workload on a corpus made as `options` say, writing the files
+/// they need in `dir`, an existing directory, and removing them after.
+pub fn run(options: &BenchOptions, dir: &Path) -> Result<Vec<Measurement>> {
+    let texts = corpus(options.docs, options.words, options.seed);
+    let settings = IndexSettings::default();
+    let iterations = options.iterations.max(1);
+    let mut measurements = vec![];
+
+    let analyzer = TextAnalyzer::default();
+    let text_bytes = texts.iter().map(|t| t.len() as u64).sum();
+    let tokenize = time(iterations, || {
+        for text in &texts {
+            black_box(analyzer.analyze(text));
+        }
+        Ok(())
+    })?;
+    measurements.push(Measurement {
+        name: "tokenize",
+        times: tokenize,
+        bytes: text_bytes,
+    });
+
+    let mut segments = vec![];
+    let mut times = vec![];
+    for _ in 0..iterations {
+        remove(&segments)?;
+        let start = Instant::now();
+        segments = write_segments(&texts, options.segments, dir, &settings)?;
+        times.push(start.elapsed());
+    }
+    measurements.push(Measurement {
+        name: "write",
+        times,
+        bytes: file_sizes(&segments)?,
+    });
+
+    let merged_dir = dir.join("merged");
+    fs::create_dir_all(&merged_dir)?;
+    let mut merged = PathBuf::new();
+    let times = time(iterations, || {
+        merged = merge_index_files(&segments, &merged_dir)?;
+        Ok(())
+    })?;
+    measurements.push(Measurement {
+        name: "merge",
+        times,
+        bytes: file_sizes(&segments)?,
+    });
+    remove(&segments)?;
+
+    let searcher = Searcher::open(&merged)?;
+    let mut times = vec![];
+    for query in queries(options.queries, options.seed) {
+        let start = Instant::now();
+        black_box(searcher.query(&query)?);
+        times.push(start.elapsed());
+    }
+    measurements.push(Measurement {
+        name: "query",
+        times,
+        bytes: 0,
+    });
+    fs::remove_dir_all(&merged_dir)?;
+    Ok(measurements)
+}
+
+/// The time of each of `iterations` runs of `work`.
+fn time(
+    iterations: usize,
+    mut work: impl FnMut() -> Result<()>,
+) -> Result<Vec<Duration>> {
+    (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            work()?;
+            Ok(start.elapsed())
+        })
+        .collect()
+}
+
+fn file_sizes(files: &[PathBuf]) -> io::Result<u64> {
+    files.iter().map(|f| Ok(fs::metadata(f)?.len())).sum()
+}
+
+fn remove(files: &[PathBuf]) -> io::Result<()> {
+    files.iter().try_for_each(fs::remove_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corpora_are_the_same_for_the_same_seed() {
+        let texts = corpus(20, 30, 7);
+        assert_eq!(texts, corpus(20, 30, 7));
+        assert_ne!(texts, corpus(20, 30, 8));
+        assert!(texts.iter().all(|t| t.split_whitespace().count() == 30));
+        // Frequent words are frequent.
+        let first = word(0);
+        let count = texts
+            .iter()
+            .flat_map(|t| t.split_whitespace())
+            .filter(|w| *w == first)
+            .count();
+        assert!(count > 20, "{} occurs {} times", first, count);
+    }
+
+    #[test]
+    fn every_workload_is_measured() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = BenchOptions {
+            docs: 40,
+            words: 50,
+            segments: 3,
+            iterations: 2,
+            queries: 8,
+            seed: 3,
+        };
+        let measurements = run(&options, dir.path()).unwrap();
+        let names: Vec<_> = measurements.iter().map(|m| m.name).collect();
+        assert_eq!(names, ["tokenize", "write", "merge", "query"]);
+        assert_eq!(measurements[0].times.len(), 2);
+        assert_eq!(measurements[3].times.len(), 8);
+        assert!(measurements[1].throughput().is_some());
+        // Everything written was removed.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
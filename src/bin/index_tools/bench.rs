@@ -0,0 +1,90 @@
+use clap::Args;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::bench::{self, BenchOptions, Measurement};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Time tokenizing, writing segments, merging them and answering queries on
+/// a synthetic corpus, the same every time for the same options, to compare
+/// builds or machines. `cargo bench` runs the same workloads with criterion.
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Number of documents of the corpus.
+    #[clap(long, default_value_t = BenchOptions::default().docs)]
+    docs: usize,
+    /// Number of words of each document.
+    #[clap(long, default_value_t = BenchOptions::default().words)]
+    words: usize,
+    /// Number of segments the corpus is written in, then merged from.
+    #[clap(long, default_value_t = BenchOptions::default().segments)]
+    segments: usize,
+    /// Number of times tokenizing, writing and merging are timed.
+    #[clap(long, default_value_t = BenchOptions::default().iterations)]
+    iterations: usize,
+    /// Number of queries timed.
+    #[clap(long, default_value_t = BenchOptions::default().queries)]
+    queries: usize,
+    /// Seed of the corpus and the queries.
+    #[clap(long, default_value_t = BenchOptions::default().seed)]
+    seed: u64,
+    /// Directory to write the index files in, on the disk to measure. By
+    /// default, the temporary directory of the system.
+    #[clap(long)]
+    dir: Option<PathBuf>,
+}
+
+pub fn run(args: BenchArgs) -> io::Result<()> {
+    let options = BenchOptions {
+        docs: args.docs,
+        words: args.words,
+        segments: args.segments,
+        iterations: args.iterations,
+        queries: args.queries,
+        seed: args.seed,
+    };
+    let dir = args
+        .dir
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("index_tools-bench-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    println!(
+        "{} documents of {} words, in {} segments",
+        options.docs, options.words, options.segments
+    );
+    let measurements = bench::run(&options, &dir);
+    fs::remove_dir_all(&dir)?;
+    for m in measurements? {
+        print_measurement(&m);
+    }
+    Ok(())
+}
+
+fn print_measurement(m: &Measurement) {
+    match m.throughput() {
+        Some(throughput) => println!(
+            "{:<10} {:>10} median {:>10} fastest {:>8.1} MB/s",
+            m.name,
+            duration(m.median()),
+            duration(m.percentile(0.0)),
+            throughput / 1e6
+        ),
+        None => println!(
+            "{:<10} {:>10} median {:>10} p99 ({} runs)",
+            m.name,
+            duration(m.median()),
+            duration(m.percentile(99.0)),
+            m.times.len()
+        ),
+    }
+}
+
+fn duration(d: Duration) -> String {
+    format!("{:.1?}", d)
+}
@@ -0,0 +1,156 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::prelude::*;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The results of the last queries run, by query, so that asking for
+/// another page of a search doesn't run it again. The results are those of
+/// one generation of a searcher (see `reload`); those of earlier ones are
+/// dropped as a newer one is followed.
+pub struct QueryCache {
+    /// Number of queries to keep results of; the oldest go first.
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// The generation of the searcher the results are of.
+    generation: u64,
+    results: HashMap<String, Arc<SearchResults>>,
+    /// The queries of `results`, oldest first.
+    order: VecDeque<String>,
+}
+
+impl QueryCache {
+    /// A cache of the results of `capacity` queries, none if 0.
+    pub fn new(capacity: usize) -> QueryCache {
+        QueryCache {
+            capacity,
+            entries: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep results of the searcher of `generation` from now on, dropping
+    /// those of earlier ones.
+    pub fn follow(&self, generation: u64) {
+        let mut entries = self.lock();
+        if entries.generation != generation {
+            *entries = Entries {
+                generation,
+                ..Entries::default()
+            };
+        }
+    }
+
+    /// The results of `query` on the searcher of `generation`, kept or
+    /// returned by `run`, which they are kept from unless another searcher
+    /// was followed meanwhile.
+    pub fn get_or_run<E>(
+        &self,
+        generation: u64,
+        query: &str,
+        run: impl FnOnce() -> Result<SearchResults, E>,
+    ) -> Result<Arc<SearchResults>, E> {
+        let kept = {
+            let entries = self.lock();
+            let results = entries.results.get(query);
+            results
+                .filter(|_| entries.generation == generation)
+                .cloned()
+        };
+        if let Some(results) = kept {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(results);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let results = Arc::new(run()?);
+        let mut entries = self.lock();
+        // Another thread may have run the same query meanwhile, or followed
+        // a newer searcher, which these results are stale for.
+        if self.capacity > 0
+            && entries.generation == generation
+            && !entries.results.contains_key(query)
+        {
+            if entries.order.len() >= self.capacity {
+                let oldest = entries.order.pop_front().unwrap();
+                entries.results.remove(&oldest);
+            }
+            entries.results.insert(query.to_string(), results.clone());
+            entries.order.push_back(query.to_string());
+        }
+        Ok(results)
+    }
+
+    /// Number of queries answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of queries run.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        // Entries are inserted whole, or not at all.
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(results: &mut usize) -> Result<SearchResults, ()> {
+        *results += 1;
+        Ok(SearchResults::default())
+    }
+
+    #[test]
+    fn results_are_kept_for_their_generation_only() {
+        let cache = QueryCache::new(2);
+        let mut runs = 0;
+        cache.get_or_run(0, "cat", || run(&mut runs)).unwrap();
+        cache.get_or_run(0, "cat", || run(&mut runs)).unwrap();
+        assert_eq!((runs, cache.hits(), cache.misses()), (1, 1, 1));
+
+        // Loaded again while the query runs on the earlier searcher.
+        cache
+            .get_or_run(0, "dog", || {
+                cache.follow(1);
+                run(&mut runs)
+            })
+            .unwrap();
+        cache.get_or_run(1, "dog", || run(&mut runs)).unwrap();
+        cache.get_or_run(1, "cat", || run(&mut runs)).unwrap();
+        assert_eq!(runs, 4);
+        // Nor are the results of the newer searcher handed to requests
+        // still on the earlier one.
+        cache.get_or_run(0, "cat", || run(&mut runs)).unwrap();
+        cache.get_or_run(1, "cat", || run(&mut runs)).unwrap();
+        assert_eq!((runs, cache.hits()), (5, 2));
+    }
+
+    #[test]
+    fn the_oldest_results_go_first() {
+        let cache = QueryCache::new(2);
+        let mut runs = 0;
+        for query in ["a", "b", "c", "b", "a"] {
+            cache.get_or_run(0, query, || run(&mut runs)).unwrap();
+        }
+        assert_eq!(runs, 4);
+        assert!(QueryCache::new(0)
+            .get_or_run(0, "a", || run(&mut runs))
+            .is_ok());
+    }
+}
@@ -0,0 +1,125 @@
+//! `index_tools.toml`: the options of `index`, kept in a file.
+//!
+//! Options that stay the same from one run to the next, like the files to
+//! index, the analyzer settings or the memory budget, can be written down
+//! once instead of given on every command line. `index` reads
+//! `index_tools.toml` in the current directory, if there is one, or the
+//! file given with `--config`. Its keys are the long names of the options,
+//! with underscores, and `inputs` for the files to index:
+//!
+//! ```toml
+//! inputs = ["docs", "notes"]
+//! exclude = ["drafts/**"]
+//! output = "index"
+//! memory = "2G"
+//! threads = 4
+//! compress = "zstd"
+//! stop_words = ["english"]
+//! stem = "english"
+//! ```
+//!
+//! Options given on the command line win over those of the file. Relative
+//! paths in the file are relative to the directory it is in.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The file read when `--config` isn't given, if it exists.
+pub const FILE_NAME: &str = "index_tools.toml";
+
+/// The options of `index` set in a configuration file, by the name of the
+/// option they stand for.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct IndexConfig {
+    /// Where the configuration was read from.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// The files and directories to index.
+    pub inputs: Option<Vec<String>>,
+    pub output: Option<PathBuf>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub gitignore: Option<bool>,
+    pub threads: Option<u16>,
+    pub io_threads: Option<u16>,
+    pub read_ahead: Option<usize>,
+    /// A size like "256M".
+    pub stream_larger_than: Option<String>,
+    /// A size like "64M".
+    pub max_file_size: Option<String>,
+    pub max_tokens: Option<u32>,
+    /// "skip" or "truncate".
+    pub oversized: Option<String>,
+    /// A size like "512M".
+    pub memory: Option<String>,
+    pub merge_fanin: Option<u16>,
+    pub dedup: Option<bool>,
+    pub stem: Option<String>,
+    pub stop_words: Option<Vec<String>>,
+    pub stop_words_file: Option<PathBuf>,
+    pub detect_language: Option<bool>,
+    pub unicode_words: Option<bool>,
+    pub ngram: Option<u8>,
+    pub preserve_case: Option<bool>,
+    pub routes: Option<PathBuf>,
+    pub skip_numbers: Option<bool>,
+    pub min_word_len: Option<usize>,
+    pub max_word_len: Option<usize>,
+    /// A codec name, as for `--compress`.
+    pub compress: Option<String>,
+    /// An identity name, as for `--doc-id`.
+    pub doc_id: Option<String>,
+    pub bloom_filter: Option<bool>,
+    pub omit_positions: Option<bool>,
+    pub byte_offsets: Option<bool>,
+    pub line_numbers: Option<bool>,
+    pub encrypt: Option<bool>,
+}
+
+impl IndexConfig {
+    /// Read the configuration at `path`, or else in `FILE_NAME` if there is
+    /// such a file in the current directory.
+    pub fn load(path: Option<&Path>) -> io::Result<Option<IndexConfig>> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(FILE_NAME).is_file() => Path::new(FILE_NAME),
+            None => return Ok(None),
+        };
+        let annotate =
+            |e: &dyn ToString| format!("{}: {}", path.display(), e.to_string());
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), annotate(&e)))?;
+        let mut config: IndexConfig = toml::from_str(&text).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidInput, annotate(&e))
+        })?;
+        config.path = path.to_path_buf();
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for input in config.inputs.iter_mut().flatten() {
+            *input = dir.join(&input).to_string_lossy().into_owned();
+        }
+        let paths = [
+            &mut config.output,
+            &mut config.stop_words_file,
+            &mut config.routes,
+        ];
+        for path in paths.into_iter().flatten() {
+            *path = dir.join(&path);
+        }
+        Ok(Some(config))
+    }
+
+    /// An error about the value of `key` in this configuration.
+    pub fn invalid(&self, key: &str, message: String) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}: {}", self.path.display(), key, message),
+        )
+    }
+}
@@ -0,0 +1,215 @@
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::prelude::*;
+
+use crate::cache::QueryCache;
+use crate::serve::{self, Failure, Page};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Answer searches sent as JSON, one per line on stdin, with one line of
+/// JSON each on stdout, so that an editor plugin can keep a process running
+/// with the index loaded.
+///
+/// A request is an object like `{"id": 1, "method": "search", "params":
+/// {"q": "rust AND async", "top": 5}}`. The `search` method takes the
+/// parameters of `GET /search` of `serve`: `q`, then `top` or `limit`,
+/// `page` or `offset`, `cursor`, and `facets` as a list of names; the
+/// `stats` method takes none. The response repeats the `id`, any JSON value,
+/// with either a `result`, what `serve` would answer, or an `error` with a
+/// `message`, and the `position` of a query syntax error. Responses come in
+/// the order of the requests. The daemon exits at the end of its input.
+#[derive(Default, Args, Debug)]
+pub struct DaemonArgs {
+    /// Path to index.dat file, to an index directory (see `index
+    /// --segments`), to the directory of a sharded index (see `index
+    /// --shards`), or to a pack (see `pack`).
+    #[clap(short, long, default_value = "index.dat")]
+    index_file: String,
+    /// Number of hits per page, unless the request asks for another one
+    /// with `top`.
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Failure>,
+}
+
+/// The parameters of the `search` method.
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+    top: Option<usize>,
+    limit: Option<usize>,
+    page: Option<usize>,
+    offset: Option<usize>,
+    cursor: Option<String>,
+    #[serde(default)]
+    facets: Vec<String>,
+}
+
+pub fn run(args: DaemonArgs) -> io::Result<()> {
+    let searcher = crate::search::open(Path::new(&args.index_file))?
+        .with_facets(&Facet::ALL);
+    answer_lines(&searcher, io::stdin().lock(), io::stdout().lock(), args.top)
+}
+
+/// Answer the requests read from `input`, a line each, on `out`.
+fn answer_lines(
+    searcher: &MultiSearcher,
+    input: impl BufRead,
+    mut out: impl Write,
+    top: usize,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => Response {
+                id: request.id.clone(),
+                ..answer(searcher, &request, top)
+            },
+            // Without a readable id, the error goes with none.
+            Err(e) => Response {
+                id: Value::Null,
+                result: None,
+                error: Some(Failure::bad_request(e.to_string())),
+            },
+        };
+        serde_json::to_writer(&mut out, &response)?;
+        writeln!(out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// The response to `request`, but for its id.
+fn answer(searcher: &MultiSearcher, request: &Request, top: usize) -> Response {
+    let result = match request.method.as_str() {
+        "search" => serde_json::from_value(request.params.clone())
+            .map_err(|e| Failure::bad_request(e.to_string()))
+            .and_then(|params| search(searcher, params, top)),
+        "stats" => serde_json::to_value(serve::stats(searcher))
+            .map_err(|e| Failure::bad_request(e.to_string())),
+        method => {
+            Err(Failure::bad_request(format!("unknown method {}", method)))
+        }
+    };
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(failure) => (None, Some(failure)),
+    };
+    Response {
+        id: Value::Null,
+        result,
+        error,
+    }
+}
+
+fn search(
+    searcher: &MultiSearcher,
+    params: SearchParams,
+    top: usize,
+) -> Result<Value, Failure> {
+    let limit = params.limit.or(params.top).unwrap_or(top);
+    // An offset takes precedence over a page number.
+    let offset = match params.offset {
+        Some(offset) => offset,
+        None => serve::page_offset(params.page.unwrap_or(1), limit)?,
+    };
+    let facets = params
+        .facets
+        .iter()
+        .map(|name| serve::facet(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let page = Page {
+        offset,
+        limit,
+        cursor: params.cursor,
+        facets,
+    };
+    // Unlike `serve`, the daemon keeps no results; its client can.
+    serve::search(searcher, 0, &params.q, &page, &QueryCache::new(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_index;
+    use serde_json::json;
+
+    /// A request of the `search` method.
+    fn search_request(id: u32, params: Value) -> Value {
+        json!({"id": id, "method": "search", "params": params})
+    }
+
+    #[test]
+    fn every_request_line_is_answered_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_file =
+            write_index(dir.path(), &["the cat", "a cat", "a dog"]);
+        let searcher = crate::search::open(&index_file).unwrap();
+        let requests = [
+            search_request(1, json!({"q": "cat", "top": 1})),
+            json!({"id": "two", "method": "stats"}),
+            search_request(3, json!({"q": "a", "page": 2, "top": 1})),
+            search_request(4, json!({"q": "(cat"})),
+            search_request(5, json!({"q": "cat", "page": 0})),
+            json!({"id": 6, "method": "index"}),
+        ];
+        let mut lines: Vec<String> =
+            requests.iter().map(Value::to_string).collect();
+        lines.insert(1, String::new());
+        lines.push("not json".to_string());
+        let input = lines.join("\n");
+        let mut out = vec![];
+        answer_lines(&searcher, input.as_bytes(), &mut out, 10).unwrap();
+        let responses: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let ids: Vec<String> =
+            responses.iter().map(|r| r["id"].to_string()).collect();
+        assert_eq!(ids, ["1", "\"two\"", "3", "4", "5", "6", "null"]);
+
+        let cat = &responses[0]["result"];
+        assert_eq!((&cat["total"], &cat["page"]), (&json!(2), &json!(1)));
+        assert_eq!(cat["hits"].as_array().unwrap().len(), 1);
+        assert!(cat["next"].is_string());
+        assert_eq!(responses[1]["result"]["documents"], 3);
+        let second = &responses[2]["result"];
+        assert_eq!(
+            (&second["offset"], &second["page"]),
+            (&json!(1), &json!(2))
+        );
+        assert_eq!(responses[3]["error"]["position"], 0);
+        for response in &responses[3..] {
+            assert!(response.get("result").is_none(), "{}", response);
+            assert!(response["error"]["message"].is_string(), "{}", response);
+        }
+    }
+}
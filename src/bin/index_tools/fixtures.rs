@@ -0,0 +1,41 @@
+//! Index files of a few short texts, shared by the tests of the commands.
+//!
+//! The document of text `i` has the hash `[i; 32]` and the path `i.txt`, so
+//! tests can name documents by number.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::index::Doc;
+use index_tools::prelude::*;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The document numbered `n`.
+pub fn doc(n: u8) -> Doc {
+    Doc::new(&[n; 32])
+}
+
+/// Write an index of `texts` to `index.dat` in `dir`, replacing the one
+/// there, and return its path.
+pub fn write_index(dir: &Path, texts: &[&str]) -> PathBuf {
+    let mut index = InMemoryIndex::new();
+    for (i, text) in texts.iter().enumerate() {
+        let hash = doc(i as u8).hash;
+        let mut one =
+            InMemoryIndex::from_single_document(&hash, text.to_string());
+        one.docs
+            .iter_mut()
+            .for_each(|(_, info)| info.path = format!("{}.txt", i));
+        index.merge(one);
+    }
+    let settings = IndexSettings::default();
+    let written =
+        write_index_to_tmp_file(index, &mut TmpDir::new(dir), &settings)
+            .unwrap();
+    let path = dir.join("index.dat");
+    fs::rename(written, &path).unwrap();
+    path
+}
@@ -0,0 +1,357 @@
+use clap::Args;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::{RwLock, RwLockReadGuard};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::indexer::Document;
+use index_tools::prelude::*;
+use index_tools::Error;
+
+use crate::cache::QueryCache;
+use crate::serve::{self, Failure, Page};
+
+use proto::health_response::Status as Health;
+use proto::index_tools_server::{IndexTools, IndexToolsServer};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The messages and service of proto/index_tools.proto.
+mod proto {
+    tonic::include_proto!("index_tools");
+}
+
+/// Serve searches over an index through gRPC, with the service of
+/// proto/index_tools.proto, for clients generated from it in any language.
+///
+/// `Search` and `Stats` answer what `GET /search` and `GET /stats` of
+/// `serve` do. Given an index directory (see `index --segments`),
+/// `IndexDocument` indexes a document sent with the request into a segment
+/// of its own, with the settings of the index; searches see it at once.
+/// `Health` tells whether the server is up.
+#[derive(Default, Args, Debug)]
+pub struct GrpcArgs {
+    /// Path to index.dat file, to an index directory (see `index
+    /// --segments`), to the directory of a sharded index (see `index
+    /// --shards`), or to a pack (see `pack`).
+    #[clap(short, long, default_value = "index.dat")]
+    index_file: String,
+    /// Address to listen on.
+    #[clap(short, long, default_value = "127.0.0.1:50051")]
+    addr: String,
+    /// Number of hits per page, unless the request asks for another number.
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+}
+
+struct Service {
+    index_file: PathBuf,
+    top: usize,
+    /// Loaded again after each document added.
+    searcher: RwLock<MultiSearcher>,
+}
+
+pub fn run(args: GrpcArgs) -> io::Result<()> {
+    let index_file = PathBuf::from(&args.index_file);
+    let service = Service {
+        searcher: RwLock::new(open(&index_file)?),
+        index_file,
+        top: args.top,
+    };
+    let Some(addr) = args.addr.to_socket_addrs()?.next() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no address", args.addr),
+        ));
+    };
+    println!("Listening on {}", addr);
+    tokio::runtime::Runtime::new()?.block_on(async {
+        Server::builder()
+            .add_service(IndexToolsServer::new(service))
+            .serve(addr)
+            .await
+            .map_err(io::Error::other)
+    })
+}
+
+fn open(index_file: &Path) -> io::Result<MultiSearcher> {
+    Ok(crate::search::open(index_file)?.with_facets(&Facet::ALL))
+}
+
+#[tonic::async_trait]
+impl IndexTools for Service {
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        let request = request.into_inner();
+        let page = Page {
+            offset: request.offset as usize,
+            limit: request.limit.map_or(self.top, |limit| limit as usize),
+            cursor: request.cursor,
+            facets: request
+                .facets
+                .iter()
+                .map(|name| serve::facet(name))
+                .collect::<Result<_, _>>()
+                .map_err(status)?,
+        };
+        let searcher = self.searcher();
+        // Adding documents changes the results; none are kept.
+        let cache = QueryCache::new(0);
+        let (results, offset) =
+            serve::results(&searcher, 0, &request.query, &page, &cache)
+                .map_err(status)?;
+        let limit = page.limit;
+        let hits = results
+            .range(offset, limit)
+            .iter()
+            .map(|hit| proto::Hit {
+                path: searcher.doc_info(&hit.doc).map(|d| d.path.clone()),
+                hash: hit.doc.to_hex(),
+                score: hit.score,
+            })
+            .collect();
+        let facets = results
+            .facets()
+            .iter()
+            .filter(|(facet, _)| page.facets.contains(facet))
+            .map(|(facet, values)| proto::FacetValues {
+                facet: facet.name().to_string(),
+                values: values
+                    .iter()
+                    .map(|(value, count)| proto::FacetValue {
+                        value: value.clone(),
+                        count: *count as u64,
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(Response::new(proto::SearchResponse {
+            total: results.len() as u64,
+            offset: offset as u64,
+            page: (offset.checked_div(limit).unwrap_or(0) + 1) as u64,
+            hits,
+            facets,
+            next: results
+                .cursor_after(offset, limit)
+                .map(|cursor| cursor.to_string()),
+            query: request.query,
+        }))
+    }
+
+    async fn index_document(
+        &self,
+        request: Request<proto::IndexDocumentRequest>,
+    ) -> Result<Response<proto::IndexDocumentResponse>, Status> {
+        let request = request.into_inner();
+        // Writing the segment blocks; other requests go on on other threads.
+        tokio::task::block_in_place(|| self.index_document(request))
+            .map(Response::new)
+            .map_err(error)
+    }
+
+    async fn stats(
+        &self,
+        _: Request<proto::StatsRequest>,
+    ) -> Result<Response<proto::StatsResponse>, Status> {
+        let stats = serve::stats(&self.searcher());
+        Ok(Response::new(proto::StatsResponse {
+            documents: stats.documents as u64,
+            terms: stats.terms as u64,
+            words: stats.words as u64,
+            avg_doc_len: stats.avg_doc_len,
+            avg_field_lens: stats
+                .avg_field_lens
+                .into_iter()
+                .map(|(name, len)| (name.to_string(), len))
+                .collect(),
+        }))
+    }
+
+    async fn health(
+        &self,
+        _: Request<proto::HealthRequest>,
+    ) -> Result<Response<proto::HealthResponse>, Status> {
+        // The index is loaded before the server starts.
+        Ok(Response::new(proto::HealthResponse {
+            status: Health::Serving.into(),
+        }))
+    }
+}
+
+impl Service {
+    fn searcher(&self) -> RwLockReadGuard<'_, MultiSearcher> {
+        // A panicked request leaves the last searcher loaded in place.
+        self.searcher.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Index the document of `request` into the index directory, replacing
+    /// the one with the same id, and load the index again.
+    fn index_document(
+        &self,
+        request: proto::IndexDocumentRequest,
+    ) -> index_tools::Result<proto::IndexDocumentResponse> {
+        if !IndexDir::is_index_dir(&self.index_file) {
+            return Err(Error::Unsupported(
+                "documents can only be added to an index directory".into(),
+            ));
+        }
+        let index_dir = IndexDir::open(&self.index_file)?;
+        let settings = index_dir.settings();
+        settings.identity.check_available()?;
+        let path = PathBuf::from(request.path);
+        let document = Document {
+            doc: settings.identity.doc(&path, request.text.as_bytes()),
+            size: request.text.len() as u64,
+            text: request.text,
+            title: request.title,
+            path,
+            mtime: request.mtime,
+            language: None,
+            duplicates: vec![],
+            streamed: false,
+            truncated: None,
+        };
+        let analyzers = LanguageAnalyzers::new(&settings.analyzer)?;
+        let analyzers = analyzers.for_path(&document.path);
+        let language = analyzers.detect(&document.text);
+        let analyzer = analyzers.get(language.as_deref());
+        let doc = document.doc.clone();
+        let mut index = InMemoryIndex::new();
+        index.record_byte_offsets(settings.byte_offsets);
+        index.record_line_numbers(settings.line_numbers);
+        Document {
+            language,
+            ..document
+        }
+        .add_to(&mut index, analyzer)?;
+
+        let replaced = index_dir.delete(slice::from_ref(&doc))? > 0;
+        index_dir.add_index(index)?;
+        let searcher = index_dir.searcher()?;
+        *self.searcher.write().unwrap_or_else(|e| e.into_inner()) =
+            searcher.with_facets(&Facet::ALL);
+        Ok(proto::IndexDocumentResponse {
+            hash: doc.to_hex(),
+            replaced,
+        })
+    }
+}
+
+/// The gRPC status of a request that failed as it would over HTTP.
+fn status(failure: Failure) -> Status {
+    match failure.status {
+        400 => Status::invalid_argument(failure.message),
+        _ => Status::internal(failure.message),
+    }
+}
+
+/// The gRPC status of a request that failed with `e`.
+fn error(e: Error) -> Status {
+    match e {
+        Error::Locked(_) => Status::unavailable(e.to_string()),
+        Error::Unsupported(_) | Error::Incompatible(_) | Error::Key(_) => {
+            Status::failed_precondition(e.to_string())
+        }
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_index;
+    use tonic::Code;
+
+    /// The service of `index_file`, loaded.
+    fn service(index_file: &Path) -> Service {
+        Service {
+            index_file: index_file.to_path_buf(),
+            top: 10,
+            searcher: RwLock::new(open(index_file).unwrap()),
+        }
+    }
+
+    /// A request to index `text`, as if read from `path`.
+    fn document(
+        path: &str,
+        text: &str,
+    ) -> Request<proto::IndexDocumentRequest> {
+        Request::new(proto::IndexDocumentRequest {
+            path: path.to_string(),
+            text: text.to_string(),
+            title: None,
+            mtime: 0,
+        })
+    }
+
+    /// A search for `query`, `limit` hits at a time, with their extensions.
+    fn query(query: &str, limit: u32) -> Request<proto::SearchRequest> {
+        Request::new(proto::SearchRequest {
+            query: query.to_string(),
+            limit: Some(limit),
+            facets: vec!["extension".to_string()],
+            ..proto::SearchRequest::default()
+        })
+    }
+
+    #[test]
+    fn documents_indexed_are_searched_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        let service = service(dir.path());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let health = service.health(Request::new(proto::HealthRequest {}));
+            let health = health.await.unwrap().into_inner();
+            assert_eq!(health.status(), Health::Serving);
+            for (path, text) in [("a.txt", "the cat"), ("b.md", "a cat")] {
+                let added =
+                    IndexTools::index_document(&service, document(path, text));
+                assert!(!added.await.unwrap().into_inner().replaced);
+            }
+            let again = IndexTools::index_document(
+                &service,
+                document("a.txt", "the cat"),
+            );
+            assert!(again.await.unwrap().into_inner().replaced);
+
+            let found = service.search(query("cat", 1)).await.unwrap();
+            let found = found.into_inner();
+            assert_eq!((found.total, found.page, found.hits.len()), (2, 1, 1));
+            assert_eq!(found.facets[0].facet, "extension");
+            let next = Request::new(proto::SearchRequest {
+                cursor: found.next,
+                ..query("cat", 1).into_inner()
+            });
+            let next = service.search(next).await.unwrap().into_inner();
+            assert_eq!((next.offset, next.next), (1, None));
+            assert_ne!(next.hits[0].path, found.hits[0].path);
+            let stats = service.stats(Request::new(proto::StatsRequest {}));
+            assert_eq!(stats.await.unwrap().into_inner().documents, 2);
+
+            let error = service.search(query("(cat", 1)).await.unwrap_err();
+            assert_eq!(error.code(), Code::InvalidArgument);
+        });
+    }
+
+    #[test]
+    fn index_files_take_no_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = service(&write_index(dir.path(), &["the cat"]));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let error = runtime
+            .block_on(IndexTools::index_document(
+                &service,
+                document("a.txt", "a dog"),
+            ))
+            .unwrap_err();
+        assert_eq!(error.code(), Code::FailedPrecondition);
+    }
+}
@@ -0,0 +1,1014 @@
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::Duration;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::analyzer::TokenizerKind;
+use index_tools::archive;
+use index_tools::index::Doc;
+use index_tools::indexer::{Document, DEFAULT_READ_AHEAD};
+use index_tools::prelude::*;
+use index_tools::routing;
+use index_tools::stopwords;
+
+use crate::config::{IndexConfig, FILE_NAME};
+use crate::progress::ProgressBar;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Generate an index with `indexer`, or a new segment of `index_dir`,
+/// showing a progress bar, then list the files that were skipped.
+fn build(indexer: Indexer, index_dir: Option<&IndexDir>) -> io::Result<()> {
+    let progress = Arc::new(ProgressBar::new());
+    let indexer = indexer.reporter(Arc::clone(&progress));
+    let result = match index_dir {
+        Some(index_dir) => index_dir.build(indexer),
+        None => indexer.run(),
+    };
+    let skipped = progress.finish();
+    println!("Wrote {}", result?.display());
+
+    if !skipped.is_empty() {
+        println!("Skipped {} files:", skipped.len());
+        for (path, reason) in skipped.iter() {
+            println!("\t{}: {}", path.display(), reason);
+        }
+    }
+    Ok(())
+}
+
+/// Mark documents as deleted in the index in `output_dir`, sharded or not,
+/// or in the index directory `output_dir`.
+///
+/// `filenames` are hashed the way the index identifies documents, so they
+/// must still exist; documents whose file is already gone can be removed by
+/// hash with `hashes`.
+fn remove_documents(
+    filenames: Vec<String>,
+    filter: &FileFilter,
+    hashes: Vec<String>,
+    output_dir: &Path,
+) -> io::Result<()> {
+    let index_dir = IndexDir::is_index_dir(output_dir)
+        .then(|| IndexDir::open(output_dir))
+        .transpose()?;
+    let sharded = ShardedIndex::is_sharded(output_dir)
+        .then(|| ShardedIndex::open(output_dir))
+        .transpose()?;
+    let index_file = output_dir.join("index.dat");
+    let identity = match (&index_dir, &sharded) {
+        (Some(index_dir), _) => index_dir.settings().identity,
+        (None, Some(sharded)) => sharded.settings().identity,
+        (None, None) => IndexSettings::load(&index_file)?.identity,
+    };
+
+    let mut docs = vec![];
+    let extractors = Extractors::default();
+    for filename in filter.expand(&filenames)? {
+        if archive::is_archive(&filename) {
+            // Members that couldn't be read as text were never indexed.
+            for member in archive::members(&filename)? {
+                if let Ok(document) =
+                    Document::from_member(member, &extractors, identity)
+                {
+                    docs.push(document.doc);
+                }
+            }
+        } else {
+            let document =
+                Document::read_with(&filename, &extractors, identity)?;
+            docs.push(document.doc);
+        }
+    }
+    for hash in hashes {
+        let doc = Doc::from_hex(&hash).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a valid document hash: {}", hash),
+            )
+        })?;
+        if doc.hash.len() != identity.hash_len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a {} document hash", hash, identity.name()),
+            ));
+        }
+        docs.push(doc);
+    }
+
+    if let Some(index_dir) = index_dir {
+        let deleted = index_dir.delete(&docs)?;
+        println!("{} documents marked as deleted", deleted);
+        return Ok(());
+    }
+    if let Some(sharded) = sharded {
+        let deleted = sharded.delete(&docs)?;
+        println!("{} documents marked as deleted", deleted);
+        return Ok(());
+    }
+    let mut tombstones = Tombstones::load(&index_file)?;
+    for doc in docs {
+        tombstones.add(doc);
+    }
+    tombstones.save(&index_file)?;
+    println!("{} documents marked as deleted", tombstones.len());
+    Ok(())
+}
+
+/// Build the index for `filenames`, then keep it up to date as files are
+/// created, modified or deleted, until the process is killed.
+///
+/// Changed documents are indexed into a new segment that is merged into the
+/// existing index; the previous version of a modified or deleted document is
+/// hidden with a tombstone. Changed files are checked against the include and
+/// exclude patterns of `filter`, but not against `.gitignore` files. Archives
+/// are indexed once, but changes to them are not picked up.
+fn watch(
+    filenames: Vec<String>,
+    filter: &FileFilter,
+    output_dir: PathBuf,
+    settings: &IndexSettings,
+) -> io::Result<()> {
+    let index_file = output_dir.join("index.dat");
+    let documents = filter.expand(&filenames)?;
+
+    // Remember which document each path currently holds, so that we know
+    // what to delete when the file changes.
+    let extractors = Extractors::default().routed(&settings.analyzer.routes)?;
+    let mut known = HashMap::new();
+    for path in &documents {
+        match Document::read_with(path, &extractors, settings.identity) {
+            Ok(document) => {
+                known.insert(document.path, document.doc);
+            }
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let analyzers = LanguageAnalyzers::new(&settings.analyzer)?;
+    Indexer::with_inputs(documents)
+        .output(&output_dir)
+        .threads(1)
+        .settings(settings.clone())
+        .run()?;
+    let _ = fs::remove_file(Tombstones::sidecar_path(&index_file));
+
+    let (tx, rx) = channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).map_err(io::Error::other)?;
+    for name in &filenames {
+        watcher
+            .watch(Path::new(name), RecursiveMode::Recursive)
+            .map_err(io::Error::other)?;
+    }
+    // Events carry absolute paths; the patterns apply to paths relative to
+    // the watched directories.
+    let roots: Vec<PathBuf> = filenames
+        .iter()
+        .map(fs::canonicalize)
+        .collect::<io::Result<_>>()?;
+    println!("Watching for changes...");
+
+    while let Ok(event) = rx.recv() {
+        // Editors often produce bursts of events for a single save, so
+        // collect everything that arrives in a short window.
+        let mut changed = HashSet::new();
+        let mut next = Some(event);
+        while let Some(event) = next {
+            let event = event.map_err(io::Error::other)?;
+            if !matches!(event.kind, EventKind::Access(_)) {
+                changed.extend(event.paths.into_iter().filter(|path| {
+                    roots.iter().any(|root| match path.strip_prefix(root) {
+                        Ok(rel) => {
+                            rel.as_os_str().is_empty() || filter.is_match(rel)
+                        }
+                        Err(_) => false,
+                    })
+                }));
+            }
+            next = rx.recv_timeout(Duration::from_millis(200)).ok();
+        }
+
+        update_index(&changed, &mut known, &index_file, &analyzers, settings)?;
+    }
+    Ok(())
+}
+
+/// Apply a batch of changed paths to the index.
+fn update_index(
+    changed: &HashSet<PathBuf>,
+    known: &mut HashMap<PathBuf, Doc>,
+    index_file: &Path,
+    analyzers: &LanguageAnalyzers,
+    settings: &IndexSettings,
+) -> io::Result<()> {
+    let output_dir = index_file.parent().unwrap_or(Path::new("."));
+    let extractors = Extractors::default().routed(&settings.analyzer.routes)?;
+    let mut tombstones = Tombstones::load(index_file)?;
+    let mut segment = InMemoryIndex::new();
+    segment.record_byte_offsets(settings.byte_offsets);
+    segment.record_line_numbers(settings.line_numbers);
+
+    for path in changed {
+        // The index lives in the output directory, which may well be one of
+        // the watched ones; never index our own files.
+        let is_ours = path.extension().is_some_and(|ext| ext == "dat")
+            || path.file_name().is_some_and(|name| {
+                name.to_string_lossy().starts_with("index.dat")
+            });
+        if is_ours || archive::is_archive(path) {
+            continue;
+        }
+        let current = match path.metadata() {
+            Ok(meta) if meta.is_file() => {
+                match Document::read_with(path, &extractors, settings.identity)
+                {
+                    Ok(document) => Some(document),
+                    // A file that turned binary is no longer a document.
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        println!("Skipping {}: {}", path.display(), e);
+                        None
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            _ => None,
+        };
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let previous = known.remove(&key);
+
+        if let Some(mut document) = current {
+            let doc = document.doc.clone();
+            if previous.as_ref() == Some(&doc) {
+                known.insert(key, doc);
+                continue;
+            }
+            println!("Indexing {}", path.display());
+            if tombstones.contains(&doc) {
+                // The same content was deleted earlier; its old hits must be
+                // purged before the document can be visible again.
+                tombstones.save(index_file)?;
+                compact(index_file)?;
+                tombstones = Tombstones::default();
+            }
+            // Identical content at another path is already in the index.
+            if !known.values().any(|d| *d == doc) {
+                let analyzers = analyzers.for_path(&document.path);
+                document.language = analyzers.detect(&document.text);
+                let analyzer = analyzers.get(document.language.as_deref());
+                document.add_to(&mut segment, analyzer)?;
+            }
+            known.insert(key, doc);
+        } else {
+            println!("Removing {}", path.display());
+        }
+
+        if let Some(old) = previous {
+            if !known.values().any(|d| *d == old) {
+                tombstones.add(old);
+            }
+        }
+    }
+
+    tombstones.save(index_file)?;
+    if !segment.is_empty() {
+        let mut merge = FileMerge::with_settings(output_dir, settings.clone());
+        merge.add_file(index_file.to_path_buf())?;
+        merge.add_index(segment);
+        merge.finish()?;
+    }
+    Ok(())
+}
+
+/// Make an inverted index for searching documents.
+#[derive(Default, Args, Debug)]
+pub struct IndexArgs {
+    /// Names of files/directories to index.
+    /// Directories are walked recursively; hidden files
+    /// and directories are skipped.
+    filenames: Vec<String>,
+    /// Read the options not given on the command line from this TOML file
+    /// (by default, index_tools.toml in the current directory, if there is
+    /// one). Its keys are the long names of the options, with underscores,
+    /// and `inputs` for the files to index.
+    #[clap(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Directory of the index, index.dat, or the index directory.
+    #[clap(short, long, default_value = ".")]
+    output: PathBuf,
+    /// Add the documents to an index directory, created if needed, as a new
+    /// segment instead of writing a whole new index.dat, replacing those
+    /// indexed before from the same files. Implied when the output directory
+    /// already is an index directory.
+    #[clap(long, conflicts_with_all = ["resume", "watch"])]
+    segments: bool,
+    /// Split the index into this many files by term, index.0.dat,
+    /// index.1.dat, ..., listed in shards.json, so that they can be loaded
+    /// in parallel. Searches take the output directory.
+    #[clap(
+        long,
+        conflicts_with_all = ["segments", "watch"],
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    shards: Option<u16>,
+    /// Only index files under the directories whose relative path matches
+    /// one of these globs, e.g. "*.md". May be given several times.
+    #[clap(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip files and directories whose relative path matches one of these
+    /// globs, e.g. "target/**". May be given several times.
+    #[clap(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Also skip the files that `.gitignore` files ignore.
+    #[clap(long)]
+    gitignore: bool,
+    /// Do all the work on a single thread.
+    #[clap(short, long)]
+    single_threaded: bool,
+    /// Number of threads reading and tokenizing documents (by default, one
+    /// per CPU).
+    #[clap(
+        long,
+        conflicts_with = "single_threaded",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    threads: Option<u16>,
+    /// Number of threads reading, decoding and hashing files, apart from
+    /// those tokenizing them (by default, none). Helps when reading is the
+    /// slow part, e.g. on a network disk.
+    #[clap(long, default_value_t = 0, conflicts_with = "single_threaded")]
+    io_threads: u16,
+    /// With --io-threads, the number of files read ahead of the threads
+    /// tokenizing them.
+    #[clap(long, default_value_t = DEFAULT_READ_AHEAD)]
+    read_ahead: usize,
+    /// Tokenize text files larger than this as they are read, e.g. "256M",
+    /// instead of loading them whole. Their language isn't detected.
+    #[clap(long, value_parser = parse_size, default_value = "256M")]
+    stream_larger_than: usize,
+    /// Leave out, or truncate (see --oversized), the files larger than
+    /// this, e.g. "64M", and the members of archives too.
+    #[clap(long, value_parser = parse_size)]
+    max_file_size: Option<usize>,
+    /// Leave out, or truncate (see --oversized), the documents of more
+    /// words than this.
+    #[clap(long)]
+    max_tokens: Option<u32>,
+    /// What becomes of the documents larger than --max-file-size or
+    /// --max-tokens: "skip" leaves them out, "truncate" indexes their
+    /// beginning and records that they were truncated.
+    #[clap(long, value_parser = parse_policy, default_value = "skip")]
+    oversized: LimitPolicy,
+    /// Memory that in-memory indexes may use before they are written to
+    /// temporary files, e.g. "512M" or "2G".
+    #[clap(long, value_parser = parse_size, default_value = "512M")]
+    memory: usize,
+    /// Merge at most this many temporary index files at a time. Each of them
+    /// is kept open during the merge, so keep it below the open file limit.
+    #[clap(
+        long,
+        default_value_t = 128,
+        value_parser = clap::value_parser!(u16).range(2..)
+    )]
+    merge_fanin: u16,
+    /// Files/directories whose documents should be removed from the existing
+    /// index instead of indexing anything.
+    #[clap(long, num_args = 1.., value_delimiter = ' ')]
+    remove: Vec<String>,
+    /// Hex hashes of documents to remove from the existing index.
+    #[clap(long, num_args = 1.., value_delimiter = ' ')]
+    remove_hash: Vec<String>,
+    /// Rewrite the existing index without the removed documents. In an
+    /// index directory, merge segments as the merge policy says.
+    #[clap(long)]
+    compact: bool,
+    #[clap(flatten)]
+    merge_policy: MergePolicyArgs,
+    /// Resume an indexing run into the output directory that was
+    /// interrupted, keeping the temporary index files it recorded in
+    /// `index.manifest`, instead of starting over.
+    #[clap(long)]
+    resume: bool,
+    /// Build the same index.dat, byte for byte, every time the same files
+    /// are indexed, whatever the number of threads.
+    #[clap(long)]
+    deterministic: bool,
+    /// Index files with the same contents only once, recording the paths
+    /// of the copies with the file that is indexed. Every file is then read
+    /// twice.
+    #[clap(long)]
+    dedup: bool,
+    /// After indexing, keep watching the given files/directories and update
+    /// the index as they change.
+    #[clap(short, long)]
+    watch: bool,
+    /// Reduce words to their stem with the Snowball stemmer for this
+    /// language (e.g. "english"), or "auto" for the language of each
+    /// document. Requires the `stemming` feature.
+    #[clap(long)]
+    stem: Option<String>,
+    /// Drop the built-in stop words of these languages (comma separated,
+    /// e.g. "english,french"); "auto" stands for the language of each
+    /// document.
+    #[clap(long, value_delimiter = ',')]
+    stop_words: Vec<String>,
+    /// Detect the language of each document, so that searches can be
+    /// restricted to it with `lang:`. Implied by "auto" in --stem or
+    /// --stop-words. Requires the `language` feature.
+    #[clap(long)]
+    detect_language: bool,
+    /// Drop the words listed in this file (one per line, `#` comments).
+    #[clap(long)]
+    stop_words_file: Option<PathBuf>,
+    /// Split words at Unicode word boundaries (UAX #29) instead of at every
+    /// non-alphanumeric character.
+    #[clap(long, conflicts_with = "ngram")]
+    unicode_words: bool,
+    /// Break Chinese/Japanese/Korean text into character n-grams of this
+    /// size (2 or 3 work best).
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..))]
+    ngram: Option<u8>,
+    /// Also index every word in its original case, so that searches can
+    /// be case-sensitive (`search --case-sensitive`).
+    #[clap(long)]
+    preserve_case: bool,
+    /// Analyze or extract the files matching globs differently, as set in
+    /// this TOML file of `[[route]]` tables, e.g. `glob = "*.rs"` and
+    /// `tokenizer = "code"`. Their options replace the ones given here.
+    #[clap(long)]
+    routes: Option<PathBuf>,
+    /// Don't index numbers, words made of digits only.
+    #[clap(long)]
+    skip_numbers: bool,
+    /// Don't index words shorter than this many characters.
+    #[clap(long)]
+    min_word_len: Option<usize>,
+    /// Don't index words longer than this many characters, like hashes
+    /// and encoded data.
+    #[clap(long)]
+    max_word_len: Option<usize>,
+    /// Compress the postings of each term with this codec: none, zstd
+    /// (needs the `zstd` feature) or lz4 (needs the `lz4` feature).
+    #[clap(long, value_parser = parse_codec, default_value = "none")]
+    compress: Codec,
+    /// How to identify documents: sha256 (the hash of their contents), xxh3
+    /// (a faster, shorter hash of their contents), blake3 (a hash of their
+    /// contents as strong as sha256 and faster; needs the `blake3` feature)
+    /// or path (a hash of their path, so that a file keeps its id when it
+    /// changes and files aren't hashed at all).
+    #[clap(long, value_parser = parse_identity, default_value = "sha256")]
+    doc_id: DocIdentity,
+    /// End each index file with a bloom filter of its terms, so that
+    /// searches of an index directory skip the segments without a term.
+    #[clap(long)]
+    bloom_filter: bool,
+    /// Only record which documents contain each word and how many times,
+    /// not where: the index gets much smaller, but phrase and proximity
+    /// queries only check that all their words occur, and search results
+    /// have no snippets.
+    #[clap(long)]
+    omit_positions: bool,
+    /// Also record where each word starts in its document, in bytes, so
+    /// that `index_search --format json` can tell editors where to jump.
+    #[clap(long, conflicts_with = "omit_positions")]
+    byte_offsets: bool,
+    /// Also record the lines each word occurs on, so that `search --format
+    /// grep` can print `path:line:` like grep -n.
+    #[clap(long, conflicts_with = "omit_positions")]
+    line_numbers: bool,
+    /// Encrypt the index files, with the 32-byte key given in hex by
+    /// INDEX_TOOLS_KEY, or in the file named by INDEX_TOOLS_KEY_FILE. The
+    /// same key is needed to search them.
+    #[clap(long)]
+    encrypt: bool,
+}
+
+impl IndexArgs {
+    /// Take the options that weren't given on the command line, as
+    /// `matches` tell, from `config`.
+    fn with_config(
+        mut self,
+        config: IndexConfig,
+        matches: &ArgMatches,
+    ) -> io::Result<IndexArgs> {
+        let given = |id: &str| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        };
+        let IndexConfig {
+            inputs,
+            output,
+            include,
+            exclude,
+            gitignore,
+            threads,
+            io_threads,
+            read_ahead,
+            stream_larger_than,
+            max_file_size,
+            max_tokens,
+            oversized,
+            memory,
+            merge_fanin,
+            dedup,
+            stem,
+            stop_words,
+            stop_words_file,
+            detect_language,
+            unicode_words,
+            ngram,
+            preserve_case,
+            routes,
+            skip_numbers,
+            min_word_len,
+            max_word_len,
+            compress,
+            doc_id,
+            bloom_filter,
+            omit_positions,
+            byte_offsets,
+            line_numbers,
+            encrypt,
+            ..
+        } = &config;
+        set(
+            inputs,
+            given("filenames"),
+            &mut self.filenames,
+            Clone::clone,
+        );
+        set(output, given("output"), &mut self.output, Clone::clone);
+        set(include, given("include"), &mut self.include, Clone::clone);
+        set(exclude, given("exclude"), &mut self.exclude, Clone::clone);
+        set(gitignore, given("gitignore"), &mut self.gitignore, |&b| b);
+        let threads_given = given("threads") || given("single_threaded");
+        set(threads, threads_given, &mut self.threads, |&n| Some(n));
+        set(
+            io_threads,
+            given("io_threads"),
+            &mut self.io_threads,
+            |&n| n,
+        );
+        set(
+            read_ahead,
+            given("read_ahead"),
+            &mut self.read_ahead,
+            |&n| n,
+        );
+        if let (Some(size), false) =
+            (stream_larger_than, given("stream_larger_than"))
+        {
+            self.stream_larger_than = parse_size(size)
+                .map_err(|e| config.invalid("stream_larger_than", e))?;
+        }
+        if let (Some(size), false) = (max_file_size, given("max_file_size")) {
+            self.max_file_size = Some(
+                parse_size(size)
+                    .map_err(|e| config.invalid("max_file_size", e))?,
+            );
+        }
+        set(
+            max_tokens,
+            given("max_tokens"),
+            &mut self.max_tokens,
+            |&n| Some(n),
+        );
+        if let (Some(name), false) = (oversized, given("oversized")) {
+            self.oversized = parse_policy(name)
+                .map_err(|e| config.invalid("oversized", e))?;
+        }
+        if let (Some(memory), false) = (memory, given("memory")) {
+            self.memory =
+                parse_size(memory).map_err(|e| config.invalid("memory", e))?;
+        }
+        set(
+            merge_fanin,
+            given("merge_fanin"),
+            &mut self.merge_fanin,
+            |&n| n,
+        );
+        set(dedup, given("dedup"), &mut self.dedup, |&b| b);
+        set(stem, given("stem"), &mut self.stem, |s| Some(s.clone()));
+        set(
+            stop_words,
+            given("stop_words"),
+            &mut self.stop_words,
+            Clone::clone,
+        );
+        let file_given = given("stop_words_file");
+        set(
+            stop_words_file,
+            file_given,
+            &mut self.stop_words_file,
+            |p| Some(p.clone()),
+        );
+        set(
+            detect_language,
+            given("detect_language"),
+            &mut self.detect_language,
+            |&b| b,
+        );
+        // A tokenizer given on the command line replaces the one of the
+        // file, whichever option it is given with.
+        let tokenizer_given = given("unicode_words") || given("ngram");
+        set(
+            unicode_words,
+            tokenizer_given,
+            &mut self.unicode_words,
+            |&b| b,
+        );
+        set(ngram, tokenizer_given, &mut self.ngram, |&n| Some(n));
+        set(
+            preserve_case,
+            given("preserve_case"),
+            &mut self.preserve_case,
+            |&b| b,
+        );
+        set(routes, given("routes"), &mut self.routes, |p| {
+            Some(p.clone())
+        });
+        set(
+            skip_numbers,
+            given("skip_numbers"),
+            &mut self.skip_numbers,
+            |&b| b,
+        );
+        set(
+            min_word_len,
+            given("min_word_len"),
+            &mut self.min_word_len,
+            |&n| Some(n),
+        );
+        set(
+            max_word_len,
+            given("max_word_len"),
+            &mut self.max_word_len,
+            |&n| Some(n),
+        );
+        if let (Some(name), false) = (compress, given("compress")) {
+            self.compress =
+                parse_codec(name).map_err(|e| config.invalid("compress", e))?;
+        }
+        if let (Some(name), false) = (doc_id, given("doc_id")) {
+            self.doc_id = parse_identity(name)
+                .map_err(|e| config.invalid("doc_id", e))?;
+        }
+        set(
+            bloom_filter,
+            given("bloom_filter"),
+            &mut self.bloom_filter,
+            |&b| b,
+        );
+        set(
+            omit_positions,
+            given("omit_positions"),
+            &mut self.omit_positions,
+            |&b| b,
+        );
+        set(
+            byte_offsets,
+            given("byte_offsets"),
+            &mut self.byte_offsets,
+            |&b| b,
+        );
+        set(
+            line_numbers,
+            given("line_numbers"),
+            &mut self.line_numbers,
+            |&b| b,
+        );
+        set(encrypt, given("encrypt"), &mut self.encrypt, |&b| b);
+        Ok(self)
+    }
+}
+
+/// Set `option` to `value`, converted, unless it was given on the command
+/// line.
+fn set<T, U>(
+    value: &Option<T>,
+    given: bool,
+    option: &mut U,
+    convert: impl FnOnce(&T) -> U,
+) {
+    if let (Some(value), false) = (value, given) {
+        *option = convert(value);
+    }
+}
+
+/// How the segments of an index directory are merged, after new ones are
+/// added and with --compact. See `TieredPolicy`.
+#[derive(Args, Debug)]
+pub struct MergePolicyArgs {
+    /// Merge the segments of a similar size once there are this many.
+    #[clap(
+        long,
+        default_value_t = TieredPolicy::default().segments_per_tier as u16,
+        value_parser = clap::value_parser!(u16).range(2..)
+    )]
+    segments_per_tier: u16,
+    /// How much larger the segments of each size tier are than those of
+    /// the tier below.
+    #[clap(long, default_value_t = TieredPolicy::default().size_ratio)]
+    size_ratio: f64,
+    /// Merge the smallest segments whenever there are more than this many.
+    #[clap(long, default_value_t = TieredPolicy::default().max_segments)]
+    max_segments: usize,
+    /// Don't merge segments into one larger than this, e.g. "5G".
+    #[clap(long, value_parser = parse_size, default_value = "5G")]
+    max_segment_size: usize,
+}
+
+impl Default for MergePolicyArgs {
+    fn default() -> Self {
+        let policy = TieredPolicy::default();
+        MergePolicyArgs {
+            segments_per_tier: policy.segments_per_tier as u16,
+            size_ratio: policy.size_ratio,
+            max_segments: policy.max_segments,
+            max_segment_size: policy.max_segment_bytes as usize,
+        }
+    }
+}
+
+impl From<&MergePolicyArgs> for MergePolicy {
+    fn from(args: &MergePolicyArgs) -> MergePolicy {
+        MergePolicy::Tiered(TieredPolicy {
+            segments_per_tier: args.segments_per_tier as usize,
+            size_ratio: args.size_ratio,
+            max_segments: args.max_segments,
+            max_segment_bytes: args.max_segment_size as u64,
+            ..TieredPolicy::default()
+        })
+    }
+}
+
+/// Merge the segments of `index_dir` as its merge policy says, and tell how
+/// many merges that took.
+fn merge_segments(index_dir: &IndexDir) -> io::Result<()> {
+    let merges = index_dir.compact()?;
+    if merges > 0 {
+        println!("Merged segments {} times", merges);
+    }
+    Ok(())
+}
+
+fn parse_codec(name: &str) -> Result<Codec, String> {
+    let codec = Codec::from_name(name)
+        .ok_or_else(|| format!("unknown codec {}", name))?;
+    codec.check_available().map_err(|e| e.to_string())?;
+    Ok(codec)
+}
+
+fn parse_identity(name: &str) -> Result<DocIdentity, String> {
+    let identity = DocIdentity::from_name(name)
+        .ok_or_else(|| format!("unknown document identity {}", name))?;
+    identity.check_available().map_err(|e| e.to_string())?;
+    Ok(identity)
+}
+
+fn parse_policy(name: &str) -> Result<LimitPolicy, String> {
+    LimitPolicy::from_name(name)
+        .ok_or_else(|| format!("unknown policy {} (skip or truncate)", name))
+}
+
+/// Parse a size in bytes, with an optional K, M or G suffix (powers of
+/// 1024).
+pub fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let upper = size.to_ascii_uppercase();
+    let digits = upper.trim_end_matches(['I', 'B']);
+    let (digits, shift) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 10),
+        Some('M') => (&digits[..digits.len() - 1], 20),
+        Some('G') => (&digits[..digits.len() - 1], 30),
+        _ => (digits, 0),
+    };
+    digits
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("not a valid size: {}", size))
+}
+
+pub fn run(args: IndexArgs, matches: &ArgMatches) -> io::Result<()> {
+    let args = match IndexConfig::load(args.config.as_deref())? {
+        Some(config) => args.with_config(config, matches)?,
+        None => args,
+    };
+    let nothing_else =
+        args.remove.is_empty() && args.remove_hash.is_empty() && !args.compact;
+    if args.filenames.is_empty() && nothing_else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no files to index: give them on the command line, or as \
+                 `inputs` in {}",
+                FILE_NAME
+            ),
+        ));
+    }
+    let output_dir = args.output;
+    let custom_stop_words = match &args.stop_words_file {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(text) => stopwords::parse_list(&text),
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", path.display(), e),
+                ))
+            }
+        },
+        None => vec![],
+    };
+    let mut analyzer = AnalyzerConfig {
+        tokenizer: match (args.unicode_words, args.ngram) {
+            (true, _) => TokenizerKind::Unicode,
+            (false, Some(n)) => TokenizerKind::Ngram(n as usize),
+            (false, None) => TokenizerKind::Simple,
+        },
+        stop_words: args.stop_words,
+        custom_stop_words,
+        stemmer: args.stem,
+        detect_language: args.detect_language,
+        preserve_case: args.preserve_case,
+        skip_numbers: args.skip_numbers,
+        min_length: args.min_word_len,
+        max_length: args.max_word_len,
+        routes: vec![],
+    };
+    if let Some(path) = &args.routes {
+        analyzer.routes = routing::load(path, &analyzer)?;
+    }
+    let settings = IndexSettings {
+        codec: args.compress,
+        analyzer,
+        bloom_filter: args.bloom_filter,
+        omit_positions: args.omit_positions,
+        byte_offsets: args.byte_offsets,
+        line_numbers: args.line_numbers,
+        encrypted: args.encrypt,
+        identity: args.doc_id,
+    };
+    let filter = FileFilter::new(&args.include, &args.exclude)?
+        .git_ignore(args.gitignore);
+    let segments = args.segments || IndexDir::is_index_dir(&output_dir);
+    if !args.remove.is_empty() || !args.remove_hash.is_empty() {
+        remove_documents(args.remove, &filter, args.remove_hash, &output_dir)
+    } else if args.watch {
+        if segments {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--watch doesn't update index directories",
+            ));
+        }
+        if !settings.identity.is_by_contents() {
+            // Tombstones couldn't tell a changed file from its earlier
+            // version.
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--watch needs documents identified by their contents",
+            ));
+        }
+        watch(args.filenames, &filter, output_dir, &settings)
+    } else if args.compact && segments {
+        let index_dir = IndexDir::open(&output_dir)?
+            .with_merge_policy((&args.merge_policy).into());
+        merge_segments(&index_dir)
+    } else if args.compact && ShardedIndex::is_sharded(&output_dir) {
+        let purged = ShardedIndex::open(&output_dir)?.compact()?;
+        println!("{} deleted documents purged", purged);
+        Ok(())
+    } else if args.compact {
+        let purged = compact(output_dir.join("index.dat"))?;
+        println!("{} deleted documents purged", purged);
+        Ok(())
+    } else {
+        fs::create_dir_all(&output_dir).map_err(|e| {
+            io::Error::new(e.kind(), format!("{}: {}", output_dir.display(), e))
+        })?;
+        // The settings are saved in the index header, so that searches
+        // analyze queries the same way.
+        let mut indexer = Indexer::with_inputs(args.filenames)
+            .filter(filter)
+            .output(&output_dir)
+            .settings(settings.clone())
+            .io_threads(args.io_threads as usize)
+            .read_ahead(args.read_ahead)
+            .stream_larger_than(args.stream_larger_than as u64)
+            .limits(DocLimits {
+                max_file_size: args.max_file_size.map(|size| size as u64),
+                max_tokens: args.max_tokens,
+                policy: args.oversized,
+            })
+            .memory_budget(args.memory)
+            .merge_fan_in(args.merge_fanin as usize)
+            .resume(args.resume)
+            .deterministic(args.deterministic)
+            .dedup(args.dedup);
+        if args.single_threaded {
+            indexer = indexer.threads(1);
+        } else if let Some(threads) = args.threads {
+            indexer = indexer.threads(threads as usize);
+        }
+        if let Some(shards) = args.shards {
+            indexer = indexer.shards(shards as usize);
+        }
+        if segments {
+            let index_dir = IndexDir::create(&output_dir, settings)?
+                .with_merge_policy((&args.merge_policy).into());
+            build(indexer, Some(&index_dir))?;
+            return merge_segments(&index_dir);
+        }
+        build(indexer, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    #[derive(Parser)]
+    struct Command {
+        #[clap(flatten)]
+        index: IndexArgs,
+    }
+
+    /// The options of `index` given `args`, with the configuration `text`
+    /// written to `dir`.
+    fn configured(
+        dir: &Path,
+        text: &str,
+        args: &[&str],
+    ) -> io::Result<IndexArgs> {
+        let config = dir.join(FILE_NAME);
+        fs::write(&config, text).unwrap();
+        let config = config.display().to_string();
+        let args = ["index", "--config", &config]
+            .into_iter()
+            .chain(args.iter().copied());
+        let matches = Command::command().try_get_matches_from(args).unwrap();
+        let index = Command::from_arg_matches(&matches).unwrap().index;
+        let config = IndexConfig::load(index.config.as_deref())?.unwrap();
+        index.with_config(config, &matches)
+    }
+
+    #[test]
+    fn the_command_line_wins_over_the_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let text = "inputs = [\"docs\"]\n\
+                    output = \"out\"\n\
+                    memory = \"2G\"\n\
+                    threads = 4\n\
+                    stem = \"english\"\n";
+        let args = configured(dir, text, &[]).unwrap();
+        assert_eq!(args.filenames, [dir.join("docs").display().to_string()]);
+        assert_eq!(args.output, dir.join("out"));
+        assert_eq!((args.memory, args.threads), (2 << 30, Some(4)));
+        assert_eq!(args.stem.as_deref(), Some("english"));
+
+        let args =
+            configured(dir, text, &["other", "--threads", "2", "-o", "."])
+                .unwrap();
+        assert_eq!(args.filenames, ["other"]);
+        assert_eq!(args.output, Path::new("."));
+        assert_eq!((args.memory, args.threads), (2 << 30, Some(2)));
+        let args = configured(dir, text, &["--single-threaded"]).unwrap();
+        assert_eq!(args.threads, None);
+
+        for (text, key) in [
+            ("memory = \"lots\"", "memory"),
+            ("compress = \"rar\"", "compress"),
+            ("colour = \"red\"", "colour"),
+            ("threads = \"four\"", "threads"),
+        ] {
+            let e = configured(dir, text, &[]).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", text);
+            assert!(e.to_string().contains(key), "{}", e);
+        }
+    }
+
+    #[test]
+    fn missing_output_directories_are_made() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = dir.path().join("cat.txt");
+        fs::write(&doc, "the cat sat").unwrap();
+        let output = dir.path().join("new").join("out");
+        let args = ["index", "-o", output.to_str().unwrap()];
+        let matches = Command::command()
+            .try_get_matches_from(args.into_iter().chain(doc.to_str()))
+            .unwrap();
+        let index = Command::from_arg_matches(&matches).unwrap().index;
+        run(index, &matches).unwrap();
+        assert!(output.join("index.dat").is_file());
+    }
+}
@@ -0,0 +1,491 @@
+use clap::{
+    ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use clap_complete::Shell;
+use env_logger::Env;
+use log::LevelFilter;
+use std::io::{self, BufWriter};
+use std::iter;
+use std::path::PathBuf;
+use std::process;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::prelude::*;
+
+mod bench;
+mod cache;
+mod config;
+mod daemon;
+#[cfg(test)]
+mod fixtures;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod index;
+mod metrics;
+mod progress;
+mod reload;
+mod search;
+mod serve;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Examples shown at the end of `--help`.
+const EXAMPLES: &str = "\
+Examples:
+  index_tools index docs notes -o index --stem english
+  index_tools search -i index/index.dat 'rust AND (async OR tokio)' --top 10
+  index_tools search -i index/index.dat 'title:\"inverted index\"' --explain
+  index_tools concordance -i index/index.dat borrow
+  index_tools completions bash > ~/.local/share/bash-completion/completions/index_tools
+
+Run `index_tools help COMMAND` for the options of a command.";
+
+/// Build, search and inspect inverted indexes of text files.
+#[derive(Parser, Debug)]
+#[clap(version, about, after_long_help = EXAMPLES)]
+struct Arguments {
+    #[clap(subcommand)]
+    command: Command,
+    /// Log more of what is going on, to stderr: -v for merges, -vv for
+    /// segments and files opened, -vvv for every file. `RUST_LOG` takes
+    /// precedence.
+    #[clap(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Index files and directories, or update an existing index.
+    Index(Box<index::IndexArgs>),
+    /// Search an index.
+    Search(search::SearchArgs),
+    /// Answer searches over HTTP: `GET /search?q=QUERY[&top=N][&page=N]`
+    /// returns the hits as JSON, `GET /stats` describes the index, `GET
+    /// /metrics` reports metrics for Prometheus.
+    Serve(serve::ServeArgs),
+    /// Answer searches sent as JSON lines on stdin, one JSON line each on
+    /// stdout, for editor plugins to keep the index loaded.
+    Daemon(daemon::DaemonArgs),
+    /// Answer searches, and index documents into an index directory, over
+    /// gRPC, with the service of proto/index_tools.proto.
+    #[cfg(feature = "grpc")]
+    Grpc(grpc::GrpcArgs),
+    /// Merge index files into one, leaving them as they are.
+    Merge {
+        /// The index files to merge.
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+        /// Directory of the merged index, index.dat.
+        #[clap(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Merge all the segments of an index directory into one, or rewrite
+    /// index.dat, or its shards, without the removed documents.
+    Compact {
+        /// Directory of the index, index.dat, or the index directory.
+        #[clap(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Print statistics about an index: number of documents, terms and
+    /// postings, average document length, most frequent terms of the body,
+    /// the title and the path, and the size of each section of the file.
+    Stats {
+        /// Path to index.dat file.
+        #[clap(short, long, default_value = "index.dat")]
+        index_file: PathBuf,
+        /// Number of most frequent terms to list, for each field.
+        #[clap(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Print every occurrence of a word in the indexed documents, with the
+    /// words around it, one per line (a concordance), followed by its
+    /// document and its offset in words (`@word N`). The documents are read
+    /// again, so they must still be where they were indexed.
+    Concordance {
+        /// The word to look for, analyzed like in searches.
+        word: String,
+        /// Path to index.dat file.
+        #[clap(short, long, default_value = "index.dat")]
+        index_file: PathBuf,
+        /// Number of words shown on each side of the word.
+        #[clap(long, default_value_t = 5)]
+        context: usize,
+    },
+    /// Read a whole index file and check it for corruption.
+    Verify {
+        /// Path to index.dat file.
+        #[clap(short, long, default_value = "index.dat")]
+        index_file: PathBuf,
+    },
+    /// Rewrite an index file of an older format version in the current one,
+    /// so that it can be searched without indexing the documents again.
+    Upgrade {
+        /// The index file to upgrade.
+        old: PathBuf,
+        /// Where to write the upgraded file. It can be the old one, which is
+        /// then replaced.
+        new: PathBuf,
+    },
+    /// Print the description an index file ends with: its sections, codec,
+    /// analyzer settings and counts.
+    Inspect {
+        /// Path to index.dat file.
+        #[clap(short, long, default_value = "index.dat")]
+        index_file: PathBuf,
+        /// Print the description as JSON, the way it is stored.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Export an index as text: every term with the documents that contain
+    /// it and the word offsets, one document per line.
+    Dump {
+        /// Path to index.dat file.
+        #[clap(short, long, default_value = "index.dat")]
+        index_file: PathBuf,
+        /// Output format. Only JSON Lines keeps the settings and the
+        /// document table of the index.
+        #[clap(short, long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Time tokenizing, writing segments, merging and querying on a
+    /// synthetic corpus.
+    Bench(bench::BenchArgs),
+    /// Print the completion script of a shell, to be sourced by it or saved
+    /// where it looks for them, e.g. ~/.local/share/bash-completion/
+    /// completions/index_tools for bash or a directory of `$fpath` for zsh.
+    Completions {
+        /// The shell to complete commands in.
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+    /// Bundle an index, with its deleted documents, into a single file that
+    /// can be copied anywhere and searched read-only, like an index file.
+    #[cfg(feature = "mmap")]
+    Pack {
+        /// Directory of the index, index.dat, or the index directory, or
+        /// the directory of a sharded index.
+        #[clap(default_value = ".")]
+        index: PathBuf,
+        /// The pack to write.
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Build an index from the output of `dump`.
+    Import {
+        /// The dump to import.
+        file: PathBuf,
+        /// Format of the dump.
+        #[clap(short, long, value_enum, default_value_t)]
+        format: Format,
+        /// Directory of the index, index.dat.
+        #[clap(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Memory that postings may use before they are written to
+        /// temporary files, e.g. "512M" or "2G".
+        #[clap(long, value_parser = index::parse_size, default_value = "512M")]
+        memory: usize,
+    },
+}
+
+/// Text format of `dump` and `import`.
+#[derive(Default, Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// `term,doc_hash,offsets` rows.
+    Csv,
+}
+
+fn merge(files: Vec<PathBuf>, output: PathBuf) -> io::Result<()> {
+    let merged = merge_index_files(&files, &output)?;
+    println!("Merged {} files into {}", files.len(), merged.display());
+    Ok(())
+}
+
+fn compact_index(output: PathBuf) -> io::Result<()> {
+    if IndexDir::is_index_dir(&output) {
+        let index_dir = IndexDir::open(&output)?;
+        index_dir.compact_with(&MergePolicy::Full)?;
+        println!("{} segments left", index_dir.segments().len());
+    } else if ShardedIndex::is_sharded(&output) {
+        let purged = ShardedIndex::open(&output)?.compact()?;
+        println!("{} deleted documents purged", purged);
+    } else {
+        let purged = compact(output.join("index.dat"))?;
+        println!("{} deleted documents purged", purged);
+    }
+    Ok(())
+}
+
+fn stats(index_file: PathBuf, top: usize) -> io::Result<()> {
+    let stats = index_stats(index_file, top)?;
+    println!("Documents: {}", stats.documents);
+    if stats.deleted_documents > 0 {
+        println!("Deleted documents: {}", stats.deleted_documents);
+    }
+    println!("Terms: {}", stats.terms);
+    println!("Postings: {}", stats.postings);
+    println!("Occurrences: {}", stats.occurrences);
+    println!("Average document length: {:.1}", stats.avg_doc_len);
+    for (field, avg) in Field::NAMED.iter().zip(stats.avg_field_lens) {
+        println!("Average {} length: {:.1}", field.name(), avg);
+    }
+    println!("Codec: {}", stats.settings.codec.name());
+    println!("Document ids: {}", stats.settings.identity.name());
+    if stats.settings.omit_positions {
+        println!("Positions: omitted");
+    }
+    if stats.settings.analyzer.preserve_case {
+        println!("Case: preserved");
+    }
+
+    let fields = Field::NAMED.iter().map(|field| field.name());
+    let top_terms = iter::once(("body", &stats.top_terms))
+        .chain(fields.zip(&stats.top_field_terms));
+    for (field, terms) in top_terms.filter(|(_, terms)| !terms.is_empty()) {
+        println!("\nMost frequent {} terms:", field);
+        for t in terms {
+            println!(
+                "\t{:>8} {:>6} docs  {}",
+                t.occurrences, t.documents, t.term
+            );
+        }
+    }
+
+    let sections = &stats.sections;
+    println!("\nSize on disk:");
+    for (name, size) in [
+        ("header", sections.header),
+        ("postings", sections.postings),
+        ("document table", sections.doc_table),
+        ("table of contents", sections.table_of_contents),
+        ("bloom filter", sections.bloom_filter),
+        ("description", sections.description),
+        ("tombstones", sections.tombstones),
+        ("total", sections.total()),
+    ] {
+        println!("\t{:<18} {:>10} bytes", name, size);
+    }
+    Ok(())
+}
+
+/// Width of the left context column in `concordance`, in characters.
+const LEFT_WIDTH: usize = 40;
+
+fn print_concordance(
+    index_file: PathBuf,
+    word: String,
+    context: usize,
+) -> io::Result<()> {
+    let mut searcher = IndexFileSearcher::open(&index_file)?;
+    let routes = &searcher.settings().analyzer.routes;
+    let extractors = Extractors::default().routed(routes)?;
+    let occurrences = concordance(&mut searcher, &word, context, &extractors)?;
+    for o in &occurrences {
+        // Keep the end of long left contexts, next to the word.
+        let skip = o.left.chars().count().saturating_sub(LEFT_WIDTH);
+        let left: String = o.left.chars().skip(skip).collect();
+        println!(
+            "{:>width$}  {}  {}\t{} @word {}",
+            left,
+            o.word,
+            o.right,
+            o.path,
+            o.position,
+            width = LEFT_WIDTH
+        );
+    }
+    println!("{} occurrences", occurrences.len());
+    Ok(())
+}
+
+fn verify_file(index_file: PathBuf) -> io::Result<()> {
+    let report = verify(&index_file)?;
+    println!(
+        "{} documents, {} terms, {} postings",
+        report.documents, report.terms, report.postings
+    );
+    if report.is_ok() {
+        println!("{}: OK", index_file.display());
+        return Ok(());
+    }
+    for problem in &report.problems {
+        println!("\t{}", problem);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{}: {} problems found",
+            index_file.display(),
+            report.problems.len()
+        ),
+    ))
+}
+
+fn upgrade_file(old: PathBuf, new: PathBuf) -> io::Result<()> {
+    let from = upgrade(&old, &new)?;
+    let to = FileDescription::load(&new)?.version;
+    println!(
+        "Upgraded {} from format version {} to {} in {}",
+        old.display(),
+        from,
+        to,
+        new.display()
+    );
+    Ok(())
+}
+
+fn inspect(index_file: PathBuf, json: bool) -> io::Result<()> {
+    let description = FileDescription::load(&index_file)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&description)?);
+        return Ok(());
+    }
+    println!(
+        "Format: {} version {}",
+        description.format, description.version
+    );
+    println!("Codec: {}", description.codec);
+    println!("Document ids: {}", description.identity);
+    if description.analyzer.is_empty() {
+        println!("Analyzer: default");
+    } else {
+        println!("Analyzer:");
+        for (name, value) in &description.analyzer {
+            println!("\t{} = {}", name, value);
+        }
+    }
+    if !description.positions {
+        println!("Positions: omitted");
+    }
+    println!("Skip interval: {}", description.skip_interval);
+    let counts = &description.counts;
+    println!("Documents: {}", counts.documents);
+    println!("Terms: {}", counts.terms);
+    println!("Postings: {}", counts.postings);
+    println!("Words: {}", counts.words);
+    println!("\nSections:");
+    for section in &description.sections {
+        println!(
+            "\t{:<18} at {:>10}  {:>10} bytes",
+            section.name, section.offset, section.length
+        );
+    }
+    Ok(())
+}
+
+impl From<Format> for DumpFormat {
+    fn from(format: Format) -> DumpFormat {
+        match format {
+            Format::Jsonl => DumpFormat::JsonLines,
+            Format::Csv => DumpFormat::Csv,
+        }
+    }
+}
+
+fn dump(index_file: PathBuf, format: Format) -> io::Result<()> {
+    let out = BufWriter::new(io::stdout().lock());
+    export(index_file, format.into(), out)?;
+    Ok(())
+}
+
+fn completions(shell: Shell) -> io::Result<()> {
+    let mut command = Arguments::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+fn pack_index(index: PathBuf, output: PathBuf) -> io::Result<()> {
+    let index = if index.is_dir()
+        && !IndexDir::is_index_dir(&index)
+        && !ShardedIndex::is_sharded(&index)
+    {
+        index.join("index.dat")
+    } else {
+        index
+    };
+    pack(&index, &output)?;
+    let files = PackedIndex::open(&output)?.readers().len();
+    println!(
+        "Packed {} index files of {} into {}",
+        files,
+        index.display(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn import_dump(
+    file: PathBuf,
+    format: Format,
+    output: PathBuf,
+    memory: usize,
+) -> io::Result<()> {
+    let index_file = import(&file, format.into(), &output, memory)?;
+    println!("Imported {} into {}", file.display(), index_file.display());
+    Ok(())
+}
+
+fn main() {
+    // The matches tell the options given on the command line from those
+    // left to their default, which a configuration file overrides.
+    let matches = Arguments::command().get_matches();
+    let args =
+        Arguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let level = match args.verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::from_env(
+        Env::default().default_filter_or(level.as_str()),
+    )
+    .init();
+    let result = match args.command {
+        Command::Index(args) => {
+            let matches = matches.subcommand_matches("index").unwrap();
+            index::run(*args, matches)
+        }
+        Command::Search(args) => search::run(args),
+        Command::Serve(args) => serve::run(args),
+        Command::Daemon(args) => daemon::run(args),
+        #[cfg(feature = "grpc")]
+        Command::Grpc(args) => grpc::run(args),
+        Command::Merge { files, output } => merge(files, output),
+        Command::Compact { output } => compact_index(output),
+        Command::Stats { index_file, top } => stats(index_file, top),
+        Command::Concordance {
+            word,
+            index_file,
+            context,
+        } => print_concordance(index_file, word, context),
+        Command::Verify { index_file } => verify_file(index_file),
+        Command::Upgrade { old, new } => upgrade_file(old, new),
+        Command::Inspect { index_file, json } => inspect(index_file, json),
+        Command::Dump { index_file, format } => dump(index_file, format),
+        Command::Bench(args) => bench::run(args),
+        Command::Completions { shell } => completions(shell),
+        #[cfg(feature = "mmap")]
+        Command::Pack { index, output } => pack_index(index, output),
+        Command::Import {
+            file,
+            format,
+            output,
+            memory,
+        } => import_dump(file, format, output, memory),
+    };
+    if let Err(e) = result {
+        // Like other tools, output piped into one that stops reading, like
+        // `head`, ends quietly.
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            return;
+        }
+        println!("error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::cache::QueryCache;
+use crate::reload::Loaded;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Upper bounds of the buckets of the histogram of query latencies, in
+/// seconds.
+const BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+/// What `serve` counts of the searches it answers, for `GET /metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    errors: AtomicU64,
+    /// Number of queries answered within each bound of `BUCKETS`, but not
+    /// within the one before.
+    buckets: [AtomicU64; BUCKETS.len()],
+    /// Number of queries answered within none of `BUCKETS`.
+    slower: AtomicU64,
+    /// Time spent answering all queries, in microseconds.
+    micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Count a query answered in `elapsed`, which failed unless `ok`.
+    pub fn observe(&self, elapsed: Duration, ok: bool) {
+        if !ok {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let seconds = elapsed.as_secs_f64();
+        match BUCKETS.iter().position(|&le| seconds <= le) {
+            Some(bucket) => {
+                self.buckets[bucket].fetch_add(1, Ordering::Relaxed)
+            }
+            None => self.slower.fetch_add(1, Ordering::Relaxed),
+        };
+        let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// The metrics, in the text format of Prometheus, along with those of
+    /// `cache` and of the index `loaded` from `index_file`.
+    pub fn render(
+        &self,
+        loaded: &Loaded,
+        cache: &QueryCache,
+        index_file: &Path,
+    ) -> io::Result<String> {
+        let mut out = String::new();
+        // Every query is counted in exactly one bucket, so that the buckets,
+        // read once, add up to the count, even as queries are answered.
+        let buckets: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let queries =
+            buckets.iter().sum::<u64>() + self.slower.load(Ordering::Relaxed);
+        metric(
+            &mut out,
+            "queries_total",
+            "counter",
+            "Searches answered, failed or not.",
+            queries,
+        );
+        metric(
+            &mut out,
+            "query_errors_total",
+            "counter",
+            "Searches that failed.",
+            self.errors.load(Ordering::Relaxed),
+        );
+        let name = "index_tools_query_duration_seconds";
+        out.push_str(&format!(
+            "# HELP {} Time taken to answer searches.\n\
+             # TYPE {} histogram\n",
+            name, name
+        ));
+        let mut count = 0;
+        for (le, bucket) in BUCKETS.iter().zip(&buckets) {
+            count += bucket;
+            out.push_str(&format!(
+                "{}_bucket{{le=\"{}\"}} {}\n",
+                name, le, count
+            ));
+        }
+        out.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, queries));
+        let seconds = self.micros.load(Ordering::Relaxed) as f64 / 1e6;
+        out.push_str(&format!("{}_sum {}\n", name, seconds));
+        out.push_str(&format!("{}_count {}\n", name, queries));
+
+        let (hits, misses) = (cache.hits(), cache.misses());
+        metric(
+            &mut out,
+            "cache_hits_total",
+            "counter",
+            "Searches answered with the results of an earlier one.",
+            hits,
+        );
+        metric(
+            &mut out,
+            "cache_misses_total",
+            "counter",
+            "Searches whose query was run.",
+            misses,
+        );
+        let ratio = match hits + misses {
+            0 => 0.0,
+            all => hits as f64 / all as f64,
+        };
+        metric(
+            &mut out,
+            "cache_hit_ratio",
+            "gauge",
+            "Share of searches answered with the results of an earlier one.",
+            ratio,
+        );
+
+        metric(
+            &mut out,
+            "reloads_total",
+            "counter",
+            "Times the index was loaded again after it changed.",
+            loaded.generation,
+        );
+        let indexes = loaded.searcher.searchers();
+        let documents: HashSet<_> =
+            indexes.iter().flat_map(|s| s.index().docs.keys()).collect();
+        metric(
+            &mut out,
+            "segments",
+            "gauge",
+            "Index files open, one per segment or shard.",
+            indexes.len(),
+        );
+        metric(
+            &mut out,
+            "documents",
+            "gauge",
+            "Documents in the index.",
+            documents.len(),
+        );
+        metric(
+            &mut out,
+            "index_size_bytes",
+            "gauge",
+            "Size of the index on disk.",
+            disk_size(index_file)?,
+        );
+        Ok(out)
+    }
+}
+
+/// Write the metric `index_tools_NAME`, with its help and type.
+fn metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl Display,
+) {
+    out.push_str(&format!(
+        "# HELP index_tools_{0} {1}\n\
+         # TYPE index_tools_{0} {2}\n\
+         index_tools_{0} {3}\n",
+        name, help, kind, value
+    ));
+}
+
+/// Size of the file at `path`, or of all the files in the directory.
+fn disk_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    fs::read_dir(path)?
+        .map(|entry| disk_size(&entry?.path()))
+        .sum()
+}
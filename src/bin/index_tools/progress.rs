@@ -0,0 +1,212 @@
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::prelude::*;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Width of the bar itself, in characters.
+const WIDTH: usize = 30;
+
+/// How often the bar is redrawn, at most.
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+/// A progress bar on stderr, with an estimate of the time left from the
+/// bytes read so far. Nothing is drawn unless stderr is a terminal, but
+/// skipped files are collected either way.
+pub struct ProgressBar {
+    draw: bool,
+    state: Mutex<State>,
+}
+
+struct State {
+    start: Instant,
+    last_draw: Option<Instant>,
+    total_bytes: u64,
+    skipped: Vec<(PathBuf, String)>,
+}
+
+impl ProgressBar {
+    pub fn new() -> ProgressBar {
+        ProgressBar {
+            draw: io::stderr().is_terminal(),
+            state: Mutex::new(State {
+                start: Instant::now(),
+                last_draw: None,
+                total_bytes: 0,
+                skipped: vec![],
+            }),
+        }
+    }
+
+    /// Erase the bar, and return the files that were skipped, sorted.
+    pub fn finish(&self) -> Vec<(PathBuf, String)> {
+        self.clear();
+        let mut skipped =
+            std::mem::take(&mut self.state.lock().unwrap().skipped);
+        skipped.sort();
+        skipped
+    }
+
+    fn clear(&self) {
+        if self.draw {
+            eprint!("\r\x1b[K");
+        }
+    }
+
+    fn line(&self, text: &str) {
+        if self.draw {
+            eprintln!("\r\x1b[K{}", text);
+        }
+    }
+}
+
+impl ProgressReporter for ProgressBar {
+    fn report(&self, progress: &Progress) {
+        let mut state = self.state.lock().unwrap();
+        match progress {
+            Progress::Discovered { bytes, .. } => {
+                state.start = Instant::now();
+                state.total_bytes = *bytes;
+            }
+            Progress::Indexed {
+                done,
+                total,
+                bytes,
+                docs,
+                ..
+            } => {
+                let now = Instant::now();
+                let due = state
+                    .last_draw
+                    .is_none_or(|last| now - last >= REDRAW_EVERY);
+                if !self.draw || !(due || done == total) {
+                    return;
+                }
+                state.last_draw = Some(now);
+                let fraction = if state.total_bytes > 0 {
+                    *bytes as f64 / state.total_bytes as f64
+                } else {
+                    *done as f64 / (*total).max(1) as f64
+                };
+                let fraction = fraction.clamp(0.0, 1.0);
+                let filled = (fraction * WIDTH as f64) as usize;
+                let eta = eta((now - state.start).as_secs_f64(), fraction);
+                let bar = "=".repeat(filled) + &" ".repeat(WIDTH - filled);
+                eprint!(
+                    "\r\x1b[K[{}] {:>3}% {}/{} files, {} docs, {}/{}, ETA {}",
+                    bar,
+                    (fraction * 100.0) as u32,
+                    done,
+                    total,
+                    docs,
+                    size(*bytes),
+                    size(state.total_bytes),
+                    eta
+                );
+                let _ = io::stderr().flush();
+            }
+            Progress::Skipped { path, reason } => {
+                state.skipped.push((path.clone(), reason.clone()));
+            }
+            Progress::SegmentWritten { .. } => {}
+            Progress::Merging { segments } => {
+                self.line(&format!("Merging {} segments", segments));
+            }
+            Progress::Merged { files } => {
+                self.line(&format!("Merged {} files", files));
+            }
+        }
+    }
+}
+
+/// The time left, `elapsed` seconds after the start of work that is done
+/// up to `fraction`, if it goes on at the same pace.
+fn eta(elapsed: f64, fraction: f64) -> String {
+    if fraction > 0.0 {
+        duration(elapsed * (1.0 - fraction) / fraction)
+    } else {
+        "?".to_string()
+    }
+}
+
+/// `bytes` in KiB, MiB or GiB, like the sizes `--memory` accepts.
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+/// `seconds` as `m:ss`, or `h:mm:ss` when that's long.
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (h, m, s) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_left_follows_the_pace_so_far() {
+        let cases = [
+            (10.0, 0.5, "0:10"),
+            (30.0, 0.25, "1:30"),
+            (3600.0, 0.5, "1:00:00"),
+            (5.0, 1.0, "0:00"),
+            (5.0, 0.0, "?"),
+        ];
+        for (elapsed, fraction, expected) in cases {
+            assert_eq!(eta(elapsed, fraction), expected, "{}", fraction);
+        }
+        assert_eq!(duration(3725.4), "1:02:05");
+    }
+
+    #[test]
+    fn sizes_are_written_in_binary_units() {
+        let cases = [
+            (0, "0B"),
+            (1023, "1023B"),
+            (1024, "1.0K"),
+            (1536, "1.5K"),
+            (5 << 20, "5.0M"),
+            (3 << 40, "3072.0G"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(size(bytes), expected);
+        }
+    }
+
+    #[test]
+    fn skipped_files_are_collected_sorted() {
+        let bar = ProgressBar::new();
+        for path in ["b.bin", "a.bin"] {
+            bar.report(&Progress::Skipped {
+                path: PathBuf::from(path),
+                reason: "binary".to_string(),
+            });
+        }
+        let skipped = bar.finish();
+        let paths: Vec<_> = skipped.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("a.bin"), PathBuf::from("b.bin")]);
+        assert!(bar.finish().is_empty());
+    }
+}
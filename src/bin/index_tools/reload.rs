@@ -0,0 +1,162 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+use std::io;
+use std::path::Path;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Weak;
+use std::sync::{Arc, RwLock};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::SystemTime;
+
+#[cfg(not(target_arch = "wasm32"))]
+use log::{error, info};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::prelude::*;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The searcher of an index being served, loaded again in the background
+/// whenever the index changes. Requests hold on to the searcher they
+/// started with, so a new one takes over without failing those in flight.
+pub struct LiveSearcher {
+    current: RwLock<Arc<Loaded>>,
+}
+
+/// A searcher, as loaded at some point.
+pub struct Loaded {
+    pub searcher: MultiSearcher,
+    /// Number of times the index was loaded again before this one.
+    pub generation: u64,
+}
+
+/// What tells whether an index changed.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, PartialEq)]
+enum Version {
+    /// The generation of an index directory (see `IndexDir::generation`).
+    Generation(u64),
+    /// The modification time and size of each file of an index file and its
+    /// deletions, or of a sharded index.
+    Files(Vec<(PathBuf, SystemTime, u64)>),
+}
+
+impl LiveSearcher {
+    /// Load the index at `index_file` with `open`, and, unless `interval` is
+    /// `None`, check at that interval whether it changed, to load it again,
+    /// on a thread of its own (not on wasm32, which has none). A failure to
+    /// load it again leaves the previous searcher in place.
+    pub fn open(
+        index_file: &Path,
+        interval: Option<Duration>,
+        open: fn(&Path) -> io::Result<MultiSearcher>,
+    ) -> io::Result<Arc<LiveSearcher>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let version = version(index_file)?;
+        let live = Arc::new(LiveSearcher {
+            current: RwLock::new(Arc::new(Loaded {
+                searcher: open(index_file)?,
+                generation: 0,
+            })),
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(interval) = interval {
+            let weak = Arc::downgrade(&live);
+            let index_file = index_file.to_path_buf();
+            thread::spawn(move || {
+                watch(weak, &index_file, version, interval, open)
+            });
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = interval;
+        Ok(live)
+    }
+
+    /// The searcher loaded last.
+    pub fn get(&self) -> Arc<Loaded> {
+        // Swapping the searcher can't panic half-way.
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// The body of the thread loading the index again, until `live` is
+/// dropped.
+#[cfg(not(target_arch = "wasm32"))]
+fn watch(
+    live: Weak<LiveSearcher>,
+    index_file: &Path,
+    mut loaded: Version,
+    interval: Duration,
+    open: fn(&Path) -> io::Result<MultiSearcher>,
+) {
+    loop {
+        thread::sleep(interval);
+        let Some(live) = live.upgrade() else {
+            return;
+        };
+        // Read before loading, so that a change made while loading is seen
+        // next time.
+        let version = match version(index_file) {
+            Ok(version) if version == loaded => continue,
+            Ok(version) => version,
+            Err(e) => {
+                error!("checking {}: {}", index_file.display(), e);
+                continue;
+            }
+        };
+        match open(index_file) {
+            Ok(searcher) => {
+                let mut current =
+                    live.current.write().unwrap_or_else(|e| e.into_inner());
+                let generation = current.generation + 1;
+                *current = Arc::new(Loaded {
+                    searcher,
+                    generation,
+                });
+                info!("Reloaded {}", index_file.display());
+                loaded = version;
+            }
+            Err(e) => {
+                error!("reloading {}: {}", index_file.display(), e)
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn version(index_file: &Path) -> io::Result<Version> {
+    if IndexDir::is_index_dir(index_file) {
+        return Ok(Version::Generation(IndexDir::generation(index_file)?));
+    }
+    let mut paths = match index_file.is_dir() {
+        true => fs::read_dir(index_file)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?,
+        false => vec![
+            index_file.to_path_buf(),
+            Tombstones::sidecar_path(index_file),
+        ],
+    };
+    paths.sort();
+    let mut files = vec![];
+    for path in paths {
+        match fs::metadata(&path) {
+            Ok(metadata) => {
+                files.push((path, metadata.modified()?, metadata.len()))
+            }
+            // No deletions.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Version::Files(files))
+}
@@ -0,0 +1,781 @@
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use index_tools::index::{Doc, Offsets};
+use index_tools::prelude::*;
+use index_tools::search::DEFAULT_TITLE_BOOST;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use serde::Serialize;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Ranking function used to order results.
+#[derive(Default, Clone, Copy, Debug, ValueEnum)]
+enum Ranking {
+    #[default]
+    Bm25,
+    TfIdf,
+}
+
+/// How results are printed.
+#[derive(Default, Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    /// Readable text.
+    #[default]
+    Human,
+    /// A single JSON object, for scripts and editors.
+    Json,
+    /// A `path:line:text` line for each line of each document with a word
+    /// of the search, like `grep -n`, for editors and CI scripts. The index
+    /// must have been built with `index --line-numbers`; documents of other
+    /// indexes are printed as their path alone.
+    Grep,
+}
+
+/// Search terms in index.dat file.
+#[derive(Default, Args, Debug)]
+pub struct SearchArgs {
+    /// Terms to search in index divided by space symbol.
+    #[clap(short, long, value_parser, num_args = 1.., value_delimiter = ' ')]
+    terms: Vec<String>,
+    /// Boolean query, e.g. "rust AND (async OR tokio) NOT blocking". Prefix
+    /// a word or phrase with "title:" or "path:" to search that field;
+    /// "lang:french" matches the documents detected to be in French, and
+    /// "mtime:>2023-01-01" or "size:<1MB" those whose file was modified
+    /// after that day or is smaller than that.
+    /// "colour~1" also matches words one typo away, and "index search"~3
+    /// matches the words in any order, at most 3 extra words apart.
+    #[clap(conflicts_with = "terms")]
+    query: Option<String>,
+    /// Load the index once, then read queries from the terminal and run
+    /// them one after the other. With `--top`, results are shown a page at
+    /// a time, and `:next` shows the next page.
+    #[clap(long, conflicts_with_all = ["terms", "query"])]
+    interactive: bool,
+    /// Path to index.dat file, to an index directory (see `index
+    /// --segments`), to the directory of a sharded index (see `index
+    /// --shards`), or to a pack (see `pack`).
+    #[clap(short, long, default_value = "index.dat")]
+    index_file: String,
+    /// Print only the N best scoring documents.
+    #[clap(long)]
+    top: Option<usize>,
+    /// Print the Nth group of `--top` documents instead of the first.
+    #[clap(
+        long,
+        default_value_t = 1,
+        requires = "top",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    page: u32,
+    /// Ranking function used to order results.
+    #[clap(long, value_enum, default_value_t)]
+    ranking: Ranking,
+    /// How much more a match in a document's title counts than one in its
+    /// text (0 to ignore titles).
+    #[clap(long, default_value_t = DEFAULT_TITLE_BOOST)]
+    title_boost: f64,
+    /// Analyze the search terms as words of this language (e.g. "french"),
+    /// for indexes built with "auto" stemming or stop words. By default, the
+    /// most common language of the index.
+    #[clap(long)]
+    lang: Option<String>,
+    /// Only match words written in the same case as in the query. The index
+    /// must have been built with `index --preserve-case`.
+    #[clap(long)]
+    case_sensitive: bool,
+    /// Print excerpts of each document with the hits highlighted.
+    #[clap(long)]
+    snippets: bool,
+    /// Number of words shown on each side of a hit in snippets.
+    #[clap(long, default_value_t = 5, requires = "snippets")]
+    context: usize,
+    /// Also count the matching documents by directory, extension or
+    /// language. Can be repeated, or given several comma-separated names.
+    #[clap(long, value_parser = parse_facet, value_delimiter = ',')]
+    facet: Vec<Facet>,
+    /// Print the results as readable text, as JSON or like grep.
+    #[clap(long, value_enum, default_value_t)]
+    format: Format,
+    /// Also print how the query was parsed, or which terms the search terms
+    /// were analyzed into, and the analyzer settings the index was built
+    /// with; then, for each document, the terms it matched with the number
+    /// of their occurrences in it and of documents containing them, what
+    /// each added to its score, and the index or segment their postings
+    /// came from.
+    #[clap(long)]
+    explain: bool,
+}
+
+/// What is needed to print snippets for the results.
+struct Snippets {
+    extractors: Extractors,
+    options: SnippetOptions,
+}
+
+/// The results, as printed by `--format json`.
+#[derive(Serialize)]
+struct JsonResults<'a> {
+    /// The query or the terms, as given.
+    query: String,
+    /// Number of matching documents, including those not printed.
+    total: usize,
+    hits: Vec<JsonHit<'a>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    facets: JsonFacets<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explain: Option<Explanation>,
+}
+
+/// How a search was understood, as printed by `--explain`.
+#[derive(Serialize)]
+struct Explanation {
+    /// The parsed query, or the analyzed search terms joined with `OR`, as
+    /// displayed by `Query`. One for each index whose analyzer parses it
+    /// differently.
+    parsed: Vec<String>,
+    /// The analyzer settings of each index, if they differ, by name.
+    analyzer: Vec<BTreeMap<String, String>>,
+}
+
+/// Facet counts as printed in JSON, by facet name.
+pub type JsonFacets<'a> = BTreeMap<&'static str, Vec<JsonFacetValue<'a>>>;
+
+#[derive(Serialize)]
+pub struct JsonFacetValue<'a> {
+    value: &'a str,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct JsonHit<'a> {
+    path: Option<&'a str>,
+    /// Paths of the copies of the document, see `index --dedup`.
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    duplicates: &'a [String],
+    /// Whether only the beginning of the document is indexed, see `index
+    /// --oversized`.
+    #[serde(skip_serializing_if = "is_false")]
+    truncated: bool,
+    hash: String,
+    score: f64,
+    terms: Vec<JsonTerm<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snippets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explain: Option<JsonHitExplanation>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Why a document matched, as printed by `--explain`.
+#[derive(Serialize)]
+struct JsonHitExplanation {
+    /// The index or segment the document was scored in, by path.
+    source: Option<String>,
+    terms: Vec<TermScore>,
+}
+
+/// Where a term occurs in a document, or only how many times in an index
+/// without positions.
+#[derive(Serialize)]
+struct JsonTerm<'a> {
+    term: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offsets: Option<&'a Offsets>,
+    /// Where each occurrence starts in the text of the document, in bytes,
+    /// if the index was built with `index --byte-offsets`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    byte_offsets: Option<&'a Offsets>,
+    /// The lines it occurs on, from 1, if the index was built with `index
+    /// --line-numbers`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lines: Option<&'a Offsets>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    occurrences: Option<usize>,
+}
+
+/// Load the index file, index directory or pack at `path` for searching.
+pub fn open(path: &Path) -> index_tools::Result<MultiSearcher> {
+    #[cfg(feature = "mmap")]
+    if PackedIndex::is_packed(path) {
+        return PackedIndex::open(path)?.searcher();
+    }
+    if IndexDir::is_index_dir(path) {
+        IndexDir::open_read_only(path)?.searcher()
+    } else {
+        Ok(MultiSearcher::new(vec![Searcher::open(path)?]))
+    }
+}
+
+pub fn run(args: SearchArgs) -> std::io::Result<()> {
+    let human = args.format == Format::Human;
+    let searcher = open(Path::new(&args.index_file))?;
+    let searcher = match args.ranking {
+        Ranking::Bm25 => searcher,
+        Ranking::TfIdf => searcher.with_scorer(TfIdf),
+    };
+    let mut searcher =
+        searcher.with_field_boost(Field::Title, args.title_boost);
+    if let Some(language) = &args.lang {
+        searcher = searcher.with_language(language)?;
+    }
+    if args.case_sensitive {
+        searcher = searcher.with_case_sensitive(true)?;
+    }
+    if !args.facet.is_empty() {
+        searcher = searcher.with_facets(&args.facet);
+    }
+
+    let snippets = match args.snippets {
+        true => {
+            let mut options = SnippetOptions {
+                context: args.context,
+                ..SnippetOptions::default()
+            };
+            if human && std::io::stdout().is_terminal() {
+                options.open = "\x1b[1m".to_string();
+                options.close = "\x1b[0m".to_string();
+            }
+            Some(Snippets {
+                extractors: extractors(&searcher)?,
+                options,
+            })
+        }
+        false => None,
+    };
+    if args.interactive {
+        return interactive(&searcher, &args, snippets.as_ref());
+    }
+    let (results, query, show_offsets) = match &args.query {
+        Some(query) => {
+            let results = searcher.query(query).inspect_err(|e| {
+                if let (true, index_tools::Error::Query(e)) = (human, e) {
+                    point_at(query, e.position);
+                }
+            })?;
+            (results, query.clone(), false)
+        }
+        None => {
+            if human {
+                let words: usize = searcher
+                    .searchers()
+                    .iter()
+                    .map(|s| s.index().word_count)
+                    .sum();
+                writeln!(
+                    io::stdout(),
+                    "Word count in entire index: {}\n",
+                    words
+                )?;
+            }
+            let results = searcher.any_of(&args.terms);
+            (results, args.terms.join(" "), true)
+        }
+    };
+    let hits = match args.top {
+        Some(top) => results.page(args.page.saturating_sub(1) as usize, top),
+        None => results.hits(),
+    };
+    print_results(
+        &searcher,
+        &args,
+        query,
+        &results,
+        hits,
+        show_offsets,
+        snippets.as_ref(),
+    )
+}
+
+/// Read queries from the terminal, with line editing and history, and run
+/// each of them against the index loaded once.
+fn interactive(
+    searcher: &MultiSearcher,
+    args: &SearchArgs,
+    snippets: Option<&Snippets>,
+) -> std::io::Result<()> {
+    let mut editor = DefaultEditor::new().map_err(std::io::Error::other)?;
+    let human = args.format == Format::Human;
+    if human {
+        println!("Type a query, or an empty line to quit.");
+    }
+    let per_page = args.top.unwrap_or(usize::MAX);
+    // The last query, its results, and where their next page starts.
+    let mut last: Option<(String, SearchResults, usize)> = None;
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(std::io::Error::other(e)),
+        };
+        let query = line.trim();
+        if query.is_empty() {
+            break;
+        }
+        let _ = editor.add_history_entry(query);
+        let (query, results, offset) = if query == ":next" {
+            match last.take() {
+                Some(page) if page.2 < page.1.len() => page,
+                page => {
+                    println!("No more results.");
+                    last = page;
+                    continue;
+                }
+            }
+        } else {
+            match searcher.query(query) {
+                Ok(results) => (query.to_string(), results, 0),
+                Err(index_tools::Error::Query(e)) => {
+                    point_at(query, e.position);
+                    println!("Error: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let hits = results.range(offset, per_page);
+        print_results(
+            searcher,
+            args,
+            query.clone(),
+            &results,
+            hits,
+            false,
+            snippets,
+        )?;
+        let next = offset + hits.len();
+        if human && next < results.len() {
+            println!("({} more, :next to show them)", results.len() - next);
+        }
+        last = Some((query, results, next));
+    }
+    Ok(())
+}
+
+/// Print `hits`, a page of `results`, in the format asked for.
+fn print_results(
+    searcher: &MultiSearcher,
+    args: &SearchArgs,
+    query: String,
+    results: &SearchResults,
+    hits: &[SearchHit],
+    show_offsets: bool,
+    snippets: Option<&Snippets>,
+) -> std::io::Result<()> {
+    let out = &mut io::stdout().lock();
+    if args.format == Format::Grep {
+        return print_lines(out, searcher, results, hits);
+    }
+    // Searches by terms are the ones that show offsets.
+    let explanation = args
+        .explain
+        .then(|| explain(searcher, &query, results, show_offsets));
+    if args.format == Format::Json {
+        let json = JsonResults {
+            query,
+            total: results.len(),
+            hits: hits
+                .iter()
+                .map(|hit| {
+                    json_hit(searcher, results, hit, snippets, args.explain)
+                })
+                .collect(),
+            facets: json_facets(results.facets(), &Facet::ALL),
+            explain: explanation,
+        };
+        let json = serde_json::to_string_pretty(&json)?;
+        writeln!(out, "{}", json)?;
+        return Ok(());
+    }
+
+    if let Some(explanation) = &explanation {
+        for parsed in &explanation.parsed {
+            writeln!(out, "Parsed as: {}", parsed)?;
+        }
+        for settings in &explanation.analyzer {
+            if settings.is_empty() {
+                writeln!(out, "Analyzer: default")?;
+                continue;
+            }
+            writeln!(out, "Analyzer:")?;
+            for (name, value) in settings {
+                writeln!(out, "\t{} = {}", name, value)?;
+            }
+        }
+        writeln!(out)?;
+    }
+
+    if show_offsets {
+        writeln!(out, "Terms were found in {} documents:", results.len())?;
+    } else {
+        writeln!(out, "Query matched {} documents:", results.len())?;
+    }
+    for hit in hits {
+        display(
+            out,
+            searcher,
+            results,
+            hit,
+            show_offsets,
+            snippets,
+            args.explain,
+        )?;
+    }
+    for (facet, values) in results.facets().iter() {
+        writeln!(out, "\nBy {}:", facet.name())?;
+        if values.is_empty() {
+            writeln!(out, "\t(no values)")?;
+        }
+        for (value, count) in values {
+            writeln!(out, "\t{:>6}  {}", count, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// How `query` was understood by the indexes of `searcher`: parsed, or, for
+/// a search by `terms`, analyzed into the terms of `results`.
+fn explain(
+    searcher: &MultiSearcher,
+    query: &str,
+    results: &SearchResults,
+    terms: bool,
+) -> Explanation {
+    let mut parsed = vec![];
+    if terms {
+        let any = results
+            .terms()
+            .iter()
+            .map(|t| Query::Term(t.clone()))
+            .reduce(|left, right| Query::Or(Box::new(left), Box::new(right)));
+        parsed.push(any.map_or("(no terms)".to_string(), |q| q.to_string()));
+    }
+    let mut analyzer = vec![];
+    for searcher in searcher.searchers() {
+        // Queries are parsed again, as each index parsed them.
+        if !terms {
+            if let Ok(query) = Query::parse_with(query, searcher.analyzer()) {
+                let query = query.to_string();
+                if !parsed.contains(&query) {
+                    parsed.push(query);
+                }
+            }
+        }
+        let settings = searcher
+            .config()
+            .encode()
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        if !analyzer.contains(&settings) {
+            analyzer.push(settings);
+        }
+    }
+    Explanation { parsed, analyzer }
+}
+
+/// The counts of `facets` among `counts`, to print as JSON.
+pub fn json_facets<'a>(
+    counts: &'a FacetCounts,
+    facets: &[Facet],
+) -> JsonFacets<'a> {
+    counts
+        .iter()
+        .filter(|(facet, _)| facets.contains(facet))
+        .map(|(facet, values)| {
+            let values = values
+                .iter()
+                .map(|(value, count)| JsonFacetValue {
+                    value,
+                    count: *count,
+                })
+                .collect();
+            (facet.name(), values)
+        })
+        .collect()
+}
+
+fn parse_facet(name: &str) -> Result<Facet, String> {
+    Facet::from_name(name).ok_or_else(|| {
+        format!("unknown facet {} (directory, extension or language)", name)
+    })
+}
+
+/// Print `query` with a caret under the character at `position`.
+fn point_at(query: &str, position: usize) {
+    println!("\t{}", query);
+    println!("\t{}^", " ".repeat(position));
+}
+
+fn display(
+    out: &mut impl Write,
+    searcher: &MultiSearcher,
+    results: &SearchResults,
+    hit: &SearchHit,
+    show_offsets: bool,
+    snippets: Option<&Snippets>,
+    explain: bool,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "\t Document: {} (score: {:.4})",
+        searcher
+            .doc_info(&hit.doc)
+            .map_or("Unknown", |d| d.path.as_str()),
+        hit.score
+    )?;
+    if let Some(info) = searcher.doc_info(&hit.doc) {
+        for path in &info.duplicates {
+            writeln!(out, "\t Also at: {}", path)?;
+        }
+        if info.truncated {
+            writeln!(out, "\t Truncated: only its beginning is indexed")?;
+        }
+    }
+    if explain {
+        if let Some(explained) = searcher.explain(results, hit) {
+            print_explanation(out, &explained)?;
+        }
+    }
+    // The index the document was found in.
+    let Some(searcher) = searcher.searcher_of(&hit.doc) else {
+        return Ok(());
+    };
+    let index = searcher.index();
+    if show_offsets {
+        for term in results.terms() {
+            let offsets = index.map.get(term).and_then(|e| e.get(&hit.doc));
+            match offsets {
+                Some(offsets) if index.positions => {
+                    writeln!(
+                        out,
+                        "\t Term \"{}\" offsets: {:?}",
+                        term, offsets
+                    )?;
+                    if let Some(lines) = index.line_numbers(term, &hit.doc) {
+                        writeln!(
+                            out,
+                            "\t Term \"{}\" lines: {:?}",
+                            term, lines
+                        )?;
+                    }
+                }
+                Some(offsets) => writeln!(
+                    out,
+                    "\t Term \"{}\" occurrences: {}",
+                    term,
+                    offsets.len()
+                )?,
+                None => {}
+            }
+        }
+    }
+    if let Some(snippets) = snippets {
+        for snippet in
+            make_snippets(snippets, searcher, results.terms(), &hit.doc)
+        {
+            writeln!(out, "\t   {}", snippet)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print how the score of a document adds up, under it.
+fn print_explanation(
+    out: &mut impl Write,
+    explained: &HitExplanation,
+) -> io::Result<()> {
+    for t in &explained.terms {
+        writeln!(
+            out,
+            "\t   {:>8.4} = {} x {}: {} occurrences, in {} documents",
+            t.score, t.weight, t.term, t.tf, t.df
+        )?;
+    }
+    match &explained.source {
+        Some(source) => {
+            writeln!(out, "\t   Postings from {}", source.display())
+        }
+        None => writeln!(out, "\t   Postings from documents in memory"),
+    }
+}
+
+fn json_hit<'a>(
+    searcher: &'a MultiSearcher,
+    results: &'a SearchResults,
+    hit: &SearchHit,
+    snippets: Option<&Snippets>,
+    explain: bool,
+) -> JsonHit<'a> {
+    // The index the document was found in.
+    let found_in = searcher.searcher_of(&hit.doc);
+    let terms = match found_in {
+        Some(found_in) => results
+            .terms()
+            .iter()
+            .filter_map(|term| {
+                let index = found_in.index();
+                let offsets = index.map.get(term)?.get(&hit.doc)?;
+                Some(JsonTerm {
+                    term,
+                    offsets: index.positions.then_some(offsets),
+                    byte_offsets: index.byte_offsets(term, &hit.doc),
+                    lines: index.line_numbers(term, &hit.doc),
+                    occurrences: (!index.positions).then_some(offsets.len()),
+                })
+            })
+            .collect(),
+        None => vec![],
+    };
+    let info = searcher.doc_info(&hit.doc);
+    JsonHit {
+        path: info.map(|d| d.path.as_str()),
+        duplicates: info.map_or(&[], |d| &d.duplicates),
+        truncated: info.is_some_and(|d| d.truncated),
+        hash: hit.doc.to_hex(),
+        score: hit.score,
+        terms,
+        snippets: snippets.zip(found_in).map(|(s, found_in)| {
+            make_snippets(s, found_in, results.terms(), &hit.doc)
+        }),
+        explain: explain
+            .then(|| searcher.explain(results, hit))
+            .flatten()
+            .map(|explained| JsonHitExplanation {
+                source: explained
+                    .source
+                    .map(|source| source.display().to_string()),
+                terms: explained.terms,
+            }),
+    }
+}
+
+/// The extractors to read documents with, to get the same text as when
+/// they were indexed.
+fn extractors(searcher: &MultiSearcher) -> std::io::Result<Extractors> {
+    let routes = match searcher.searchers().first() {
+        Some(first) => first.config().routes.clone(),
+        None => vec![],
+    };
+    Extractors::default().routed(&routes)
+}
+
+/// Print the lines of the documents of `hits` that have a term of
+/// `results`, as `path:line:text`, the text being read again like when the
+/// document was indexed. Documents without line numbers are printed as
+/// their path alone.
+fn print_lines(
+    out: &mut impl Write,
+    searcher: &MultiSearcher,
+    results: &SearchResults,
+    hits: &[SearchHit],
+) -> std::io::Result<()> {
+    let extractors = extractors(searcher)?;
+    for hit in hits {
+        let (Some(info), Some(found_in)) =
+            (searcher.doc_info(&hit.doc), searcher.searcher_of(&hit.doc))
+        else {
+            continue;
+        };
+        let index = found_in.index();
+        let mut lines: Offsets = results
+            .terms()
+            .iter()
+            .filter_map(|term| index.line_numbers(term, &hit.doc))
+            .flatten()
+            .copied()
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        if lines.is_empty() {
+            writeln!(out, "{}", info.path)?;
+            continue;
+        }
+        // A document that can't be read any more still gets its lines.
+        let text = match extractors.read(&info.path) {
+            Ok(extracted) => extracted.text,
+            Err(_) => String::new(),
+        };
+        let text: Vec<&str> = text.lines().collect();
+        for line in lines {
+            let content = (line as usize)
+                .checked_sub(1)
+                .and_then(|i| text.get(i))
+                .map_or("", |content| content);
+            writeln!(out, "{}:{}:{}", info.path, line, content)?;
+        }
+    }
+    Ok(())
+}
+
+/// Re-read a matching document and cut excerpts around the hits of `terms`
+/// in it, according to the index of `searcher`. An index without positions
+/// can't tell where the hits are, so it gets none.
+fn make_snippets(
+    snippets: &Snippets,
+    searcher: &Searcher,
+    terms: &[String],
+    doc: &Doc,
+) -> Vec<String> {
+    let index = searcher.index();
+    if !index.positions {
+        return vec![];
+    }
+    let Some(info) = index.docs.get(doc) else {
+        return vec![];
+    };
+    // Extracted the same way as when it was indexed, so the offsets match.
+    let text = match snippets.extractors.read(&info.path) {
+        Ok(extracted) => extracted.text,
+        Err(_) => return vec![],
+    };
+    // Offsets of other fields don't point into the text.
+    let mut offsets: Offsets = terms
+        .iter()
+        .filter(|term| Field::of_term(term) == Field::Body)
+        .filter_map(|term| index.map.get(term).and_then(|e| e.get(doc)))
+        .flatten()
+        .copied()
+        .collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let config = searcher.config().for_path(Path::new(&info.path));
+    index_tools::snippet::snippets(
+        &text,
+        &offsets,
+        config.tokenizer.tokenizer().as_ref(),
+        &snippets.options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Command {
+        #[clap(flatten)]
+        search: SearchArgs,
+    }
+
+    #[test]
+    fn pages_count_from_one() {
+        let parse = |args: &[&str]| {
+            let args = ["search", "cat", "--top", "5"].iter().chain(args);
+            Command::try_parse_from(args).map(|command| command.search.page)
+        };
+        assert_eq!(parse(&[]).unwrap(), 1);
+        assert_eq!(parse(&["--page", "3"]).unwrap(), 3);
+        assert!(parse(&["--page", "0"]).is_err());
+    }
+}
@@ -0,0 +1,837 @@
+use clap::Args;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io;
+#[cfg(feature = "async")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use index_tools::index::is_twin_term;
+#[cfg(feature = "async")]
+use index_tools::index::Doc;
+use index_tools::prelude::*;
+use index_tools::Error;
+
+use crate::cache::QueryCache;
+use crate::metrics::Metrics;
+use crate::reload::LiveSearcher;
+use crate::search::JsonFacets;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Serve searches over an index.dat file or an index directory through a
+/// small HTTP API.
+///
+/// `GET /search?q=QUERY[&top=N][&page=N]` runs a boolean query (the syntax of
+/// `index_tools search`) and returns the hits as JSON. Pages can also be
+/// asked for with `offset=N` (counting hits from 0) and `limit=N`, the same
+/// as `top`, or with `cursor=C`, where `C` is the `next` cursor returned with
+/// the previous page. With `facets=NAMES`, a comma-separated list of
+/// `directory`, `extension` and `language`, the response also counts all
+/// matching documents by those facets. `GET /stats` describes the index. The
+/// results of the last queries are kept, so that other pages of a search
+/// are answered without running it again. Requests are answered on a pool
+/// of threads, so a slow search doesn't hold up the requests behind it.
+///
+/// The index is loaded at startup, and loaded again in the background when
+/// it changes, as segments are added to an index directory, merged or have
+/// documents deleted, or an index file is replaced. Searches started before
+/// it is swapped in finish on the previous one.
+///
+/// `GET /metrics` reports, in the text format of Prometheus, the number of
+/// searches (whose rate is the number of queries per second), a histogram
+/// of the time taken to answer them, how many were answered from the kept
+/// results, the number of index files open and the size of the index.
+///
+/// Built with the `async` feature, and given an index file, the server also
+/// answers `GET /lookup?term=T` with the documents holding the index term
+/// `T`, read from the file for each request by an `AsyncIndexFileSearcher`,
+/// opened again when the index is loaded again.
+/// Lookups run as tokio tasks, so slow reads don't hold up the requests
+/// behind them; the reads themselves are blocking, on tokio's pool of
+/// blocking threads.
+#[derive(Default, Args, Debug)]
+pub struct ServeArgs {
+    /// Path to index.dat file, to an index directory (see `index
+    /// --segments`), to the directory of a sharded index (see `index
+    /// --shards`), or to a pack (see `pack`).
+    #[clap(short, long, default_value = "index.dat")]
+    index_file: String,
+    /// Address to listen on.
+    #[clap(short, long, default_value = "127.0.0.1:8080")]
+    addr: String,
+    /// Number of hits per page, unless the request asks for another one
+    /// with `top`.
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+    /// Number of queries whose results are kept, to answer them again
+    /// without running them; 0 keeps none.
+    #[clap(long, default_value_t = 100)]
+    cache: usize,
+    /// Seconds between checks of whether the index changed, to load it
+    /// again; 0 never loads it again.
+    #[clap(long, default_value_t = 1)]
+    reload_interval: u64,
+    /// Number of threads answering requests (by default, one per CPU).
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+}
+
+/// What requests are answered with.
+struct Served {
+    live: Arc<LiveSearcher>,
+    index_file: PathBuf,
+    top: usize,
+    cache: QueryCache,
+    metrics: Metrics,
+    #[cfg(feature = "async")]
+    lookups: Lookups,
+}
+
+#[derive(Serialize)]
+struct SearchResponse<'a> {
+    query: &'a str,
+    /// Number of matching documents, on all pages.
+    total: usize,
+    /// Position of the first hit in all the results, counting from 0.
+    offset: usize,
+    page: usize,
+    hits: Vec<Hit<'a>>,
+    /// The values of the facets asked for, with their number of matching
+    /// documents.
+    #[serde(skip_serializing_if = "JsonFacets::is_empty")]
+    facets: JsonFacets<'a>,
+    /// The cursor to ask for the next page with, if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Hit<'a> {
+    path: Option<&'a str>,
+    hash: String,
+    score: f64,
+}
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    pub documents: usize,
+    pub terms: usize,
+    pub words: usize,
+    pub avg_doc_len: f64,
+    /// Average length of the other fields, by name.
+    pub avg_field_lens: HashMap<&'static str, f64>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Serialize)]
+struct LookupResponse<'a> {
+    term: &'a str,
+    documents: Vec<LookupHit<'a>>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Serialize)]
+struct LookupHit<'a> {
+    path: Option<&'a str>,
+    hash: String,
+    /// Number of times the document has the term.
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    error: &'a str,
+    /// For query syntax errors, where in the query the problem is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<usize>,
+}
+
+/// Why a request can't be answered, over HTTP, on stdin (see `daemon`) or
+/// over gRPC (see `grpc`).
+#[derive(Serialize)]
+pub struct Failure {
+    /// The HTTP status to answer with.
+    #[serde(skip)]
+    pub status: u16,
+    pub message: String,
+    /// For query syntax errors, where in the query the problem is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+}
+
+impl Failure {
+    /// A request that can't be answered as it is.
+    pub fn bad_request(message: impl Into<String>) -> Failure {
+        Failure {
+            status: 400,
+            message: message.into(),
+            position: None,
+        }
+    }
+}
+
+/// A page of results to return, as asked for by a request.
+pub struct Page {
+    /// Position of the first hit, counting from 0.
+    pub offset: usize,
+    /// Number of hits.
+    pub limit: usize,
+    /// The `next` cursor of the previous page, which takes precedence over
+    /// `offset`.
+    pub cursor: Option<String>,
+    /// The facets to count the matching documents by.
+    pub facets: Vec<Facet>,
+}
+
+type JsonResponse = Response<io::Cursor<Vec<u8>>>;
+
+pub fn run(args: ServeArgs) -> io::Result<()> {
+    let served = Served::open(&args)?;
+    let server = Server::http(&args.addr).map_err(io::Error::other)?;
+    println!("Listening on http://{}", args.addr);
+    let threads = args.threads.map_or_else(
+        || thread::available_parallelism().map_or(1, |n| n.get()),
+        usize::from,
+    );
+    serve(&server, &served, threads);
+    Ok(())
+}
+
+impl Served {
+    fn open(args: &ServeArgs) -> io::Result<Served> {
+        let index_file = PathBuf::from(&args.index_file);
+        let interval = Some(Duration::from_secs(args.reload_interval))
+            .filter(|interval| !interval.is_zero());
+        Ok(Served {
+            live: LiveSearcher::open(&index_file, interval, |path| {
+                Ok(crate::search::open(path)?.with_facets(&Facet::ALL))
+            })?,
+            #[cfg(feature = "async")]
+            lookups: Lookups::open(&index_file)?,
+            index_file,
+            top: args.top,
+            cache: QueryCache::new(args.cache),
+            metrics: Metrics::default(),
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl Served {
+    /// Start answering `request` if it is a lookup, or give it back.
+    fn lookup(&self, request: Request) -> Option<Request> {
+        self.lookups.take(request, self.live.get().generation)
+    }
+}
+
+/// Answer the requests to `server` on `threads` threads, each until
+/// `server` is unblocked.
+fn serve(server: &Server, served: &Served, threads: usize) {
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    #[cfg(feature = "async")]
+                    let Some(request) = served.lookup(request) else {
+                        continue;
+                    };
+                    let response = respond(served, &request);
+                    finish(request, response);
+                }
+            });
+        }
+    });
+}
+
+/// Log `request` and send it `response`.
+fn finish(request: Request, response: JsonResponse) {
+    println!(
+        "{} {} {}",
+        request.method(),
+        request.url(),
+        response.status_code().0
+    );
+    if let Err(e) = request.respond(response) {
+        println!("error: {}", e);
+    }
+}
+
+/// Answers `/lookup` requests in tokio tasks.
+#[cfg(feature = "async")]
+struct Lookups {
+    runtime: tokio::runtime::Runtime,
+    path: PathBuf,
+    /// The generation of the searcher (see `reload`) loaded when the index
+    /// was opened, and the index, `None` for index directories, which have
+    /// no single file to read.
+    index: Mutex<(u64, Option<LookupIndex>)>,
+}
+
+#[cfg(feature = "async")]
+#[derive(Clone)]
+struct LookupIndex {
+    searcher: AsyncIndexFileSearcher,
+    /// The path of each document, to answer with.
+    paths: Arc<HashMap<Doc, String>>,
+}
+
+#[cfg(feature = "async")]
+impl Lookups {
+    fn open(path: &Path) -> io::Result<Lookups> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let index = LookupIndex::open(&runtime, path)?;
+        Ok(Lookups {
+            runtime,
+            path: path.to_path_buf(),
+            index: Mutex::new((0, index)),
+        })
+    }
+
+    /// The index to look up in for the searcher of `generation`, opened
+    /// again if it was opened for another one. A failure to open it again
+    /// leaves the previous one in place, until the next request.
+    fn index(&self, generation: u64) -> Option<LookupIndex> {
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        if index.0 != generation {
+            match LookupIndex::open(&self.runtime, &self.path) {
+                Ok(opened) => *index = (generation, opened),
+                Err(e) => println!("error: {}: {}", self.path.display(), e),
+            }
+        }
+        index.1.clone()
+    }
+
+    /// Start answering `request` if it is a lookup, with the index of the
+    /// searcher of `generation`, or give it back.
+    fn take(&self, request: Request, generation: u64) -> Option<Request> {
+        let url = request.url();
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        if path != "/lookup" || *request.method() != Method::Get {
+            return Some(request);
+        }
+        let params = parse_params(query);
+        let index = self.index(generation);
+        self.runtime.spawn(async move {
+            let response = match (&index, params.get("term")) {
+                (None, _) => error(404, "lookups need an index file", None),
+                (_, None) => error(400, "missing parameter term", None),
+                (Some(index), Some(term)) => lookup(index, term).await,
+            };
+            // Sending may block on the connection too.
+            tokio::task::spawn_blocking(move || finish(request, response));
+        });
+        None
+    }
+}
+
+#[cfg(feature = "async")]
+impl LookupIndex {
+    /// The index of the file at `path`, or `None` if it isn't one.
+    fn open(
+        runtime: &tokio::runtime::Runtime,
+        path: &Path,
+    ) -> io::Result<Option<LookupIndex>> {
+        // Packs are searched, but not looked up in.
+        #[cfg(feature = "mmap")]
+        let file = path.is_file() && !PackedIndex::is_packed(path);
+        #[cfg(not(feature = "mmap"))]
+        let file = path.is_file();
+        if !file {
+            return Ok(None);
+        }
+        let searcher = runtime.block_on(AsyncIndexFileSearcher::open(path))?;
+        let paths = searcher
+            .searcher()
+            .docs()
+            .iter()
+            .map(|(doc, info)| (doc.clone(), info.path.clone()))
+            .collect();
+        Ok(Some(LookupIndex {
+            searcher,
+            paths: Arc::new(paths),
+        }))
+    }
+}
+
+#[cfg(feature = "async")]
+async fn lookup(index: &LookupIndex, term: &str) -> JsonResponse {
+    let found = match index.searcher.lookup(term).await {
+        Ok(found) => found.unwrap_or_default(),
+        Err(e) => return error(500, &e.to_string(), None),
+    };
+    let mut documents: Vec<_> = found
+        .iter()
+        .map(|(doc, offsets)| LookupHit {
+            path: index.paths.get(doc).map(String::as_str),
+            hash: doc.to_hex(),
+            count: offsets.len(),
+        })
+        .collect();
+    documents.sort_by(|a, b| a.path.cmp(&b.path).then(a.hash.cmp(&b.hash)));
+    json(200, &LookupResponse { term, documents })
+}
+
+fn respond(served: &Served, request: &Request) -> JsonResponse {
+    // The searcher of the whole request, even if another one is loaded.
+    let loaded = served.live.get();
+    let searcher = &loaded.searcher;
+    served.cache.follow(loaded.generation);
+    if *request.method() != Method::Get {
+        return error(405, "only GET is supported", None);
+    }
+    let (path, query) =
+        request.url().split_once('?').unwrap_or((request.url(), ""));
+    let params = parse_params(query);
+    let answer = match path {
+        "/search" => {
+            let started = Instant::now();
+            let answer =
+                search_params(&params, served.top).and_then(|(query, page)| {
+                    let generation = loaded.generation;
+                    search(searcher, generation, query, &page, &served.cache)
+                });
+            served.metrics.observe(started.elapsed(), answer.is_ok());
+            answer
+        }
+        "/metrics" => {
+            let metrics = &served.metrics;
+            return match metrics.render(
+                &loaded,
+                &served.cache,
+                &served.index_file,
+            ) {
+                Ok(body) => text(body),
+                Err(e) => error(500, &e.to_string(), None),
+            };
+        }
+        "/stats" => serde_json::to_value(stats(searcher))
+            .map_err(|e| internal_error(&e)),
+        _ => return error(404, "not found", None),
+    };
+    match answer {
+        Ok(body) => json(200, &body),
+        Err(failure) => {
+            error(failure.status, &failure.message, failure.position)
+        }
+    }
+}
+
+/// The query and the page a `/search` request asks for, with `top` hits
+/// per page unless it says otherwise.
+fn search_params(
+    params: &HashMap<String, String>,
+    top: usize,
+) -> Result<(&str, Page), Failure> {
+    let Some(query) = params.get("q") else {
+        return Err(Failure::bad_request("missing parameter q"));
+    };
+    let number = |name: &str, default: usize| match params.get(name) {
+        Some(value) => value.parse::<usize>().map_err(|_| {
+            Failure::bad_request(format!("{} must be a number", name))
+        }),
+        None => Ok(default),
+    };
+    let facets = params
+        .get("facets")
+        .map_or("", String::as_str)
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(facet)
+        .collect::<Result<Vec<_>, _>>()?;
+    let limit = number("top", top).and_then(|top| number("limit", top))?;
+    // An offset takes precedence over a page number.
+    let offset = number("page", 1)
+        .and_then(|page| page_offset(page, limit))
+        .and_then(|offset| number("offset", offset))?;
+    let page = Page {
+        offset,
+        limit,
+        cursor: params.get("cursor").cloned(),
+        facets,
+    };
+    Ok((query, page))
+}
+
+/// Position of the first hit of the `page`th page of `limit` hits. Pages
+/// count from 1.
+pub fn page_offset(page: usize, limit: usize) -> Result<usize, Failure> {
+    match page {
+        0 => Err(Failure::bad_request("page must be at least 1")),
+        page => Ok((page - 1).saturating_mul(limit)),
+    }
+}
+
+/// The facet named `name`.
+pub fn facet(name: &str) -> Result<Facet, Failure> {
+    Facet::from_name(name)
+        .ok_or_else(|| Failure::bad_request(format!("unknown facet {}", name)))
+}
+
+/// Run `query`, unless `cache` has its results, and describe `page` of its
+/// results, as JSON. `searcher` is of `generation` (see `reload`), which
+/// `cache` keeps results by.
+pub fn search(
+    searcher: &MultiSearcher,
+    generation: u64,
+    query: &str,
+    page: &Page,
+    cache: &QueryCache,
+) -> Result<serde_json::Value, Failure> {
+    let (results, offset) = results(searcher, generation, query, page, cache)?;
+    let limit = page.limit;
+    let hits = results
+        .range(offset, limit)
+        .iter()
+        .map(|hit| Hit {
+            path: searcher.doc_info(&hit.doc).map(|d| d.path.as_str()),
+            hash: hit.doc.to_hex(),
+            score: hit.score,
+        })
+        .collect();
+    let response = SearchResponse {
+        query,
+        total: results.len(),
+        offset,
+        page: offset.checked_div(limit).unwrap_or(0) + 1,
+        hits,
+        facets: crate::search::json_facets(results.facets(), &page.facets),
+        next: results
+            .cursor_after(offset, limit)
+            .map(|cursor| cursor.to_string()),
+    };
+    serde_json::to_value(response).map_err(|e| internal_error(&e))
+}
+
+/// Run `query`, unless `cache` has its results, and return them with the
+/// position of the first hit of `page`. `searcher` is of `generation`, as
+/// for `search`.
+pub fn results(
+    searcher: &MultiSearcher,
+    generation: u64,
+    query: &str,
+    page: &Page,
+    cache: &QueryCache,
+) -> Result<(Arc<SearchResults>, usize), Failure> {
+    let cursor = match page.cursor.as_deref().map(str::parse::<Cursor>) {
+        Some(Ok(cursor)) => Some(cursor),
+        Some(Err(e)) => return Err(Failure::bad_request(e.to_string())),
+        None => None,
+    };
+    let run = || match searcher.query(query) {
+        Err(Error::Query(e)) => Err(Failure {
+            position: Some(e.position),
+            ..Failure::bad_request(e.to_string())
+        }),
+        results => results.map_err(|e| internal_error(&e)),
+    };
+    let results = cache.get_or_run(generation, query, run)?;
+    let offset =
+        cursor.map_or(page.offset, |cursor| results.offset_after(&cursor));
+    Ok((results, offset))
+}
+
+/// Describe the index of `searcher`.
+pub fn stats(searcher: &MultiSearcher) -> StatsResponse {
+    let indexes = searcher.searchers().iter().map(|s| s.index());
+    let documents: HashSet<_> = indexes
+        .clone()
+        .flat_map(|index| index.docs.keys())
+        .collect();
+    let terms: HashSet<&str> = indexes
+        .clone()
+        .flat_map(|index| index.map.keys().map(String::as_str))
+        .filter(|term| !is_twin_term(term))
+        .collect();
+    StatsResponse {
+        documents: documents.len(),
+        terms: terms.len(),
+        words: indexes.map(|index| index.word_count).sum(),
+        avg_doc_len: searcher.stats().avg_doc_len,
+        avg_field_lens: Field::NAMED
+            .iter()
+            .map(|&field| (field.name(), searcher.stats().avg_len(field)))
+            .collect(),
+    }
+}
+
+/// A failure of the server rather than of the request.
+fn internal_error(e: &dyn std::fmt::Display) -> Failure {
+    Failure {
+        status: 500,
+        ..Failure::bad_request(e.to_string())
+    }
+}
+
+fn text(body: String) -> JsonResponse {
+    // The version of the text format of Prometheus.
+    let content_type =
+        Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+            .unwrap();
+    Response::from_data(body).with_header(content_type)
+}
+
+fn json<T: Serialize>(status: u16, body: &T) -> JsonResponse {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_data(body)
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn error(status: u16, message: &str, position: Option<usize>) -> JsonResponse {
+    json(
+        status,
+        &ErrorResponse {
+            error: message,
+            position,
+        },
+    )
+}
+
+/// Parse a URL query string like `q=rust+async&top=5`.
+fn parse_params(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes and `+` for space. Invalid escapes are kept as they
+/// are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                out.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_index;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::path::Path;
+
+    /// Serve `index_file` on `threads` threads while `client` runs, with
+    /// the address to send requests to.
+    pub fn serving(
+        index_file: &Path,
+        reload_interval: u64,
+        threads: usize,
+        client: impl FnOnce(SocketAddr),
+    ) {
+        let args = ServeArgs {
+            index_file: index_file.display().to_string(),
+            top: 10,
+            cache: 10,
+            reload_interval,
+            ..ServeArgs::default()
+        };
+        let served = Served::open(&args).unwrap();
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| serve(&server, &served, threads));
+            client(addr);
+            for _ in 0..threads {
+                server.unblock();
+            }
+        });
+    }
+
+    /// The status and the body of the answer to `GET url`.
+    pub fn get(addr: SocketAddr, url: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.0\r\n\r\n", url).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    /// The JSON answer to `GET url`.
+    pub fn get_json(addr: SocketAddr, url: &str) -> (u16, serde_json::Value) {
+        let (status, body) = get(addr, url);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    /// The paths of the hits of a `/search` answer.
+    fn paths(answer: &serde_json::Value) -> Vec<&str> {
+        let hits = answer["hits"].as_array().unwrap();
+        let mut paths: Vec<_> = hits
+            .iter()
+            .map(|hit| hit["path"].as_str().unwrap())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn concurrent_queries_are_answered_on_the_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts: Vec<String> =
+            (0..20).map(|i| format!("common word{}", i % 4)).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let index_file = write_index(dir.path(), &texts);
+        serving(&index_file, 0, 4, |addr| {
+            thread::scope(|scope| {
+                let clients: Vec<_> = (0..16)
+                    .map(|i| {
+                        scope.spawn(move || {
+                            let url = format!("/search?q=word{}&top=20", i % 4);
+                            (i % 4, get_json(addr, &url))
+                        })
+                    })
+                    .collect();
+                for client in clients {
+                    let (word, (status, answer)) = client.join().unwrap();
+                    assert_eq!(status, 200);
+                    let expected: Vec<String> = (0..20)
+                        .filter(|i| i % 4 == word)
+                        .map(|i| format!("{}.txt", i))
+                        .collect();
+                    let mut expected: Vec<&str> =
+                        expected.iter().map(String::as_str).collect();
+                    expected.sort();
+                    assert_eq!(paths(&answer), expected);
+                }
+            });
+            let (status, answer) = get_json(addr, "/search?q=common&top=3");
+            assert_eq!((status, answer["total"].as_u64()), (200, Some(20)));
+            assert_eq!(get_json(addr, "/nowhere").0, 404);
+        });
+    }
+
+    #[test]
+    fn pages_are_asked_for_by_number_or_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["a", "a b", "a b c", "a b c d", "a b c d e"];
+        let index_file = write_index(dir.path(), &texts);
+        let all = ["0.txt", "1.txt", "2.txt", "3.txt", "4.txt"];
+        serving(&index_file, 0, 1, |addr| {
+            let mut seen = vec![];
+            let mut url = "/search?q=a&top=2".to_string();
+            loop {
+                let (status, answer) = get_json(addr, &url);
+                assert_eq!(status, 200);
+                seen.extend(paths(&answer).into_iter().map(str::to_string));
+                let Some(next) = answer["next"].as_str() else {
+                    break;
+                };
+                url = format!("/search?q=a&top=2&cursor={}", next);
+            }
+            seen.sort();
+            assert_eq!(seen, all);
+
+            let (_, answer) = get_json(addr, "/search?q=a&top=2&page=3");
+            assert_eq!(answer["offset"], 4);
+            assert_eq!(paths(&answer).len(), 1);
+            for url in ["/search?q=a&page=0", "/search?q=a&cursor=none"] {
+                assert_eq!(get_json(addr, url).0, 400, "{}", url);
+            }
+        });
+    }
+
+    #[test]
+    fn metrics_count_the_searches_answered() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_file = write_index(dir.path(), &["the cat", "a dog"]);
+        serving(&index_file, 0, 1, |addr| {
+            for (url, status) in [
+                ("/search?q=cat", 200),
+                ("/search?q=cat", 200),
+                ("/search?q=(cat", 400),
+            ] {
+                assert_eq!(get(addr, url).0, status, "{}", url);
+            }
+            let (status, text) = get(addr, "/metrics");
+            assert_eq!(status, 200);
+            let metrics: HashMap<&str, &str> = text
+                .lines()
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(|line| line.rsplit_once(' '))
+                .collect();
+            let size = fs::metadata(&index_file).unwrap().len().to_string();
+            for (name, value) in [
+                ("index_tools_queries_total", "3"),
+                ("index_tools_query_errors_total", "1"),
+                ("index_tools_query_duration_seconds_count", "3"),
+                (
+                    "index_tools_query_duration_seconds_bucket{le=\"+Inf\"}",
+                    "3",
+                ),
+                ("index_tools_cache_hits_total", "1"),
+                ("index_tools_reloads_total", "0"),
+                ("index_tools_segments", "1"),
+                ("index_tools_documents", "2"),
+                ("index_tools_index_size_bytes", &size),
+            ] {
+                assert_eq!(metrics.get(name), Some(&value), "{}", name);
+            }
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn lookups_follow_the_index_loaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_file = write_index(dir.path(), &["cat", "dog"]);
+        let looked_up = |addr, term| {
+            let (status, answer) =
+                get_json(addr, &format!("/lookup?term={}", term));
+            assert_eq!(status, 200);
+            let documents = answer["documents"].as_array().unwrap().iter();
+            documents
+                .map(|d| d["path"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        serving(&index_file, 1, 2, |addr| {
+            assert_eq!(looked_up(addr, "cat"), ["0.txt"]);
+            write_index(dir.path(), &["a bird", "a cat", "another cat"]);
+            let started = Instant::now();
+            while get_json(addr, "/search?q=bird").1["total"] != 1 {
+                assert!(started.elapsed() < Duration::from_secs(20));
+                thread::sleep(Duration::from_millis(50));
+            }
+            assert_eq!(looked_up(addr, "cat"), ["1.txt", "2.txt"]);
+            assert_eq!(looked_up(addr, "dog"), Vec::<String>::new());
+        });
+    }
+}
@@ -0,0 +1,247 @@
+//! Bloom filters over the terms of index files.
+//!
+//! An index written with `IndexSettings::bloom_filter` has a footer, right
+//! before its description (see `description`), holding a bloom filter of
+//! its terms: the number of hash functions (u8), the bits of the filter, and
+//! the length of those two as a u64. A filter never says a term of the file is missing, and says a missing term is
+//! there about once in a hundred lookups, so searching several files can
+//! skip most of those that don't have a term without reading their table of
+//! contents.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::crypt::IndexFile;
+use crate::description::description_start;
+use crate::error::{Error, Result};
+use crate::header::{read_header, IndexSettings};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Bits of filter per term. Ten bits and seven hash functions give a false
+/// positive rate just under 1%.
+const BITS_PER_TERM: usize = 10;
+
+/// Number of hash functions.
+const HASHES: u8 = 7;
+
+/// A set of terms that can tell for sure that a term isn't in it.
+#[derive(Debug, Clone)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u8>,
+    hashes: u8,
+}
+
+impl BloomFilter {
+    /// A filter of the terms whose `term_hash` are `hashes`.
+    pub fn from_hashes(hashes: &[u64]) -> BloomFilter {
+        let len = (hashes.len() * BITS_PER_TERM).div_ceil(8).max(8);
+        let mut filter = BloomFilter {
+            bits: vec![0; len],
+            hashes: HASHES,
+        };
+        for &hash in hashes {
+            for bit in filter.positions(hash) {
+                filter.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter
+    }
+
+    /// False if `term` is certainly not in the set.
+    pub fn might_contain(&self, term: &str) -> bool {
+        self.positions(term_hash(term))
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// The bits of a term, by double hashing: the two halves of its hash
+    /// make as many hash functions as needed.
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 8;
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        (0..self.hashes as u64).map(move |i| ((h1 + i * h2) % len) as usize)
+    }
+
+    /// The footer holding the filter.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + self.bits.len() + 8);
+        out.push(self.hashes);
+        out.extend_from_slice(&self.bits);
+        out.write_u64::<LittleEndian>(1 + self.bits.len() as u64)
+            .unwrap();
+        out
+    }
+
+    /// Read the footer of `f`, an index file written with `settings`.
+    /// Returns the filter, if the file has one, and where the footer
+    /// starts.
+    pub fn read_footer<R: Read + Seek>(
+        f: &mut R,
+        settings: &IndexSettings,
+    ) -> Result<(Option<BloomFilter>, u64)> {
+        let start = footer_start(f, settings)?;
+        if !settings.bloom_filter {
+            return Ok((None, start));
+        }
+        let truncated = Error::truncated("bloom filter");
+        let end = description_start(f)? - 8;
+        f.seek(SeekFrom::Start(start))?;
+        let hashes = f.read_u8().map_err(&truncated)?;
+        let mut bits = vec![0; (end - start - 1) as usize];
+        f.read_exact(&mut bits).map_err(&truncated)?;
+        if hashes == 0 {
+            return Err(bad_footer());
+        }
+        Ok((Some(BloomFilter { bits, hashes }), start))
+    }
+
+    /// Read the filter of `index_file`, and only that, if it has one.
+    pub fn load<P: AsRef<Path>>(index_file: P) -> Result<Option<BloomFilter>> {
+        let mut f = BufReader::new(IndexFile::open(index_file)?);
+        let settings = read_header(&mut f)?.settings;
+        Ok(BloomFilter::read_footer(&mut f, &settings)?.0)
+    }
+}
+
+/// Where the footer of `f`, an index file written with `settings`, starts,
+/// which is where its table of contents ends: where its description starts,
+/// if it has no bloom filter.
+pub(crate) fn footer_start<R: Read + Seek>(
+    f: &mut R,
+    settings: &IndexSettings,
+) -> Result<u64> {
+    let end = description_start(f)?;
+    footer_start_before(f, settings, end)
+}
+
+/// Where the footer of `f` starts, given that it ends at `end`: before
+/// version 8, at the end of the file (see `upgrade`).
+pub(crate) fn footer_start_before<R: Read + Seek>(
+    f: &mut R,
+    settings: &IndexSettings,
+    end: u64,
+) -> Result<u64> {
+    if !settings.bloom_filter {
+        return Ok(end);
+    }
+    let length_start = end.checked_sub(8).ok_or_else(bad_footer)?;
+    f.seek(SeekFrom::Start(length_start))?;
+    let len = f
+        .read_u64::<LittleEndian>()
+        .map_err(Error::truncated("bloom filter"))?;
+    len.checked_add(8)
+        .and_then(|n| end.checked_sub(n))
+        .filter(|_| len >= 2)
+        .ok_or_else(bad_footer)
+}
+
+/// The hash of a term in filters: FNV-1a, with the bits mixed afterwards
+/// (by the finalizer of SplitMix64) so that both halves are usable. It must
+/// never change, as filters are saved.
+pub(crate) fn term_hash(term: &str) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in term.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+fn bad_footer() -> Error {
+    Error::format("bloom filter footer is out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    use crate::fixtures::write_index_with;
+    use crate::read::{IndexFileReader, IndexFileSearcher};
+
+    fn filter_of(terms: &[String]) -> BloomFilter {
+        let hashes: Vec<u64> = terms.iter().map(|t| term_hash(t)).collect();
+        BloomFilter::from_hashes(&hashes)
+    }
+
+    #[test]
+    fn terms_are_never_missing() {
+        let terms: Vec<String> =
+            (0..1000).map(|i| format!("term{i}")).collect();
+        let filter = filter_of(&terms);
+        assert!(terms.iter().all(|t| filter.might_contain(t)));
+        let false_positives = (0..10_000)
+            .filter(|i| filter.might_contain(&format!("other{i}")))
+            .count();
+        assert!(false_positives < 300, "{false_positives} false positives");
+
+        let empty = filter_of(&[]);
+        assert!(!empty.might_contain("term0"));
+        assert!(!empty.might_contain(""));
+    }
+
+    /// Write an index of `texts`, with or without a filter.
+    fn write_index(dir: &Path, texts: &[&str], bloom_filter: bool) -> PathBuf {
+        let settings = IndexSettings {
+            bloom_filter,
+            ..Default::default()
+        };
+        write_index_with(dir, texts, &settings)
+    }
+
+    #[test]
+    fn filters_read_back_from_the_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["the cat sat", "the dog and the cat"];
+        let plain = write_index(dir.path(), &texts, false);
+        let file = write_index(dir.path(), &texts, true);
+        assert!(BloomFilter::load(&plain).unwrap().is_none());
+        let filter = BloomFilter::load(&file).unwrap().unwrap();
+        for term in ["the", "cat", "sat", "dog", "and"] {
+            assert!(filter.might_contain(term));
+        }
+
+        // The footer doesn't get in the way of reading the rest.
+        let expected = IndexFileReader::get_index_from_file(&plain).unwrap();
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert_eq!(index.map, expected.map);
+        let searcher = IndexFileSearcher::open(&file).unwrap();
+        assert!(searcher.might_contain("cat"));
+        assert_eq!(searcher.entry("dog").unwrap().doc_count, 1);
+        assert_eq!(searcher.terms().count(), 5);
+    }
+
+    #[test]
+    fn damaged_footers_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["a cat"], true);
+        let good = fs::read(&file).unwrap();
+        // The filter ends where the description of the file starts.
+        let end = description_start(&mut io::Cursor::new(&good)).unwrap();
+        let end = end as usize;
+        let mut cases =
+            vec![good[..good.len() - 1].to_vec(), good[..end - 9].to_vec()];
+        for footer_len in [0, 1, end as u64, u64::MAX] {
+            let mut bytes = good.clone();
+            bytes[end - 8..end].copy_from_slice(&footer_len.to_le_bytes());
+            cases.push(bytes);
+        }
+        let mut no_hashes = good.clone();
+        let filter_len =
+            u64::from_le_bytes(good[end - 8..end].try_into().unwrap());
+        no_hashes[end - 8 - filter_len as usize] = 0;
+        cases.push(no_hashes);
+        for bytes in cases {
+            fs::write(&file, &bytes).unwrap();
+            assert!(BloomFilter::load(&file).is_err());
+            assert!(IndexFileSearcher::open(&file).is_err());
+        }
+    }
+}
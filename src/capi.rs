@@ -0,0 +1,361 @@
+//! A C interface, to build and search indexes from other languages.
+//!
+//! `index_tools_index` indexes a directory, `index_tools_open` loads an
+//! index for searching, and `index_tools_search` answers a query with its
+//! results in JSON:
+//!
+//! ```text
+//! {"query": "cat", "total": 12,
+//!  "hits": [{"path": "docs/a.txt", "hash": "4f2a...", "score": 1.5}]}
+//! ```
+//!
+//! Strings are UTF-8 and NUL-terminated. Those returned are the caller's, to
+//! free with `index_tools_free_string`; indexes are freed with
+//! `index_tools_close`. A function that fails returns NULL, and
+//! `index_tools_last_error` tells why.
+//!
+//! The header include/index_tools.h declares these functions. It is
+//! generated with cbindgen, configured by cbindgen.toml:
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output include/index_tools.h
+//! ```
+//!
+//! CI runs it again and fails if the header differs.
+//!
+//! Only available with the `capi` feature. The library to link against is
+//! built with it as a dynamic library, or with `--crate-type staticlib` as a
+//! static one:
+//!
+//! ```text
+//! cargo rustc --lib --release --features capi --crate-type cdylib
+//! ```
+
+use serde::Serialize;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::indexer::Indexer;
+#[cfg(feature = "mmap")]
+use crate::pack::PackedIndex;
+use crate::search::{MultiSearcher, Searcher};
+use crate::segments::IndexDir;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+thread_local! {
+    /// Why the last function that failed on this thread failed.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An index loaded for searching.
+pub struct IndexToolsIndex {
+    searcher: MultiSearcher,
+}
+
+#[derive(Serialize)]
+struct SearchResponse<'a> {
+    query: &'a str,
+    total: usize,
+    hits: Vec<Hit<'a>>,
+}
+
+#[derive(Serialize)]
+struct Hit<'a> {
+    path: Option<&'a str>,
+    hash: String,
+    score: f64,
+}
+
+/// Index the files under the directory, or the file, `input` into the
+/// directory `output`, created if need be, with the default settings.
+/// Returns the path of the index file written, or NULL.
+///
+/// # Safety
+///
+/// `input` and `output` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn index_tools_index(
+    input: *const c_char,
+    output: *const c_char,
+) -> *mut c_char {
+    let index_file = catch_panic(|| {
+        let (input, output) = (str_arg(input)?, str_arg(output)?);
+        fs::create_dir_all(output)?;
+        let index_file = Indexer::new(input).output(output).run()?;
+        Ok(index_file.to_string_lossy().into_owned())
+    });
+    string_or_null(index_file)
+}
+
+/// Load the index file, index directory or pack (with the `mmap` feature)
+/// at `path` for searching. Returns NULL if it can't be loaded.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn index_tools_open(
+    path: *const c_char,
+) -> *mut IndexToolsIndex {
+    match catch_panic(|| str_arg(path).and_then(|path| open(Path::new(path)))) {
+        Ok(searcher) => Box::into_raw(Box::new(IndexToolsIndex { searcher })),
+        Err(e) => {
+            set_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Search `index` for `query`, in the syntax of `Query`. Returns the first
+/// `limit` results, in JSON, or NULL if the query is invalid.
+///
+/// # Safety
+///
+/// `index` must come from `index_tools_open` and not be closed, and `query`
+/// must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn index_tools_search(
+    index: *const IndexToolsIndex,
+    query: *const c_char,
+    limit: usize,
+) -> *mut c_char {
+    let json = catch_panic(|| {
+        let searcher = match index.as_ref() {
+            Some(index) => &index.searcher,
+            None => return Err(invalid("index is NULL")),
+        };
+        let query = str_arg(query)?;
+        let results = searcher.query(query)?;
+        let hits = results
+            .range(0, limit)
+            .iter()
+            .map(|hit| Hit {
+                path: searcher.doc_info(&hit.doc).map(|d| d.path.as_str()),
+                hash: hit.doc.to_hex(),
+                score: hit.score,
+            })
+            .collect();
+        let response = SearchResponse {
+            query,
+            total: results.len(),
+            hits,
+        };
+        Ok(serde_json::to_string(&response).map_err(io::Error::other)?)
+    });
+    string_or_null(json)
+}
+
+/// Free `index`. Does nothing if it is NULL.
+///
+/// # Safety
+///
+/// `index` must come from `index_tools_open`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn index_tools_close(index: *mut IndexToolsIndex) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
+
+/// Free a string returned by another function. Does nothing if it is NULL.
+///
+/// # Safety
+///
+/// `string` must come from this library, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn index_tools_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Why the last function that failed on this thread failed, or NULL if none
+/// did. The string is valid until the next failure on this thread.
+#[no_mangle]
+pub extern "C" fn index_tools_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Load the index at `path`, like `index_tools search` does.
+fn open(path: &Path) -> Result<MultiSearcher> {
+    #[cfg(feature = "mmap")]
+    if PackedIndex::is_packed(path) {
+        return PackedIndex::open(path)?.searcher();
+    }
+    if IndexDir::is_index_dir(path) {
+        IndexDir::open_read_only(path)?.searcher()
+    } else {
+        Ok(MultiSearcher::new(vec![Searcher::open(path)?]))
+    }
+}
+
+/// The string `arg` points to.
+unsafe fn str_arg<'a>(arg: *const c_char) -> Result<&'a str> {
+    if arg.is_null() {
+        return Err(invalid("string argument is NULL"));
+    }
+    CStr::from_ptr(arg)
+        .to_str()
+        .map_err(|_| invalid("string argument isn't UTF-8"))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+/// Run `f`, turning a panic into an error, as unwinding into C would abort
+/// the caller.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let msg = match payload.downcast_ref::<&str>() {
+            Some(msg) => msg.to_string(),
+            None => payload
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_default(),
+        };
+        Err(Error::Io(io::Error::other(format!("panicked: {}", msg))))
+    })
+}
+
+/// `result` as a string for the caller to free, or NULL, recording the
+/// error.
+fn string_or_null(result: Result<String>) -> *mut c_char {
+    match result
+        .and_then(|s| CString::new(s).map_err(|e| invalid(&e.to_string())))
+    {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn set_error(e: &Error) {
+    // Messages don't hold NULs but in paths, and those can go.
+    let msg = e.to_string().replace('\0', "");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(msg).ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    /// Take the string `s`, freeing it.
+    unsafe fn take(s: *mut c_char) -> String {
+        assert!(
+            !s.is_null(),
+            "{:?}",
+            CStr::from_ptr(index_tools_last_error())
+        );
+        let taken = CStr::from_ptr(s).to_str().unwrap().to_string();
+        index_tools_free_string(s);
+        taken
+    }
+
+    #[test]
+    fn indexes_are_built_and_searched_through_the_c_interface() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(docs.join("a.txt"), "the cat sat").unwrap();
+        fs::write(docs.join("b.txt"), "the dog ran").unwrap();
+        let out = dir.path().join("out");
+        unsafe {
+            let input = c(docs.to_str().unwrap());
+            let output = c(out.to_str().unwrap());
+            let index_file =
+                take(index_tools_index(input.as_ptr(), output.as_ptr()));
+            assert!(Path::new(&index_file).is_file());
+
+            let index = index_tools_open(c(&index_file).as_ptr());
+            assert!(!index.is_null());
+            let json = take(index_tools_search(index, c("cat").as_ptr(), 10));
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(json["total"], 1);
+            assert!(json["hits"][0]["path"]
+                .as_str()
+                .unwrap()
+                .ends_with("a.txt"));
+            let json = take(index_tools_search(index, c("the").as_ptr(), 1));
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(json["total"], 2);
+            assert_eq!(json["hits"].as_array().unwrap().len(), 1);
+
+            let bad = index_tools_search(index, c("(cat").as_ptr(), 10);
+            assert!(bad.is_null());
+            assert!(!index_tools_last_error().is_null());
+            index_tools_close(index);
+
+            let missing = c(dir.path().join("missing").to_str().unwrap());
+            assert!(index_tools_open(missing.as_ptr()).is_null());
+            assert!(index_tools_open(ptr::null()).is_null());
+            let error = CStr::from_ptr(index_tools_last_error());
+            assert!(error.to_str().unwrap().contains("NULL"));
+        }
+    }
+
+    #[test]
+    fn corrupt_index_files_fail_to_open_or_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("a.txt");
+        fs::write(&docs, "the cat sat on the mat").unwrap();
+        let out = dir.path().join("out");
+        let index_file = unsafe {
+            let input = c(docs.to_str().unwrap());
+            let output = c(out.to_str().unwrap());
+            take(index_tools_index(input.as_ptr(), output.as_ptr()))
+        };
+        let bytes = fs::read(&index_file).unwrap();
+        let corrupt = dir.path().join("corrupt.dat");
+        let path = c(corrupt.to_str().unwrap());
+
+        let truncated = (0..bytes.len()).map(|len| bytes[..len].to_vec());
+        let flipped = (0..bytes.len()).map(|i| {
+            let mut bytes = bytes.clone();
+            bytes[i] ^= 0xff;
+            bytes
+        });
+        let mut failed = 0;
+        for corrupted in truncated.chain(flipped) {
+            fs::write(&corrupt, corrupted).unwrap();
+            unsafe {
+                let index = index_tools_open(path.as_ptr());
+                if index.is_null() {
+                    assert!(!index_tools_last_error().is_null());
+                    failed += 1;
+                    continue;
+                }
+                let json = index_tools_search(index, c("cat").as_ptr(), 10);
+                index_tools_free_string(json);
+                index_tools_close(index);
+            }
+        }
+        // Every truncated one does, at least.
+        assert!(failed >= bytes.len());
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let result: Result<()> = catch_panic(|| panic!("at the disco"));
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("panicked: at the disco"), "{}", error);
+    }
+}
@@ -0,0 +1,232 @@
+//! Compression of postings.
+//!
+//! The hits of each term are compressed on their own, so that a lookup only
+//! has to decompress the term it reads. The codec is chosen when the index is
+//! written and recorded in the file header; readers decompress
+//! transparently.
+//!
+//! Each codec other than `None` needs the cargo feature of the same name.
+
+use std::borrow::Cow;
+#[cfg(feature = "zstd")]
+use std::io::Read;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// How many times larger than its compressed form LZ4 data can get: each
+/// byte of a match length stands for at most 255 bytes.
+#[cfg(feature = "lz4")]
+const LZ4_MAX_RATIO: usize = 255;
+
+/// A compression codec for postings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Postings are stored as they are.
+    #[default]
+    None,
+    /// Zstandard. Needs the `zstd` feature.
+    Zstd,
+    /// LZ4. Needs the `lz4` feature.
+    Lz4,
+}
+
+impl Codec {
+    /// The codec's id, as recorded in index file headers.
+    pub fn id(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+        }
+    }
+
+    /// The codec with the given id, if there is one.
+    pub fn from_id(id: u8) -> Option<Codec> {
+        match id {
+            0 => Some(Codec::None),
+            1 => Some(Codec::Zstd),
+            2 => Some(Codec::Lz4),
+            _ => None,
+        }
+    }
+
+    /// The codec's name, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+        }
+    }
+
+    /// Parse a name returned by `name`.
+    pub fn from_name(name: &str) -> Option<Codec> {
+        [Codec::None, Codec::Zstd, Codec::Lz4]
+            .into_iter()
+            .find(|c| c.name() == name)
+    }
+
+    /// True if this build of the crate can compress and decompress with the
+    /// codec.
+    pub fn is_available(self) -> bool {
+        match self {
+            Codec::None => true,
+            Codec::Zstd => cfg!(feature = "zstd"),
+            Codec::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// Compress the postings of one term.
+    pub fn compress(self, data: &[u8]) -> Result<Cow<'_, [u8]>> {
+        self.check_available()?;
+        match self {
+            Codec::None => Ok(Cow::Borrowed(data)),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => Ok(Cow::Owned(zstd::bulk::compress(data, 0)?)),
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => Ok(Cow::Owned(lz4_flex::compress_prepend_size(data))),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    /// Decompress the postings of one term, which can't take more than
+    /// `limit` bytes once decompressed: corrupt data claiming more is an
+    /// error rather than a huge allocation. Data stored as is isn't checked,
+    /// as it takes no more memory than it did in the file.
+    #[cfg_attr(
+        not(any(feature = "zstd", feature = "lz4")),
+        allow(unused_variables)
+    )]
+    pub fn decompress(
+        self,
+        data: &[u8],
+        limit: usize,
+    ) -> Result<Cow<'_, [u8]>> {
+        self.check_available()?;
+        match self {
+            Codec::None => Ok(Cow::Borrowed(data)),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                let mut out = vec![];
+                zstd::stream::read::Decoder::new(data)
+                    .and_then(|d| {
+                        d.take((limit as u64).saturating_add(1))
+                            .read_to_end(&mut out)
+                    })
+                    .map_err(|e| Error::format(format!("zstd: {}", e)))?;
+                if out.len() > limit {
+                    return Err(too_long(limit));
+                }
+                Ok(Cow::Owned(out))
+            }
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => {
+                // The size is prepended, and trusted for the allocation.
+                if let Some(size) = data.get(..4) {
+                    let size = u32::from_le_bytes(size.try_into().unwrap());
+                    let size = size as usize;
+                    if size > limit || size / LZ4_MAX_RATIO > data.len() {
+                        return Err(too_long(limit));
+                    }
+                }
+                lz4_flex::decompress_size_prepended(data)
+                    .map(Cow::Owned)
+                    .map_err(|e| Error::format(format!("lz4: {}", e)))
+            }
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    /// Fail with a clear error if `is_available` is false.
+    pub fn check_available(self) -> Result<()> {
+        if self.is_available() {
+            return Ok(());
+        }
+        Err(Error::Unsupported(format!(
+            "{} compression is not supported by this build (enable the `{}` \
+             feature)",
+            self.name(),
+            self.name()
+        )))
+    }
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+fn too_long(limit: usize) -> Error {
+    Error::format(format!(
+        "postings take more than {} bytes decompressed",
+        limit
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Codec; 3] = [Codec::None, Codec::Zstd, Codec::Lz4];
+
+    #[test]
+    fn ids_and_names_read_back() {
+        for codec in ALL {
+            assert_eq!(Codec::from_id(codec.id()), Some(codec));
+            assert_eq!(Codec::from_name(codec.name()), Some(codec));
+        }
+        assert_eq!(Codec::from_id(3), None);
+        assert_eq!(Codec::from_name("gzip"), None);
+    }
+
+    #[test]
+    fn data_reads_back() {
+        let data: Vec<u8> =
+            (0..1000u32).flat_map(|n| n.to_le_bytes()).collect();
+        for codec in ALL.into_iter().filter(|c| c.is_available()) {
+            for data in [&data[..], &[]] {
+                let packed = codec.compress(data).unwrap();
+                assert_eq!(
+                    codec.decompress(&packed, data.len()).unwrap(),
+                    data
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_data_is_an_error() {
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            if !codec.is_available() {
+                let e = codec.compress(b"abc").unwrap_err();
+                assert!(matches!(e, Error::Unsupported(_)));
+                continue;
+            }
+            let packed = codec.compress(&[7; 256]).unwrap().into_owned();
+            assert!(codec
+                .decompress(&packed[..packed.len() / 2], 256)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn data_past_the_limit_is_an_error() {
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            if !codec.is_available() {
+                continue;
+            }
+            let packed = codec.compress(&[7; 256]).unwrap().into_owned();
+            assert!(codec.decompress(&packed, 256).is_ok());
+            let e = codec.decompress(&packed, 255).unwrap_err();
+            assert!(matches!(e, Error::Format(_)), "{:?}", e);
+        }
+        if Codec::Lz4.is_available() {
+            // A hostile size prefix isn't allocated.
+            let mut packed = u32::MAX.to_le_bytes().to_vec();
+            packed.extend([0; 16]);
+            assert!(Codec::Lz4.decompress(&packed, usize::MAX).is_err());
+        }
+    }
+}
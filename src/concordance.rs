@@ -0,0 +1,190 @@
+//! Concordances: every occurrence of a word, with the words around it.
+//!
+//! A concordance, or keyword-in-context (KWIC) listing, lines up each place
+//! a word occurs in the corpus with the words before and after it. The index
+//! tells which documents have the word and at which offsets; the words
+//! around it come from the documents themselves, read again from the paths
+//! in the document table and split with the tokenizer of the index, like
+//! `snippet` does. This needs an index with positions. Documents that can't
+//! be read anymore are left out.
+
+use std::io;
+use std::path::Path;
+
+use log::debug;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{Analyzer, CaseForms, Tokenizer};
+use crate::error::{Error, Result};
+use crate::extract::Extractors;
+use crate::index::Doc;
+use crate::read::IndexFileSearcher;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// An occurrence of a word in a document, with its context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    /// The document it is in.
+    pub doc: Doc,
+    /// Path of the document when it was indexed.
+    pub path: String,
+    /// Offset of the word in the document, in words.
+    pub position: u32,
+    /// The words before it, with whitespace collapsed to single spaces.
+    pub left: String,
+    /// The word, as written in the document.
+    pub word: String,
+    /// The words after it, like `left`.
+    pub right: String,
+}
+
+/// Every occurrence of `word` in the live documents of the index, with up
+/// to `context` words on each side, sorted by path and then by position.
+///
+/// `word` is analyzed like a query word, so that e.g. "Cats" finds "cat"
+/// and "cats" in an index of stemmed words. A word that the analyzer drops,
+/// like a stop word, occurs nowhere. The documents are read with
+/// `extractors`, which must extract the same text as when they were
+/// indexed (see `Extractors::routed`), and skipped if they can't be read.
+pub fn concordance(
+    searcher: &mut IndexFileSearcher,
+    word: &str,
+    context: usize,
+    extractors: &Extractors,
+) -> Result<Vec<Occurrence>> {
+    let settings = searcher.settings().clone();
+    if settings.omit_positions {
+        return Err(Error::Unsupported(
+            "a concordance needs positions, which this index omits".into(),
+        ));
+    }
+    let analyzer = settings
+        .analyzer
+        .build()?
+        .with_case_forms(CaseForms::Folded);
+    let term = match analyzer.analyze(word).as_slice() {
+        [] => return Ok(vec![]),
+        [token] => token.text.clone(),
+        _ => {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is more than one word", word),
+            )))
+        }
+    };
+    let Some(entry) = searcher.lookup(&term)? else {
+        return Ok(vec![]);
+    };
+
+    let mut occurrences = vec![];
+    for (doc, info) in searcher.docs() {
+        let Some(offsets) = entry.get(doc) else {
+            continue;
+        };
+        let text = match extractors.read(&info.path) {
+            Ok(extracted) => extracted.text,
+            Err(e) => {
+                debug!("skipping {}: {}", info.path, e);
+                continue;
+            }
+        };
+        // Split like when indexed, which depends on the route of the path.
+        let config = settings.analyzer.for_path(Path::new(&info.path));
+        let tokenizer = config.tokenizer.tokenizer();
+        let found = in_context(&text, offsets, tokenizer.as_ref(), context);
+        occurrences.extend(found.into_iter().map(
+            |(position, (left, word, right))| Occurrence {
+                doc: doc.clone(),
+                path: info.path.clone(),
+                position,
+                left,
+                word,
+                right,
+            },
+        ));
+    }
+    occurrences
+        .sort_by(|a, b| (&a.path, a.position).cmp(&(&b.path, b.position)));
+    Ok(occurrences)
+}
+
+/// The words of `text` at `offsets`, by offset, with up to `context` words
+/// before and after each. `tokenizer` must be the one the index was built
+/// with; offsets that match no word are ignored.
+fn in_context(
+    text: &str,
+    offsets: &[u32],
+    tokenizer: &dyn Tokenizer,
+    context: usize,
+) -> Vec<(u32, (String, String, String))> {
+    let words = tokenizer.tokenize(text);
+    let collapse = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut found: Vec<_> = (0..words.len())
+        .filter(|&i| offsets.contains(&words[i].position))
+        .map(|i| {
+            let first = &words[i.saturating_sub(context)];
+            let last = &words[(i + context).min(words.len() - 1)];
+            let word = &words[i];
+            let left = collapse(&text[first.start..word.start]);
+            let right = collapse(&text[word.end..last.end]);
+            let written = text[word.start..word.end].to_string();
+            (word.position, (left, written, right))
+        })
+        .collect();
+    found.sort_by_key(|(position, _)| *position);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::SimpleTokenizer;
+    use crate::indexer::Indexer;
+    use std::fs;
+
+    #[test]
+    fn words_are_shown_with_their_neighbours() {
+        let text = "The cat sat on\nthe  mat, and the cat slept.";
+        let found = in_context(text, &[1, 8, 42], &SimpleTokenizer, 2);
+        let found: Vec<_> = found
+            .iter()
+            .map(|(p, (l, w, r))| (*p, l.as_str(), w.as_str(), r.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [(1, "The", "cat", "sat on"), (8, "and the", "cat", "slept")]
+        );
+    }
+
+    #[test]
+    fn occurrences_are_found_across_the_corpus() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(docs.join("a.txt"), "a Cat and a cat").unwrap();
+        fs::write(docs.join("b.txt"), "no dogs here").unwrap();
+        fs::write(docs.join("c.txt"), "the cat").unwrap();
+        let file = Indexer::new(&docs).output(dir.path()).run().unwrap();
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+
+        let extractors = Extractors::default();
+        let found = concordance(&mut searcher, "CAT", 1, &extractors).unwrap();
+        let lines: Vec<_> = found
+            .iter()
+            .map(|o| (o.position, o.left.as_str(), o.word.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [(1, "a", "Cat"), (4, "a", "cat"), (1, "the", "cat")]
+        );
+        assert!(found[0].path.ends_with("a.txt"));
+        assert_eq!(found[0].right, "and");
+        assert!(found[2].path.ends_with("c.txt"));
+        assert!(concordance(&mut searcher, "bird", 1, &extractors)
+            .unwrap()
+            .is_empty());
+        assert!(concordance(&mut searcher, "the cat", 1, &extractors).is_err());
+    }
+}
@@ -0,0 +1,590 @@
+//! Encryption of index files at rest.
+//!
+//! An index file written with `IndexSettings::encrypted` keeps its header in
+//! the clear, with flag `16` set (see `header`), so that its settings can be
+//! read without the key. Everything after it is encrypted with AES-256-GCM,
+//! which also tells if any of the file was changed. The key is 32 bytes,
+//! given in hex by the environment variable `INDEX_TOOLS_KEY`, or read from
+//! the file named by `INDEX_TOOLS_KEY_FILE` (see `Key::from_env`).
+//!
+//! The header is followed by a random salt of `SALT_LEN` bytes, from which
+//! and the key the key of the file is derived, with HKDF-SHA256, so that no
+//! two files share one. Then comes the rest of the file, cut into chunks of
+//! `CHUNK_LEN` bytes, each encrypted on its own with the number of the chunk
+//! as nonce, and followed by its tag: reading a byte anywhere only takes
+//! decrypting its chunk, so readers can still seek. The last chunk is
+//! shorter than the others, maybe empty, has the last byte of its nonce set,
+//! and authenticates the header as well, so that neither the header nor the
+//! length of the file can be changed unnoticed.
+//!
+//! Offsets, in the header and everywhere else, are those of the decrypted
+//! file, which readers see through `IndexFile`.
+//!
+//! The records of the write-ahead log of an encrypted index directory (see
+//! `wal`) are encrypted one by one, each with a salt of its own (see
+//! `Key::seal`).
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::aead::{MAX_TAG_LEN, NONCE_LEN};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::header::read_any_header;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Environment variable holding the key, in hex.
+pub const KEY_ENV: &str = "INDEX_TOOLS_KEY";
+
+/// Environment variable holding the path of a file holding the key, in hex
+/// or as raw bytes.
+pub const KEY_FILE_ENV: &str = "INDEX_TOOLS_KEY_FILE";
+
+/// Length of keys, in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Length of the salt after the header of an encrypted file.
+const SALT_LEN: usize = 16;
+
+/// Length of the decrypted chunks of an encrypted file, but the last.
+const CHUNK_LEN: usize = 64 << 10;
+
+/// Length of the tag after each chunk.
+const TAG_LEN: usize = MAX_TAG_LEN;
+
+/// What the key of a file is derived for.
+const KEY_INFO: &[u8] = b"index_tools file key";
+
+/// A key to encrypt and decrypt index files with.
+#[derive(Clone)]
+pub struct Key([u8; KEY_LEN]);
+
+impl Key {
+    pub fn new(bytes: [u8; KEY_LEN]) -> Key {
+        Key(bytes)
+    }
+
+    /// Parse a key written in hex.
+    pub fn from_hex(hex: &str) -> Option<Key> {
+        if hex.len() != 2 * KEY_LEN || !hex.is_ascii() {
+            return None;
+        }
+        let mut key = [0; KEY_LEN];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+        }
+        Some(Key(key))
+    }
+
+    /// The key given by `INDEX_TOOLS_KEY`, or else in the file named by
+    /// `INDEX_TOOLS_KEY_FILE`, which holds either the 32 bytes of the key or
+    /// their hex, maybe followed by a newline. Fails with `Error::Key` if
+    /// neither is set, or the key is malformed.
+    pub fn from_env() -> Result<Key> {
+        if let Some(hex) = env::var_os(KEY_ENV) {
+            return hex.to_str().and_then(Key::from_hex).ok_or_else(|| {
+                Error::Key(format!(
+                    "{} must be {} bytes in hex",
+                    KEY_ENV, KEY_LEN
+                ))
+            });
+        }
+        let Some(path) = env::var_os(KEY_FILE_ENV) else {
+            return Err(Error::Key(format!(
+                "no key given: set {} or {}",
+                KEY_ENV, KEY_FILE_ENV
+            )));
+        };
+        let path = Path::new(&path);
+        let bytes = fs::read(path)
+            .map_err(|e| Error::Key(format!("{}: {}", path.display(), e)))?;
+        if let Ok(key) = <[u8; KEY_LEN]>::try_from(&bytes[..]) {
+            return Ok(Key(key));
+        }
+        std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| Key::from_hex(text.trim()))
+            .ok_or_else(|| {
+                Error::Key(format!(
+                    "{} must hold {} bytes, raw or in hex",
+                    path.display(),
+                    KEY_LEN
+                ))
+            })
+    }
+
+    /// `data` encrypted with a key derived from a new salt, which it starts
+    /// with, for records too small to be cut into chunks.
+    pub(crate) fn seal(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut sealed = vec![0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut sealed)
+            .map_err(|_| io::Error::other("no random numbers for the salt"))?;
+        let key = self.file_key(&sealed);
+        sealed.extend_from_slice(data);
+        let mut body = sealed.split_off(SALT_LEN);
+        key.seal_in_place_append_tag(nonce(0, true), Aad::empty(), &mut body)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        sealed.append(&mut body);
+        Ok(sealed)
+    }
+
+    /// What `seal` encrypted into `sealed`, or `None` if it was encrypted
+    /// with another key, or changed since.
+    pub(crate) fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < SALT_LEN + TAG_LEN {
+            return None;
+        }
+        let (salt, body) = sealed.split_at(SALT_LEN);
+        let mut body = body.to_vec();
+        let len = self
+            .file_key(salt)
+            .open_in_place(nonce(0, true), Aad::empty(), &mut body)
+            .ok()?
+            .len();
+        body.truncate(len);
+        Some(body)
+    }
+
+    /// The key of a file with `salt`.
+    fn file_key(&self, salt: &[u8]) -> LessSafeKey {
+        let prk = Salt::new(HKDF_SHA256, salt).extract(&self.0);
+        let okm = prk
+            .expand(&[KEY_INFO], &AES_256_GCM)
+            .expect("HKDF-SHA256 derives keys of 32 bytes");
+        LessSafeKey::new(UnboundKey::from(okm))
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+/// The nonce of chunk `chunk`.
+fn nonce(chunk: u64, last: bool) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..8].copy_from_slice(&chunk.to_le_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Writes what comes after the header of an encrypted file, a chunk at a
+/// time.
+pub(crate) struct Sealer<W> {
+    out: W,
+    key: LessSafeKey,
+    /// Number of the chunk being filled.
+    chunk: u64,
+    /// The chunk being filled, shorter than `CHUNK_LEN`.
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Sealer<W> {
+    /// Start encrypting with `key` what is written after the header of a
+    /// file, already written to `out`.
+    pub fn new(mut out: W, key: &Key) -> io::Result<Sealer<W>> {
+        let mut salt = [0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| io::Error::other("no random numbers for the salt"))?;
+        out.write_all(&salt)?;
+        Ok(Sealer {
+            out,
+            key: key.file_key(&salt),
+            chunk: 0,
+            buf: Vec::with_capacity(CHUNK_LEN + TAG_LEN),
+        })
+    }
+
+    /// Write the last chunk, which authenticates `header`, the final header
+    /// of the file, and return the output.
+    pub fn finish(mut self, header: &[u8]) -> io::Result<W> {
+        self.seal(true, header)?;
+        Ok(self.out)
+    }
+
+    fn seal(&mut self, last: bool, aad: &[u8]) -> io::Result<()> {
+        let next = Vec::with_capacity(CHUNK_LEN + TAG_LEN);
+        let mut chunk = mem::replace(&mut self.buf, next);
+        self.key
+            .seal_in_place_append_tag(
+                nonce(self.chunk, last),
+                Aad::from(aad),
+                &mut chunk,
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        self.out.write_all(&chunk)?;
+        self.chunk += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Sealer<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(CHUNK_LEN - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == CHUNK_LEN {
+            self.seal(false, &[])?;
+        }
+        Ok(n)
+    }
+
+    /// Flushes the output, leaving the chunk being filled as it is.
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// An index file opened for reading, decrypted as it is read if it is
+/// encrypted.
+#[derive(Debug)]
+pub(crate) struct IndexFile {
+    /// Shared with the clones of `self` (see `try_clone`): each reads it at
+    /// its own position.
+    file: Arc<File>,
+    /// Length of the decrypted file.
+    len: u64,
+    /// Position in the decrypted file.
+    pos: u64,
+    sealed: Option<Sealed>,
+}
+
+/// What it takes to read an encrypted file.
+#[derive(Debug)]
+struct Sealed {
+    key: Arc<LessSafeKey>,
+    /// The header, read as it is.
+    header: Arc<[u8]>,
+    /// Number of the last chunk.
+    last: u64,
+    /// Length of the last chunk, decrypted.
+    last_len: usize,
+    /// The chunk read last, decrypted, and its number.
+    chunk: Option<(u64, Vec<u8>)>,
+}
+
+impl IndexFile {
+    /// Open the index file at `path`. Fails with `Error::Key` if it is
+    /// encrypted, and there is no key, or the wrong one, or the file was
+    /// changed. Files that aren't index files open as they are, for readers
+    /// to reject.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<IndexFile> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let header = read_any_header(&mut BufReader::new(&file));
+        file.rewind()?;
+        let header = match header {
+            Ok((_, header)) if header.settings.encrypted => header,
+            _ => {
+                return Ok(IndexFile {
+                    file: Arc::new(file),
+                    len,
+                    pos: 0,
+                    sealed: None,
+                })
+            }
+        };
+        let cannot = |why: &dyn fmt::Display| {
+            Error::Key(format!("can't decrypt {}: {}", path.display(), why))
+        };
+        let key = Key::from_env().map_err(|e| cannot(&e))?;
+        let mut head = vec![0; header.len as usize];
+        file.read_exact(&mut head)?;
+        let mut salt = [0; SALT_LEN];
+        file.read_exact(&mut salt)
+            .map_err(Error::truncated("encrypted data"))?;
+        let sealed_len = (len - header.len)
+            .checked_sub((SALT_LEN + TAG_LEN) as u64)
+            .ok_or_else(|| Error::format("encrypted data is truncated"))?;
+        let full = (CHUNK_LEN + TAG_LEN) as u64;
+        let last_len = (sealed_len % full) as usize;
+        if last_len >= CHUNK_LEN {
+            return Err(Error::format("encrypted data is truncated"));
+        }
+        let last = sealed_len / full;
+        let mut file = IndexFile {
+            file: Arc::new(file),
+            len: header.len + last * CHUNK_LEN as u64 + last_len as u64,
+            pos: 0,
+            sealed: Some(Sealed {
+                key: Arc::new(key.file_key(&salt)),
+                header: head.into(),
+                last,
+                last_len,
+                chunk: None,
+            }),
+        };
+        // Which checks the key, and that the file is whole.
+        file.load(last)
+            .map_err(|_| cannot(&"wrong key, or the file was changed"))?;
+        Ok(file)
+    }
+
+    /// Length of the file, decrypted.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Another reader of the same file, at its start, even if the file was
+    /// replaced or removed since it was opened.
+    #[cfg(feature = "async")]
+    pub fn try_clone(&self) -> IndexFile {
+        IndexFile {
+            file: Arc::clone(&self.file),
+            len: self.len,
+            pos: 0,
+            sealed: self.sealed.as_ref().map(|sealed| Sealed {
+                key: Arc::clone(&sealed.key),
+                header: Arc::clone(&sealed.header),
+                chunk: None,
+                ..*sealed
+            }),
+        }
+    }
+
+    /// The file, unless it is encrypted, which takes reading it through
+    /// `self`.
+    #[cfg(feature = "mmap")]
+    pub fn plain(&self) -> Option<&File> {
+        self.sealed.is_none().then_some(&self.file)
+    }
+
+    /// Decrypt chunk `number`, unless it was the last one read.
+    fn load(&mut self, number: u64) -> io::Result<()> {
+        let sealed = self.sealed.as_mut().unwrap();
+        if sealed.chunk.as_ref().is_some_and(|(n, _)| *n == number) {
+            return Ok(());
+        }
+        let is_last = number == sealed.last;
+        let len = if is_last { sealed.last_len } else { CHUNK_LEN };
+        let start = sealed.header.len() as u64
+            + SALT_LEN as u64
+            + number * (CHUNK_LEN + TAG_LEN) as u64;
+        let mut buf = match sealed.chunk.take() {
+            Some((_, buf)) => buf,
+            None => Vec::with_capacity(CHUNK_LEN + TAG_LEN),
+        };
+        buf.resize(len + TAG_LEN, 0);
+        read_exact_at(&self.file, &mut buf, start)?;
+        let aad = if is_last { &sealed.header[..] } else { &[] };
+        sealed
+            .key
+            .open_in_place(nonce(number, is_last), Aad::from(aad), &mut buf)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("chunk {} of the index file was changed", number),
+                )
+            })?;
+        buf.truncate(len);
+        sealed.chunk = Some((number, buf));
+        Ok(())
+    }
+}
+
+impl Read for IndexFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.pos;
+        let Some(sealed) = &self.sealed else {
+            let n = read_at(&self.file, buf, pos)?;
+            self.pos += n as u64;
+            return Ok(n);
+        };
+        let header_len = sealed.header.len() as u64;
+        if pos >= self.len {
+            return Ok(0);
+        }
+        let n = if pos < header_len {
+            let header = &sealed.header[pos as usize..];
+            let n = header.len().min(buf.len());
+            buf[..n].copy_from_slice(&header[..n]);
+            n
+        } else {
+            let offset = pos - header_len;
+            self.load(offset / CHUNK_LEN as u64)?;
+            let sealed = self.sealed.as_ref().unwrap();
+            let (_, chunk) = sealed.chunk.as_ref().unwrap();
+            let chunk = &chunk[(offset % CHUNK_LEN as u64) as usize..];
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            n
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for IndexFile {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let pos = match to {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        let Some(pos) = pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative position",
+            ));
+        };
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+/// Read from `file` at `pos`, whatever the position of its handle.
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, pos)
+}
+
+/// Read from `file` at `pos`, whatever the position of its handle.
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, pos)
+}
+
+/// Read from `file` at `pos`, seeking its handle there first.
+#[cfg(not(any(unix, windows)))]
+fn read_at(mut file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(pos))?;
+    file.read(buf)
+}
+
+/// Fill `buf` from `file` at `pos`, like `Read::read_exact`.
+fn read_exact_at(
+    file: &File,
+    mut buf: &mut [u8],
+    mut pos: u64,
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read_at(file, buf, pos) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ))
+            }
+            Ok(n) => {
+                buf = &mut buf[n..];
+                pos += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{write_header, IndexSettings, OFFSETS_POSITION};
+    use crate::index::InMemoryIndex;
+    use crate::read::{IndexFileReader, IndexFileSearcher};
+    use crate::tmp::TmpDir;
+    use crate::write::write_index_to_tmp_file;
+
+    /// Every test that needs a key sets this one.
+    fn set_key() {
+        env::set_var(KEY_ENV, "07".repeat(KEY_LEN));
+    }
+
+    /// An encrypted file with `body` after its header, and the header.
+    fn sealed_file(path: &Path, key: &Key, body: &[u8]) -> Vec<u8> {
+        let settings = IndexSettings {
+            encrypted: true,
+            ..IndexSettings::default()
+        };
+        let mut header = vec![];
+        write_header(&mut header, &settings).unwrap();
+        let len = header.len() as u64;
+        let at = OFFSETS_POSITION as usize;
+        header[at..at + 8].copy_from_slice(&len.to_le_bytes());
+        header[at + 8..at + 16].copy_from_slice(&len.to_le_bytes());
+        let mut out = File::create(path).unwrap();
+        out.write_all(&header).unwrap();
+        let mut sealer = Sealer::new(out, key).unwrap();
+        sealer.write_all(body).unwrap();
+        sealer.finish(&header).unwrap();
+        header
+    }
+
+    #[test]
+    fn encrypted_files_read_back_anywhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.dat");
+        let key = Key::new([7; KEY_LEN]);
+        let body: Vec<u8> = (0..3 * CHUNK_LEN + 5).map(|i| i as u8).collect();
+        let header = sealed_file(&path, &key, &body);
+        assert!(!fs::read(&path).unwrap().ends_with(&body[body.len() - 64..]));
+
+        set_key();
+        let mut file = IndexFile::open(&path).unwrap();
+        assert_eq!(file.len(), (header.len() + body.len()) as u64);
+        let mut all = vec![];
+        file.read_to_end(&mut all).unwrap();
+        assert_eq!(all[..header.len()], header);
+        assert_eq!(all[header.len()..], body);
+        let mut end = [0; 10];
+        file.seek(SeekFrom::End(-10)).unwrap();
+        file.read_exact(&mut end).unwrap();
+        assert_eq!(end, body[body.len() - 10..]);
+        file.seek(SeekFrom::Start((header.len() + CHUNK_LEN - 3) as u64))
+            .unwrap();
+        let mut across = [0; 6];
+        file.read_exact(&mut across).unwrap();
+        assert_eq!(across, body[CHUNK_LEN - 3..CHUNK_LEN + 3]);
+    }
+
+    #[test]
+    fn encrypted_indexes_open_with_their_key_only() {
+        set_key();
+        let dir = tempfile::tempdir().unwrap();
+        let settings = IndexSettings {
+            encrypted: true,
+            ..IndexSettings::default()
+        };
+        let index = InMemoryIndex::from_single_document(
+            &[1; 32],
+            "the platypus sat".to_string(),
+        );
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &settings,
+        )
+        .unwrap();
+        let bytes = fs::read(&file).unwrap();
+        assert!(!bytes.windows(8).any(|w| w == b"platypus"));
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+        assert_eq!(searcher.settings(), &settings);
+        assert_eq!(
+            searcher.lookup("platypus").unwrap().unwrap(),
+            index.map["platypus"]
+        );
+
+        let mut changed = bytes;
+        let last = changed.len() - 1;
+        changed[last] ^= 1;
+        fs::write(&file, changed).unwrap();
+        assert!(matches!(IndexFileSearcher::open(&file), Err(Error::Key(_))));
+        let other = dir.path().join("other.dat");
+        sealed_file(&other, &Key::new([8; KEY_LEN]), b"");
+        assert!(matches!(IndexFile::open(&other), Err(Error::Key(_))));
+    }
+}
@@ -0,0 +1,97 @@
+//! Turning the bytes of a file into text.
+//!
+//! Documents are not always UTF-8. Files starting with a byte order mark are
+//! decoded accordingly; other files that aren't valid UTF-8 have their
+//! encoding guessed (e.g. Windows-1252 or Shift_JIS) and are transcoded,
+//! with any bytes that still don't fit replaced by U+FFFD.
+//!
+//! Binary files are detected the way git does it: a NUL byte near the start
+//! of the file, which text in any encoding other than UTF-16 never contains.
+//! They are not decoded at all, and the indexer skips them.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// How many bytes at the start of a file are checked for NUL bytes.
+pub(crate) const BINARY_SNIFF_LEN: usize = 8000;
+
+/// The text of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub text: String,
+    /// Name of the encoding the file was decoded from, e.g. "UTF-8".
+    pub encoding: &'static str,
+    /// True if some bytes were invalid in that encoding and were replaced.
+    pub lossy: bool,
+}
+
+/// True if `bytes` look like the contents of a binary file.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    Encoding::for_bom(head).is_none() && head.contains(&0)
+}
+
+/// True if `head`, the start of a file, is UTF-8 text, but for a character
+/// cut at its end: the files whose text can be decoded in parts, as the
+/// indexer does with large files (see `Indexer::stream_larger_than`).
+pub fn is_utf8_text(head: &[u8]) -> bool {
+    if is_binary(head) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // Only the last character is incomplete.
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// `bytes`, the beginning of a file cut at an arbitrary length, without the
+/// character cut at its end if it is UTF-8 text, so that it isn't taken for
+/// text in another encoding.
+pub(crate) fn trim_cut_char(bytes: &[u8]) -> &[u8] {
+    match std::str::from_utf8(bytes) {
+        Err(e) if e.error_len().is_none() => &bytes[..e.valid_up_to()],
+        _ => bytes,
+    }
+}
+
+/// Decode the contents of a text file, or return `None` if it looks binary.
+pub fn decode(bytes: &[u8]) -> Option<Decoded> {
+    if is_binary(bytes) {
+        return None;
+    }
+    let encoding = match Encoding::for_bom(bytes) {
+        Some((encoding, _)) => encoding,
+        None if std::str::from_utf8(bytes).is_ok() => UTF_8,
+        None => {
+            let mut detector = EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, true)
+        }
+    };
+    // Strips the byte order mark, if any.
+    let (text, encoding, lossy) = encoding.decode(bytes);
+    Some(Decoded {
+        text: text.into_owned(),
+        encoding: encoding.name(),
+        lossy,
+    })
+}
+
+/// Read and decode the file at `path`. Fails with `InvalidData` if it looks
+/// binary.
+pub fn read_text<P: AsRef<Path>>(path: P) -> io::Result<Decoded> {
+    let bytes = fs::read(path)?;
+    decode(&bytes).ok_or_else(binary_file)
+}
+
+/// The error for a file that is skipped because it looks binary.
+pub(crate) fn binary_file() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "binary file")
+}
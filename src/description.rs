@@ -0,0 +1,228 @@
+//! The self-describing footer of index files.
+//!
+//! Every index file ends with a description of itself, in JSON, so that
+//! other tools can find their way around it without reimplementing the
+//! header: where each section starts and how long it is, the codec and
+//! analyzer it was written with, and how many documents, terms, postings
+//! and words it holds. The last 16 bytes of the file are the length of the
+//! JSON (u64, little-endian) and the magic number of the header again:
+//!
+//! ```text
+//! … | description (JSON) | length (u64) | IDXTOOLS
+//! ```
+//!
+//! The description is written last and read by nothing that searches, so
+//! it can grow new fields without a new format version. `inspect` in the
+//! command-line tool prints it.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::crypt::IndexFile;
+use crate::error::{Error, Result};
+use crate::header::{IndexSettings, MAGIC, VERSION};
+use crate::write::SKIP_INTERVAL;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Length of what follows the description: its length and the magic number.
+const TRAILER_LEN: u64 = 16;
+
+/// What an index file says about itself in its footer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDescription {
+    /// Always "index_tools".
+    pub format: String,
+    /// The format version, as in the header.
+    pub version: u32,
+    /// The sections of the file, in order, except this description.
+    pub sections: Vec<Section>,
+    /// The codec the hits of each term are compressed with.
+    pub codec: String,
+    /// How documents are identified, by the name of the `DocIdentity`.
+    /// Files from before version 10 don't say: theirs are SHA-256 hashes.
+    #[serde(default = "sha256")]
+    pub identity: String,
+    /// The settings of the analyzer, by name, as in the header.
+    pub analyzer: BTreeMap<String, String>,
+    /// Whether hits have the offsets of the words, or only their number.
+    pub positions: bool,
+    /// Number of documents between two skip points of a term's hits.
+    pub skip_interval: u32,
+    pub counts: Counts,
+}
+
+/// A section of an index file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    /// One of `header`, `postings`, `document_table`, `table_of_contents`
+    /// and `bloom_filter`.
+    pub name: String,
+    /// Where the section starts, in bytes from the start of the file.
+    pub offset: u64,
+    /// Length of the section, in bytes.
+    pub length: u64,
+}
+
+/// How much an index file holds.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Counts {
+    pub documents: u64,
+    pub terms: u64,
+    /// Number of (term, document) pairs.
+    pub postings: u64,
+    /// Number of words of the documents' bodies.
+    pub words: u64,
+}
+
+fn sha256() -> String {
+    "sha256".to_string()
+}
+
+impl FileDescription {
+    /// The description of a file written with `settings`, with `sections`
+    /// and `counts`.
+    pub(crate) fn new(
+        settings: &IndexSettings,
+        sections: Vec<Section>,
+        counts: Counts,
+    ) -> FileDescription {
+        let analyzer = settings.analyzer.encode();
+        FileDescription {
+            format: "index_tools".to_string(),
+            version: VERSION,
+            sections,
+            codec: settings.codec.name().to_string(),
+            identity: settings.identity.name().to_string(),
+            analyzer: analyzer
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            positions: !settings.omit_positions,
+            skip_interval: SKIP_INTERVAL,
+            counts,
+        }
+    }
+
+    /// The footer holding the description.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = serde_json::to_vec(self).unwrap();
+        let len = out.len() as u64;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(MAGIC);
+        out
+    }
+
+    /// Read the description at the end of `f`.
+    pub fn read<R: Read + Seek>(f: &mut R) -> Result<FileDescription> {
+        let start = description_start(f)?;
+        let end = f.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        f.seek(SeekFrom::Start(start))?;
+        let mut json = vec![0; (end - start) as usize];
+        f.read_exact(&mut json)
+            .map_err(Error::truncated("description"))?;
+        serde_json::from_slice(&json)
+            .map_err(|e| Error::format(format!("bad description: {}", e)))
+    }
+
+    /// Read the description of `index_file`, and only that.
+    pub fn load<P: AsRef<Path>>(index_file: P) -> Result<FileDescription> {
+        FileDescription::read(&mut BufReader::new(IndexFile::open(index_file)?))
+    }
+
+    /// The section called `name`, if the file has one.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+}
+
+/// Where the description of `f` starts, which is where the rest of the file
+/// ends.
+pub(crate) fn description_start<R: Read + Seek>(f: &mut R) -> Result<u64> {
+    let end = f.seek(SeekFrom::End(0))?;
+    if end < TRAILER_LEN {
+        return Err(Error::format("description is truncated"));
+    }
+    f.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    let len = f
+        .read_u64::<LittleEndian>()
+        .map_err(Error::truncated("description"))?;
+    let mut magic = [0; 8];
+    f.read_exact(&mut magic)
+        .map_err(Error::truncated("description"))?;
+    if &magic != MAGIC {
+        return Err(Error::format("description footer has a bad magic number"));
+    }
+    len.checked_add(TRAILER_LEN)
+        .and_then(|n| end.checked_sub(n))
+        .ok_or_else(|| Error::format("description footer is out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io;
+
+    use crate::index::InMemoryIndex;
+    use crate::tmp::TmpDir;
+    use crate::write::write_index_to_tmp_file;
+    use crate::HASH_LENGTH;
+
+    #[test]
+    fn files_describe_their_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = InMemoryIndex::new();
+        for (i, text) in ["the cat sat", "the dog"].iter().enumerate() {
+            index.merge(InMemoryIndex::from_single_document(
+                &[i as u8; HASH_LENGTH],
+                text.to_string(),
+            ));
+        }
+        let settings = IndexSettings {
+            bloom_filter: true,
+            ..IndexSettings::default()
+        };
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &settings,
+        )
+        .unwrap();
+        let description = FileDescription::load(&file).unwrap();
+        assert_eq!(description.format, "index_tools");
+        assert!(!description.sections.is_empty());
+        let mut end = 0;
+        for section in &description.sections {
+            assert_eq!(section.offset, end, "{}", section.name);
+            end += section.length;
+        }
+        assert!(description.section("bloom_filter").is_some());
+        let bytes = fs::read(&file).unwrap();
+        assert_eq!(
+            description_start(&mut io::Cursor::new(&bytes)).unwrap(),
+            end
+        );
+        assert_eq!(description.counts.documents, 2);
+        assert_eq!(description.counts.words, 5);
+
+        // A damaged trailer is an error, not a panic.
+        for len in [0, 8, bytes.len() - 1] {
+            fs::write(&file, &bytes[..len]).unwrap();
+            assert!(FileDescription::load(&file).is_err());
+        }
+        let mut bytes = bytes;
+        let n = bytes.len();
+        bytes[n - 16..n - 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&file, &bytes).unwrap();
+        assert!(FileDescription::load(&file).is_err());
+    }
+}
@@ -0,0 +1,543 @@
+//! The term dictionary, which is the table of contents of an index file.
+//!
+//! Terms are sorted, and neighbours often share a long prefix (`index`,
+//! `indexed`, `indexes`), so each entry only stores the length of the prefix
+//! it shares with the term before it, and the rest of the term. Entries come
+//! in blocks of `BLOCK_LEN`, and the first entry of each block stands on its
+//! own, so that a term can be found by binary search over the first terms of
+//! the blocks, decoding a single block.
+//!
+//! An entry is a sequence of varints (see `varint`):
+//!
+//! | field           | content                                            |
+//! |-----------------|----------------------------------------------------|
+//! | shared          | length of the prefix shared with the previous term |
+//! | suffix length   | followed by the rest of the term, in UTF-8         |
+//! | gap             | from the end of the previous term's data to this one's; the offset itself for the first of a block |
+//! | nbytes          | length of the term's data                          |
+//! | doc count       | number of documents containing the term            |
+//! | occurrences     | number of times the term occurs in all of them     |
+//! | skip count      | followed by the skip points (see `read::Skip`), each id and offset as the difference from the previous point's |
+//! | block maxima    | for each block of hits, one more than there are skip points, the highest number of occurrences and the shortest field length (see `read::BlockMax`) |
+//!
+//! After the entries come the positions of the blocks in the table, the
+//! number of terms and the number of blocks, all u64 little-endian.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Seek, SeekFrom};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::read::{BlockMax, Entry, Skip};
+use crate::varint;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Number of entries in a block.
+const BLOCK_LEN: u64 = 16;
+
+/// Size of the number of terms and the number of blocks at the end.
+const TRAILER_LEN: u64 = 16;
+
+/// Builds the table of contents of an index file, one term at a time, in
+/// term order.
+#[derive(Debug, Default)]
+pub(crate) struct DictionaryWriter {
+    buf: Vec<u8>,
+    /// Positions of the blocks in `buf`.
+    blocks: Vec<u64>,
+    /// Number of entries written.
+    count: u64,
+    previous: Vec<u8>,
+    /// End of the data of the previous term in the file.
+    previous_end: u64,
+}
+
+impl DictionaryWriter {
+    pub fn add(&mut self, e: &Entry) {
+        debug_assert_eq!(e.blocks.len(), e.skips.len() + 1);
+        if self.count.is_multiple_of(BLOCK_LEN) {
+            self.blocks.push(self.buf.len() as u64);
+            self.previous.clear();
+            self.previous_end = 0;
+        }
+        let term = e.term.as_bytes();
+        let shared = self
+            .previous
+            .iter()
+            .zip(term)
+            .take_while(|(a, b)| a == b)
+            .count();
+        varint::write_u64(&mut self.buf, shared as u64);
+        varint::write_u64(&mut self.buf, (term.len() - shared) as u64);
+        self.buf.extend_from_slice(&term[shared..]);
+        varint::write_u64(&mut self.buf, e.offset - self.previous_end);
+        varint::write_u64(&mut self.buf, e.nbytes);
+        varint::write_u64(&mut self.buf, e.doc_count as u64);
+        varint::write_u64(&mut self.buf, e.occurrences);
+        varint::write_u64(&mut self.buf, e.skips.len() as u64);
+        let mut last = Skip {
+            last_id: 0,
+            offset: 0,
+        };
+        for skip in &e.skips {
+            varint::write_u64(
+                &mut self.buf,
+                (skip.last_id - last.last_id) as u64,
+            );
+            varint::write_u64(
+                &mut self.buf,
+                (skip.offset - last.offset) as u64,
+            );
+            last = *skip;
+        }
+        for block in &e.blocks {
+            varint::write_u64(&mut self.buf, block.max_tf as u64);
+            varint::write_u64(&mut self.buf, block.min_len as u64);
+        }
+
+        self.previous.clear();
+        self.previous.extend_from_slice(term);
+        self.previous_end = e.offset + e.nbytes;
+        self.count += 1;
+    }
+
+    /// The whole table, with the block positions after the entries.
+    pub fn finish(mut self) -> Vec<u8> {
+        for position in &self.blocks {
+            self.buf.write_u64::<LittleEndian>(*position).unwrap();
+        }
+        self.buf.write_u64::<LittleEndian>(self.count).unwrap();
+        self.buf
+            .write_u64::<LittleEndian>(self.blocks.len() as u64)
+            .unwrap();
+        self.buf
+    }
+}
+
+/// Decodes entries one after the other, from the beginning of the table or
+/// of a block.
+#[derive(Debug, Default)]
+pub(crate) struct EntryDecoder {
+    /// Number of entries decoded, counting from the beginning of the table.
+    read: u64,
+    previous: Vec<u8>,
+    previous_end: u64,
+}
+
+impl EntryDecoder {
+    /// Decode entries from the beginning of block `block`.
+    fn at_block(block: usize) -> EntryDecoder {
+        EntryDecoder {
+            read: block as u64 * BLOCK_LEN,
+            ..EntryDecoder::default()
+        }
+    }
+
+    /// Read the next entry from `f`, or `None` if `f` is at its end.
+    pub fn read<R: Read>(&mut self, f: &mut R) -> Result<Option<Entry>> {
+        if self.read.is_multiple_of(BLOCK_LEN) {
+            self.previous.clear();
+            self.previous_end = 0;
+        }
+        let shared = match varint::read_u64_from(f) {
+            Ok(shared) => shared,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(None)
+            }
+            Err(e) => return Err(malformed(e)),
+        };
+        if shared > self.previous.len() as u64 {
+            return Err(Error::format(
+                "term shares more than the previous term",
+            ));
+        }
+        self.previous.truncate(shared as usize);
+        let suffix_len = number(f)?;
+        let read = f
+            .take(suffix_len)
+            .read_to_end(&mut self.previous)
+            .map_err(malformed)?;
+        if read as u64 != suffix_len {
+            return Err(truncated());
+        }
+        let term = String::from_utf8(self.previous.clone())?;
+
+        let offset = self
+            .previous_end
+            .checked_add(number(f)?)
+            .ok_or_else(out_of_range)?;
+        let nbytes = number(f)?;
+        let doc_count =
+            u32::try_from(number(f)?).map_err(|_| out_of_range())?;
+        let occurrences = number(f)?;
+        let mut skips = vec![];
+        let mut last = Skip {
+            last_id: 0,
+            offset: 0,
+        };
+        for _ in 0..number(f)? {
+            let skip = Skip {
+                last_id: add_u32(last.last_id, number(f)?)?,
+                offset: add_u32(last.offset, number(f)?)?,
+            };
+            skips.push(skip);
+            last = skip;
+        }
+        let mut blocks = Vec::with_capacity(skips.len() + 1);
+        for _ in 0..=skips.len() {
+            let max_tf = u32::try_from(number(f)?);
+            let min_len = u32::try_from(number(f)?);
+            blocks.push(BlockMax {
+                max_tf: max_tf.map_err(|_| out_of_range())?,
+                min_len: min_len.map_err(|_| out_of_range())?,
+            });
+        }
+
+        self.previous_end =
+            offset.checked_add(nbytes).ok_or_else(out_of_range)?;
+        self.read += 1;
+        Ok(Some(Entry {
+            term,
+            doc_count,
+            occurrences,
+            offset,
+            nbytes,
+            skips,
+            blocks,
+        }))
+    }
+}
+
+/// Length of the entries of the table of contents that runs from `start` to
+/// `end` in `f`, which is left at the start of the entries.
+pub(crate) fn entries_len<R: Read + Seek>(
+    f: &mut R,
+    start: u64,
+    end: u64,
+) -> Result<u64> {
+    let table_len = end
+        .checked_sub(start)
+        .filter(|len| *len >= TRAILER_LEN)
+        .ok_or_else(truncated)?;
+    f.seek(SeekFrom::Start(end - TRAILER_LEN))?;
+    f.read_u64::<LittleEndian>()?;
+    let blocks = f.read_u64::<LittleEndian>()?;
+    let len = blocks
+        .checked_mul(8)
+        .and_then(|n| table_len.checked_sub(TRAILER_LEN + n))
+        .ok_or_else(truncated)?;
+    f.seek(SeekFrom::Start(start))?;
+    Ok(len)
+}
+
+/// A table of contents loaded in memory, still encoded, for lookups.
+#[derive(Debug)]
+pub(crate) struct TermDictionary {
+    /// The entries of the table, without the trailer.
+    entries: Vec<u8>,
+    /// Positions of the blocks in `entries`.
+    blocks: Vec<usize>,
+    /// Number of terms.
+    len: usize,
+}
+
+impl TermDictionary {
+    /// Load the table of contents `table`, checking all of it: the entries
+    /// must decode, be sorted by term and have their data before
+    /// `data_end`, so that lookups can't fail later.
+    pub fn load(mut table: Vec<u8>, data_end: u64) -> Result<TermDictionary> {
+        let len =
+            entries_len(&mut io::Cursor::new(&table), 0, table.len() as u64)?
+                as usize;
+        let mut trailer = &table[len..];
+        let mut blocks = vec![];
+        for _ in 0..(trailer.len() as u64 - TRAILER_LEN) / 8 {
+            blocks.push(trailer.read_u64::<LittleEndian>()? as usize);
+        }
+        let count = trailer.read_u64::<LittleEndian>()?;
+        table.truncate(len);
+
+        let mut decoder = EntryDecoder::default();
+        let mut rest = &table[..];
+        let mut previous: Option<String> = None;
+        let mut n = 0u64;
+        loop {
+            if n.is_multiple_of(BLOCK_LEN)
+                && !rest.is_empty()
+                && blocks.get((n / BLOCK_LEN) as usize)
+                    != Some(&(len - rest.len()))
+            {
+                return Err(Error::format(
+                    "table of contents blocks are out of place",
+                ));
+            }
+            let Some(entry) = decoder.read(&mut rest)? else {
+                break;
+            };
+            entry.check_bounds(data_end)?;
+            if previous.as_ref().is_some_and(|p| *p >= entry.term) {
+                return Err(Error::format(format!(
+                    "term {:?} is out of order",
+                    entry.term
+                )));
+            }
+            previous = Some(entry.term);
+            n += 1;
+        }
+        if n != count || blocks.len() as u64 != n.div_ceil(BLOCK_LEN) {
+            return Err(Error::format(
+                "table of contents doesn't match its trailer",
+            ));
+        }
+        Ok(TermDictionary {
+            entries: table,
+            blocks,
+            len: n as usize,
+        })
+    }
+
+    /// Number of terms.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The entry for `term`, if the term is in the index.
+    pub fn get(&self, term: &str) -> Option<Entry> {
+        self.iter_from(self.block_of(term))
+            .take(BLOCK_LEN as usize)
+            .take_while(|e| e.term.as_str() <= term)
+            .find(|e| e.term == term)
+    }
+
+    /// All entries, in term order.
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0)
+    }
+
+    /// The entries of the terms starting with `prefix`, in term order.
+    pub fn with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = Entry> + 'a {
+        self.iter_from(self.block_of(prefix))
+            .skip_while(move |e| e.term.as_str() < prefix)
+            .take_while(move |e| e.term.starts_with(prefix))
+    }
+
+    fn iter_from(&self, block: usize) -> Iter<'_> {
+        let start = self.blocks.get(block).copied().unwrap_or(0);
+        Iter {
+            rest: &self.entries[start..],
+            decoder: EntryDecoder::at_block(block),
+        }
+    }
+
+    /// The last block whose first term isn't after `term`, where `term`
+    /// would be.
+    fn block_of(&self, term: &str) -> usize {
+        self.blocks
+            .partition_point(|&start| self.first_term(start) <= term.as_bytes())
+            .saturating_sub(1)
+    }
+
+    /// The first term of the block at `start`, which is stored in full.
+    fn first_term(&self, start: usize) -> &[u8] {
+        let mut rest = &self.entries[start..];
+        let _shared = varint::read_u64_from(&mut rest);
+        let len = varint::read_u64_from(&mut rest).unwrap_or(0) as usize;
+        &rest[..len]
+    }
+}
+
+/// Iterator over the entries of a `TermDictionary`.
+#[derive(Debug)]
+pub(crate) struct Iter<'a> {
+    rest: &'a [u8],
+    decoder: EntryDecoder,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        self.decoder
+            .read(&mut self.rest)
+            .expect("the table of contents was checked when loaded")
+    }
+}
+
+/// Read a number of an entry, which must be there.
+fn number<R: Read>(f: &mut R) -> Result<u64> {
+    varint::read_u64_from(f).map_err(malformed)
+}
+
+fn add_u32(base: u32, delta: u64) -> Result<u32> {
+    u32::try_from(delta)
+        .ok()
+        .and_then(|delta| base.checked_add(delta))
+        .ok_or_else(out_of_range)
+}
+
+fn malformed(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => truncated(),
+        io::ErrorKind::InvalidData => Error::format(e.to_string()),
+        _ => Error::Io(e),
+    }
+}
+
+fn truncated() -> Error {
+    Error::format("table of contents is truncated")
+}
+
+fn out_of_range() -> Error {
+    Error::format("table of contents entry is out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Terms with long shared prefixes, in order, spanning several blocks.
+    fn terms() -> Vec<String> {
+        let mut terms: Vec<String> = (0..50)
+            .flat_map(|i| [format!("index{i:02}"), format!("indexed{i:02}")])
+            .chain(["a", "b", "zebra", "ü"].map(String::from))
+            .collect();
+        terms.sort();
+        terms
+    }
+
+    /// A table of `terms`, each with 10 bytes of data after the previous
+    /// one's and `i` documents, the 41st with skip points.
+    fn table(terms: &[String]) -> Vec<u8> {
+        let mut writer = DictionaryWriter::default();
+        for (i, term) in terms.iter().enumerate() {
+            let skips = match i {
+                40 => vec![
+                    Skip {
+                        last_id: 127,
+                        offset: 300,
+                    },
+                    Skip {
+                        last_id: 300,
+                        offset: 700,
+                    },
+                ],
+                _ => vec![],
+            };
+            let blocks: Vec<_> = (0..=skips.len() as u32)
+                .map(|b| BlockMax {
+                    max_tf: i as u32 + b,
+                    min_len: b,
+                })
+                .collect();
+            writer.add(&Entry {
+                term: term.clone(),
+                doc_count: i as u32,
+                occurrences: 3 * i as u64,
+                offset: 100 + 10 * i as u64,
+                nbytes: 10,
+                skips,
+                blocks,
+            });
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn entries_read_back() {
+        let terms = terms();
+        let table = table(&terms);
+        let dict = TermDictionary::load(table.clone(), u64::MAX).unwrap();
+        assert_eq!(dict.len(), terms.len());
+        for (i, entry) in dict.iter().enumerate() {
+            assert_eq!(entry.term, terms[i]);
+            assert_eq!(entry.doc_count, i as u32);
+            assert_eq!(entry.occurrences, 3 * i as u64);
+            assert_eq!(entry.offset, 100 + 10 * i as u64);
+            assert_eq!(entry.nbytes, 10);
+        }
+        let entry = dict.get(&terms[40]).unwrap();
+        assert_eq!(entry.skips[1].last_id, 300);
+        assert_eq!(entry.skips[1].offset, 700);
+        let maxima: Vec<_> =
+            entry.blocks.iter().map(|b| (b.max_tf, b.min_len)).collect();
+        assert_eq!(maxima, [(40, 0), (41, 1), (42, 2)]);
+        assert_eq!(dict.get(&terms[3]).unwrap().blocks.len(), 1);
+
+        // Reading the table linearly gives the same entries.
+        let mut f = io::Cursor::new(&table);
+        let len = entries_len(&mut f, 0, table.len() as u64).unwrap();
+        let mut entries = f.take(len);
+        let mut decoder = EntryDecoder::default();
+        for term in &terms {
+            assert_eq!(
+                decoder.read(&mut entries).unwrap().unwrap().term,
+                *term
+            );
+        }
+        assert!(decoder.read(&mut entries).unwrap().is_none());
+    }
+
+    #[test]
+    fn terms_are_found_by_name_and_prefix() {
+        let terms = terms();
+        let dict = TermDictionary::load(table(&terms), u64::MAX).unwrap();
+        for (i, term) in terms.iter().enumerate() {
+            assert_eq!(dict.get(term).unwrap().doc_count, i as u32);
+        }
+        for missing in ["", "0", "index", "index005", "indexed50", "zz"] {
+            assert!(dict.get(missing).is_none(), "{missing}");
+        }
+        let found: Vec<_> =
+            dict.with_prefix("indexed1").map(|e| e.term).collect();
+        let expected: Vec<_> =
+            (10..20).map(|i| format!("indexed{i}")).collect();
+        assert_eq!(found, expected);
+        assert_eq!(dict.with_prefix("index").count(), 100);
+        assert_eq!(dict.with_prefix("").count(), terms.len());
+        assert_eq!(dict.with_prefix("q").count(), 0);
+    }
+
+    #[test]
+    fn empty_table_has_no_terms() {
+        let dict =
+            TermDictionary::load(DictionaryWriter::default().finish(), 0)
+                .unwrap();
+        assert_eq!(dict.len(), 0);
+        assert!(dict.get("a").is_none());
+        assert_eq!(dict.iter().count(), 0);
+        assert_eq!(dict.with_prefix("").count(), 0);
+    }
+
+    #[test]
+    fn damaged_tables_are_an_error() {
+        let terms = terms();
+        let good = table(&terms);
+        for len in [0, 8, 15, good.len() / 2, good.len() - 1] {
+            let e = TermDictionary::load(good[..len].to_vec(), u64::MAX);
+            assert!(matches!(e, Err(Error::Format(_))), "length {len}");
+        }
+        // Data past the end of the term data.
+        assert!(TermDictionary::load(good.clone(), 500).is_err());
+        // Terms out of order.
+        let mut swapped = terms.clone();
+        swapped.swap(3, 4);
+        assert!(TermDictionary::load(table(&swapped), u64::MAX).is_err());
+        // Any damaged byte is caught when loading, never later.
+        for i in 0..good.len() {
+            let mut bytes = good.clone();
+            bytes[i] ^= 0x55;
+            if let Ok(dict) = TermDictionary::load(bytes, u64::MAX) {
+                for term in &terms {
+                    dict.get(term);
+                }
+                dict.iter().count();
+                dict.with_prefix("index").count();
+            }
+        }
+    }
+}
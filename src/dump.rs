@@ -0,0 +1,823 @@
+//! Exporting an index as text, and importing it back.
+//!
+//! `export` streams an index file into one of two text formats, so it can be
+//! inspected or post-processed with standard tools:
+//!
+//! * CSV, with a `term,doc_hash,offsets` header and one row per term and
+//!   document. Offsets are separated by spaces.
+//! * JSON Lines, with one record per line. The first line holds the settings
+//!   of the index, then comes one line per document of the document table,
+//!   then one line per term and document. Documents list the paths of
+//!   their duplicates, if they have any (see `Indexer::dedup`), and
+//!   `"truncated":true` if only their beginning was indexed (see
+//!   `DocLimits`):
+//!
+//! ```text
+//! {"codec":"none","analyzer":"stemmer=english\n"}
+//! {"doc":"40255d…","path":"docs/en.txt","token_count":19,"field_lens":{"path":3,"title":2},"mtime":0,"size":112,"language":"english"}
+//! {"term":"cat","doc":"40255d…","offsets":[1,7]}
+//! ```
+//!
+//! Only JSON Lines keeps the settings and the document table; CSV is for
+//! looking at postings. Deleted documents are left out of both. Indexes
+//! without positions (see `IndexSettings::omit_positions`) are dumped with
+//! offsets 0, 1, 2… standing for the number of occurrences. Those with
+//! byte offsets (see `IndexSettings::byte_offsets`) are dumped with a twin
+//! of each term, like `@cat`, whose offsets are byte offsets, and those with
+//! line numbers (see `IndexSettings::line_numbers`) with another, like
+//! `#cat`, whose offsets are line numbers. Encrypted indexes (see `crypt`)
+//! are dumped in the clear; their settings say so, and importing their dump
+//! encrypts it again.
+//!
+//! `import` turns a dump back into an index file, written with the current
+//! format version, which makes dumps a way to migrate indexes between
+//! versions, or to build small indexes by hand. A dump without settings
+//! gets the default ones; one without documents gets a document table made
+//! up from its postings, with empty paths and the number of words of each
+//! field, not counting their original case forms or twins, as lengths.
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{self, AnalyzerConfig};
+use crate::codec::Codec;
+use crate::error::{Error, Result};
+use crate::field::Field;
+use crate::header::IndexSettings;
+use crate::identity::DocIdentity;
+use crate::index::{Doc, DocInfo, Hit, InMemoryIndex, Offsets, MAX_DOC_WORDS};
+use crate::merge::{FileMerge, MERGED_FILENAME};
+use crate::read::IndexFileReader;
+use crate::tmp::TmpDir;
+use crate::tombstone::Tombstones;
+use crate::write::write_index_to_tmp_file;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The text formats of `export` and `import`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    #[default]
+    JsonLines,
+    Csv,
+}
+
+/// A line of a JSON Lines dump.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Record<'a> {
+    Settings {
+        codec: &'a str,
+        analyzer: String,
+        #[serde(skip_serializing_if = "is_false")]
+        bloom_filter: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        omit_positions: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        byte_offsets: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        line_numbers: bool,
+        #[serde(skip_serializing_if = "is_false")]
+        encrypted: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        identity: Option<&'static str>,
+    },
+    Document {
+        doc: String,
+        path: &'a str,
+        token_count: u32,
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        field_lens: BTreeMap<&'static str, u32>,
+        mtime: u64,
+        size: u64,
+        language: &'a str,
+        #[serde(skip_serializing_if = "<[String]>::is_empty")]
+        duplicates: &'a [String],
+        #[serde(skip_serializing_if = "is_false")]
+        truncated: bool,
+    },
+    Posting {
+        term: &'a str,
+        doc: String,
+        offsets: &'a [u32],
+    },
+}
+
+/// A line of a JSON Lines dump, as read by `import`. Only what identifies a
+/// record is required.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OwnedRecord {
+    Settings {
+        codec: String,
+        #[serde(default)]
+        analyzer: String,
+        #[serde(default)]
+        bloom_filter: bool,
+        #[serde(default)]
+        omit_positions: bool,
+        #[serde(default)]
+        byte_offsets: bool,
+        #[serde(default)]
+        line_numbers: bool,
+        #[serde(default)]
+        encrypted: bool,
+        #[serde(default)]
+        identity: Option<String>,
+    },
+    Document {
+        doc: String,
+        path: String,
+        #[serde(default)]
+        token_count: u32,
+        #[serde(default)]
+        field_lens: HashMap<String, u32>,
+        #[serde(default)]
+        mtime: u64,
+        #[serde(default)]
+        size: u64,
+        #[serde(default)]
+        language: String,
+        #[serde(default)]
+        duplicates: Vec<String>,
+        #[serde(default)]
+        truncated: bool,
+    },
+    Posting {
+        term: String,
+        doc: String,
+        offsets: Offsets,
+    },
+}
+
+/// Settings that are off, or at their default, are left out of dumps, so
+/// that they read the same as before the settings existed.
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// A record of a dump in either format.
+enum Line {
+    Settings(IndexSettings),
+    Document(Doc, DocInfo),
+    Posting(String, Doc, Offsets),
+}
+
+/// Write the contents of `index_file` to `out` in `format`, reading it one
+/// term at a time. Terms come in order, and the documents of each term by
+/// hash. Returns the number of postings written.
+pub fn export<P: AsRef<Path>, W: Write>(
+    index_file: P,
+    format: DumpFormat,
+    mut out: W,
+) -> Result<u64> {
+    let index_file = index_file.as_ref();
+    let tombstones = Tombstones::load(index_file)?;
+    let mut reader = IndexFileReader::open(index_file)?;
+
+    match format {
+        DumpFormat::Csv => writeln!(out, "term,doc_hash,offsets")?,
+        DumpFormat::JsonLines => {
+            let settings = reader.settings();
+            write_record(
+                &mut out,
+                &Record::Settings {
+                    codec: settings.codec.name(),
+                    analyzer: settings.analyzer.encode(),
+                    bloom_filter: settings.bloom_filter,
+                    omit_positions: settings.omit_positions,
+                    byte_offsets: settings.byte_offsets,
+                    line_numbers: settings.line_numbers,
+                    encrypted: settings.encrypted,
+                    identity: (settings.identity != DocIdentity::default())
+                        .then(|| settings.identity.name()),
+                },
+            )?;
+            for (doc, info) in reader.docs() {
+                if tombstones.contains(doc) {
+                    continue;
+                }
+                write_record(
+                    &mut out,
+                    &Record::Document {
+                        doc: doc.to_hex(),
+                        path: &info.path,
+                        token_count: info.token_count,
+                        field_lens: Field::NAMED
+                            .iter()
+                            .zip(info.field_lens)
+                            .filter(|(_, len)| *len > 0)
+                            .map(|(field, len)| (field.name(), len))
+                            .collect(),
+                        mtime: info.mtime,
+                        size: info.size,
+                        language: &info.language,
+                        duplicates: &info.duplicates,
+                        truncated: info.truncated,
+                    },
+                )?;
+            }
+        }
+    }
+
+    let mut count = 0;
+    for entry in reader.entries() {
+        let (term, mut postings) = entry?;
+        tombstones.purge(&mut postings);
+        let mut postings: Vec<_> = postings.into_iter().collect();
+        postings.sort_by(|a, b| a.0.hash.cmp(&b.0.hash));
+        for (doc, offsets) in postings {
+            match format {
+                DumpFormat::Csv => {
+                    let offsets: Vec<String> =
+                        offsets.iter().map(u32::to_string).collect();
+                    writeln!(
+                        out,
+                        "{},{},{}",
+                        csv_field(&term),
+                        doc.to_hex(),
+                        offsets.join(" ")
+                    )?;
+                }
+                DumpFormat::JsonLines => write_record(
+                    &mut out,
+                    &Record::Posting {
+                        term: &term,
+                        doc: doc.to_hex(),
+                        offsets: &offsets,
+                    },
+                )?,
+            }
+            count += 1;
+        }
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    writeln!(out)
+}
+
+/// Quote `text` for CSV if it contains a separator, a quote or a line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Build an index file in `output_dir` from `dump_file`, a dump in `format`,
+/// and return its path.
+///
+/// The dump is read once to collect the documents, then once more for each
+/// group of documents whose postings fit in `memory_budget` bytes. Each
+/// group is written to a temporary file, and those are merged like the
+/// indexer's.
+pub fn import<P: AsRef<Path>>(
+    dump_file: P,
+    format: DumpFormat,
+    output_dir: &Path,
+    memory_budget: usize,
+) -> Result<PathBuf> {
+    let dump_file = dump_file.as_ref();
+
+    // First pass: the settings, the document table, and how much memory
+    // the postings of each document take.
+    let mut settings = None;
+    let mut docs: Vec<(Doc, DocInfo)> = vec![];
+    let mut ids: HashMap<Doc, usize> = HashMap::new();
+    let mut sizes: Vec<usize> = vec![];
+    let mut doc_table = false;
+    let mut postings = false;
+    for_each_line(dump_file, format, |number, line| {
+        let error = |message: String| Error::Dump {
+            line: number,
+            message,
+        };
+        let identity = settings
+            .as_ref()
+            .map_or(DocIdentity::default(), |s: &IndexSettings| s.identity);
+        if let Line::Document(doc, _) | Line::Posting(_, doc, _) = &line {
+            if doc.hash.len() != identity.hash_len() {
+                return Err(error(format!(
+                    "bad document hash {:?} ({} ids have {} bytes)",
+                    doc.to_hex(),
+                    identity.name(),
+                    identity.hash_len()
+                )));
+            }
+        }
+        match line {
+            Line::Settings(s) => {
+                if settings.is_some() || !docs.is_empty() || postings {
+                    return Err(error("settings must come first".into()));
+                }
+                settings = Some(s);
+            }
+            Line::Document(doc, info) => {
+                if postings {
+                    return Err(error(
+                        "documents must come before postings".into(),
+                    ));
+                }
+                if ids.contains_key(&doc) {
+                    return Err(error(format!(
+                        "document {} is listed twice",
+                        doc.to_hex()
+                    )));
+                }
+                ids.insert(doc.clone(), docs.len());
+                docs.push((doc, info));
+                sizes.push(0);
+                doc_table = true;
+            }
+            Line::Posting(term, doc, offsets) => {
+                postings = true;
+                let id = match ids.get(&doc) {
+                    Some(&id) => id,
+                    None if doc_table => {
+                        return Err(error(format!(
+                            "document {} is not in the document table",
+                            doc.to_hex()
+                        )))
+                    }
+                    None => {
+                        let info = DocInfo {
+                            path: String::new(),
+                            token_count: 0,
+                            field_lens: [0; Field::NAMED.len()],
+                            mtime: 0,
+                            size: 0,
+                            language: String::new(),
+                            duplicates: vec![],
+                            truncated: false,
+                        };
+                        ids.insert(doc.clone(), docs.len());
+                        docs.push((doc, info));
+                        sizes.push(0);
+                        docs.len() - 1
+                    }
+                };
+                let (field, word) = Field::split(&term);
+                if !doc_table
+                    && !analyzer::is_exact(word)
+                    && !analyzer::is_twin(word)
+                {
+                    let info = &mut docs[id].1;
+                    let len = match field.named_index() {
+                        Some(i) => &mut info.field_lens[i],
+                        None => &mut info.token_count,
+                    };
+                    *len = u32::try_from(offsets.len())
+                        .ok()
+                        .and_then(|n| len.checked_add(n))
+                        .ok_or_else(|| {
+                            error(format!(
+                                "document of more than {} words",
+                                MAX_DOC_WORDS
+                            ))
+                        })?;
+                }
+                sizes[id] += term.len() + 8 + 4 * offsets.len();
+            }
+        }
+        Ok(())
+    })?;
+    let settings = settings.unwrap_or_default();
+
+    // Split the documents into groups that fit in the budget. Each document
+    // is in exactly one group, as merging concatenates document tables.
+    let mut groups: Vec<Vec<usize>> = vec![vec![]];
+    let mut group_size = 0;
+    for (id, &size) in sizes.iter().enumerate() {
+        if group_size > 0 && group_size + size > memory_budget {
+            groups.push(vec![]);
+            group_size = 0;
+        }
+        groups.last_mut().unwrap().push(id);
+        group_size += size;
+    }
+
+    let mut tmp_dir = TmpDir::new(output_dir);
+    let mut merge = FileMerge::with_settings(output_dir, settings.clone());
+    let last = groups.len() - 1;
+    for (n, group) in groups.into_iter().enumerate() {
+        // Ids of the group's documents in its own document table.
+        let local: HashMap<usize, u32> = group
+            .iter()
+            .enumerate()
+            .map(|(local, &id)| (id, local as u32))
+            .collect();
+        let mut terms: HashMap<String, Vec<(u32, Offsets)>> = HashMap::new();
+        for_each_line(dump_file, format, |_, line| {
+            if let Line::Posting(term, doc, offsets) = line {
+                if let Some(&id) = local.get(&ids[&doc]) {
+                    terms.entry(term).or_default().push((id, offsets));
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut index = InMemoryIndex::new();
+        for &id in &group {
+            index.word_count += docs[id].1.token_count as usize;
+            index.docs.push(docs[id].clone());
+        }
+        for (term, mut postings) in terms {
+            postings.sort_by_key(|(id, _)| *id);
+            let hits = postings
+                .into_iter()
+                .map(|(id, offsets)| to_hit(id, offsets))
+                .collect();
+            index.map.insert(term, hits);
+        }
+        if n == last {
+            // Merged straight from memory, after the others.
+            merge.add_index(index);
+        } else {
+            let file = write_index_to_tmp_file(index, &mut tmp_dir, &settings)?;
+            merge.add_file(file)?;
+        }
+    }
+    merge.finish()?;
+    Ok(output_dir.join(MERGED_FILENAME))
+}
+
+/// Read `dump_file` and call `f` on every record with its line number.
+fn for_each_line<F>(
+    dump_file: &Path,
+    format: DumpFormat,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(usize, Line) -> Result<()>,
+{
+    let reader = BufReader::new(File::open(dump_file)?);
+    for (i, text) in reader.lines().enumerate() {
+        let text = text?;
+        let number = i + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let line = match format {
+            DumpFormat::JsonLines => parse_json_line(&text),
+            DumpFormat::Csv
+                if number == 1 && text == "term,doc_hash,offsets" =>
+            {
+                continue
+            }
+            DumpFormat::Csv => parse_csv_line(&text),
+        };
+        let line = line.map_err(|message| Error::Dump {
+            line: number,
+            message,
+        })?;
+        f(number, line)?;
+    }
+    Ok(())
+}
+
+fn parse_json_line(text: &str) -> std::result::Result<Line, String> {
+    let record: OwnedRecord =
+        serde_json::from_str(text).map_err(|e| e.to_string())?;
+    Ok(match record {
+        OwnedRecord::Settings {
+            codec,
+            analyzer,
+            bloom_filter,
+            omit_positions,
+            byte_offsets,
+            line_numbers,
+            encrypted,
+            identity,
+        } => {
+            let codec = Codec::from_name(&codec)
+                .ok_or_else(|| format!("unknown codec {:?}", codec))?;
+            let identity = match identity {
+                Some(name) => {
+                    DocIdentity::from_name(&name).ok_or_else(|| {
+                        format!("unknown document identity {:?}", name)
+                    })?
+                }
+                None => DocIdentity::default(),
+            };
+            let analyzer =
+                AnalyzerConfig::decode(&analyzer).map_err(|e| e.to_string())?;
+            Line::Settings(IndexSettings {
+                codec,
+                analyzer,
+                bloom_filter,
+                omit_positions,
+                byte_offsets,
+                line_numbers,
+                encrypted,
+                identity,
+            })
+        }
+        OwnedRecord::Document {
+            doc,
+            path,
+            token_count,
+            field_lens: lens,
+            mtime,
+            size,
+            language,
+            duplicates,
+            truncated,
+        } => {
+            let mut field_lens = [0; Field::NAMED.len()];
+            for (name, len) in lens {
+                let i = Field::from_name(&name)
+                    .and_then(Field::named_index)
+                    .ok_or_else(|| format!("unknown field {:?}", name))?;
+                field_lens[i] = len;
+            }
+            Line::Document(
+                parse_doc(&doc)?,
+                DocInfo {
+                    path,
+                    token_count,
+                    field_lens,
+                    mtime,
+                    size,
+                    language,
+                    duplicates,
+                    truncated,
+                },
+            )
+        }
+        OwnedRecord::Posting { term, doc, offsets } => {
+            Line::Posting(term, parse_doc(&doc)?, check_offsets(offsets)?)
+        }
+    })
+}
+
+fn parse_csv_line(text: &str) -> std::result::Result<Line, String> {
+    // Only the term can be quoted: hashes and offsets never need it.
+    let (term, rest) = match text.strip_prefix('"') {
+        Some(quoted) => {
+            let mut term = String::new();
+            let mut chars = quoted.char_indices();
+            loop {
+                match chars.next() {
+                    Some((i, '"')) => {
+                        if quoted[i + 1..].starts_with('"') {
+                            term.push('"');
+                            chars.next();
+                        } else {
+                            break (term, &quoted[i + 1..]);
+                        }
+                    }
+                    Some((_, c)) => term.push(c),
+                    None => return Err("unclosed quote".into()),
+                }
+            }
+        }
+        None => match text.find(',') {
+            Some(i) => (text[..i].to_string(), &text[i..]),
+            None => (text.to_string(), ""),
+        },
+    };
+    let mut fields = rest
+        .strip_prefix(',')
+        .ok_or("expected 3 fields: term,doc_hash,offsets")?
+        .split(',');
+    let (Some(doc), Some(offsets), None) =
+        (fields.next(), fields.next(), fields.next())
+    else {
+        return Err("expected 3 fields: term,doc_hash,offsets".into());
+    };
+    let offsets = offsets
+        .split_whitespace()
+        .map(|n| n.parse().map_err(|_| format!("bad offset {:?}", n)))
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(Line::Posting(
+        term,
+        parse_doc(doc)?,
+        check_offsets(offsets)?,
+    ))
+}
+
+/// Parse a document id. Its length is checked against the settings of the
+/// dump by `import`.
+fn parse_doc(hex: &str) -> std::result::Result<Doc, String> {
+    Doc::from_hex(hex)
+        .filter(|doc| !doc.hash.is_empty())
+        .ok_or_else(|| format!("bad document hash {:?}", hex))
+}
+
+/// Offsets are stored in increasing order, without repeats. Dumps written
+/// by hand may have them in any order.
+fn check_offsets(mut offsets: Offsets) -> std::result::Result<Offsets, String> {
+    if offsets.is_empty() {
+        return Err("posting without offsets".into());
+    }
+    offsets.sort_unstable();
+    offsets.dedup();
+    Ok(offsets)
+}
+
+/// Lay out a posting as an in-memory `Hit`.
+fn to_hit(id: u32, offsets: Offsets) -> Hit {
+    let mut hit = Vec::with_capacity(8 + 4 * offsets.len());
+    hit.write_u32::<LittleEndian>(id).unwrap();
+    hit.write_u32::<LittleEndian>(offsets.len() as u32).unwrap();
+    for offset in offsets {
+        hit.write_u32::<LittleEndian>(offset).unwrap();
+    }
+    hit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::fixtures::{doc, write_index};
+    use crate::index::ParsedIndex;
+
+    fn export_to_string(file: &Path, format: DumpFormat) -> (String, u64) {
+        let mut out = vec![];
+        let count = export(file, format, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), count)
+    }
+
+    #[test]
+    fn json_lines_hold_settings_documents_and_postings() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["the cat", "the dog"]);
+        let (dump, count) = export_to_string(&file, DumpFormat::JsonLines);
+        assert_eq!(count, 4);
+        let lines: Vec<serde_json::Value> = dump
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1 + 2 + 4);
+        assert_eq!(lines[0]["codec"], "none");
+        assert_eq!(lines[1]["doc"], doc(0).to_hex());
+        assert_eq!(lines[2]["token_count"], 2);
+        let postings: Vec<_> = lines[3..]
+            .iter()
+            .map(|l| (l["term"].as_str().unwrap(), l["offsets"].clone()))
+            .collect();
+        assert_eq!(postings[0], ("cat", serde_json::json!([1])));
+        assert_eq!(postings[1].0, "dog");
+        assert_eq!(postings[2], ("the", serde_json::json!([0])));
+        assert_eq!(lines[5]["doc"], doc(0).to_hex());
+        assert_eq!(lines[6]["doc"], doc(1).to_hex());
+    }
+
+    #[test]
+    fn csv_has_one_row_per_posting() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["cat cat", "a dog"]);
+        let (dump, count) = export_to_string(&file, DumpFormat::Csv);
+        assert_eq!(count, 3);
+        let expected = format!(
+            "term,doc_hash,offsets\na,{1},0\ncat,{0},0 1\ndog,{1},1\n",
+            doc(0).to_hex(),
+            doc(1).to_hex()
+        );
+        assert_eq!(dump, expected);
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("plain"), "plain");
+    }
+
+    #[test]
+    fn deleted_documents_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["a cat", "a dog"]);
+        let mut tombstones = Tombstones::default();
+        tombstones.add(doc(0));
+        tombstones.save(&file).unwrap();
+        for format in [DumpFormat::JsonLines, DumpFormat::Csv] {
+            let (dump, count) = export_to_string(&file, format);
+            assert_eq!(count, 2);
+            assert!(!dump.contains(&doc(0).to_hex()));
+            assert!(!dump.contains("cat"));
+        }
+    }
+
+    /// Export `file` in `format` and import the dump with `memory_budget`.
+    fn round_trip(
+        file: &Path,
+        format: DumpFormat,
+        memory_budget: usize,
+    ) -> ParsedIndex {
+        let dir = file.parent().unwrap();
+        let dump = dir.join("dump.txt");
+        export(file, format, File::create(&dump).unwrap()).unwrap();
+        let imported = dir.join(format!("imported-{memory_budget}"));
+        fs::create_dir(&imported).unwrap();
+        let file = import(&dump, format, &imported, memory_budget).unwrap();
+        IndexFileReader::get_index_from_file(file).unwrap()
+    }
+
+    #[test]
+    fn json_lines_dumps_import_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["the cat sat", "the dog and the cat", "a bird"];
+        let file = write_index(dir.path(), &texts);
+        let expected = IndexFileReader::get_index_from_file(&file).unwrap();
+        // A budget of one byte puts every document in a group of its own.
+        for memory_budget in [1 << 20, 1] {
+            let imported =
+                round_trip(&file, DumpFormat::JsonLines, memory_budget);
+            assert_eq!(imported.map, expected.map);
+            assert_eq!(imported.docs, expected.docs);
+        }
+    }
+
+    #[test]
+    fn csv_dumps_import_without_a_document_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["the cat sat", "a dog"]);
+        let expected = IndexFileReader::get_index_from_file(&file).unwrap();
+        let imported = round_trip(&file, DumpFormat::Csv, 1 << 20);
+        assert_eq!(imported.map, expected.map);
+        let info = &imported.docs[&doc(0)];
+        assert_eq!((info.path.as_str(), info.token_count), ("", 3));
+    }
+
+    /// Import `dump` and return the line of the error.
+    fn error_line(dump: &str, format: DumpFormat) -> usize {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("dump.txt");
+        fs::write(&file, dump).unwrap();
+        match import(&file, format, dir.path(), 1 << 20) {
+            Err(Error::Dump { line, .. }) => line,
+            other => panic!("expected a dump error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_dumps_report_the_line() {
+        let hex = doc(0).to_hex();
+        let settings = r#"{"codec":"none","analyzer":""}"#;
+        let document = format!(
+            r#"{{"doc":"{hex}","path":"","token_count":1,"mtime":0,"language":""}}"#
+        );
+        let posting = |doc: &str, offsets: &str| {
+            format!(r#"{{"term":"cat","doc":"{doc}","offsets":{offsets}}}"#)
+        };
+        let json = |lines: &[&str]| lines.join("\n");
+        let other = doc(1).to_hex();
+        let cases = [
+            (json(&[settings, "{not json"]), 2),
+            (json(&[&posting(&hex, "[0]"), settings]), 2),
+            (json(&[&document, &document]), 2),
+            (json(&[settings, &document, &posting(&other, "[0]")]), 3),
+            (json(&[&posting(&hex, "[]")]), 1),
+            (json(&[&posting("abc", "[0]")]), 1),
+        ];
+        for (dump, line) in cases {
+            assert_eq!(
+                error_line(&dump, DumpFormat::JsonLines),
+                line,
+                "{dump}"
+            );
+        }
+
+        let csv =
+            format!("term,doc_hash,offsets\ncat,{hex},0\n\"cat,{hex},0\n");
+        assert_eq!(error_line(&csv, DumpFormat::Csv), 3);
+        let csv = format!("cat,{hex},0 x\n");
+        assert_eq!(error_line(&csv, DumpFormat::Csv), 1);
+        let csv = format!("cat,{hex}\n");
+        assert_eq!(error_line(&csv, DumpFormat::Csv), 1);
+    }
+
+    #[test]
+    fn csv_terms_can_be_quoted() {
+        let hex = doc(0).to_hex();
+        let line = format!("\"a \"\"b\"\",c\",{hex},2 1 2");
+        match parse_csv_line(&line).unwrap() {
+            Line::Posting(term, doc, offsets) => {
+                assert_eq!(term, "a \"b\",c");
+                assert_eq!(doc.to_hex(), hex);
+                assert_eq!(offsets, vec![1, 2]);
+            }
+            _ => panic!("expected a posting"),
+        }
+    }
+
+    #[test]
+    fn empty_index_has_only_a_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &[]);
+        let (dump, count) = export_to_string(&file, DumpFormat::Csv);
+        assert_eq!((dump.as_str(), count), ("term,doc_hash,offsets\n", 0));
+        let (dump, count) = export_to_string(&file, DumpFormat::JsonLines);
+        assert_eq!((dump.lines().count(), count), (1, 0));
+    }
+}
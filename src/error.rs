@@ -0,0 +1,91 @@
+//! The error type of the crate.
+//!
+//! Reading an index file can fail in more ways than the underlying I/O: the
+//! file may be truncated, corrupt, or not an index at all. Those cases get
+//! their own variants, so that callers can tell a bad index from a failing
+//! disk.
+
+use std::io;
+use std::string::FromUtf8Error;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::query::SyntaxError;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// An error while reading, writing or merging index files.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The underlying file operation failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The file is not a valid index file.
+    #[error("malformed index file: {0}")]
+    Format(String),
+    /// A term or path stored in the index is not valid UTF-8.
+    #[error("malformed index file: {0}")]
+    Utf8(#[from] FromUtf8Error),
+    /// The file is a valid index, but uses something this build can't
+    /// handle, such as a newer format version or a codec whose feature is
+    /// disabled.
+    #[error("unsupported index file: {0}")]
+    Unsupported(String),
+    /// Index files that can't be combined, e.g. built with different
+    /// analyzers.
+    #[error("{0}")]
+    Incompatible(String),
+    /// A search query could not be parsed.
+    #[error(transparent)]
+    Query(#[from] SyntaxError),
+    /// Another process is writing to the directory (see `lock`).
+    #[error("{0} is being written by another process")]
+    Locked(String),
+    /// A text dump of an index (see `dump`) could not be imported.
+    #[error("malformed dump, line {line}: {message}")]
+    Dump { line: usize, message: String },
+    /// An encrypted index file can't be read or written without a key, or
+    /// the key is wrong (see `crypt`).
+    #[error("{0}")]
+    Key(String),
+}
+
+/// A `Result` with the crate's `Error`.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn format(msg: impl Into<String>) -> Error {
+        Error::Format(msg.into())
+    }
+
+    /// For `map_err` on reads of `what`: running out of data there means
+    /// that the file is cut short, not that reading failed.
+    pub(crate) fn truncated(what: &str) -> impl Fn(io::Error) -> Error + '_ {
+        move |e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                Error::format(format!("{} is truncated", what))
+            } else {
+                Error::Io(e)
+            }
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            Error::Format(_) | Error::Utf8(_) | Error::Dump { .. } => {
+                io::Error::new(io::ErrorKind::InvalidData, e)
+            }
+            Error::Unsupported(_) => {
+                io::Error::new(io::ErrorKind::Unsupported, e)
+            }
+            Error::Incompatible(_) | Error::Query(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, e)
+            }
+            Error::Locked(_) => io::Error::new(io::ErrorKind::ResourceBusy, e),
+            Error::Key(_) => io::Error::new(io::ErrorKind::PermissionDenied, e),
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Getting the plain text out of documents.
+//!
+//! Indexing works on plain text, but many document collections are made of
+//! HTML pages, Markdown notes or PDFs. An `Extractor` turns the contents of
+//! one kind of file into the text that gets indexed: markup is dropped and
+//! only the words a reader would see are kept.
+//!
+//! `Extractors` holds a list of them and picks one for each file, by file
+//! extension or by sniffing the first bytes of the file. Files no extractor
+//! claims are read as plain text, as described in the `decode` module.
+//!
+//! Extractors can also find the title of a document, which is indexed as a
+//! separate field (see the `field` module).
+//!
+//! Snippets are cut from the extracted text, so searches must extract
+//! documents the same way as the indexer did.
+
+use std::io;
+use std::path::Path;
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::archive::read_file;
+use crate::decode::{binary_file, decode};
+use crate::routing::{Route, Router};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// How many bytes at the start of a file extractors get to look at to
+/// recognize it.
+const SNIFF_LEN: usize = 1024;
+
+/// The names of the extractors routes can choose (see `routing`): `text`
+/// reads files as plain text.
+pub const EXTRACTORS: &[&str] = &["text", "html", "markdown", "pdf"];
+
+/// What an extractor got out of a document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Extracted {
+    /// The text of the document, indexed as its body.
+    pub text: String,
+    /// The title of the document, if it has one.
+    pub title: Option<String>,
+}
+
+impl Extracted {
+    /// Just the body text.
+    pub fn text(text: String) -> Extracted {
+        Extracted { text, title: None }
+    }
+}
+
+/// Turns the contents of some kind of file into plain text.
+pub trait Extractor: Send + Sync {
+    /// Short name of the file format, e.g. "html".
+    fn name(&self) -> &'static str;
+
+    /// True if this extractor handles the file at `path`, which starts with
+    /// the bytes `head`.
+    fn accepts(&self, path: &Path, head: &[u8]) -> bool;
+
+    /// Extract the text of a file, given its contents. Fails with
+    /// `InvalidData` if the contents aren't what was expected.
+    fn extract(&self, bytes: &[u8]) -> io::Result<Extracted>;
+}
+
+/// The extractors tried on each file, in order.
+pub struct Extractors {
+    list: Vec<Box<dyn Extractor>>,
+    /// The extractor of the files some routes choose one for, `None` for
+    /// plain text.
+    routes: Router<Option<Box<dyn Extractor>>>,
+}
+
+impl Default for Extractors {
+    /// The built-in extractors: HTML, Markdown, and PDF if the `pdf` feature
+    /// is enabled.
+    fn default() -> Self {
+        let extractors = Extractors::plain_text().with(Html).with(Markdown);
+        #[cfg(feature = "pdf")]
+        let extractors = extractors.with(Pdf);
+        extractors
+    }
+}
+
+impl Extractors {
+    /// No extractors: every file is read as plain text.
+    pub fn plain_text() -> Extractors {
+        Extractors {
+            list: vec![],
+            routes: Router::default(),
+        }
+    }
+
+    /// Also try `extractor`, after the ones already added.
+    pub fn with<E: Extractor + 'static>(mut self, extractor: E) -> Extractors {
+        self.list.push(Box::new(extractor));
+        self
+    }
+
+    /// Extract the files that `routes` choose an extractor for with that
+    /// one, whatever their contents (see `routing`). Fails if one of them
+    /// is unknown or disabled.
+    pub fn routed(mut self, routes: &[Route]) -> io::Result<Extractors> {
+        self.routes = Router::new(routes, |route| {
+            route.extractor.as_deref().map(named).transpose()
+        })?;
+        Ok(self)
+    }
+
+    /// The extractor that handles the file at `path` with contents `bytes`,
+    /// if any.
+    pub fn find(&self, path: &Path, bytes: &[u8]) -> Option<&dyn Extractor> {
+        if let Some(routed) = self.routes.get(path) {
+            return routed.as_deref();
+        }
+        let head = &bytes[..bytes.len().min(SNIFF_LEN)];
+        self.list
+            .iter()
+            .find(|e| e.accepts(path, head))
+            .map(|e| e.as_ref())
+    }
+
+    /// Extract the text of the file at `path`, with contents `bytes`.
+    pub fn extract(&self, path: &Path, bytes: &[u8]) -> io::Result<Extracted> {
+        match self.find(path, bytes) {
+            Some(extractor) => extractor.extract(bytes),
+            None => decode_text(bytes).map(Extracted::text),
+        }
+    }
+
+    /// Read the file at `path` and extract its text. `path` may also be the
+    /// path of an archive member (see the `archive` module).
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> io::Result<Extracted> {
+        let path = path.as_ref();
+        self.extract(path, &read_file(path)?)
+    }
+}
+
+/// The extractor named `name` in `EXTRACTORS`, `None` for plain text.
+fn named(name: &str) -> io::Result<Option<Box<dyn Extractor>>> {
+    match name {
+        "text" => Ok(None),
+        "html" => Ok(Some(Box::new(Html))),
+        "markdown" => Ok(Some(Box::new(Markdown))),
+        #[cfg(feature = "pdf")]
+        "pdf" => Ok(Some(Box::new(Pdf))),
+        #[cfg(not(feature = "pdf"))]
+        "pdf" => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the pdf extractor requires the `pdf` feature",
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown extractor {}", name),
+        )),
+    }
+}
+
+/// HTML pages: tags, comments, scripts and style sheets are dropped and
+/// character references are decoded. The title is the `<title>` element.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Html;
+
+impl Extractor for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn accepts(&self, path: &Path, head: &[u8]) -> bool {
+        if has_extension(path, &["html", "htm", "xhtml"]) {
+            return true;
+        }
+        let head = String::from_utf8_lossy(head);
+        let head = head.trim_start_matches('\u{feff}').trim_start();
+        starts_with_ignore_case(head, "<!doctype html")
+            || starts_with_ignore_case(head, "<html")
+    }
+
+    fn extract(&self, bytes: &[u8]) -> io::Result<Extracted> {
+        let html = decode_text(bytes)?;
+        let title = find_ignore_case(&html, "<title").and_then(|start| {
+            let start = start + html[start..].find('>')? + 1;
+            let end = start + find_ignore_case(&html[start..], "</title")?;
+            Some(strip_html(&html[start..end]).trim().to_string())
+        });
+        Ok(Extracted {
+            text: strip_html(&html),
+            title: title.filter(|t| !t.is_empty()),
+        })
+    }
+}
+
+/// Markdown documents: the text of headings, paragraphs, lists, tables and
+/// code is kept, without the formatting. Embedded HTML is stripped. The
+/// title is the first heading.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Markdown;
+
+impl Extractor for Markdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn accepts(&self, path: &Path, _head: &[u8]) -> bool {
+        has_extension(path, &["md", "markdown", "mdown", "mkd"])
+    }
+
+    fn extract(&self, bytes: &[u8]) -> io::Result<Extracted> {
+        let source = decode_text(bytes)?;
+        let mut text = String::with_capacity(source.len());
+        // Where the first heading starts in `text`, and once it's over, its
+        // text.
+        let mut heading = None;
+        let mut title = None;
+        for event in Parser::new(&source) {
+            match event {
+                Event::Start(Tag::Heading { .. }) if heading.is_none() => {
+                    heading = Some(text.len());
+                }
+                Event::End(TagEnd::Heading(_)) if title.is_none() => {
+                    let start = heading.unwrap_or(0);
+                    title = Some(text[start..].trim().to_string());
+                    text.push('\n');
+                }
+                Event::Text(s) | Event::Code(s) => text.push_str(&s),
+                Event::Html(s) | Event::InlineHtml(s) => {
+                    text.push_str(&strip_html(&s))
+                }
+                Event::SoftBreak | Event::HardBreak | Event::Rule => {
+                    text.push('\n')
+                }
+                Event::End(
+                    TagEnd::Paragraph
+                    | TagEnd::Heading(_)
+                    | TagEnd::Item
+                    | TagEnd::CodeBlock
+                    | TagEnd::TableCell
+                    | TagEnd::TableRow,
+                ) => text.push('\n'),
+                _ => {}
+            }
+        }
+        Ok(Extracted {
+            text,
+            title: title.filter(|t| !t.is_empty()),
+        })
+    }
+}
+
+/// PDF documents, through the `pdf-extract` crate.
+#[cfg(feature = "pdf")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pdf;
+
+#[cfg(feature = "pdf")]
+impl Extractor for Pdf {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn accepts(&self, path: &Path, head: &[u8]) -> bool {
+        has_extension(path, &["pdf"]) || head.starts_with(b"%PDF-")
+    }
+
+    fn extract(&self, bytes: &[u8]) -> io::Result<Extracted> {
+        pdf_extract::extract_text_from_mem(bytes)
+            .map(Extracted::text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+fn decode_text(bytes: &[u8]) -> io::Result<String> {
+    decode(bytes).map(|d| d.text).ok_or_else(binary_file)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions.iter().any(|e| ext.eq_ignore_ascii_case(e))
+        })
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// Elements that start a new line of text when rendered. Tags of other
+/// elements are removed without a trace, so that `<b>w</b>ord` stays one
+/// word.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Elements whose contents are not text of the page. The title is a field
+/// of its own (see `Extracted::title`).
+const RAW_ELEMENTS: &[&str] = &["script", "style", "template", "title"];
+
+/// The text of an HTML document or fragment.
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('&') {
+            let (c, len) = char_reference(rest).unwrap_or(('&', 1));
+            text.push(c);
+            rest = &rest[len..];
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            // Not a tag after all.
+            text.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            text.push('\n');
+        } else if !tag.starts_with('/') && RAW_ELEMENTS.contains(&name.as_str())
+        {
+            // Skip everything up to the closing tag.
+            let close = format!("</{}", name);
+            rest = find_ignore_case(rest, &close)
+                .and_then(|i| rest[i..].find('>').map(|end| i + end + 1))
+                .map_or("", |i| &rest[i..]);
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| starts_with_ignore_case(&haystack[i..], needle))
+}
+
+/// Decode the character reference at the start of `s`, like `&amp;` or
+/// `&#233;`. Returns the character it stands for, if it is a known
+/// reference, and the number of bytes it takes up.
+fn char_reference(s: &str) -> Option<(char, usize)> {
+    let end = s[1..].find(';').map(|i| i + 1).filter(|&i| i <= 10)?;
+    let name = &s[1..end];
+    let c = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => {
+                    u32::from_str_radix(&hex[1..], 16).ok()
+                }
+                Some(dec) => dec.parse().ok(),
+                None => None,
+            };
+            code.and_then(char::from_u32)
+        }
+    };
+    c.map(|c| (c, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extracted(
+        path: &str,
+        contents: &str,
+    ) -> (Option<&'static str>, Extracted) {
+        let extractors = Extractors::default();
+        let path = Path::new(path);
+        let name = extractors.find(path, contents.as_bytes()).map(|e| e.name());
+        (name, extractors.extract(path, contents.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn extractors_are_chosen_by_extension_or_contents() {
+        let cases = [
+            ("page.html", "plain words", Some("html")),
+            ("PAGE.HTM", "plain words", Some("html")),
+            ("page.xhtml", "plain words", Some("html")),
+            ("page", "\u{feff} <!DOCTYPE html><p>x", Some("html")),
+            ("page.txt", "<HTML><body>x", Some("html")),
+            ("notes.md", "# Notes", Some("markdown")),
+            ("notes.Markdown", "plain words", Some("markdown")),
+            ("notes.txt", "# Notes", None),
+            ("page.txt", "text about <html>", None),
+            ("README", "plain words", None),
+        ];
+        for (path, contents, expected) in cases {
+            assert_eq!(extracted(path, contents).0, expected, "{}", path);
+        }
+        let routed = Extractors::default()
+            .routed(&[Route {
+                glob: "*.txt".to_string(),
+                extractor: Some("markdown".to_string()),
+                analyzer: None,
+            }])
+            .unwrap();
+        let found = routed.find(Path::new("a.txt"), b"<html>");
+        assert_eq!(found.map(|e| e.name()), Some("markdown"));
+        let plain = Extractors::plain_text();
+        assert!(plain.find(Path::new("page.html"), b"<html>").is_none());
+    }
+
+    #[test]
+    fn titles_and_text_are_extracted() {
+        let cases = [
+            (
+                "page.html",
+                "<html><head><title> The &amp; <b>Title</b> </title>\
+                 <style>p { color: red }</style></head>\
+                 <body><h1>Head</h1><p>One <b>w</b>ord<br>two&#233;\
+                 <script>var x = 1;</script><!-- gone --></p></body>",
+                Some("The & Title"),
+                "\nHead\n\nOne word\ntwoé\n",
+            ),
+            ("page.html", "<TITLE></TITLE><p>a < b", None, "\na < b"),
+            (
+                "notes.md",
+                "Intro *text*\n\n# First `code`\n\n## Second\n\n\
+                 - item <b>bold</b>\n",
+                Some("First code"),
+                "Intro text\nFirst code\nSecond\nitem bold\n",
+            ),
+            ("notes.md", "no heading", None, "no heading\n"),
+            (
+                "notes.txt",
+                "<title>kept</title>",
+                None,
+                "<title>kept</title>",
+            ),
+        ];
+        for (path, contents, title, text) in cases {
+            let (_, extracted) = extracted(path, contents);
+            assert_eq!(extracted.title.as_deref(), title, "{}", contents);
+            assert_eq!(extracted.text, text, "{}", contents);
+        }
+    }
+
+    #[test]
+    fn binary_files_are_not_extracted() {
+        let bytes = [0u8, 159, 146, 150, 0, 1, 2];
+        let extractors = Extractors::default();
+        for path in ["page.html", "notes.md", "data.bin"] {
+            let error =
+                extractors.extract(Path::new(path), &bytes).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", path);
+        }
+    }
+}
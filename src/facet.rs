@@ -0,0 +1,292 @@
+//! Counting search results by document attribute.
+//!
+//! A `Facet` is something the document table records about every document,
+//! like the extension of its file. Searches can count how many of the
+//! matching documents have each value of some facets, e.g. to show that 40
+//! hits are `.md` files and 12 are `.txt` files, and let the user narrow the
+//! search down.
+//!
+//! The value of each facet is worked out once per document, when the facets
+//! are set up (see `Searcher::with_facets`), so counting only takes a lookup
+//! per matching document.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::index::{Doc, DocInfo};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// An attribute of documents that search results can be counted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Facet {
+    /// The directory, right under the one all documents of the index are
+    /// in, that a document's file is in; `.` for files right in that one.
+    Directory,
+    /// The extension of the document's file, like `md`.
+    Extension,
+    /// The language the document was detected to be in (see `language`).
+    Language,
+}
+
+impl Facet {
+    pub const ALL: [Facet; 3] =
+        [Facet::Directory, Facet::Extension, Facet::Language];
+
+    /// The name used on the command line and in JSON output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Facet::Directory => "directory",
+            Facet::Extension => "extension",
+            Facet::Language => "language",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Facet> {
+        Facet::ALL.into_iter().find(|facet| facet.name() == name)
+    }
+}
+
+/// How many matching documents have each value of some facets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FacetCounts {
+    counts: Vec<(Facet, Vec<(String, usize)>)>,
+}
+
+impl FacetCounts {
+    /// The values of `facet` with their number of documents, most frequent
+    /// first, then in alphabetical order. Empty for facets that weren't
+    /// counted. Documents without a value, like files without extension,
+    /// aren't counted.
+    pub fn get(&self, facet: Facet) -> &[(String, usize)] {
+        self.counts
+            .iter()
+            .find(|(f, _)| *f == facet)
+            .map_or(&[], |(_, values)| values)
+    }
+
+    /// The facets counted, with their values, in the order they were asked
+    /// for.
+    pub fn iter(&self) -> impl Iterator<Item = (Facet, &[(String, usize)])> {
+        self.counts
+            .iter()
+            .map(|(facet, values)| (*facet, values.as_slice()))
+    }
+
+    /// True if no facet was counted.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+/// The value of some facets for every document of one or more indexes.
+#[derive(Debug, Default)]
+pub(crate) struct FacetValues {
+    columns: Vec<Column>,
+}
+
+/// The values of one facet.
+#[derive(Debug)]
+struct Column {
+    facet: Facet,
+    /// The distinct values.
+    values: Vec<String>,
+    /// The position of the value of each document in `values`. Documents
+    /// without a value are left out.
+    of_doc: HashMap<Doc, u32>,
+}
+
+impl FacetValues {
+    /// Work out the value of `facets` for `docs`. A document listed twice
+    /// keeps its first value.
+    pub fn new<'a, I>(facets: &[Facet], docs: I) -> FacetValues
+    where
+        I: IntoIterator<Item = (&'a Doc, &'a DocInfo)>,
+        I::IntoIter: Clone,
+    {
+        let docs = docs.into_iter();
+        let root = common_dir(docs.clone().map(|(_, info)| &info.path));
+        let columns = facets
+            .iter()
+            .map(|&facet| {
+                let mut column = Column {
+                    facet,
+                    values: vec![],
+                    of_doc: HashMap::new(),
+                };
+                let mut ids: HashMap<String, u32> = HashMap::new();
+                for (doc, info) in docs.clone() {
+                    if column.of_doc.contains_key(doc) {
+                        continue;
+                    }
+                    let Some(value) = value_of(facet, info, &root) else {
+                        continue;
+                    };
+                    let id = *ids.entry(value).or_insert_with_key(|value| {
+                        column.values.push(value.clone());
+                        column.values.len() as u32 - 1
+                    });
+                    column.of_doc.insert(doc.clone(), id);
+                }
+                column
+            })
+            .collect();
+        FacetValues { columns }
+    }
+
+    /// Count the values of `docs`, each of which must appear only once.
+    pub fn count<'a, I>(&self, docs: I) -> FacetCounts
+    where
+        I: IntoIterator<Item = &'a Doc>,
+        I::IntoIter: Clone,
+    {
+        let docs = docs.into_iter();
+        let counts = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut counts = vec![0; column.values.len()];
+                for doc in docs.clone() {
+                    if let Some(&id) = column.of_doc.get(doc) {
+                        counts[id as usize] += 1;
+                    }
+                }
+                let mut values: Vec<(String, usize)> = column
+                    .values
+                    .iter()
+                    .zip(counts)
+                    .filter(|(_, count)| *count > 0)
+                    .map(|(value, count)| (value.clone(), count))
+                    .collect();
+                values.sort_by(|(a, a_count), (b, b_count)| {
+                    b_count.cmp(a_count).then_with(|| a.cmp(b))
+                });
+                (column.facet, values)
+            })
+            .collect();
+        FacetCounts { counts }
+    }
+}
+
+/// The value of `facet` for the document `info` describes, with `root` the
+/// directory all documents are in.
+fn value_of(facet: Facet, info: &DocInfo, root: &Path) -> Option<String> {
+    match facet {
+        Facet::Directory if info.path.is_empty() => None,
+        Facet::Directory => {
+            let parent = Path::new(&info.path).parent()?;
+            let dir = match parent.strip_prefix(root).ok()?.components().next()
+            {
+                Some(dir) => dir.as_os_str().to_string_lossy().into_owned(),
+                None => ".".to_string(),
+            };
+            Some(dir)
+        }
+        Facet::Extension => Path::new(&info.path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase()),
+        Facet::Language => {
+            Some(info.language.clone()).filter(|language| !language.is_empty())
+        }
+    }
+}
+
+/// The deepest directory that all of `paths` are in.
+fn common_dir<'a>(paths: impl Iterator<Item = &'a String>) -> PathBuf {
+    let mut common: Option<Vec<Component>> = None;
+    for path in paths.filter(|path| !path.is_empty()) {
+        let Some(parent) = Path::new(path.as_str()).parent() else {
+            continue;
+        };
+        let components: Vec<Component> = parent.components().collect();
+        common = Some(match common {
+            None => components,
+            Some(mut common) => {
+                let shared = common
+                    .iter()
+                    .zip(&components)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                common.truncate(shared);
+                common
+            }
+        });
+    }
+    common.unwrap_or_default().into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::doc;
+
+    fn info(path: &str, language: &str) -> DocInfo {
+        DocInfo {
+            path: path.to_string(),
+            token_count: 1,
+            field_lens: [0; 2],
+            mtime: 0,
+            size: 0,
+            language: language.to_string(),
+            duplicates: vec![],
+            truncated: false,
+        }
+    }
+
+    fn counts(paths: &[(&str, &str)], matching: &[u8]) -> FacetCounts {
+        let docs: Vec<(Doc, DocInfo)> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, (path, language))| (doc(i as u8), info(path, language)))
+            .collect();
+        let values =
+            FacetValues::new(&Facet::ALL, docs.iter().map(|(d, i)| (d, i)));
+        let matching: Vec<Doc> = matching.iter().map(|&n| doc(n)).collect();
+        values.count(&matching)
+    }
+
+    #[test]
+    fn matching_documents_are_counted_by_value() {
+        let docs = [
+            ("/notes/rust/a.md", "english"),
+            ("/notes/rust/b.MD", "english"),
+            ("/notes/go/c.txt", "french"),
+            ("/notes/d.md", ""),
+            ("/notes/rust/deep/e", "english"),
+            ("", "german"),
+        ];
+        let all = counts(&docs, &[0, 1, 2, 3, 4, 5]);
+        let owned = |values: &[(&str, usize)]| -> Vec<(String, usize)> {
+            values.iter().map(|&(v, n)| (v.to_string(), n)).collect()
+        };
+        let cases: [(Facet, &[(&str, usize)]); 3] = [
+            (Facet::Directory, &[("rust", 3), (".", 1), ("go", 1)]),
+            (Facet::Extension, &[("md", 3), ("txt", 1)]),
+            (
+                Facet::Language,
+                &[("english", 3), ("french", 1), ("german", 1)],
+            ),
+        ];
+        for (facet, expected) in cases {
+            assert_eq!(all.get(facet), owned(expected), "{}", facet.name());
+        }
+        let facets: Vec<Facet> = all.iter().map(|(facet, _)| facet).collect();
+        assert_eq!(facets, Facet::ALL);
+
+        // Only the matching documents count.
+        let some = counts(&docs, &[2, 3]);
+        assert_eq!(some.get(Facet::Directory), owned(&[(".", 1), ("go", 1)]));
+        assert!(counts(&docs, &[]).get(Facet::Extension).is_empty());
+    }
+
+    #[test]
+    fn facets_are_named() {
+        for facet in Facet::ALL {
+            assert_eq!(Facet::from_name(facet.name()), Some(facet));
+        }
+        assert_eq!(Facet::from_name("size"), None);
+        assert!(FacetCounts::default().is_empty());
+    }
+}
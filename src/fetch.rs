@@ -0,0 +1,291 @@
+//! Index files searched through async fetches of their parts.
+//!
+//! `FetchedIndex` looks terms up in an index file it doesn't hold, such as
+//! one on a web server, searched from a browser: the header, and the tables
+//! at the end of the file, are fetched when it is opened, and the hits of a
+//! term when it is looked up, each as a range of bytes, through `Fetch`.
+//! Nothing here needs an async runtime or a file system, so `Fetch` can be
+//! the `fetch` of a browser, on wasm32, as well as any HTTP client.
+//!
+//! ```text
+//! let index = FetchedIndex::open(HttpRanges::new(url)).await?;
+//! let hits = index.lookup("cat").await?;
+//! let docs = index.intersect(&["cat", "dog"]).await?;
+//! ```
+//!
+//! Deleted documents, in a file of their own (see `tombstone`), and
+//! encrypted index files (see `crypt`) aren't fetched: compact the index,
+//! or pack it and download the pack whole (see `StaticIndex::from_vec`).
+
+use byteorder::{ByteOrder, LittleEndian};
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::bloom::footer_start;
+use crate::dictionary::TermDictionary;
+use crate::error::{Error, Result};
+use crate::header::{read_header, IndexSettings, OFFSETS_POSITION};
+use crate::index::{Doc, DocEntry, DocTable};
+use crate::read::{decode_doc_table, decode_hits, hits_limit};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Length of the header up to the length of the analyzer configuration it
+/// ends with (see `header`).
+const HEADER_START: u64 = OFFSETS_POSITION + 8 + 8 + 2 + 4;
+
+/// Where a `FetchedIndex` gets the bytes of its index file from.
+pub trait Fetch {
+    /// The bytes of the file from `start` to `end`, or to the end of the
+    /// file if `end` is `None`, like the HTTP range `bytes=start-(end-1)`.
+    fn fetch(
+        &self,
+        start: u64,
+        end: Option<u64>,
+    ) -> impl Future<Output = io::Result<Vec<u8>>>;
+}
+
+/// An index file whose terms are looked up with the bytes `F` fetches.
+#[derive(Debug)]
+pub struct FetchedIndex<F> {
+    fetch: F,
+    /// The table of contents, sorted by term.
+    terms: TermDictionary,
+    /// The document table.
+    docs: DocTable,
+    /// The settings from the file header.
+    settings: IndexSettings,
+    /// The most bytes the hits of a term can take decompressed (see
+    /// `read::hits_limit`).
+    hits_limit: usize,
+}
+
+impl<F: Fetch> FetchedIndex<F> {
+    /// Fetch the header and the tables of the index file of `fetch`. Fails
+    /// with `Error::Unsupported` if it is encrypted.
+    pub async fn open(fetch: F) -> Result<FetchedIndex<F>> {
+        let mut head = fetch.fetch(0, Some(HEADER_START)).await?;
+        if head.len() as u64 == HEADER_START {
+            let analyzer_len = LittleEndian::read_u32(&head[head.len() - 4..]);
+            let end = HEADER_START + analyzer_len as u64;
+            head.extend(fetch.fetch(HEADER_START, Some(end)).await?);
+        }
+        let header = read_header(&mut &head[..])?;
+        if header.settings.encrypted {
+            return Err(Error::Unsupported(
+                "fetching encrypted index files".to_string(),
+            ));
+        }
+        let start = header.doc_table_offset;
+        let mut tail = Tail {
+            start,
+            bytes: io::Cursor::new(fetch.fetch(start, None).await?),
+        };
+        let len = tail.seek(SeekFrom::End(0))?;
+        header.check_len(len)?;
+        let table_end = footer_start(&mut tail, &header.settings)?;
+        let bytes = tail.bytes.into_inner();
+        let at = |offset: u64| offset.saturating_sub(start) as usize;
+        let docs = decode_doc_table(
+            &bytes[..at(header.table_contents_offset)],
+            header.settings.identity,
+        )?;
+        let table = bytes
+            .get(at(header.table_contents_offset)..at(table_end))
+            .ok_or_else(|| {
+                Error::format("table of contents runs into the footer")
+            })?
+            .to_vec();
+        let terms = TermDictionary::load(table, start)?;
+        let hits_limit = hits_limit(&docs, !header.settings.omit_positions);
+        Ok(FetchedIndex {
+            fetch,
+            terms,
+            docs,
+            settings: header.settings,
+            hits_limit,
+        })
+    }
+
+    /// The document table of the file, by document id.
+    pub fn docs(&self) -> &DocTable {
+        &self.docs
+    }
+
+    /// The settings from the file header.
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    /// All terms in the index, in sorted order.
+    pub fn terms(&self) -> impl Iterator<Item = String> + '_ {
+        self.terms.iter().map(|e| e.term)
+    }
+
+    /// Fetch and decode the hits for `term`, an analyzed term.
+    ///
+    /// Returns `Ok(None)` if the term does not occur in the index.
+    pub async fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        let Some(e) = self.terms.get(term) else {
+            return Ok(None);
+        };
+        let end = e.offset + e.nbytes;
+        let data = self.fetch.fetch(e.offset, Some(end)).await?;
+        if data.len() as u64 != e.nbytes {
+            return Err(Error::format(format!(
+                "hits of {:?} are truncated",
+                e.term
+            )));
+        }
+        let hits = self.settings.codec.decompress(&data, self.hits_limit)?;
+        let positions = !self.settings.omit_positions;
+        let entry = decode_hits(&hits, e.doc_count, &self.docs, positions)?;
+        Ok(Some(entry))
+    }
+
+    /// The documents containing all of `terms`, in no particular order.
+    pub async fn intersect(&self, terms: &[&str]) -> Result<Vec<Doc>> {
+        let Some((first, rest)) = terms.split_first() else {
+            return Ok(vec![]);
+        };
+        let Some(mut docs) = self.lookup(first).await? else {
+            return Ok(vec![]);
+        };
+        for term in rest {
+            let Some(found) = self.lookup(term).await? else {
+                return Ok(vec![]);
+            };
+            docs.retain(|doc, _| found.contains_key(doc));
+        }
+        Ok(docs.into_keys().collect())
+    }
+}
+
+/// The bytes of an index file from `start` to its end, read at the
+/// positions they have in the file.
+struct Tail {
+    start: u64,
+    bytes: io::Cursor<Vec<u8>>,
+}
+
+impl Read for Tail {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.bytes.read(buf)
+    }
+}
+
+impl Seek for Tail {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let to = match to {
+            SeekFrom::Start(pos) => {
+                SeekFrom::Start(pos.checked_sub(self.start).ok_or_else(
+                    || io::Error::other("seek before the fetched bytes"),
+                )?)
+            }
+            to => to,
+        };
+        Ok(self.start + self.bytes.seek(to)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::index_of;
+    use crate::pack::StaticIndex;
+    use crate::write::write_index_to_vec;
+    use std::cell::RefCell;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Bytes in memory, recording the ranges fetched.
+    struct Memory {
+        bytes: Vec<u8>,
+        fetched: RefCell<Vec<(u64, Option<u64>)>>,
+    }
+
+    impl Fetch for Memory {
+        async fn fetch(
+            &self,
+            start: u64,
+            end: Option<u64>,
+        ) -> io::Result<Vec<u8>> {
+            self.fetched.borrow_mut().push((start, end));
+            let len = self.bytes.len() as u64;
+            let end = end.unwrap_or(len).min(len);
+            Ok(self.bytes[start.min(end) as usize..end as usize].to_vec())
+        }
+    }
+
+    /// Run `future`, which never waits.
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        let mut context = Context::from_waker(Waker::noop());
+        match pin!(future).poll(&mut context) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("fetches never wait"),
+        }
+    }
+
+    fn fetched(settings: &IndexSettings) -> (FetchedIndex<Memory>, Vec<u8>) {
+        let texts = ["cat dog", "dog bird", "cat fish dog", "ünïcode cat"];
+        let bytes = write_index_to_vec(index_of(0, &texts), settings).unwrap();
+        let memory = Memory {
+            bytes: bytes.clone(),
+            fetched: RefCell::default(),
+        };
+        (ready(FetchedIndex::open(memory)).unwrap(), bytes)
+    }
+
+    #[test]
+    fn lookups_fetch_only_the_hits_of_their_term() {
+        for settings in [
+            IndexSettings::default(),
+            IndexSettings {
+                bloom_filter: true,
+                omit_positions: true,
+                ..IndexSettings::default()
+            },
+        ] {
+            let (index, bytes) = fetched(&settings);
+            let in_memory = StaticIndex::from_vec(bytes).unwrap();
+            let terms: Vec<String> = index.terms().collect();
+            assert!(terms.contains(&"ünïcode".to_string()));
+            assert_eq!(index.docs().len(), 4);
+            let opened = index.fetch.fetched.borrow().len();
+            for term in terms.iter().map(String::as_str).chain(["none"]) {
+                let found = ready(index.lookup(term)).unwrap();
+                assert_eq!(found, in_memory.lookup(term).unwrap(), "{}", term);
+            }
+            let fetched = index.fetch.fetched.borrow();
+            assert_eq!(fetched.len(), opened + terms.len());
+            let data_end = fetched[opened - 1].0;
+            assert!(fetched[opened..]
+                .iter()
+                .all(|&(_, end)| end.is_some_and(|end| end <= data_end)));
+            drop(fetched);
+
+            let mut both = ready(index.intersect(&["cat", "dog"])).unwrap();
+            both.sort_by(|a, b| a.hash.cmp(&b.hash));
+            let numbered = |n: u8| crate::fixtures::doc(n);
+            assert_eq!(both, [numbered(0), numbered(2)]);
+            assert!(ready(index.intersect(&["cat", "none"]))
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_an_error() {
+        let (index, _) = fetched(&IndexSettings::default());
+        let bytes = &index.fetch.bytes;
+        for len in [10, bytes.len() - 10] {
+            let memory = Memory {
+                bytes: bytes[..len].to_vec(),
+                fetched: RefCell::default(),
+            };
+            assert!(ready(FetchedIndex::open(memory)).is_err(), "{}", len);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Document fields.
+//!
+//! Besides its text (the body), a document has a few short fields that are
+//! indexed separately: its title, when the extractor can find one, and the
+//! path of its file. Words of a field are stored in the index as terms of
+//! their own, made of the field name, a colon and the word, like
+//! `title:rust`, with word offsets counted from the start of the field. Body
+//! terms are stored as they always were, without a prefix.
+//!
+//! Queries select a field the same way, with `title:rust` or
+//! `title:"inverted index"`. Plain query terms only match the body, but the
+//! searcher also looks them up in the title to boost documents whose title
+//! contains them.
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// A part of a document that is indexed separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    /// The text of the document.
+    Body,
+    /// The title, e.g. the `<title>` of an HTML page or the first heading of
+    /// a Markdown document.
+    Title,
+    /// The path of the file the document was read from.
+    Path,
+}
+
+impl Field {
+    /// The fields other than the body, which have a name.
+    pub const NAMED: [Field; 2] = [Field::Title, Field::Path];
+
+    /// The name used in queries and as the prefix of the field's terms.
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Body => "body",
+            Field::Title => "title",
+            Field::Path => "path",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        match name {
+            "body" => Some(Field::Body),
+            "title" => Some(Field::Title),
+            "path" => Some(Field::Path),
+            _ => None,
+        }
+    }
+
+    /// The index term for `word` in this field.
+    pub fn term(self, word: &str) -> String {
+        match self {
+            Field::Body => word.to_string(),
+            field => format!("{}:{}", field.name(), word),
+        }
+    }
+
+    /// Split a query word like `title:rust` into its field and the rest.
+    /// Words without a known field prefix are in the body.
+    pub fn split(word: &str) -> (Field, &str) {
+        word.split_once(':')
+            .and_then(|(name, rest)| Some((Field::from_name(name)?, rest)))
+            .unwrap_or((Field::Body, word))
+    }
+
+    /// The field an index term belongs to.
+    pub fn of_term(term: &str) -> Field {
+        Field::split(term).0
+    }
+
+    /// Position of the field in `NAMED`, or `None` for the body.
+    pub(crate) fn named_index(self) -> Option<usize> {
+        Field::NAMED.iter().position(|field| *field == self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::TextAnalyzer;
+    use crate::fixtures::doc;
+    use crate::index::InMemoryIndex;
+
+    #[test]
+    fn words_are_split_from_their_field() {
+        let cases = [
+            ("rust", Field::Body, "rust"),
+            ("title:rust", Field::Title, "rust"),
+            ("path:src", Field::Path, "src"),
+            ("body:rust", Field::Body, "rust"),
+            ("title:", Field::Title, ""),
+            ("author:me", Field::Body, "author:me"),
+            ("Title:rust", Field::Body, "Title:rust"),
+        ];
+        for (word, field, rest) in cases {
+            assert_eq!(Field::split(word), (field, rest), "{}", word);
+            assert_eq!(Field::of_term(word), field, "{}", word);
+        }
+        for field in [Field::Body, Field::Title, Field::Path] {
+            assert_eq!(Field::from_name(field.name()), Some(field));
+            assert_eq!(Field::split(&field.term("w")), (field, "w"));
+        }
+        assert_eq!(Field::Body.term("w"), "w");
+        assert_eq!(Field::Title.named_index(), Some(0));
+        assert_eq!(Field::Body.named_index(), None);
+    }
+
+    #[test]
+    fn fields_are_indexed_with_offsets_of_their_own() {
+        let fields = [
+            (Field::Title, "The Cat".to_string()),
+            (Field::Path, "pets/cat.txt".to_string()),
+        ];
+        let text = "a cat sat".to_string();
+        let memory = InMemoryIndex::from_single_document_fields(
+            &doc(0).hash,
+            text,
+            &fields,
+            &TextAnalyzer::default(),
+        );
+        // Only words of the body count.
+        assert_eq!(memory.word_count, 3);
+        let index = memory.to_parsed();
+        let offsets =
+            |term: &str| index.map.get(term).map(|e| e[&doc(0)].clone());
+        assert_eq!(offsets("cat"), Some(vec![1]));
+        assert_eq!(offsets("title:cat"), Some(vec![1]));
+        assert_eq!(offsets("path:cat"), Some(vec![1]));
+        assert_eq!(offsets("path:txt"), Some(vec![2]));
+        assert_eq!(offsets("the"), None);
+        let info = &index.docs[&doc(0)];
+        assert_eq!(info.token_count, 3);
+        assert_eq!(info.len_of(Field::Title), 2);
+        assert_eq!(info.len_of(Field::Path), 3);
+    }
+}
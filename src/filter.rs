@@ -0,0 +1,288 @@
+//! Query filters on what the document table records about files.
+//!
+//! A filter is a query word made of an attribute name, a colon, an optional
+//! comparison and a value:
+//!
+//! ```text
+//! mtime:>2023-01-01 size:<1MB
+//! ```
+//!
+//! `mtime` compares the modification time of files with a date, written
+//! `2023-01-01`, or a time, written `2023-01-01T12:30` or
+//! `2023-01-01T12:30:15`, in UTC. `size` compares the size of files with a
+//! number of bytes, optionally followed by a unit: `K`, `M`, `G` or `T`
+//! (powers of 1024, with an optional `B` or `iB`), like `1.5MB`. The
+//! comparison is one of `<`, `<=`, `>`, `>=` and `=`, the default.
+//!
+//! A value stands for everything it doesn't say more precisely: the date
+//! `2023-01-01` is the whole day, so `mtime:2023-01-01` matches files
+//! modified at any time that day, and `mtime:>2023-01-01` those modified
+//! from the next day on.
+
+use std::ops::RangeInclusive;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::index::DocInfo;
+use crate::query::{Query, SyntaxError};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Something recorded about every document that queries can filter on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    /// The modification time of the document's file, in seconds since the
+    /// Unix epoch.
+    Modified,
+    /// The size of the document's file, in bytes.
+    Size,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 2] = [Attribute::Modified, Attribute::Size];
+
+    /// The name used in queries.
+    pub fn name(self) -> &'static str {
+        match self {
+            Attribute::Modified => "mtime",
+            Attribute::Size => "size",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Attribute> {
+        Attribute::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    /// The value of the attribute for the document `info` describes.
+    pub fn value_of(self, info: &DocInfo) -> u64 {
+        match self {
+            Attribute::Modified => info.mtime,
+            Attribute::Size => info.size,
+        }
+    }
+
+    /// The range of values written `value`, both ends included.
+    fn parse_value(self, value: &str) -> Option<RangeInclusive<u64>> {
+        match self {
+            Attribute::Modified => parse_time(value),
+            Attribute::Size => parse_size(value).map(|size| size..=size),
+        }
+    }
+}
+
+/// True if `word` is a filter, like `size:<1MB`, rather than a term.
+pub(crate) fn is_filter(word: &str) -> bool {
+    split(word).is_some()
+}
+
+/// Parse the filter `word`, found at position `at` of a query, into a
+/// `Query::Range`.
+pub(crate) fn parse(word: &str, at: usize) -> Result<Query, SyntaxError> {
+    let Some((attribute, rest)) = split(word) else {
+        return Err(SyntaxError::new("expected a filter", at));
+    };
+    // Where the comparison starts, in characters.
+    let at = at + attribute.name().len() + 1;
+    let operators = ["<=", ">=", "<", ">", "="];
+    let (operator, value) = operators
+        .iter()
+        .find_map(|op| Some((*op, rest.strip_prefix(op)?)))
+        .unwrap_or(("=", rest));
+    let value_at = at + rest.len() - value.len();
+    if value.is_empty() {
+        let message = format!("expected a value after `{}:`", attribute.name());
+        return Err(SyntaxError::new(message, value_at));
+    }
+    let Some(written) = attribute.parse_value(value) else {
+        let message = match attribute {
+            Attribute::Modified => "expected a date like 2023-01-01",
+            Attribute::Size => "expected a size like 10KB",
+        };
+        return Err(SyntaxError::new(message, value_at));
+    };
+    let (low, high) = written.into_inner();
+    let range = match operator {
+        "<" if low == 0 => empty(),
+        "<" => 0..=low - 1,
+        "<=" => 0..=high,
+        ">" if high == u64::MAX => empty(),
+        ">" => high + 1..=u64::MAX,
+        ">=" => low..=u64::MAX,
+        _ => low..=high,
+    };
+    Ok(Query::Range(attribute, range))
+}
+
+/// A range of no values.
+#[allow(clippy::reversed_empty_ranges)]
+fn empty() -> RangeInclusive<u64> {
+    // An inclusive range is empty when it starts after its end.
+    1..=0
+}
+
+/// Split a filter into its attribute and what follows the colon.
+fn split(word: &str) -> Option<(Attribute, &str)> {
+    let (name, rest) = word.split_once(':')?;
+    Some((Attribute::from_name(name)?, rest))
+}
+
+/// Parse a date or a time, in UTC, into the seconds since the Unix epoch it
+/// covers.
+fn parse_time(value: &str) -> Option<RangeInclusive<u64>> {
+    let (date, time) = match value.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = number(parts.next()?, 4)?;
+    let month: u32 = number(parts.next()?, 2)?;
+    let day: u32 = number(parts.next()?, 2)?;
+    if year < 1970 || !(1..=12).contains(&month) {
+        return None;
+    }
+    if day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let start = days_from_civil(year, month, day) as u64 * 86_400;
+    let Some(time) = time else {
+        return Some(start..=start + 86_399);
+    };
+    let mut parts = time.splitn(3, ':');
+    let hour: u64 = number(parts.next()?, 2)?;
+    let minute: u64 = number(parts.next()?, 2)?;
+    let second: Option<u64> = match parts.next() {
+        Some(second) => Some(number(second, 2)?),
+        None => None,
+    };
+    if hour > 23 || minute > 59 || second.is_some_and(|s| s > 59) {
+        return None;
+    }
+    let start = start + hour * 3600 + minute * 60 + second.unwrap_or(0);
+    match second {
+        Some(_) => Some(start..=start),
+        None => Some(start..=start + 59),
+    }
+}
+
+/// Parse `digits`, which must be exactly `len` ASCII digits.
+fn number<T: std::str::FromStr>(digits: &str, len: usize) -> Option<T> {
+    if digits.len() != len || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Number of days from 1970-01-01 to a date of the proleptic Gregorian
+/// calendar, from Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    // Months counted from March, so that the leap day ends the year.
+    let month = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + day as i64 - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse a size in bytes, with an optional unit.
+fn parse_size(value: &str) -> Option<u64> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = unit.to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    let size = (number * multiplier as f64).round();
+    // Sizes past `u64::MAX` saturate, which no file reaches anyway.
+    Some(size as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2023-01-01, in seconds since the Unix epoch.
+    const DAY: u64 = 1_672_531_200;
+
+    fn range(word: &str) -> RangeInclusive<u64> {
+        match parse(word, 0) {
+            Ok(Query::Range(_, range)) => range,
+            other => panic!("{}: {:?}", word, other),
+        }
+    }
+
+    #[test]
+    fn filters_are_parsed_into_ranges() {
+        let cases = [
+            ("mtime:2023-01-01", DAY..=DAY + 86_399),
+            ("mtime:=2023-01-01", DAY..=DAY + 86_399),
+            ("mtime:>2023-01-01", DAY + 86_400..=u64::MAX),
+            ("mtime:>=2023-01-01", DAY..=u64::MAX),
+            ("mtime:<2023-01-01", 0..=DAY - 1),
+            ("mtime:<=2023-01-01", 0..=DAY + 86_399),
+            ("mtime:2023-01-01T12:30", DAY + 45_000..=DAY + 45_059),
+            ("mtime:2023-01-01t12:30:15", DAY + 45_015..=DAY + 45_015),
+            ("mtime:1970-01-01", 0..=86_399),
+            ("mtime:2024-02-29", 1_709_164_800..=1_709_251_199),
+            ("size:10", 10..=10),
+            ("size:<1KB", 0..=1023),
+            ("size:>1k", 1025..=u64::MAX),
+            ("size:1.5MiB", 1_572_864..=1_572_864),
+            ("size:2G", 1 << 31..=1 << 31),
+            ("size:<=1TB", 0..=1 << 40),
+        ];
+        for (word, expected) in cases {
+            assert_eq!(range(word), expected, "{}", word);
+        }
+        // Nothing is older than the epoch or bigger than the largest size.
+        assert!(range("mtime:<1970-01-01").is_empty());
+        assert!(range("size:<0").is_empty());
+    }
+
+    #[test]
+    fn bad_filters_are_syntax_errors_where_the_value_is() {
+        let cases = [
+            ("size:", 5),
+            ("size:<", 6),
+            ("size:10XB", 5),
+            ("size:>=ten", 7),
+            ("mtime:2023-1-1", 6),
+            ("mtime:2023-02-29", 6),
+            ("mtime:1969-12-31", 6),
+            ("mtime:2023-13-01", 6),
+            ("mtime:<2023-01-01T24:00", 7),
+            ("mtime:2023-01-01T12:60", 6),
+            ("mtime:2023-01-01T12", 6),
+        ];
+        for (word, position) in cases {
+            let error = parse(word, 3).unwrap_err();
+            assert_eq!(error.position, position + 3, "{}", word);
+        }
+        assert!(is_filter("size:1") && is_filter("mtime:x"));
+        assert!(!is_filter("title:rust") && !is_filter("size"));
+    }
+}
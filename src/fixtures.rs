@@ -0,0 +1,49 @@
+//! Indexes of a few short texts, shared by the tests of the other modules.
+//!
+//! The document of text `i` has the hash `[i; 32]` (or `[first + i; 32]`),
+//! so tests can name documents by number.
+
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::header::IndexSettings;
+use crate::index::{Doc, InMemoryIndex};
+use crate::tmp::TmpDir;
+use crate::write::write_index_to_tmp_file;
+use crate::HASH_LENGTH;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The document numbered `n`.
+pub fn doc(n: u8) -> Doc {
+    Doc::new(&[n; HASH_LENGTH])
+}
+
+/// An index of `texts`, the documents numbered from `first` up.
+pub fn index_of(first: u8, texts: &[&str]) -> InMemoryIndex {
+    let mut index = InMemoryIndex::new();
+    for (i, text) in texts.iter().enumerate() {
+        index.merge(InMemoryIndex::from_single_document(
+            &doc(first + i as u8).hash,
+            text.to_string(),
+        ));
+    }
+    index
+}
+
+/// Write an index of `texts` to a file in `dir`, with the default settings,
+/// the documents numbered from 0 up.
+pub fn write_index(dir: &Path, texts: &[&str]) -> PathBuf {
+    write_index_with(dir, texts, &IndexSettings::default())
+}
+
+/// Like `write_index`, with `settings`.
+pub fn write_index_with(
+    dir: &Path,
+    texts: &[&str],
+    settings: &IndexSettings,
+) -> PathBuf {
+    write_index_to_tmp_file(index_of(0, texts), &mut TmpDir::new(dir), settings)
+        .unwrap()
+}
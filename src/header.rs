@@ -0,0 +1,405 @@
+//! The header of index files.
+//!
+//! Every index file starts with a header that identifies it and records how
+//! it was written:
+//!
+//! | bytes | content                                        |
+//! |-------|------------------------------------------------|
+//! | 8     | magic number, `IDXTOOLS`                       |
+//! | 4     | format version                                 |
+//! | 4     | flags, see below                               |
+//! | 8     | offset of the table of contents                |
+//! | 8     | offset of the document table                   |
+//! | 1     | codec id                                       |
+//! | 1     | document identity id, since version 10         |
+//! | 4 + n | analyzer configuration, `AnalyzerConfig::encode` |
+//!
+//! All numbers are little-endian. The two offsets are only known once the
+//! rest of the file has been written, so the writer fills them in last.
+//!
+//! Flag `1` says that the file ends with a bloom filter of its terms (see
+//! `bloom`), after the table of contents, and flag `2` that the hits of its
+//! terms have no offsets (see `IndexSettings::omit_positions`). Flag `4`
+//! says that every term has a twin holding its byte offsets (see
+//! `IndexSettings::byte_offsets`), and flag `8` that every term of the body
+//! has one holding its line numbers (see `IndexSettings::line_numbers`).
+//! Flag `16` says that everything after the header is encrypted (see
+//! `crypt`).
+//!
+//! Document ids and word offsets are u32, which sets the two limits of the
+//! format: a file holds at most `index::MAX_DOCS` documents, and at most
+//! `index::MAX_DOC_WORDS` words of each field of a document are indexed.
+//! The numbers bounded by them are stored as u32 too: the number of
+//! documents of a term, of words of a document and of occurrences of a term
+//! in a document. Everything else that can grow with a corpus, like the
+//! offsets of sections, the length of terms and the number of occurrences
+//! of a term in all documents, is a u64 or a varint. Writers fail with an
+//! error rather than go past a limit, but for documents with too many
+//! words, which are truncated (see `DocInfo::truncated`). Paths in the
+//! document table may take up to `u32::MAX` bytes, and languages 255.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::AnalyzerConfig;
+use crate::codec::Codec;
+use crate::error::{Error, Result};
+use crate::identity::DocIdentity;
+use crate::read::read_bytes;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Every index file starts with these bytes.
+pub(crate) const MAGIC: &[u8; 8] = b"IDXTOOLS";
+
+/// Version of the file format written by this crate. Files of any other
+/// version are rejected, but those of older versions can be rewritten in
+/// this one (see `upgrade`).
+///
+/// Version 2 added the language of each document to the document table,
+/// version 3 skip data to the table of contents, version 4 made it a
+/// front-coded term dictionary, version 5 added the length of each field
+/// to the document table, version 6 the maxima of each block of hits to
+/// the table of contents, version 7 the size of each document's file to
+/// the document table, version 8 the description at the end of the file
+/// (see `description`), version 9 the paths of each document's duplicates
+/// to the document table, version 10 the document identity to the header,
+/// version 11 the number of occurrences of each term to the table of
+/// contents, and version 12 the flags of each document, which say whether
+/// it was truncated, to the document table.
+pub(crate) const VERSION: u32 = 12;
+
+/// The file ends with a bloom filter footer.
+const FLAG_BLOOM_FILTER: u32 = 1;
+
+/// The hits of terms have no offsets, only their number.
+const FLAG_NO_POSITIONS: u32 = 2;
+
+/// Every term has a twin holding its byte offsets.
+const FLAG_BYTE_OFFSETS: u32 = 4;
+
+/// Every term of the body has a twin holding its line numbers.
+const FLAG_LINE_NUMBERS: u32 = 8;
+
+/// Everything after the header is encrypted.
+const FLAG_ENCRYPTED: u32 = 16;
+
+/// Files with unknown flags set are rejected, so that future versions can
+/// use them for features older readers must not ignore.
+const KNOWN_FLAGS: u32 = FLAG_BLOOM_FILTER
+    | FLAG_NO_POSITIONS
+    | FLAG_BYTE_OFFSETS
+    | FLAG_LINE_NUMBERS
+    | FLAG_ENCRYPTED;
+
+/// Position of the table of contents offset, the first of the two offsets
+/// that are filled in when the file is finished.
+pub(crate) const OFFSETS_POSITION: u64 = 16;
+
+/// How an index was built, recorded in the header of each of its files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexSettings {
+    /// The codec postings are compressed with.
+    pub codec: Codec,
+    /// The analyzer documents were indexed with. Queries must be analyzed
+    /// the same way.
+    pub analyzer: AnalyzerConfig,
+    /// End the file with a bloom filter of its terms, so that lookups of
+    /// terms it doesn't have can skip it.
+    pub bloom_filter: bool,
+    /// Store how many times each term occurs in each document, but not
+    /// where. Files get much smaller, but phrase and proximity queries only
+    /// check that all their words occur, and snippets can't be cut.
+    pub omit_positions: bool,
+    /// Also store where each occurrence of a term starts in the text of its
+    /// document, in bytes, as the offsets of a twin term (see
+    /// `analyzer::BYTE_OFFSETS_PREFIX`), for editors to jump to. Needs
+    /// positions.
+    pub byte_offsets: bool,
+    /// Also store the numbers of the lines each term of the body occurs on
+    /// in each document, as the offsets of a twin term (see
+    /// `analyzer::LINE_NUMBERS_PREFIX`), for output like that of `grep -n`.
+    /// Needs positions.
+    pub line_numbers: bool,
+    /// How documents are identified.
+    pub identity: DocIdentity,
+    /// Encrypt everything but the header, with the key given in the
+    /// environment (see `crypt`), for indexes of sensitive documents kept
+    /// on shared machines. Writing and reading the files takes the key.
+    pub encrypted: bool,
+}
+
+impl IndexSettings {
+    /// Read the settings from the header of `index_file`.
+    pub fn load<P: AsRef<Path>>(index_file: P) -> Result<IndexSettings> {
+        let mut f = BufReader::new(File::open(index_file)?);
+        Ok(read_header(&mut f)?.settings)
+    }
+}
+
+/// The header of an index file.
+#[derive(Debug, Clone)]
+pub(crate) struct Header {
+    pub settings: IndexSettings,
+    /// Offset of the table of contents from the beginning of the file.
+    pub table_contents_offset: u64,
+    /// Offset of the document table from the beginning of the file.
+    pub doc_table_offset: u64,
+    /// Length of the header, in bytes.
+    pub len: u64,
+}
+
+impl Header {
+    /// Check that the sections the header points to lie within a file of
+    /// `len` bytes. The document table is already known to come before the
+    /// table of contents.
+    pub(crate) fn check_len(&self, len: u64) -> Result<()> {
+        if self.table_contents_offset > len {
+            return Err(Error::format(
+                "table of contents offset is past the end of the file",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Write a header with both offsets set to zero. Returns its size in bytes.
+pub(crate) fn write_header<W: Write>(
+    f: &mut W,
+    settings: &IndexSettings,
+) -> io::Result<u64> {
+    let analyzer = settings.analyzer.encode();
+    f.write_all(MAGIC)?;
+    f.write_u32::<LittleEndian>(VERSION)?;
+    let mut flags = 0;
+    if settings.bloom_filter {
+        flags |= FLAG_BLOOM_FILTER;
+    }
+    if settings.omit_positions {
+        flags |= FLAG_NO_POSITIONS;
+    }
+    if settings.byte_offsets {
+        flags |= FLAG_BYTE_OFFSETS;
+    }
+    if settings.line_numbers {
+        flags |= FLAG_LINE_NUMBERS;
+    }
+    if settings.encrypted {
+        flags |= FLAG_ENCRYPTED;
+    }
+    f.write_u32::<LittleEndian>(flags)?;
+    f.write_u64::<LittleEndian>(0)?;
+    f.write_u64::<LittleEndian>(0)?;
+    f.write_u8(settings.codec.id())?;
+    f.write_u8(settings.identity.id())?;
+    f.write_u32::<LittleEndian>(analyzer.len() as u32)?;
+    f.write_all(analyzer.as_bytes())?;
+    Ok(OFFSETS_POSITION + 8 + 8 + 2 + 4 + analyzer.len() as u64)
+}
+
+/// Read and check the header. The reader is left right after it.
+pub(crate) fn read_header<R: Read>(f: &mut R) -> Result<Header> {
+    let (version, header) = read_any_header(f)?;
+    if version != VERSION {
+        return Err(Error::Unsupported(format!(
+            "format version {} (expected {})",
+            version, VERSION
+        )));
+    }
+    Ok(header)
+}
+
+/// Read and check the header of a file of any version up to the current
+/// one. The layout has been the same since version 1, but for the document
+/// identity, which files have recorded since version 10; before, documents
+/// were identified by SHA-256. Returns the version with the header.
+pub(crate) fn read_any_header<R: Read>(f: &mut R) -> Result<(u32, Header)> {
+    let mut magic = [0; 8];
+    f.read_exact(&mut magic).map_err(|_| not_an_index())?;
+    if &magic != MAGIC {
+        return Err(not_an_index());
+    }
+    let version = f
+        .read_u32::<LittleEndian>()
+        .map_err(Error::truncated("header"))?;
+    if version == 0 || version > VERSION {
+        return Err(Error::Unsupported(format!(
+            "format version {} (expected {})",
+            version, VERSION
+        )));
+    }
+    let flags = f
+        .read_u32::<LittleEndian>()
+        .map_err(Error::truncated("header"))?;
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(Error::Unsupported(format!(
+            "unknown features (flags {:#x})",
+            flags
+        )));
+    }
+    let table_contents_offset = f
+        .read_u64::<LittleEndian>()
+        .map_err(Error::truncated("header"))?;
+    let doc_table_offset = f
+        .read_u64::<LittleEndian>()
+        .map_err(Error::truncated("header"))?;
+
+    let codec_id = f.read_u8().map_err(Error::truncated("header"))?;
+    let codec = Codec::from_id(codec_id).ok_or_else(|| {
+        Error::Unsupported(format!("unknown codec id {}", codec_id))
+    })?;
+    codec.check_available()?;
+    let identity = if version >= 10 {
+        let id = f.read_u8().map_err(Error::truncated("header"))?;
+        DocIdentity::from_id(id).ok_or_else(|| {
+            Error::Unsupported(format!("unknown document identity id {}", id))
+        })?
+    } else {
+        DocIdentity::Sha256
+    };
+
+    let analyzer_len = f
+        .read_u32::<LittleEndian>()
+        .map_err(Error::truncated("header"))?;
+    let analyzer = read_bytes(f, analyzer_len as u64, "header")?;
+    let analyzer = AnalyzerConfig::decode(&String::from_utf8(analyzer)?)
+        .map_err(|e| Error::format(e.to_string()))?;
+
+    let ids = if version >= 10 { 2 } else { 1 };
+    let size = OFFSETS_POSITION + 8 + 8 + ids + 4 + analyzer_len as u64;
+    if doc_table_offset < size || doc_table_offset > table_contents_offset {
+        return Err(Error::format("header offsets are out of range"));
+    }
+    let header = Header {
+        settings: IndexSettings {
+            codec,
+            analyzer,
+            bloom_filter: flags & FLAG_BLOOM_FILTER != 0,
+            omit_positions: flags & FLAG_NO_POSITIONS != 0,
+            byte_offsets: flags & FLAG_BYTE_OFFSETS != 0,
+            line_numbers: flags & FLAG_LINE_NUMBERS != 0,
+            identity,
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+        },
+        table_contents_offset,
+        doc_table_offset,
+        len: size,
+    };
+    Ok((version, header))
+}
+
+fn not_an_index() -> Error {
+    Error::format("not an index file (bad magic number)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::TokenizerKind;
+    use crate::routing::Route;
+
+    fn settings() -> IndexSettings {
+        IndexSettings {
+            codec: Codec::None,
+            analyzer: AnalyzerConfig {
+                tokenizer: TokenizerKind::Ngram(2),
+                stop_words: vec!["english".to_string()],
+                custom_stop_words: vec!["foo".to_string(), "bar".to_string()],
+                stemmer: Some("english".to_string()),
+                detect_language: true,
+                preserve_case: true,
+                skip_numbers: true,
+                min_length: Some(2),
+                max_length: Some(40),
+                routes: vec![Route {
+                    glob: "*.log".to_string(),
+                    extractor: Some("text".to_string()),
+                    analyzer: Some(AnalyzerConfig {
+                        skip_numbers: true,
+                        ..Default::default()
+                    }),
+                }],
+            },
+            bloom_filter: true,
+            omit_positions: true,
+            byte_offsets: true,
+            line_numbers: true,
+            identity: DocIdentity::Xxh3,
+            encrypted: true,
+        }
+    }
+
+    /// A header for `settings` with the offsets filled in just past it.
+    fn header_bytes(settings: &IndexSettings) -> Vec<u8> {
+        let mut buf = vec![];
+        let size = write_header(&mut buf, settings).unwrap();
+        assert_eq!(size, buf.len() as u64);
+        buf[16..24].copy_from_slice(&size.to_le_bytes());
+        buf[24..32].copy_from_slice(&size.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn header_reads_back() {
+        for settings in [IndexSettings::default(), settings()] {
+            let buf = header_bytes(&settings);
+            let mut reader = &buf[..];
+            let header = read_header(&mut reader).unwrap();
+            assert!(reader.is_empty());
+            assert_eq!(header.settings, settings);
+            assert_eq!(header.table_contents_offset, buf.len() as u64);
+            assert_eq!(header.doc_table_offset, buf.len() as u64);
+        }
+    }
+
+    fn read_error(buf: &[u8]) -> Error {
+        read_header(&mut &buf[..]).unwrap_err()
+    }
+
+    #[test]
+    fn foreign_files_are_rejected() {
+        let e = read_error(b"");
+        assert!(matches!(e, Error::Format(_)));
+        assert!(e.to_string().contains("magic"));
+        let mut buf = header_bytes(&settings());
+        buf[0] = b'X';
+        assert!(read_error(&buf).to_string().contains("magic"));
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        let good = header_bytes(&settings());
+        let mut buf = good.clone();
+        buf[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let e = read_error(&buf);
+        assert!(matches!(e, Error::Unsupported(_)));
+        assert!(e.to_string().contains("version"));
+        let mut buf = good.clone();
+        buf[15] = 0x80;
+        assert!(read_error(&buf).to_string().contains("flags"));
+        let mut buf = good.clone();
+        buf[32] = 9;
+        assert!(read_error(&buf).to_string().contains("codec"));
+        let mut buf = good.clone();
+        buf[33] = 9;
+        assert!(read_error(&buf).to_string().contains("identity"));
+    }
+
+    #[test]
+    fn damaged_headers_are_an_error() {
+        let good = header_bytes(&settings());
+        for len in [4, 20, 33, good.len() - 1] {
+            let e = read_error(&good[..len]);
+            assert!(matches!(e, Error::Format(_)), "length {len}: {e}");
+        }
+        // The document table can't start inside the header.
+        let mut buf = good.clone();
+        buf[24..32].copy_from_slice(&1u64.to_le_bytes());
+        assert!(read_error(&buf).to_string().contains("offsets"));
+    }
+}
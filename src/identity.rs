@@ -0,0 +1,235 @@
+//! How documents are identified.
+//!
+//! Every document of an index has an id, its `Doc`, which the document
+//! table maps to what is known about it and which tombstones refer to. The
+//! id is made when the document is read, in one of several ways chosen when
+//! the index is built and recorded in the file header:
+//!
+//! * `Sha256`, the SHA-256 hash of the document's bytes (32 bytes), the
+//!   default. A document keeps its id wherever it is moved, and copies of
+//!   it have the same one.
+//! * `Xxh3`, the 128-bit xxh3 hash of its bytes (16 bytes): much faster to
+//!   compute and half as large, but not meant to resist documents crafted
+//!   to collide.
+//! * `Path`, the 128-bit xxh3 hash of its path (16 bytes). Files aren't
+//!   hashed at all, and a file that is changed keeps its id, but copies of
+//!   a document are different documents.
+//! * `Blake3`, the BLAKE3 hash of its bytes (32 bytes), with the `blake3`
+//!   feature: as hard to make collide as SHA-256, and several times faster
+//!   to compute on large files.
+//!
+//! All the documents of an index have ids of the same length, `hash_len`.
+
+use std::io::{self, Read};
+use std::path::Path;
+
+use ring::digest::{digest, Context, SHA256};
+use twox_hash::XxHash3_128;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::index::Doc;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// How the documents of an index are identified.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DocIdentity {
+    /// The SHA-256 hash of the document's bytes.
+    #[default]
+    Sha256,
+    /// The 128-bit xxh3 hash of the document's bytes.
+    Xxh3,
+    /// The 128-bit xxh3 hash of the document's path.
+    Path,
+    /// The BLAKE3 hash of the document's bytes, with the `blake3` feature.
+    Blake3,
+}
+
+/// Every identity, in the order of their ids.
+const ALL: [DocIdentity; 4] = [
+    DocIdentity::Sha256,
+    DocIdentity::Xxh3,
+    DocIdentity::Path,
+    DocIdentity::Blake3,
+];
+
+impl DocIdentity {
+    /// The identity's id, as recorded in index file headers.
+    pub fn id(self) -> u8 {
+        match self {
+            DocIdentity::Sha256 => 0,
+            DocIdentity::Xxh3 => 1,
+            DocIdentity::Path => 2,
+            DocIdentity::Blake3 => 3,
+        }
+    }
+
+    /// The identity with the given id, if there is one.
+    pub fn from_id(id: u8) -> Option<DocIdentity> {
+        ALL.into_iter().find(|i| i.id() == id)
+    }
+
+    /// The identity's name, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            DocIdentity::Sha256 => "sha256",
+            DocIdentity::Xxh3 => "xxh3",
+            DocIdentity::Path => "path",
+            DocIdentity::Blake3 => "blake3",
+        }
+    }
+
+    /// Parse a name returned by `name`.
+    pub fn from_name(name: &str) -> Option<DocIdentity> {
+        ALL.into_iter().find(|i| i.name() == name)
+    }
+
+    /// Length of the ids, in bytes.
+    pub fn hash_len(self) -> usize {
+        match self {
+            DocIdentity::Sha256 | DocIdentity::Blake3 => 32,
+            DocIdentity::Xxh3 | DocIdentity::Path => 16,
+        }
+    }
+
+    /// True if this build of the crate can compute the identity's ids.
+    /// Index files identified by one it can't are still read and searched,
+    /// but documents can't be added to them.
+    pub fn is_available(self) -> bool {
+        self != DocIdentity::Blake3 || cfg!(feature = "blake3")
+    }
+
+    /// Fail with a clear error if `is_available` is false.
+    pub fn check_available(self) -> Result<()> {
+        if self.is_available() {
+            return Ok(());
+        }
+        Err(Error::Unsupported(format!(
+            "{} document ids are not supported by this build (enable the \
+             `{}` feature)",
+            self.name(),
+            self.name()
+        )))
+    }
+
+    /// True if documents with the same bytes get the same id.
+    pub fn is_by_contents(self) -> bool {
+        self != DocIdentity::Path
+    }
+
+    /// The id of the document with `bytes`, read from `path`.
+    ///
+    /// Panics if the identity isn't available, see `check_available`.
+    pub fn doc(self, path: &Path, bytes: &[u8]) -> Doc {
+        match self {
+            DocIdentity::Sha256 => Doc::new(digest(&SHA256, bytes).as_ref()),
+            DocIdentity::Xxh3 => xxh3(bytes),
+            DocIdentity::Path => xxh3(path.to_string_lossy().as_bytes()),
+            #[cfg(feature = "blake3")]
+            DocIdentity::Blake3 => Doc::new(blake3::hash(bytes).as_bytes()),
+            #[allow(unreachable_patterns)]
+            _ => panic!("{}", self.check_available().unwrap_err()),
+        }
+    }
+
+    /// The hash of everything `r` reads, with the hash of this identity,
+    /// or xxh3 for `Path`. Documents identified by their contents get it as
+    /// their id. Fails with `Unsupported` if the identity isn't available.
+    pub fn hash_contents<R: Read>(self, r: &mut R) -> io::Result<Doc> {
+        self.check_hashable()?;
+        let mut buf = vec![0; 64 << 10];
+        match self {
+            DocIdentity::Sha256 => {
+                let mut context = Context::new(&SHA256);
+                loop {
+                    match r.read(&mut buf)? {
+                        0 => break,
+                        n => context.update(&buf[..n]),
+                    }
+                }
+                Ok(Doc::new(context.finish().as_ref()))
+            }
+            DocIdentity::Xxh3 | DocIdentity::Path => {
+                let mut hasher = XxHash3_128::new();
+                loop {
+                    match r.read(&mut buf)? {
+                        0 => break,
+                        n => hasher.write(&buf[..n]),
+                    }
+                }
+                Ok(Doc::new(&hasher.finish_128().to_le_bytes()))
+            }
+            #[cfg(feature = "blake3")]
+            DocIdentity::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                loop {
+                    match r.read(&mut buf)? {
+                        0 => break,
+                        n => hasher.update(&buf[..n]),
+                    };
+                }
+                Ok(Doc::new(hasher.finalize().as_bytes()))
+            }
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    /// `check_available`, as an I/O error for the readers of documents.
+    pub(crate) fn check_hashable(self) -> io::Result<()> {
+        self.check_available().map_err(|e| {
+            io::Error::new(io::ErrorKind::Unsupported, e.to_string())
+        })
+    }
+}
+
+fn xxh3(bytes: &[u8]) -> Doc {
+    Doc::new(&XxHash3_128::oneshot(bytes).to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_have_the_identity_length() {
+        let path = Path::new("docs/a.txt");
+        for identity in ALL.into_iter().filter(|i| i.is_available()) {
+            assert_eq!(DocIdentity::from_id(identity.id()), Some(identity));
+            assert_eq!(DocIdentity::from_name(identity.name()), Some(identity));
+            let doc = identity.doc(path, b"the cat");
+            assert_eq!(doc.hash.len(), identity.hash_len());
+            assert_eq!(identity.doc(path, b"the cat"), doc);
+            // Contents, or paths, tell documents apart.
+            let other = identity.doc(Path::new("docs/b.txt"), b"the dog");
+            assert_ne!(other, doc);
+            let copy = identity.doc(Path::new("docs/b.txt"), b"the cat");
+            assert_eq!(copy == doc, identity.is_by_contents());
+        }
+        assert_eq!(DocIdentity::from_id(4), None);
+        assert_eq!(DocIdentity::from_name("md5"), None);
+    }
+
+    #[test]
+    fn sha256_ids_are_sha256() {
+        let doc = DocIdentity::Sha256.doc(Path::new("a"), b"abc");
+        assert_eq!(
+            doc.to_hex(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_ids_are_blake3() {
+        let doc = DocIdentity::Blake3.doc(Path::new("a"), b"abc");
+        assert_eq!(
+            doc.to_hex(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        let mut bytes = &b"abc"[..];
+        assert_eq!(DocIdentity::Blake3.hash_contents(&mut bytes).unwrap(), doc);
+    }
+}
@@ -3,29 +3,59 @@
 //! The first step in building the index is to index documents in memory.
 //! `InMemoryIndex` can be used to do that, up to the size of the machine's
 //! memory.
+//!
+//! Documents are usually added as one `String`. One too large to be loaded
+//! at once, like a log file of several gigabytes, can be read from a
+//! `BufRead` instead with `add_document_stream`, which analyzes it in parts
+//! of `STREAM_CHUNK_LEN` bytes: only its hits are kept in memory. Either
+//! way, `limit_tokens` bounds the number of words of each document that are
+//! indexed, `record_byte_offsets` also records where each word starts in
+//! the text (see `analyzer::BYTE_OFFSETS_PREFIX`), and `record_line_numbers`
+//! on which line (see `analyzer::LINE_NUMBERS_PREFIX`).
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use std::{collections::HashMap, path::PathBuf};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead};
+use std::mem::{self, size_of};
 
-use crate::HASH_LENGTH;
+use crate::analyzer::{self, Analyzer, TextAnalyzer, Token};
+use crate::field::Field;
 
 // ───── Body ─────────────────────────────────────────────────────────────── //
 
-/// Break a string into words.
-fn tokenize(text: &str) -> Vec<&str> {
-    text.split(|ch: char| !ch.is_alphanumeric())
-        .filter(|word| !word.is_empty())
-        .collect()
-}
-
 /// A `Hit` indicates that a particular document contains some term, how many
 /// times it appears, and at what offsets (that is, the word count, from the
 /// beginning of the document, of each place where the term appears).
 ///
 /// The buffer contains all the hit data in binary form, little-endian. The
-/// first u32 of the data is the document id. The remaining [u32] are offsets.
+/// first u32 of the data is the document id, the second the number of
+/// offsets. The remaining [u32] are offsets.
+///
+/// Document ids are local to an index: they are positions in its document
+/// table, which maps them to the documents' hashes. Offsets are in increasing
+/// order, which index files rely on to store them as deltas.
 pub type Hit = Vec<u8>;
 
+/// Size of a document id in a `Hit`.
+pub(crate) const DOC_ID_LENGTH: usize = 4;
+
+/// Most documents an index, in memory or in a file, can hold: document ids
+/// are u32.
+pub const MAX_DOCS: u32 = u32::MAX;
+
+/// Most words of each field of a document that are indexed: word offsets
+/// are u32. Documents with more are truncated (see `DocInfo::truncated`).
+pub const MAX_DOC_WORDS: u32 = u32::MAX;
+
+/// Memory an in-memory index may use before it is written to disk, unless
+/// configured otherwise.
+pub const DEFAULT_MEMORY_BUDGET: usize = 512 << 20;
+
+/// Size of the parts `InMemoryIndex::add_document_stream` analyzes a
+/// document in, in bytes.
+pub const STREAM_CHUNK_LEN: usize = 1 << 20;
+
 /// An in-memory index.
 ///
 /// Of course, a real index for a large corpus of documets wont' fit in memory.
@@ -47,6 +77,27 @@ pub struct InMemoryIndex {
     /// might want to run on the index, so we preserve this property wherever
     /// possible.
     pub map: HashMap<String, Vec<Hit>>,
+    /// The indexed documents, by document id. Saved as the index file's
+    /// document table.
+    pub docs: DocTable,
+    /// Estimated memory used by `map` and `docs`, in bytes. Kept up to date
+    /// by `add_document_fields` and `merge`.
+    pub(crate) memory: usize,
+    /// Number of words of the body of the documents added past which the
+    /// others are left out, see `limit_tokens`.
+    max_tokens: Option<u32>,
+    /// Whether the byte offsets of words are recorded, see
+    /// `record_byte_offsets`.
+    byte_offsets: bool,
+    /// Whether the line numbers of words are recorded, see
+    /// `record_line_numbers`.
+    line_numbers: bool,
+}
+
+impl Default for InMemoryIndex {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InMemoryIndex {
@@ -55,65 +106,434 @@ impl InMemoryIndex {
         InMemoryIndex {
             word_count: 0,
             map: HashMap::new(),
+            docs: DocTable::new(),
+            memory: 0,
+            max_tokens: None,
+            byte_offsets: false,
+            line_numbers: false,
         }
     }
 
-    /// Index a single document.
+    /// An index of `docs`, with the hits of `map`, as written to the log of
+    /// an index directory (see `wal`).
+    pub(crate) fn from_parts(
+        word_count: usize,
+        map: HashMap<String, Vec<Hit>>,
+        docs: DocTable,
+    ) -> InMemoryIndex {
+        let memory = map
+            .iter()
+            .map(|(term, hits)| {
+                term_memory(term)
+                    + hits
+                        .iter()
+                        .map(|hit| size_of::<Hit>() + hit.len())
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+            + docs.iter().map(doc_memory).sum::<usize>();
+        InMemoryIndex {
+            word_count,
+            map,
+            docs,
+            memory,
+            ..InMemoryIndex::new()
+        }
+    }
+
+    /// Index only the first `max` words of the body of the documents added
+    /// from now on (by default, all of them), not counting their original
+    /// case forms. Documents with more are recorded as truncated (see
+    /// `DocInfo::truncated`); their other fields are indexed whole.
+    pub fn limit_tokens(&mut self, max: Option<u32>) {
+        self.max_tokens = max;
+    }
+
+    /// Also record, for the documents added from now on, the byte offset in
+    /// its text of each word of each field, as the offsets of a twin of its
+    /// term (see `analyzer::BYTE_OFFSETS_PREFIX`), for an index written with
+    /// `IndexSettings::byte_offsets`. Words starting past `u32::MAX` bytes
+    /// are then left out, and their documents recorded as truncated.
+    pub fn record_byte_offsets(&mut self, record: bool) {
+        self.byte_offsets = record;
+    }
+
+    /// Also record, for the documents added from now on, the numbers of the
+    /// lines of their body each word occurs on, from 1, as the offsets of a
+    /// twin of its term (see `analyzer::LINE_NUMBERS_PREFIX`), for an index
+    /// written with `IndexSettings::line_numbers`. Words starting past
+    /// `u32::MAX` bytes are then left out, as with `record_byte_offsets`.
+    pub fn record_line_numbers(&mut self, record: bool) {
+        self.line_numbers = record;
+    }
+
+    /// Index a single document with the default analyzer.
     ///
     /// The resulting index contains exactly on one `Hit` per term.
     pub fn from_single_document(
         document_hash: &[u8],
         text: String,
+    ) -> InMemoryIndex {
+        Self::from_single_document_with(
+            document_hash,
+            text,
+            &TextAnalyzer::default(),
+        )
+    }
+
+    /// Index a single document, using `analyzer` to extract its terms.
+    ///
+    /// The resulting index contains exactly on one `Hit` per term.
+    pub fn from_single_document_with(
+        document_hash: &[u8],
+        text: String,
+        analyzer: &dyn Analyzer,
+    ) -> InMemoryIndex {
+        Self::from_single_document_fields(document_hash, text, &[], analyzer)
+    }
+
+    /// Index a single document with `text` as its body, plus other `fields`
+    /// (see `add_document_fields`).
+    ///
+    /// The resulting index contains exactly on one `Hit` per term.
+    pub fn from_single_document_fields(
+        document_hash: &[u8],
+        text: String,
+        fields: &[(Field, String)],
+        analyzer: &dyn Analyzer,
     ) -> InMemoryIndex {
         let mut index = InMemoryIndex::new();
+        index.add_document_fields(document_hash, text, fields, analyzer);
+        index
+    }
+
+    /// Add a document to the index with the default analyzer, and return
+    /// its document id.
+    pub fn add_document(&mut self, document_hash: &[u8], text: String) -> u32 {
+        self.add_document_with(document_hash, text, &TextAnalyzer::default())
+    }
 
-        let text = text.to_lowercase();
-        let tokens = tokenize(&text);
-        for (i, token) in tokens.iter().enumerate() {
-            let vec_with_hits =
-                index.map.entry(token.to_string()).or_insert_with(|| {
-                    let mut hits = Vec::with_capacity(4 + 4); // 4 bytes + 4 bytes; u32 is 4 bytes
-                                                              // document_hash has length of 32 bytes
-                    for byte in document_hash {
-                        hits.write_u8(*byte).unwrap(); // Write doc hash to hit
+    /// Add a document to the index, using `analyzer` to extract its terms,
+    /// and return its document id.
+    pub fn add_document_with(
+        &mut self,
+        document_hash: &[u8],
+        text: String,
+        analyzer: &dyn Analyzer,
+    ) -> u32 {
+        self.add_document_fields(document_hash, text, &[], analyzer)
+    }
+
+    /// Add a document with `text` as its body, plus other `fields`, and
+    /// return its document id. The words of each field are numbered from
+    /// zero, and stored as terms prefixed by the field name (see the `field`
+    /// module).
+    ///
+    /// The document gets the next id, and its hit for each term is appended
+    /// to the term's list, so an index sorted by document id stays sorted.
+    /// Only words of the body count in `word_count`, and words in their
+    /// original case (see `analyzer::EXACT_PREFIX`) don't count again. Words
+    /// of the body past `limit_tokens`, and of any field past
+    /// `MAX_DOC_WORDS`, are left out.
+    ///
+    /// Panics if the index already holds `MAX_DOCS` documents, which takes
+    /// more memory than any machine has.
+    pub fn add_document_fields(
+        &mut self,
+        document_hash: &[u8],
+        text: String,
+        fields: &[(Field, String)],
+        analyzer: &dyn Analyzer,
+    ) -> u32 {
+        assert!(
+            self.docs.len() < MAX_DOCS as usize,
+            "an index holds at most {} documents",
+            MAX_DOCS
+        );
+        let id = self.docs.len() as u32;
+        let mut token_count = 0;
+        let mut truncated = false;
+        let mut lines = Lines::new();
+        for token in analyzer.analyze(&text) {
+            let (position, start) = (token.position, token.start as u64);
+            if self.is_past_limits(&token, position, start, token_count) {
+                truncated = true;
+                break;
+            }
+            if !analyzer::is_exact(&token.text) {
+                token_count += 1;
+            }
+            self.add_word(Field::Body, &token.text, id, position, start);
+            if self.line_numbers {
+                let line = lines.line_at(&text, token.start);
+                self.add_line(&token.text, id, line);
+            }
+        }
+        let mut field_lens = [0; Field::NAMED.len()];
+        for (field, text) in fields {
+            for token in analyzer.analyze(text) {
+                let start = token.start as u64;
+                if token.position == MAX_DOC_WORDS
+                    || self.is_past_byte_limit(start)
+                {
+                    truncated = true;
+                    break;
+                }
+                self.add_word(*field, &token.text, id, token.position, start);
+                match field.named_index() {
+                    Some(i) if !analyzer::is_exact(&token.text) => {
+                        field_lens[i] += 1
                     }
-                    // Write place for offsets count
-                    hits.write_u32::<LittleEndian>(0).unwrap();
-                    vec![hits]
-                });
-            vec_with_hits[0]
-                .write_u32::<LittleEndian>(i as u32) // Write word offset to hit
-                .unwrap();
-
-            // Update offsets count
-            let offsets_count = (&vec_with_hits[0]
-                [HASH_LENGTH..HASH_LENGTH + 4])
-                .read_u32::<LittleEndian>()
-                .unwrap()
-                + 1;
-            let offsets_count = offsets_count.to_le_bytes();
-            for (idx, byte) in vec_with_hits[0][HASH_LENGTH..HASH_LENGTH + 4]
-                .iter_mut()
-                .enumerate()
-            {
-                *byte = offsets_count[idx];
+                    _ => {}
+                }
+            }
+        }
+        let info = DocInfo {
+            path: String::new(),
+            token_count,
+            field_lens,
+            mtime: 0,
+            size: 0,
+            language: String::new(),
+            duplicates: vec![],
+            truncated,
+        };
+        let entry = (Doc::new(document_hash), info);
+        self.memory += doc_memory(&entry);
+        self.docs.push(entry);
+        self.word_count += token_count as usize;
+        id
+    }
+
+    /// Add a document whose body is the UTF-8 text `reader` reads, plus
+    /// other `fields`, like `add_document_fields`, and return its document
+    /// id. The text is analyzed `STREAM_CHUNK_LEN` bytes at a time, cut
+    /// after whitespace so that words stay whole (a word longer than that
+    /// is cut anyway), with word offsets counted from the beginning of the
+    /// document. Bytes that aren't UTF-8 are read as U+FFFD. Past
+    /// `limit_tokens`, or `MAX_DOC_WORDS`, the rest of the text isn't read.
+    ///
+    /// If reading fails, the index is left as it was.
+    pub fn add_document_stream<R: BufRead>(
+        &mut self,
+        document_hash: &[u8],
+        reader: R,
+        fields: &[(Field, String)],
+        analyzer: &dyn Analyzer,
+    ) -> io::Result<u32> {
+        // The document is indexed on its own first, so that an error leaves
+        // `self` alone.
+        let mut document = InMemoryIndex::new();
+        document.byte_offsets = self.byte_offsets;
+        document.line_numbers = self.line_numbers;
+        let mut chunks = TextChunks {
+            reader,
+            rest: vec![],
+        };
+        let mut words: u32 = 0;
+        let mut bytes: u64 = 0;
+        let mut lines = Lines::new();
+        let mut token_count = 0;
+        let mut truncated = false;
+        while let Some(chunk) = chunks.next_chunk()? {
+            let (tokens, count) = analyzer.analyze_counted(&chunk);
+            for token in tokens {
+                let position = words.saturating_add(token.position);
+                let start = bytes + token.start as u64;
+                if self.is_past_limits(&token, position, start, token_count) {
+                    truncated = true;
+                    break;
+                }
+                if !analyzer::is_exact(&token.text) {
+                    token_count += 1;
+                }
+                document.add_word(Field::Body, &token.text, 0, position, start);
+                if self.line_numbers {
+                    let line = lines.line_at(&chunk, token.start);
+                    document.add_line(&token.text, 0, line);
+                }
+            }
+            if truncated {
+                break;
             }
+            words = words.saturating_add(count);
+            bytes += chunk.len() as u64;
+            lines.next_chunk(&chunk);
+        }
+        document.add_document_fields(
+            document_hash,
+            String::new(),
+            fields,
+            analyzer,
+        );
+        document.docs[0].1.token_count = token_count;
+        document.docs[0].1.truncated = truncated;
+        document.word_count = token_count as usize;
 
-            index.word_count += 1;
+        let id = self.docs.len() as u32;
+        self.merge(document);
+        Ok(id)
+    }
+
+    /// True if `token`, at word offset `position` and byte offset `start`
+    /// of the body of a document with `token_count` words so far, is past
+    /// `MAX_DOC_WORDS`, `limit_tokens` or the byte offsets recorded.
+    fn is_past_limits(
+        &self,
+        token: &Token,
+        position: u32,
+        start: u64,
+        token_count: u32,
+    ) -> bool {
+        position == MAX_DOC_WORDS
+            || self.is_past_byte_limit(start)
+            || (!analyzer::is_exact(&token.text)
+                && self.max_tokens.is_some_and(|max| token_count >= max))
+    }
+
+    /// True if byte offsets or line numbers are recorded and `start`, or
+    /// the number of the line of a word starting there, may be too large
+    /// for one.
+    fn is_past_byte_limit(&self, start: u64) -> bool {
+        (self.byte_offsets || self.line_numbers) && start >= u32::MAX as u64
+    }
+
+    /// Record that document `id`, the last one of the index, has `word` in
+    /// `field` at word offset `position`, and at byte offset `start` if
+    /// byte offsets are recorded.
+    fn add_word(
+        &mut self,
+        field: Field,
+        word: &str,
+        id: u32,
+        position: u32,
+        start: u64,
+    ) {
+        if self.byte_offsets {
+            let twin = field.term(&analyzer::byte_offsets_word(word));
+            self.add_hit(twin, id, start as u32);
         }
-        index
+        self.add_hit(field.term(word), id, position);
+    }
+
+    /// Record that document `id`, the last one of the index, has `word` in
+    /// its body on line `line`, unless it already has it on that line.
+    fn add_line(&mut self, word: &str, id: u32, line: u32) {
+        let twin = analyzer::line_numbers_word(word);
+        let recorded = self
+            .map
+            .get(&twin)
+            .and_then(|hits| hits.last())
+            .is_some_and(|hit| {
+                doc_id(hit) == id && hit[hit.len() - 4..] == line.to_le_bytes()
+            });
+        if !recorded {
+            self.add_hit(twin, id, line);
+        }
+    }
+
+    /// Record that document `id`, the last one of the index, has `term` at
+    /// word offset `position`.
+    fn add_hit(&mut self, term: String, id: u32, position: u32) {
+        let hits = match self.map.entry(term) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.memory += term_memory(entry.key());
+                entry.insert(vec![])
+            }
+        };
+        if hits.last().is_none_or(|hit| doc_id(hit) != id) {
+            let mut hit = Vec::with_capacity(DOC_ID_LENGTH + 4 + 4);
+            hit.write_u32::<LittleEndian>(id).unwrap();
+            // The number of offsets, counted below.
+            hit.write_u32::<LittleEndian>(0).unwrap();
+            self.memory += size_of::<Hit>() + hit.len();
+            hits.push(hit);
+        }
+        let hit = hits.last_mut().unwrap();
+        hit.write_u32::<LittleEndian>(position).unwrap();
+        self.memory += 4;
+
+        let count = &mut hit[DOC_ID_LENGTH..DOC_ID_LENGTH + 4];
+        let offsets = (&count[..]).read_u32::<LittleEndian>().unwrap() + 1;
+        count.copy_from_slice(&offsets.to_le_bytes());
     }
 
     /// Add all search hits from `other` to this index.
     ///
-    /// If both `*self` and `other` are sorted by document id, and all document
-    /// ids in `other` are greater than every document id in `*self`, then
-    /// `*self` remain sorted by document id after merging.
+    /// The documents of `other` are numbered after those of `*self`, so if
+    /// both are sorted by document id, `*self` remains sorted by document id
+    /// after merging. Panics if they hold more than `MAX_DOCS` documents
+    /// together.
     pub fn merge(&mut self, other: InMemoryIndex) {
-        for (term, hits) in other.map {
-            self.map.entry(term).or_insert_with(|| vec![]).extend(hits);
+        assert!(
+            self.docs.len() + other.docs.len() <= MAX_DOCS as usize,
+            "an index holds at most {} documents",
+            MAX_DOCS
+        );
+        let base = self.docs.len() as u32;
+        for (term, mut hits) in other.map {
+            if base > 0 {
+                for hit in &mut hits {
+                    let id = doc_id(hit);
+                    hit[..DOC_ID_LENGTH]
+                        .copy_from_slice(&(base + id).to_le_bytes());
+                }
+            }
+            // The key and list of a term already in `*self` are freed.
+            if self.map.contains_key(&term) {
+                self.memory -= term_memory(&term);
+            }
+            self.map.entry(term).or_default().extend(hits);
+        }
+        self.docs.extend(other.docs);
+        self.word_count += other.word_count;
+        self.memory += other.memory;
+    }
+
+    /// Sort the hits of every term by document id, merging the hits of a
+    /// document that is there more than once (see `sort_by_doc`), so that
+    /// the index is sorted by document id again. Writing an index to a file
+    /// does this, as files must be.
+    pub fn sort_by_doc(&mut self) {
+        for hits in self.map.values_mut() {
+            sort_by_doc(hits);
         }
-        self.word_count += other.word_count
+    }
+
+    /// Decode the index for searching, without writing it to disk first.
+    pub fn to_parsed(&self) -> ParsedIndex {
+        let map: HashMap<String, DocEntry> = self
+            .map
+            .iter()
+            .map(|(term, hits)| (term.clone(), self.decode(hits)))
+            .collect();
+        ParsedIndex {
+            word_count: map.len(),
+            map,
+            docs: self.docs.iter().cloned().collect(),
+            positions: true,
+        }
+    }
+
+    /// The documents containing `term`, with its offsets in each, if the
+    /// term is in the index.
+    pub fn lookup(&self, term: &str) -> Option<DocEntry> {
+        self.map.get(term).map(|hits| self.decode(hits))
+    }
+
+    fn decode(&self, hits: &[Hit]) -> DocEntry {
+        let mut entry = DocEntry::with_capacity(hits.len());
+        for hit in hits {
+            let mut data = &hit[..];
+            let id = data.read_u32::<LittleEndian>().unwrap();
+            let count = data.read_u32::<LittleEndian>().unwrap();
+            let offsets = (0..count)
+                .map(|_| data.read_u32::<LittleEndian>().unwrap())
+                .collect();
+            entry.insert(self.docs[id as usize].0.clone(), offsets);
+        }
+        entry
     }
 
     /// True if this index contains no data.
@@ -121,16 +541,97 @@ impl InMemoryIndex {
         self.word_count == 0
     }
 
+    /// Estimated number of bytes of memory used by the index: the terms,
+    /// the hits and the document table, plus the hash map's own overhead.
+    /// Spare capacity of the buffers is not counted.
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+
     /// True if this index is large enough that we should dump it to disk
-    /// rather than keep adding more data to it.
+    /// rather than keep adding more data to it, with the default memory
+    /// budget.
     pub fn is_large(&self) -> bool {
-        //This depends on how much memory your computer has, of course.
-        const REASONABLE_SIZE: usize = 100_000_000;
-        self.word_count > REASONABLE_SIZE
+        self.memory_usage() > DEFAULT_MEMORY_BUDGET
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// The document id of a hit.
+fn doc_id(hit: &[u8]) -> u32 {
+    (&hit[..DOC_ID_LENGTH]).read_u32::<LittleEndian>().unwrap()
+}
+
+/// The number of offsets of a hit.
+fn offsets_count(hit: &[u8]) -> u32 {
+    (&hit[DOC_ID_LENGTH..]).read_u32::<LittleEndian>().unwrap()
+}
+
+/// True if `hits` have distinct document ids, in increasing order.
+pub(crate) fn is_sorted_by_doc(hits: &[Hit]) -> bool {
+    hits.windows(2).all(|w| doc_id(&w[0]) < doc_id(&w[1]))
+}
+
+/// Sort `hits` by document id. The hits of a document that is there more
+/// than once become one: their offsets are merged, or, for hits without
+/// offsets (see `IndexSettings::omit_positions`), their counts summed.
+pub(crate) fn sort_by_doc(hits: &mut Vec<Hit>) {
+    if is_sorted_by_doc(hits) {
+        return;
+    }
+    hits.sort_by_key(|hit| doc_id(hit));
+    let mut merged: Vec<Hit> = Vec::with_capacity(hits.len());
+    for hit in hits.drain(..) {
+        match merged.last_mut() {
+            Some(last) if doc_id(last) == doc_id(&hit) => merge_hit(last, &hit),
+            _ => merged.push(hit),
+        }
+    }
+    *hits = merged;
+}
+
+/// Add the offsets of `other`, a hit of the same document, to `hit`.
+fn merge_hit(hit: &mut Hit, other: &Hit) {
+    const OFFSETS_START: usize = DOC_ID_LENGTH + 4;
+    let count = if hit.len() == OFFSETS_START && other.len() == OFFSETS_START {
+        offsets_count(hit) + offsets_count(other)
+    } else {
+        let mut offsets: Vec<u32> = hit[OFFSETS_START..]
+            .chunks_exact(4)
+            .chain(other[OFFSETS_START..].chunks_exact(4))
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        hit.truncate(OFFSETS_START);
+        for offset in &offsets {
+            hit.extend_from_slice(&offset.to_le_bytes());
+        }
+        offsets.len() as u32
+    };
+    hit[DOC_ID_LENGTH..OFFSETS_START].copy_from_slice(&count.to_le_bytes());
+}
+
+/// Memory used by a term's key and its entry in the map, without its hits.
+/// A hash map needs a bit more than one slot per entry, plus a control byte.
+fn term_memory(term: &str) -> usize {
+    let slot = size_of::<(String, Vec<Hit>)>();
+    slot + slot / 8 + 1 + term.len()
+}
+
+/// Memory used by an entry of the document table.
+fn doc_memory((doc, info): &(Doc, DocInfo)) -> usize {
+    size_of::<(Doc, DocInfo)>()
+        + doc.hash.len()
+        + info.path.len()
+        + info.language.len()
+        + info
+            .duplicates
+            .iter()
+            .map(|path| size_of::<String>() + path.len())
+            .sum::<usize>()
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Doc {
     pub hash: Vec<u8>,
 }
@@ -147,14 +648,581 @@ impl Doc {
     pub fn new(hash: &[u8]) -> Self {
         Doc { hash: hash.into() }
     }
+
+    /// The document hash as a lowercase hex string.
+    pub fn to_hex(&self) -> String {
+        self.hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Parse a document hash written as a hex string.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return None;
+        }
+        let hash = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Doc { hash })
+    }
 }
 
 pub type Offsets = Vec<u32>;
 
 pub type DocEntry = HashMap<Doc, Offsets>;
 
+/// What the document table records about a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocInfo {
+    /// Path of the document when it was indexed.
+    pub path: String,
+    /// Number of words the document was split into.
+    pub token_count: u32,
+    /// Number of words of each field other than the body, in the order of
+    /// `Field::NAMED`.
+    pub field_lens: [u32; Field::NAMED.len()],
+    /// Modification time of the file, in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// Language the document is written in, like "english", or empty if it
+    /// wasn't detected.
+    pub language: String,
+    /// Paths of other files with the same contents, which weren't indexed
+    /// again (see `Indexer::dedup`).
+    pub duplicates: Vec<String>,
+    /// True if only the beginning of the document was indexed, because it
+    /// was larger than the limits of the indexer (see `DocLimits`).
+    pub truncated: bool,
+}
+
+impl DocInfo {
+    /// Number of words of `field`, `token_count` for the body.
+    pub fn len_of(&self, field: Field) -> u32 {
+        match field.named_index() {
+            Some(i) => self.field_lens[i],
+            None => self.token_count,
+        }
+    }
+}
+
+/// The document table of an index: its documents, by document id.
+pub type DocTable = Vec<(Doc, DocInfo)>;
+
 #[derive(Debug)]
 pub struct ParsedIndex {
     pub word_count: usize,
     pub map: HashMap<String, DocEntry>,
+    pub docs: HashMap<Doc, DocInfo>,
+    /// False if the index was built without positions (see
+    /// `IndexSettings::omit_positions`): the offsets of a document then only
+    /// tell how many times it has the term.
+    pub positions: bool,
+}
+
+impl ParsedIndex {
+    /// Every document that appears anywhere in the index.
+    pub fn all_docs(&self) -> HashSet<Doc> {
+        self.map
+            .values()
+            .flat_map(|entry| entry.keys().cloned())
+            .collect()
+    }
+
+    /// Find the documents containing `terms` as an exact phrase, that is, with
+    /// each term appearing at the word offset right after the previous one.
+    ///
+    /// Terms are lowercased, the same way they are at indexing time. The
+    /// documents are returned ordered by hash.
+    pub fn phrase_search(&self, terms: &[&str]) -> Vec<Doc> {
+        let terms: Vec<String> =
+            terms.iter().map(|t| t.to_lowercase()).collect();
+        let positioned: Vec<(&str, u32)> = terms
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.as_str(), i as u32))
+            .collect();
+        self.phrase_search_at(&positioned)
+    }
+
+    /// Like `phrase_search`, but with an explicit position for each term
+    /// relative to the start of the phrase. This allows gaps, e.g. where the
+    /// analyzer dropped stop words: "state of the art" is searched as
+    /// `[("state", 0), ("art", 3)]`.
+    ///
+    /// Terms are used as given, without lowercasing. Without positions,
+    /// this finds the documents containing all the terms.
+    pub fn phrase_search_at(&self, terms: &[(&str, u32)]) -> Vec<Doc> {
+        if !self.positions {
+            return self.containing_all(terms.iter().map(|(term, _)| *term));
+        }
+        let entries = match terms
+            .iter()
+            .map(|(term, position)| self.map.get(*term).map(|e| (e, position)))
+            .collect::<Option<Vec<(&DocEntry, &u32)>>>()
+        {
+            Some(entries) if !entries.is_empty() => entries,
+            _ => return vec![],
+        };
+
+        let (first_entry, &first_position) = entries[0];
+        let mut docs: Vec<Doc> = first_entry
+            .iter()
+            .filter(|(doc, first_offsets)| {
+                first_offsets.iter().any(|&offset| {
+                    let Some(start) = offset.checked_sub(first_position) else {
+                        return false;
+                    };
+                    entries[1..].iter().all(|(entry, &position)| {
+                        entry.get(*doc).is_some_and(|offsets| {
+                            offsets.contains(&(start + position))
+                        })
+                    })
+                })
+            })
+            .map(|(doc, _)| doc.clone())
+            .collect();
+        docs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        docs
+    }
+
+    /// Find the documents where `a` and `b` occur within `distance` words of
+    /// each other, in either order. The documents are returned ordered by
+    /// hash. Without positions, this finds the documents containing both.
+    pub fn near_search(&self, a: &str, b: &str, distance: u32) -> Vec<Doc> {
+        if !self.positions {
+            return self.containing_all([a, b]);
+        }
+        let (a_entry, b_entry) = match (self.map.get(a), self.map.get(b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return vec![],
+        };
+        let mut docs: Vec<Doc> = a_entry
+            .iter()
+            .filter(|(doc, a_offsets)| {
+                b_entry.get(*doc).is_some_and(|b_offsets| {
+                    within(a_offsets, b_offsets, distance)
+                })
+            })
+            .map(|(doc, _)| doc.clone())
+            .collect();
+        docs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        docs
+    }
+
+    /// Find the documents where all of `terms` occur, in any order, with at
+    /// most `distance` words between the first and the last of them. The
+    /// documents are returned ordered by hash. Without positions, this finds
+    /// the documents containing all of `terms`.
+    pub fn proximity_search(&self, terms: &[&str], distance: u32) -> Vec<Doc> {
+        if !self.positions {
+            return self.containing_all(terms.iter().copied());
+        }
+        let entries = match terms
+            .iter()
+            .map(|term| self.map.get(*term))
+            .collect::<Option<Vec<&DocEntry>>>()
+        {
+            Some(entries) if !entries.is_empty() => entries,
+            _ => return vec![],
+        };
+        let mut docs: Vec<Doc> = entries[0]
+            .keys()
+            .filter(|doc| {
+                entries
+                    .iter()
+                    .map(|entry| entry.get(*doc).map(Vec::as_slice))
+                    .collect::<Option<Vec<&[u32]>>>()
+                    .is_some_and(|offsets| span_within(&offsets, distance))
+            })
+            .cloned()
+            .collect();
+        docs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        docs
+    }
+
+    /// The documents containing all of `terms`, ordered by hash.
+    fn containing_all<'a>(
+        &self,
+        terms: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<Doc> {
+        let Some(entries) = terms
+            .into_iter()
+            .map(|term| self.map.get(term))
+            .collect::<Option<Vec<&DocEntry>>>()
+        else {
+            return vec![];
+        };
+        let Some((first, others)) = entries.split_first() else {
+            return vec![];
+        };
+        let mut docs: Vec<Doc> = first
+            .keys()
+            .filter(|doc| others.iter().all(|entry| entry.contains_key(*doc)))
+            .cloned()
+            .collect();
+        docs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        docs
+    }
+
+    /// The terms of the index in the same field as `term` that are at most
+    /// `distance` edits (inserted, deleted or replaced characters) away from
+    /// it, `term` itself included if it is in the index. Words in their
+    /// original case only match words in their original case, and the
+    /// other way round.
+    pub fn fuzzy_terms(&self, term: &str, distance: u32) -> Vec<&str> {
+        fuzzy_matches(term, distance, self.map.keys().map(String::as_str))
+    }
+
+    /// Where each occurrence of `term` in `doc` starts in its text, in
+    /// bytes, in the order of its word offsets, if the index has byte
+    /// offsets (see `IndexSettings::byte_offsets`).
+    pub fn byte_offsets(&self, term: &str, doc: &Doc) -> Option<&Offsets> {
+        let (field, word) = Field::split(term);
+        let twin = field.term(&analyzer::byte_offsets_word(word));
+        self.map.get(&twin)?.get(doc)
+    }
+
+    /// The numbers of the lines of the body of `doc` that `term` occurs on,
+    /// from 1, in increasing order, if the index has line numbers (see
+    /// `IndexSettings::line_numbers`).
+    pub fn line_numbers(&self, term: &str, doc: &Doc) -> Option<&Offsets> {
+        let twin = analyzer::line_numbers_word(term);
+        self.map.get(&twin)?.get(doc)
+    }
+}
+
+/// True if `term`, an index term, holds the byte offsets or line numbers of
+/// another term rather than a word (see `analyzer::BYTE_OFFSETS_PREFIX` and
+/// `analyzer::LINE_NUMBERS_PREFIX`). Statistics of terms leave those out.
+pub fn is_twin_term(term: &str) -> bool {
+    analyzer::is_twin(Field::split(term).1)
+}
+
+/// The terms of `candidates` that `ParsedIndex::fuzzy_terms` would find,
+/// sorted. Terms holding byte offsets never match.
+pub(crate) fn fuzzy_matches<'a>(
+    term: &str,
+    distance: u32,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Vec<&'a str> {
+    let (field, word) = Field::split(term);
+    let exact = analyzer::is_exact(word);
+    let word: Vec<char> = word.chars().collect();
+    let mut terms: Vec<&str> = candidates
+        .filter(|candidate| {
+            let (f, w) = Field::split(candidate);
+            f == field
+                && !analyzer::is_twin(w)
+                && analyzer::is_exact(w) == exact
+                && edit_distance(&word, w) <= distance as usize
+        })
+        .collect();
+    terms.sort_unstable();
+    terms
+}
+
+/// True if one offset can be picked from each of `lists` so that they are
+/// all at most `distance` apart. Repeatedly moves past the smallest offset
+/// picked so far, which can only make the range narrower.
+fn span_within(lists: &[&[u32]], distance: u32) -> bool {
+    let mut next = vec![0; lists.len()];
+    loop {
+        let mut min = (u32::MAX, 0);
+        let mut max = 0;
+        for (i, list) in lists.iter().enumerate() {
+            let Some(&offset) = list.get(next[i]) else {
+                return false;
+            };
+            min = min.min((offset, i));
+            max = max.max(offset);
+        }
+        if max - min.0 <= distance {
+            return true;
+        }
+        next[min.1] += 1;
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+fn edit_distance(a: &[char], b: &str) -> usize {
+    let mut row: Vec<usize> = (0..=a.len()).collect();
+    for (j, cb) in b.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = j + 1;
+        for (i, &ca) in a.iter().enumerate() {
+            let replace = diagonal + usize::from(ca != cb);
+            diagonal = row[i + 1];
+            row[i + 1] = replace.min(row[i] + 1).min(diagonal + 1);
+        }
+    }
+    row[a.len()]
+}
+
+/// True if some offset in `a` is at most `distance` away from some offset in
+/// `b`. Offsets are stored in increasing order, so the two lists are walked
+/// together, like a merge.
+fn within(a: &[u32], b: &[u32], distance: u32) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].abs_diff(b[j]) <= distance {
+            return true;
+        }
+        if a[i] < b[j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    false
+}
+
+/// The text a `BufRead` reads, in parts of about `STREAM_CHUNK_LEN` bytes.
+struct TextChunks<R> {
+    reader: R,
+    /// What was read past the end of the previous part.
+    rest: Vec<u8>,
+}
+
+impl<R: BufRead> TextChunks<R> {
+    /// The next part of the text, if any, ending after whitespace unless a
+    /// word takes the whole part, and never in the middle of a character.
+    fn next_chunk(&mut self) -> io::Result<Option<String>> {
+        let mut chunk = mem::take(&mut self.rest);
+        while chunk.len() < STREAM_CHUNK_LEN {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let n = buf.len().min(STREAM_CHUNK_LEN - chunk.len());
+            chunk.extend_from_slice(&buf[..n]);
+            self.reader.consume(n);
+        }
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+        if chunk.len() == STREAM_CHUNK_LEN {
+            let cut = match chunk.iter().rposition(u8::is_ascii_whitespace) {
+                Some(i) => i + 1,
+                // Before the last character, if it is incomplete.
+                None => match std::str::from_utf8(&chunk) {
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    _ => chunk.len(),
+                },
+            };
+            self.rest = chunk.split_off(cut);
+        }
+        Ok(Some(match String::from_utf8(chunk) {
+            Ok(text) => text,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }))
+    }
+}
+
+/// Numbers the lines of a text, read whole or in parts, as its words are
+/// recorded.
+struct Lines {
+    /// Number of the line of the byte at `counted`, from 1.
+    line: u32,
+    /// Position in the current part of the text up to which newlines are
+    /// counted.
+    counted: usize,
+}
+
+impl Lines {
+    fn new() -> Lines {
+        Lines {
+            line: 1,
+            counted: 0,
+        }
+    }
+
+    /// The number of the line of the byte at `start` of `text`, the current
+    /// part of the text.
+    fn line_at(&mut self, text: &str, start: usize) -> u32 {
+        let newlines = |bytes: &[u8]| {
+            let n = bytes.iter().filter(|&&b| b == b'\n').count();
+            u32::try_from(n).unwrap_or(u32::MAX)
+        };
+        // Words usually come in order, but analyzers don't have to.
+        if start >= self.counted {
+            let n = newlines(&text.as_bytes()[self.counted..start]);
+            self.line = self.line.saturating_add(n);
+        } else {
+            let n = newlines(&text.as_bytes()[start..self.counted]);
+            self.line = self.line.saturating_sub(n);
+        }
+        self.counted = start;
+        self.line
+    }
+
+    /// Move on to the part of the text after `text`.
+    fn next_chunk(&mut self, text: &str) {
+        self.line_at(text, text.len());
+        self.counted = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HASH_LENGTH;
+
+    const TEXTS: [&str; 3] = ["the cat sat", "a dog", "the cat and the dog"];
+
+    #[test]
+    fn added_documents_match_merged_ones() {
+        let mut added = InMemoryIndex::new();
+        let mut merged = InMemoryIndex::new();
+        for (i, text) in TEXTS.into_iter().enumerate() {
+            let hash = [i as u8; HASH_LENGTH];
+            assert_eq!(added.add_document(&hash, text.to_string()), i as u32);
+            merged.merge(InMemoryIndex::from_single_document(
+                &hash,
+                text.to_string(),
+            ));
+        }
+        assert_eq!(added.map, merged.map);
+        assert_eq!(added.docs, merged.docs);
+        assert_eq!(added.word_count, 10);
+        assert_eq!(added.memory_usage(), merged.memory_usage());
+    }
+
+    #[test]
+    fn added_hits_stay_sorted_by_document_id() {
+        let mut index = InMemoryIndex::new();
+        for (i, text) in TEXTS.into_iter().enumerate() {
+            index.add_document(&[i as u8; HASH_LENGTH], text.to_string());
+        }
+        for hits in index.map.values() {
+            let ids: Vec<u32> = hits.iter().map(|hit| doc_id(hit)).collect();
+            assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+        }
+        // "the" twice in the last document: one hit with both offsets.
+        let the = index.lookup("the").unwrap();
+        assert_eq!(the[&Doc::new(&[2; HASH_LENGTH])], vec![0, 3]);
+    }
+
+    #[test]
+    fn hits_of_a_document_are_merged_when_sorting() {
+        let mut index = InMemoryIndex::new();
+        index.add_document(&[0; HASH_LENGTH], "the cat".to_string());
+        index.add_document(&[1; HASH_LENGTH], "the dog".to_string());
+        // The hits of "the" in the wrong order, the first document twice.
+        let the = index.map.get_mut("the").unwrap();
+        the.reverse();
+        the.push(the[1].clone());
+        assert!(!is_sorted_by_doc(the));
+
+        index.sort_by_doc();
+        let the = &index.map["the"];
+        assert!(is_sorted_by_doc(the));
+        assert_eq!(the.len(), 2);
+        assert_eq!(offsets_count(&the[0]), 1);
+    }
+
+    #[test]
+    fn byte_offsets_follow_word_offsets() {
+        let analyzer = crate::analyzer::AnalyzerConfig {
+            preserve_case: true,
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut index = InMemoryIndex::new();
+        index.record_byte_offsets(true);
+        let fields = [(Field::Title, "Über cats".to_string())];
+        let text = "Über the cat, über the dog".to_string();
+        index.add_document_fields(&[0; HASH_LENGTH], text, &fields, &analyzer);
+        let index = index.to_parsed();
+        let doc = Doc::new(&[0; HASH_LENGTH]);
+        assert_eq!(index.map["über"][&doc], vec![0, 3]);
+        assert_eq!(index.byte_offsets("über", &doc), Some(&vec![0, 15]));
+        assert_eq!(index.byte_offsets("=über", &doc), Some(&vec![15]));
+        assert_eq!(index.byte_offsets("title:cats", &doc), Some(&vec![6]));
+        assert_eq!(index.byte_offsets("dog", &doc), Some(&vec![25]));
+        // They count neither as words nor as terms to match.
+        assert_eq!(index.docs[&doc].token_count, 6);
+        assert_eq!(index.fuzzy_terms("cat", 1), ["cat"]);
+        assert!(is_twin_term("title:@cats"));
+    }
+
+    #[test]
+    fn line_numbers_are_recorded_once_per_line() {
+        let mut index = InMemoryIndex::new();
+        index.record_line_numbers(true);
+        let fields = [(Field::Title, "The end".to_string())];
+        let text = "the cat\nthe dog, the end\n\r\n\ncat".to_string();
+        let analyzer = TextAnalyzer::default();
+        index.add_document_fields(&[0; HASH_LENGTH], text, &fields, &analyzer);
+        let index = index.to_parsed();
+        let doc = Doc::new(&[0; HASH_LENGTH]);
+        assert_eq!(index.map["the"][&doc], vec![0, 2, 4]);
+        assert_eq!(index.line_numbers("the", &doc), Some(&vec![1, 2]));
+        assert_eq!(index.line_numbers("cat", &doc), Some(&vec![1, 5]));
+        assert_eq!(index.line_numbers("title:end", &doc), None);
+        assert_eq!(index.byte_offsets("the", &doc), None);
+        assert_eq!(index.docs[&doc].token_count, 7);
+        assert!(is_twin_term("#cat"));
+    }
+
+    #[test]
+    fn streamed_documents_match_loaded_ones() {
+        let analyzer = crate::analyzer::AnalyzerConfig {
+            stop_words: vec!["english".to_string()],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        // Several chunks, with stop words and multi-byte characters.
+        let text: String = (0..400_000)
+            .map(|i| match i % 4 {
+                0 => "the ".to_string(),
+                1 => format!("wörd{}\n", i % 97),
+                _ => format!("été{} ", i % 13),
+            })
+            .collect();
+        assert!(text.len() > 2 * STREAM_CHUNK_LEN);
+        let fields = [(Field::Path, "logs/big.log".to_string())];
+        let hash = [7; HASH_LENGTH];
+
+        // Byte offsets too are counted from the beginning of the document.
+        let mut loaded = InMemoryIndex::new();
+        loaded.record_byte_offsets(true);
+        loaded.record_line_numbers(true);
+        loaded.add_document(&[0; HASH_LENGTH], "a cat".to_string());
+        let mut streamed = InMemoryIndex::new();
+        streamed.record_byte_offsets(true);
+        streamed.record_line_numbers(true);
+        streamed.add_document(&[0; HASH_LENGTH], "a cat".to_string());
+        loaded.add_document_fields(&hash, text.clone(), &fields, &analyzer);
+        let id = streamed
+            .add_document_stream(&hash, text.as_bytes(), &fields, &analyzer)
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(streamed.map, loaded.map);
+        assert_eq!(streamed.docs, loaded.docs);
+        assert_eq!(streamed.word_count, loaded.word_count);
+        let streamed = streamed.to_parsed();
+        let ete = streamed.byte_offsets("été2", &Doc::new(&hash)).unwrap();
+        assert!(*ete.last().unwrap() as usize > STREAM_CHUNK_LEN);
+        for &start in ete {
+            assert!(text[start as usize..].starts_with("été2 "));
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        let ete = streamed.line_numbers("été2", &Doc::new(&hash)).unwrap();
+        assert!(ete.len() > 1000);
+        for &line in ete {
+            assert!(lines[line as usize - 1].contains("été2 "));
+        }
+
+        // A word longer than a chunk is cut, but not its characters.
+        let long = "é".repeat(STREAM_CHUNK_LEN);
+        let mut index = InMemoryIndex::new();
+        index
+            .add_document_stream(&hash, long.as_bytes(), &[], &analyzer)
+            .unwrap();
+        assert!(index.map.keys().all(|term| term.chars().all(|c| c == 'é')));
+        assert_eq!(index.docs[0].1.token_count, 2);
+    }
 }
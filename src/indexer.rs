@@ -0,0 +1,1654 @@
+//! Building an index from a set of text files.
+//!
+//! This is where the pieces from `index`, `write` and `merge` are put
+//! together, in two different ways:
+//!
+//! *    `run_single_threaded` simply does everything in one thread, in the
+//!      most straightforward possible way.
+//!
+//! *    `run_parallel` spreads reading and tokenizing the documents over a
+//!      work-stealing thread pool, and merges the resulting in-memory indexes
+//!      in a parallel reduction. Not on wasm32, which has no threads.
+//!
+//! Both are driven by an `Indexer`, which collects the inputs, output
+//! directory and settings, and can report progress to a `ProgressReporter`.
+//! With `Indexer::dedup`, a first stage hashes the input files and leaves
+//! out those that are copies of another. With `Indexer::io_threads`, files
+//! are read, decoded and hashed by threads of their own, a few files ahead
+//! of those tokenizing them (see `prefetch`). Text files too large to be
+//! loaded whole are tokenized as they are read, see
+//! `Indexer::stream_larger_than`, and `Indexer::limits` bounds the size of
+//! the documents indexed.
+
+use log::{debug, info, trace};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufReader, Read, Seek};
+use std::mem::{self, size_of};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::ThreadPoolBuilder;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::Analyzer;
+use crate::archive::{self, Member};
+use crate::decode::{self, BINARY_SNIFF_LEN};
+use crate::error::{Error, Result};
+use crate::extract::Extractors;
+use crate::field::Field;
+use crate::header::IndexSettings;
+use crate::identity::DocIdentity;
+use crate::index::{Doc, InMemoryIndex, DEFAULT_MEMORY_BUDGET};
+use crate::language::LanguageAnalyzers;
+use crate::limits::{DocLimits, LimitPolicy};
+use crate::lock::DirLock;
+use crate::manifest::{input_key, Manifest};
+use crate::merge::{FileMerge, DEFAULT_FAN_IN, MERGED_FILENAME};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::prefetch::prefetch;
+use crate::tmp::TmpDir;
+use crate::walk::FileFilter;
+use crate::write::write_index_to_tmp_file;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Number of files read ahead of the threads indexing them, unless
+/// configured otherwise (see `Indexer::read_ahead`).
+pub const DEFAULT_READ_AHEAD: usize = 64;
+
+/// Size in bytes above which text files are streamed rather than loaded,
+/// unless configured otherwise (see `Indexer::stream_larger_than`).
+pub const DEFAULT_STREAM_LEN: u64 = 256 << 20;
+
+/// Something that happened while building an index, passed to the
+/// `ProgressReporter` set with `Indexer::reporter` or `Indexer::on_progress`.
+#[derive(Debug, Clone)]
+pub enum Progress {
+    /// The inputs were walked: `files` files, of `bytes` bytes in all, are
+    /// to be indexed. Comes first.
+    Discovered { files: usize, bytes: u64 },
+    /// A file was read and indexed in memory (for archives, all of their
+    /// members). `done` of the `total` files have been indexed or skipped
+    /// so far, `bytes` bytes of them have been read, and `docs` documents
+    /// indexed.
+    Indexed {
+        path: PathBuf,
+        done: usize,
+        total: usize,
+        bytes: u64,
+        docs: usize,
+    },
+    /// A file was left out of the index because it couldn't be read as text,
+    /// e.g. because it is binary, or because it is larger than the limits of
+    /// the indexer (see `Indexer::limits`).
+    Skipped { path: PathBuf, reason: String },
+    /// A batch of in-memory indexes was saved to a temporary file.
+    SegmentWritten { path: PathBuf },
+    /// All documents are indexed; the `segments` temporary files, and the
+    /// last batch of documents, still in memory, are being merged. The
+    /// batch in memory counts as a segment.
+    Merging { segments: usize },
+    /// `files` temporary files were merged into one, counting the batch in
+    /// memory when it is part of the merge. With many segments, this
+    /// happens several times, and may start before `Merging`.
+    Merged { files: usize },
+}
+
+/// Receives the progress of building an index. Any
+/// `Fn(&Progress) + Send + Sync` is one.
+///
+/// With several threads, `report` is called from the worker threads.
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, progress: &Progress);
+}
+
+impl<F> ProgressReporter for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn report(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Builds an index file from a set of text files.
+///
+/// ```text
+/// Indexer::new("docs").threads(8).output("out").run()?;
+/// ```
+pub struct Indexer {
+    inputs: Vec<PathBuf>,
+    filter: FileFilter,
+    extractors: Extractors,
+    output_dir: PathBuf,
+    threads: usize,
+    io_threads: usize,
+    read_ahead: usize,
+    stream_len: u64,
+    limits: DocLimits,
+    memory_budget: usize,
+    merge_fan_in: usize,
+    shards: usize,
+    settings: IndexSettings,
+    resume: bool,
+    deterministic: bool,
+    dedup: bool,
+    /// The inputs left out by `dedup`, by the input with the same contents
+    /// that is indexed.
+    duplicates: HashMap<PathBuf, Vec<PathBuf>>,
+    progress: Option<Arc<dyn ProgressReporter>>,
+}
+
+impl Indexer {
+    /// Index `input`, a file or a directory. Directories are walked
+    /// recursively; see `filter`.
+    pub fn new<P: AsRef<Path>>(input: P) -> Indexer {
+        Indexer::with_inputs([input])
+    }
+
+    /// Index all of `inputs`, each a file or a directory.
+    pub fn with_inputs<I, P>(inputs: I) -> Indexer
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Indexer {
+            inputs: inputs
+                .into_iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+            filter: FileFilter::default(),
+            extractors: Extractors::default(),
+            output_dir: PathBuf::from("."),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            io_threads: 0,
+            read_ahead: DEFAULT_READ_AHEAD,
+            stream_len: DEFAULT_STREAM_LEN,
+            limits: DocLimits::default(),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            merge_fan_in: DEFAULT_FAN_IN,
+            shards: 1,
+            settings: IndexSettings::default(),
+            resume: false,
+            deterministic: false,
+            dedup: false,
+            duplicates: HashMap::new(),
+            progress: None,
+        }
+    }
+
+    /// Also index `input`.
+    pub fn input<P: AsRef<Path>>(mut self, input: P) -> Indexer {
+        self.inputs.push(input.as_ref().to_path_buf());
+        self
+    }
+
+    /// Only index the files under the input directories that pass `filter`.
+    pub fn filter(mut self, filter: FileFilter) -> Indexer {
+        self.filter = filter;
+        self
+    }
+
+    /// Get the text of documents with `extractors` (by default, the
+    /// built-in ones).
+    pub fn extractors(mut self, extractors: Extractors) -> Indexer {
+        self.extractors = extractors;
+        self
+    }
+
+    /// Write the index to `index.dat` in `dir` (by default, the current
+    /// directory). Temporary files are created there too.
+    pub fn output<P: AsRef<Path>>(mut self, dir: P) -> Indexer {
+        self.output_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Number of threads reading and tokenizing documents, and merging the
+    /// temporary files (by default, one per CPU). With 1, and always on
+    /// wasm32, all the work is done on the calling thread.
+    pub fn threads(mut self, threads: usize) -> Indexer {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Number of threads reading, decoding and hashing files, apart from
+    /// those tokenizing them (by default, none: each thread reads the files
+    /// it tokenizes). When reading is slow, e.g. from a network disk or
+    /// with large files, this keeps the tokenizing threads busy. Also the
+    /// number of threads hashing files for `dedup`. Ignored on wasm32.
+    pub fn io_threads(mut self, threads: usize) -> Indexer {
+        self.io_threads = threads;
+        self
+    }
+
+    /// With `io_threads`, the number of files read ahead of the threads
+    /// tokenizing them (by default, 64), all of which may be in memory at
+    /// once, twice over with several tokenizing threads.
+    pub fn read_ahead(mut self, files: usize) -> Indexer {
+        self.read_ahead = files.max(1);
+        self
+    }
+
+    /// Tokenize the UTF-8 text files larger than `bytes` (by default, 256
+    /// MiB) as they are read, instead of loading them whole, so that a log
+    /// file of several gigabytes only takes memory for its hits. Their
+    /// language isn't detected. Files an extractor handles, or in another
+    /// encoding, are still loaded.
+    pub fn stream_larger_than(mut self, bytes: u64) -> Indexer {
+        self.stream_len = bytes;
+        self
+    }
+
+    /// Skip or truncate the documents larger than `limits` (by default,
+    /// there are no limits), so that a single pathological file can't blow
+    /// up the index, or the memory it is built in. See `DocLimits`.
+    pub fn limits(mut self, limits: DocLimits) -> Indexer {
+        self.limits = limits;
+        self
+    }
+
+    /// Memory, in bytes, that in-memory indexes may use before they are
+    /// written to temporary files (by default, 512 MiB). It is shared
+    /// between the threads.
+    pub fn memory_budget(mut self, bytes: usize) -> Indexer {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Merge at most `fan_in` temporary files at a time (by default, 128).
+    /// Each of them is open during the merge, so this must stay well below
+    /// the open file limit.
+    pub fn merge_fan_in(mut self, fan_in: usize) -> Indexer {
+        self.merge_fan_in = fan_in;
+        self
+    }
+
+    /// Write the index as `shards` files split by term, listed in
+    /// `shards.json`, instead of a single `index.dat` (see `shard`).
+    pub fn shards(mut self, shards: usize) -> Indexer {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// The codec and analyzer to build the index with.
+    pub fn settings(mut self, settings: IndexSettings) -> Indexer {
+        self.settings = settings;
+        self
+    }
+
+    /// Resume the build interrupted in the output directory, if any,
+    /// instead of starting over. See `manifest`.
+    pub fn resume(mut self, resume: bool) -> Indexer {
+        self.resume = resume;
+        self
+    }
+
+    /// Build the same index file, byte for byte, every time the same files
+    /// are indexed with the same settings, whatever the number of threads
+    /// and however the work gets scheduled.
+    ///
+    /// Documents get ids in the order of the inputs and the temporary files
+    /// are merged in that order too, and modification times are left out
+    /// of the document table (recorded as 0). A resumed build (see `resume`)
+    /// has the same documents, but not necessarily in the same order.
+    pub fn deterministic(mut self, deterministic: bool) -> Indexer {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Index files with the same contents only once, under the first of
+    /// their paths among the inputs, and record the paths of the others in
+    /// the document table as its duplicates (see `DocInfo::duplicates`).
+    /// Members of archives that are copies of each other get the paths of
+    /// the same members of the copies.
+    ///
+    /// The copies are found by hashing all the input files before indexing
+    /// any, so every file is read twice.
+    pub fn dedup(mut self, dedup: bool) -> Indexer {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Report progress to `reporter` as the work goes on.
+    pub fn reporter<R>(mut self, reporter: Arc<R>) -> Indexer
+    where
+        R: ProgressReporter + 'static,
+    {
+        self.progress = Some(reporter);
+        self
+    }
+
+    /// Call `callback` as the work progresses. With several threads, it is
+    /// called from the worker threads.
+    pub fn on_progress<F>(self, callback: F) -> Indexer
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.reporter(Arc::new(callback))
+    }
+
+    /// Build the index. Returns the path of the index file, or of the output
+    /// directory if the index is sharded. Fails with `Error::Locked` if
+    /// another process is writing to the output directory, and with
+    /// `Error::Incompatible` if the settings ask for byte offsets or line
+    /// numbers without positions.
+    pub fn run(mut self) -> Result<PathBuf> {
+        self.settings.identity.check_available()?;
+        let settings = &self.settings;
+        if settings.omit_positions && settings.byte_offsets {
+            return Err(Error::Incompatible(
+                "byte offsets can't be stored without positions".into(),
+            ));
+        }
+        if settings.omit_positions && settings.line_numbers {
+            return Err(Error::Incompatible(
+                "line numbers can't be stored without positions".into(),
+            ));
+        }
+        // Held until the index is complete.
+        let _writing = DirLock::write(&self.output_dir)?;
+        // Inputs are numbered so that segments can be put back in order.
+        let mut documents: Vec<(usize, PathBuf)> = self
+            .filter
+            .expand(&self.inputs)?
+            .into_iter()
+            .enumerate()
+            .collect();
+        if self.dedup {
+            documents = self.remove_duplicates(documents)?;
+        }
+        let analyzers = LanguageAnalyzers::new(&self.settings.analyzer)?;
+        let extractors =
+            mem::replace(&mut self.extractors, Extractors::plain_text());
+        self.extractors = extractors.routed(&self.settings.analyzer.routes)?;
+        let manifest = if self.resume {
+            let manifest = Manifest::load(&self.output_dir, &self.settings)?;
+            let done = manifest.inputs();
+            documents.retain(|(_, path)| !done.contains(&input_key(path)));
+            info!(
+                "Resuming with {} segments, {} files left to index",
+                manifest.segments().count(),
+                documents.len()
+            );
+            manifest
+        } else {
+            Manifest::new(&self.output_dir, &self.settings)
+        };
+        let manifest = Mutex::new(manifest);
+        let reporter = Reporter {
+            callback: self.progress.take(),
+            done: AtomicUsize::new(0),
+            total: documents.len(),
+            bytes: AtomicU64::new(0),
+            docs: AtomicUsize::new(0),
+        };
+        reporter.report(|| Progress::Discovered {
+            files: documents.len(),
+            bytes: documents.iter().map(|(_, path)| file_size(path)).sum(),
+        });
+
+        let rest = match self.threads {
+            #[cfg(not(target_arch = "wasm32"))]
+            2.. => {
+                self.run_parallel(documents, &analyzers, &reporter, &manifest)?
+            }
+            _ => self.run_single_threaded(
+                documents, &analyzers, &reporter, &manifest,
+            )?,
+        };
+
+        // Merge the temporary index files, those of an interrupted build
+        // first, and then the documents indexed last, which are still in
+        // memory: they are never written to a temporary file, so when all
+        // the documents fit in the memory budget, the index is written in
+        // one go.
+        let mut manifest = manifest.into_inner().unwrap();
+        if self.deterministic {
+            manifest.sort_by_input();
+        }
+        let in_memory = !rest.index.is_empty();
+        reporter.merging(manifest.segments().count() + in_memory as usize);
+        let mut merge = self.file_merge(&reporter);
+        for file in manifest.segments() {
+            merge.add_file(file.to_path_buf())?;
+        }
+        if in_memory {
+            merge.add_index(rest.index);
+        }
+        merge.finish()?;
+        manifest.remove()?;
+        if self.shards > 1 {
+            return Ok(self.output_dir.clone());
+        }
+        Ok(self.output_dir.join(MERGED_FILENAME))
+    }
+
+    /// Leave out the files of `documents` with the same contents as one
+    /// before them, recording their paths in `duplicates`.
+    fn remove_duplicates(
+        &mut self,
+        documents: Vec<(usize, PathBuf)>,
+    ) -> Result<Vec<(usize, PathBuf)>> {
+        let identity = self.settings.identity;
+        let threads = match self.io_threads {
+            0 => self.threads,
+            io_threads => io_threads,
+        };
+        let hashes = match threads {
+            #[cfg(not(target_arch = "wasm32"))]
+            2.. => ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(io::Error::other)?
+                .install(|| {
+                    documents
+                        .par_iter()
+                        .map(|(_, path)| hash_file(path, identity))
+                        .collect::<io::Result<Vec<_>>>()
+                })?,
+            _ => documents
+                .iter()
+                .map(|(_, path)| hash_file(path, identity))
+                .collect::<io::Result<Vec<_>>>()?,
+        };
+
+        let mut first: HashMap<Doc, PathBuf> = HashMap::new();
+        let mut kept = vec![];
+        let mut skipped = 0;
+        for ((position, path), hash) in documents.into_iter().zip(hashes) {
+            match first.entry(hash) {
+                Entry::Occupied(e) => {
+                    trace!(
+                        "{} is a copy of {}",
+                        path.display(),
+                        e.get().display()
+                    );
+                    self.duplicates
+                        .entry(e.get().clone())
+                        .or_default()
+                        .push(path);
+                    skipped += 1;
+                }
+                Entry::Vacant(e) => {
+                    e.insert(path.clone());
+                    kept.push((position, path));
+                }
+            }
+        }
+        if skipped > 0 {
+            info!("Skipping {} copies of other files", skipped);
+        }
+        Ok(kept)
+    }
+
+    /// The paths of the copies of `document`, read from the input file at
+    /// `path`, found by `dedup`. The copies of an archive's members are
+    /// the same members of the copies of the archive.
+    fn duplicates_of(&self, path: &Path, document: &Document) -> Vec<PathBuf> {
+        let Some(copies) = self.duplicates.get(path) else {
+            return vec![];
+        };
+        let input = canonical(path).to_string_lossy().into_owned();
+        let name = document.path.to_string_lossy();
+        let member = name.strip_prefix(input.as_str()).unwrap_or("");
+        copies
+            .iter()
+            .map(|copy| {
+                let mut copy = canonical(copy).into_os_string();
+                copy.push(member);
+                PathBuf::from(copy)
+            })
+            .collect()
+    }
+
+    /// The merge of the temporary index files into the final one.
+    fn file_merge(&self, reporter: &Reporter) -> FileMerge {
+        FileMerge::with_settings(&self.output_dir, self.settings.clone())
+            .fan_in(self.merge_fan_in)
+            .threads(self.threads)
+            .shards(self.shards)
+            .report_to(reporter.callback.clone())
+    }
+
+    /// Write `batch` to a temporary index file and record it in `manifest`.
+    fn write_segment(
+        &self,
+        batch: Batch,
+        tmp_dir: &Mutex<TmpDir>,
+        reporter: &Reporter,
+        manifest: &Mutex<Manifest>,
+    ) -> Result<()> {
+        let file = write_index_to_tmp_file(
+            batch.index,
+            &mut tmp_dir.lock().unwrap(),
+            &self.settings,
+        )?;
+        reporter.segment_written(&file);
+        manifest.lock().unwrap().add(file, batch.files, batch.first)
+    }
+
+    /// Index the given list of `documents` into temporary index files in
+    /// `output_dir`, recorded in `manifest`, but for the last of them:
+    /// returns the batch they are in, not written out.
+    fn run_single_threaded(
+        &self,
+        documents: Vec<(usize, PathBuf)>,
+        analyzers: &LanguageAnalyzers,
+        reporter: &Reporter,
+        manifest: &Mutex<Manifest>,
+    ) -> Result<Batch> {
+        // If all the documents fit comfortably in memory, we'll create the
+        // whole index in memory.
+        let mut batch = Batch::new();
+
+        // A tool for generating temporary filenames.
+        let tmp_dir = Mutex::new(TmpDir::new(&self.output_dir));
+
+        // For each document in the set...
+        self.read_files(&documents, reporter, |files| -> Result<()> {
+            for (position, filename, read) in files {
+                // ...load it into memory and index it...
+                let index =
+                    self.index_documents(filename, read?, analyzers, reporter)?;
+
+                // ...and add its contents to the in-memory batch.
+                batch.merge(Batch::of_file(position, filename, index));
+                if batch.index.memory_usage() > self.memory_budget {
+                    // To avoid running out of memory, write the batch to a
+                    // largeish temporary index file, to be merged with the
+                    // others at the end.
+                    let full = mem::replace(&mut batch, Batch::new());
+                    self.write_segment(full, &tmp_dir, reporter, manifest)?;
+                }
+            }
+            Ok(())
+        })?;
+
+        // Done reading documents! The last data set goes straight to the
+        // merge.
+        Ok(batch)
+    }
+
+    /// Read the documents of the files of `documents` and pass them, in
+    /// order, to `index`: on `io_threads` threads of their own, at most
+    /// `read_ahead` files ahead of `index`, or without them, on the thread
+    /// of `index` as it asks for them.
+    fn read_files<'a, R>(
+        &self,
+        documents: &'a [(usize, PathBuf)],
+        reporter: &Reporter,
+        index: impl FnOnce(&mut dyn Iterator<Item = ReadFile<'a>>) -> R,
+    ) -> R {
+        let read = |(position, path): &'a (usize, PathBuf)| {
+            (*position, path.as_path(), self.read_file(path, reporter))
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.io_threads > 0 {
+            let threads = self.io_threads;
+            return prefetch(documents, threads, self.read_ahead, read, index);
+        }
+        index(&mut documents.iter().map(read))
+    }
+
+    /// Read the documents in the file at `path`, as `Reporter::read` does.
+    fn read_file(
+        &self,
+        path: &Path,
+        reporter: &Reporter,
+    ) -> io::Result<Vec<Document>> {
+        let identity = self.settings.identity;
+        let (extractors, limits) = (&self.extractors, &self.limits);
+        reporter.read(path, extractors, identity, self.stream_len, limits)
+    }
+
+    /// Read the documents in the file at `path` and index them in memory,
+    /// each with the analyzer for its language.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn index_file(
+        &self,
+        path: &Path,
+        analyzers: &LanguageAnalyzers,
+        reporter: &Reporter,
+    ) -> Result<InMemoryIndex> {
+        let documents = self.read_file(path, reporter)?;
+        self.index_documents(path, documents, analyzers, reporter)
+    }
+
+    /// Index `documents`, read from the file at `path`, in memory, each
+    /// with the analyzer for its language. Documents with more words than
+    /// the limits allow are truncated or skipped.
+    fn index_documents(
+        &self,
+        path: &Path,
+        documents: Vec<Document>,
+        analyzers: &LanguageAnalyzers,
+        reporter: &Reporter,
+    ) -> Result<InMemoryIndex> {
+        let mut index = InMemoryIndex::new();
+        let max_tokens = self.limits.max_tokens;
+        let (byte_offsets, line_numbers) =
+            (self.settings.byte_offsets, self.settings.line_numbers);
+        index.limit_tokens(max_tokens);
+        index.record_byte_offsets(byte_offsets);
+        index.record_line_numbers(line_numbers);
+        // Skipped documents are indexed on their own first, to be left out
+        // once found too long.
+        let skip_long =
+            self.limits.policy == LimitPolicy::Skip && max_tokens.is_some();
+        if !documents.is_empty() {
+            let mut count = 0;
+            for mut document in documents {
+                if self.deterministic {
+                    document.mtime = 0;
+                }
+                document.duplicates = self.duplicates_of(path, &document);
+                let analyzers = analyzers.for_path(&document.path);
+                document.language = analyzers.detect(&document.text);
+                let analyzer = analyzers.get(document.language.as_deref());
+                if !skip_long {
+                    document.add_to(&mut index, analyzer)?;
+                    count += 1;
+                    continue;
+                }
+                let path = document.path.clone();
+                let mut one = InMemoryIndex::new();
+                one.limit_tokens(max_tokens);
+                one.record_byte_offsets(byte_offsets);
+                one.record_line_numbers(line_numbers);
+                document.add_to(&mut one, analyzer)?;
+                if one.docs[0].1.truncated {
+                    let max = max_tokens.unwrap_or_default();
+                    reporter.skipped(path, format!("more than {} words", max));
+                } else {
+                    index.merge(one);
+                    count += 1;
+                }
+            }
+            reporter.indexed(path, count);
+        }
+        Ok(index)
+    }
+
+    /// Index the given list of `documents` into temporary index files in
+    /// `output_dir`, recorded in `manifest`, but for the last batch.
+    ///
+    /// On success this does exactly the same thing as `run_single_threaded`,
+    /// but faster since it spreads the work over a pool of `threads` threads.
+    /// Each thread reads and indexes documents, folding them into its own
+    /// in-memory index, and the per-thread indexes are merged pairwise at the
+    /// end. Any index that grows past its share of the memory budget along
+    /// the way is written to a temporary file.
+    ///
+    /// With `io_threads`, the threads only tokenize: they index the files
+    /// read ahead, `read_ahead` at a time, while the next ones are read.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run_parallel(
+        &self,
+        documents: Vec<(usize, PathBuf)>,
+        analyzers: &LanguageAnalyzers,
+        reporter: &Reporter,
+        manifest: &Mutex<Manifest>,
+    ) -> Result<Batch> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .map_err(io::Error::other)?;
+        // Every thread holds an index of its own.
+        let budget = self.memory_budget / self.threads;
+
+        let tmp_dir = Mutex::new(TmpDir::new(&self.output_dir));
+        // Merge `b` into `a`, and write the result out if it's getting large.
+        let combine = |mut a: Batch, b: Batch| -> Result<_> {
+            if b.flushed {
+                // Part of `b` is already written out, and `a` comes before
+                // it, so `a` goes to a segment of its own.
+                if !a.index.is_empty() {
+                    self.write_segment(a, &tmp_dir, reporter, manifest)?;
+                }
+                a = b;
+            } else {
+                a.merge(b);
+            }
+            if a.index.memory_usage() > budget {
+                self.write_segment(a, &tmp_dir, reporter, manifest)?;
+                a = Batch::new();
+                a.flushed = true;
+            }
+            Ok(a)
+        };
+
+        if self.io_threads == 0 {
+            return pool.install(|| {
+                documents
+                    .par_iter()
+                    .map(|(position, filename)| {
+                        self.index_file(filename, analyzers, reporter).map(
+                            |index| Batch::of_file(*position, filename, index),
+                        )
+                    })
+                    .try_fold(Batch::new, |acc, batch| combine(acc, batch?))
+                    .try_reduce(Batch::new, combine)
+            });
+        }
+
+        self.read_files(&documents, reporter, |files| {
+            let mut batch = Batch::new();
+            loop {
+                let read: Vec<_> = files.take(self.read_ahead).collect();
+                if read.is_empty() {
+                    return Ok(batch);
+                }
+                let indexed = pool.install(|| {
+                    read.into_par_iter()
+                        .map(|(position, filename, documents)| -> Result<_> {
+                            let index = self.index_documents(
+                                filename, documents?, analyzers, reporter,
+                            )?;
+                            Ok(Batch::of_file(position, filename, index))
+                        })
+                        .try_fold(Batch::new, |acc, batch| combine(acc, batch?))
+                        .try_reduce(Batch::new, combine)
+                })?;
+                batch = combine(batch, indexed)?;
+            }
+        })
+    }
+}
+
+/// A file of the inputs, by its position among them, and the documents read
+/// from it.
+type ReadFile<'a> = (usize, &'a Path, io::Result<Vec<Document>>);
+
+/// Documents indexed in memory, with the input files they come from.
+///
+/// Both ways of running fold consecutive inputs into a batch, in order (the
+/// parallel fold and reduction keep the order of the inputs), and a batch
+/// that follows written out inputs is never merged into an earlier one. So a
+/// batch holds a range of inputs, and batches written out in the order of
+/// their first input hold the documents in the order of the inputs.
+struct Batch {
+    index: InMemoryIndex,
+    /// Keys of the files in the manifest, see `input_key`.
+    files: Vec<PathBuf>,
+    /// Position of the first file among the inputs.
+    first: usize,
+    /// Whether inputs folded before the ones in the batch, in the same
+    /// fold, have been written out. Only folds of `run_parallel` have.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    flushed: bool,
+}
+
+impl Batch {
+    fn new() -> Batch {
+        Batch {
+            index: InMemoryIndex::new(),
+            files: vec![],
+            first: usize::MAX,
+            flushed: false,
+        }
+    }
+
+    fn of_file(position: usize, path: &Path, index: InMemoryIndex) -> Batch {
+        Batch {
+            index,
+            files: vec![input_key(path)],
+            first: position,
+            flushed: false,
+        }
+    }
+
+    fn merge(&mut self, other: Batch) {
+        self.index.merge(other.index);
+        self.files.extend(other.files);
+        self.first = self.first.min(other.first);
+    }
+}
+
+/// Modification time, in seconds since the Unix epoch.
+fn modified(metadata: &Metadata) -> io::Result<u64> {
+    Ok(metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()))
+}
+
+/// The canonical form of `path`, or `path` itself if it can't be resolved,
+/// as documents record it.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The hash of the contents of the file at `path`, as `identity` hashes
+/// them.
+fn hash_file(path: &Path, identity: DocIdentity) -> io::Result<Doc> {
+    identity.hash_contents(&mut File::open(path)?)
+}
+
+/// Size of the file at `path`, or 0 if it can't be told.
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |m| m.len())
+}
+
+/// Keeps count of the work done and passes progress to the user's reporter,
+/// if any.
+struct Reporter {
+    callback: Option<Arc<dyn ProgressReporter>>,
+    /// Files indexed or skipped.
+    done: AtomicUsize,
+    total: usize,
+    /// Bytes of the files read.
+    bytes: AtomicU64,
+    /// Documents indexed, counting each member of an archive.
+    docs: AtomicUsize,
+}
+
+impl Reporter {
+    /// Read the documents in the file at `path`: the file itself, or the
+    /// members of an archive. Files larger than `stream_len` are streamed
+    /// if they can be, and those larger than `limits` allow truncated.
+    /// Documents that can't be decoded, or are skipped by `limits`, are
+    /// reported and skipped rather than failing the whole run.
+    fn read(
+        &self,
+        path: &Path,
+        extractors: &Extractors,
+        identity: DocIdentity,
+        stream_len: u64,
+        limits: &DocLimits,
+    ) -> io::Result<Vec<Document>> {
+        let size = file_size(path);
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+        let results = if archive::is_archive(path) {
+            match archive::members(path) {
+                Ok(members) => members
+                    .into_iter()
+                    .map(|m| {
+                        let size = m.bytes.len() as u64;
+                        let document =
+                            limits.bytes_to_index(size).and_then(|len| {
+                                Document::from_bytes(
+                                    m.path.clone(),
+                                    &m.bytes,
+                                    len as usize,
+                                    m.mtime,
+                                    extractors,
+                                    identity,
+                                )
+                            });
+                        (m.path, document)
+                    })
+                    .collect(),
+                Err(e) => vec![(path.to_path_buf(), Err(e))],
+            }
+        } else {
+            let document = limits.bytes_to_index(size).and_then(|len| {
+                if len > stream_len {
+                    Document::read_streamed_prefix(
+                        path, len, extractors, identity,
+                    )
+                } else if len < size {
+                    Document::read_prefix(path, len, extractors, identity)
+                } else {
+                    Document::read_with(path, extractors, identity)
+                }
+            });
+            vec![(path.to_path_buf(), document)]
+        };
+
+        let mut documents = vec![];
+        for (path, result) in results {
+            match result {
+                Ok(document) => documents.push(document),
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    self.skipped(path, e.to_string());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if documents.is_empty() {
+            self.done.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(documents)
+    }
+
+    /// The document at `path` was left out of the index, for `reason`.
+    fn skipped(&self, path: PathBuf, reason: String) {
+        debug!("Skipping {}: {}", path.display(), reason);
+        self.report(|| Progress::Skipped { path, reason });
+    }
+
+    /// The `docs` documents of the file at `path` were indexed.
+    fn indexed(&self, path: &Path, docs: usize) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        trace!("Indexed {} ({} documents)", path.display(), docs);
+        let docs = self.docs.fetch_add(docs, Ordering::Relaxed) + docs;
+        self.report(|| Progress::Indexed {
+            path: path.to_path_buf(),
+            done,
+            total: self.total,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            docs,
+        });
+    }
+
+    fn segment_written(&self, path: &Path) {
+        self.report(|| Progress::SegmentWritten {
+            path: path.to_path_buf(),
+        });
+    }
+
+    fn merging(&self, segments: usize) {
+        info!(
+            "Indexed {} files, merging {} segments",
+            self.total, segments
+        );
+        self.report(|| Progress::Merging { segments });
+    }
+
+    /// Only build the event if someone is listening.
+    fn report(&self, event: impl FnOnce() -> Progress) {
+        if let Some(callback) = &self.callback {
+            callback.report(&event());
+        }
+    }
+}
+
+/// A document loaded from disk, ready to be indexed.
+#[derive(Debug)]
+pub struct Document {
+    /// The document's id: the hash of its contents, or of its path (see
+    /// `DocIdentity`).
+    pub doc: Doc,
+    pub text: String,
+    /// The title, if the extractor found one.
+    pub title: Option<String>,
+    /// Absolute path of the file, if it can be resolved.
+    pub path: PathBuf,
+    /// Modification time, in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// The language the text is written in, if it was detected.
+    pub language: Option<String>,
+    /// Paths of other files with the same contents, see `Indexer::dedup`.
+    pub duplicates: Vec<PathBuf>,
+    /// True if the text wasn't loaded: `text` is empty, and the text is
+    /// read from `path` as the document is indexed (see `read_streamed`).
+    pub streamed: bool,
+    /// The number of bytes of the file that are indexed, if not all of
+    /// them (see `DocLimits`). `text` is that of those bytes only.
+    pub truncated: Option<u64>,
+}
+
+impl Document {
+    /// Read a document and compute its hash, getting its text with the
+    /// built-in extractors, for an index with the default settings. Files
+    /// that can't be made sense of, such as binary files, are refused with
+    /// `InvalidData`.
+    pub fn read(path: &Path) -> io::Result<Document> {
+        Document::read_with(
+            path,
+            &Extractors::default(),
+            DocIdentity::default(),
+        )
+    }
+
+    /// Read a document and identify it by `identity`, getting its text with
+    /// `extractors`.
+    pub fn read_with(
+        path: &Path,
+        extractors: &Extractors,
+        identity: DocIdentity,
+    ) -> io::Result<Document> {
+        let mut f = File::open(path)?;
+        let mut bytes = vec![];
+        f.read_to_end(&mut bytes)?;
+        let mtime = modified(&f.metadata()?)?;
+        let path = canonical(path);
+        let len = bytes.len();
+        Document::from_bytes(path, &bytes, len, mtime, extractors, identity)
+    }
+
+    /// Like `read_with`, but only the first `len` bytes of the file are
+    /// loaded and indexed. The document is still identified, and its size
+    /// given, by the whole file.
+    pub fn read_prefix(
+        path: &Path,
+        len: u64,
+        extractors: &Extractors,
+        identity: DocIdentity,
+    ) -> io::Result<Document> {
+        let mut f = File::open(path)?;
+        let mut bytes = vec![];
+        (&mut f).take(len).read_to_end(&mut bytes)?;
+        let metadata = f.metadata()?;
+        if metadata.len() <= len {
+            // Nothing is left out after all.
+            let path = canonical(path);
+            let (len, mtime) = (bytes.len(), modified(&metadata)?);
+            return Document::from_bytes(
+                path, &bytes, len, mtime, extractors, identity,
+            );
+        }
+        let text = decode::trim_cut_char(&bytes);
+        let mut document = Document::from_bytes(
+            canonical(path),
+            text,
+            text.len(),
+            modified(&metadata)?,
+            extractors,
+            identity,
+        )?;
+        if identity.is_by_contents() {
+            f.rewind()?;
+            document.doc = identity.hash_contents(&mut f)?;
+        }
+        document.size = metadata.len();
+        document.truncated = Some(text.len() as u64);
+        Ok(document)
+    }
+
+    /// Like `read_with`, for a file too large to be loaded at once: only its
+    /// id is computed, and its text is streamed from the file when the
+    /// document is indexed (see `InMemoryIndex::add_document_stream`). A
+    /// file that an extractor handles, or that isn't UTF-8 text, is loaded
+    /// after all.
+    pub fn read_streamed(
+        path: &Path,
+        extractors: &Extractors,
+        identity: DocIdentity,
+    ) -> io::Result<Document> {
+        Document::read_streamed_prefix(path, u64::MAX, extractors, identity)
+    }
+
+    /// Like `read_streamed`, but only the first `len` bytes of the file are
+    /// indexed, as with `read_prefix`, which is what a file that can't be
+    /// streamed is read with.
+    pub fn read_streamed_prefix(
+        path: &Path,
+        len: u64,
+        extractors: &Extractors,
+        identity: DocIdentity,
+    ) -> io::Result<Document> {
+        let mut f = File::open(path)?;
+        let mut head = vec![];
+        (&mut f)
+            .take(BINARY_SNIFF_LEN as u64)
+            .read_to_end(&mut head)?;
+        let metadata = f.metadata()?;
+        if extractors.find(path, &head).is_some()
+            || !decode::is_utf8_text(&head)
+        {
+            return match len < metadata.len() {
+                true => Document::read_prefix(path, len, extractors, identity),
+                false => Document::read_with(path, extractors, identity),
+            };
+        }
+        identity.check_hashable()?;
+        let path = canonical(path);
+        let doc = if identity.is_by_contents() {
+            f.rewind()?;
+            identity.hash_contents(&mut f)?
+        } else {
+            identity.doc(&path, &[])
+        };
+        Ok(Document {
+            doc,
+            text: String::new(),
+            title: None,
+            path,
+            mtime: modified(&metadata)?,
+            size: metadata.len(),
+            language: None,
+            duplicates: vec![],
+            streamed: true,
+            truncated: (len < metadata.len()).then_some(len),
+        })
+    }
+
+    /// A document for a member of an archive, identified by `identity`,
+    /// getting its text with `extractors`.
+    pub fn from_member(
+        member: Member,
+        extractors: &Extractors,
+        identity: DocIdentity,
+    ) -> io::Result<Document> {
+        Document::from_bytes(
+            member.path,
+            &member.bytes,
+            member.bytes.len(),
+            member.mtime,
+            extractors,
+            identity,
+        )
+    }
+
+    /// A document of the file at `path`, of contents `bytes`, of which the
+    /// first `len` are indexed.
+    fn from_bytes(
+        path: PathBuf,
+        bytes: &[u8],
+        len: usize,
+        mtime: u64,
+        extractors: &Extractors,
+        identity: DocIdentity,
+    ) -> io::Result<Document> {
+        identity.check_hashable()?;
+        let text = match len < bytes.len() {
+            true => decode::trim_cut_char(&bytes[..len]),
+            false => bytes,
+        };
+        let extracted = extractors.extract(&path, text)?;
+
+        // Hashing the raw bytes, so that the identity of a document doesn't
+        // depend on how it was decoded.
+        Ok(Document {
+            doc: identity.doc(&path, bytes),
+            text: extracted.text,
+            title: extracted.title,
+            path,
+            mtime,
+            size: bytes.len() as u64,
+            language: None,
+            duplicates: vec![],
+            streamed: false,
+            truncated: (text.len() < bytes.len()).then_some(text.len() as u64),
+        })
+    }
+
+    /// Index the document on its own, with its title and path as separate
+    /// fields, and record it in the document table. Fails only if the
+    /// document is streamed and reading it does.
+    pub fn index(self, analyzer: &dyn Analyzer) -> io::Result<InMemoryIndex> {
+        let mut index = InMemoryIndex::new();
+        self.add_to(&mut index, analyzer)?;
+        Ok(index)
+    }
+
+    /// Add the document to `index`, with its title and path as separate
+    /// fields, and record it in the document table. Fails only if the
+    /// document is streamed and reading it does, leaving `index` as it was.
+    pub fn add_to(
+        self,
+        index: &mut InMemoryIndex,
+        analyzer: &dyn Analyzer,
+    ) -> io::Result<()> {
+        let path = self.path.to_string_lossy().into_owned();
+        let mut fields = vec![(Field::Path, path.clone())];
+        fields.extend(self.title.map(|title| (Field::Title, title)));
+        let hash = &self.doc.hash;
+        if self.streamed {
+            let len = self.truncated.unwrap_or(u64::MAX);
+            let text = BufReader::new(File::open(&self.path)?.take(len));
+            index.add_document_stream(hash, text, &fields, analyzer)?;
+        } else {
+            index.add_document_fields(hash, self.text, &fields, analyzer);
+        }
+        let (_, info) = index.docs.last_mut().unwrap();
+        info.path = path;
+        info.mtime = self.mtime;
+        info.size = self.size;
+        info.language = self.language.unwrap_or_default();
+        info.truncated |= self.truncated.is_some();
+        info.duplicates = self
+            .duplicates
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        index.memory += info.path.len()
+            + info.language.len()
+            + info
+                .duplicates
+                .iter()
+                .map(|path| size_of::<String>() + path.len())
+                .sum::<usize>();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::AnalyzerConfig;
+    use crate::manifest::MANIFEST_FILENAME;
+    use crate::read::IndexFileReader;
+    use crate::routing;
+    use std::fs;
+    use std::sync::Mutex;
+
+    /// A directory of text files, and one to write the index to.
+    fn corpus(texts: &[&str]) -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        let out = dir.path().join("out");
+        fs::create_dir(&docs).unwrap();
+        fs::create_dir(&out).unwrap();
+        for (i, text) in texts.iter().enumerate() {
+            fs::write(docs.join(format!("{i}.txt")), text).unwrap();
+        }
+        (dir, docs, out)
+    }
+
+    const TEXTS: [&str; 4] =
+        ["the cat sat", "the dog and the cat", "a bird", "the end"];
+
+    #[test]
+    fn threads_build_the_same_index() {
+        let (_dir, docs, out) = corpus(&TEXTS);
+        let file = Indexer::new(&docs).threads(1).output(&out).run().unwrap();
+        let single = IndexFileReader::get_index_from_file(&file).unwrap();
+        let file = Indexer::new(&docs).threads(3).output(&out).run().unwrap();
+        let pipeline = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert_eq!(single.docs.len(), 4);
+        assert_eq!(single.map, pipeline.map);
+        assert_eq!(single.map["the"].len(), 3);
+        let mut paths: Vec<_> =
+            single.docs.values().map(|info| info.path.clone()).collect();
+        paths.sort();
+        let expected: Vec<_> = (0..4)
+            .map(|i| docs.join(format!("{i}.txt")).canonicalize().unwrap())
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn progress_is_reported() {
+        let (_dir, docs, out) = corpus(&TEXTS);
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        Indexer::new(&docs)
+            .threads(1)
+            .output(&out)
+            .on_progress(move |p| seen.lock().unwrap().push(p.clone()))
+            .run()
+            .unwrap();
+        let events = events.lock().unwrap();
+        let indexed: Vec<_> = events
+            .iter()
+            .filter_map(|p| match p {
+                Progress::Indexed { done, total, .. } => Some((*done, *total)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(indexed, [(1, 4), (2, 4), (3, 4), (4, 4)]);
+        assert!(matches!(events[0], Progress::Discovered { files: 4, .. }));
+        assert!(events
+            .iter()
+            .any(|p| matches!(p, Progress::Merging { segments: 1 })));
+    }
+
+    #[test]
+    fn parallel_runs_index_every_document() {
+        let texts: Vec<String> = (0..100)
+            .map(|i| format!("doc{i} shared word{} tail", i % 7))
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let (_dir, docs, out) = corpus(&texts);
+        let done = Arc::new(Mutex::new(vec![]));
+        let seen = done.clone();
+        let file = Indexer::new(&docs)
+            .threads(4)
+            .output(&out)
+            .on_progress(move |p| {
+                if let Progress::Indexed { done, .. } = p {
+                    seen.lock().unwrap().push(*done);
+                }
+            })
+            .run()
+            .unwrap();
+        let parallel = IndexFileReader::get_index_from_file(file).unwrap();
+        let mut done = done.lock().unwrap().clone();
+        done.sort();
+        assert_eq!(done, (1..=100).collect::<Vec<_>>());
+
+        let file = Indexer::new(&docs).threads(1).output(&out).run().unwrap();
+        let single = IndexFileReader::get_index_from_file(file).unwrap();
+        assert_eq!(parallel.docs.len(), 100);
+        assert_eq!(parallel.map, single.map);
+        assert_eq!(parallel.map["shared"].len(), 100);
+        assert_eq!(parallel.map["word3"].len(), 14);
+    }
+
+    #[test]
+    fn copies_are_indexed_once() {
+        let (_dir, docs, out) =
+            corpus(&["the cat", "a dog", "the cat", "the cat"]);
+        let path = |i| {
+            docs.join(format!("{i}.txt"))
+                .canonicalize()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        };
+        for threads in [1, 3] {
+            let file = Indexer::new(&docs)
+                .threads(threads)
+                .output(&out)
+                .dedup(true)
+                .deterministic(true)
+                .run()
+                .unwrap();
+            let index = IndexFileReader::get_index_from_file(&file).unwrap();
+            assert_eq!(index.docs.len(), 2);
+            assert_eq!(index.map["cat"].len(), 1);
+            let cat = index.docs.values().find(|d| d.path == path(0)).unwrap();
+            assert_eq!(cat.duplicates, [path(2), path(3)]);
+            let dog = index.docs.values().find(|d| d.path == path(1)).unwrap();
+            assert!(dog.duplicates.is_empty());
+        }
+    }
+
+    #[test]
+    fn reading_threads_build_the_same_file() {
+        let texts: Vec<String> = (0..60)
+            .map(|i| format!("doc{i} word{} end", i % 5))
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let (_dir, docs, out) = corpus(&texts);
+        let build = |threads, io_threads, read_ahead| {
+            let file = Indexer::new(&docs)
+                .threads(threads)
+                .io_threads(io_threads)
+                .read_ahead(read_ahead)
+                // Small enough for several segments.
+                .memory_budget(4096)
+                .deterministic(true)
+                .output(&out)
+                .run()
+                .unwrap();
+            fs::read(file).unwrap()
+        };
+        let expected = build(1, 0, 1);
+        assert!(build(1, 2, 3) == expected);
+        assert!(build(4, 3, 5) == expected);
+        assert!(build(3, 1, 100) == expected);
+    }
+
+    #[test]
+    fn streamed_files_build_the_same_file() {
+        let (_dir, docs, out) = corpus(&TEXTS);
+        fs::write(docs.join("page.html"), "<title>A cat</title><p>sat")
+            .unwrap();
+        let build = |stream_len| {
+            let file = Indexer::new(&docs)
+                .threads(2)
+                .stream_larger_than(stream_len)
+                .deterministic(true)
+                .output(&out)
+                .run()
+                .unwrap();
+            fs::read(file).unwrap()
+        };
+        assert!(build(0) == build(DEFAULT_STREAM_LEN));
+    }
+
+    #[test]
+    fn oversized_documents_are_skipped_or_truncated() {
+        let long = "the cat sat on the mat ".repeat(100);
+        let (_dir, docs, out) = corpus(&["a dog", &long]);
+        let long_doc =
+            DocIdentity::default().doc(Path::new(""), long.as_bytes());
+        let by_size = DocLimits {
+            max_file_size: Some(1000),
+            ..DocLimits::default()
+        };
+        let by_tokens = DocLimits {
+            max_tokens: Some(100),
+            ..DocLimits::default()
+        };
+        for (limits, stream_len) in [
+            (by_size.clone(), DEFAULT_STREAM_LEN),
+            (by_size, 0),
+            (by_tokens.clone(), DEFAULT_STREAM_LEN),
+            (by_tokens, 0),
+        ] {
+            for policy in [LimitPolicy::Skip, LimitPolicy::Truncate] {
+                let skipped = Arc::new(Mutex::new(0));
+                let reported = Arc::clone(&skipped);
+                let limits = DocLimits {
+                    policy,
+                    ..limits.clone()
+                };
+                let file = Indexer::new(&docs)
+                    .threads(1)
+                    .stream_larger_than(stream_len)
+                    .limits(limits.clone())
+                    .output(&out)
+                    .on_progress(move |p| {
+                        if let Progress::Skipped { .. } = p {
+                            *reported.lock().unwrap() += 1;
+                        }
+                    })
+                    .run()
+                    .unwrap();
+                let index =
+                    IndexFileReader::get_index_from_file(&file).unwrap();
+                let skipped = *skipped.lock().unwrap();
+                if policy == LimitPolicy::Skip {
+                    assert_eq!((index.docs.len(), skipped), (1, 1));
+                    assert!(!index.docs.values().any(|info| info.truncated));
+                    continue;
+                }
+                assert_eq!((index.docs.len(), skipped), (2, 0), "{limits:?}");
+                // Still identified by its whole contents.
+                let info = &index.docs[&long_doc];
+                assert!(info.truncated);
+                assert_eq!(info.size, long.len() as u64);
+                assert!(info.token_count > 0 && info.token_count < 300);
+                if limits.max_tokens.is_some() {
+                    assert_eq!(info.token_count, 100);
+                }
+                let offsets = &index.map["mat"][&long_doc];
+                assert!(offsets.iter().all(|&n| n < info.token_count));
+            }
+        }
+    }
+
+    #[test]
+    fn documents_get_ids_of_the_chosen_identity() {
+        let (_dir, docs, out) = corpus(&["the cat", "a dog"]);
+        let path = docs.join("0.txt").canonicalize().unwrap();
+        for identity in [DocIdentity::Xxh3, DocIdentity::Path] {
+            let settings = IndexSettings {
+                identity,
+                ..Default::default()
+            };
+            let file = Indexer::new(&docs)
+                .output(&out)
+                .settings(settings)
+                .run()
+                .unwrap();
+            let reader = IndexFileReader::open(&file).unwrap();
+            assert_eq!(reader.settings().identity, identity);
+            let index = IndexFileReader::get_index_from_file(&file).unwrap();
+            assert_eq!(index.docs.len(), 2);
+            let cat = identity.doc(&path, b"the cat");
+            assert_eq!(cat.hash.len(), 16);
+            assert!(index.docs.contains_key(&cat));
+        }
+    }
+
+    #[test]
+    fn routed_files_are_extracted_and_analyzed_their_way() {
+        let (_dir, docs, out) = corpus(&[]);
+        fs::write(docs.join("app.log"), "job 42 failed").unwrap();
+        fs::write(docs.join("main.rs"), "fn parseRequest() {}").unwrap();
+        fs::write(docs.join("notes.txt"), "# Title\n\n*words*").unwrap();
+        let routes = r#"
+            [[route]]
+            glob = "*.log"
+            skip_numbers = true
+            [[route]]
+            glob = "*.rs"
+            tokenizer = "code"
+            [[route]]
+            glob = "*.txt"
+            extractor = "markdown"
+        "#;
+        let base = AnalyzerConfig::default();
+        let settings = IndexSettings {
+            analyzer: AnalyzerConfig {
+                routes: routing::parse(routes, &base).unwrap(),
+                ..base
+            },
+            ..Default::default()
+        };
+        let file = Indexer::new(&docs)
+            .output(&out)
+            .settings(settings.clone())
+            .run()
+            .unwrap();
+        assert_eq!(IndexSettings::load(&file).unwrap(), settings);
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        let terms: Vec<_> = index.map.keys().map(String::as_str).collect();
+        for term in ["job", "failed", "fn", "parse", "request", "words"] {
+            assert!(terms.contains(&term), "{} in {:?}", term, terms);
+        }
+        assert!(!terms.contains(&"42"));
+        assert!(!terms.contains(&"parserequest"));
+        assert!(terms.contains(&"title:title"));
+    }
+
+    /// The events reported while `indexer` runs.
+    fn run_reported(indexer: Indexer) -> (Result<PathBuf>, Vec<Progress>) {
+        let events = Arc::new(Mutex::new(vec![]));
+        let seen = events.clone();
+        let result = indexer
+            .on_progress(move |p| seen.lock().unwrap().push(p.clone()))
+            .run();
+        let events = events.lock().unwrap().clone();
+        (result, events)
+    }
+
+    fn segments_written(events: &[Progress]) -> usize {
+        events
+            .iter()
+            .filter(|p| matches!(p, Progress::SegmentWritten { .. }))
+            .count()
+    }
+
+    #[test]
+    fn small_memory_budgets_spill_to_segments() {
+        let (_dir, docs, out) = corpus(&TEXTS);
+        let build = |budget| {
+            let indexer = Indexer::new(&docs)
+                .threads(1)
+                .memory_budget(budget)
+                .output(&out);
+            let (file, events) = run_reported(indexer);
+            let index = IndexFileReader::get_index_from_file(file.unwrap());
+            (index.unwrap(), segments_written(&events))
+        };
+        let (whole, spilled) = build(DEFAULT_MEMORY_BUDGET);
+        assert_eq!(spilled, 0);
+        let (small, spilled) = build(1);
+        // A segment for each file.
+        assert_eq!(spilled, TEXTS.len());
+        assert_eq!(small.map, whole.map);
+        assert_eq!(small.docs.len(), TEXTS.len());
+        // The segments are gone once merged.
+        for entry in fs::read_dir(&out).unwrap() {
+            let name = entry.unwrap().file_name();
+            assert!(!name.to_string_lossy().starts_with("tmp"), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn interrupted_builds_resume_from_their_segments() {
+        let (_dir, docs, out) = corpus(&TEXTS);
+        let build = || {
+            Indexer::new(&docs)
+                .threads(1)
+                .memory_budget(1)
+                .deterministic(true)
+                .output(&out)
+        };
+        // Interrupted once all the segments are written.
+        let interrupted = std::panic::catch_unwind(|| {
+            build()
+                .on_progress(|p| {
+                    if let Progress::Merging { .. } = p {
+                        panic!("interrupted");
+                    }
+                })
+                .run()
+        });
+        assert!(interrupted.is_err());
+        assert!(out.join(MANIFEST_FILENAME).is_file());
+
+        let (file, events) = run_reported(build().resume(true));
+        let indexed = events
+            .iter()
+            .filter(|p| matches!(p, Progress::Indexed { .. }))
+            .count();
+        // Every file is in a segment already.
+        assert_eq!(indexed, 0);
+        assert_eq!(segments_written(&events), 0);
+        assert!(!out.join(MANIFEST_FILENAME).exists());
+        let resumed = IndexFileReader::get_index_from_file(file.unwrap());
+        let file = build().run().unwrap();
+        let fresh = IndexFileReader::get_index_from_file(&file).unwrap();
+        let resumed = resumed.unwrap();
+        assert_eq!(resumed.map, fresh.map);
+        assert_eq!(resumed.docs, fresh.docs);
+
+        // Manifests of builds with other settings aren't resumed.
+        fs::write(
+            out.join(MANIFEST_FILENAME),
+            r#"{"codec":"zstd","analyzer":"","segments":[]}"#,
+        )
+        .unwrap();
+        let error = build().resume(true).run().unwrap_err();
+        assert!(matches!(error, Error::Incompatible(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn deterministic_builds_ignore_modification_times() {
+        let (_dir, docs, out) = corpus(&TEXTS);
+        let build = |deterministic, threads| {
+            let file = Indexer::new(&docs)
+                .threads(threads)
+                .deterministic(deterministic)
+                .output(&out)
+                .run()
+                .unwrap();
+            fs::read(file).unwrap()
+        };
+        let before = (build(true, 1), build(false, 1));
+        let touched = File::options().write(true).open(docs.join("2.txt"));
+        let modified = UNIX_EPOCH + std::time::Duration::from_secs(86_400);
+        touched.unwrap().set_modified(modified).unwrap();
+        assert!(build(true, 3) == before.0);
+        assert!(build(false, 1) != before.1);
+        let index =
+            IndexFileReader::get_index_from_file(out.join(MERGED_FILENAME));
+        let mtimes: Vec<u64> = index
+            .unwrap()
+            .docs
+            .values()
+            .map(|info| info.mtime)
+            .collect();
+        assert!(mtimes.contains(&86_400));
+    }
+
+    #[test]
+    fn empty_and_missing_inputs_are_an_error() {
+        let (_dir, docs, out) = corpus(&[]);
+        for threads in [1, 3] {
+            let run = Indexer::new(&docs).threads(threads).output(&out).run();
+            assert!(run.is_err());
+        }
+
+        let missing = docs.join("missing.txt");
+        assert!(Indexer::new(missing).output(&out).run().is_err());
+    }
+}
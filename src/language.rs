@@ -0,0 +1,307 @@
+//! Documents in several languages.
+//!
+//! Stop words and stemming only make sense for the language a text is
+//! written in. When an index is built with `auto` stop words or stemming
+//! (see `AnalyzerConfig`), the language of each document is detected and the
+//! document is analyzed with the stop words and stemmer of that language.
+//! Documents whose language can't be told reliably, or which are in a
+//! language without stop words or stemmer, are analyzed without them.
+//!
+//! The language is recorded in the document table, so searches can be
+//! restricted to it with `lang:french`. Queries have no language of their
+//! own: by default, they are analyzed like the most common language of the
+//! index, and `Searcher::with_language` picks another one.
+//!
+//! Detection needs the `language` feature.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{AnalyzerConfig, TextAnalyzer};
+use crate::index::ParsedIndex;
+use crate::routing::Router;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Languages with built-in stop words or a stemmer, by the names used in
+/// the document table.
+pub const LANGUAGES: &[&str] = &[
+    "arabic",
+    "danish",
+    "dutch",
+    "english",
+    "finnish",
+    "french",
+    "german",
+    "greek",
+    "hungarian",
+    "italian",
+    "norwegian",
+    "portuguese",
+    "romanian",
+    "russian",
+    "spanish",
+    "swedish",
+    "tamil",
+    "turkish",
+];
+
+/// How many bytes at the start of a document are looked at to detect its
+/// language. More text hardly changes the result, and costs time.
+#[cfg(feature = "language")]
+const DETECT_LEN: usize = 4096;
+
+/// The language `text` is written in, as a lowercase English name like
+/// "french", or `None` if it can't be told reliably.
+#[cfg(feature = "language")]
+pub fn detect(text: &str) -> Option<String> {
+    let mut end = text.len().min(DETECT_LEN);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let info = whatlang::detect(&text[..end]).filter(|i| i.is_reliable())?;
+    let name = match info.lang() {
+        // Bokmål is what the Snowball stemmer calls Norwegian.
+        whatlang::Lang::Nob => "norwegian",
+        lang => lang.eng_name(),
+    };
+    Some(name.to_lowercase())
+}
+
+/// The most common language among the documents of `index`, if any of them
+/// has one.
+pub fn most_common(index: &ParsedIndex) -> Option<&str> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for info in index.docs.values() {
+        if !info.language.is_empty() {
+            *counts.entry(&info.language).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(language, _)| language)
+}
+
+/// The analyzers used to index documents: one per language when the
+/// configuration depends on it, and one for everything else, and those of
+/// the files that routes give analyzers of their own (see `routing`).
+pub struct LanguageAnalyzers {
+    detect: bool,
+    default: TextAnalyzer,
+    by_language: HashMap<&'static str, TextAnalyzer>,
+    routes: Router<LanguageAnalyzers>,
+}
+
+impl LanguageAnalyzers {
+    /// Build the analyzers described by `config`. Fails if it needs language
+    /// detection and the `language` feature is off.
+    pub fn new(config: &AnalyzerConfig) -> io::Result<LanguageAnalyzers> {
+        let detect = config.uses_language();
+        if detect && !cfg!(feature = "language") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "language detection requires the `language` feature",
+            ));
+        }
+        let per_language =
+            config.stop_words.iter().any(|l| l == AnalyzerConfig::AUTO)
+                || config.stemmer.as_deref() == Some(AnalyzerConfig::AUTO);
+        let mut by_language = HashMap::new();
+        if per_language {
+            for &language in LANGUAGES {
+                by_language.insert(language, config.build_for(Some(language))?);
+            }
+        }
+        let routes = Router::new(&config.routes, |route| {
+            route
+                .analyzer
+                .as_ref()
+                .map(LanguageAnalyzers::new)
+                .transpose()
+        })?;
+        Ok(LanguageAnalyzers {
+            detect,
+            default: config.build()?,
+            by_language,
+            routes,
+        })
+    }
+
+    /// The analyzers for the document at `path`: those of its route, if it
+    /// has one.
+    pub fn for_path(&self, path: &Path) -> &LanguageAnalyzers {
+        self.routes.get(path).unwrap_or(self)
+    }
+
+    /// The language of `text`, if the configuration asks for detection and
+    /// it can be told.
+    pub fn detect(&self, text: &str) -> Option<String> {
+        if !self.detect {
+            return None;
+        }
+        #[cfg(feature = "language")]
+        return detect(text);
+        // `new` doesn't allow detection without the feature.
+        #[cfg(not(feature = "language"))]
+        {
+            let _ = text;
+            None
+        }
+    }
+
+    /// The analyzer for documents in `language`.
+    pub fn get(&self, language: Option<&str>) -> &TextAnalyzer {
+        language
+            .and_then(|l| self.by_language.get(l))
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "language")]
+    use crate::analyzer::Analyzer;
+    use crate::fixtures::index_of;
+
+    #[cfg(feature = "language")]
+    const ENGLISH: &str = "The weather was nice today, so we went to the \
+                           park and had a picnic with our friends.";
+
+    #[cfg(feature = "language")]
+    fn terms(
+        analyzers: &LanguageAnalyzers,
+        language: &str,
+        text: &str,
+    ) -> Vec<String> {
+        let language = (!language.is_empty()).then_some(language);
+        let analyzer = analyzers.get(language);
+        analyzer.analyze(text).into_iter().map(|t| t.text).collect()
+    }
+
+    #[cfg(feature = "language")]
+    #[test]
+    fn languages_are_detected_when_reliable() {
+        let cases = [
+            (ENGLISH, Some("english")),
+            (
+                "Le renard brun rapide saute par-dessus le chien paresseux \
+                 pendant que le fermier regarde.",
+                Some("french"),
+            ),
+            (
+                "Der schnelle braune Fuchs springt über den faulen Hund, \
+                 während der Bauer zuschaut.",
+                Some("german"),
+            ),
+            (
+                "El rápido zorro marrón salta sobre el perro perezoso \
+                 mientras el granjero mira.",
+                Some("spanish"),
+            ),
+            (
+                "Быстрая коричневая лиса прыгает через ленивую собаку, пока \
+                 фермер смотрит.",
+                Some("russian"),
+            ),
+            ("ok", None),
+            ("", None),
+        ];
+        for (text, language) in cases {
+            assert_eq!(detect(text).as_deref(), language, "{}", text);
+        }
+    }
+
+    #[cfg(feature = "language")]
+    #[test]
+    fn documents_are_analyzed_with_the_stop_words_of_their_language() {
+        let config = AnalyzerConfig {
+            stop_words: vec![AnalyzerConfig::AUTO.to_string()],
+            custom_stop_words: vec!["fox".to_string()],
+            ..Default::default()
+        };
+        let analyzers = LanguageAnalyzers::new(&config).unwrap();
+        let text = "the fox and le chat der Hund";
+        let cases: &[(&str, &[&str])] = &[
+            ("english", &["le", "chat", "der", "hund"]),
+            ("french", &["the", "and", "chat", "der", "hund"]),
+            ("german", &["the", "and", "le", "chat", "hund"]),
+            // Languages without stop words, or unknown, keep them all.
+            ("greek", &["the", "and", "le", "chat", "der", "hund"]),
+            ("", &["the", "and", "le", "chat", "der", "hund"]),
+        ];
+        for &(language, expected) in cases {
+            assert_eq!(
+                terms(&analyzers, language, text),
+                expected,
+                "{}",
+                language
+            );
+        }
+        assert!(analyzers.detect(ENGLISH).is_some());
+        let plain = LanguageAnalyzers::new(&AnalyzerConfig::default()).unwrap();
+        assert_eq!(plain.detect(ENGLISH), None);
+    }
+
+    #[cfg(all(feature = "language", feature = "stemming"))]
+    #[test]
+    fn documents_are_stemmed_in_their_language() {
+        let config = AnalyzerConfig {
+            stemmer: Some(AnalyzerConfig::AUTO.to_string()),
+            ..Default::default()
+        };
+        let analyzers = LanguageAnalyzers::new(&config).unwrap();
+        let cases: &[(&str, &str, &[&str])] = &[
+            ("english", "running horses", &["run", "hors"]),
+            (
+                "french",
+                "chevaux continuellement",
+                &["cheval", "continuel"],
+            ),
+            ("german", "Häuser", &["haus"]),
+            ("russian", "книгами", &["книг"]),
+            ("", "running horses", &["running", "horses"]),
+        ];
+        for &(language, text, expected) in cases {
+            assert_eq!(
+                terms(&analyzers, language, text),
+                expected,
+                "{}",
+                language
+            );
+        }
+    }
+
+    #[cfg(not(feature = "language"))]
+    #[test]
+    fn detection_needs_the_language_feature() {
+        let config = AnalyzerConfig {
+            detect_language: true,
+            ..Default::default()
+        };
+        assert!(LanguageAnalyzers::new(&config).is_err());
+    }
+
+    #[test]
+    fn the_most_common_language_is_that_of_most_documents() {
+        let cases: &[(&[&str], Option<&str>)] = &[
+            (&["french", "english", "french"], Some("french")),
+            (&["", "", "german"], Some("german")),
+            // Ties go to the first language by name.
+            (&["spanish", "dutch"], Some("dutch")),
+            (&["", ""], None),
+        ];
+        for &(languages, expected) in cases {
+            let mut index = index_of(0, &vec!["text"; languages.len()]);
+            for ((_, info), language) in index.docs.iter_mut().zip(languages) {
+                info.language = language.to_string();
+            }
+            let parsed = index.to_parsed();
+            assert_eq!(most_common(&parsed), expected, "{:?}", languages);
+        }
+    }
+}
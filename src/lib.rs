@@ -1,33 +1,139 @@
 //! `fingertips` creates an inverted index for a set of text files.
 //!
 //! Most of the actual work is done by the modules `index`, `read`, `write`,
-//! and `merge`. In this file, `main.rs` we put the pieces together in two
-//! different ways.
+//! and `merge`. The `indexer` module puts the pieces together, either on a
+//! single thread or as a pipeline that runs on multiple CPUs, and `search`
+//! answers queries against the resulting index file, which `postings`
+//! combines the hits of terms for without decoding them. An index can also be
+//! kept as a directory of segments that are updated in place, see
+//! `segments`, or split into shards by term, see `shard`. The `async`
+//! feature looks terms up in index files from tokio tasks, see
+//! `async_read`, and the `testing` feature checks that index files written,
+//! read and merged keep their postings, see `testing`. Index files can be
+//! encrypted at rest, see `crypt`, and whole indexes packed into a single
+//! file to ship, or to build into a program, see `pack`. The `capi`
+//! feature builds and searches indexes from C, see `capi`.
 //!
-//! *    `run_single_threaded` simply does everything in one thread, in the
-//!      the most straightforward possible way.
-//!
-//! *    Then, we break the work into a five-stage pipeline so that we can run
-//!      it on multiple CPUs. `run_pipeline` puts the five stages together.
-//!
-//! The `main` function at the end handles command-line arguments. It calls one
-//! of the two functions above to do the work.
+//! The library never prints. It logs what it does through the `log` facade:
+//! merges at the info level, segments and files opened at debug, and every
+//! file indexed at trace.
+
+pub use error::{Error, Result};
 
+/// Length of SHA-256 document ids, the default and the only ones before
+/// format version 10 (see `identity`).
 pub(crate) const HASH_LENGTH: usize = 32;
 
 pub mod prelude {
+    pub use crate::analyzer::{Analyzer, AnalyzerConfig, TextAnalyzer};
+    #[cfg(feature = "async")]
+    pub use crate::async_read::AsyncIndexFileSearcher;
+    pub use crate::codec::Codec;
+    pub use crate::concordance::{concordance, Occurrence};
+    pub use crate::crypt::Key;
+    pub use crate::description::FileDescription;
+    pub use crate::dump::{export, import, DumpFormat};
+    pub use crate::extract::{Extracted, Extractor, Extractors};
+    pub use crate::facet::{Facet, FacetCounts};
+    pub use crate::fetch::{Fetch, FetchedIndex};
+    pub use crate::field::Field;
+    pub use crate::header::IndexSettings;
+    pub use crate::identity::DocIdentity;
     pub use crate::index::InMemoryIndex;
     pub use crate::index::ParsedIndex;
-    pub use crate::merge::FileMerge;
-    pub use crate::read::IndexFileReader;
+    pub use crate::indexer::{Indexer, Progress, ProgressReporter};
+    pub use crate::language::LanguageAnalyzers;
+    pub use crate::limits::{DocLimits, LimitPolicy};
+    pub use crate::merge::{merge_index_files, FileMerge};
+    pub use crate::merge_policy::{MergePolicy, TieredPolicy};
+    #[cfg(feature = "mmap")]
+    pub use crate::mmap::{MmapIndexReader, Warming};
+    #[cfg(feature = "mmap")]
+    pub use crate::pack::PackedIndex;
+    pub use crate::pack::{pack, PackKind, StaticIndex};
+    pub use crate::query::{Query, SyntaxError};
+    pub use crate::read::{Entries, IndexFileReader, IndexFileSearcher};
+    pub use crate::routing::Route;
+    pub use crate::score::{
+        rank, rank_weighted, Bm25, CorpusStats, Scorer, TermScore, TfIdf,
+    };
+    pub use crate::search::{
+        Cursor, HitExplanation, MultiSearcher, SearchHit, SearchResults,
+        Searcher, TopKSearcher,
+    };
+    pub use crate::segments::IndexDir;
+    pub use crate::shard::ShardedIndex;
+    pub use crate::snippet::{snippets, SnippetOptions};
+    pub use crate::stats::{
+        index_stats, CollectionStats, IndexStats, TermStats,
+    };
     pub use crate::tmp::TmpDir;
-    pub use crate::write::write_index_to_tmp_file;
+    pub use crate::tombstone::{compact, Tombstones};
+    pub use crate::upgrade::upgrade;
+    pub use crate::verify::{verify, Report};
+    pub use crate::walk::FileFilter;
+    pub use crate::write::{write_index_to_tmp_file, write_index_to_vec};
 }
 
 // ───── Submodules ───────────────────────────────────────────────────────── //
 
+pub mod analyzer;
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod async_read;
+pub mod bench;
+mod bloom;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod codec;
+pub mod concordance;
+pub mod crypt;
+pub mod decode;
+pub mod description;
+mod dictionary;
+pub mod dump;
+pub mod error;
+pub mod extract;
+pub mod facet;
+pub mod fetch;
+pub mod field;
+pub mod filter;
+#[cfg(test)]
+mod fixtures;
+pub mod header;
+pub mod identity;
 pub mod index;
+pub mod indexer;
+pub mod language;
+pub mod limits;
+mod lock;
+mod manifest;
 mod merge;
+pub mod merge_policy;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod pack;
+mod postings;
+#[cfg(not(target_arch = "wasm32"))]
+mod prefetch;
+pub mod query;
 mod read;
+pub mod routing;
+pub mod score;
+pub mod search;
+pub mod segments;
+pub mod shard;
+mod slice;
+pub mod snippet;
+pub mod stats;
+pub mod stopwords;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod tmp;
+pub mod tombstone;
+pub mod upgrade;
+mod varint;
+pub mod verify;
+mod wal;
+pub mod walk;
 mod write;
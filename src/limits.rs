@@ -0,0 +1,103 @@
+//! Limits on the size of the documents of an index.
+//!
+//! A single pathological file, like a log of several gigabytes or a text
+//! dump of a database, can make an index much larger, and the in-memory
+//! index it goes through much larger still. `DocLimits` bounds the size of
+//! files (`max_file_size`) and the number of words of their text
+//! (`max_tokens`), and says what becomes of a document past either:
+//!
+//! * `LimitPolicy::Skip`, the default, leaves it out of the index, and the
+//!   indexer reports it as skipped.
+//! * `LimitPolicy::Truncate` indexes only its first bytes or words. Its id
+//!   is still that of the whole document, its size that of the whole file,
+//!   and the document table records that it was truncated (see
+//!   `DocInfo::truncated`).
+//!
+//! Files an extractor gets the text of, like PDF files, may not make sense
+//! once truncated; those that don't are skipped.
+
+use std::io;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// What becomes of a document larger than `DocLimits` allow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Leave the document out of the index.
+    #[default]
+    Skip,
+    /// Index the beginning of the document, and record that it was
+    /// truncated.
+    Truncate,
+}
+
+/// Every policy.
+const ALL: [LimitPolicy; 2] = [LimitPolicy::Skip, LimitPolicy::Truncate];
+
+impl LimitPolicy {
+    /// The policy's name, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            LimitPolicy::Skip => "skip",
+            LimitPolicy::Truncate => "truncate",
+        }
+    }
+
+    /// Parse a name returned by `name`.
+    pub fn from_name(name: &str) -> Option<LimitPolicy> {
+        ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+/// Limits on the size of each document an indexer indexes. By default,
+/// there are none.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DocLimits {
+    /// Size of the largest file, or member of an archive, indexed whole, in
+    /// bytes.
+    pub max_file_size: Option<u64>,
+    /// Number of words of the body of the largest document indexed whole,
+    /// not counting their original case forms.
+    pub max_tokens: Option<u32>,
+    /// What becomes of a document past either limit.
+    pub policy: LimitPolicy,
+}
+
+impl DocLimits {
+    /// The number of bytes to index of a file of `size` bytes: all of them,
+    /// or fewer if it must be truncated. Fails with `InvalidData` if it
+    /// must be skipped.
+    pub(crate) fn bytes_to_index(&self, size: u64) -> io::Result<u64> {
+        match self.max_file_size {
+            Some(max) if size > max => match self.policy {
+                LimitPolicy::Skip => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "file of {} bytes, over the limit of {}",
+                        size, max
+                    ),
+                )),
+                LimitPolicy::Truncate => Ok(max),
+            },
+            _ => Ok(size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_files_are_skipped_or_truncated() {
+        let mut limits = DocLimits::default();
+        assert_eq!(limits.bytes_to_index(u64::MAX).unwrap(), u64::MAX);
+        limits.max_file_size = Some(100);
+        assert_eq!(limits.bytes_to_index(100).unwrap(), 100);
+        let e = limits.bytes_to_index(101).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        limits.policy = LimitPolicy::Truncate;
+        assert_eq!(limits.bytes_to_index(101).unwrap(), 100);
+        assert_eq!(LimitPolicy::from_name("truncate"), Some(limits.policy));
+    }
+}
@@ -0,0 +1,165 @@
+//! Advisory locks on output and index directories, between processes.
+//!
+//! A process that writes to a directory, an indexer or an open `IndexDir`,
+//! holds an exclusive lock on `write.lock` in it for as long as it runs, so a
+//! second writer fails at once instead of corrupting the first one's files.
+//!
+//! Readers of an index directory don't take the write lock, since the segment
+//! list is replaced atomically. They only hold a shared lock on `read.lock`
+//! while they load the list and the segments it names, and the writer takes
+//! it exclusively to remove merged segments, so no segment disappears under
+//! a reader.
+//!
+//! The locks are released when the lock file is closed, also when a process
+//! dies, so a leftover lock file doesn't need cleaning up. On wasm32, where
+//! nothing else can get at the files, locks are taken without lock files.
+
+use std::fs::File;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{OpenOptions, TryLockError};
+use std::io;
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::error::Error;
+use crate::error::Result;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Name of the lock file held by the writer of a directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const WRITE_LOCK_FILENAME: &str = "write.lock";
+
+/// Name of the lock file guarding the segments of an index directory against
+/// removal while they are read.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const READ_LOCK_FILENAME: &str = "read.lock";
+
+/// A lock on a directory, released when dropped.
+#[derive(Debug)]
+pub(crate) struct DirLock {
+    _file: Option<File>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirLock {
+    /// Become the writer of `dir`. Fails with `Error::Locked` if another
+    /// writer holds it.
+    pub fn write(dir: &Path) -> Result<DirLock> {
+        let file = open(dir, WRITE_LOCK_FILENAME)?;
+        match file.try_lock() {
+            Ok(()) => Ok(DirLock { _file: Some(file) }),
+            Err(TryLockError::WouldBlock) => {
+                Err(Error::Locked(dir.display().to_string()))
+            }
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Keep the segments of `dir` from being removed, waiting for a writer
+    /// that is removing some.
+    pub fn read(dir: &Path) -> io::Result<DirLock> {
+        let file = match open(dir, READ_LOCK_FILENAME) {
+            Ok(file) => file,
+            // Nobody can write to a directory on read-only media either.
+            Err(e)
+                if e.kind() == io::ErrorKind::PermissionDenied
+                    || e.kind() == io::ErrorKind::ReadOnlyFilesystem =>
+            {
+                match File::open(dir.join(READ_LOCK_FILENAME)) {
+                    Ok(file) => file,
+                    Err(_) => return Ok(DirLock { _file: None }),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+        file.lock_shared()?;
+        Ok(DirLock { _file: Some(file) })
+    }
+
+    /// Wait until no reader is loading the segments of `dir`, and keep them
+    /// from starting, to remove segments.
+    pub fn remove(dir: &Path) -> io::Result<DirLock> {
+        let file = open(dir, READ_LOCK_FILENAME)?;
+        file.lock()?;
+        Ok(DirLock { _file: Some(file) })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl DirLock {
+    pub fn write(_: &Path) -> Result<DirLock> {
+        Ok(DirLock { _file: None })
+    }
+
+    pub fn read(_: &Path) -> io::Result<DirLock> {
+        Ok(DirLock { _file: None })
+    }
+
+    pub fn remove(_: &Path) -> io::Result<DirLock> {
+        Ok(DirLock { _file: None })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn open(dir: &Path, name: &str) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(dir.join(name))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn directories_have_one_writer_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = DirLock::write(dir.path()).unwrap();
+        assert!(matches!(DirLock::write(dir.path()), Err(Error::Locked(_))));
+        drop(writer);
+        DirLock::write(dir.path()).unwrap();
+        assert!(dir.path().join(WRITE_LOCK_FILENAME).is_file());
+    }
+
+    #[test]
+    fn segments_are_removed_once_no_reader_is_loading_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = DirLock::read(dir.path()).unwrap();
+        let second = DirLock::read(dir.path()).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let path = dir.path().to_path_buf();
+        let remover = thread::spawn(move || {
+            let removing = DirLock::remove(&path).unwrap();
+            sender.send(()).unwrap();
+            removing
+        });
+        drop(first);
+        let wait = Duration::from_millis(100);
+        assert!(receiver.recv_timeout(wait).is_err());
+        drop(second);
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        let removing = remover.join().unwrap();
+
+        // Readers wait in turn.
+        let (sender, receiver) = mpsc::channel();
+        let path = dir.path().to_path_buf();
+        let reader = thread::spawn(move || {
+            let _reading = DirLock::read(&path).unwrap();
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(wait).is_err());
+        drop(removing);
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        reader.join().unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! Checkpoints of an index build, so that an interrupted build can resume.
+//!
+//! While documents are indexed, the temporary index files (segments) written
+//! so far are listed in a manifest in the output directory, `index.manifest`,
+//! together with the input files each of them holds. The manifest is
+//! rewritten after every segment, and removed once the segments have been
+//! merged into `index.dat`.
+//!
+//! A build resumed after an interruption (see `Indexer::resume`) takes the
+//! segments of the manifest as they are and only indexes the files they
+//! don't hold. Segments that are gone, e.g. because the interruption came
+//! during the final merge, are forgotten and their files indexed again. So
+//! are the files indexed last, which the final merge reads from memory
+//! rather than from a segment.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::header::IndexSettings;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Name of the manifest in the output directory.
+pub(crate) const MANIFEST_FILENAME: &str = "index.manifest";
+
+/// The segments written so far by a build.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// The codec of the segments, by name.
+    codec: String,
+    /// The analyzer of the segments, as `AnalyzerConfig::encode` writes it.
+    analyzer: String,
+    /// Whether the segments leave the offsets of hits out.
+    #[serde(default)]
+    omit_positions: bool,
+    /// Whether every term of the segments has a twin holding its byte
+    /// offsets.
+    #[serde(default)]
+    byte_offsets: bool,
+    /// Whether every term of the body of the segments has a twin holding
+    /// its line numbers.
+    #[serde(default)]
+    line_numbers: bool,
+    /// Whether the segments are encrypted.
+    #[serde(default)]
+    encrypted: bool,
+    segments: Vec<Segment>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Segment {
+    /// Path of the segment.
+    file: PathBuf,
+    /// The input files indexed in it, canonical where possible.
+    inputs: Vec<PathBuf>,
+    /// Position of its first input among all inputs of the build.
+    #[serde(default)]
+    first: usize,
+}
+
+impl Manifest {
+    /// An empty manifest for a build into `output_dir` with `settings`.
+    pub fn new(output_dir: &Path, settings: &IndexSettings) -> Manifest {
+        Manifest {
+            codec: settings.codec.name().to_string(),
+            analyzer: settings.analyzer.encode(),
+            omit_positions: settings.omit_positions,
+            byte_offsets: settings.byte_offsets,
+            line_numbers: settings.line_numbers,
+            encrypted: settings.encrypted,
+            segments: vec![],
+            path: output_dir.join(MANIFEST_FILENAME),
+        }
+    }
+
+    /// The manifest left in `output_dir` by an interrupted build, without
+    /// the segments that no longer exist, or an empty one if there's none.
+    /// Fails if that build used other settings.
+    pub fn load(
+        output_dir: &Path,
+        settings: &IndexSettings,
+    ) -> Result<Manifest> {
+        let mut manifest = Manifest::new(output_dir, settings);
+        let text = match fs::read_to_string(&manifest.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(manifest)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let saved: Manifest = serde_json::from_str(&text).map_err(|e| {
+            Error::format(format!("{}: {}", manifest.path.display(), e))
+        })?;
+        if saved.codec != manifest.codec
+            || saved.analyzer != manifest.analyzer
+            || saved.omit_positions != manifest.omit_positions
+            || saved.byte_offsets != manifest.byte_offsets
+            || saved.line_numbers != manifest.line_numbers
+            || saved.encrypted != manifest.encrypted
+        {
+            return Err(Error::Incompatible(format!(
+                "{} was written by a build with other settings; remove it \
+                 to start over",
+                manifest.path.display()
+            )));
+        }
+        manifest.segments = saved
+            .segments
+            .into_iter()
+            .filter(|segment| segment.file.is_file())
+            .collect();
+        Ok(manifest)
+    }
+
+    /// The segments, in the order they were written.
+    pub fn segments(&self) -> impl Iterator<Item = &Path> {
+        self.segments.iter().map(|s| s.file.as_path())
+    }
+
+    /// The input files held by the segments.
+    pub fn inputs(&self) -> HashSet<PathBuf> {
+        self.segments
+            .iter()
+            .flat_map(|s| s.inputs.iter().cloned())
+            .collect()
+    }
+
+    /// Record that `file` holds `inputs`, the first of which is input
+    /// number `first` of the build, and save the manifest.
+    pub fn add(
+        &mut self,
+        file: PathBuf,
+        inputs: Vec<PathBuf>,
+        first: usize,
+    ) -> Result<()> {
+        self.segments.push(Segment {
+            file,
+            inputs,
+            first,
+        });
+        self.save()
+    }
+
+    /// Put the segments in the order of their inputs, rather than the order
+    /// they were written in.
+    pub fn sort_by_input(&mut self) {
+        self.segments.sort_by_key(|s| s.first);
+    }
+
+    /// Write the manifest, replacing the previous one only once the new one
+    /// is complete.
+    fn save(&self) -> Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let text = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Remove the manifest, once the build is done.
+    pub fn remove(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The key of an input file in the manifest.
+pub(crate) fn input_key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -1,13 +1,30 @@
+use log::{debug, info};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fs::{self, File};
 use std::io::{self, BufWriter};
 use std::mem;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
 
 // ───── Current Crate Imports ────────────────────────────────────────────── //
 
-use crate::read::IndexFileReader;
-use crate::tmp::TmpDir;
-use crate::write::IndexFileWriter;
+use crate::error::{Error, Result};
+use crate::header::IndexSettings;
+use crate::index::{sort_by_doc, DocTable, Hit, InMemoryIndex};
+use crate::indexer::{Progress, ProgressReporter};
+#[cfg(feature = "mmap")]
+use crate::mmap::MmapIndexReader as StreamReader;
+use crate::read::HitRemapper;
+#[cfg(not(feature = "mmap"))]
+use crate::read::IndexFileReader as StreamReader;
+use crate::shard::{self, shard_of};
+use crate::tmp::{publish, TmpDir};
+use crate::tombstone::{compact, Tombstones};
+use crate::varint;
+use crate::write::{encode_hits, IndexFileWriter, RangeWriter, WrittenRange};
 
 // ───── Body ─────────────────────────────────────────────────────────────── //
 
@@ -15,35 +32,113 @@ pub struct FileMerge {
     output_dir: PathBuf,
     tmp_dir: TmpDir,
     stacks: Vec<Vec<PathBuf>>,
+    /// Documents merged straight from memory, after those of the files.
+    memory: Option<InMemoryIndex>,
+    settings: IndexSettings,
+    fan_in: usize,
+    threads: usize,
+    shards: usize,
+    progress: Option<Arc<dyn ProgressReporter>>,
 }
 
-// How many files to merge at a time, at most.
-const NSTREAMS: usize = 8;
-const MERGED_FILENAME: &'static str = "index.dat";
+// How many files to merge at a time, at most, unless set with `fan_in`.
+pub(crate) const DEFAULT_FAN_IN: usize = 128;
+pub(crate) const MERGED_FILENAME: &str = "index.dat";
 
 impl FileMerge {
     pub fn new(output_dir: &Path) -> FileMerge {
+        FileMerge::with_settings(output_dir, IndexSettings::default())
+    }
+
+    /// Like `new`, for files written with `settings`. The merged files use
+    /// the same settings; the input files may use any codec, but must have
+    /// been built with the same analyzer.
+    pub fn with_settings(
+        output_dir: &Path,
+        settings: IndexSettings,
+    ) -> FileMerge {
         FileMerge {
             output_dir: output_dir.to_owned(),
-            tmp_dir: TmpDir::new(output_dir.to_owned()),
+            tmp_dir: TmpDir::new(output_dir),
             stacks: vec![],
+            memory: None,
+            settings,
+            fan_in: DEFAULT_FAN_IN,
+            threads: 1,
+            shards: 1,
+            progress: None,
         }
     }
 
-    pub fn add_file(&mut self, mut file: PathBuf) -> io::Result<()> {
+    /// Merge at most `fan_in` files at a time (at least 2). Every file being
+    /// merged stays open, so this bounds the number of open files.
+    pub fn fan_in(mut self, fan_in: usize) -> FileMerge {
+        self.fan_in = fan_in.max(2);
+        self
+    }
+
+    /// Split the terms of each merge between up to `threads` threads (by
+    /// default, 1). Every thread reads all the files being merged, so a
+    /// merge of `n` files gets at most `fan_in / n` threads: only merges of
+    /// fewer files than the fan-in, like the last one usually is, run on
+    /// several threads. Always 1 on wasm32.
+    pub fn threads(mut self, threads: usize) -> FileMerge {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Write the final index as `shards` files split by term instead of a
+    /// single `index.dat` (see `shard`). With more than one shard, even a
+    /// single file is merged again, to split it.
+    pub fn shards(mut self, shards: usize) -> FileMerge {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// The number of threads a merge of `files` files gets.
+    fn threads_for(&self, files: usize) -> usize {
+        (self.fan_in / files.max(1)).clamp(1, self.threads)
+    }
+
+    /// Report each step of the merge to `progress`.
+    pub(crate) fn report_to(
+        mut self,
+        progress: Option<Arc<dyn ProgressReporter>>,
+    ) -> FileMerge {
+        self.progress = progress;
+        self
+    }
+
+    fn merged(&self, files: usize) {
+        if let Some(progress) = &self.progress {
+            progress.report(&Progress::Merged { files });
+        }
+    }
+
+    pub fn add_file(&mut self, mut file: PathBuf) -> Result<()> {
         let mut level = 0;
         loop {
             if level == self.stacks.len() {
                 self.stacks.push(vec![]);
             }
             self.stacks[level].push(file);
-            if self.stacks[level].len() < NSTREAMS {
+            if self.stacks[level].len() < self.fan_in {
                 break;
             }
             let (filename, out) = self.tmp_dir.create()?;
             let mut to_merge = vec![];
             mem::swap(&mut self.stacks[level], &mut to_merge);
-            merge_streams(to_merge, out)?;
+            let files = to_merge.len();
+            let threads = self.threads_for(files);
+            merge_streams(
+                to_merge,
+                None,
+                vec![out],
+                &self.settings,
+                &mut self.tmp_dir,
+                threads,
+            )?;
+            self.merged(files);
             file = filename;
             level += 1;
         }
@@ -51,93 +146,994 @@ impl FileMerge {
         Ok(())
     }
 
-    pub fn finish(mut self) -> io::Result<()> {
-        let mut tmp = Vec::with_capacity(NSTREAMS);
-        for stack in self.stacks {
+    /// Add an index that is still in memory, built with the settings of the
+    /// merge, without writing it to a file first: its terms are read
+    /// straight from memory by the final merge, and if no file was added,
+    /// it is written as `index.dat` directly. Its documents come after those
+    /// of all the files, whenever it is added; adding several indexes
+    /// appends their documents in order.
+    pub fn add_index(&mut self, index: InMemoryIndex) {
+        match &mut self.memory {
+            Some(memory) => memory.merge(index),
+            None => self.memory = Some(index),
+        }
+    }
+
+    /// Merge the files and the index added so far into `index.dat` in the
+    /// output directory. The index is written under a temporary name and
+    /// synced to disk before it is renamed into place, so a crash never
+    /// leaves a partial `index.dat` behind.
+    pub fn finish(mut self) -> Result<()> {
+        let mut tmp = Vec::with_capacity(self.fan_in);
+        for stack in mem::take(&mut self.stacks) {
             for file in stack.into_iter().rev() {
                 tmp.push(file);
-                if tmp.len() == NSTREAMS {
-                    merge_reversed(&mut tmp, &mut self.tmp_dir)?;
+                if tmp.len() == self.fan_in {
+                    let threads = self.threads_for(self.fan_in);
+                    merge_reversed(
+                        &mut tmp,
+                        None,
+                        &mut self.tmp_dir,
+                        &self.settings,
+                        threads,
+                    )?;
+                    self.merged(self.fan_in);
                 }
             }
         }
 
-        if tmp.len() > 1 {
-            merge_reversed(&mut tmp, &mut self.tmp_dir)?;
+        // The index in memory doesn't hold a file open, so it can join the
+        // last merge whatever the fan-in.
+        let memory = self.memory.take();
+        if self.shards > 1 {
+            return self.finish_sharded(tmp, memory);
+        }
+        if tmp.len() > 1 || memory.is_some() {
+            let files = tmp.len() + memory.is_some() as usize;
+            let threads = self.threads_for(tmp.len());
+            merge_reversed(
+                &mut tmp,
+                memory,
+                &mut self.tmp_dir,
+                &self.settings,
+                threads,
+            )?;
+            if files > 1 {
+                self.merged(files);
+            }
         }
 
         assert!(tmp.len() <= 1);
         match tmp.pop() {
             Some(last_file) => {
-                fs::rename(last_file, self.output_dir.join(MERGED_FILENAME))
+                let output = self.output_dir.join(MERGED_FILENAME);
+                debug!(
+                    "Renaming {} to {}",
+                    last_file.display(),
+                    output.display()
+                );
+                publish(&last_file, &output)?;
+                shard::remove_shards(&self.output_dir)?;
+                Ok(())
             }
-            None => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "No documents were parsed or none contained any words",
-            )),
+            None => Err(no_documents()),
+        }
+    }
+
+    /// The last merge of `finish`, of `files` in reverse order and
+    /// `memory`, into the shards of the index.
+    fn finish_sharded(
+        mut self,
+        mut files: Vec<PathBuf>,
+        memory: Option<InMemoryIndex>,
+    ) -> Result<()> {
+        if files.is_empty() && memory.is_none() {
+            return Err(no_documents());
         }
+        files.reverse();
+        let count = files.len() + memory.is_some() as usize;
+        let (paths, outs): (Vec<_>, Vec<_>) = (0..self.shards)
+            .map(|_| self.tmp_dir.create())
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let threads = self.threads_for(files.len());
+        merge_streams(
+            files,
+            memory,
+            outs,
+            &self.settings,
+            &mut self.tmp_dir,
+            threads,
+        )?;
+        if count > 1 {
+            self.merged(count);
+        }
+        shard::publish_shards(&paths, &self.output_dir)
+    }
+}
+
+fn no_documents() -> Error {
+    Error::Io(io::Error::other(
+        "No documents were parsed or none contained any words",
+    ))
+}
+
+/// Merge the index files `inputs` into a new `index.dat` in `output_dir`,
+/// leaving the inputs as they are, and return its path. Documents deleted
+/// from an input are purged. The merged file uses the codec of the first
+/// input; all of them must have been built with the same analyzer. The
+/// terms are merged on one thread per CPU.
+pub fn merge_index_files<P: AsRef<Path>>(
+    inputs: &[P],
+    output_dir: &Path,
+) -> Result<PathBuf> {
+    let Some(first) = inputs.first() else {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no index files to merge",
+        )));
+    };
+    let settings = IndexSettings::load(first)?;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    merge_index_files_with(inputs, output_dir, settings, threads)
+}
+
+/// Like `merge_index_files`, but the merged file uses `settings`, and the
+/// merge runs on up to `threads` threads (see `FileMerge::threads`).
+pub(crate) fn merge_index_files_with<P: AsRef<Path>>(
+    inputs: &[P],
+    output_dir: &Path,
+    settings: IndexSettings,
+    threads: usize,
+) -> Result<PathBuf> {
+    let mut tmp_dir = TmpDir::new(output_dir);
+    let mut merge =
+        FileMerge::with_settings(output_dir, settings).threads(threads);
+    for input in inputs {
+        // Merging deletes the files merged, so it gets copies.
+        let (copy, _) = tmp_dir.create()?;
+        fs::copy(input, &copy)?;
+        let tombstones = Tombstones::load(input)?;
+        if !tombstones.is_empty() {
+            tombstones.save(&copy)?;
+            compact(&copy)?;
+        }
+        merge.add_file(copy)?;
     }
+    merge.finish()?;
+    Ok(output_dir.join(MERGED_FILENAME))
 }
 
-/// Merge multiple index files into one.
+/// Merge multiple index files into one, or into one per shard.
 ///
 /// `files` - the vector with paths to files
-/// `out` - the `BufWriter<File>` to write into.
-fn merge_streams(files: Vec<PathBuf>, out: BufWriter<File>) -> io::Result<()> {
-    let mut streams: Vec<IndexFileReader> = files
+/// `memory` - an index in memory, merged after the files
+/// `outs` - the `BufWriter<File>`s to write into, one per shard (see
+/// `shard`).
+/// `threads` - the number of threads to split the terms between, see
+/// `merge_ranges`.
+///
+/// The input files are deleted once they have been merged. Deletion waits
+/// until every reader is closed, because Windows can't delete open files.
+fn merge_streams(
+    files: Vec<PathBuf>,
+    memory: Option<InMemoryIndex>,
+    outs: Vec<BufWriter<File>>,
+    settings: &IndexSettings,
+    tmp_dir: &mut TmpDir,
+    threads: usize,
+) -> Result<()> {
+    let mut streams: Vec<Segment> = files
+        .iter()
+        .map(|file| Ok(Segment::File(Box::new(StreamReader::open(file)?))))
+        .collect::<Result<_>>()?;
+    if !files.is_empty() {
+        info!("Merging {} index files", files.len());
+    }
+    if let Some(index) = memory {
+        debug!("Merging {} documents from memory", index.docs.len());
+        streams.push(Segment::Memory(MemorySegment::new(index, settings)));
+    }
+    // Ranges are merged into files of their own, which would hold the terms
+    // of encrypted files in the clear.
+    let threaded = threads > 1 && !cfg!(target_arch = "wasm32");
+    let ranges = if threaded && !settings.encrypted {
+        split_terms(&files, &streams, threads)?
+    } else {
+        vec![]
+    };
+    let split = if ranges.len() > 1 {
+        debug!("Merging terms in {} ranges: {:?}", ranges.len(), ranges);
+        Some(Split {
+            files: &files,
+            ranges,
+            tmp_dir,
+        })
+    } else {
+        None
+    };
+    merge_open_streams(streams, outs, settings, split)?;
+
+    for file in files {
+        debug!("Removing file: {}", file.display());
+        fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+/// Merge already opened index files, and the index in memory if any, into
+/// `outs`. Every output gets all the documents, and the terms of its shard.
+/// The readers are dropped (closing their files) before this returns.
+///
+/// With a `split`, the terms of each of its ranges are merged by a thread
+/// of their own, see `merge_ranges`.
+fn merge_open_streams(
+    mut streams: Vec<Segment>,
+    outs: Vec<BufWriter<File>>,
+    settings: &IndexSettings,
+    split: Option<Split>,
+) -> Result<()> {
+    let files = streams.iter().filter_map(Segment::file_settings);
+    if files.clone().any(|s| s.analyzer != settings.analyzer) {
+        return Err(Error::Incompatible(
+            "cannot merge index files built with different analyzers".into(),
+        ));
+    }
+    if files
+        .clone()
+        .any(|s| s.omit_positions != settings.omit_positions)
+    {
+        return Err(Error::Incompatible(
+            "cannot merge index files with and without positions".into(),
+        ));
+    }
+    if files
+        .clone()
+        .any(|s| s.byte_offsets != settings.byte_offsets)
+    {
+        return Err(Error::Incompatible(
+            "cannot merge index files with and without byte offsets".into(),
+        ));
+    }
+    if files
+        .clone()
+        .any(|s| s.line_numbers != settings.line_numbers)
+    {
+        return Err(Error::Incompatible(
+            "cannot merge index files with and without line numbers".into(),
+        ));
+    }
+    if files.clone().any(|s| s.identity != settings.identity) {
+        return Err(Error::Incompatible(
+            "cannot merge index files with different document ids".into(),
+        ));
+    }
+    let mut outputs = outs
         .into_iter()
-        .map(IndexFileReader::open_and_delete)
-        .collect::<io::Result<_>>()?;
-    let mut output = IndexFileWriter::new(out)?;
-
-    let mut point: u64 = 0;
-    let mut count = streams.iter().filter(|s| s.peek().is_some()).count();
-    while count > 0 {
-        let mut term = None;
-        let mut nbytes = 0;
-        let mut df = 0;
-        for s in &streams {
-            match s.peek() {
-                None => {}
-                Some(entry) => {
-                    if term.is_none() || entry.term < *term.as_ref().unwrap() {
-                        term = Some(entry.term.clone()); // XXX LAME clone
-                        nbytes = entry.nbytes;
-                        df = entry.doc_count;
-                    } else if entry.term == *term.as_ref().unwrap() {
-                        nbytes += entry.nbytes;
-                        df += entry.doc_count;
-                    }
-                }
+        .map(|out| IndexFileWriter::new(out, settings))
+        .collect::<io::Result<Vec<_>>>()?;
+    let shards = outputs.len();
+
+    // The document tables are concatenated, so each stream's document ids
+    // are shifted by the number of documents in the streams before it.
+    let mut doc_bases = Vec::with_capacity(streams.len());
+    let mut doc_base = 0;
+    for s in &streams {
+        doc_bases.push(doc_base);
+        for (doc, info) in s.docs() {
+            for output in &mut outputs {
+                output.write_doc_entry(doc, info)?;
             }
         }
+        doc_base += s.docs().len() as u32;
+    }
 
-        let term = term.expect("Bug in algorithm!");
-        for s in &mut streams {
-            if s.is_at(&term) {
-                s.move_entry_to(&mut output)?;
-                if s.peek().is_none() {
-                    count -= 1;
+    match split {
+        Some(Split {
+            files,
+            ranges,
+            tmp_dir,
+        }) => {
+            // A file per range and shard.
+            let mut paths = vec![];
+            let mut writers = vec![];
+            for _ in &ranges {
+                let (p, w): (Vec<_>, Vec<_>) = (0..shards)
+                    .map(|_| tmp_dir.create())
+                    .collect::<io::Result<Vec<_>>>()?
+                    .into_iter()
+                    .unzip();
+                paths.push(p);
+                writers.push(w);
+            }
+            let written = merge_ranges(
+                streams, files, &doc_bases, &ranges, &outputs, writers,
+            )?;
+            for (range, paths) in written.into_iter().zip(paths) {
+                for ((output, part), path) in
+                    outputs.iter_mut().zip(range).zip(paths)
+                {
+                    output.append_range(part, &path)?;
+                    fs::remove_file(path)?;
                 }
             }
         }
-        output.write_contents_entry(term, df, point, nbytes as u64);
-        point += nbytes as u64;
+        None => {
+            merge_terms(&mut streams, &doc_bases, FIRST_BYTES, |t, df, h| {
+                outputs[shard_of(&t, shards)].write_term(t, df, h)
+            })?;
+            assert!(streams.iter().all(|s| s.peek().is_none()));
+        }
+    }
+    for output in outputs {
+        output.finish()?;
+    }
+    Ok(())
+}
+
+/// Merge the terms of `streams` into `write`, up to the first term starting
+/// with the byte `end`, or all of them if `end` is `FIRST_BYTES`.
+fn merge_terms(
+    streams: &mut [Segment],
+    doc_bases: &[u32],
+    end: u16,
+    mut write: impl FnMut(String, u32, &[u8]) -> io::Result<()>,
+) -> Result<()> {
+    // A k-way merge: the heap holds the next term of every stream that has
+    // one, smallest first. Equal terms pop in stream order, which keeps the
+    // merged document ids ascending, as long as the hits of each stream are
+    // sorted; hits that aren't are merged by document.
+    let next = |s: &Segment| {
+        s.peek()
+            .filter(|(term, _)| first_byte(term) < end)
+            .map(|(term, _)| term.to_string())
+    };
+    let mut heap: BinaryHeap<Reverse<(String, usize)>> = streams
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| next(s).map(|term| Reverse((term, i))))
+        .collect();
+    while let Some(Reverse((term, first))) = heap.pop() {
+        let mut at_term = vec![first];
+        while let Some(Reverse((next, _))) = heap.peek() {
+            if *next != term {
+                break;
+            }
+            let Reverse((_, i)) = heap.pop().unwrap();
+            at_term.push(i);
+        }
+
+        let mut df = 0;
+        let mut hits = vec![];
+        let mut remapper = HitRemapper::default();
+        let positions = streams[first].positions();
+        for i in at_term {
+            let s = &mut streams[i];
+            df += s.peek().map_or(0, |(_, doc_count)| doc_count);
+            s.move_entry_to(&mut hits, &mut remapper, doc_bases[i])?;
+            if let Some(term) = next(s) {
+                heap.push(Reverse((term, i))); // XXX LAME clone
+            }
+        }
+        if !remapper.in_order() {
+            debug!("Merging the hits of {:?} by document", term);
+            (hits, df) = merge_by_doc(&hits, df, positions)?;
+        }
+        write(term, df, &hits)?;
+    }
+    Ok(())
+}
+
+/// Sort the `doc_count` hits in `hits_raw`, stored with `positions` or not,
+/// by document id, merging the hits of a document that is there more than
+/// once (see `index::sort_by_doc`). Returns the hits and their number.
+fn merge_by_doc(
+    hits_raw: &[u8],
+    doc_count: u32,
+    positions: bool,
+) -> Result<(Vec<u8>, u32)> {
+    let mut reader = hits_raw;
+    // Each hit takes at least two bytes, whatever `doc_count` says.
+    let mut hits =
+        Vec::with_capacity((doc_count as usize).min(hits_raw.len() / 2));
+    let mut id = 0u32;
+    for _ in 0..doc_count {
+        id = id.wrapping_add(varint::read_u32(&mut reader)?);
+        let count = varint::read_u32(&mut reader)?;
+        let mut hit: Hit =
+            [id, count].iter().flat_map(|n| n.to_le_bytes()).collect();
+        if positions {
+            let mut offset = 0u32;
+            for _ in 0..count {
+                offset = offset.wrapping_add(varint::read_u32(&mut reader)?);
+                hit.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        hits.push(hit);
+    }
+    sort_by_doc(&mut hits);
+    Ok((encode_hits(&hits, positions)?, hits.len() as u32))
+}
+
+/// Merge the terms of each of `ranges` on a thread of its own, into the
+/// matching writers of `writers`, one per output, and return what was
+/// written for each range and output, in order.
+///
+/// Terms are sorted, so the terms of a range are together in every file,
+/// and the ranges can be merged independently: the first thread takes
+/// `streams`, which start at the first range, and the others open the
+/// `files` again and skip to their range. What they write only needs to be
+/// concatenated. The index in memory, if any, is split between the threads.
+fn merge_ranges(
+    mut streams: Vec<Segment>,
+    files: &[PathBuf],
+    doc_bases: &[u32],
+    ranges: &[Range<u16>],
+    outputs: &[IndexFileWriter],
+    writers: Vec<Vec<BufWriter<File>>>,
+) -> Result<Vec<Vec<WrittenRange>>> {
+    // The terms in memory of every range but the first, which stay.
+    let mut memory = vec![];
+    if let Some(Segment::Memory(segment)) = streams.last_mut() {
+        for range in ranges[1..].iter().rev() {
+            // The terms are in reverse order, so later ranges come first.
+            let n = segment
+                .terms
+                .partition_point(|(term, _)| first_byte(term) >= range.start);
+            memory.push(MemorySegment {
+                docs: DocTable::new(),
+                terms: segment.terms.drain(..n).collect(),
+                positions: segment.positions,
+            });
+        }
+        memory.reverse();
+    }
+
+    thread::scope(|scope| {
+        let mut workers = vec![];
+        let mut memory = memory.into_iter();
+        let mut first = Some(streams);
+        for (range, writers) in ranges.iter().zip(writers) {
+            let streams = first.take();
+            let memory = match streams {
+                Some(_) => None,
+                None => memory.next(),
+            };
+            workers.push(scope.spawn(move || -> Result<Vec<WrittenRange>> {
+                let mut streams = match streams {
+                    Some(streams) => streams,
+                    None => open_range(files, memory, range.start)?,
+                };
+                let mut parts: Vec<RangeWriter> = outputs
+                    .iter()
+                    .zip(writers)
+                    .map(|(output, out)| output.range_writer(out))
+                    .collect();
+                let shards = parts.len();
+                merge_terms(&mut streams, doc_bases, range.end, |t, df, h| {
+                    parts[shard_of(&t, shards)].write_term(t, df, h)
+                })?;
+                let written = parts
+                    .into_iter()
+                    .map(RangeWriter::finish)
+                    .collect::<io::Result<_>>()?;
+                Ok(written)
+            }));
+        }
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("merge thread panicked"))
+            .collect()
+    })
+}
+
+/// Open `files` again, starting at the first term whose first byte is
+/// `start` or a later one, followed by the terms of the index in `memory`
+/// of the range, if any.
+fn open_range(
+    files: &[PathBuf],
+    memory: Option<MemorySegment>,
+    start: u16,
+) -> Result<Vec<Segment>> {
+    let mut streams = vec![];
+    for file in files {
+        let mut reader = StreamReader::open(file)?;
+        while reader.peek().is_some_and(|e| first_byte(&e.term) < start) {
+            reader.skip_entry()?;
+        }
+        streams.push(Segment::File(Box::new(reader)));
+    }
+    streams.extend(memory.map(Segment::Memory));
+    Ok(streams)
+}
+
+/// How the terms of a merge are split between threads.
+struct Split<'a> {
+    /// The files being merged, which every thread but the first opens
+    /// again.
+    files: &'a [PathBuf],
+    /// Ranges of the first byte of terms, see `first_byte`.
+    ranges: Vec<Range<u16>>,
+    /// Where the threads write their ranges.
+    tmp_dir: &'a mut TmpDir,
+}
+
+/// Number of values of the first byte of a term, plus one for the empty
+/// term, which sorts first.
+const FIRST_BYTES: u16 = 257;
+
+/// The first byte of `term` plus one, or 0 if it is empty, so that ranges
+/// of first bytes are ranges of terms.
+fn first_byte(term: &str) -> u16 {
+    term.bytes().next().map_or(0, |b| b as u16 + 1)
+}
+
+/// Split the terms of `streams`, opened from `files`, into at most `parts`
+/// ranges of about as many bytes of hits, by their first byte. The ranges
+/// cover all terms, in order; there are none if there are no hits.
+fn split_terms(
+    files: &[PathBuf],
+    streams: &[Segment],
+    parts: usize,
+) -> Result<Vec<Range<u16>>> {
+    let mut weights = vec![0u64; FIRST_BYTES as usize];
+    // The readers of `streams` can't go back, so the files are read again
+    // to count.
+    for file in files {
+        let mut reader = StreamReader::open(file)?;
+        while let Some(e) = reader.peek() {
+            weights[first_byte(&e.term) as usize] += e.nbytes;
+            reader.skip_entry()?;
+        }
+    }
+    if let Some(Segment::Memory(segment)) = streams.last() {
+        for (term, hits) in &segment.terms {
+            let len: usize = hits.iter().map(Vec::len).sum();
+            weights[first_byte(term) as usize] += len as u64;
+        }
     }
 
-    assert!(streams.iter().all(|s| s.peek().is_none()));
-    output.finish()
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        return Ok(vec![]);
+    }
+    let mut ranges = vec![];
+    let mut start = 0;
+    let mut sum = 0;
+    for (byte, weight) in weights.iter().enumerate() {
+        sum += weight;
+        let part = ranges.len() as u64 + 1;
+        if ranges.len() + 1 < parts && sum * parts as u64 >= total * part {
+            ranges.push(start..byte as u16 + 1);
+            start = byte as u16 + 1;
+        }
+    }
+    ranges.push(start..FIRST_BYTES);
+    Ok(ranges)
 }
 
 fn merge_reversed(
     filenames: &mut Vec<PathBuf>,
+    memory: Option<InMemoryIndex>,
     tmp_dir: &mut TmpDir,
-) -> io::Result<()> {
+    settings: &IndexSettings,
+    threads: usize,
+) -> Result<()> {
     filenames.reverse();
     let (merged_filename, out) = tmp_dir.create()?;
-    let mut to_merge = Vec::with_capacity(NSTREAMS);
+    let mut to_merge = vec![];
     mem::swap(filenames, &mut to_merge);
-    merge_streams(to_merge, out)?;
+    merge_streams(to_merge, memory, vec![out], settings, tmp_dir, threads)?;
     filenames.push(merged_filename);
     Ok(())
 }
+
+/// One of the inputs of a merge.
+enum Segment {
+    File(Box<StreamReader>),
+    /// An index that was never written to a file.
+    Memory(MemorySegment),
+}
+
+impl Segment {
+    /// The settings from the header of the file, if the segment is one. An
+    /// index in memory is built with the settings of the merge.
+    fn file_settings(&self) -> Option<&IndexSettings> {
+        match self {
+            Segment::File(reader) => Some(reader.settings()),
+            Segment::Memory(_) => None,
+        }
+    }
+
+    /// Whether the hits of the segment have offsets.
+    fn positions(&self) -> bool {
+        match self {
+            Segment::File(reader) => !reader.settings().omit_positions,
+            Segment::Memory(memory) => memory.positions,
+        }
+    }
+
+    fn docs(&self) -> &DocTable {
+        match self {
+            Segment::File(reader) => reader.docs(),
+            Segment::Memory(memory) => &memory.docs,
+        }
+    }
+
+    /// The next term and the number of its documents, if any.
+    fn peek(&self) -> Option<(&str, u32)> {
+        match self {
+            Segment::File(reader) => {
+                reader.peek().map(|e| (e.term.as_str(), e.doc_count))
+            }
+            Segment::Memory(memory) => memory
+                .terms
+                .last()
+                .map(|(term, hits)| (term.as_str(), hits.len() as u32)),
+        }
+    }
+
+    /// Append the hits of the next term to `out`, like
+    /// `IndexFileReader::move_entry_to`, and advance to the term after it.
+    fn move_entry_to(
+        &mut self,
+        out: &mut Vec<u8>,
+        remapper: &mut HitRemapper,
+        doc_base: u32,
+    ) -> Result<()> {
+        match self {
+            Segment::File(reader) => {
+                reader.move_entry_to(out, remapper, doc_base)
+            }
+            Segment::Memory(memory) => {
+                let (_, hits) = memory.terms.pop().expect("no entry to move");
+                let encoded = encode_hits(&hits, memory.positions)?;
+                let (hits, _) = remapper.remap(
+                    &encoded,
+                    hits.len() as u32,
+                    memory.positions,
+                    |id| Some(id + doc_base),
+                )?;
+                out.extend(hits);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An index in memory, read term by term like an index file.
+struct MemorySegment {
+    docs: DocTable,
+    /// The terms and their hits, sorted in reverse so that the next term
+    /// is the last.
+    terms: Vec<(String, Vec<Hit>)>,
+    /// Whether hits keep their offsets.
+    positions: bool,
+}
+
+impl MemorySegment {
+    fn new(
+        mut index: InMemoryIndex,
+        settings: &IndexSettings,
+    ) -> MemorySegment {
+        index.sort_by_doc();
+        let mut terms: Vec<(String, Vec<Hit>)> =
+            index.map.into_iter().collect();
+        terms.sort_by(|(a, _), (b, _)| b.cmp(a));
+        MemorySegment {
+            docs: index.docs,
+            terms,
+            positions: !settings.omit_positions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{AnalyzerConfig, TokenizerKind};
+    use crate::index::InMemoryIndex;
+    use crate::read::IndexFileReader;
+    use crate::write::write_index_to_tmp_file;
+    use crate::HASH_LENGTH;
+
+    fn text(i: usize) -> String {
+        format!("doc{} common word{}", i, i % 3)
+    }
+
+    fn doc_index(i: usize) -> InMemoryIndex {
+        InMemoryIndex::from_single_document(&[i as u8; HASH_LENGTH], text(i))
+    }
+
+    /// Merge `n` single-document files with the given fan-in.
+    fn merge_files(dir: &Path, n: usize, fan_in: usize) -> Result<()> {
+        let mut tmp_dir = TmpDir::new(dir);
+        let mut merge = FileMerge::new(dir).fan_in(fan_in);
+        for i in 0..n {
+            let settings = IndexSettings::default();
+            let file =
+                write_index_to_tmp_file(doc_index(i), &mut tmp_dir, &settings)?;
+            merge.add_file(file)?;
+        }
+        merge.finish()
+    }
+
+    #[test]
+    fn many_files_merge_like_one_index() {
+        let n = 20;
+        let mut whole = InMemoryIndex::new();
+        for i in 0..n {
+            whole.merge(doc_index(i));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index_to_tmp_file(
+            whole,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let expected = IndexFileReader::get_index_from_file(file).unwrap();
+
+        for fan_in in [2, 3, DEFAULT_FAN_IN] {
+            let dir = tempfile::tempdir().unwrap();
+            merge_files(dir.path(), n, fan_in).unwrap();
+            let merged = IndexFileReader::get_index_from_file(
+                dir.path().join("index.dat"),
+            )
+            .unwrap();
+            assert_eq!(merged.map, expected.map, "fan-in {fan_in}");
+            assert_eq!(merged.docs.len(), n);
+            assert_eq!(merged.map["common"].len(), n);
+            // Temporary files are cleaned up along the way.
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    fn indexes_in_memory_join_the_last_merge() {
+        let n = 9;
+        let mut whole = InMemoryIndex::new();
+        for i in 0..n {
+            whole.merge(doc_index(i));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index_to_tmp_file(
+            whole,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let expected = IndexFileReader::get_index_from_file(file).unwrap();
+
+        // Any number of files, then the rest of the documents in memory.
+        for files in [0, 1, 2, 5] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut tmp_dir = TmpDir::new(dir.path());
+            let mut merge = FileMerge::new(dir.path()).fan_in(2);
+            for i in 0..files {
+                let file = write_index_to_tmp_file(
+                    doc_index(i),
+                    &mut tmp_dir,
+                    &IndexSettings::default(),
+                )
+                .unwrap();
+                merge.add_file(file).unwrap();
+            }
+            for i in files..n {
+                merge.add_index(doc_index(i));
+            }
+            merge.finish().unwrap();
+            let merged = IndexFileReader::get_index_from_file(
+                dir.path().join("index.dat"),
+            )
+            .unwrap();
+            assert_eq!(merged.map, expected.map, "{files} files");
+            assert_eq!(merged.docs, expected.docs);
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    fn single_file_becomes_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        merge_files(dir.path(), 1, 2).unwrap();
+        let index =
+            IndexFileReader::get_index_from_file(dir.path().join("index.dat"))
+                .unwrap();
+        assert_eq!(index.docs.len(), 1);
+        assert_eq!(index.map.len(), 3);
+    }
+
+    #[test]
+    fn nothing_to_merge_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(merge_files(dir.path(), 0, 2).is_err());
+        assert!(!dir.path().join("index.dat").exists());
+    }
+
+    /// A hit of document `id` at `offsets`, as an index in memory holds it.
+    fn hit(id: u32, offsets: &[u32]) -> Hit {
+        [id, offsets.len() as u32]
+            .iter()
+            .chain(offsets)
+            .flat_map(|n| n.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn hits_out_of_order_are_merged_by_document() {
+        let unsorted = [hit(2, &[4]), hit(0, &[1]), hit(2, &[0, 4, 7])];
+        let sorted = [hit(0, &[1]), hit(2, &[0, 4, 7])];
+        for positions in [true, false] {
+            let raw = encode_hits(&unsorted, positions).unwrap();
+            let (hits, df) = merge_by_doc(&raw, 3, positions).unwrap();
+            assert_eq!(df, 2);
+            if positions {
+                assert_eq!(hits, encode_hits(&sorted, true).unwrap());
+            } else {
+                // Without offsets, the counts add up.
+                let counted = [hit(0, &[1]), hit(2, &[0, 1, 2, 3])];
+                assert_eq!(hits, encode_hits(&counted, false).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn unsorted_indexes_in_memory_merge_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = InMemoryIndex::new();
+        for i in 0..3 {
+            index.merge(doc_index(i));
+        }
+        index.map.get_mut("common").unwrap().reverse();
+        let mut merge = FileMerge::new(dir.path());
+        merge.add_index(index);
+        merge.finish().unwrap();
+
+        let file = dir.path().join("index.dat");
+        assert!(crate::verify::verify(&file).unwrap().is_ok());
+        let merged = IndexFileReader::get_index_from_file(file).unwrap();
+        assert_eq!(merged.map["common"].len(), 3);
+    }
+
+    /// Texts whose terms start with all kinds of first bytes, and an index
+    /// of those numbered `range`, with the empty term in the first one.
+    fn mixed_index(range: Range<usize>) -> InMemoryIndex {
+        const TEXTS: [&str; 6] = [
+            "apple banana common",
+            "ünïcode café common",
+            "日本語 テキスト common",
+            "zebra 123 _under common",
+            "ωmega ящик common",
+            "~tilde €uro common",
+        ];
+        let mut index = InMemoryIndex::new();
+        for i in range.clone() {
+            let text = TEXTS[i % TEXTS.len()].to_string();
+            let hash = [i as u8; HASH_LENGTH];
+            index.merge(InMemoryIndex::from_single_document(&hash, text));
+        }
+        index.map.insert(String::new(), vec![hit(0, &[0])]);
+        index
+    }
+
+    /// Merge each of `files` written to a file of its own, and `memory`,
+    /// on up to `threads` threads, into `index.dat` in a directory of its
+    /// own, and return its bytes.
+    fn merged_bytes(
+        files: Vec<InMemoryIndex>,
+        memory: Option<InMemoryIndex>,
+        threads: usize,
+    ) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let mut merge = FileMerge::new(dir.path()).threads(threads);
+        for index in files {
+            let settings = IndexSettings::default();
+            let file = write_index_to_tmp_file(index, &mut tmp_dir, &settings)
+                .unwrap();
+            merge.add_file(file).unwrap();
+        }
+        if let Some(index) = memory {
+            merge.add_index(index);
+        }
+        merge.finish().unwrap();
+        let file = dir.path().join("index.dat");
+        assert!(crate::verify::verify(&file).unwrap().is_ok());
+        fs::read(file).unwrap()
+    }
+
+    #[test]
+    fn threads_write_the_same_file_as_one() {
+        let inputs = |name| match name {
+            "files" => {
+                let files = (0..4).map(|i| mixed_index(i * 6..i * 6 + 6));
+                (files.collect(), None)
+            }
+            "files and memory" => {
+                let files = (0..3).map(|i| mixed_index(i * 5..i * 5 + 5));
+                (files.collect(), Some(mixed_index(15..24)))
+            }
+            _ => (vec![], Some(mixed_index(0..12))),
+        };
+        for name in ["files", "files and memory", "memory"] {
+            let (files, memory) = inputs(name);
+            let one = merged_bytes(files, memory, 1);
+            for threads in [2, 3, 8] {
+                let (files, memory) = inputs(name);
+                let bytes = merged_bytes(files, memory, threads);
+                assert!(bytes == one, "{} on {} threads", name, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn ranges_without_terms_are_merged_to_nothing() {
+        // Nearly all the hits are of one term, so several ranges after it
+        // have no terms at all.
+        let heavy = "a ".repeat(2000);
+        let inputs = || {
+            (0..3)
+                .map(|i| {
+                    let mut index = InMemoryIndex::new();
+                    for (n, text) in [heavy.clone(), "b c".to_string()]
+                        .into_iter()
+                        .enumerate()
+                    {
+                        let hash = [(i * 2 + n) as u8; HASH_LENGTH];
+                        let one =
+                            InMemoryIndex::from_single_document(&hash, text);
+                        index.merge(one);
+                    }
+                    index
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let files: Vec<PathBuf> = inputs()
+            .into_iter()
+            .map(|index| {
+                let settings = IndexSettings::default();
+                write_index_to_tmp_file(index, &mut tmp_dir, &settings).unwrap()
+            })
+            .collect();
+        let ranges = split_terms(&files, &[], 8).unwrap();
+        assert_eq!(ranges.first().map(|r| r.start), Some(0));
+        assert_eq!(ranges.last().map(|r| r.end), Some(FIRST_BYTES));
+        let terms = ["a", "b", "c"].map(first_byte);
+        assert!(ranges
+            .iter()
+            .any(|range| !terms.iter().any(|byte| range.contains(byte))));
+        assert!(split_terms(&[], &[], 8).unwrap().is_empty());
+
+        let one = merged_bytes(inputs(), None, 1);
+        assert!(merged_bytes(inputs(), None, 8) == one);
+    }
+
+    #[test]
+    fn different_analyzers_are_incompatible() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let unicode = IndexSettings {
+            analyzer: AnalyzerConfig {
+                tokenizer: TokenizerKind::Unicode,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut merge = FileMerge::new(dir.path()).fan_in(2);
+        for settings in [IndexSettings::default(), unicode] {
+            let file =
+                write_index_to_tmp_file(doc_index(0), &mut tmp_dir, &settings)
+                    .unwrap();
+            if let Err(e) = merge.add_file(file) {
+                assert!(matches!(e, Error::Incompatible(_)));
+                return;
+            }
+        }
+        panic!("files with different analyzers were merged");
+    }
+}
@@ -0,0 +1,277 @@
+//! Deciding which segments of an index directory to merge.
+//!
+//! Every segment makes searches a little slower, but merging segments means
+//! rewriting them, so merging everything after every update would make
+//! updates as slow as building the index from scratch. `MergePolicy::Tiered`
+//! strikes the balance the way log-structured merge trees do: segments of
+//! about the same size form a tier, each tier `size_ratio` times larger than
+//! the one below, and a tier is merged into one segment of the next tier
+//! once it holds `segments_per_tier` segments. Each document is then only
+//! rewritten about once per tier, while the number of segments grows with
+//! the logarithm of the size of the index.
+//!
+//! Segments with many deleted documents are rewritten whatever their size,
+//! since deleted documents still take space and slow searches down.
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Which segments `IndexDir::compact` merges.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergePolicy {
+    /// Merge all segments into one.
+    Full,
+    /// Merge the segments smaller than this many bytes, and those with
+    /// deleted documents.
+    Small(u64),
+    /// Keep tiers of segments of similar size. See the module documentation.
+    Tiered(TieredPolicy),
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy::Tiered(TieredPolicy::default())
+    }
+}
+
+/// The knobs of `MergePolicy::Tiered`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TieredPolicy {
+    /// Segments up to this size, in bytes, are all in the lowest tier.
+    pub floor_bytes: u64,
+    /// How much larger the segments of a tier are than those of the tier
+    /// below.
+    pub size_ratio: f64,
+    /// Merge the segments of a tier once there are this many.
+    pub segments_per_tier: usize,
+    /// Merge the smallest segments whenever there are more than this many
+    /// segments in all.
+    pub max_segments: usize,
+    /// Don't merge segments into one larger than this, in bytes.
+    pub max_segment_bytes: u64,
+    /// Rewrite segments once this fraction of their documents is deleted.
+    pub max_deleted: f64,
+}
+
+impl Default for TieredPolicy {
+    fn default() -> Self {
+        TieredPolicy {
+            floor_bytes: 2 << 20,
+            size_ratio: 10.0,
+            segments_per_tier: 10,
+            max_segments: 50,
+            max_segment_bytes: 5 << 30,
+            max_deleted: 0.2,
+        }
+    }
+}
+
+/// What a merge policy knows of a segment.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SegmentSize {
+    pub bytes: u64,
+    /// Number of documents, deleted ones included.
+    pub documents: usize,
+    pub deleted: usize,
+}
+
+impl SegmentSize {
+    /// Size without the deleted documents, roughly.
+    fn live_bytes(&self) -> u64 {
+        match self.documents {
+            0 => self.bytes,
+            n => self.bytes / n as u64 * (n - self.deleted.min(n)) as u64,
+        }
+    }
+}
+
+impl MergePolicy {
+    /// The segments to merge next, by position in `segments`, or none if
+    /// the segments are fine as they are. A single segment is only picked
+    /// to purge its deleted documents.
+    pub(crate) fn pick(&self, segments: &[SegmentSize]) -> Vec<usize> {
+        let picked = match self {
+            MergePolicy::Full => (0..segments.len()).collect(),
+            MergePolicy::Small(small) => (0..segments.len())
+                .filter(|&i| {
+                    segments[i].bytes < *small || segments[i].deleted > 0
+                })
+                .collect(),
+            MergePolicy::Tiered(tiered) => tiered.pick(segments),
+        };
+        let worth_it = picked.len() >= 2
+            || picked.iter().any(|&i| segments[i].deleted > 0);
+        if worth_it {
+            picked
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl TieredPolicy {
+    fn pick(&self, segments: &[SegmentSize]) -> Vec<usize> {
+        // Segments with too many deleted documents come first.
+        let emptied: Vec<usize> = (0..segments.len())
+            .filter(|&i| {
+                let s = &segments[i];
+                s.deleted > 0
+                    && s.deleted as f64 >= self.max_deleted * s.documents as f64
+            })
+            .collect();
+        if !emptied.is_empty() {
+            return emptied;
+        }
+
+        // Then the lowest tier that is full.
+        let mut by_size: Vec<usize> = (0..segments.len()).collect();
+        by_size.sort_by_key(|&i| segments[i].live_bytes());
+        let mut tiers: Vec<Vec<usize>> = vec![];
+        for &i in &by_size {
+            let tier = self.tier(segments[i].live_bytes());
+            if tiers.len() <= tier {
+                tiers.resize(tier + 1, vec![]);
+            }
+            tiers[tier].push(i);
+        }
+        for tier in &tiers {
+            if tier.len() >= self.segments_per_tier {
+                let picked = self.up_to_max(segments, tier);
+                if picked.len() >= 2 {
+                    return picked;
+                }
+            }
+        }
+
+        // Then the smallest segments, if there are too many.
+        if segments.len() > self.max_segments {
+            let excess = segments.len() - self.max_segments + 1;
+            let picked = self.up_to_max(segments, &by_size[..excess.max(2)]);
+            if picked.len() >= 2 {
+                return picked;
+            }
+        }
+        vec![]
+    }
+
+    /// The tier of segments of `bytes` bytes, 0 being the lowest.
+    fn tier(&self, bytes: u64) -> usize {
+        let ratio = self.size_ratio.max(2.0);
+        let mut limit = self.floor_bytes.max(1) as f64;
+        let mut tier = 0;
+        while bytes as f64 > limit {
+            limit *= ratio;
+            tier += 1;
+        }
+        tier
+    }
+
+    /// The first of `candidates` (sorted by size), as long as the merged
+    /// segment stays below the maximum size.
+    fn up_to_max(
+        &self,
+        segments: &[SegmentSize],
+        candidates: &[usize],
+    ) -> Vec<usize> {
+        let mut total = 0;
+        candidates
+            .iter()
+            .copied()
+            .take_while(|&i| {
+                total += segments[i].live_bytes();
+                total <= self.max_segment_bytes
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Segments of `bytes` bytes, of 10 documents none of which is deleted.
+    fn sizes(bytes: &[u64]) -> Vec<SegmentSize> {
+        bytes
+            .iter()
+            .map(|&bytes| SegmentSize {
+                bytes,
+                documents: 10,
+                deleted: 0,
+            })
+            .collect()
+    }
+
+    fn tiered(segments_per_tier: usize) -> MergePolicy {
+        MergePolicy::Tiered(TieredPolicy {
+            floor_bytes: 100,
+            segments_per_tier,
+            ..TieredPolicy::default()
+        })
+    }
+
+    #[test]
+    fn single_segments_are_only_merged_to_purge_deletions() {
+        let mut segments = sizes(&[50]);
+        for policy in [MergePolicy::Full, MergePolicy::Small(100), tiered(2)] {
+            assert!(policy.pick(&segments).is_empty(), "{:?}", policy);
+        }
+        segments[0].deleted = 5;
+        for policy in [MergePolicy::Full, MergePolicy::Small(100), tiered(2)] {
+            assert_eq!(policy.pick(&segments), [0], "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn small_merges_segments_below_the_size_or_with_deletions() {
+        let mut segments = sizes(&[50, 500, 60, 700]);
+        assert_eq!(MergePolicy::Small(100).pick(&segments), [0, 2]);
+        segments[3].deleted = 1;
+        assert_eq!(MergePolicy::Small(100).pick(&segments), [0, 2, 3]);
+        assert_eq!(MergePolicy::Full.pick(&segments), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn tiered_merges_the_lowest_full_tier() {
+        // Tiers of up to 100 bytes, 1000, 10000...
+        let segments = sizes(&[5000, 60, 900, 50, 2000, 40, 800]);
+        assert_eq!(tiered(3).pick(&segments), [5, 3, 1]);
+        assert!(tiered(4).pick(&segments).is_empty());
+        // The lowest tier has too few segments, the next one enough.
+        let segments = sizes(&[5000, 60, 900, 700, 800]);
+        assert_eq!(tiered(3).pick(&segments), [3, 4, 2]);
+    }
+
+    #[test]
+    fn tiered_rewrites_segments_with_many_deletions_first() {
+        let mut segments = sizes(&[60, 50, 40, 9000]);
+        segments[3].deleted = 1;
+        assert_eq!(tiered(3).pick(&segments), [2, 1, 0]);
+        segments[3].deleted = 2;
+        assert_eq!(tiered(3).pick(&segments), [3]);
+    }
+
+    #[test]
+    fn tiered_keeps_merges_below_the_maximum_size() {
+        let policy = MergePolicy::Tiered(TieredPolicy {
+            floor_bytes: 100,
+            segments_per_tier: 3,
+            max_segment_bytes: 100,
+            ..TieredPolicy::default()
+        });
+        assert_eq!(policy.pick(&sizes(&[60, 30, 40])), [1, 2]);
+        assert!(policy.pick(&sizes(&[60, 50, 70])).is_empty());
+    }
+
+    #[test]
+    fn tiered_merges_the_smallest_segments_when_there_are_too_many() {
+        let policy = MergePolicy::Tiered(TieredPolicy {
+            floor_bytes: 10,
+            size_ratio: 2.0,
+            segments_per_tier: 10,
+            max_segments: 3,
+            ..TieredPolicy::default()
+        });
+        let segments = sizes(&[1000, 100, 10000, 30, 400]);
+        assert_eq!(policy.pick(&segments), [3, 1, 4]);
+        assert!(policy.pick(&segments[..3]).is_empty());
+    }
+}
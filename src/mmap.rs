@@ -0,0 +1,423 @@
+//! Memory-mapped index files.
+//!
+//! `MmapIndexReader` maps the whole index file into memory and serves both
+//! the linear pass used for merging (`peek`/`move_entry_to`, like
+//! `IndexFileReader`) and random-access term lookups (like
+//! `IndexFileSearcher`). Term data is copied straight out of the mapping,
+//! with no intermediate buffering or seeking.
+//!
+//! Pages of the mapping are read from disk on first use, so the first
+//! lookups after opening a file that isn't in the OS page cache wait on
+//! the disk. `warm_frequent` and `warm_terms` bring the hits of terms into
+//! memory ahead of the lookups: by asking the OS to read them in the
+//! background (`madvise(MADV_WILLNEED)`), or by touching every page of them
+//! (see `Warming`).
+//!
+//! Encrypted files (see `crypt`) can't be mapped as they are; they are
+//! decrypted into memory whole instead, and need no warming. The index
+//! files of a pack (see `pack`) are read in place, from its mapping.
+//!
+//! Only available with the `mmap` feature.
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+use log::debug;
+#[cfg(unix)]
+use memmap2::Advice;
+use memmap2::Mmap;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::crypt::IndexFile;
+use crate::error::Result;
+use crate::header::IndexSettings;
+use crate::index::{Doc, DocEntry, DocTable, ParsedIndex};
+use crate::postings::{self, DocIds};
+use crate::read::{live_docs, Entry, HitRemapper};
+use crate::slice::SliceReader;
+use crate::tombstone::Tombstones;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Distance between the bytes read to touch every page of a range. Pages
+/// are at least this large on all supported platforms.
+const PAGE_SIZE: usize = 4096;
+
+/// How `MmapIndexReader` brings the hits of terms into memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Warming {
+    /// Ask the OS to read the pages in the background, returning at once.
+    /// Where the OS can't be asked (outside unix), the same as `Touch`.
+    #[default]
+    Advise,
+    /// Read a byte of every page, returning once all of them are in memory.
+    Touch,
+}
+
+/// An index file reader backed by a read-only memory map.
+#[derive(Debug)]
+pub struct MmapIndexReader {
+    /// The mapped file.
+    reader: SliceReader<Mapping>,
+    /// Position of the next entry for the linear `peek`/`move_entry_to`
+    /// pass.
+    cursor: usize,
+}
+
+impl MmapIndexReader {
+    /// Map an index file and load its table of contents.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<MmapIndexReader> {
+        let tombstones = Tombstones::load(filename.as_ref())?;
+        let mut file = IndexFile::open(filename)?;
+        let mmap = match file.plain() {
+            // SAFETY: index files are never modified in place once written;
+            // they are only ever created, renamed and deleted.
+            Some(plain) => Mapping::File(unsafe { Mmap::map(plain)? }),
+            None => {
+                let mut bytes = Vec::with_capacity(file.len() as usize);
+                file.read_to_end(&mut bytes)?;
+                Mapping::Decrypted(bytes)
+            }
+        };
+        MmapIndexReader::with_mapping(mmap, tombstones)
+    }
+
+    /// Read the index file at `range` of `mmap`, the mapping of a larger
+    /// file, like a pack (see `pack`), with `tombstones` deleted.
+    pub(crate) fn open_part(
+        mmap: &Arc<Mmap>,
+        range: Range<usize>,
+        tombstones: Tombstones,
+    ) -> Result<MmapIndexReader> {
+        let mmap = Mapping::Part(mmap.clone(), range);
+        MmapIndexReader::with_mapping(mmap, tombstones)
+    }
+
+    fn with_mapping(
+        mmap: Mapping,
+        tombstones: Tombstones,
+    ) -> Result<MmapIndexReader> {
+        Ok(MmapIndexReader {
+            reader: SliceReader::open(mmap, tombstones)?,
+            cursor: 0,
+        })
+    }
+
+    /// Map an index file for a single linear pass, then delete it.
+    ///
+    /// Like `IndexFileReader::open_and_delete`, the file disappears from its
+    /// directory immediately but its contents stay available until the
+    /// mapping is dropped.
+    pub fn open_and_delete<P: AsRef<Path>>(
+        filename: P,
+    ) -> Result<MmapIndexReader> {
+        let reader = MmapIndexReader::open(filename.as_ref())?;
+        fs::remove_file(filename)?;
+        Ok(reader)
+    }
+
+    /// The settings from the file header.
+    pub fn settings(&self) -> &IndexSettings {
+        self.reader.settings()
+    }
+
+    /// The document table of the file, by document id, including deleted
+    /// documents.
+    pub fn docs(&self) -> &DocTable {
+        self.reader.docs()
+    }
+
+    /// Borrow a reference to the next entry in the table of contents.
+    ///
+    /// Returns `None` if we've reached the end of the file.
+    pub fn peek(&self) -> Option<&Entry> {
+        self.reader.entries().get(self.cursor)
+    }
+
+    /// True if the next entry is for the given term.
+    pub fn is_at(&self, term: &str) -> bool {
+        self.peek().is_some_and(|e| e.term == term)
+    }
+
+    /// Append the hits of the next entry to `out` through `remapper` and
+    /// advance to the entry after it. `doc_base` is added to every document
+    /// id, as this file's document table starts at that id in the output.
+    pub fn move_entry_to(
+        &mut self,
+        out: &mut Vec<u8>,
+        remapper: &mut HitRemapper,
+        doc_base: u32,
+    ) -> Result<()> {
+        let e = self.peek().expect("no entry to move");
+        let positions = !self.settings().omit_positions;
+        let (hits, _) = remapper.remap(
+            &self.reader.hits(e)?,
+            e.doc_count,
+            positions,
+            |id| Some(id + doc_base),
+        )?;
+        out.extend(hits);
+        self.cursor += 1;
+        Ok(())
+    }
+
+    /// Advance past the next entry without reading its hits.
+    pub(crate) fn skip_entry(&mut self) -> Result<()> {
+        self.cursor += 1;
+        Ok(())
+    }
+
+    /// The table of contents entry for `term`, if the term is in the index.
+    pub fn entry(&self, term: &str) -> Option<&Entry> {
+        self.reader.entry(term)
+    }
+
+    /// Decode the hits for `term`.
+    ///
+    /// Returns `Ok(None)` if the term does not occur in the index.
+    pub fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        self.reader.lookup(term)
+    }
+
+    /// Decode the whole index, without the deleted documents, like
+    /// `IndexFileReader::get_index_from_file`.
+    pub fn load(&self) -> Result<ParsedIndex> {
+        self.reader.load()
+    }
+
+    /// The documents containing all of `terms`, by document id, like
+    /// `IndexFileSearcher::intersect`.
+    pub fn intersect(&self, terms: &[&str]) -> Result<Vec<Doc>> {
+        let mut entries = vec![];
+        for term in terms {
+            match self.entry(term) {
+                Some(e) => entries.push(e),
+                None => return Ok(vec![]),
+            }
+        }
+        let hits = entries
+            .iter()
+            .map(|e| self.reader.hits(e))
+            .collect::<Result<Vec<_>>>()?;
+        let positions = !self.settings().omit_positions;
+        let lists = hits
+            .iter()
+            .zip(&entries)
+            .map(|(hits, e)| DocIds::of_hits(hits, e, positions))
+            .collect();
+        let ids = postings::intersect(lists)?;
+        live_docs(&ids, self.docs(), self.reader.tombstones())
+    }
+
+    /// Bring the hits of the `n` terms found in the most documents into
+    /// memory, as `how` says. These are the longest lists of hits and the
+    /// ones most queries read, so warming them after opening spares the
+    /// first queries most of their disk reads. Returns the number of bytes
+    /// warmed.
+    pub fn warm_frequent(&self, n: usize, how: Warming) -> Result<usize> {
+        let mut entries: Vec<&Entry> = self.reader.entries().iter().collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.doc_count));
+        entries.truncate(n);
+        self.warm(&entries, how)
+    }
+
+    /// Bring the hits of `terms` into memory, as `how` says, e.g. the terms
+    /// of queries expected soon. Terms not in the index are skipped.
+    /// Returns the number of bytes warmed.
+    pub fn warm_terms(&self, terms: &[&str], how: Warming) -> Result<usize> {
+        let entries: Vec<&Entry> =
+            terms.iter().filter_map(|term| self.entry(term)).collect();
+        self.warm(&entries, how)
+    }
+
+    fn warm(&self, entries: &[&Entry], how: Warming) -> Result<usize> {
+        let mut warmed = 0;
+        for e in entries {
+            let data = self.reader.data(e);
+            match how {
+                #[cfg(unix)]
+                Warming::Advise => {
+                    if let Some((mmap, start)) = self.reader.bytes().mapped() {
+                        mmap.advise_range(
+                            Advice::WillNeed,
+                            start + e.offset as usize,
+                            data.len(),
+                        )?
+                    }
+                }
+                _ => touch(data),
+            }
+            warmed += data.len();
+        }
+        debug!("warmed {} bytes of hits of {} terms", warmed, entries.len());
+        Ok(warmed)
+    }
+}
+
+/// The bytes of an index file.
+enum Mapping {
+    File(Mmap),
+    /// An encrypted file, decrypted.
+    Decrypted(Vec<u8>),
+    /// Part of a larger file.
+    Part(Arc<Mmap>, Range<usize>),
+}
+
+impl Mapping {
+    /// The mapping holding the bytes, and where they start in it, unless
+    /// they were decrypted into memory.
+    #[cfg(unix)]
+    fn mapped(&self) -> Option<(&Mmap, usize)> {
+        match self {
+            Mapping::File(mmap) => Some((mmap, 0)),
+            Mapping::Decrypted(_) => None,
+            Mapping::Part(mmap, range) => Some((mmap, range.start)),
+        }
+    }
+}
+
+impl fmt::Debug for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mapping::File(mmap) => mmap.fmt(f),
+            Mapping::Decrypted(bytes) => {
+                write!(f, "Decrypted({} bytes)", bytes.len())
+            }
+            Mapping::Part(mmap, range) => write!(f, "{:?}[{:?}]", mmap, range),
+        }
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Mapping::File(mmap) => mmap,
+            Mapping::Decrypted(bytes) => bytes,
+            Mapping::Part(mmap, range) => &mmap[range.clone()],
+        }
+    }
+}
+
+/// Read a byte of every page of `data`, so the OS loads them all.
+fn touch(data: &[u8]) {
+    for i in (0..data.len()).step_by(PAGE_SIZE) {
+        std::hint::black_box(data[i]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_index;
+    use crate::index::InMemoryIndex;
+    use crate::read::{IndexFileReader, IndexFileSearcher};
+    use crate::tmp::TmpDir;
+    use crate::write::write_index_to_tmp_file;
+    use crate::HASH_LENGTH;
+
+    #[test]
+    fn lookups_match_the_file_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["the cat", "the dog and the cat"]);
+        let parsed = IndexFileReader::get_index_from_file(&file).unwrap();
+        let reader = MmapIndexReader::open(&file).unwrap();
+        assert_eq!(reader.docs().len(), 2);
+        for term in ["the", "cat", "dog", "and"] {
+            assert_eq!(reader.lookup(term).unwrap().unwrap(), parsed.map[term]);
+        }
+        assert_eq!(reader.lookup("bird").unwrap(), None);
+        let the = reader.lookup("the").unwrap().unwrap();
+        assert_eq!(the[&Doc::new(&[1; HASH_LENGTH])], vec![0, 3]);
+    }
+
+    #[test]
+    fn intersections_match_the_file_searcher() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts: Vec<String> = (0..400)
+            .map(|i| format!("all n{} m{}", i % 2, i % 5))
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let mut index = InMemoryIndex::new();
+        for (i, text) in texts.iter().enumerate() {
+            let mut hash = [0; HASH_LENGTH];
+            hash[..4].copy_from_slice(&(i as u32).to_le_bytes());
+            index.merge(InMemoryIndex::from_single_document(
+                &hash,
+                text.to_string(),
+            ));
+        }
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let reader = MmapIndexReader::open(&file).unwrap();
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+        for terms in [&["all", "n0"][..], &["n1", "m3", "all"], &["m2", "x"]] {
+            let found = reader.intersect(terms).unwrap();
+            assert_eq!(found, searcher.intersect(terms).unwrap());
+        }
+        assert_eq!(reader.intersect(&["n1", "m3"]).unwrap().len(), 40);
+    }
+
+    #[test]
+    fn warming_leaves_lookups_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["the cat", "the dog", "a cat"]);
+        let reader = MmapIndexReader::open(&file).unwrap();
+        let before = reader.lookup("the").unwrap();
+        for how in [Warming::Advise, Warming::Touch] {
+            let the = reader.entry("the").unwrap().nbytes as usize;
+            let warmed = reader.warm_terms(&["the", "bird"], how).unwrap();
+            assert_eq!(warmed, the);
+            assert_eq!(reader.warm_frequent(1, how).unwrap(), the);
+            assert!(reader.warm_frequent(100, how).unwrap() > the);
+        }
+        assert_eq!(reader.warm_terms(&[], Warming::Touch).unwrap(), 0);
+        assert_eq!(reader.lookup("the").unwrap(), before);
+    }
+
+    #[test]
+    fn linear_pass_visits_terms_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["b a", "c"]);
+        let mut reader = MmapIndexReader::open(&file).unwrap();
+        let mut terms = vec![];
+        let mut out = vec![];
+        while let Some(e) = reader.peek() {
+            terms.push(e.term.clone());
+            reader
+                .move_entry_to(&mut out, &mut HitRemapper::default(), 0)
+                .unwrap();
+        }
+        assert_eq!(terms, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn damaged_files_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["the cat"]);
+        let good = fs::read(&file).unwrap();
+        let damaged = dir.path().join("damaged.dat");
+        for len in [0, 10, good.len() / 2, good.len() - 1] {
+            fs::write(&damaged, &good[..len]).unwrap();
+            assert!(MmapIndexReader::open(&damaged).is_err(), "length {len}");
+        }
+        for i in 0..good.len() {
+            let mut bytes = good.clone();
+            bytes[i] ^= 0xff;
+            fs::write(&damaged, &bytes).unwrap();
+            if let Ok(reader) = MmapIndexReader::open(&damaged) {
+                let _ = reader.lookup("cat");
+            }
+        }
+    }
+}
@@ -0,0 +1,705 @@
+//! Indexes packed into a single file.
+//!
+//! `pack` bundles an index, be it an index file, a sharded index or an
+//! index directory, into one file, so that it can be shipped as a single
+//! artifact and searched read-only wherever it is copied to. `PackedIndex`
+//! maps the pack into memory, and reads its index files in place, like
+//! `MmapIndexReader` does whole files. `StaticIndex` reads a pack, or a
+//! single index file, from bytes that stay in memory for good, such as ones
+//! built into a program with `include_bytes!`, to search its documentation
+//! offline, with no file to open, or from a downloaded pack
+//! (`StaticIndex::from_vec`):
+//!
+//! ```text
+//! static DOCS: &[u8] = include_bytes!("docs.pack");
+//! let results = StaticIndex::from_bytes(DOCS)?.searcher()?.query("install")?;
+//! ```
+//!
+//! A pack starts with `PACK_MAGIC`, its version and the number of its
+//! sections, as u32s, followed by the table of sections: for each, the
+//! length of its name as a u16, the name, then its offset and length as
+//! u64s. Offsets count from the start of the pack, so that it can be moved.
+//! The sections are, in order:
+//!
+//! * `manifest`: what was packed, in JSON, with the names of its index
+//!   files in order.
+//! * Each index file, under its name, as it is, with its own header,
+//!   document table and table of contents.
+//! * The tombstones of each index file with deleted documents, under the
+//!   name of their sidecar (see `tombstone`).
+//!
+//! Encrypted index files (see `crypt`) can't be read in place, and aren't
+//! packed.
+//!
+//! `PackedIndex` is only available with the `mmap` feature.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::debug;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::iter;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::header::{read_header, IndexSettings, MAGIC};
+use crate::index::{DocEntry, ParsedIndex};
+#[cfg(feature = "mmap")]
+use crate::mmap::MmapIndexReader;
+use crate::search::{MultiSearcher, Searcher};
+use crate::segments::IndexDir;
+use crate::shard::{self, shard_of, ShardedIndex};
+use crate::slice::SliceReader;
+use crate::tmp::publish;
+use crate::tombstone::Tombstones;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// First bytes of every pack.
+pub const PACK_MAGIC: &[u8; 8] = b"IDXTPACK";
+
+/// Version of the layout of packs.
+pub const PACK_VERSION: u32 = 1;
+
+/// Name of the section describing the pack.
+const MANIFEST: &str = "manifest";
+
+/// What kind of index a pack holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackKind {
+    /// A single index file.
+    File,
+    /// The segments of an index directory, searched together.
+    Segments,
+    /// The shards of a sharded index, loaded into one index.
+    Shards,
+}
+
+/// The `manifest` section.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    kind: PackKind,
+    /// Names of the index files, in order.
+    files: Vec<String>,
+}
+
+/// Bundle the index at `index`, an index file, a sharded index or an index
+/// directory, with its deleted documents, into the pack `pack_file`,
+/// replacing it atomically if it exists. Documents that a writer of an index
+/// directory still buffers aren't in any segment yet, and are left out.
+pub fn pack<P: AsRef<Path>, Q: AsRef<Path>>(
+    index: P,
+    pack_file: Q,
+) -> Result<()> {
+    let (index, pack_file) = (index.as_ref(), pack_file.as_ref());
+    if IndexDir::is_index_dir(index) {
+        IndexDir::open_read_only(index)?.with_segments(|files| {
+            write_pack(PackKind::Segments, files, pack_file)
+        })
+    } else if ShardedIndex::is_sharded(index) {
+        let sharded = ShardedIndex::open(index)?;
+        write_pack(PackKind::Shards, sharded.files(), pack_file)
+    } else {
+        write_pack(PackKind::File, &[index.to_path_buf()], pack_file)
+    }
+}
+
+fn write_pack(
+    kind: PackKind,
+    files: &[PathBuf],
+    pack_file: &Path,
+) -> Result<()> {
+    let mut manifest = Manifest {
+        kind,
+        files: vec![],
+    };
+    // The sections after the manifest: their names, files and lengths.
+    let mut sections = vec![];
+    for file in files {
+        if IndexSettings::load(file)?.encrypted {
+            return Err(Error::Unsupported(format!(
+                "{} is encrypted, and packs are read in place",
+                file.display()
+            )));
+        }
+        let name = file_name(file)?;
+        manifest.files.push(name.clone());
+        sections.push((name, file.clone(), fs::metadata(file)?.len()));
+        let sidecar = Tombstones::sidecar_path(file);
+        match fs::metadata(&sidecar) {
+            Ok(metadata) => {
+                sections.push((file_name(&sidecar)?, sidecar, metadata.len()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let manifest = serde_json::to_vec(&manifest).map_err(io::Error::other)?;
+
+    let mut tmp = pack_file.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    let names: Vec<&str> = iter::once(MANIFEST)
+        .chain(sections.iter().map(|(name, ..)| name.as_str()))
+        .collect();
+    let lens: Vec<u64> = iter::once(manifest.len() as u64)
+        .chain(sections.iter().map(|&(.., len)| len))
+        .collect();
+    let written = (|| -> Result<()> {
+        out.write_all(PACK_MAGIC)?;
+        out.write_u32::<LittleEndian>(PACK_VERSION)?;
+        out.write_u32::<LittleEndian>(names.len() as u32)?;
+        let table_len: usize = names.iter().map(|name| name.len() + 18).sum();
+        let mut offset = (PACK_MAGIC.len() + 8 + table_len) as u64;
+        for (name, &len) in names.iter().zip(&lens) {
+            out.write_u16::<LittleEndian>(name.len() as u16)?;
+            out.write_all(name.as_bytes())?;
+            out.write_u64::<LittleEndian>(offset)?;
+            out.write_u64::<LittleEndian>(len)?;
+            offset += len;
+        }
+        out.write_all(&manifest)?;
+        for (_, file, len) in &sections {
+            debug!("Packing {}", file.display());
+            if io::copy(&mut File::open(file)?, &mut out)? != *len {
+                return Err(Error::Io(io::Error::other(format!(
+                    "{} changed while it was packed",
+                    file.display()
+                ))));
+            }
+        }
+        out.flush()?;
+        Ok(publish(&tmp, pack_file)?)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+/// The name of `file`, which names its section.
+fn file_name(file: &Path) -> Result<String> {
+    file.file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.len() <= u16::MAX as usize)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            Error::Unsupported(format!(
+                "{} has no name a pack can hold",
+                file.display()
+            ))
+        })
+}
+
+/// A pack, open for searching.
+#[cfg(feature = "mmap")]
+pub struct PackedIndex {
+    path: PathBuf,
+    kind: PackKind,
+    /// The index files, in the order of the manifest.
+    readers: Vec<MmapIndexReader>,
+}
+
+#[cfg(feature = "mmap")]
+impl PackedIndex {
+    /// True if `path` is a pack.
+    pub fn is_packed<P: AsRef<Path>>(path: P) -> bool {
+        let mut magic = [0; 8];
+        File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .is_ok_and(|()| &magic == PACK_MAGIC)
+    }
+
+    /// Map the pack at `path`, and load the tables of its index files.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<PackedIndex> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // SAFETY: packs are never modified in place once written; they are
+        // only ever created, renamed and deleted.
+        let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+        let (kind, parts) = parts(&mmap)?;
+        let readers = parts
+            .into_iter()
+            .map(|(range, tombstones)| {
+                MmapIndexReader::open_part(&mmap, range, tombstones)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        debug!("Opened {}, {} index files", path.display(), readers.len());
+        Ok(PackedIndex {
+            path: path.to_path_buf(),
+            kind,
+            readers,
+        })
+    }
+
+    /// What kind of index was packed.
+    pub fn kind(&self) -> PackKind {
+        self.kind
+    }
+
+    /// The index files of the pack, in order: segments oldest first, or
+    /// shards by number.
+    pub fn readers(&self) -> &[MmapIndexReader] {
+        &self.readers
+    }
+
+    /// Load the index for searching, like `IndexDir::searcher` for the
+    /// segments of an index directory, or `Searcher::open` for the others.
+    pub fn searcher(&self) -> Result<MultiSearcher> {
+        let indexes = self
+            .readers
+            .iter()
+            .map(|reader| Ok((reader.load()?, reader.settings())))
+            .collect::<Result<Vec<_>>>()?;
+        searcher(self.kind, indexes, Some(&self.path))
+    }
+
+    /// Decode the hits for `term`, an analyzed term, from every index file
+    /// that may hold it, without loading them: its own shard only, in packs
+    /// of shards.
+    ///
+    /// Returns `Ok(None)` if no live document contains the term.
+    pub fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        let readers =
+            &self.readers[holding(self.kind, self.readers.len(), term)];
+        union(readers.iter().map(|reader| reader.lookup(term)))
+    }
+}
+
+/// An index searched in place in bytes in memory, that never change: a
+/// pack, or a single index file, built into the program with
+/// `include_bytes!`, or downloaded. Like `PackedIndex`, without a file or a
+/// mapping.
+pub struct StaticIndex {
+    kind: PackKind,
+    /// The index files, in the order of the manifest.
+    readers: Vec<SliceReader<Bytes>>,
+}
+
+/// The bytes of an index file of a `StaticIndex`.
+enum Bytes {
+    Static(&'static [u8]),
+    /// Part of bytes shared by the index files of a pack.
+    Shared(Arc<[u8]>, Range<usize>),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Static(bytes) => bytes,
+            Bytes::Shared(bytes, range) => &bytes[range.clone()],
+        }
+    }
+}
+
+impl StaticIndex {
+    /// Load the tables of the index files of `bytes`, the contents of a pack
+    /// or of an index file, read in place.
+    pub fn from_bytes(bytes: &'static [u8]) -> Result<StaticIndex> {
+        StaticIndex::open(bytes, |range| Bytes::Static(&bytes[range]))
+    }
+
+    /// Load the tables of the index files of `bytes`, the contents of a pack
+    /// or of an index file, e.g. fetched by a browser, which the index
+    /// keeps.
+    pub fn from_vec(bytes: Vec<u8>) -> Result<StaticIndex> {
+        let bytes: Arc<[u8]> = bytes.into();
+        StaticIndex::open(&bytes, |range| Bytes::Shared(bytes.clone(), range))
+    }
+
+    /// Load the index files of `bytes`, each of which `part` gives the bytes
+    /// of from where it is.
+    fn open(
+        bytes: &[u8],
+        part: impl Fn(Range<usize>) -> Bytes,
+    ) -> Result<StaticIndex> {
+        let (kind, parts) = match bytes.starts_with(MAGIC) {
+            true => {
+                in_place(bytes)?;
+                (
+                    PackKind::File,
+                    vec![(0..bytes.len(), Tombstones::default())],
+                )
+            }
+            false => parts(bytes)?,
+        };
+        let readers = parts
+            .into_iter()
+            .map(|(range, tombstones)| {
+                SliceReader::open(part(range), tombstones)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(StaticIndex { kind, readers })
+    }
+
+    /// What kind of index the bytes hold.
+    pub fn kind(&self) -> PackKind {
+        self.kind
+    }
+
+    /// Load the index for searching, like `PackedIndex::searcher`.
+    pub fn searcher(&self) -> Result<MultiSearcher> {
+        let indexes = self
+            .readers
+            .iter()
+            .map(|reader| Ok((reader.load()?, reader.settings())))
+            .collect::<Result<Vec<_>>>()?;
+        searcher(self.kind, indexes, None)
+    }
+
+    /// Decode the hits for `term`, an analyzed term, like
+    /// `PackedIndex::lookup`.
+    pub fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        let readers =
+            &self.readers[holding(self.kind, self.readers.len(), term)];
+        union(readers.iter().map(|reader| reader.lookup(term)))
+    }
+}
+
+/// A searcher of the index files of a pack of `kind`, loaded as `indexes`,
+/// each with its settings, recording `source` as where they were loaded
+/// from. Shards are joined into one index.
+fn searcher(
+    kind: PackKind,
+    indexes: Vec<(ParsedIndex, &IndexSettings)>,
+    source: Option<&Path>,
+) -> Result<MultiSearcher> {
+    let searcher = |index, settings: &IndexSettings| {
+        let searcher = Searcher::new(index, settings.clone())?;
+        Ok(match source {
+            Some(source) => searcher.with_source(source.to_path_buf()),
+            None => searcher,
+        })
+    };
+    let searchers = match kind {
+        PackKind::Shards if !indexes.is_empty() => {
+            let settings = indexes[0].1.clone();
+            let names = (0..).map(|n| format!("shard {}", n));
+            let shards = indexes.into_iter().map(|(index, _)| index);
+            vec![searcher(shard::join(shards.zip(names))?, &settings)?]
+        }
+        _ => indexes
+            .into_iter()
+            .map(|(index, settings)| searcher(index, settings))
+            .collect::<Result<Vec<_>>>()?,
+    };
+    Ok(MultiSearcher::new(searchers))
+}
+
+/// Which of the `n` index files of a pack of `kind` may hold `term`.
+fn holding(kind: PackKind, n: usize, term: &str) -> Range<usize> {
+    match kind {
+        PackKind::Shards if n > 0 => {
+            let shard = shard_of(term, n);
+            shard..shard + 1
+        }
+        _ => 0..n,
+    }
+}
+
+/// The documents of all of the hits of a term, in several index files.
+fn union(
+    hits: impl Iterator<Item = Result<Option<DocEntry>>>,
+) -> Result<Option<DocEntry>> {
+    let mut entry = DocEntry::new();
+    for found in hits {
+        if let Some(hits) = found? {
+            entry.extend(hits);
+        }
+    }
+    Ok(Some(entry).filter(|e| !e.is_empty()))
+}
+
+/// Where an index file is in a pack, with its deleted documents.
+type Part = (Range<usize>, Tombstones);
+
+/// What kind of index the pack `pack` holds, and where its index files
+/// are in it, in order, each with its deleted documents.
+fn parts(pack: &[u8]) -> Result<(PackKind, Vec<Part>)> {
+    let sections = read_sections(pack)?;
+    let section = |name: &str| {
+        sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, range)| range.clone())
+    };
+    let manifest = section(MANIFEST)
+        .ok_or_else(|| Error::format("pack has no manifest"))?;
+    let manifest: Manifest = serde_json::from_slice(&pack[manifest])
+        .map_err(|e| Error::format(format!("bad pack manifest: {}", e)))?;
+    if manifest.kind == PackKind::File && manifest.files.len() != 1 {
+        return Err(Error::format("pack of a file holds several files"));
+    }
+    let mut parts = vec![];
+    for name in &manifest.files {
+        let range = section(name).ok_or_else(|| {
+            Error::format(format!("pack has no section {}", name))
+        })?;
+        let hash_len = in_place(&pack[range.clone()])?.identity.hash_len();
+        let sidecar = Tombstones::sidecar_path(name);
+        let tombstones = match section(&sidecar.to_string_lossy()) {
+            Some(deleted) => Tombstones::from_bytes(&pack[deleted], hash_len)?,
+            None => Tombstones::default(),
+        };
+        parts.push((range, tombstones));
+    }
+    Ok((manifest.kind, parts))
+}
+
+/// The settings of the index file `bytes`, unless it is encrypted, which
+/// can't be read in place.
+fn in_place(bytes: &[u8]) -> Result<IndexSettings> {
+    let settings = read_header(&mut &bytes[..])?.settings;
+    if settings.encrypted {
+        return Err(Error::Unsupported(
+            "encrypted index files can't be read in place".to_string(),
+        ));
+    }
+    Ok(settings)
+}
+
+/// The names of the sections of `pack`, with where they are in it.
+fn read_sections(pack: &[u8]) -> Result<Vec<(String, Range<usize>)>> {
+    let mut f = pack;
+    let truncated = Error::truncated("pack header");
+    let mut magic = [0; 8];
+    f.read_exact(&mut magic).map_err(&truncated)?;
+    if &magic != PACK_MAGIC {
+        return Err(Error::format("not a pack"));
+    }
+    let version = f.read_u32::<LittleEndian>().map_err(&truncated)?;
+    if version != PACK_VERSION {
+        return Err(Error::Unsupported(format!(
+            "pack version {} (expected {})",
+            version, PACK_VERSION
+        )));
+    }
+    let count = f.read_u32::<LittleEndian>().map_err(&truncated)?;
+    let mut sections = vec![];
+    for _ in 0..count {
+        let len = f.read_u16::<LittleEndian>().map_err(&truncated)?;
+        let mut name = vec![0; len as usize];
+        f.read_exact(&mut name).map_err(&truncated)?;
+        let name = String::from_utf8(name)
+            .map_err(|_| Error::format("pack section name isn't UTF-8"))?;
+        let offset = f.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let len = f.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= pack.len() as u64)
+            .ok_or_else(|| {
+                Error::format(format!("pack section {} is truncated", name))
+            })?;
+        sections.push((name, offset as usize..end as usize));
+    }
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{doc, index_of};
+    use crate::index::Doc;
+    use crate::search::SearchResults;
+    use crate::tmp::TmpDir;
+    use crate::write::{write_index_to_tmp_file, write_index_to_vec};
+
+    fn hits(results: SearchResults) -> Vec<(Doc, f64)> {
+        results
+            .into_iter()
+            .map(|hit| (hit.doc, hit.score))
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn packs_search_like_what_they_were_made_of() {
+        use crate::merge::FileMerge;
+
+        let dir = tempfile::tempdir().unwrap();
+        let moved = tempfile::tempdir().unwrap();
+        let segments = dir.path().join("segments");
+        let index_dir =
+            IndexDir::create(&segments, IndexSettings::default()).unwrap();
+        index_dir
+            .add_index(index_of(1, &["the cat sat", "a cat ran"]))
+            .unwrap();
+        index_dir.add_index(index_of(3, &["the dog sat"])).unwrap();
+        index_dir.delete(&[doc(2)]).unwrap();
+        let shards = dir.path().join("shards");
+        fs::create_dir(&shards).unwrap();
+        let mut merge =
+            FileMerge::with_settings(&shards, IndexSettings::default())
+                .shards(3);
+        merge.add_index(index_of(1, &["the cat sat", "a dog ran", "cats"]));
+        merge.finish().unwrap();
+
+        for (index, kind, files) in [
+            (&segments, PackKind::Segments, 2),
+            (&shards, PackKind::Shards, 3),
+        ] {
+            let pack_file = dir.path().join("index.pack");
+            pack(index, &pack_file).unwrap();
+            // Packs don't depend on where they are.
+            let pack_file = {
+                let to = moved.path().join("index.pack");
+                fs::rename(&pack_file, &to).unwrap();
+                to
+            };
+            assert!(PackedIndex::is_packed(&pack_file));
+            let packed = PackedIndex::open(&pack_file).unwrap();
+            assert_eq!(packed.kind(), kind);
+            assert_eq!(packed.readers().len(), files);
+
+            let original = match kind {
+                PackKind::Segments => index_dir.searcher().unwrap(),
+                _ => MultiSearcher::new(vec![Searcher::open(index).unwrap()]),
+            };
+            let searcher = packed.searcher().unwrap();
+            for query in ["cat", "sat OR dog", "the AND sat"] {
+                assert_eq!(
+                    hits(searcher.query(query).unwrap()),
+                    hits(original.query(query).unwrap()),
+                    "{}",
+                    query
+                );
+            }
+            for term in ["cat", "sat", "ran", "horse"] {
+                let expected = match kind {
+                    PackKind::Segments => index_dir.lookup(term).unwrap(),
+                    _ => {
+                        ShardedIndex::open(index).unwrap().lookup(term).unwrap()
+                    }
+                };
+                assert_eq!(packed.lookup(term).unwrap(), expected, "{}", term);
+            }
+        }
+    }
+
+    #[test]
+    fn static_indexes_search_like_what_they_were_made_of() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir = IndexDir::create(
+            dir.path().join("index"),
+            IndexSettings::default(),
+        )
+        .unwrap();
+        index_dir
+            .add_index(index_of(1, &["the cat sat", "a cat ran"]))
+            .unwrap();
+        index_dir.add_index(index_of(3, &["the dog sat"])).unwrap();
+        index_dir.delete(&[doc(2)]).unwrap();
+        let file = write_index_to_tmp_file(
+            index_of(1, &["the cat sat", "a dog ran"]),
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let pack_file = dir.path().join("index.pack");
+        pack(index_dir.path(), &pack_file).unwrap();
+        let leak = |path: &Path| -> &'static [u8] {
+            Box::leak(fs::read(path).unwrap().into_boxed_slice())
+        };
+
+        let packed = StaticIndex::from_bytes(leak(&pack_file)).unwrap();
+        assert_eq!(packed.kind(), PackKind::Segments);
+        let fetched =
+            StaticIndex::from_vec(fs::read(&pack_file).unwrap()).unwrap();
+        let single = StaticIndex::from_bytes(leak(&file)).unwrap();
+        assert_eq!(single.kind(), PackKind::File);
+        for (index, original) in [
+            (&packed, index_dir.searcher().unwrap()),
+            (&fetched, index_dir.searcher().unwrap()),
+            (
+                &single,
+                MultiSearcher::new(vec![Searcher::open(&file).unwrap()]),
+            ),
+        ] {
+            let searcher = index.searcher().unwrap();
+            for query in ["cat", "sat OR dog", "the AND sat"] {
+                assert_eq!(
+                    hits(searcher.query(query).unwrap()),
+                    hits(original.query(query).unwrap()),
+                    "{}",
+                    query
+                );
+            }
+        }
+        for term in ["cat", "sat", "ran", "horse"] {
+            let expected = index_dir.lookup(term).unwrap();
+            assert_eq!(packed.lookup(term).unwrap(), expected, "{}", term);
+            assert_eq!(fetched.lookup(term).unwrap(), expected, "{}", term);
+        }
+
+        let good = leak(&pack_file);
+        for len in [0, 10, 40, good.len() / 2, good.len() - 1] {
+            assert!(StaticIndex::from_bytes(&good[..len]).is_err(), "{}", len);
+        }
+    }
+
+    /// Without files or threads, as on wasm32.
+    #[test]
+    fn in_memory_indexes_are_searched_without_files() {
+        let texts = ["the cat sat", "a dog ran", "the dog sat"];
+        let bytes =
+            write_index_to_vec(index_of(1, &texts), &IndexSettings::default())
+                .unwrap();
+        let index = StaticIndex::from_vec(bytes).unwrap();
+        assert_eq!(index.kind(), PackKind::File);
+        let searcher = index.searcher().unwrap();
+        let found = |query| {
+            let mut docs: Vec<_> = searcher
+                .query(query)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.doc)
+                .collect();
+            docs.sort_by(|a, b| a.hash.cmp(&b.hash));
+            docs
+        };
+        assert_eq!(found("dog AND sat"), [doc(3)]);
+        assert_eq!(found("cat OR ran"), [doc(1), doc(2)]);
+        assert_eq!(found("horse"), []);
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn damaged_packs_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        let file = index_dir.add_index(index_of(1, &["the cat"])).unwrap();
+        let pack_file = dir.path().join("index.pack");
+        pack(&file, &pack_file).unwrap();
+        assert!(!PackedIndex::is_packed(&file));
+        assert_eq!(
+            PackedIndex::open(&pack_file).unwrap().kind(),
+            PackKind::File
+        );
+
+        let good = fs::read(&pack_file).unwrap();
+        let damaged = dir.path().join("damaged.pack");
+        for len in [0, 10, 40, good.len() / 2, good.len() - 1] {
+            fs::write(&damaged, &good[..len]).unwrap();
+            assert!(PackedIndex::open(&damaged).is_err(), "length {}", len);
+        }
+        for i in 0..good.len().min(200) {
+            let mut bytes = good.clone();
+            bytes[i] ^= 0xff;
+            fs::write(&damaged, &bytes).unwrap();
+            if let Ok(packed) = PackedIndex::open(&damaged) {
+                let _ = packed.lookup("cat");
+            }
+        }
+    }
+}
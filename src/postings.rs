@@ -0,0 +1,343 @@
+//! Set operations on the documents of posting lists.
+//!
+//! The hits of a term are stored sorted by document id, each document once
+//! (see `write`), so the documents matching a boolean combination of terms
+//! can be found by walking their hits side by side, still encoded: offsets
+//! are skipped over rather than decoded, and no set of documents is built
+//! along the way. `Postings` walks the hits of one term, jumping ahead with
+//! its skip points; `DocIds` is either that or ids already found, like those
+//! of a subquery, so that `intersect`, `union` and `difference` combine
+//! both.
+//!
+//! Moving to the first document at or after an id gallops: it looks 1, 2,
+//! 4… skip points (or ids) ahead until it overshoots, then searches the
+//! last step, so short moves, the common case when lists are of similar
+//! length, stay cheap.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::read::{skip_offsets, Entry, Skip};
+use crate::varint;
+use crate::write::SKIP_INTERVAL;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Reads the document ids in the hits of one term in increasing order,
+/// jumping over the documents it doesn't need with the skip points of the
+/// term.
+pub(crate) struct Postings<'a> {
+    hits: &'a [u8],
+    skips: &'a [Skip],
+    doc_count: u32,
+    /// Whether the hits have offsets.
+    positions: bool,
+    /// The hits not read yet.
+    rest: &'a [u8],
+    /// Number of documents read so far.
+    read: u32,
+    /// Id of the last document read.
+    id: u32,
+    /// The document the cursor is on, if it has been moved yet.
+    current: Option<u32>,
+    /// Number of occurrences of the term in that document.
+    tf: u32,
+}
+
+impl<'a> Postings<'a> {
+    /// Read `hits`, the decompressed hits of `entry`, stored with
+    /// `positions` or not.
+    pub fn new(
+        hits: &'a [u8],
+        entry: &'a Entry,
+        positions: bool,
+    ) -> Postings<'a> {
+        Postings {
+            hits,
+            skips: &entry.skips,
+            doc_count: entry.doc_count,
+            positions,
+            rest: hits,
+            read: 0,
+            id: 0,
+            current: None,
+            tf: 0,
+        }
+    }
+
+    /// Move to the next document, and return its id, or `None` past the
+    /// last one.
+    pub fn next(&mut self) -> Result<Option<u32>> {
+        if self.read == self.doc_count {
+            self.current = None;
+            return Ok(None);
+        }
+        self.id = self.id.wrapping_add(varint::read_u32(&mut self.rest)?);
+        self.tf = skip_offsets(&mut self.rest, self.positions)?;
+        self.read += 1;
+        self.current = Some(self.id);
+        Ok(self.current)
+    }
+
+    /// Move to the first document with an id of at least `target`, unless
+    /// the cursor is already on one, and return its id, or `None` if there
+    /// is none.
+    pub fn advance_to(&mut self, target: u32) -> Result<Option<u32>> {
+        if self.current.is_some_and(|id| id >= target) {
+            return Ok(self.current);
+        }
+        // The last skip point that no document at or after `target` comes
+        // before. Skip `k` is before document `(k + 1) * SKIP_INTERVAL`, so
+        // the points already passed are all before it.
+        let passed = (self.read / SKIP_INTERVAL) as usize;
+        let k = gallop(self.skips, passed, |s| s.last_id < target);
+        if k > 0 {
+            let skip = self.skips[k - 1];
+            let docs_before = k as u32 * SKIP_INTERVAL;
+            if docs_before > self.read && docs_before < self.doc_count {
+                self.rest = self
+                    .hits
+                    .get(skip.offset as usize..)
+                    .ok_or_else(|| Error::format("skip point out of bounds"))?;
+                self.read = docs_before;
+                self.id = skip.last_id;
+            }
+        }
+        while let Some(id) = self.next()? {
+            if id >= target {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Number of occurrences of the term in the document the cursor is on.
+    pub fn tf(&self) -> u32 {
+        self.tf
+    }
+
+    /// Index of the block that would hold the document with id `id`, in
+    /// the blocks of the entry.
+    pub fn block_of(&self, id: u32) -> usize {
+        self.skips.partition_point(|s| s.last_id < id)
+    }
+
+    /// Id of the last document of block `block`, `u32::MAX` for the last
+    /// block.
+    pub fn block_end(&self, block: usize) -> u32 {
+        self.skips.get(block).map_or(u32::MAX, |s| s.last_id)
+    }
+}
+
+/// Increasing document ids, each once, read one at a time.
+pub(crate) enum DocIds<'a> {
+    /// The documents of a term, read from its encoded hits.
+    Hits(Postings<'a>),
+    /// Ids found already, and how many of them were read.
+    Found(Vec<u32>, usize),
+}
+
+impl<'a> DocIds<'a> {
+    /// The documents of `hits`, the decompressed hits of `entry`, stored
+    /// with `positions` or not.
+    pub fn of_hits(
+        hits: &'a [u8],
+        entry: &'a Entry,
+        positions: bool,
+    ) -> DocIds<'a> {
+        DocIds::Hits(Postings::new(hits, entry, positions))
+    }
+
+    /// `ids`, which must be increasing.
+    pub fn found(ids: Vec<u32>) -> DocIds<'a> {
+        DocIds::Found(ids, 0)
+    }
+
+    /// The number of documents, read or not. Hits take at least two bytes
+    /// each, which bounds the count of corrupt ones, so that it can't make
+    /// us allocate a huge list.
+    fn len(&self) -> usize {
+        match self {
+            DocIds::Hits(postings) => {
+                (postings.doc_count as usize).min(postings.hits.len() / 2)
+            }
+            DocIds::Found(ids, _) => ids.len(),
+        }
+    }
+
+    /// Move to the next document, and return its id, or `None` past the
+    /// last one.
+    fn next(&mut self) -> Result<Option<u32>> {
+        match self {
+            DocIds::Hits(postings) => postings.next(),
+            DocIds::Found(ids, read) => {
+                // Past the last id, `read` is one more than their number.
+                let id = ids.get(*read).copied();
+                *read = (*read + 1).min(ids.len() + 1);
+                Ok(id)
+            }
+        }
+    }
+
+    /// Move to the first document with an id of at least `target`, unless
+    /// on one already, like `Postings::advance_to`.
+    fn advance_to(&mut self, target: u32) -> Result<Option<u32>> {
+        match self {
+            DocIds::Hits(postings) => postings.advance_to(target),
+            DocIds::Found(ids, read) => {
+                // The id the cursor is on stays if it is far enough.
+                let on = read.saturating_sub(1);
+                let at = match ids.len() {
+                    len if *read > len => len,
+                    _ => gallop(ids, on, |&id| id < target),
+                };
+                *read = at + 1;
+                Ok(ids.get(at).copied())
+            }
+        }
+    }
+
+    /// Read all the remaining ids.
+    pub fn collect(mut self) -> Result<Vec<u32>> {
+        let mut ids = Vec::with_capacity(self.len());
+        while let Some(id) = self.next()? {
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+}
+
+/// The index of the first of `items`, from `from` on, that isn't `before`
+/// the target, like `partition_point`: `items` must be partitioned by it.
+fn gallop<T>(items: &[T], from: usize, before: impl Fn(&T) -> bool) -> usize {
+    let mut start = from.min(items.len());
+    let mut step = 1;
+    while start + step <= items.len() && before(&items[start + step - 1]) {
+        start += step;
+        step *= 2;
+    }
+    let end = (start + step).min(items.len());
+    start + items[start..end].partition_point(before)
+}
+
+/// The ids of the documents found in all of `lists`, in increasing order.
+///
+/// The shortest list is walked document by document, and the others only
+/// read where it leads them, through their skip points.
+pub(crate) fn intersect(mut lists: Vec<DocIds>) -> Result<Vec<u32>> {
+    lists.sort_by_key(DocIds::len);
+    let mut ids = vec![];
+    let Some((first, others)) = lists.split_first_mut() else {
+        return Ok(ids);
+    };
+    let mut candidate = first.next()?;
+    'candidates: while let Some(id) = candidate {
+        for list in others.iter_mut() {
+            match list.advance_to(id)? {
+                None => break 'candidates,
+                Some(found) if found > id => {
+                    candidate = first.advance_to(found)?;
+                    continue 'candidates;
+                }
+                Some(_) => {}
+            }
+        }
+        ids.push(id);
+        candidate = first.next()?;
+    }
+    Ok(ids)
+}
+
+/// The ids of the documents found in any of `lists`, in increasing order.
+pub(crate) fn union(mut lists: Vec<DocIds>) -> Result<Vec<u32>> {
+    // A k-way merge: the heap holds the next id of every list that has one,
+    // smallest first.
+    let mut heap = BinaryHeap::with_capacity(lists.len());
+    for (i, list) in lists.iter_mut().enumerate() {
+        if let Some(id) = list.next()? {
+            heap.push(Reverse((id, i)));
+        }
+    }
+    let mut ids: Vec<u32> = vec![];
+    while let Some(Reverse((id, i))) = heap.pop() {
+        if ids.last() != Some(&id) {
+            ids.push(id);
+        }
+        if let Some(next) = lists[i].next()? {
+            heap.push(Reverse((next, i)));
+        }
+    }
+    Ok(ids)
+}
+
+/// The ids of the documents of `left` that aren't in `right`, in
+/// increasing order. `right` is only read where `left` leads it.
+pub(crate) fn difference(
+    mut left: DocIds,
+    mut right: DocIds,
+) -> Result<Vec<u32>> {
+    let mut ids = vec![];
+    let mut excluded = right.next()?;
+    while let Some(id) = left.next()? {
+        if excluded.is_some_and(|e| e < id) {
+            excluded = right.advance_to(id)?;
+        }
+        if excluded != Some(id) {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(ids: &[u32]) -> DocIds<'static> {
+        DocIds::found(ids.to_vec())
+    }
+
+    #[test]
+    fn found_ids_gallop_to_targets() {
+        let mut ids = found(&[1, 4, 9, 16, 25, 36]);
+        assert_eq!(ids.advance_to(0).unwrap(), Some(1));
+        assert_eq!(ids.advance_to(1).unwrap(), Some(1));
+        assert_eq!(ids.advance_to(10).unwrap(), Some(16));
+        assert_eq!(ids.next().unwrap(), Some(25));
+        assert_eq!(ids.advance_to(25).unwrap(), Some(25));
+        assert_eq!(ids.advance_to(37).unwrap(), None);
+        assert_eq!(ids.next().unwrap(), None);
+
+        for target in 0..40 {
+            let items: Vec<u32> = (0..20).map(|i| i * 2).collect();
+            let expected = items.partition_point(|&id| id < target);
+            for from in 0..=expected {
+                assert_eq!(gallop(&items, from, |&id| id < target), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn set_operations_combine_id_lists() {
+        let evens: Vec<u32> = (0..30).filter(|i| i % 2 == 0).collect();
+        let threes: Vec<u32> = (0..30).filter(|i| i % 3 == 0).collect();
+        let both = intersect(vec![found(&evens), found(&threes)]).unwrap();
+        assert_eq!(both, [0, 6, 12, 18, 24]);
+        let either = union(vec![found(&evens), found(&threes)]).unwrap();
+        let expected: Vec<u32> =
+            (0..30).filter(|i| i % 2 == 0 || i % 3 == 0).collect();
+        assert_eq!(either, expected);
+        let odd_threes = difference(found(&threes), found(&evens)).unwrap();
+        assert_eq!(odd_threes, [3, 9, 15, 21, 27]);
+
+        assert!(intersect(vec![found(&evens), found(&[])])
+            .unwrap()
+            .is_empty());
+        assert!(intersect(vec![]).unwrap().is_empty());
+        assert!(union(vec![]).unwrap().is_empty());
+        assert_eq!(difference(found(&[1, 2]), found(&[])).unwrap(), [1, 2]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Reading inputs ahead of the threads that use them.
+//!
+//! `prefetch` loads inputs on threads of its own, in the order they come,
+//! and hands them out in that same order, whichever thread finished first.
+//! Loaders stay at most `read_ahead` inputs ahead of what was handed out, so
+//! that a slow consumer doesn't leave every input loaded in memory. The
+//! indexer uses it to read, decode and hash files while other threads
+//! tokenize those read before (see `Indexer::io_threads`).
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Load every one of `inputs` with `load`, on `threads` threads, and pass
+/// what they loaded, in the order of `inputs`, to `consume`. Returns what
+/// `consume` returns; inputs it leaves unread are not all loaded.
+pub(crate) fn prefetch<'a, I, T, R>(
+    inputs: &'a [I],
+    threads: usize,
+    read_ahead: usize,
+    load: impl Fn(&'a I) -> T + Sync,
+    consume: impl FnOnce(&mut dyn Iterator<Item = T>) -> R,
+) -> R
+where
+    I: Sync,
+    T: Send,
+{
+    let threads = threads.max(1);
+    let shared = Shared {
+        state: Mutex::new(State {
+            next: 0,
+            taken: 0,
+            loaded: BTreeMap::new(),
+            loaders: threads,
+            stop: false,
+        }),
+        changed: Condvar::new(),
+    };
+    let read_ahead = read_ahead.max(1);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| load_inputs(inputs, read_ahead, &load, &shared));
+        }
+        // Stops the loaders when dropped, even if `consume` panics.
+        let mut loaded = Loaded {
+            shared: &shared,
+            len: inputs.len(),
+        };
+        consume(&mut loaded)
+    })
+}
+
+/// Where loaders and the consumer meet.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Notified whenever `state` changes.
+    changed: Condvar,
+}
+
+struct State<T> {
+    /// Position of the next input to load.
+    next: usize,
+    /// Number of inputs handed out to the consumer.
+    taken: usize,
+    /// Inputs loaded but not handed out yet, by position.
+    loaded: BTreeMap<usize, T>,
+    /// Number of loaders still running.
+    loaders: usize,
+    /// Set once the consumer is done, possibly before the last input.
+    stop: bool,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // A loader that panicked holds no invariant half-updated.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The body of a loader thread: load inputs, in order, until there are
+/// none left or the consumer is done.
+fn load_inputs<'a, I, T>(
+    inputs: &'a [I],
+    read_ahead: usize,
+    load: &impl Fn(&'a I) -> T,
+    shared: &Shared<T>,
+) {
+    let _running = Loader(shared);
+    loop {
+        let position = {
+            let mut state = shared.lock();
+            while !state.stop
+                && state.next < inputs.len()
+                && state.next >= state.taken + read_ahead
+            {
+                state = shared
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+            if state.stop || state.next >= inputs.len() {
+                return;
+            }
+            state.next += 1;
+            state.next - 1
+        };
+        let item = load(&inputs[position]);
+        shared.lock().loaded.insert(position, item);
+        shared.changed.notify_all();
+    }
+}
+
+/// Counts a loader out when it returns or panics.
+struct Loader<'a, T>(&'a Shared<T>);
+
+impl<T> Drop for Loader<'_, T> {
+    fn drop(&mut self) {
+        self.0.lock().loaders -= 1;
+        self.0.changed.notify_all();
+    }
+}
+
+/// The loaded inputs, in order, as the consumer sees them.
+struct Loaded<'a, T> {
+    shared: &'a Shared<T>,
+    len: usize,
+}
+
+impl<T> Iterator for Loaded<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let mut state = self.shared.lock();
+        while state.taken < self.len {
+            let position = state.taken;
+            if let Some(item) = state.loaded.remove(&position) {
+                state.taken += 1;
+                self.shared.changed.notify_all();
+                return Some(item);
+            }
+            if state.loaders == 0 {
+                // A loader panicked; the scope passes the panic on.
+                return None;
+            }
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        None
+    }
+}
+
+impl<T> Drop for Loaded<'_, T> {
+    fn drop(&mut self) {
+        self.shared.lock().stop = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn inputs_come_in_order_and_no_further_ahead_than_asked() {
+        let inputs: Vec<usize> = (0..200).collect();
+        let started = AtomicUsize::new(0);
+        let load = |&i: &usize| {
+            started.fetch_add(1, Ordering::SeqCst);
+            // Later inputs often finish first.
+            thread::sleep(Duration::from_micros(((i * 7) % 5) as u64 * 50));
+            i * 2
+        };
+        let loaded = prefetch(&inputs, 4, 8, load, |loaded| {
+            let mut out = vec![];
+            for (n, item) in loaded.enumerate() {
+                // The loaders wait for the consumer past the read-ahead.
+                assert!(started.load(Ordering::SeqCst) <= n + 1 + 8);
+                out.push(item);
+            }
+            out
+        });
+        assert_eq!(loaded, inputs.iter().map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stopping_early_leaves_the_rest_unloaded() {
+        let inputs: Vec<usize> = (0..1000).collect();
+        let started = AtomicUsize::new(0);
+        let load = |&i: &usize| {
+            started.fetch_add(1, Ordering::SeqCst);
+            i
+        };
+        let first: Vec<usize> =
+            prefetch(&inputs, 3, 4, load, |loaded| loaded.take(10).collect());
+        assert_eq!(first, (0..10).collect::<Vec<_>>());
+        assert!(started.load(Ordering::SeqCst) <= 10 + 4);
+    }
+}
@@ -0,0 +1,761 @@
+//! Boolean queries over a parsed index.
+//!
+//! A query is a small expression language over terms:
+//!
+//! ```text
+//! rust AND (async OR tokio) NOT blocking
+//! ```
+//!
+//! `AND`, `OR` and `NOT` are the operators (they must be written in upper
+//! case, otherwise they are ordinary terms), parentheses group
+//! sub-expressions, and two terms written next to each other are implicitly
+//! joined with `AND`. `a NOT b` means "documents containing `a` but not `b`".
+//! Words in double quotes, like `"inverted index"`, form a phrase that must
+//! appear exactly in that order. `a NEAR/5 b` matches documents where `a` and
+//! `b` occur within 5 words of each other, in either order. A word or phrase
+//! prefixed with a field name, like `title:rust` or `path:"2023 reports"`, is
+//! searched in that field instead of the body. `lang:french` matches the
+//! documents that were detected to be in French (see the `language` module),
+//! and filters like `mtime:>2023-01-01` or `size:<1MB` the documents whose
+//! file was modified after that day, or is smaller than that (see the
+//! `filter` module).
+//!
+//! A word followed by a tilde is fuzzy: `colour~1` matches every term at most
+//! one edit (an inserted, deleted or replaced letter) away from `colour`, and
+//! a bare `colour~` allows two. A phrase followed by a tilde and a number,
+//! like `"index inverted"~2`, is a proximity search: its words may appear in
+//! any order, spread over at most 2 more words than the phrase itself.
+//!
+//! `Query::parse` turns a string into a `Query` tree, and `Query::evaluate`
+//! runs it against a `ParsedIndex`, producing a single set of documents.
+//! `IndexFileSearcher::evaluate` runs it against an index file instead,
+//! reading only the hits of its terms.
+//! Queries that can't be parsed are reported as a `SyntaxError`, which tells
+//! where in the query the problem is. A `Query` displays as an expression
+//! that shows how it was parsed.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{Analyzer, TextAnalyzer};
+use crate::field::Field;
+use crate::filter::{self, Attribute};
+use crate::index::{Doc, ParsedIndex};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Edit distance of a fuzzy word written without a number, like `colour~`.
+pub const DEFAULT_FUZZY_DISTANCE: u32 = 2;
+
+/// A parsed boolean query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Documents containing this (lowercased) term.
+    Term(String),
+    /// Documents containing these terms, each at the given word offset
+    /// relative to the start of the phrase.
+    Phrase(Vec<(String, u32)>),
+    /// Documents where the two terms occur within the given number of words
+    /// of each other.
+    Near(String, String, u32),
+    /// Documents containing a term of the same field at most the given
+    /// number of edits away from this one.
+    Fuzzy(String, u32),
+    /// Documents where all these terms occur, in any order, with at most the
+    /// given number of words between the first and the last of them.
+    Proximity(Vec<String>, u32),
+    /// Documents matching both sides.
+    And(Box<Query>, Box<Query>),
+    /// Documents matching either side.
+    Or(Box<Query>, Box<Query>),
+    /// Documents not matching the inner query.
+    Not(Box<Query>),
+    /// Documents in this language, like "french".
+    Language(String),
+    /// Documents whose value of the attribute is in the range.
+    Range(Attribute, RangeInclusive<u64>),
+}
+
+/// The query as an expression in prefix notation, showing how it was
+/// parsed: how operators were grouped and which terms the analyzer turned
+/// the words into, like `(AND rust (NOT block))`. Phrase words are followed
+/// by their offsets in the phrase, proximity searches by how far apart their
+/// first and last terms may be, and ranges of attributes have their bounds as
+/// numbers, with open ends left out.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Query::Term(term) => write!(f, "{}", term),
+            Query::Phrase(terms) => {
+                write!(f, "(PHRASE")?;
+                for (term, offset) in terms {
+                    write!(f, " {}@{}", term, offset)?;
+                }
+                write!(f, ")")
+            }
+            Query::Near(left, right, distance) => {
+                write!(f, "(NEAR/{} {} {})", distance, left, right)
+            }
+            Query::Fuzzy(term, distance) => {
+                write!(f, "(FUZZY/{} {})", distance, term)
+            }
+            Query::Proximity(terms, span) => {
+                write!(f, "(WITHIN/{} {})", span, terms.join(" "))
+            }
+            Query::And(left, right) => write!(f, "(AND {} {})", left, right),
+            Query::Or(left, right) => write!(f, "(OR {} {})", left, right),
+            Query::Not(inner) => write!(f, "(NOT {})", inner),
+            Query::Language(language) => write!(f, "lang:{}", language),
+            Query::Range(attribute, range) => {
+                write!(f, "{}:", attribute.name())?;
+                if *range.start() > 0 {
+                    write!(f, "{}", range.start())?;
+                }
+                write!(f, "..")?;
+                if *range.end() < u64::MAX {
+                    write!(f, "={}", range.end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Why a query couldn't be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("query syntax error at position {position}: {message}")]
+pub struct SyntaxError {
+    pub message: String,
+    /// Where the problem is, in characters from the start of the query
+    /// (counting from 0). The length of the query for problems at its end.
+    pub position: usize,
+}
+
+impl SyntaxError {
+    pub(crate) fn new(
+        message: impl Into<String>,
+        position: usize,
+    ) -> SyntaxError {
+        SyntaxError {
+            message: message.into(),
+            position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    /// The words between the quotes, with the field prefix if there was
+    /// one, and the number after a `~` that follows the phrase.
+    Phrase(String, Option<u32>),
+    And,
+    Or,
+    Not,
+    Near(u32),
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "word `{}`", word),
+            Token::Phrase(..) => write!(f, "phrase"),
+            Token::And => write!(f, "`AND`"),
+            Token::Or => write!(f, "`OR`"),
+            Token::Not => write!(f, "`NOT`"),
+            Token::Near(distance) => write!(f, "`NEAR/{}`", distance),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+        }
+    }
+}
+
+/// Split a query string into tokens, each with the position of its first
+/// character. Operators are recognized only when written in upper case.
+/// Words are separated by whitespace and kept as written, punctuation
+/// included; the parser runs them through the analyzer, so they are split
+/// exactly like the indexed text was.
+fn lex(input: &str) -> Result<Vec<(Token, usize)>, SyntaxError> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let mut start = 0;
+
+    let flush = |word: &mut String, start: usize, tokens: &mut Vec<_>| {
+        if word.is_empty() {
+            return;
+        }
+        let token = match word.as_str() {
+            "AND" => Token::And,
+            "OR" => Token::Or,
+            "NOT" => Token::Not,
+            _ => match word.strip_prefix("NEAR/").map(str::parse) {
+                Some(Ok(distance)) => Token::Near(distance),
+                _ => Token::Word(word.clone()),
+            },
+        };
+        tokens.push((token, start));
+        word.clear();
+    };
+
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => {
+                // A field prefix, as in `title:"..."`, stays with the phrase.
+                let (mut phrase, phrase_start) = match word.strip_suffix(':') {
+                    Some(prefix) if Field::from_name(prefix).is_some() => {
+                        (std::mem::take(&mut word), start)
+                    }
+                    _ => (String::new(), i),
+                };
+                flush(&mut word, start, &mut tokens);
+                let mut closed = false;
+                for (_, c) in chars.by_ref() {
+                    if c == '"' {
+                        closed = true;
+                        break;
+                    }
+                    phrase.push(c);
+                }
+                if !closed {
+                    return Err(SyntaxError::new("unclosed quote", i));
+                }
+                let mut slop = None;
+                if let Some((tilde, _)) = chars.next_if(|&(_, c)| c == '~') {
+                    let mut digits = String::new();
+                    while let Some((_, c)) =
+                        chars.next_if(|(_, c)| c.is_ascii_digit())
+                    {
+                        digits.push(c);
+                    }
+                    slop = Some(digits.parse().map_err(|_| {
+                        SyntaxError::new(
+                            "expected a number of words",
+                            tilde + 1,
+                        )
+                    })?);
+                }
+                tokens.push((Token::Phrase(phrase, slop), phrase_start));
+            }
+            '(' | ')' => {
+                flush(&mut word, start, &mut tokens);
+                let token = if ch == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                };
+                tokens.push((token, i));
+            }
+            c if c.is_whitespace() => flush(&mut word, start, &mut tokens),
+            c => {
+                if word.is_empty() {
+                    start = i;
+                }
+                word.push(c);
+            }
+        }
+    }
+    flush(&mut word, start, &mut tokens);
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the token stream.
+///
+/// ```text
+/// or    := and ("OR" and)*
+/// and   := near (["AND"] near | "NOT" near)*
+/// near  := unary ("NEAR/" number unary)*
+/// unary := "NOT" unary | "(" or ")" | "lang:" word | filter
+///        | word ["~" [number]] | phrase ["~" number]
+/// ```
+///
+/// Every rule yields `None` when all of its words were dropped by the
+/// analyzer (stop words, for instance); such sub-expressions are simply left
+/// out of the query.
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Length of the query, in characters.
+    end: usize,
+    analyzer: &'a dyn Analyzer,
+}
+
+type Parsed = Result<Option<Query>, SyntaxError>;
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    /// Where the next token starts.
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |&(_, at)| at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Parsed {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            let right = self.parse_and()?;
+            left = combine(left, right, Query::Or);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Parsed {
+        let mut left = self.parse_near()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                    let right = self.parse_near()?;
+                    left = combine(left, right, Query::And);
+                }
+                Some(Token::Not) => {
+                    self.next();
+                    let right = self.parse_near()?;
+                    let right = right.map(|q| Query::Not(Box::new(q)));
+                    left = combine(left, right, Query::And);
+                }
+                Some(Token::Word(_))
+                | Some(Token::Phrase(..))
+                | Some(Token::LParen) => {
+                    let right = self.parse_near()?;
+                    left = combine(left, right, Query::And);
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn parse_near(&mut self) -> Parsed {
+        let mut left = self.parse_unary()?;
+        while let Some(&Token::Near(distance)) = self.peek() {
+            let at = self.position();
+            self.next();
+            let right = self.parse_unary()?;
+            left = match (left, right) {
+                (Some(Query::Term(l)), Some(Query::Term(r))) => {
+                    Some(Query::Near(l, r, distance))
+                }
+                (Some(_), Some(_)) => {
+                    return Err(SyntaxError::new(
+                        "NEAR can only join single words",
+                        at,
+                    ))
+                }
+                (l, r) => l.or(r),
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Parsed {
+        let at = self.position();
+        match self.next() {
+            Some(Token::Not) => {
+                Ok(self.parse_unary()?.map(|q| Query::Not(Box::new(q))))
+            }
+            Some(Token::LParen) => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err(SyntaxError::new("unclosed parenthesis", at)),
+                }
+            }
+            Some(Token::Word(text)) if text.starts_with("lang:") => {
+                match text[5..].to_lowercase() {
+                    language if language.is_empty() => Err(SyntaxError::new(
+                        "expected a language after `lang:`",
+                        at,
+                    )),
+                    language => Ok(Some(Query::Language(language))),
+                }
+            }
+            Some(Token::Word(text)) if filter::is_filter(&text) => {
+                filter::parse(&text, at).map(Some)
+            }
+            Some(Token::Word(text)) => match fuzzy(&text, at)? {
+                Some((word, distance)) => {
+                    let (field, word) = Field::split(word);
+                    let mut terms = self.terms(field, word);
+                    match terms.len() {
+                        0 => Ok(None),
+                        1 => {
+                            let term = terms.remove(0).0;
+                            Ok(Some(Query::Fuzzy(term, distance)))
+                        }
+                        _ => Err(SyntaxError::new(
+                            "only single words can be fuzzy",
+                            at,
+                        )),
+                    }
+                }
+                None => {
+                    let (field, text) = Field::split(&text);
+                    Ok(phrase(self.terms(field, text)))
+                }
+            },
+            Some(Token::Phrase(text, slop)) => {
+                let (field, text) = Field::split(&text);
+                let terms = self.terms(field, text);
+                match slop {
+                    Some(slop) if terms.len() > 1 => {
+                        let span = terms.last().map_or(0, |(_, p)| *p);
+                        let terms = terms.into_iter().map(|(t, _)| t);
+                        Ok(Some(Query::Proximity(terms.collect(), span + slop)))
+                    }
+                    _ => Ok(phrase(terms)),
+                }
+            }
+            Some(token) => {
+                Err(SyntaxError::new(format!("unexpected {}", token), at))
+            }
+            None => Err(SyntaxError::new("unexpected end of query", at)),
+        }
+    }
+
+    /// Analyze `text` into the terms of `field`, each with its word offset
+    /// from the first one.
+    fn terms(&self, field: Field, text: &str) -> Vec<(String, u32)> {
+        let tokens = self.analyzer.analyze(text);
+        let start = tokens.first().map_or(0, |t| t.position);
+        tokens
+            .into_iter()
+            .map(|t| (field.term(&t.text), t.position - start))
+            .collect()
+    }
+}
+
+/// Split a fuzzy word like `colour~1` into the word and the edit distance.
+/// Returns `None` for words without a tilde at the end.
+fn fuzzy(text: &str, at: usize) -> Result<Option<(&str, u32)>, SyntaxError> {
+    let Some((word, distance)) = text.rsplit_once('~') else {
+        return Ok(None);
+    };
+    if word.is_empty() || !distance.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    if distance.is_empty() {
+        return Ok(Some((word, DEFAULT_FUZZY_DISTANCE)));
+    }
+    let tilde = at + word.chars().count();
+    match distance.parse() {
+        Ok(distance) => Ok(Some((word, distance))),
+        Err(_) => Err(SyntaxError::new("edit distance is too large", tilde)),
+    }
+}
+
+/// The query for the analyzed words of a word or phrase.
+fn phrase(mut terms: Vec<(String, u32)>) -> Option<Query> {
+    match terms.len() {
+        0 => None,
+        1 => Some(Query::Term(terms.remove(0).0)),
+        _ => Some(Query::Phrase(terms)),
+    }
+}
+
+/// Join two optional sub-queries with a binary operator, keeping whichever
+/// side is present if the other one is empty.
+fn combine(
+    left: Option<Query>,
+    right: Option<Query>,
+    op: fn(Box<Query>, Box<Query>) -> Query,
+) -> Option<Query> {
+    match (left, right) {
+        (Some(l), Some(r)) => Some(op(Box::new(l), Box::new(r))),
+        (l, r) => l.or(r),
+    }
+}
+
+impl Query {
+    /// Parse a query string, analyzing words with the default analyzer.
+    pub fn parse(input: &str) -> Result<Query, SyntaxError> {
+        Self::parse_with(input, &TextAnalyzer::default())
+    }
+
+    /// Parse a query string, analyzing words with `analyzer`. This must be
+    /// the analyzer the index was built with, or terms won't match.
+    pub fn parse_with(
+        input: &str,
+        analyzer: &dyn Analyzer,
+    ) -> Result<Query, SyntaxError> {
+        let mut parser = Parser {
+            tokens: lex(input)?,
+            pos: 0,
+            end: input.chars().count(),
+            analyzer,
+        };
+        let query = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            let message = match token {
+                Token::RParen => "unmatched `)`".to_string(),
+                token => format!("unexpected {}", token),
+            };
+            return Err(SyntaxError::new(message, parser.position()));
+        }
+        query
+            .ok_or_else(|| SyntaxError::new("query has no searchable terms", 0))
+    }
+
+    /// The terms that contribute to a match, i.e. every term that is not
+    /// under a `NOT`. These are the terms used for ranking. Fuzzy terms are
+    /// included as written; see `terms_in`.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = vec![];
+        self.collect_terms(None, &mut terms);
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+
+    /// Like `terms`, but with every fuzzy term replaced by the terms of
+    /// `index` it matches.
+    pub fn terms_in(&self, index: &ParsedIndex) -> Vec<String> {
+        let mut terms = vec![];
+        self.collect_terms(Some(index), &mut terms);
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+
+    fn collect_terms(
+        &self,
+        index: Option<&ParsedIndex>,
+        out: &mut Vec<String>,
+    ) {
+        match self {
+            Query::Term(term) => out.push(term.clone()),
+            Query::Phrase(terms) => {
+                out.extend(terms.iter().map(|(t, _)| t.clone()))
+            }
+            Query::Near(left, right, _) => {
+                out.push(left.clone());
+                out.push(right.clone());
+            }
+            Query::Fuzzy(term, distance) => match index {
+                Some(index) => out.extend(
+                    index
+                        .fuzzy_terms(term, *distance)
+                        .into_iter()
+                        .map(String::from),
+                ),
+                None => out.push(term.clone()),
+            },
+            Query::Proximity(terms, _) => out.extend(terms.iter().cloned()),
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.collect_terms(index, out);
+                right.collect_terms(index, out);
+            }
+            Query::Not(_) | Query::Language(_) | Query::Range(..) => {}
+        }
+    }
+
+    /// Evaluate the query against `index`, returning the set of matching
+    /// documents.
+    pub fn evaluate(&self, index: &ParsedIndex) -> HashSet<Doc> {
+        match self {
+            Query::Term(term) => match index.map.get(term) {
+                Some(entry) => entry.keys().cloned().collect(),
+                None => HashSet::new(),
+            },
+            Query::Phrase(terms) => {
+                let terms: Vec<(&str, u32)> =
+                    terms.iter().map(|(t, p)| (t.as_str(), *p)).collect();
+                index.phrase_search_at(&terms).into_iter().collect()
+            }
+            Query::Near(left, right, distance) => index
+                .near_search(left, right, *distance)
+                .into_iter()
+                .collect(),
+            Query::Fuzzy(term, distance) => index
+                .fuzzy_terms(term, *distance)
+                .into_iter()
+                .flat_map(|term| index.map[term].keys().cloned())
+                .collect(),
+            Query::Proximity(terms, distance) => {
+                let terms: Vec<&str> =
+                    terms.iter().map(String::as_str).collect();
+                index
+                    .proximity_search(&terms, *distance)
+                    .into_iter()
+                    .collect()
+            }
+            Query::And(left, right) => {
+                let left = left.evaluate(index);
+                let right = right.evaluate(index);
+                left.intersection(&right).cloned().collect()
+            }
+            Query::Or(left, right) => {
+                let mut left = left.evaluate(index);
+                left.extend(right.evaluate(index));
+                left
+            }
+            Query::Not(inner) => {
+                let excluded = inner.evaluate(index);
+                index
+                    .all_docs()
+                    .into_iter()
+                    .filter(|doc| !excluded.contains(doc))
+                    .collect()
+            }
+            Query::Language(language) => index
+                .docs
+                .iter()
+                .filter(|(_, info)| &info.language == language)
+                .map(|(doc, _)| doc.clone())
+                .collect(),
+            Query::Range(attribute, range) => index
+                .docs
+                .iter()
+                .filter(|(_, info)| range.contains(&attribute.value_of(info)))
+                .map(|(doc, _)| doc.clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// The index of `texts`, the document `i` having the hash `[i; 32]`.
+    fn index_of(texts: &[&str]) -> ParsedIndex {
+        fixtures::index_of(0, texts).to_parsed()
+    }
+
+    /// The numbers of the documents of `index` matching `query`, in order.
+    fn matches(index: &ParsedIndex, query: &str) -> Vec<u8> {
+        let mut found: Vec<u8> = Query::parse(query)
+            .unwrap()
+            .evaluate(index)
+            .iter()
+            .map(|doc| doc.hash[0])
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn animals() -> ParsedIndex {
+        index_of(&[
+            "the quick brown fox",
+            "the lazy brown dog",
+            "a quick dog jumps over the fox",
+        ])
+    }
+
+    #[test]
+    fn boolean_queries_combine_documents() {
+        let index = animals();
+        for (query, expected) in [
+            ("quick AND fox", &[0, 2][..]),
+            ("quick fox", &[0, 2]),
+            ("lazy OR jumps", &[1, 2]),
+            ("brown AND (fox OR dog)", &[0, 1]),
+            ("the NOT fox", &[1]),
+            ("brown NOT (fox OR cat)", &[1]),
+            ("NOT quick", &[1]),
+            ("quick AND cat", &[]),
+            ("cat OR bird", &[]),
+        ] {
+            assert_eq!(matches(&index, query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn phrases_need_their_words_in_order_and_adjacent() {
+        let index = animals();
+        for (query, expected) in [
+            ("\"brown fox\"", &[0][..]),
+            ("\"the quick brown\"", &[0]),
+            ("\"quick dog\"", &[2]),
+            // Both words are in documents 0 and 2, but not next to each other,
+            // or not in this order.
+            ("\"quick fox\"", &[]),
+            ("\"fox brown\"", &[]),
+            ("\"brown fox\" OR \"brown dog\"", &[0, 1]),
+            ("\"brown cat\"", &[]),
+        ] {
+            assert_eq!(matches(&index, query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn near_matches_terms_within_the_distance_in_either_order() {
+        let index = animals();
+        for (query, expected) in [
+            ("quick NEAR/1 dog", &[2][..]),
+            ("quick NEAR/1 fox", &[]),
+            ("quick NEAR/2 fox", &[0]),
+            ("fox NEAR/2 quick", &[0]),
+            ("quick NEAR/5 fox", &[0, 2]),
+            ("brown NEAR/1 dog lazy", &[1]),
+            ("quick NEAR/5 cat", &[]),
+        ] {
+            assert_eq!(matches(&index, query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_problem() {
+        for (query, position, message) in [
+            ("(cat", 0, "unclosed parenthesis"),
+            ("cat AND", 7, "unexpected end of query"),
+            ("NOT", 3, "unexpected end of query"),
+            ("\"", 0, "unclosed quote"),
+            ("  \"cat", 2, "unclosed quote"),
+            ("cat)", 3, "unmatched `)`"),
+            ("()", 1, "unexpected `)`"),
+            ("cat AND OR dog", 8, "unexpected `OR`"),
+            ("size:<abc", 6, "expected a size like 10KB"),
+            // In characters, not bytes.
+            ("café AND (x", 9, "unclosed parenthesis"),
+        ] {
+            let e = Query::parse(query).unwrap_err();
+            let found = (e.position, e.message.as_str());
+            assert_eq!(found, (position, message), "{}", query);
+        }
+    }
+
+    #[test]
+    fn fuzzy_words_match_terms_a_few_edits_away() {
+        let index = animals();
+        for (query, expected) in [
+            ("quik~1", &[0, 2][..]),
+            ("quik", &[]),
+            ("dgo~1", &[]),
+            ("dgo~2", &[1, 2]),
+            ("lazzy~ fox", &[]),
+            ("lazzy~ dog", &[1]),
+        ] {
+            assert_eq!(matches(&index, query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn queries_display_how_they_were_parsed() {
+        for (query, parsed) in [
+            (
+                "Rust AND (async OR tokio) NOT blocking",
+                "(AND (AND rust (OR async tokio)) (NOT blocking))",
+            ),
+            ("title:\"Inverted index\" colour~1", "(AND (PHRASE title:inverted@0 title:index@1) (FUZZY/1 colour))"),
+            ("a NEAR/3 b lang:french", "(AND (NEAR/3 a b) lang:french)"),
+            ("size:<1KB \"a b\"~2", "(AND size:..=1023 (WITHIN/3 a b))"),
+        ] {
+            assert_eq!(Query::parse(query).unwrap().to_string(), parsed);
+        }
+    }
+}
@@ -2,18 +2,34 @@
 //! index files.
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use log::debug;
 use std::collections::HashMap;
-use std::fs::{self, File};
+use std::fs;
 use std::io::prelude::*;
 use std::io::{self, BufReader, SeekFrom};
 use std::path::Path;
 
 // ───── Current Crate Imports ────────────────────────────────────────────── //
 
-use crate::index::{Doc, Hit, Offsets};
-use crate::prelude::{InMemoryIndex, ParsedIndex};
-use crate::write::IndexFileWriter;
-use crate::HASH_LENGTH;
+use crate::bloom::{footer_start, BloomFilter};
+use crate::codec::Codec;
+use crate::crypt::IndexFile;
+use crate::dictionary::{entries_len, EntryDecoder, TermDictionary};
+use crate::error::{Error, Result};
+use crate::field::Field;
+use crate::header::{read_header, IndexSettings};
+use crate::identity::DocIdentity;
+use crate::index::{
+    fuzzy_matches, is_twin_term, Doc, DocEntry, DocInfo, DocTable, Offsets,
+    MAX_DOCS,
+};
+use crate::postings::{self, DocIds};
+use crate::prelude::ParsedIndex;
+use crate::query::Query;
+use crate::score::CorpusStats;
+use crate::stats::{CollectionStats, TermStats};
+use crate::tombstone::Tombstones;
+use crate::varint;
 
 // ───── Body ─────────────────────────────────────────────────────────────── //
 
@@ -30,15 +46,26 @@ pub struct IndexFileReader {
     /// We have two readers. The index data is most of the file. There's also a
     /// table of contents, stored separately at the end. We have to read them
     /// in tandem, so we open the file twice.
-    data: BufReader<File>,
+    data: BufReader<IndexFile>,
     /// Reader that reads the table of contents. (Since this table is stored at
     /// the end of the file, we have to begin by `seek`ing to it; see the code
-    /// in `IndexFileReader::open_and_delete`).
-    table_of_contents: BufReader<File>,
+    /// in `IndexFileReader::open_and_delete`). It stops where the entries do.
+    table_of_contents: io::Take<BufReader<IndexFile>>,
+    /// Decodes the front-coded entries of the table of contents.
+    decoder: EntryDecoder,
     /// The next entry in the table of contents, if any; or `None` if we've
     /// reached the end of the table. `IndexFileReader` always reads ahead one
     /// entry in the contents and stores it here.
     next: Option<Entry>,
+    /// The document table, read in full when the file is opened.
+    docs: DocTable,
+    /// The settings from the file header.
+    settings: IndexSettings,
+    /// End of the term data, which is where the document table starts.
+    data_end: u64,
+    /// The most bytes the hits of a term can take decompressed (see
+    /// `hits_limit`).
+    hits_limit: usize,
 }
 
 /// An entry in the table of contents of an index file.
@@ -54,147 +81,175 @@ pub struct Entry {
     pub term: String,
     /// Total number of documents in the corpus that contain this term.
     pub doc_count: u32,
+    /// Number of times the term occurs in all of them.
+    pub occurrences: u64,
     /// Offset of the index data for this term from the beginning of the file,
     /// in bytes.
     pub offset: u64,
     /// Length of the index data for this term, in bytes.
     pub nbytes: u64,
+    /// Where reading the hits can start other than at the beginning.
+    pub(crate) skips: Vec<Skip>,
+    /// What bounds the score of the documents of each block of the hits,
+    /// one more than there are skip points: block `k` ends right before
+    /// skip point `k`.
+    pub(crate) blocks: Vec<BlockMax>,
+}
+
+/// A point in the hits of a term where reading can start, recorded in its
+/// table of contents entry before every `SKIP_INTERVAL`th document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Skip {
+    /// Id of the document right before the point, which the id of the
+    /// document after it is stored relative to.
+    pub last_id: u32,
+    /// Position of the point in the decompressed hits, in bytes.
+    pub offset: u32,
+}
+
+/// The most a term can score in a block of its hits: the highest number of
+/// times it occurs in one of the block's documents, and the shortest length
+/// of its field among them. Scores grow with the first and shrink with the
+/// second, so scoring both together bounds the score of every document of
+/// the block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct BlockMax {
+    pub max_tf: u32,
+    pub min_len: u32,
 }
 
 impl IndexFileReader {
     /// Open an index file to read it from beginning to end.
     ///
-    /// This deletes the file, which may not work properly on Windows. Patches
-    /// welcome! On Unix, it works like this: the file immediately disappears
-    /// from its directory, but it'll still take up space on disk until the
-    /// file is closed, which normally happens when the `IndexFileReader` is
-    /// dropped.
+    /// This deletes the file, which only works on Unix: the file immediately
+    /// disappears from its directory, but it'll still take up space on disk
+    /// until the file is closed, which normally happens when the
+    /// `IndexFileReader` is dropped. Windows refuses to delete open files, so
+    /// portable code should use `open` and delete the file after dropping the
+    /// reader, which is what the merge does.
     pub fn open_and_delete<P: AsRef<Path>>(
         filename: P,
-    ) -> io::Result<IndexFileReader> {
+    ) -> Result<IndexFileReader> {
+        let filename = filename.as_ref();
+        let reader = IndexFileReader::open(filename)?;
+
+        debug!("Removing file: {}", filename.display());
+        fs::remove_file(filename)?; // YOLO
+
+        Ok(reader)
+    }
+
+    /// Open an index file to read it from beginning to end, leaving the file
+    /// in place.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<IndexFileReader> {
         let filename = filename.as_ref();
-        let mut data_raw = File::open(filename)?;
+        let mut data_raw = IndexFile::open(filename)?;
 
         // Read the file header.
-        let table_contents_offset = data_raw.read_u64::<LittleEndian>()?;
-        println!(
+        let header = read_header(&mut data_raw)?;
+        header.check_len(data_raw.len())?;
+        debug!(
             "Opened {}, table of contents starts at {}",
             filename.display(),
-            table_contents_offset
+            header.table_contents_offset
         );
 
-        // Open again so we have two read heads;
-        // move the contents read head to its starting position.
+        // Open again so we have two read heads. The document table comes
+        // right before the table of contents, so reading it leaves the
+        // contents read head at its starting position.
         // Set up buffering.
-        let mut table_contents_raw = File::open(filename)?;
-        table_contents_raw.seek(SeekFrom::Start(table_contents_offset))?;
         let data = BufReader::new(data_raw);
-        let mut table = BufReader::new(table_contents_raw);
+        let mut table = BufReader::new(IndexFile::open(filename)?);
+        let docs = read_doc_table(
+            &mut table,
+            header.doc_table_offset,
+            header.table_contents_offset,
+            header.settings.identity,
+        )?;
 
         // We always read ahead one entry, so load the first entry right away.
-        let first = IndexFileReader::read_entry(&mut table)?;
-
-        println!("Removing file: {}", filename.display());
-        fs::remove_file(filename)?; // YOLO
+        let data_end = header.doc_table_offset;
+        let table_end = footer_start(&mut table, &header.settings)?;
+        let len =
+            entries_len(&mut table, header.table_contents_offset, table_end)?;
+        let mut table = table.take(len);
+        let mut decoder = EntryDecoder::default();
+        let first = decoder.read(&mut table)?;
+        if let Some(ref e) = first {
+            e.check_bounds(data_end)?;
+        }
 
+        let hits_limit = hits_limit(&docs, !header.settings.omit_positions);
         Ok(IndexFileReader {
             data,
             table_of_contents: table,
+            decoder,
             next: first,
+            docs,
+            settings: header.settings,
+            data_end,
+            hits_limit,
         })
     }
 
     /// Read and parse index from binary file to a user-friendly format.
+    ///
+    /// Documents deleted through the index's tombstones are left out, and so
+    /// are terms that only occurred in deleted documents.
     pub fn get_index_from_file<P: AsRef<Path>>(
         filename: P,
-    ) -> io::Result<ParsedIndex> {
+    ) -> Result<ParsedIndex> {
         let filename = filename.as_ref();
-        let mut f = File::open(filename)?;
-
-        // Read the file header.
-        let table_contents_offset = f.read_u64::<LittleEndian>()?;
-        println!(
-            "Opened {}, table of contents starts at {}",
-            filename.display(),
-            table_contents_offset
-        );
-
-        // Open again so we have two read heads;
-        // move the contents read head to its starting position.
-        // Set up buffering.
-        let mut table_contents_raw = File::open(filename)?;
-        table_contents_raw.seek(SeekFrom::Start(table_contents_offset))?;
-
-        // Data - reader over beginning of the index, 8 bytes skipped (u64 with
-        // offset information).
-        // Table - table of contents section.
-        let mut data = BufReader::new(f);
-        let mut table = BufReader::new(table_contents_raw);
+        let tombstones = Tombstones::load(filename)?;
+        let mut reader = IndexFileReader::open(filename)?;
 
         // It will be our `HashMap` with term : DocEntry pairs.
         let mut map = HashMap::new();
         let mut word_count = 0;
 
-        loop {
-            // Offset from beginning of the binary file.
-            let offset = match table.read_u64::<LittleEndian>() {
-                Ok(v) => v,
-                Err(e) => {
-                    if e.kind() == io::ErrorKind::UnexpectedEof {
-                        break;
-                    } else {
-                        panic!("Wrong table format");
-                    }
-                }
-            };
-            // Length in bytes of our term's data.
-            let nbytes = table.read_u64::<LittleEndian>()?;
-            // Amount of documents where our term occurs.
-            let doc_count = table.read_u32::<LittleEndian>()?;
-            // Length of term in bytes
-            let term_length = table.read_u32::<LittleEndian>()?;
-            // Get term
-            let mut term = vec![0; term_length as usize];
-            table.read_exact(&mut term)?;
-            let term = String::from_utf8(term).unwrap();
-
-            word_count += 1;
-
-            // Seek to our term's data first byte
-            data.seek(SeekFrom::Start(offset))?;
-
-            let mut hits_raw = vec![0; nbytes as usize];
-            data.read_exact(&mut hits_raw)?;
-
-            // This entry is multiple docs and offsets which corresponds to
-            // one term.
-            let mut entry: HashMap<Doc, Offsets> = HashMap::new();
-
-            let reader = &mut hits_raw[..].as_ref();
-
-            for _ in 0..doc_count {
-                // Firsly we read hash, and create `Doc` object.
-                let hash = &mut [0; HASH_LENGTH];
-                reader.read_exact(&mut hash[..])?;
-                let doc = Doc::new(&hash[..]);
-
-                // How much offsets in this document existing.
-                let offsets_count = reader.read_u32::<LittleEndian>()?;
-                let mut offsets = vec![];
-
-                // Read all offsets.
-                for _ in 0..offsets_count {
-                    let word_offset = reader.read_u32::<LittleEndian>()?;
-                    offsets.push(word_offset);
-                }
-                // Push doc and offsets to entry
-                entry.insert(doc, offsets);
+        for entry in reader.entries() {
+            let (term, mut postings) = entry?;
+            tombstones.purge(&mut postings);
+            if postings.is_empty() {
+                continue;
             }
+
             // Insert entry for term
-            map.insert(term, entry);
+            if !is_twin_term(&term) {
+                word_count += 1;
+            }
+            map.insert(term, postings);
         }
 
-        Ok(ParsedIndex { word_count, map })
+        let docs = reader
+            .docs
+            .into_iter()
+            .filter(|(doc, _)| !tombstones.contains(doc))
+            .collect();
+
+        Ok(ParsedIndex {
+            word_count,
+            map,
+            docs,
+            positions: !reader.settings.omit_positions,
+        })
+    }
+
+    /// The document table of the file, by document id, including deleted
+    /// documents.
+    pub fn docs(&self) -> &DocTable {
+        &self.docs
+    }
+
+    /// Iterate over the remaining entries as `(term, postings)` pairs, in
+    /// term order.
+    ///
+    /// The hits of a term are only read and decoded when the iterator gets
+    /// to it, so this can stream over indexes far too large to load with
+    /// `get_index_from_file`. Like `docs`, the postings include deleted
+    /// documents; use `Tombstones::purge` to drop them.
+    pub fn entries(&mut self) -> Entries<'_> {
+        Entries { reader: self }
     }
 
     /// Borrow a reference to the next entry in the table of contents.
@@ -213,72 +268,1387 @@ impl IndexFileReader {
         }
     }
 
+    /// The settings from the file header.
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    /// Append the hits of the next entry to `out` through `remapper` and
+    /// advance to the entry after it. `doc_base` is added to every document
+    /// id, as this file's document table starts at that id in the output.
     pub fn move_entry_to(
         &mut self,
-        out: &mut IndexFileWriter,
-    ) -> io::Result<()> {
-        // This block limits the scope of borrowing `self.next` (for`e`),
-        // because after this block is over we'll want to assign to `self.next`.
-        {
-            let e = self.next.as_ref().expect("no entry to move");
-            if e.nbytes > usize::MAX as u64 {
-                // This can only happen on 32-bit platforms.
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "Computer's archutecture do not
-                    allow to hold such big index entry",
-                ));
+        out: &mut Vec<u8>,
+        remapper: &mut HitRemapper,
+        doc_base: u32,
+    ) -> Result<()> {
+        let (e, buf) = self.take_entry()?.expect("no entry to move");
+        let positions = !self.settings.omit_positions;
+        let (buf, _) = remapper
+            .remap(&buf, e.doc_count, positions, |id| Some(id + doc_base))?;
+        out.extend(buf);
+        Ok(())
+    }
+
+    /// Advance past the next entry without reading its hits.
+    pub(crate) fn skip_entry(&mut self) -> Result<()> {
+        self.next = self.decoder.read(&mut self.table_of_contents)?;
+        if let Some(ref next) = self.next {
+            next.check_bounds(self.data_end)?;
+        }
+        Ok(())
+    }
+
+    /// Read the next entry together with its decompressed hits and advance
+    /// past it.
+    ///
+    /// Returns `Ok(None)` if we've reached the end of the file.
+    pub fn take_entry(&mut self) -> Result<Option<(Entry, Vec<u8>)>> {
+        let e = match self.next.take() {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+        let codec = self.settings.codec;
+        let buf = read_hits(&mut self.data, &e, codec, self.hits_limit)?;
+
+        self.skip_entry()?;
+        Ok(Some((e, buf)))
+    }
+}
+
+/// Iterator over the entries of an index file, returned by
+/// `IndexFileReader::entries`.
+///
+/// Iteration stops after the first error.
+#[derive(Debug)]
+pub struct Entries<'a> {
+    reader: &'a mut IndexFileReader,
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<(String, DocEntry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.reader.take_entry().and_then(|entry| {
+            entry
+                .map(|(e, hits_raw)| {
+                    let docs = &self.reader.docs;
+                    let positions = !self.reader.settings.omit_positions;
+                    let postings =
+                        decode_hits(&hits_raw, e.doc_count, docs, positions)?;
+                    Ok((e.term, postings))
+                })
+                .transpose()
+        });
+        if result.is_err() {
+            // Don't try to read past a corrupt entry.
+            self.reader.next = None;
+        }
+        result.transpose()
+    }
+}
+
+impl Entry {
+    /// Check that the entry's data lies before `data_end`, where the term
+    /// data of the file ends, so that a corrupt entry can't make us read
+    /// into other sections or allocate a huge buffer.
+    pub(crate) fn check_bounds(&self, data_end: u64) -> Result<()> {
+        match self.offset.checked_add(self.nbytes) {
+            Some(end) if end <= data_end => Ok(()),
+            _ => Err(Error::format(format!(
+                "data for term {:?} is out of bounds",
+                self.term
+            ))),
+        }
+    }
+}
+
+/// Read the document table, stored between `start` and `end`, of documents
+/// identified by `identity`. The reader is left at `end`.
+fn read_doc_table<R: Read + Seek>(
+    f: &mut R,
+    start: u64,
+    end: u64,
+    identity: DocIdentity,
+) -> Result<DocTable> {
+    f.seek(SeekFrom::Start(start))?;
+    let raw = read_bytes(f, end - start, "document table")?;
+    decode_doc_table(&raw, identity)
+}
+
+/// Read exactly `len` bytes of `what`. Unlike `read_exact` into a buffer of
+/// that size, a corrupt length can't make us allocate more than the file
+/// holds.
+pub(crate) fn read_bytes<R: Read>(
+    f: &mut R,
+    len: u64,
+    what: &str,
+) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    f.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(Error::format(format!("{} is truncated", what)));
+    }
+    Ok(bytes)
+}
+
+/// The most bytes the decompressed hits of a term can take in an index of
+/// `docs`, stored with `positions` or not: for each document, up to 5 bytes
+/// each for its id and its number of offsets, and with positions, 5 for each
+/// offset, of which there can't be more than the document has words.
+pub(crate) fn hits_limit(docs: &DocTable, positions: bool) -> usize {
+    let words: u64 = match positions {
+        true => docs.iter().map(|(_, info)| doc_words(info)).sum(),
+        false => 0,
+    };
+    let limit = 10 * docs.len() as u64 + 5 * words;
+    usize::try_from(limit).unwrap_or(usize::MAX)
+}
+
+/// The number of words of a document, in all of its fields: a term can't
+/// occur in it more often than that. Forms in the original case (see
+/// `analyzer::EXACT_PREFIX`) aren't counted, but each stands for a word
+/// that is.
+fn doc_words(info: &DocInfo) -> u64 {
+    let fields: u64 = info.field_lens.iter().map(|&n| n as u64).sum();
+    info.token_count as u64 + fields
+}
+
+/// Flag of a document of the document table that was truncated, see
+/// `DocInfo::truncated`.
+pub(crate) const DOC_TRUNCATED: u8 = 1;
+
+/// Documents with other flags set are rejected, so that future versions can
+/// use them for what older readers must not ignore.
+const KNOWN_DOC_FLAGS: u8 = DOC_TRUNCATED;
+
+/// Decode the document table of an index file.
+///
+/// Entries are stored by document id. Each is a document hash (as long as
+/// the ids of `identity`), the
+/// document's token count (u32), the number of words of each of the
+/// `Field::NAMED` (u32 each), its modification time (u64), the size of its
+/// file (u64), its path (u32 length, then UTF-8 bytes), its language (u8
+/// length, then UTF-8 bytes, empty if unknown), the paths of its
+/// duplicates (u32 count, then each path as above) and its flags (u8, see
+/// `DOC_TRUNCATED`). Entries with unknown flags are rejected.
+pub(crate) fn decode_doc_table(
+    mut raw: &[u8],
+    identity: DocIdentity,
+) -> Result<DocTable> {
+    let truncated = Error::truncated("document table");
+    let mut docs = DocTable::new();
+    while !raw.is_empty() {
+        if docs.len() >= MAX_DOCS as usize {
+            return Err(Error::format(format!(
+                "document table has more than {} documents",
+                MAX_DOCS
+            )));
+        }
+        let mut hash = vec![0; identity.hash_len()];
+        raw.read_exact(&mut hash).map_err(&truncated)?;
+        let token_count = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+        let mut field_lens = [0; Field::NAMED.len()];
+        for len in &mut field_lens {
+            *len = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+        }
+        let mtime = raw.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let size = raw.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let path = read_path(&mut raw)?;
+        let language_len = raw.read_u8().map_err(&truncated)? as usize;
+        if language_len > raw.len() {
+            return Err(Error::format("document table is truncated"));
+        }
+        let mut language = vec![0; language_len];
+        raw.read_exact(&mut language).map_err(&truncated)?;
+        let language = String::from_utf8(language)?;
+        let count = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+        // Each path takes at least its length.
+        if count as usize > raw.len() / 4 {
+            return Err(Error::format("document table is truncated"));
+        }
+        let duplicates = (0..count)
+            .map(|_| read_path(&mut raw))
+            .collect::<Result<_>>()?;
+        let flags = raw.read_u8().map_err(&truncated)?;
+        if flags & !KNOWN_DOC_FLAGS != 0 {
+            return Err(Error::format(format!(
+                "unknown document flags {:#x}",
+                flags
+            )));
+        }
+        let info = DocInfo {
+            path,
+            token_count,
+            field_lens,
+            mtime,
+            size,
+            language,
+            duplicates,
+            truncated: flags & DOC_TRUNCATED != 0,
+        };
+        docs.push((Doc::new(&hash), info));
+    }
+    Ok(docs)
+}
+
+/// Read a path of the document table: a u32 length, then UTF-8 bytes.
+fn read_path(raw: &mut &[u8]) -> Result<String> {
+    let len = raw
+        .read_u32::<LittleEndian>()
+        .map_err(Error::truncated("document table"))? as usize;
+    if len > raw.len() {
+        return Err(Error::format("document table is truncated"));
+    }
+    let (path, rest) = raw.split_at(len);
+    *raw = rest;
+    Ok(String::from_utf8(path.to_vec())?)
+}
+
+/// Decode the hits stored for one term into a `DocEntry`.
+///
+/// `hits_raw` is the term's data exactly as it is stored in the index file:
+/// `doc_count` hits back-to-back, each made of a document id, the number of
+/// offsets, and the offsets themselves, all as varints. Document ids are
+/// stored as the difference from the previous hit's id and offsets as the
+/// difference from the previous offset in the same hit. Ids are resolved
+/// through `docs`, the file's document table.
+///
+/// Without `positions`, hits only have the number of offsets, and each
+/// document gets offsets 0, 1, 2… standing for that number, which can't be
+/// more than the document has words.
+pub(crate) fn decode_hits(
+    hits_raw: &[u8],
+    doc_count: u32,
+    docs: &DocTable,
+    positions: bool,
+) -> Result<DocEntry> {
+    // This entry is multiple docs and offsets which corresponds to
+    // one term.
+    let mut entry: HashMap<Doc, Offsets> = HashMap::new();
+
+    let reader = &mut &hits_raw[..];
+    let mut id = 0u32;
+
+    for _ in 0..doc_count {
+        // Firsly we read the document id, and look up its `Doc`.
+        id = id.wrapping_add(varint::read_u32(reader)?);
+        let (doc, info) = match docs.get(id as usize) {
+            Some((doc, info)) => (doc.clone(), info),
+            None => return Err(unknown_doc_id(id)),
+        };
+
+        // How much offsets in this document existing.
+        let offsets_count = varint::read_u32(reader)?;
+        if !positions {
+            if offsets_count as u64 > doc_words(info) {
+                return Err(Error::format(format!(
+                    "hit of document id {} has more offsets than the \
+                     document has words",
+                    id
+                )));
             }
-            let mut buf = Vec::with_capacity(e.nbytes as usize);
-            buf.resize(e.nbytes as usize, 0);
-            self.data.read_exact(&mut buf)?;
-            out.write_data(&buf)?;
+            entry.insert(doc, (0..offsets_count).collect());
+            continue;
         }
+        let mut offsets = vec![];
 
-        self.next = Self::read_entry(&mut self.table_of_contents)?;
-        Ok(())
+        // Read all offsets.
+        let mut word_offset = 0u32;
+        for _ in 0..offsets_count {
+            word_offset = word_offset.wrapping_add(varint::read_u32(reader)?);
+            offsets.push(word_offset);
+        }
+        // Push doc and offsets to entry
+        entry.insert(doc, offsets);
     }
+    Ok(entry)
 }
 
-impl IndexFileReader {
-    /// Read the next entry from the table of contents.
+/// Rewrites the document ids in the raw hits of terms, for merging and
+/// compacting.
+///
+/// Document ids are delta-encoded, so hits can't simply be copied from one
+/// place to another: the first id of each batch is relative to the last id
+/// written before it. A `HitRemapper` remembers that id, so the batches of
+/// one term written through it can be concatenated. Use a new one for every
+/// term.
+///
+/// It also notices when the ids it writes don't increase, which merges rely
+/// on to put the hits of a term back in document order (see
+/// `check_doc_order`).
+#[derive(Debug, Default)]
+pub struct HitRemapper {
+    last_id: u32,
+    /// Whether any hit was written yet.
+    started: bool,
+    /// Whether every id written was greater than the one before.
+    out_of_order: bool,
+}
+
+impl HitRemapper {
+    /// Rewrite the document ids in `hits_raw`, stored with `positions` or
+    /// not, through `remap`. Hits for which `remap` returns `None` are
+    /// dropped.
+    ///
+    /// Returns the rewritten hits and their number.
+    pub(crate) fn remap(
+        &mut self,
+        hits_raw: &[u8],
+        doc_count: u32,
+        positions: bool,
+        remap: impl Fn(u32) -> Option<u32>,
+    ) -> Result<(Vec<u8>, u32)> {
+        let mut reader = hits_raw;
+        let mut out = Vec::with_capacity(hits_raw.len());
+        let mut kept = 0;
+        let mut id = 0u32;
+        for _ in 0..doc_count {
+            id = id.wrapping_add(varint::read_u32(&mut reader)?);
+            let start = reader;
+            skip_offsets(&mut reader, positions)?;
+            // The offsets count and the offsets are copied as they are.
+            let rest = &start[..start.len() - reader.len()];
+            if let Some(new_id) = remap(id) {
+                varint::write_u32(&mut out, new_id.wrapping_sub(self.last_id));
+                out.extend_from_slice(rest);
+                self.out_of_order |= self.started && new_id <= self.last_id;
+                self.started = true;
+                self.last_id = new_id;
+                kept += 1;
+            }
+        }
+        Ok((out, kept))
+    }
+
+    /// True if the ids written so far are sorted, each document once.
+    pub(crate) fn in_order(&self) -> bool {
+        !self.out_of_order
+    }
+}
+
+/// Check that the `doc_count` hits in `hits_raw`, stored with `positions`
+/// or not, are sorted by document id, each document once, as writers keep
+/// them.
+pub(crate) fn check_doc_order(
+    hits_raw: &[u8],
+    doc_count: u32,
+    positions: bool,
+) -> Result<()> {
+    let mut reader = hits_raw;
+    let mut id = 0u32;
+    for i in 0..doc_count {
+        let delta = varint::read_u32(&mut reader)?;
+        let next = id.wrapping_add(delta);
+        if i > 0 && next <= id {
+            return Err(Error::format(format!(
+                "hits are out of document order: {} after {}",
+                next, id
+            )));
+        }
+        id = next;
+        skip_offsets(&mut reader, positions)?;
+    }
+    Ok(())
+}
+
+/// The documents with ids `ids` in `docs`, leaving out deleted ones.
+pub(crate) fn live_docs(
+    ids: &[u32],
+    docs: &DocTable,
+    tombstones: &Tombstones,
+) -> Result<Vec<Doc>> {
+    let mut live = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let (doc, _) =
+            docs.get(id as usize).ok_or_else(|| unknown_doc_id(id))?;
+        if !tombstones.contains(doc) {
+            live.push(doc.clone());
+        }
+    }
+    Ok(live)
+}
+
+/// Read the number of offsets of a hit, and move `reader` past the offsets,
+/// which are only there with `positions`.
+pub(crate) fn skip_offsets(reader: &mut &[u8], positions: bool) -> Result<u32> {
+    let count = varint::read_u32(reader)?;
+    if positions {
+        for _ in 0..count {
+            varint::read_u32(reader)?;
+        }
+    }
+    Ok(count)
+}
+
+fn unknown_doc_id(id: u32) -> Error {
+    Error::format(format!("hit refers to unknown document id {}", id))
+}
+
+/// An `IndexFileSearcher` answers single-term lookups against an index file
+/// without loading the whole index into memory.
+///
+/// Only the table of contents is read when the file is opened. The hits for a
+/// term are read from disk, with a single seek, when the term is looked up.
+#[derive(Debug)]
+pub struct IndexFileSearcher {
+    /// Reader over the whole index file, used to fetch term data on demand.
+    data: BufReader<IndexFile>,
+    /// The table of contents, sorted by term (the writer always emits it in
+    /// this order), so that lookups can use binary search.
+    terms: TermDictionary,
+    /// The bloom filter of the terms, if the index has one.
+    bloom: Option<BloomFilter>,
+    /// The document table, including deleted documents.
+    docs: DocTable,
+    /// Deleted documents, hidden from lookups.
+    tombstones: Tombstones,
+    /// The settings from the file header.
+    settings: IndexSettings,
+    /// The most bytes the hits of a term can take decompressed (see
+    /// `hits_limit`).
+    hits_limit: usize,
+}
+
+impl IndexFileSearcher {
+    /// Open an index file and load its table of contents.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<IndexFileSearcher> {
+        let mut data = BufReader::new(IndexFile::open(filename.as_ref())?);
+        let header = read_header(&mut data)?;
+        header.check_len(data.get_ref().len())?;
+
+        let mut table = BufReader::new(IndexFile::open(filename.as_ref())?);
+        let tombstones = Tombstones::load(filename)?;
+        let docs = read_doc_table(
+            &mut table,
+            header.doc_table_offset,
+            header.table_contents_offset,
+            header.settings.identity,
+        )?;
+
+        let (bloom, table_end) =
+            BloomFilter::read_footer(&mut table, &header.settings)?;
+        table.seek(SeekFrom::Start(header.table_contents_offset))?;
+        let mut contents = vec![];
+        table
+            .take(table_end.saturating_sub(header.table_contents_offset))
+            .read_to_end(&mut contents)?;
+        let terms = TermDictionary::load(contents, header.doc_table_offset)?;
+
+        let hits_limit = hits_limit(&docs, !header.settings.omit_positions);
+        Ok(IndexFileSearcher {
+            data,
+            terms,
+            bloom,
+            docs,
+            tombstones,
+            settings: header.settings,
+            hits_limit,
+        })
+    }
+
+    /// The table of contents entry for `term`, if the term is in the index.
+    pub fn entry(&self, term: &str) -> Option<Entry> {
+        if !self.might_contain(term) {
+            return None;
+        }
+        self.terms.get(term)
+    }
+
+    /// False if the index certainly doesn't contain `term`, as told by its
+    /// bloom filter, without a lookup in the table of contents. Always true
+    /// for indexes written without one.
+    pub fn might_contain(&self, term: &str) -> bool {
+        self.bloom.as_ref().is_none_or(|b| b.might_contain(term))
+    }
+
+    /// Number of documents containing `term`, read from the table of
+    /// contents only. Deleted documents are still counted until the index is
+    /// compacted.
+    pub fn doc_frequency(&self, term: &str) -> u32 {
+        self.entry(term).map_or(0, |e| e.doc_count)
+    }
+
+    /// The number of documents containing `term` and of its occurrences in
+    /// them, read from the table of contents only. Like `doc_frequency`,
+    /// counts deleted documents until the index is compacted.
+    pub fn term_stats(&self, term: &str) -> TermStats {
+        let entry = self.entry(term);
+        TermStats {
+            term: term.to_string(),
+            documents: entry.as_ref().map_or(0, |e| e.doc_count as usize),
+            occurrences: entry.map_or(0, |e| e.occurrences),
+        }
+    }
+
+    /// Statistics of the whole index, read from the table of contents and
+    /// the document table without decoding any hits. Deleted documents are
+    /// left out of `documents` and the lengths, but their terms, postings
+    /// and occurrences count until the index is compacted.
+    pub fn collection_stats(&self) -> CollectionStats {
+        let (terms, postings, occurrences) = self
+            .terms
+            .iter()
+            .filter(|e| !is_twin_term(&e.term))
+            .fold((0, 0, 0), |(terms, postings, occurrences), e| {
+                (
+                    terms + 1,
+                    postings + e.doc_count as u64,
+                    occurrences + e.occurrences,
+                )
+            });
+        let live = self
+            .docs
+            .iter()
+            .filter(|(doc, _)| !self.tombstones.contains(doc))
+            .map(|(doc, info)| (doc, info));
+        CollectionStats::new(
+            &CorpusStats::from_docs(live),
+            terms,
+            postings,
+            occurrences,
+        )
+    }
+
+    /// The document table, by document id, including deleted documents.
+    pub fn docs(&self) -> &DocTable {
+        &self.docs
+    }
+
+    /// True if `doc` was deleted (see `Tombstones`).
+    pub fn is_deleted(&self, doc: &Doc) -> bool {
+        self.tombstones.contains(doc)
+    }
+
+    /// The settings from the file header.
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    /// Number of terms in the index.
+    pub fn term_count(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// All terms in the index, in sorted order.
+    pub fn terms(&self) -> impl Iterator<Item = String> + '_ {
+        self.terms.iter().map(|e| e.term)
+    }
+
+    /// The terms starting with `prefix`, in sorted order.
+    pub fn terms_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = String> + 'a {
+        self.terms.with_prefix(prefix).map(|e| e.term)
+    }
+
+    /// Read and decode the hits for `term`.
     ///
-    /// Returns `Ok(None)` if we have reached the end of the file.
-    fn read_entry(f: &mut BufReader<File>) -> io::Result<Option<Entry>> {
-        // If the first read here fails with `Undexpected Eof`,
-        // that's considered a success, with no entry read.
-        let offset = match f.read_u64::<LittleEndian>() {
-            Ok(value) => value,
-            Err(e) => {
-                if e.kind() == io::ErrorKind::UnexpectedEof {
-                    return Ok(None);
-                } else {
-                    return Err(e);
+    /// Returns `Ok(None)` if the term does not occur in the index.
+    pub fn lookup(&mut self, term: &str) -> Result<Option<DocEntry>> {
+        let Some(e) = self.entry(term) else {
+            return Ok(None);
+        };
+        let hits_raw = self.read_hits(&e)?;
+        self.decode(&e, &hits_raw)
+    }
+
+    /// Decode `hits_raw`, the hits of `e` as `read_hits` returns them,
+    /// leaving out deleted documents, like `lookup`.
+    pub(crate) fn decode(
+        &self,
+        e: &Entry,
+        hits_raw: &[u8],
+    ) -> Result<Option<DocEntry>> {
+        let positions = !self.settings.omit_positions;
+        let mut entry =
+            decode_hits(hits_raw, e.doc_count, &self.docs, positions)?;
+        self.tombstones.purge(&mut entry);
+        Ok(Some(entry).filter(|e| !e.is_empty()))
+    }
+
+    /// The documents containing all of `terms`, by document id. The hits of
+    /// frequent terms are mostly skipped over rather than decoded.
+    pub fn intersect(&mut self, terms: &[&str]) -> Result<Vec<Doc>> {
+        let Some(entries) = self.entries_of(terms) else {
+            return Ok(vec![]);
+        };
+        let hits = entries
+            .iter()
+            .map(|e| self.read_hits(e))
+            .collect::<Result<Vec<_>>>()?;
+        self.intersect_hits(&entries, &hits)
+    }
+
+    /// The live documents matching `query`, by document id.
+    ///
+    /// `AND`, `OR` and `NOT` combine the hits of their terms as they are
+    /// stored (see `postings`), without decoding offsets or building sets
+    /// of documents. Phrases and proximity queries decode the hits of their
+    /// own terms, in the documents that have them all, to check where the
+    /// terms are.
+    pub fn evaluate(&mut self, query: &Query) -> Result<Vec<Doc>> {
+        let ids = self.ids_of(query)?;
+        live_docs(&ids, &self.docs, &self.tombstones)
+    }
+
+    /// The ids of the documents matching `query`, deleted ones included.
+    fn ids_of(&mut self, query: &Query) -> Result<Vec<u32>> {
+        let positions = !self.settings.omit_positions;
+        match query {
+            Query::Term(_) | Query::Fuzzy(..) => {
+                let operand = self.operand(query)?;
+                operand.doc_ids(positions)?.collect()
+            }
+            Query::And(..) => {
+                let mut conjuncts = vec![];
+                flatten(query, &mut conjuncts, |q| match q {
+                    Query::And(left, right) => Some((&**left, &**right)),
+                    _ => None,
+                });
+                // `a NOT b` leaves out the documents of `b` from those of
+                // `a`, rather than intersecting with everything but `b`.
+                let (excluded, included): (Vec<&Query>, Vec<&Query>) =
+                    conjuncts.iter().partition(|q| matches!(q, Query::Not(_)));
+                let included = match included.is_empty() {
+                    true => vec![Operand::Ids(self.all_ids())],
+                    false => self.operands(&included)?,
+                };
+                let lists = included
+                    .iter()
+                    .map(|o| o.doc_ids(positions))
+                    .collect::<Result<_>>()?;
+                let mut ids = postings::intersect(lists)?;
+                for query in excluded {
+                    let Query::Not(inner) = query else {
+                        unreachable!()
+                    };
+                    let excluded = self.operand(inner)?;
+                    ids = postings::difference(
+                        DocIds::found(ids),
+                        excluded.doc_ids(positions)?,
+                    )?;
                 }
+                Ok(ids)
+            }
+            Query::Or(..) => {
+                let mut disjuncts = vec![];
+                flatten(query, &mut disjuncts, |q| match q {
+                    Query::Or(left, right) => Some((&**left, &**right)),
+                    _ => None,
+                });
+                let operands = self.operands(&disjuncts)?;
+                let lists = operands
+                    .iter()
+                    .map(|o| o.doc_ids(positions))
+                    .collect::<Result<_>>()?;
+                postings::union(lists)
+            }
+            Query::Not(inner) => {
+                let excluded = self.operand(inner)?;
+                postings::difference(
+                    DocIds::found(self.all_ids()),
+                    excluded.doc_ids(positions)?,
+                )
+            }
+            Query::Phrase(_) | Query::Near(..) | Query::Proximity(..) => {
+                self.positional_ids(query)
+            }
+            Query::Language(language) => {
+                Ok(self.ids_where(|info| info.language == *language))
+            }
+            Query::Range(attribute, range) => Ok(self
+                .ids_where(|info| range.contains(&attribute.value_of(info)))),
+        }
+    }
+
+    /// `query` as an operand of a set operation: the hits of a term, or of
+    /// the terms a fuzzy term matches, are read but not decoded.
+    fn operand(&mut self, query: &Query) -> Result<Operand> {
+        let terms = match query {
+            Query::Term(term) => vec![term.clone()],
+            Query::Fuzzy(term, distance) => {
+                let all: Vec<String> = self.terms().collect();
+                fuzzy_matches(term, *distance, all.iter().map(String::as_str))
+                    .into_iter()
+                    .map(String::from)
+                    .collect()
+            }
+            _ => return Ok(Operand::Ids(self.ids_of(query)?)),
+        };
+        let mut hits = vec![];
+        for term in terms {
+            if let Some(e) = self.entry(&term) {
+                let raw = self.read_hits(&e)?;
+                hits.push((e, raw));
+            }
+        }
+        Ok(Operand::Hits(hits))
+    }
+
+    fn operands(&mut self, queries: &[&Query]) -> Result<Vec<Operand>> {
+        queries.iter().map(|query| self.operand(query)).collect()
+    }
+
+    /// The ids of the documents matching `query`, a phrase, `NEAR` or
+    /// proximity query: those that have all its terms are found from the
+    /// hits, then the offsets of the terms in them are decoded and checked.
+    fn positional_ids(&mut self, query: &Query) -> Result<Vec<u32>> {
+        let terms = query.terms();
+        let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+        let Some(entries) = self.entries_of(&terms) else {
+            return Ok(vec![]);
+        };
+        let hits = entries
+            .iter()
+            .map(|e| self.read_hits(e))
+            .collect::<Result<Vec<_>>>()?;
+        let positions = !self.settings.omit_positions;
+        let lists = hits
+            .iter()
+            .zip(&entries)
+            .map(|(hits, e)| DocIds::of_hits(hits, e, positions))
+            .collect();
+        let candidates = postings::intersect(lists)?;
+        if candidates.is_empty() {
+            return Ok(candidates);
+        }
+        let mut map = HashMap::new();
+        for (e, hits) in entries.iter().zip(&hits) {
+            let entry = decode_hits(hits, e.doc_count, &self.docs, positions)?;
+            map.insert(e.term.clone(), entry);
+        }
+        let index = ParsedIndex {
+            word_count: 0,
+            map,
+            docs: HashMap::new(),
+            positions,
+        };
+        let found = query.evaluate(&index);
+        Ok(candidates
+            .into_iter()
+            .filter(|&id| found.contains(&self.docs[id as usize].0))
+            .collect())
+    }
+
+    /// The ids of every document of the file.
+    fn all_ids(&self) -> Vec<u32> {
+        (0..self.docs.len() as u32).collect()
+    }
+
+    /// The ids of the documents `keep` is true for.
+    fn ids_where(&self, keep: impl Fn(&DocInfo) -> bool) -> Vec<u32> {
+        (0..self.docs.len() as u32)
+            .filter(|&id| keep(&self.docs[id as usize].1))
+            .collect()
+    }
+
+    /// The entries of `terms`, or `None` if one of them isn't in the index.
+    pub(crate) fn entries_of(&self, terms: &[&str]) -> Option<Vec<Entry>> {
+        terms.iter().map(|term| self.entry(term)).collect()
+    }
+
+    /// The live documents in all of `hits`, the hits of `entries` as
+    /// `read_hits` returns them, like `intersect`.
+    pub(crate) fn intersect_hits(
+        &self,
+        entries: &[Entry],
+        hits: &[Vec<u8>],
+    ) -> Result<Vec<Doc>> {
+        let positions = !self.settings.omit_positions;
+        let lists = hits
+            .iter()
+            .zip(entries)
+            .map(|(hits, e)| DocIds::of_hits(hits, e, positions))
+            .collect();
+        let ids = postings::intersect(lists)?;
+        live_docs(&ids, &self.docs, &self.tombstones)
+    }
+
+    /// Read the hits of an entry and decompress them.
+    pub(crate) fn read_hits(&mut self, e: &Entry) -> Result<Vec<u8>> {
+        read_hits(&mut self.data, e, self.settings.codec, self.hits_limit)
+    }
+
+    /// Another reader of the index file, for `read_hits_from`.
+    #[cfg(feature = "async")]
+    pub(crate) fn reader(&self) -> BufReader<IndexFile> {
+        BufReader::new(self.data.get_ref().try_clone())
+    }
+
+    /// `read_hits`, with `data`, another reader of the same index file.
+    #[cfg(feature = "async")]
+    pub(crate) fn read_hits_from(
+        &self,
+        data: &mut BufReader<IndexFile>,
+        e: &Entry,
+    ) -> Result<Vec<u8>> {
+        read_hits(data, e, self.settings.codec, self.hits_limit)
+    }
+}
+
+/// An operand of the set operations of `IndexFileSearcher::evaluate`.
+enum Operand {
+    /// The table of contents entries of terms, with their decompressed
+    /// hits: the operand is the union of their documents.
+    Hits(Vec<(Entry, Vec<u8>)>),
+    /// The ids of the documents of a subquery.
+    Ids(Vec<u32>),
+}
+
+impl Operand {
+    /// The documents of the operand, read from hits stored with
+    /// `positions` or not.
+    fn doc_ids(&self, positions: bool) -> Result<DocIds<'_>> {
+        Ok(match self {
+            Operand::Hits(hits) if hits.len() == 1 => {
+                let (e, hits) = &hits[0];
+                DocIds::of_hits(hits, e, positions)
+            }
+            Operand::Hits(hits) => {
+                // The documents of several terms are merged once, up front.
+                let lists = hits
+                    .iter()
+                    .map(|(e, hits)| DocIds::of_hits(hits, e, positions))
+                    .collect();
+                DocIds::found(postings::union(lists)?)
             }
+            Operand::Ids(ids) => DocIds::found(ids.clone()),
+        })
+    }
+}
+
+/// Push the operands of `query`, an `AND` or an `OR`, to `out`, through the
+/// nested operators of the same kind: `split` returns the two sides of one.
+fn flatten<'q>(
+    query: &'q Query,
+    out: &mut Vec<&'q Query>,
+    split: impl Fn(&'q Query) -> Option<(&'q Query, &'q Query)> + Copy,
+) {
+    match split(query) {
+        Some((left, right)) => {
+            flatten(left, out, split);
+            flatten(right, out, split);
+        }
+        None => out.push(query),
+    }
+}
+
+/// Read the hits of `e` from `data` and decompress them with `codec`, into
+/// no more than `limit` bytes.
+fn read_hits<R: Read + Seek>(
+    data: &mut R,
+    e: &Entry,
+    codec: Codec,
+    limit: usize,
+) -> Result<Vec<u8>> {
+    data.seek(SeekFrom::Start(e.offset))?;
+    let hits_raw = read_bytes(data, e.nbytes, "term data")?;
+    match codec {
+        Codec::None => Ok(hits_raw),
+        codec => Ok(codec.decompress(&hits_raw, limit)?.into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{doc, index_of};
+    use crate::header::OFFSETS_POSITION;
+    use crate::index::InMemoryIndex;
+    use crate::merge::FileMerge;
+    use crate::postings::Postings;
+    use crate::tmp::TmpDir;
+    use crate::write::{write_index_to_tmp_file, SKIP_INTERVAL};
+    use crate::HASH_LENGTH;
+
+    #[test]
+    fn documents_read_back_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = index_of(0, &["the cat", "the dog and the cat", "a bird"]);
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert_eq!(index.docs.len(), 3);
+        assert_eq!(index.map["cat"].len(), 2);
+        assert_eq!(index.map["cat"][&doc(0)], vec![1]);
+        assert_eq!(index.map["cat"][&doc(1)], vec![4]);
+        assert_eq!(index.map["the"][&doc(1)], vec![0, 3]);
+        assert_eq!(index.map["bird"][&doc(2)], vec![1]);
+
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+        assert_eq!(searcher.lookup("cat").unwrap().unwrap(), index.map["cat"]);
+        assert_eq!(searcher.lookup("horse").unwrap(), None);
+    }
+
+    #[test]
+    fn document_tables_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = index_of(0, &["the cat", "", "a bird in ünïcode"]);
+        let attributes = [
+            ("docs/cat.txt", 1_700_000_000, 7, "english", vec![]),
+            ("", 0, 0, "", vec![]),
+            ("docs/ü/bird.md", u64::MAX, 1 << 40, "", vec!["copy.md"]),
+        ];
+        for ((_, info), (path, mtime, size, language, duplicates)) in
+            index.docs.iter_mut().zip(attributes)
+        {
+            info.path = path.to_string();
+            info.mtime = mtime;
+            info.size = size;
+            info.language = language.to_string();
+            info.duplicates =
+                duplicates.iter().map(|d| d.to_string()).collect();
+        }
+        index.docs[2].1.truncated = true;
+        let written: HashMap<Doc, DocInfo> =
+            index.docs.iter().cloned().collect();
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let read = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert_eq!(read.docs, written);
+        assert_eq!(read.docs[&doc(0)].token_count, 2);
+        assert_eq!(read.docs[&doc(1)].token_count, 0);
+        assert_eq!(read.docs[&doc(2)].path, "docs/ü/bird.md");
+        let searcher = IndexFileSearcher::open(&file).unwrap();
+        assert_eq!(searcher.docs().len(), 3);
+    }
+
+    #[test]
+    fn merged_files_keep_their_documents_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let mut merge = FileMerge::new(dir.path());
+        for (first, texts) in [(0, ["a cat", "a dog"]), (10, ["a bird", "cat"])]
+        {
+            let index = index_of(first, &texts);
+            let file = write_index_to_tmp_file(
+                index,
+                &mut tmp_dir,
+                &IndexSettings::default(),
+            )
+            .unwrap();
+            merge.add_file(file).unwrap();
+        }
+        merge.finish().unwrap();
+        let index =
+            IndexFileReader::get_index_from_file(dir.path().join("index.dat"))
+                .unwrap();
+        assert_eq!(index.docs.len(), 4);
+        let cats: HashMap<_, _> =
+            [(doc(0), vec![1]), (doc(11), vec![0])].into();
+        assert_eq!(index.map["cat"], cats);
+        assert_eq!(index.map["a"].len(), 3);
+        assert_eq!(index.map["bird"][&doc(10)], vec![1]);
+    }
+
+    #[test]
+    fn compressed_files_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let texts = ["the cat", "the dog and the cat", "a bird"];
+        let plain = write_index_to_tmp_file(
+            index_of(0, &texts),
+            &mut tmp_dir,
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let plain = IndexFileReader::get_index_from_file(plain).unwrap();
+        for codec in [Codec::Zstd, Codec::Lz4] {
+            let settings = IndexSettings {
+                codec,
+                ..Default::default()
+            };
+            let written = write_index_to_tmp_file(
+                index_of(0, &texts),
+                &mut tmp_dir,
+                &settings,
+            );
+            if !codec.is_available() {
+                assert!(written.is_err());
+                continue;
+            }
+            let file = written.unwrap();
+            assert_eq!(IndexSettings::load(&file).unwrap(), settings);
+            let index = IndexFileReader::get_index_from_file(&file).unwrap();
+            assert_eq!(index.map, plain.map);
+            let mut searcher = IndexFileSearcher::open(&file).unwrap();
+            assert_eq!(
+                searcher.lookup("cat").unwrap().unwrap(),
+                plain.map["cat"]
+            );
+        }
+    }
+
+    #[test]
+    fn damaged_files_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = index_of(0, &["the cat", "the dog and the cat"]);
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let good = fs::read(&file).unwrap();
+        let damaged = dir.path().join("damaged.dat");
+        for len in [0, 10, good.len() / 2, good.len() - 1] {
+            fs::write(&damaged, &good[..len]).unwrap();
+            assert!(IndexFileReader::get_index_from_file(&damaged).is_err());
+            assert!(IndexFileSearcher::open(&damaged).is_err());
+        }
+        // Damaged bytes anywhere must not make reading panic.
+        for i in 0..good.len() {
+            let mut bytes = good.clone();
+            bytes[i] ^= 0xff;
+            fs::write(&damaged, &bytes).unwrap();
+            let _ = IndexFileReader::get_index_from_file(&damaged);
+            if let Ok(mut searcher) = IndexFileSearcher::open(&damaged) {
+                let _ = searcher.lookup("cat");
+            }
+        }
+    }
+
+    #[test]
+    fn entries_stream_in_term_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let settings = IndexSettings::default();
+        let texts = ["the cat", "the dog and the cat", "a bird"];
+        let file = write_index_to_tmp_file(
+            index_of(0, &texts),
+            &mut tmp_dir,
+            &settings,
+        )
+        .unwrap();
+        let parsed = IndexFileReader::get_index_from_file(&file).unwrap();
+        let mut reader = IndexFileReader::open(&file).unwrap();
+        let entries: Vec<_> = reader.entries().collect::<Result<_>>().unwrap();
+        let terms: Vec<_> = entries.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(terms, ["a", "and", "bird", "cat", "dog", "the"]);
+        for (term, postings) in &entries {
+            assert_eq!(postings, &parsed.map[term]);
+        }
+        assert!(reader.entries().next().is_none());
+
+        let empty = write_index_to_tmp_file(
+            InMemoryIndex::new(),
+            &mut tmp_dir,
+            &settings,
+        )
+        .unwrap();
+        let mut reader = IndexFileReader::open(&empty).unwrap();
+        assert!(reader.docs().is_empty());
+        assert!(reader.entries().next().is_none());
+    }
+
+    #[test]
+    fn entries_stop_after_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = index_of(0, &["a cat", "a dog"]);
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        // Point the first hit of the first term at a document that isn't in
+        // the document table.
+        let first =
+            IndexFileReader::open(&file).unwrap().peek().unwrap().offset;
+        let mut bytes = fs::read(&file).unwrap();
+        bytes[first as usize] = 0x7f;
+        fs::write(&file, bytes).unwrap();
+        let mut reader = IndexFileReader::open(&file).unwrap();
+        let mut entries = reader.entries();
+        assert!(entries.next().unwrap().is_err());
+        assert!(entries.next().is_none());
+    }
+
+    /// A document numbered past what fits in a byte.
+    fn wide_doc(n: u32) -> Doc {
+        let mut hash = [0; HASH_LENGTH];
+        hash[..4].copy_from_slice(&n.to_le_bytes());
+        Doc::new(&hash)
+    }
+
+    /// A searcher over `n` documents: all contain "all", even ones "even"
+    /// and every 300th "rare".
+    fn skip_searcher(dir: &Path, n: u32) -> IndexFileSearcher {
+        let mut index = InMemoryIndex::new();
+        for i in 0..n {
+            let mut text = String::from("all");
+            if i % 2 == 0 {
+                text.push_str(" even");
+            }
+            if i % 300 == 0 {
+                text.push_str(" rare");
+            }
+            index.merge(InMemoryIndex::from_single_document(
+                &wide_doc(i).hash,
+                text,
+            ));
+        }
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        IndexFileSearcher::open(file).unwrap()
+    }
+
+    #[test]
+    fn long_posting_lists_have_skip_points() {
+        let dir = tempfile::tempdir().unwrap();
+        let searcher = skip_searcher(dir.path(), 1000);
+        let all = searcher.entry("all").unwrap();
+        assert_eq!(all.skips.len(), 999 / SKIP_INTERVAL as usize);
+        assert!(all
+            .skips
+            .windows(2)
+            .all(|w| w[0].last_id < w[1].last_id && w[0].offset < w[1].offset));
+        assert_eq!(searcher.entry("even").unwrap().skips.len(), 3);
+        assert!(searcher.entry("rare").unwrap().skips.is_empty());
+    }
+
+    #[test]
+    fn intersections_skip_to_matching_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut searcher = skip_searcher(dir.path(), 1000);
+        // Documents come by id, which is the order they were added in.
+        let rare: Vec<_> = (0..1000).step_by(300).map(wide_doc).collect();
+        let found = searcher.intersect(&["all", "even", "rare"]).unwrap();
+        assert_eq!(found, rare);
+        let even: Vec<_> = (0..1000).step_by(2).map(wide_doc).collect();
+        assert_eq!(searcher.intersect(&["even", "all"]).unwrap(), even);
+        assert!(searcher.intersect(&["all", "missing"]).unwrap().is_empty());
+        assert!(searcher.intersect(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn postings_advance_through_skip_points() {
+        let ids: Vec<u32> = (0..300).map(|i| i * 3).collect();
+        let hits: Vec<_> = ids.iter().map(|&id| (id, &[0][..])).collect();
+        let raw = hits_of(&hits);
+        // Every hit takes 3 bytes: the id delta, the count and the offset.
+        let skips: Vec<_> = (1..3)
+            .map(|k| k * SKIP_INTERVAL as usize)
+            .map(|i| Skip {
+                last_id: ids[i - 1],
+                offset: 3 * i as u32,
+            })
+            .collect();
+        let entry = |skips| Entry {
+            term: "t".to_string(),
+            doc_count: ids.len() as u32,
+            occurrences: ids.len() as u64,
+            offset: 0,
+            nbytes: raw.len() as u64,
+            skips,
+            blocks: vec![BlockMax::default(); 3],
         };
+        let good = entry(skips.clone());
+        let mut postings = Postings::new(&raw, &good, true);
+        assert_eq!(postings.advance_to(1).unwrap(), Some(3));
+        assert_eq!(postings.advance_to(3).unwrap(), Some(3));
+        assert_eq!(postings.advance_to(700).unwrap(), Some(702));
+        assert_eq!(postings.next().unwrap(), Some(705));
+        assert_eq!(postings.advance_to(897).unwrap(), Some(897));
+        assert_eq!(postings.advance_to(898).unwrap(), None);
 
-        let nbytes = f.read_u64::<LittleEndian>()?;
-        let doc_count = f.read_u32::<LittleEndian>()?;
-        let term_len = f.read_u32::<LittleEndian>()? as usize;
-        let mut bytes = Vec::with_capacity(term_len);
-        bytes.resize(term_len, 0);
-        f.read_exact(&mut bytes)?;
-        let term = match String::from_utf8(bytes) {
-            Ok(s) => s,
-            Err(_) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "Unicode fail",
-                ))
+        // A skip point past the end of the hits is an error, not a panic.
+        let mut bad = entry(skips);
+        bad.skips[1].offset = raw.len() as u32 + 1;
+        let mut postings = Postings::new(&raw, &bad, true);
+        assert!(postings.advance_to(800).is_err());
+    }
+
+    /// The hits of one term as stored in a file, for documents by id and
+    /// their offsets, in increasing order.
+    fn hits_of(hits: &[(u32, &[u32])]) -> Vec<u8> {
+        let mut raw = vec![];
+        let mut last_id = 0;
+        for &(id, offsets) in hits {
+            varint::write_u32(&mut raw, id - last_id);
+            last_id = id;
+            varint::write_u32(&mut raw, offsets.len() as u32);
+            let mut last_offset = 0;
+            for &offset in offsets {
+                varint::write_u32(&mut raw, offset - last_offset);
+                last_offset = offset;
             }
+        }
+        raw
+    }
+
+    #[test]
+    fn hits_of_unknown_documents_are_an_error() {
+        let docs = index_of(0, &["a cat"]).docs;
+        let hits = hits_of(&[(1, &[0])]);
+        let e = decode_hits(&hits, 1, &docs, true).unwrap_err();
+        assert!(matches!(e, Error::Format(_)));
+        // A hit cut short.
+        let hits = hits_of(&[(0, &[300, 400])]);
+        let short = &hits[..hits.len() - 1];
+        assert!(decode_hits(short, 1, &docs, true).is_err());
+        assert!(HitRemapper::default().remap(short, 1, true, Some).is_err());
+    }
+
+    #[test]
+    fn delta_encoded_hits_decode() {
+        let docs = index_of(0, &["a", "b", "c", "d"]).docs;
+        let hits = hits_of(&[(1, &[3, 500, 70_000]), (3, &[0])]);
+        let entry = decode_hits(&hits, 2, &docs, true).unwrap();
+        assert_eq!(entry.len(), 2);
+        assert_eq!(entry[&doc(1)], vec![3, 500, 70_000]);
+        assert_eq!(entry[&doc(3)], vec![0]);
+    }
+
+    #[test]
+    fn remapped_hits_keep_their_offsets() {
+        let hits = hits_of(&[(0, &[3, 500]), (1, &[2])]);
+        let mut remapper = HitRemapper::default();
+        let (moved, count) =
+            remapper.remap(&hits, 2, true, |id| Some(id + 200)).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(moved, hits_of(&[(200, &[3, 500]), (201, &[2])]));
+        // A second batch of the same term goes on from the last id.
+        let (more, _) =
+            remapper.remap(&hits, 2, true, |id| Some(id + 300)).unwrap();
+        let mut all = moved;
+        all.extend(more);
+        let expected = hits_of(&[
+            (200, &[3, 500]),
+            (201, &[2]),
+            (300, &[3, 500]),
+            (301, &[2]),
+        ]);
+        assert_eq!(all, expected);
+
+        let (kept, count) = HitRemapper::default()
+            .remap(&hits, 2, true, |id| (id == 1).then_some(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(kept, hits_of(&[(0, &[2])]));
+    }
+
+    #[test]
+    fn hits_without_positions_keep_their_counts() {
+        let docs = index_of(0, &["a a a", "b"]).docs;
+        let mut hits = vec![];
+        for (delta, count) in [(0, 3), (1, 1)] {
+            varint::write_u32(&mut hits, delta);
+            varint::write_u32(&mut hits, count);
+        }
+        let entry = decode_hits(&hits, 2, &docs, false).unwrap();
+        assert_eq!(entry[&doc(0)], vec![0, 1, 2]);
+        assert_eq!(entry[&doc(1)], vec![0]);
+        let (moved, _) = HitRemapper::default()
+            .remap(&hits, 2, false, |id| Some(id + 5))
+            .unwrap();
+        assert_eq!(moved, [5, 3, 1, 1]);
+    }
+
+    #[test]
+    fn hostile_lengths_are_an_error() {
+        // More occurrences than the document has words.
+        let docs = index_of(0, &["a cat"]).docs;
+        let mut hits = vec![];
+        varint::write_u32(&mut hits, 0);
+        varint::write_u32(&mut hits, u32::MAX);
+        let e = decode_hits(&hits, 1, &docs, false).unwrap_err();
+        assert!(matches!(e, Error::Format(_)), "{:?}", e);
+
+        // Sections past the end of the file.
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index_to_tmp_file(
+            index_of(0, &["the cat"]),
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let mut bytes = fs::read(&file).unwrap();
+        let at = OFFSETS_POSITION as usize;
+        for offset in [at, at + 8] {
+            bytes[offset..offset + 8]
+                .copy_from_slice(&(1u64 << 60).to_le_bytes());
+        }
+        fs::write(&file, &bytes).unwrap();
+        let e = IndexFileReader::open(&file).unwrap_err();
+        assert!(matches!(e, Error::Format(_)), "{:?}", e);
+        let e = IndexFileSearcher::open(&file).unwrap_err();
+        assert!(matches!(e, Error::Format(_)), "{:?}", e);
+    }
+
+    #[test]
+    fn files_without_positions_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = IndexSettings {
+            omit_positions: true,
+            ..Default::default()
         };
+        let index = index_of(0, &["the cat and the dog", "a dog"]);
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &settings,
+        )
+        .unwrap();
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert_eq!(index.map["the"][&doc(0)], vec![0, 1]);
+        assert_eq!(index.map["dog"].len(), 2);
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+        assert_eq!(searcher.intersect(&["dog", "cat"]).unwrap(), [doc(0)]);
+    }
 
-        Ok(Some(Entry {
-            term,
-            doc_count,
-            offset,
-            nbytes,
-        }))
+    #[test]
+    fn queries_evaluate_on_stored_hits_like_on_parsed_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = [
+            "the cat sat on the mat",
+            "the dog and the cat",
+            "a bird",
+            "the dog barked at a bird",
+            "cats and dogs",
+        ];
+        let file = write_index_to_tmp_file(
+            index_of(0, &texts),
+            &mut TmpDir::new(dir.path()),
+            &IndexSettings::default(),
+        )
+        .unwrap();
+        let parsed = IndexFileReader::get_index_from_file(&file).unwrap();
+        let mut searcher = IndexFileSearcher::open(&file).unwrap();
+        for query in [
+            "cat",
+            "the cat",
+            "cat OR bird",
+            "the NOT cat",
+            "NOT the",
+            "(dog OR bird) AND a NOT barked",
+            "\"the cat\"",
+            "\"cat the\"",
+            "dog NEAR/2 cat",
+            "\"dog bird\"~3",
+            "cat~1",
+            "missing OR bird",
+        ] {
+            let query = Query::parse(query).unwrap();
+            let mut expected: Vec<Doc> =
+                query.evaluate(&parsed).into_iter().collect();
+            expected.sort_by_key(|doc| doc.hash[0]);
+            let found = searcher.evaluate(&query).unwrap();
+            assert_eq!(found, expected, "{:?}", query);
+        }
     }
 }
@@ -0,0 +1,328 @@
+//! Analyzing and extracting files differently by path.
+//!
+//! One analyzer rarely suits every file of a mixed collection: identifiers
+//! in source code are best split where their case changes, numbers flood
+//! the dictionary of log files with terms nobody searches for, and some
+//! Markdown notes are named `.txt`. Routes pick, by a glob on the path of
+//! each file, another analyzer or extractor for it. They are written in
+//! TOML:
+//!
+//! ```toml
+//! [[route]]
+//! glob = "*.rs"
+//! tokenizer = "code"
+//!
+//! [[route]]
+//! glob = "*/notes/*.txt"
+//! extractor = "markdown"
+//!
+//! [[route]]
+//! glob = "*.log"
+//! skip_numbers = true
+//! min_length = 2
+//! ```
+//!
+//! A route sets the extractor (`text`, `html`, `markdown` or `pdf`), or
+//! analyzer options (`tokenizer`, `stop_words`, `stemmer`, `skip_numbers`,
+//! `min_length` and `max_length`) that replace those of the index for the
+//! files it matches, or both. A file gets its extractor from the first
+//! route that matches it and sets one, and its analyzer from the first that
+//! sets analyzer options. Globs are matched against the whole path of the
+//! file as it is indexed, in which `*` also matches `/`: `*.rs` matches
+//! Rust files anywhere.
+//!
+//! The routes are part of the `AnalyzerConfig` of the index, recorded in
+//! its header, so that snippets and concordances split every document the
+//! way it was indexed. Queries don't have a path, so they are analyzed with
+//! the analyzer of the index: routes are best used for what queries still
+//! find, like splitting words further or dropping some.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use globset::{Glob, GlobMatcher};
+use serde::Deserialize;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{invalid_config, AnalyzerConfig, TokenizerKind};
+use crate::extract::EXTRACTORS;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The files matching a glob, and how to read and analyze them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// The glob the paths of the files must match.
+    pub glob: String,
+    /// The name of the extractor to get their text with, if not the one
+    /// chosen for any file.
+    pub extractor: Option<String>,
+    /// The configuration to analyze them with, if not the one of the index.
+    /// It has no routes of its own.
+    pub analyzer: Option<AnalyzerConfig>,
+}
+
+impl Route {
+    /// True if the file at `path` is routed by this route.
+    pub fn matches(&self, path: &Path) -> bool {
+        matcher(&self.glob).is_ok_and(|m| m.is_match(path))
+    }
+
+    /// Serialize as `key=value` pairs separated by `;`, with the analyzer
+    /// settings of `AnalyzerConfig::encode` and `extractor`. The tokenizer
+    /// is always written, so that a route with an analyzer has one pair at
+    /// least.
+    pub(crate) fn encode(&self) -> String {
+        let mut pairs = vec![];
+        if let Some(extractor) = &self.extractor {
+            pairs.push(format!("extractor={}", extractor));
+        }
+        if let Some(analyzer) = &self.analyzer {
+            pairs.push(format!("tokenizer={}", analyzer.tokenizer.name()));
+            pairs.extend(
+                analyzer
+                    .encode()
+                    .lines()
+                    .filter(|line| !line.starts_with("tokenizer="))
+                    .map(String::from),
+            );
+        }
+        pairs.join(";")
+    }
+
+    /// Parse the route for `glob` from the output of `encode`.
+    pub(crate) fn decode(glob: &str, value: &str) -> io::Result<Route> {
+        check_glob(glob).map_err(invalid_config)?;
+        let mut extractor = None;
+        let mut settings = vec![];
+        for pair in value.split(';').filter(|p| !p.trim().is_empty()) {
+            match pair.split_once('=') {
+                Some(("extractor", name)) => {
+                    check_extractor(name).map_err(invalid_config)?;
+                    extractor = Some(name.to_string());
+                }
+                _ => settings.push(pair),
+            }
+        }
+        let analyzer = match settings.is_empty() {
+            true => None,
+            false => Some(AnalyzerConfig::decode(&settings.join("\n"))?),
+        };
+        Ok(Route {
+            glob: glob.to_string(),
+            extractor,
+            analyzer,
+        })
+    }
+}
+
+/// A file of routes, as written in TOML.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoutesFile {
+    #[serde(default)]
+    route: Vec<RouteTable>,
+}
+
+/// One `[[route]]` of a file of routes.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteTable {
+    glob: String,
+    extractor: Option<String>,
+    tokenizer: Option<String>,
+    stop_words: Option<Vec<String>>,
+    stemmer: Option<String>,
+    skip_numbers: Option<bool>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+}
+
+/// Parse `text`, routes in TOML, for an index analyzed with `base`: their
+/// analyzer options replace those of `base`.
+pub fn parse(text: &str, base: &AnalyzerConfig) -> io::Result<Vec<Route>> {
+    let file: RoutesFile = toml::from_str(text).map_err(invalid_input)?;
+    file.route
+        .into_iter()
+        .map(|table| route(table, base).map_err(invalid_input))
+        .collect()
+}
+
+/// Read the routes in TOML in the file at `path`, like `parse`.
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    base: &AnalyzerConfig,
+) -> io::Result<Vec<Route>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+    })?;
+    parse(&text, base).map_err(|e| {
+        io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+    })
+}
+
+fn route(table: RouteTable, base: &AnalyzerConfig) -> Result<Route, String> {
+    check_glob(&table.glob)?;
+    if let Some(name) = &table.extractor {
+        check_extractor(name)?;
+    }
+    let mut analyzer = AnalyzerConfig {
+        routes: vec![],
+        ..base.clone()
+    };
+    let mut options = false;
+    if let Some(name) = table.tokenizer {
+        analyzer.tokenizer = TokenizerKind::from_name(&name)
+            .ok_or_else(|| format!("unknown tokenizer {}", name))?;
+        options = true;
+    }
+    if let Some(languages) = table.stop_words {
+        analyzer.stop_words = languages;
+        options = true;
+    }
+    if let Some(language) = table.stemmer {
+        analyzer.stemmer = Some(language);
+        options = true;
+    }
+    if let Some(skip) = table.skip_numbers {
+        analyzer.skip_numbers = skip;
+        options = true;
+    }
+    if let Some(min) = table.min_length {
+        analyzer.min_length = Some(min);
+        options = true;
+    }
+    if let Some(max) = table.max_length {
+        analyzer.max_length = Some(max);
+        options = true;
+    }
+    Ok(Route {
+        glob: table.glob,
+        extractor: table.extractor,
+        analyzer: options.then_some(analyzer),
+    })
+}
+
+/// Globs are recorded as keys of `AnalyzerConfig::encode`, so they can't
+/// contain what separates keys and values there.
+fn check_glob(glob: &str) -> Result<(), String> {
+    if glob.contains(['=', ';', '\n']) || glob.trim() != glob {
+        return Err(format!(
+            "glob {:?} contains '=', ';', a line break or outer spaces",
+            glob
+        ));
+    }
+    matcher(glob).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn check_extractor(name: &str) -> Result<(), String> {
+    match EXTRACTORS.contains(&name) {
+        true => Ok(()),
+        false => Err(format!("unknown extractor {}", name)),
+    }
+}
+
+fn matcher(glob: &str) -> Result<GlobMatcher, globset::Error> {
+    Ok(Glob::new(glob)?.compile_matcher())
+}
+
+fn invalid_input<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+/// What routes set, by their compiled globs, for looking up many paths.
+pub(crate) struct Router<T> {
+    routes: Vec<(GlobMatcher, T)>,
+}
+
+impl<T> Router<T> {
+    /// What `part` returns for each of `routes`, for those it returns
+    /// something for.
+    pub fn new(
+        routes: &[Route],
+        mut part: impl FnMut(&Route) -> io::Result<Option<T>>,
+    ) -> io::Result<Router<T>> {
+        let mut compiled = vec![];
+        for route in routes {
+            if let Some(value) = part(route)? {
+                let matcher = matcher(&route.glob).map_err(invalid_input)?;
+                compiled.push((matcher, value));
+            }
+        }
+        Ok(Router { routes: compiled })
+    }
+
+    /// What the first route matching `path` sets, if one does.
+    pub fn get(&self, path: &Path) -> Option<&T> {
+        self.routes
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(path))
+            .map(|(_, value)| value)
+    }
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Self {
+        Router { routes: vec![] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTES: &str = r#"
+        [[route]]
+        glob = "*.rs"
+        tokenizer = "code"
+
+        [[route]]
+        glob = "*/notes/*.txt"
+        extractor = "markdown"
+
+        [[route]]
+        glob = "*.log"
+        skip_numbers = true
+        min_length = 2
+    "#;
+
+    #[test]
+    fn files_get_the_analyzer_of_their_first_route() {
+        let base = AnalyzerConfig {
+            stop_words: vec!["english".to_string()],
+            ..Default::default()
+        };
+        let routes = parse(ROUTES, &base).unwrap();
+        assert_eq!(routes.len(), 3);
+        assert_eq!(routes[1].extractor.as_deref(), Some("markdown"));
+        assert_eq!(routes[1].analyzer, None);
+        let config = AnalyzerConfig { routes, ..base };
+
+        let code = config.for_path(Path::new("/src/lib.rs"));
+        assert_eq!(code.tokenizer, TokenizerKind::Code);
+        assert_eq!(code.stop_words, ["english"]);
+        assert!(config.for_path(Path::new("/var/app.log")).skip_numbers);
+        assert_eq!(config.for_path(Path::new("/a/notes/b.txt")), &config);
+        assert_eq!(AnalyzerConfig::decode(&config.encode()).unwrap(), config);
+    }
+
+    #[test]
+    fn bad_routes_are_rejected() {
+        let base = AnalyzerConfig::default();
+        for bad in [
+            "[[route]]\nglob = \"*.x\"\nextractor = \"word\"",
+            "[[route]]\nglob = \"*.x\"\ntokenizer = \"words\"",
+            "[[route]]\nglob = \"a=b\"\nskip_numbers = true",
+            "[[route]]\nglob = \"[\"\nskip_numbers = true",
+            "[[route]]\nglob = \"*.x\"\ncolor = \"red\"",
+            "[[route]]\nextractor = \"html\"",
+        ] {
+            let e = parse(bad, &base).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput, "{}", bad);
+        }
+        assert!(parse("", &base).unwrap().is_empty());
+    }
+}
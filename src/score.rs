@@ -0,0 +1,507 @@
+//! Ranking of search results.
+//!
+//! A `Scorer` turns the per-term statistics stored in the index (how many
+//! times a term occurs in a document, and in how many documents it occurs at
+//! all) into a relevance score. `Bm25` is the default; `TfIdf` is the classic
+//! and simpler alternative. `rank` sums the per-term scores for a set of
+//! documents and sorts them best first; `rank_weighted` does the same with a
+//! weight for each term, which is how matches in the title are boosted.
+//! `explain` tells what each term added to the score of a document.
+//!
+//! Scores from different indexes can only be compared if they were computed
+//! with the same statistics; `CorpusStats::combine` puts together those of
+//! several indexes, for searching them as one.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::field::Field;
+use crate::index::{Doc, DocInfo, ParsedIndex};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Corpus-wide numbers that scoring functions need besides the per-term
+/// statistics.
+#[derive(Debug)]
+pub struct CorpusStats {
+    /// Number of distinct documents in the index.
+    pub doc_count: usize,
+    /// Average document length, in words of the body.
+    pub avg_doc_len: f64,
+    /// Average length of each field other than the body, in words, in the
+    /// order of `Field::NAMED`. Only documents that have the field count.
+    pub avg_field_lens: [f64; Field::NAMED.len()],
+    /// Length of every document, in words of the body.
+    pub doc_lens: HashMap<Doc, usize>,
+    /// Length of the other fields of every document, in the order of
+    /// `Field::NAMED`.
+    pub field_lens: HashMap<Doc, [u32; Field::NAMED.len()]>,
+}
+
+impl CorpusStats {
+    /// Compute corpus statistics from the document table of `index`, which
+    /// records the length of every field of every document.
+    pub fn from_index(index: &ParsedIndex) -> CorpusStats {
+        CorpusStats::from_docs(&index.docs)
+    }
+
+    /// Compute corpus statistics from what a document table records about
+    /// `docs`.
+    pub fn from_docs<'a, I>(docs: I) -> CorpusStats
+    where
+        I: IntoIterator<Item = (&'a Doc, &'a DocInfo)>,
+    {
+        let mut doc_lens = HashMap::new();
+        let mut field_lens = HashMap::new();
+        for (doc, info) in docs {
+            doc_lens.insert(doc.clone(), info.token_count as usize);
+            field_lens.insert(doc.clone(), info.field_lens);
+        }
+        CorpusStats::from_lens(doc_lens, field_lens)
+    }
+
+    /// Statistics of several indexes taken together. A document in more
+    /// than one of them counts once, with the length it has in the last.
+    pub fn combine<'a, I>(stats: I) -> CorpusStats
+    where
+        I: IntoIterator<Item = &'a CorpusStats>,
+    {
+        let mut doc_lens = HashMap::new();
+        let mut field_lens = HashMap::new();
+        for stats in stats {
+            doc_lens.extend(
+                stats.doc_lens.iter().map(|(doc, len)| (doc.clone(), *len)),
+            );
+            field_lens.extend(
+                stats
+                    .field_lens
+                    .iter()
+                    .map(|(doc, lens)| (doc.clone(), *lens)),
+            );
+        }
+        CorpusStats::from_lens(doc_lens, field_lens)
+    }
+
+    fn from_lens(
+        doc_lens: HashMap<Doc, usize>,
+        field_lens: HashMap<Doc, [u32; Field::NAMED.len()]>,
+    ) -> CorpusStats {
+        let doc_count = doc_lens.len();
+        let avg_doc_len = average(doc_lens.values().copied());
+        let mut avg_field_lens = [0.0; Field::NAMED.len()];
+        for (i, avg) in avg_field_lens.iter_mut().enumerate() {
+            *avg = average(
+                field_lens
+                    .values()
+                    .map(|lens| lens[i] as usize)
+                    .filter(|len| *len > 0),
+            );
+        }
+        CorpusStats {
+            doc_count,
+            avg_doc_len,
+            avg_field_lens,
+            doc_lens,
+            field_lens,
+        }
+    }
+
+    /// Average length of `field`, in words.
+    pub fn avg_len(&self, field: Field) -> f64 {
+        match field.named_index() {
+            Some(i) => self.avg_field_lens[i],
+            None => self.avg_doc_len,
+        }
+    }
+
+    /// Length of `field` in `doc`, in words, as scorers should see it. They
+    /// compare lengths with `avg_doc_len`, so the lengths of other fields
+    /// are scaled by how much longer documents are than the field on
+    /// average, which normalizes them by their own average.
+    pub fn norm_len(&self, doc: &Doc, field: Field) -> usize {
+        let len = match field.named_index() {
+            Some(i) => self.field_lens.get(doc).map_or(0, |lens| lens[i]),
+            None => self.doc_lens.get(doc).map_or(0, |len| *len as u32),
+        };
+        self.normalize(field, len)
+    }
+
+    /// `len`, a length of `field` in words, as scorers should see it (see
+    /// `norm_len`).
+    pub fn normalize(&self, field: Field, len: u32) -> usize {
+        let Some(i) = field.named_index() else {
+            return len as usize;
+        };
+        let avg = self.avg_field_lens[i];
+        if avg > 0.0 {
+            (len as f64 * self.avg_doc_len / avg).round() as usize
+        } else {
+            self.avg_doc_len.round() as usize
+        }
+    }
+}
+
+/// The mean of `values`, 0 if there are none.
+fn average(values: impl Iterator<Item = usize>) -> f64 {
+    let (count, total) =
+        values.fold((0, 0), |(count, total), len| (count + 1, total + len));
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+/// What a term of a search added to the score of a document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermScore {
+    /// The index term, with the prefix of its field if it isn't the body.
+    pub term: String,
+    /// Number of occurrences of the term in the document.
+    pub tf: u32,
+    /// Number of documents containing the term.
+    pub df: u32,
+    /// What the score of the term was multiplied by, like the boost of its
+    /// field.
+    pub weight: f64,
+    /// The score of the term in the document, weight included.
+    pub score: f64,
+}
+
+/// A ranking function.
+///
+/// `score` is called once per (query term, matching document) pair; the
+/// results for all terms of a query are summed. Scorers are `Send` and
+/// `Sync`, so that a searcher can answer queries from several threads.
+pub trait Scorer: Send + Sync {
+    /// Score a single term in a single document.
+    ///
+    /// `tf` is the number of occurrences of the term in the document, `df` the
+    /// number of documents containing the term, and `doc_len` the length of
+    /// the document in words, or that of the field of the term, normalized
+    /// (see `CorpusStats::norm_len`).
+    fn score(
+        &self,
+        tf: u32,
+        df: u32,
+        doc_len: usize,
+        stats: &CorpusStats,
+    ) -> f64;
+}
+
+/// Okapi BM25.
+#[derive(Debug, Clone, Copy)]
+pub struct Bm25 {
+    /// Term frequency saturation.
+    pub k1: f64,
+    /// Document length normalization, from 0 (none) to 1 (full).
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25 {
+    fn score(
+        &self,
+        tf: u32,
+        df: u32,
+        doc_len: usize,
+        stats: &CorpusStats,
+    ) -> f64 {
+        let n = stats.doc_count as f64;
+        let df = df as f64;
+        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+        let tf = tf as f64;
+        let norm = if stats.avg_doc_len > 0.0 {
+            doc_len as f64 / stats.avg_doc_len
+        } else {
+            1.0
+        };
+        idf * tf * (self.k1 + 1.0)
+            / (tf + self.k1 * (1.0 - self.b + self.b * norm))
+    }
+}
+
+/// Plain TF-IDF with logarithmic term frequency.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TfIdf;
+
+impl Scorer for TfIdf {
+    fn score(
+        &self,
+        tf: u32,
+        df: u32,
+        _doc_len: usize,
+        stats: &CorpusStats,
+    ) -> f64 {
+        if tf == 0 || df == 0 {
+            return 0.0;
+        }
+        let idf = (stats.doc_count as f64 / df as f64).ln() + 1.0;
+        (1.0 + (tf as f64).ln()) * idf
+    }
+}
+
+/// Score every document in `docs` against `terms` and sort them best first.
+/// Ties are broken by document hash so the order is stable.
+pub fn rank<I>(
+    index: &ParsedIndex,
+    stats: &CorpusStats,
+    terms: &[String],
+    docs: I,
+    scorer: &dyn Scorer,
+) -> Vec<(Doc, f64)>
+where
+    I: IntoIterator<Item = Doc>,
+{
+    let terms: Vec<(String, f64)> =
+        terms.iter().map(|term| (term.clone(), 1.0)).collect();
+    rank_weighted(index, stats, &terms, docs, scorer)
+}
+
+/// Like `rank`, but the score of each term is multiplied by its weight.
+///
+/// Terms of fields other than the body are scored with the length of their
+/// field, compared with its average length (see `CorpusStats::norm_len`).
+pub fn rank_weighted<I>(
+    index: &ParsedIndex,
+    stats: &CorpusStats,
+    terms: &[(String, f64)],
+    docs: I,
+    scorer: &dyn Scorer,
+) -> Vec<(Doc, f64)>
+where
+    I: IntoIterator<Item = Doc>,
+{
+    let df = |term: &str| index.map.get(term).map_or(0, |entry| entry.len());
+    let mut ranked = score(index, stats, terms, docs, scorer, &df);
+    sort_ranked(&mut ranked);
+    ranked
+}
+
+/// Score every document in `docs` like `rank_weighted`, without sorting,
+/// but with the number of documents containing each term given by `df`.
+pub(crate) fn score<I>(
+    index: &ParsedIndex,
+    stats: &CorpusStats,
+    terms: &[(String, f64)],
+    docs: I,
+    scorer: &dyn Scorer,
+    df: &dyn Fn(&str) -> usize,
+) -> Vec<(Doc, f64)>
+where
+    I: IntoIterator<Item = Doc>,
+{
+    docs.into_iter()
+        .map(|doc| {
+            let score = terms
+                .iter()
+                .filter_map(|(term, weight)| {
+                    let (_, _, score) =
+                        term_score(index, stats, term, &doc, scorer, df)?;
+                    Some(weight * score)
+                })
+                // Not `sum`, which is -0.0 for no terms, as for documents
+                // matched by filters only.
+                .fold(0.0, |sum, score| sum + score);
+            (doc, score)
+        })
+        .collect()
+}
+
+/// What each of `terms` that occurs in `doc` adds to its score, as scored
+/// by `score`, in the order of `terms`.
+pub(crate) fn explain(
+    index: &ParsedIndex,
+    stats: &CorpusStats,
+    terms: &[(String, f64)],
+    doc: &Doc,
+    scorer: &dyn Scorer,
+    df: &dyn Fn(&str) -> usize,
+) -> Vec<TermScore> {
+    terms
+        .iter()
+        .filter_map(|(term, weight)| {
+            let (tf, df, score) =
+                term_score(index, stats, term, doc, scorer, df)?;
+            Some(TermScore {
+                term: term.clone(),
+                tf,
+                df,
+                weight: *weight,
+                score: weight * score,
+            })
+        })
+        .collect()
+}
+
+/// The number of occurrences of `term` in `doc`, the number of documents
+/// containing it, and its score there before weighting, if it occurs there.
+fn term_score(
+    index: &ParsedIndex,
+    stats: &CorpusStats,
+    term: &str,
+    doc: &Doc,
+    scorer: &dyn Scorer,
+    df: &dyn Fn(&str) -> usize,
+) -> Option<(u32, u32, f64)> {
+    let tf = index.map.get(term)?.get(doc)?.len() as u32;
+    let df = df(term) as u32;
+    let doc_len = stats.norm_len(doc, Field::of_term(term));
+    Some((tf, df, scorer.score(tf, df, doc_len, stats)))
+}
+
+/// Sort scored documents best first, ties broken by document hash.
+pub(crate) fn sort_ranked(ranked: &mut [(Doc, f64)]) {
+    ranked.sort_by(|(a_doc, a), (b_doc, b)| {
+        b.total_cmp(a).then_with(|| a_doc.hash.cmp(&b_doc.hash))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::TextAnalyzer;
+    use crate::fixtures::{doc, index_of};
+    use crate::index::InMemoryIndex;
+
+    /// The documents of `texts` containing any of `terms`, by number, best
+    /// first.
+    fn order(texts: &[&str], terms: &[&str], scorer: &dyn Scorer) -> Vec<u8> {
+        let index = index_of(0, texts).to_parsed();
+        let stats = CorpusStats::from_index(&index);
+        let terms: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+        let docs = index.all_docs().into_iter().filter(|doc| {
+            terms
+                .iter()
+                .any(|t| index.map.get(t).is_some_and(|e| e.contains_key(doc)))
+        });
+        let ranked = rank(&index, &stats, &terms, docs, scorer);
+        ranked.iter().map(|(doc, _)| doc.hash[0]).collect()
+    }
+
+    #[test]
+    fn documents_are_ranked_best_first() {
+        // Texts, query, then the order with BM25 and TF-IDF.
+        type Case = (
+            &'static [&'static str],
+            &'static str,
+            &'static [u8],
+            &'static [u8],
+        );
+        let cases: &[Case] = &[
+            (
+                &["cat", "cat cat", "cat cat cat"],
+                "cat",
+                &[2, 1, 0],
+                &[2, 1, 0],
+            ),
+            // BM25 prefers short documents, TF-IDF doesn't care.
+            (&["cat dog dog dog dog", "cat"], "cat", &[1, 0], &[0, 1]),
+            // Rare terms weigh more.
+            (
+                &["cat", "dog", "cat", "cat"],
+                "cat dog",
+                &[1, 0, 2, 3],
+                &[1, 0, 2, 3],
+            ),
+            (
+                &["cat dog", "cat", "dog"],
+                "cat dog",
+                &[0, 1, 2],
+                &[0, 1, 2],
+            ),
+            // Ties go by document hash.
+            (
+                &["a cat", "the cat", "one cat"],
+                "cat",
+                &[0, 1, 2],
+                &[0, 1, 2],
+            ),
+            (&["cat"], "bird", &[], &[]),
+        ];
+        for &(texts, query, bm25, tf_idf) in cases {
+            let terms: Vec<&str> = query.split(' ').collect();
+            let terms = &terms[..];
+            assert_eq!(
+                order(texts, terms, &Bm25::default()),
+                bm25,
+                "{:?}",
+                texts
+            );
+            assert_eq!(order(texts, terms, &TfIdf), tf_idf, "{:?}", texts);
+        }
+    }
+
+    #[test]
+    fn weights_multiply_the_score_of_their_term() {
+        let index = index_of(0, &["cat dog", "cat dog"]).to_parsed();
+        let stats = CorpusStats::from_index(&index);
+        let weighted = |cat: f64, dog: f64| {
+            let terms = [("cat".to_string(), cat), ("dog".to_string(), dog)];
+            let scorer = Bm25::default();
+            rank_weighted(&index, &stats, &terms, [doc(0)], &scorer)[0].1
+        };
+        let one = weighted(1.0, 0.0);
+        assert!(one > 0.0);
+        assert_eq!(weighted(1.0, 1.0), 2.0 * one);
+        assert_eq!(weighted(3.0, 0.0), 3.0 * one);
+        let df = |term: &str| index.map[term].len();
+        let terms = [("cat".to_string(), 2.0), ("bird".to_string(), 1.0)];
+        let explained = explain(&index, &stats, &terms, &doc(1), &TfIdf, &df);
+        assert_eq!(explained.len(), 1);
+        assert_eq!((explained[0].tf, explained[0].df), (1, 2));
+        assert_eq!(explained[0].score, 2.0 * TfIdf.score(1, 2, 2, &stats));
+    }
+
+    #[test]
+    fn matches_in_short_fields_weigh_more() {
+        let titles = ["Cat", "The cat of the old house", "Dog", "A dog"];
+        let mut index = InMemoryIndex::new();
+        for (n, title) in (0..).zip(titles) {
+            index.merge(InMemoryIndex::from_single_document_fields(
+                &doc(n).hash,
+                "a cat and a dog".to_string(),
+                &[(Field::Title, title.to_string())],
+                &TextAnalyzer::default(),
+            ));
+        }
+        let index = index.to_parsed();
+        let stats = CorpusStats::from_index(&index);
+        assert_eq!(stats.avg_len(Field::Title), 2.5);
+        assert_eq!(stats.avg_len(Field::Body), 5.0);
+        // Titles are scaled to the length of the body.
+        assert_eq!(stats.norm_len(&doc(0), Field::Title), 2);
+        assert_eq!(stats.norm_len(&doc(1), Field::Title), 12);
+        assert_eq!(stats.norm_len(&doc(0), Field::Body), 5);
+
+        let terms = ["title:cat".to_string()];
+        let docs = [doc(1), doc(0)];
+        let ranked = rank(&index, &stats, &terms, docs, &Bm25::default());
+        let order: Vec<u8> =
+            ranked.iter().map(|(doc, _)| doc.hash[0]).collect();
+        assert_eq!(order, [0, 1]);
+        assert!(ranked[0].1 > ranked[1].1);
+    }
+
+    #[test]
+    fn combined_stats_count_shared_documents_once() {
+        let a = CorpusStats::from_index(
+            &index_of(0, &["a b", "a b c d"]).to_parsed(),
+        );
+        let b =
+            CorpusStats::from_index(&index_of(1, &["a b c d e f"]).to_parsed());
+        let both = CorpusStats::combine([&a, &b]);
+        assert_eq!(both.doc_count, 2);
+        assert_eq!(both.avg_doc_len, 4.0);
+        assert_eq!(both.norm_len(&doc(1), Field::Body), 6);
+        assert_eq!(both.norm_len(&doc(5), Field::Body), 0);
+    }
+}
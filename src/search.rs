@@ -0,0 +1,1405 @@
+//! Searching an index file from an application.
+//!
+//! `Searcher` bundles everything a search needs: the parsed index, the
+//! analyzer it was built with, the corpus statistics and a ranking function.
+//! `Searcher::query` parses a boolean query (see the `query` module), runs it
+//! and ranks the matching documents, counting them by facet if asked to
+//! (see `Searcher::with_facets`). The `SearchResults` it returns are
+//! sorted best first and can be read a page at a time, by offset or by
+//! `Cursor`, which tells where the previous page ended and can be handed to
+//! clients to ask for the next one.
+//!
+//! Documents whose title contains the words searched for in the body rank
+//! higher; see `Searcher::with_field_boost`. `Searcher::explain` tells why a
+//! document matched and how its score adds up, for tuning the ranking.
+//!
+//! In an index whose documents were analyzed according to their language,
+//! queries are analyzed like the most common language of the index, unless
+//! `Searcher::with_language` says otherwise.
+//!
+//! Searches ignore case. In an index that also stores words in their original
+//! case (see `AnalyzerConfig::preserve_case`), `Searcher::with_case_sensitive`
+//! makes them match it exactly.
+//!
+//! `MultiSearcher` searches several index files as one, e.g. indexes built
+//! per directory or per month that would take too long to merge. Each index
+//! answers the query, each on a thread of its own, and all the matching
+//! documents are ranked together, with the statistics of all indexes, so
+//! that their scores compare.
+//!
+//! `TopKSearcher` ranks the documents of an index file too large to load,
+//! reading only the hits of the terms searched for. It finds the best `k`
+//! documents without scoring all those that contain a term: it skips the
+//! documents, and whole blocks of hits, whose scores can't reach the `k`th
+//! best found so far (block-max WAND).
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::{Analyzer, AnalyzerConfig, CaseForms, TextAnalyzer};
+use crate::error::{Error, Result};
+use crate::facet::{Facet, FacetCounts, FacetValues};
+use crate::field::Field;
+use crate::header::IndexSettings;
+use crate::index::{is_twin_term, Doc, DocInfo, ParsedIndex};
+use crate::language;
+use crate::postings::Postings;
+use crate::query::Query;
+use crate::read::{IndexFileReader, IndexFileSearcher};
+use crate::score::{self, rank_weighted, Bm25, CorpusStats, Scorer, TermScore};
+use crate::shard::ShardedIndex;
+use crate::stats::{CollectionStats, TermStats};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// An index file loaded for searching.
+pub struct Searcher {
+    index: ParsedIndex,
+    config: AnalyzerConfig,
+    analyzer: TextAnalyzer,
+    stats: CorpusStats,
+    scorer: Box<dyn Scorer>,
+    boosts: Vec<(Field, f64)>,
+    facets: FacetValues,
+    /// The file or directory the index was loaded from, if any.
+    source: Option<PathBuf>,
+}
+
+/// Weight of a match in the title, relative to a match in the body, unless
+/// configured otherwise.
+pub const DEFAULT_TITLE_BOOST: f64 = 2.0;
+
+/// One matching document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub doc: Doc,
+    pub score: f64,
+}
+
+/// Why a document matched a search, and how its score adds up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitExplanation {
+    /// Which of the indexes searched the document was scored in, counting
+    /// from 0, in the order of `MultiSearcher::searchers`.
+    pub index: usize,
+    /// The file or directory that index was loaded from, like a segment of
+    /// an index directory; none for documents that were only in memory.
+    pub source: Option<PathBuf>,
+    /// The terms of the search that occur in the document, in the order of
+    /// `SearchResults::terms`, each followed by the terms of the fields it
+    /// is boosted in. Their scores add up to that of the document.
+    pub terms: Vec<TermScore>,
+}
+
+/// The documents matching a search, best first.
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    terms: Vec<String>,
+    hits: Vec<SearchHit>,
+    facets: FacetCounts,
+}
+
+/// Where a page of results ended: the score and document of its last hit.
+/// It is written as an opaque string by `to_string`, and read back by
+/// `parse`.
+///
+/// The page after a cursor starts with the first hit ranked after it, so
+/// paging by cursor neither repeats nor skips hits when the results change
+/// between two pages, e.g. because the index was updated, as long as the
+/// hits already seen keep their scores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    score: f64,
+    doc: Doc,
+}
+
+/// A string that isn't a `Cursor`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid results cursor")]
+pub struct InvalidCursor;
+
+impl Searcher {
+    /// Load `index_file` for searching, ranking results with BM25. A
+    /// sharded index (see `shard`) is loaded whole, from all its shards.
+    ///
+    /// Queries are analyzed with the analyzer recorded in the file header,
+    /// so they match the indexed terms.
+    pub fn open<P: AsRef<Path>>(index_file: P) -> Result<Searcher> {
+        let source = Some(index_file.as_ref().to_path_buf());
+        if ShardedIndex::is_sharded(&index_file) {
+            let searcher = ShardedIndex::open(index_file)?.searcher()?;
+            return Ok(Searcher { source, ..searcher });
+        }
+        let settings = IndexSettings::load(&index_file)?;
+        let index = IndexFileReader::get_index_from_file(index_file)?;
+        Ok(Searcher {
+            source,
+            ..Searcher::new(index, settings)?
+        })
+    }
+
+    /// Search `index`, built with `settings`, e.g. an `InMemoryIndex` that
+    /// wasn't written to disk (see `InMemoryIndex::to_parsed`).
+    pub fn new(
+        index: ParsedIndex,
+        settings: IndexSettings,
+    ) -> Result<Searcher> {
+        let analyzer = settings
+            .analyzer
+            .build_for(language::most_common(&index))?
+            .with_case_forms(CaseForms::Folded);
+        let stats = CorpusStats::from_index(&index);
+        Ok(Searcher {
+            index,
+            config: settings.analyzer,
+            analyzer,
+            stats,
+            scorer: Box::new(Bm25::default()),
+            boosts: vec![(Field::Title, DEFAULT_TITLE_BOOST)],
+            facets: FacetValues::default(),
+            source: None,
+        })
+    }
+
+    /// Rank results with `scorer` instead.
+    pub fn with_scorer<S: Scorer + 'static>(mut self, scorer: S) -> Searcher {
+        self.scorer = Box::new(scorer);
+        self
+    }
+
+    /// When a word is searched for in the body, also score its matches in
+    /// `field`, multiplied by `boost` (0 turns this off). This affects the
+    /// ranking only, not which documents match.
+    pub fn with_field_boost(mut self, field: Field, boost: f64) -> Searcher {
+        self.boosts.retain(|(f, _)| *f != field);
+        if field != Field::Body && boost > 0.0 {
+            self.boosts.push((field, boost));
+        }
+        self
+    }
+
+    /// Record that the index was loaded from `source` (see `source`).
+    pub(crate) fn with_source(mut self, source: PathBuf) -> Searcher {
+        self.source = Some(source);
+        self
+    }
+
+    /// Count the matching documents of every search by the values of
+    /// `facets` (see `SearchResults::facets`).
+    pub fn with_facets(mut self, facets: &[Facet]) -> Searcher {
+        self.facets = FacetValues::new(facets, &self.index.docs);
+        self
+    }
+
+    /// Analyze queries as text in `language` (e.g. "french"), for indexes
+    /// whose documents were analyzed according to their language.
+    pub fn with_language(mut self, language: &str) -> Result<Searcher> {
+        let forms = self.analyzer.case_forms();
+        self.analyzer = self
+            .config
+            .build_for(Some(language))?
+            .with_case_forms(forms);
+        Ok(self)
+    }
+
+    /// Match query words only in the case they are written in, or in any
+    /// case (the default). Fails for a case-sensitive search of an index
+    /// that doesn't preserve case.
+    pub fn with_case_sensitive(mut self, sensitive: bool) -> Result<Searcher> {
+        if sensitive && !self.config.preserve_case {
+            return Err(Error::Unsupported(
+                "case-sensitive search needs an index that preserves case"
+                    .to_string(),
+            ));
+        }
+        let forms = if sensitive {
+            CaseForms::Exact
+        } else {
+            CaseForms::Folded
+        };
+        self.analyzer =
+            std::mem::take(&mut self.analyzer).with_case_forms(forms);
+        Ok(self)
+    }
+
+    /// The loaded index.
+    pub fn index(&self) -> &ParsedIndex {
+        &self.index
+    }
+
+    /// Statistics of the indexed documents, used for ranking.
+    pub fn stats(&self) -> &CorpusStats {
+        &self.stats
+    }
+
+    /// The number of documents containing `term`, as the index stores it
+    /// (see `analyzer`), and of its occurrences in them.
+    pub fn term_stats(&self, term: &str) -> TermStats {
+        match self.index.map.get(term) {
+            Some(postings) => TermStats::of_postings(term, postings),
+            None => TermStats {
+                term: term.to_string(),
+                ..TermStats::default()
+            },
+        }
+    }
+
+    /// Statistics of the whole index, for ranking documents or analyzing
+    /// the corpus outside of the crate.
+    pub fn collection_stats(&self) -> CollectionStats {
+        let postings = self
+            .index
+            .map
+            .iter()
+            .filter(|(term, _)| !is_twin_term(term))
+            .map(|(_, postings)| postings);
+        CollectionStats::new(
+            &self.stats,
+            postings.clone().count(),
+            postings.clone().map(|p| p.len() as u64).sum(),
+            postings
+                .flat_map(|p| p.values())
+                .map(|o| o.len() as u64)
+                .sum(),
+        )
+    }
+
+    /// The analyzer the index was built with, producing the forms of words
+    /// that queries look up.
+    pub fn analyzer(&self) -> &TextAnalyzer {
+        &self.analyzer
+    }
+
+    /// The analyzer settings the index was built with, those of its routes
+    /// included.
+    pub fn config(&self) -> &AnalyzerConfig {
+        &self.config
+    }
+
+    /// What the document table records about `doc`, if it is in the index.
+    pub fn doc_info(&self, doc: &Doc) -> Option<&DocInfo> {
+        self.index.docs.get(doc)
+    }
+
+    /// The file or directory the index was loaded from, if it was.
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    /// How the score of `hit`, one of `results`, adds up, from the terms of
+    /// the search that occur in it. `None` if it isn't in the index.
+    pub fn explain(
+        &self,
+        results: &SearchResults,
+        hit: &SearchHit,
+    ) -> Option<HitExplanation> {
+        self.doc_info(&hit.doc)?;
+        let df = |term: &str| self.index.map.get(term).map_or(0, |e| e.len());
+        Some(HitExplanation {
+            index: 0,
+            source: self.source.clone(),
+            terms: score::explain(
+                &self.index,
+                &self.stats,
+                &self.weighted(&results.terms),
+                &hit.doc,
+                self.scorer.as_ref(),
+                &df,
+            ),
+        })
+    }
+
+    /// Parse `query` and run it. Fails only if the query can't be parsed.
+    pub fn query(&self, query: &str) -> Result<SearchResults> {
+        let query = Query::parse_with(query, &self.analyzer)?;
+        Ok(self.run(&query))
+    }
+
+    /// Run an already parsed query.
+    pub fn run(&self, query: &Query) -> SearchResults {
+        let terms = query.terms_in(&self.index);
+        let docs = query.evaluate(&self.index);
+        self.rank(terms, docs)
+    }
+
+    /// Find the documents containing any of `terms`. Each term is analyzed
+    /// first, so it may turn into several index terms, or none. Terms may
+    /// have a field prefix, like `title:rust`.
+    pub fn any_of<S: AsRef<str>>(&self, terms: &[S]) -> SearchResults {
+        let (terms, docs) = self.match_any(terms);
+        self.rank(terms, docs)
+    }
+
+    /// The index terms of `terms`, and the documents containing any of them.
+    fn match_any<S: AsRef<str>>(
+        &self,
+        terms: &[S],
+    ) -> (Vec<String>, HashSet<Doc>) {
+        let terms = analyze_terms(&self.analyzer, terms);
+        let docs = terms
+            .iter()
+            .filter_map(|term| self.index.map.get(term))
+            .flat_map(|entry| entry.keys().cloned())
+            .collect();
+        (terms, docs)
+    }
+
+    fn rank(&self, terms: Vec<String>, docs: HashSet<Doc>) -> SearchResults {
+        let facets = self.facets.count(&docs);
+        let weighted = self.weighted(&terms);
+        let scorer = self.scorer.as_ref();
+        let hits =
+            rank_weighted(&self.index, &self.stats, &weighted, docs, scorer)
+                .into_iter()
+                .map(|(doc, score)| SearchHit { doc, score })
+                .collect();
+        SearchResults {
+            terms,
+            hits,
+            facets,
+        }
+    }
+
+    /// `terms` with their weights, plus the boosted fields of body terms.
+    fn weighted(&self, terms: &[String]) -> Vec<(String, f64)> {
+        weighted(terms, &self.boosts)
+    }
+}
+
+/// The index terms of `terms`, analyzed with `analyzer`, in order. Terms
+/// may have a field prefix, like `title:rust`.
+fn analyze_terms<S: AsRef<str>>(
+    analyzer: &TextAnalyzer,
+    terms: &[S],
+) -> Vec<String> {
+    terms
+        .iter()
+        .flat_map(|term| {
+            let (field, text) = Field::split(term.as_ref());
+            analyzer
+                .analyze(text)
+                .into_iter()
+                .map(move |token| field.term(&token.text))
+        })
+        .collect()
+}
+
+/// `terms` with their weights, plus the fields of body terms boosted by
+/// `boosts`.
+fn weighted(terms: &[String], boosts: &[(Field, f64)]) -> Vec<(String, f64)> {
+    let mut weighted = vec![];
+    for term in terms {
+        weighted.push((term.clone(), 1.0));
+        if Field::of_term(term) == Field::Body {
+            weighted.extend(
+                boosts
+                    .iter()
+                    .map(|(field, boost)| (field.term(term), *boost)),
+            );
+        }
+    }
+    weighted
+}
+
+/// Several index files searched as one.
+///
+/// A document found in more than one index is only returned once, scored
+/// against the first index (in the order they were opened) that matched it.
+pub struct MultiSearcher {
+    searchers: Vec<Searcher>,
+    stats: CorpusStats,
+    scorer: Box<dyn Scorer>,
+    facets: FacetValues,
+}
+
+impl MultiSearcher {
+    /// Load `index_files` for searching, ranking results with BM25.
+    ///
+    /// Each index analyzes queries with its own analyzer, like `Searcher`,
+    /// so indexes built with different settings can be searched together.
+    pub fn open<P: AsRef<Path>>(index_files: &[P]) -> Result<MultiSearcher> {
+        let searchers = index_files
+            .iter()
+            .map(Searcher::open)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(MultiSearcher::new(searchers))
+    }
+
+    /// Search `searchers` as one. Their scorers are replaced by BM25.
+    pub fn new(searchers: Vec<Searcher>) -> MultiSearcher {
+        let stats = CorpusStats::combine(searchers.iter().map(|s| &s.stats));
+        MultiSearcher {
+            searchers,
+            stats,
+            scorer: Box::new(Bm25::default()),
+            facets: FacetValues::default(),
+        }
+    }
+
+    /// Rank results with `scorer` instead.
+    pub fn with_scorer<S: Scorer + 'static>(
+        mut self,
+        scorer: S,
+    ) -> MultiSearcher {
+        self.scorer = Box::new(scorer);
+        self
+    }
+
+    /// Like `Searcher::with_field_boost`, for every index.
+    pub fn with_field_boost(self, field: Field, boost: f64) -> MultiSearcher {
+        MultiSearcher {
+            searchers: self
+                .searchers
+                .into_iter()
+                .map(|s| s.with_field_boost(field, boost))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Like `Searcher::with_facets`, over the documents of all indexes, so
+    /// that a directory is the same in all of them.
+    pub fn with_facets(mut self, facets: &[Facet]) -> MultiSearcher {
+        let docs = self.searchers.iter().flat_map(|s| &s.index.docs);
+        self.facets = FacetValues::new(facets, docs);
+        self
+    }
+
+    /// Like `Searcher::with_language`, for every index.
+    pub fn with_language(self, language: &str) -> Result<MultiSearcher> {
+        Ok(MultiSearcher {
+            searchers: self
+                .searchers
+                .into_iter()
+                .map(|s| s.with_language(language))
+                .collect::<Result<_>>()?,
+            ..self
+        })
+    }
+
+    /// Like `Searcher::with_case_sensitive`, for every index.
+    pub fn with_case_sensitive(self, sensitive: bool) -> Result<MultiSearcher> {
+        Ok(MultiSearcher {
+            searchers: self
+                .searchers
+                .into_iter()
+                .map(|s| s.with_case_sensitive(sensitive))
+                .collect::<Result<_>>()?,
+            ..self
+        })
+    }
+
+    /// The searchers of each index, in the order they were given.
+    pub fn searchers(&self) -> &[Searcher] {
+        &self.searchers
+    }
+
+    /// Statistics of the documents of all indexes, used for ranking.
+    pub fn stats(&self) -> &CorpusStats {
+        &self.stats
+    }
+
+    /// The searcher of the first index that contains `doc`, for its offsets
+    /// or its analyzer.
+    pub fn searcher_of(&self, doc: &Doc) -> Option<&Searcher> {
+        self.searchers.iter().find(|s| s.doc_info(doc).is_some())
+    }
+
+    /// What the document table of the first index that contains `doc`
+    /// records about it.
+    pub fn doc_info(&self, doc: &Doc) -> Option<&DocInfo> {
+        self.searchers.iter().find_map(|s| s.doc_info(doc))
+    }
+
+    /// Parse `query` with the analyzer of each index and run it. Fails only
+    /// if the query can't be parsed.
+    pub fn query(&self, query: &str) -> Result<SearchResults> {
+        let queries = self
+            .searchers
+            .iter()
+            .map(|s| Query::parse_with(query, &s.analyzer))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let runs = self.searchers.iter().map(|s| &s.index).zip(&queries);
+        Ok(self.rank(evaluate_all(runs.collect())))
+    }
+
+    /// Run an already parsed query against every index.
+    pub fn run(&self, query: &Query) -> SearchResults {
+        let runs = self.searchers.iter().map(|s| (&s.index, query));
+        self.rank(evaluate_all(runs.collect()))
+    }
+
+    /// Like `Searcher::any_of`, in every index.
+    pub fn any_of<S: AsRef<str>>(&self, terms: &[S]) -> SearchResults {
+        self.rank(self.searchers.iter().map(|s| s.match_any(terms)).collect())
+    }
+
+    /// Like `Searcher::explain`, for a document scored in the first index
+    /// that contains it. If the indexes analyze the search differently, the
+    /// terms that only the others found are counted too where they occur.
+    pub fn explain(
+        &self,
+        results: &SearchResults,
+        hit: &SearchHit,
+    ) -> Option<HitExplanation> {
+        let (index, searcher) = self
+            .searchers
+            .iter()
+            .enumerate()
+            .find(|(_, s)| s.doc_info(&hit.doc).is_some())?;
+        let df = self.doc_frequencies(&results.terms);
+        let df = |term: &str| df.get(term).copied().unwrap_or(0);
+        Some(HitExplanation {
+            index,
+            source: searcher.source.clone(),
+            terms: score::explain(
+                &searcher.index,
+                &self.stats,
+                &searcher.weighted(&results.terms),
+                &hit.doc,
+                self.scorer.as_ref(),
+                &df,
+            ),
+        })
+    }
+
+    /// Number of documents containing each of `terms`, and the terms of the
+    /// fields they are boosted in, in all indexes.
+    fn doc_frequencies(&self, terms: &[String]) -> HashMap<String, usize> {
+        self.searchers
+            .iter()
+            .flat_map(|s| s.weighted(terms))
+            .map(|(term, _)| {
+                let df = self
+                    .searchers
+                    .iter()
+                    .filter_map(|s| s.index.map.get(&term))
+                    .map(|entry| entry.len())
+                    .sum();
+                (term, df)
+            })
+            .collect()
+    }
+
+    /// Rank together the terms and documents each index matched, in the
+    /// order of `self.searchers`.
+    fn rank(&self, matches: Vec<(Vec<String>, HashSet<Doc>)>) -> SearchResults {
+        let mut terms = vec![];
+        for term in matches.iter().flat_map(|(terms, _)| terms) {
+            if !terms.contains(term) {
+                terms.push(term.clone());
+            }
+        }
+        let df = self.doc_frequencies(&terms);
+        let df = |term: &str| df.get(term).copied().unwrap_or(0);
+
+        let mut seen = HashSet::new();
+        let mut ranked = vec![];
+        for (searcher, (terms, docs)) in self.searchers.iter().zip(matches) {
+            let docs: Vec<Doc> = docs
+                .into_iter()
+                .filter(|doc| seen.insert(doc.clone()))
+                .collect();
+            ranked.extend(score::score(
+                &searcher.index,
+                &self.stats,
+                &searcher.weighted(&terms),
+                docs,
+                self.scorer.as_ref(),
+                &df,
+            ));
+        }
+        score::sort_ranked(&mut ranked);
+        let hits = ranked
+            .into_iter()
+            .map(|(doc, score)| SearchHit { doc, score })
+            .collect();
+        SearchResults {
+            terms,
+            hits,
+            facets: self.facets.count(&seen),
+        }
+    }
+}
+
+/// Run each query against its index, on a thread per index if there are
+/// several and threads can be spawned (not on `wasm32`), and return the
+/// terms each found in its index and the documents it matched, in order.
+fn evaluate_all(
+    runs: Vec<(&ParsedIndex, &Query)>,
+) -> Vec<(Vec<String>, HashSet<Doc>)> {
+    let evaluate = |(index, query): (&ParsedIndex, &Query)| {
+        (query.terms_in(index), query.evaluate(index))
+    };
+    if runs.len() <= 1 || cfg!(target_arch = "wasm32") {
+        return runs.into_iter().map(evaluate).collect();
+    }
+    thread::scope(|scope| {
+        let workers: Vec<_> = runs
+            .into_iter()
+            .map(|run| scope.spawn(move || evaluate(run)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("search thread panicked"))
+            .collect()
+    })
+}
+
+/// Ranks the documents of an index file for a few terms without loading the
+/// index: opening it reads its table of contents and document table, and a
+/// search the hits of its terms.
+///
+/// Rather than scoring every document that contains a term, a search keeps
+/// the `k` best documents found so far, and only scores a document if the
+/// most its terms can score there is enough to beat the `k`th of them. The
+/// table of contents records how much each term can score at most in every
+/// block of its hits (see `read::BlockMax`), so whole blocks are passed over
+/// when they can't compete, using their skip points.
+///
+/// The results are those of `Searcher::any_of` with the same terms, cut to
+/// the first `k`, except that the number of documents containing a term
+/// still counts deleted documents until the index is compacted (see
+/// `IndexFileSearcher::doc_frequency`). This holds for scorers whose scores
+/// never decrease with the number of occurrences of a term, nor increase
+/// with the length of the document, like `Bm25` and `TfIdf`.
+pub struct TopKSearcher {
+    index: IndexFileSearcher,
+    analyzer: TextAnalyzer,
+    stats: CorpusStats,
+    scorer: Box<dyn Scorer>,
+    boosts: Vec<(Field, f64)>,
+}
+
+impl TopKSearcher {
+    /// Open `index_file` for searching, ranking results with BM25.
+    pub fn open<P: AsRef<Path>>(index_file: P) -> Result<TopKSearcher> {
+        let index = IndexFileSearcher::open(index_file)?;
+        let live = index
+            .docs()
+            .iter()
+            .filter(|(doc, _)| !index.is_deleted(doc))
+            .map(|(doc, info)| (doc, info));
+        let stats = CorpusStats::from_docs(live);
+        let analyzer = index
+            .settings()
+            .analyzer
+            .build()?
+            .with_case_forms(CaseForms::Folded);
+        Ok(TopKSearcher {
+            index,
+            analyzer,
+            stats,
+            scorer: Box::new(Bm25::default()),
+            boosts: vec![(Field::Title, DEFAULT_TITLE_BOOST)],
+        })
+    }
+
+    /// Rank results with `scorer` instead.
+    pub fn with_scorer<S: Scorer + 'static>(
+        mut self,
+        scorer: S,
+    ) -> TopKSearcher {
+        self.scorer = Box::new(scorer);
+        self
+    }
+
+    /// Like `Searcher::with_field_boost`.
+    pub fn with_field_boost(
+        mut self,
+        field: Field,
+        boost: f64,
+    ) -> TopKSearcher {
+        self.boosts.retain(|(f, _)| *f != field);
+        if field != Field::Body && boost > 0.0 {
+            self.boosts.push((field, boost));
+        }
+        self
+    }
+
+    /// Statistics of the live documents of the index, used for ranking.
+    pub fn stats(&self) -> &CorpusStats {
+        &self.stats
+    }
+
+    /// The `k` best documents containing any of `terms`, analyzed like in
+    /// `Searcher::any_of`. Unlike there, the results only hold the best `k`
+    /// documents, not all those that match, and aren't counted by facet.
+    pub fn any_of<S: AsRef<str>>(
+        &mut self,
+        terms: &[S],
+        k: usize,
+    ) -> Result<SearchResults> {
+        let terms = analyze_terms(&self.analyzer, terms);
+        let weighted = weighted(&terms, &self.boosts);
+        let hits = self.top_k(&terms, &weighted, k)?;
+        Ok(SearchResults {
+            terms,
+            hits,
+            facets: FacetCounts::default(),
+        })
+    }
+
+    /// The `k` best documents containing any of `matching`, scored with the
+    /// weighted terms `terms`, best first.
+    fn top_k(
+        &mut self,
+        matching: &[String],
+        terms: &[(String, f64)],
+        k: usize,
+    ) -> Result<Vec<SearchHit>> {
+        if k == 0 {
+            return Ok(vec![]);
+        }
+        let mut entries = vec![];
+        for (term, weight) in terms {
+            if let Some(entry) = self.index.entry(term) {
+                let hits = self.index.read_hits(&entry)?;
+                entries.push((entry, hits, *weight));
+            }
+        }
+        let positions = !self.index.settings().omit_positions;
+        let scorer = self.scorer.as_ref();
+        let stats = &self.stats;
+        let docs = self.index.docs();
+
+        let mut cursors = vec![];
+        for (entry, hits, weight) in &entries {
+            let field = Field::of_term(&entry.term);
+            let score = |tf: u32, len: u32| {
+                let len = stats.normalize(field, len);
+                weight * scorer.score(tf, entry.doc_count, len, stats)
+            };
+            let blocks: Vec<f64> = entry
+                .blocks
+                .iter()
+                .map(|b| score(b.max_tf, b.min_len))
+                .collect();
+            let mut postings = Postings::new(hits, entry, positions);
+            cursors.push(TermCursor {
+                doc: postings.next()?,
+                postings,
+                bound: blocks.iter().copied().fold(0.0, f64::max),
+                blocks,
+                weight: *weight,
+                df: entry.doc_count,
+                field,
+                matches: matching.contains(&entry.term),
+            });
+        }
+
+        // The best documents so far, the worst on top.
+        let mut best: BinaryHeap<Reverse<Ranked>> = BinaryHeap::new();
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        loop {
+            // What a document must score to get in.
+            let threshold = match best.peek() {
+                Some(Reverse(worst)) if best.len() == k => worst.score,
+                _ => f64::NEG_INFINITY,
+            };
+            order.retain(|&i| cursors[i].doc.is_some());
+            order.sort_by_key(|&i| cursors[i].doc);
+
+            // The first document that enough terms, taken from the first
+            // document on, could make score high enough.
+            let mut bound = 0.0;
+            let Some(pivot) = order.iter().position(|&i| {
+                bound += cursors[i].bound;
+                !below(bound, threshold)
+            }) else {
+                break;
+            };
+            let id = cursors[order[pivot]].doc.unwrap();
+            let lead = order.partition_point(|&i| cursors[i].doc <= Some(id));
+            let (lead, rest) = order.split_at(lead);
+
+            let bound: f64 =
+                lead.iter().map(|&i| cursors[i].block_bound(id)).sum();
+            if below(bound, threshold) {
+                // Nothing before the end of the first of the blocks of the
+                // leading terms can compete.
+                let end = lead.iter().map(|&i| cursors[i].block_end(id)).min();
+                let next = end
+                    .and_then(|end| end.checked_add(1))
+                    .into_iter()
+                    .chain(rest.first().and_then(|&i| cursors[i].doc))
+                    .min();
+                let Some(next) = next else {
+                    break;
+                };
+                for &i in lead {
+                    cursors[i].advance_to(next)?;
+                }
+            } else if cursors[lead[0]].doc == Some(id) {
+                let (doc, info) = docs.get(id as usize).ok_or_else(|| {
+                    Error::format(format!(
+                        "hit refers to unknown document id {}",
+                        id
+                    ))
+                })?;
+                // Boosted fields only score documents, like in `rank`.
+                let matches =
+                    cursors.iter().any(|c| c.doc == Some(id) && c.matches);
+                if matches && !self.index.is_deleted(doc) {
+                    // Summed in the order of the terms, as `rank` does.
+                    let score = cursors
+                        .iter()
+                        .filter(|c| c.doc == Some(id))
+                        .map(|c| {
+                            let len =
+                                stats.normalize(c.field, info.len_of(c.field));
+                            c.weight
+                                * scorer.score(
+                                    c.postings.tf(),
+                                    c.df,
+                                    len,
+                                    stats,
+                                )
+                        })
+                        .sum();
+                    let ranked = Ranked {
+                        doc: doc.clone(),
+                        score,
+                    };
+                    if best.len() < k {
+                        best.push(Reverse(ranked));
+                    } else if best.peek().is_some_and(|Reverse(w)| ranked > *w)
+                    {
+                        best.pop();
+                        best.push(Reverse(ranked));
+                    }
+                }
+                for &i in lead {
+                    cursors[i].next()?;
+                }
+            } else {
+                for &i in lead {
+                    cursors[i].advance_to(id)?;
+                }
+            }
+        }
+
+        Ok(best
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(r)| SearchHit {
+                doc: r.doc,
+                score: r.score,
+            })
+            .collect())
+    }
+}
+
+/// A term of a `TopKSearcher` search, with a cursor over its hits.
+struct TermCursor<'a> {
+    postings: Postings<'a>,
+    /// The document the cursor is on, `None` past the last.
+    doc: Option<u32>,
+    /// The most the term can score in each block of its hits, weight
+    /// included.
+    blocks: Vec<f64>,
+    /// The most the term can score in any document.
+    bound: f64,
+    weight: f64,
+    df: u32,
+    field: Field,
+    /// Whether the term was searched for, rather than only boosted.
+    matches: bool,
+}
+
+impl TermCursor<'_> {
+    fn next(&mut self) -> Result<()> {
+        self.doc = self.postings.next()?;
+        Ok(())
+    }
+
+    fn advance_to(&mut self, id: u32) -> Result<()> {
+        self.doc = self.postings.advance_to(id)?;
+        Ok(())
+    }
+
+    /// The most the term can score in the document with id `id`.
+    fn block_bound(&self, id: u32) -> f64 {
+        let block = self.postings.block_of(id);
+        self.blocks.get(block).copied().unwrap_or(self.bound)
+    }
+
+    /// Id of the last document of the block that would hold `id`.
+    fn block_end(&self, id: u32) -> u32 {
+        self.postings.block_end(self.postings.block_of(id))
+    }
+}
+
+/// A scored document, ordered best first like `score::sort_ranked` does:
+/// by score, then by hash.
+#[derive(Debug, PartialEq)]
+struct Ranked {
+    doc: Doc,
+    score: f64,
+}
+
+impl Eq for Ranked {}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Ranked) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.doc.hash.cmp(&self.doc.hash))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Ranked) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// True if a document whose score is at most `bound` can't reach
+/// `threshold`. Bounds and scores are sums of the same numbers in different
+/// orders, so they are compared with some slack for rounding.
+fn below(bound: f64, threshold: f64) -> bool {
+    bound < threshold - threshold.abs() * 1e-9
+}
+
+impl SearchResults {
+    /// Number of matching documents.
+    pub fn len(&self) -> usize {
+        self.hits.len()
+    }
+
+    /// True if no document matched.
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    /// The index terms the results were ranked by.
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    /// All matching documents, best first.
+    pub fn hits(&self) -> &[SearchHit] {
+        &self.hits
+    }
+
+    /// Number of matching documents with each value of the facets the
+    /// searcher was set up with.
+    pub fn facets(&self) -> &FacetCounts {
+        &self.facets
+    }
+
+    /// The `page`-th (counting from 0) group of `per_page` hits. Pages past
+    /// the end are empty.
+    pub fn page(&self, page: usize, per_page: usize) -> &[SearchHit] {
+        self.range(page.saturating_mul(per_page), per_page)
+    }
+
+    /// At most `limit` hits, from the `offset`-th (counting from 0) on.
+    pub fn range(&self, offset: usize, limit: usize) -> &[SearchHit] {
+        let start = offset.min(self.hits.len());
+        let end = start.saturating_add(limit).min(self.hits.len());
+        &self.hits[start..end]
+    }
+
+    /// Offset of the first hit ranked after `cursor`, where the page after
+    /// it starts.
+    pub fn offset_after(&self, cursor: &Cursor) -> usize {
+        self.hits.partition_point(|hit| {
+            match hit.score.total_cmp(&cursor.score) {
+                Ordering::Equal => hit.doc.hash <= cursor.doc.hash,
+                order => order == Ordering::Greater,
+            }
+        })
+    }
+
+    /// The cursor at the end of `range(offset, limit)`, or `None` if no hit
+    /// comes after it.
+    pub fn cursor_after(&self, offset: usize, limit: usize) -> Option<Cursor> {
+        let end = offset.saturating_add(limit);
+        if limit == 0 || end >= self.hits.len() {
+            return None;
+        }
+        let last = &self.hits[end - 1];
+        Some(Cursor {
+            score: last.score,
+            doc: last.doc.clone(),
+        })
+    }
+
+    /// Number of pages of `per_page` hits.
+    pub fn page_count(&self, per_page: usize) -> usize {
+        match per_page {
+            0 => 0,
+            n => self.hits.len().div_ceil(n),
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}{}", self.score.to_bits(), self.doc.to_hex())
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> std::result::Result<Cursor, InvalidCursor> {
+        let score = s.get(..16).ok_or(InvalidCursor)?;
+        let score =
+            u64::from_str_radix(score, 16).map_err(|_| InvalidCursor)?;
+        let doc = Doc::from_hex(&s[16..]).ok_or(InvalidCursor)?;
+        Ok(Cursor {
+            score: f64::from_bits(score),
+            doc,
+        })
+    }
+}
+
+impl IntoIterator for SearchResults {
+    type Item = SearchHit;
+    type IntoIter = std::vec::IntoIter<SearchHit>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.hits.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::fixtures::{doc, write_index};
+    use crate::index::InMemoryIndex;
+    use crate::score::TfIdf;
+    use crate::tmp::TmpDir;
+    use crate::tombstone::Tombstones;
+    use crate::write::write_index_to_tmp_file;
+    use crate::HASH_LENGTH;
+    use std::path::PathBuf;
+
+    /// A searcher over `texts`, the document `i` having the hash `[i; 32]`.
+    fn searcher(dir: &Path, texts: &[&str]) -> Searcher {
+        Searcher::open(write_index(dir, texts)).unwrap()
+    }
+
+    #[test]
+    fn documents_matched_by_no_term_score_zero() {
+        let dir = tempfile::tempdir().unwrap();
+        let searcher = searcher(dir.path(), &["the cat", "a dog"]);
+        for query in ["size:0", "NOT cat"] {
+            let results = searcher.query(query).unwrap();
+            assert!(!results.is_empty(), "{}", query);
+            for hit in results.into_iter() {
+                assert_eq!(hit.score.to_bits(), 0.0f64.to_bits(), "{}", query);
+            }
+        }
+    }
+
+    #[test]
+    fn queries_find_and_rank_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let searcher = searcher(
+            dir.path(),
+            &["the cat sat", "cat and cat and dog", "a dog", "a bird"],
+        );
+        let results = searcher.query("cat").unwrap();
+        assert_eq!(results.terms(), ["cat"]);
+        let docs: Vec<_> = results.hits().iter().map(|h| &h.doc).collect();
+        assert_eq!(docs, [&doc(1), &doc(0)]);
+        assert!(results.hits()[0].score > results.hits()[1].score);
+
+        let results = searcher.query("Cat AND dog").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results.hits()[0].doc, doc(1));
+        assert!(searcher.query("horse").unwrap().is_empty());
+        assert!(matches!(searcher.query("(cat"), Err(Error::Query(_))));
+
+        let results = searcher.any_of(&["BIRD", "sat"]);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn results_come_in_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["a", "a b", "a b c", "a b c d", "a b c d e"];
+        let results = searcher(dir.path(), &texts).query("a").unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results.page_count(2), 3);
+        assert_eq!(results.page_count(0), 0);
+        assert_eq!(results.page(0, 2), &results.hits()[..2]);
+        assert_eq!(results.page(2, 2), &results.hits()[4..]);
+        assert!(results.page(3, 2).is_empty());
+        assert!(results.page(usize::MAX, usize::MAX).is_empty());
+        assert_eq!(results.into_iter().count(), 5);
+    }
+
+    #[test]
+    fn cursors_resume_after_their_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["a", "a b", "a b c", "a b c d", "a b c d e"];
+        let results = searcher(dir.path(), &texts).query("a").unwrap();
+        let mut seen = vec![];
+        let mut offset = 0;
+        loop {
+            seen.extend_from_slice(results.range(offset, 2));
+            let Some(cursor) = results.cursor_after(offset, 2) else {
+                break;
+            };
+            let cursor: Cursor = cursor.to_string().parse().unwrap();
+            offset = results.offset_after(&cursor);
+        }
+        assert_eq!(seen, results.hits());
+        assert_eq!(results.cursor_after(0, 0), None);
+        assert_eq!("nonsense".parse::<Cursor>(), Err(InvalidCursor));
+    }
+
+    #[test]
+    fn empty_index_finds_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let searcher = searcher(dir.path(), &[]);
+        assert!(searcher.index().map.is_empty());
+        let results = searcher.query("cat").unwrap();
+        assert!(results.is_empty());
+        assert_eq!(results.page_count(10), 0);
+        assert!(searcher.doc_info(&doc(0)).is_none());
+    }
+
+    /// An index file of `n` documents of words `w0` to `w49`, drawn so that
+    /// the first words are the most common, holding some more than once.
+    fn corpus_file(dir: &Path, n: u32) -> PathBuf {
+        let mut index = InMemoryIndex::new();
+        let mut seed = 7u64;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as usize
+        };
+        for i in 0..n {
+            let len = 1 + next() % 30;
+            let words: Vec<_> = (0..len)
+                .map(|_| format!("w{}", (next() % 50) * (next() % 50) / 50))
+                .collect();
+            let mut hash = [0; HASH_LENGTH];
+            hash[..4].copy_from_slice(&i.to_le_bytes());
+            index.merge(InMemoryIndex::from_single_document(
+                &hash,
+                words.join(" "),
+            ));
+        }
+        write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir),
+            &IndexSettings::default(),
+        )
+        .unwrap()
+    }
+
+    /// Check that `top_k` holds the first `k` hits of `all`, with the same
+    /// scores, give or take rounding.
+    fn assert_top_k(all: &SearchResults, top_k: &SearchResults, k: usize) {
+        let expected = &all.hits()[..k.min(all.len())];
+        assert_eq!(top_k.len(), expected.len(), "k = {}", k);
+        for (expected, actual) in expected.iter().zip(top_k.hits()) {
+            assert_eq!(actual.doc, expected.doc, "k = {}", k);
+            assert!((actual.score - expected.score).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn top_k_matches_exhaustive_ranking() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = corpus_file(dir.path(), 700);
+        let bm25 = Searcher::open(&file).unwrap();
+        let tf_idf = Searcher::open(&file).unwrap().with_scorer(TfIdf);
+        let mut bm25_top_k = TopKSearcher::open(&file).unwrap();
+        let mut tf_idf_top_k =
+            TopKSearcher::open(&file).unwrap().with_scorer(TfIdf);
+        let queries: [&[&str]; 5] = [
+            &["w0"],
+            &["w1", "w2"],
+            &["w0", "w5", "w20", "w48"],
+            &["w30", "W30", "w3"],
+            &["w10", "nothing"],
+        ];
+        for terms in queries {
+            let bm25_all = bm25.any_of(terms);
+            let tf_idf_all = tf_idf.any_of(terms);
+            assert!(!bm25_all.is_empty());
+            for k in [1, 3, 10, 50, 1000] {
+                let found = bm25_top_k.any_of(terms, k).unwrap();
+                assert_eq!(found.terms(), bm25_all.terms());
+                assert_top_k(&bm25_all, &found, k);
+                let found = tf_idf_top_k.any_of(terms, k).unwrap();
+                assert_top_k(&tf_idf_all, &found, k);
+            }
+        }
+        assert!(bm25_top_k.any_of(&["w0"], 0).unwrap().is_empty());
+        assert!(bm25_top_k.any_of(&["nothing"], 10).unwrap().is_empty());
+        let none: [&str; 0] = [];
+        assert!(bm25_top_k.any_of(&none, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn top_k_leaves_out_deleted_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = corpus_file(dir.path(), 300);
+        let terms = ["w0", "w1"];
+        let before = Searcher::open(&file).unwrap().any_of(&terms);
+        let deleted = before.hits()[0].doc.clone();
+        let mut tombstones = Tombstones::default();
+        tombstones.add(deleted.clone());
+        tombstones.save(&file).unwrap();
+
+        let found = TopKSearcher::open(&file)
+            .unwrap()
+            .any_of(&terms, 10)
+            .unwrap();
+        assert!(found.hits().iter().all(|hit| hit.doc != deleted));
+        assert_eq!(found.len(), 10);
+    }
+
+    #[test]
+    fn several_indexes_search_like_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = [
+            "the cat sat",
+            "cat and cat and dog",
+            "a dog",
+            "a bird and a cat",
+            "the end",
+        ];
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let settings = IndexSettings::default();
+        let mut write = |first: usize, texts: &[&str]| {
+            let index = crate::fixtures::index_of(first as u8, texts);
+            let file = write_index_to_tmp_file(index, &mut tmp_dir, &settings);
+            Searcher::open(file.unwrap()).unwrap()
+        };
+        let whole = write(0, &texts);
+        let parts = MultiSearcher::new(vec![
+            write(0, &texts[..2]),
+            write(2, &texts[2..4]),
+            write(4, &texts[4..]),
+        ]);
+        for query in ["cat", "cat OR dog", "a AND NOT bird", "\"the end\""] {
+            let expected = whole.query(query).unwrap();
+            let found = parts.query(query).unwrap();
+            assert_eq!(found.len(), expected.len(), "{}", query);
+            for (hit, expected) in found.hits().iter().zip(expected.hits()) {
+                assert_eq!(hit.doc, expected.doc, "{}", query);
+                assert!((hit.score - expected.score).abs() < 1e-9, "{}", query);
+            }
+        }
+        let paged = parts.query("a OR the").unwrap();
+        assert_eq!(paged.len(), 4);
+        assert_eq!(paged.page(1, 3).len(), 1);
+    }
+
+    #[test]
+    fn case_sensitive_searches_match_the_written_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AnalyzerConfig {
+            preserve_case: true,
+            ..AnalyzerConfig::default()
+        };
+        let analyzer = config.build().unwrap();
+        let mut index = InMemoryIndex::new();
+        for (i, text) in
+            ["Rust is fast", "rust on iron", "RUST"].iter().enumerate()
+        {
+            index.add_document_with(
+                &doc(i as u8).hash,
+                text.to_string(),
+                &analyzer,
+            );
+        }
+        let settings = IndexSettings {
+            analyzer: config,
+            ..IndexSettings::default()
+        };
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &settings,
+        );
+        let found = |searcher: &Searcher, query: &str| -> Vec<Doc> {
+            let results = searcher.query(query).unwrap();
+            let mut docs: Vec<Doc> =
+                results.hits().iter().map(|h| h.doc.clone()).collect();
+            docs.sort_by(|a, b| a.hash.cmp(&b.hash));
+            docs
+        };
+        let any_case = Searcher::open(file.unwrap()).unwrap();
+        assert_eq!(found(&any_case, "rust"), [doc(0), doc(1), doc(2)]);
+        assert_eq!(found(&any_case, "RUST"), [doc(0), doc(1), doc(2)]);
+        let exact = any_case.with_case_sensitive(true).unwrap();
+        let cases: [(&str, &[Doc]); 4] = [
+            ("Rust", &[doc(0)]),
+            ("rust", &[doc(1)]),
+            ("RUST", &[doc(2)]),
+            ("rUST", &[]),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(found(&exact, query), expected, "{}", query);
+        }
+        let folded = exact.with_case_sensitive(false).unwrap();
+        assert_eq!(found(&folded, "Rust").len(), 3);
+
+        let plain = searcher(dir.path(), &["Rust"]);
+        let refused = plain.with_case_sensitive(true);
+        assert!(matches!(refused, Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn explanations_add_up_to_the_scores() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = searcher(dir.path(), &["the cat sat", "a dog"]);
+        let second = tempfile::tempdir().unwrap();
+        let second = searcher(second.path(), &["", "", "cat and cat dog"]);
+        let source = second.source().unwrap().to_path_buf();
+        let searcher = MultiSearcher::new(vec![first, second]);
+
+        let results = searcher.query("cat OR dog").unwrap();
+        assert_eq!(results.len(), 3);
+        for hit in results.hits() {
+            let explained = searcher.explain(&results, hit).unwrap();
+            let total: f64 = explained.terms.iter().map(|t| t.score).sum();
+            assert!((total - hit.score).abs() < 1e-9);
+        }
+        let hit = results.hits().iter().find(|h| h.doc == doc(2)).unwrap();
+        let explained = searcher.explain(&results, hit).unwrap();
+        assert_eq!(explained.index, 1);
+        assert_eq!(explained.source, Some(source));
+        let terms: Vec<_> = explained
+            .terms
+            .iter()
+            .map(|t| (t.term.as_str(), t.tf, t.df))
+            .collect();
+        assert_eq!(terms, [("cat", 2, 2), ("dog", 1, 2)]);
+    }
+
+    #[test]
+    fn table_of_contents_statistics_match_the_postings() {
+        let dir = tempfile::tempdir().unwrap();
+        let searcher = searcher(dir.path(), &["the cat sat", "cat and cat"]);
+        let file = IndexFileSearcher::open(searcher.source().unwrap()).unwrap();
+
+        let cat = searcher.term_stats("cat");
+        assert_eq!((cat.documents, cat.occurrences), (2, 3));
+        assert_eq!(file.term_stats("cat"), cat);
+        assert_eq!(file.term_stats("bird"), searcher.term_stats("bird"));
+
+        let stats = file.collection_stats();
+        assert_eq!(stats, searcher.collection_stats());
+        assert_eq!((stats.documents, stats.words), (2, 6));
+        assert_eq!(stats.terms, searcher.index().map.len());
+        assert!(stats.occurrences >= 6);
+        assert_eq!(stats.avg_doc_len, 3.0);
+    }
+}
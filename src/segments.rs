@@ -0,0 +1,1317 @@
+//! Indexes kept as a directory of segments.
+//!
+//! Instead of a single `index.dat`, an index directory holds any number of
+//! immutable index files, the segments, listed in `segments.json` together
+//! with the settings of the index. Adding documents writes a new segment and
+//! leaves the others alone, and deleting documents only adds tombstones to
+//! the segments that hold them (see `tombstone`), so updates are cheap.
+//! Documents added replace those indexed earlier from the same paths, which
+//! are deleted, so that files can be indexed again as they change.
+//! Searches read all the live segments, with a `MultiSearcher`. A single
+//! term can be looked up with `IndexDir::lookup` without loading them; in an
+//! index built with `IndexSettings::bloom_filter`, it skips the segments
+//! whose bloom filter says they don't have the term (see `bloom`).
+//!
+//! Every update makes for more or emptier segments, which slows searches
+//! down. `IndexDir::compact` merges segments as its `MergePolicy` says (see
+//! `merge_policy`), and can run in the background while segments are added
+//! and searched.
+//!
+//! Documents can also be added to an in-memory buffer with `IndexDir::add`,
+//! which searches see right away, without waiting for a segment to be
+//! written. The buffer becomes a segment once it outgrows its budget, or on
+//! `IndexDir::flush`. Until then, the documents and the deletions of
+//! buffered ones are kept in a write-ahead log (see `wal`), which opening
+//! the directory for writing replays, so that a crash doesn't lose them.
+//!
+//! `segments.json` is replaced atomically, by writing a new version next to
+//! it and renaming it over the old one, so readers see either list. Segment
+//! files are only removed once they are no longer listed, and no reader is
+//! loading them. Only one process at a time may open the directory for
+//! writing; others can open it read-only to search it (see `lock`).
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+use std::sync::Mutex;
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::{self, JoinHandle};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::AnalyzerConfig;
+use crate::bloom::BloomFilter;
+use crate::codec::Codec;
+use crate::crypt::Key;
+use crate::error::{Error, Result};
+use crate::header::IndexSettings;
+use crate::identity::DocIdentity;
+use crate::index::{Doc, DocEntry, DocInfo, InMemoryIndex};
+use crate::indexer::Indexer;
+use crate::lock::DirLock;
+use crate::merge::{merge_index_files_with, MERGED_FILENAME};
+use crate::merge_policy::{MergePolicy, SegmentSize};
+use crate::read::{IndexFileReader, IndexFileSearcher};
+use crate::search::{MultiSearcher, Searcher};
+use crate::tmp::{publish, TmpDir};
+use crate::tombstone::Tombstones;
+use crate::wal::{Update, Wal, WAL_EXTENSION};
+use crate::write::write_index_to_tmp_file;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Name of the segment list in an index directory.
+pub const SEGMENTS_FILENAME: &str = "segments.json";
+
+/// Memory the buffer of documents added with `IndexDir::add` may use before
+/// it is written to a segment, unless configured otherwise. Searches decode
+/// the whole buffer, so it is kept much smaller than an indexer's.
+pub const DEFAULT_BUFFER_BUDGET: usize = 16 << 20;
+
+/// An index directory, open for searching and updating.
+///
+/// Its methods may be called from several threads at once; the segment list
+/// is only locked for as long as it takes to change it.
+///
+/// Documents still buffered in memory are flushed when it is dropped, but
+/// errors can only be logged then; call `flush` to see them.
+pub struct IndexDir {
+    dir: PathBuf,
+    /// The write lock, unless opened read-only.
+    writer: Option<DirLock>,
+    settings: IndexSettings,
+    policy: MergePolicy,
+    buffer_budget: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    list: SegmentList,
+    /// Names of the segments being merged by `compact`.
+    merging: HashSet<String>,
+    /// Documents added with `add` and not yet written to a segment.
+    buffer: InMemoryIndex,
+    /// Documents of `buffer` that were deleted since.
+    buffer_deleted: Tombstones,
+    /// The log of the updates of `buffer`, unless opened read-only.
+    wal: Option<Wal>,
+}
+
+/// The contents of `segments.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SegmentList {
+    /// The codec of new segments, by name.
+    codec: String,
+    /// The analyzer of all segments, as `AnalyzerConfig::encode` writes it.
+    analyzer: String,
+    /// Whether new segments end with a bloom filter of their terms.
+    #[serde(default)]
+    bloom_filter: bool,
+    /// Whether segments leave the offsets of hits out.
+    #[serde(default)]
+    omit_positions: bool,
+    /// Whether every term has a twin holding its byte offsets.
+    #[serde(default)]
+    byte_offsets: bool,
+    /// Whether every term of the body has a twin holding its line numbers.
+    #[serde(default)]
+    line_numbers: bool,
+    /// Whether segments and the log are encrypted.
+    #[serde(default)]
+    encrypted: bool,
+    /// How documents are identified, by name.
+    #[serde(default = "sha256")]
+    identity: String,
+    /// Number of the next file created in the directory.
+    next: u64,
+    /// Number of changes searches see, segments added, merged or with
+    /// documents deleted, since the directory was created (see
+    /// `IndexDir::generation`).
+    #[serde(default)]
+    generation: u64,
+    /// File name of the write-ahead log of the buffer, in the index
+    /// directory; none if it wasn't opened for writing since logs exist.
+    #[serde(default)]
+    wal: Option<String>,
+    /// The live segments, oldest first.
+    segments: Vec<Segment>,
+}
+
+/// Index directories created before document identities could be chosen
+/// identify documents by SHA-256.
+fn sha256() -> String {
+    DocIdentity::Sha256.name().to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+    /// File name, in the index directory.
+    name: String,
+    /// Number of documents, deleted ones included.
+    documents: usize,
+    /// Size of the file, in bytes.
+    bytes: u64,
+}
+
+impl IndexDir {
+    /// True if `path` is an index directory.
+    pub fn is_index_dir<P: AsRef<Path>>(path: P) -> bool {
+        path.as_ref().join(SEGMENTS_FILENAME).is_file()
+    }
+
+    /// Open the index directory `dir` for writing, or create an empty one
+    /// with `settings`. Fails if it exists with other settings.
+    pub fn create<P: AsRef<Path>>(
+        dir: P,
+        settings: IndexSettings,
+    ) -> Result<IndexDir> {
+        let dir = dir.as_ref();
+        if IndexDir::is_index_dir(dir) {
+            let index_dir = IndexDir::open(dir)?;
+            if index_dir.settings != settings {
+                return Err(Error::Incompatible(format!(
+                    "{} was built with other settings",
+                    dir.display()
+                )));
+            }
+            return Ok(index_dir);
+        }
+        fs::create_dir_all(dir)?;
+        let writer = DirLock::write(dir)?;
+        let list = SegmentList {
+            codec: settings.codec.name().to_string(),
+            analyzer: settings.analyzer.encode(),
+            bloom_filter: settings.bloom_filter,
+            omit_positions: settings.omit_positions,
+            byte_offsets: settings.byte_offsets,
+            line_numbers: settings.line_numbers,
+            encrypted: settings.encrypted,
+            identity: settings.identity.name().to_string(),
+            next: 1,
+            generation: 0,
+            wal: None,
+            segments: vec![],
+        };
+        list.save(dir)?;
+        info!("Created index directory {}", dir.display());
+        let index_dir = IndexDir::with_list(dir, Some(writer), settings, list);
+        index_dir.recover()?;
+        Ok(index_dir)
+    }
+
+    /// The generation of the index directory `dir`: a number that grows
+    /// whenever searches of it would see a change, as a segment is added,
+    /// segments are merged or documents deleted from them, so that a
+    /// process searching it can tell when to load it again. Documents
+    /// buffered by `add` count once written.
+    pub fn generation<P: AsRef<Path>>(dir: P) -> Result<u64> {
+        Ok(SegmentList::load(dir.as_ref())?.generation)
+    }
+
+    /// Open the existing index directory `dir` for writing. Documents left
+    /// buffered by a process that crashed are replayed from the log into the
+    /// buffer first. Fails with `Error::Locked` if another process has it
+    /// open for writing.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<IndexDir> {
+        let dir = dir.as_ref();
+        let writer = DirLock::write(dir)?;
+        let index_dir = IndexDir::load(dir, Some(writer))?;
+        index_dir.recover()?;
+        Ok(index_dir)
+    }
+
+    /// Open the existing index directory `dir` for searching only, even
+    /// while another process writes to it. Each `searcher` sees the segments
+    /// as they are then.
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Result<IndexDir> {
+        let dir = dir.as_ref();
+        let _reading = DirLock::read(dir)?;
+        IndexDir::load(dir, None)
+    }
+
+    fn load(dir: &Path, writer: Option<DirLock>) -> Result<IndexDir> {
+        let list = SegmentList::load(dir)?;
+        let codec = Codec::from_name(&list.codec).ok_or_else(|| {
+            Error::Unsupported(format!("unknown codec {}", list.codec))
+        })?;
+        let identity =
+            DocIdentity::from_name(&list.identity).ok_or_else(|| {
+                Error::Unsupported(format!(
+                    "unknown document identity {}",
+                    list.identity
+                ))
+            })?;
+        let settings = IndexSettings {
+            codec,
+            analyzer: AnalyzerConfig::decode(&list.analyzer)?,
+            bloom_filter: list.bloom_filter,
+            omit_positions: list.omit_positions,
+            byte_offsets: list.byte_offsets,
+            line_numbers: list.line_numbers,
+            encrypted: list.encrypted,
+            identity,
+        };
+        Ok(IndexDir::with_list(dir, writer, settings, list))
+    }
+
+    fn with_list(
+        dir: &Path,
+        writer: Option<DirLock>,
+        settings: IndexSettings,
+        list: SegmentList,
+    ) -> IndexDir {
+        IndexDir {
+            dir: dir.to_path_buf(),
+            writer,
+            settings,
+            policy: MergePolicy::default(),
+            buffer_budget: DEFAULT_BUFFER_BUDGET,
+            state: Mutex::new(State {
+                list,
+                merging: HashSet::new(),
+                buffer: InMemoryIndex::new(),
+                buffer_deleted: Tombstones::default(),
+                wal: None,
+            }),
+        }
+    }
+
+    /// Start a new log of the buffer with the updates of the one listed,
+    /// left behind by the last process to open the directory for writing,
+    /// and replay them into the buffer. Logs no longer listed are removed.
+    fn recover(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let updates = match &state.list.wal {
+            Some(name) => {
+                Wal::read(&self.dir.join(name), self.key()?.as_ref())?
+            }
+            None => vec![],
+        };
+        // Copied first, as the listed log may end with a torn update, which
+        // nothing can be appended after.
+        let wal = self.start_wal(state, &updates)?;
+        state.list.wal = Some(wal.name().to_string());
+        state.list.save(&self.dir)?;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == WAL_EXTENSION)
+                && path.file_name().is_some_and(|name| name != wal.name())
+            {
+                debug!("Removing log {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        }
+        state.wal = Some(wal);
+        if updates.is_empty() {
+            return Ok(());
+        }
+        info!(
+            "Replaying {} buffered updates of {}",
+            updates.len(),
+            self.dir.display()
+        );
+        // Without flushing for the budget, which it may be opened with
+        // another of, so that deletions find their documents buffered.
+        let mut updates = VecDeque::from(updates);
+        while let Some(update) = updates.front() {
+            if state.conflicts(update) {
+                self.flush_locked(state, updates.make_contiguous())?;
+            }
+            self.apply(state, updates.pop_front().unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Merge segments according to `policy` when compacting, instead of
+    /// `MergePolicy::default()`.
+    pub fn with_merge_policy(mut self, policy: MergePolicy) -> IndexDir {
+        self.policy = policy;
+        self
+    }
+
+    /// Write the documents added with `add` to a segment once they take
+    /// more than `bytes` bytes of memory, instead of `DEFAULT_BUFFER_BUDGET`.
+    pub fn with_buffer_budget(mut self, bytes: usize) -> IndexDir {
+        self.buffer_budget = bytes;
+        self
+    }
+
+    /// The policy `compact` merges segments by.
+    pub fn merge_policy(&self) -> &MergePolicy {
+        &self.policy
+    }
+
+    /// The directory.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The settings new segments are written with. All segments use its
+    /// analyzer.
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    /// True if opened with `open_read_only`, which leaves the index as it
+    /// is.
+    pub fn is_read_only(&self) -> bool {
+        self.writer.is_none()
+    }
+
+    /// Paths of the live segments, oldest first. Opened read-only, these are
+    /// the segments as of the last `searcher`.
+    pub fn segments(&self) -> Vec<PathBuf> {
+        let state = self.state.lock().unwrap();
+        state.list.paths(&self.dir)
+    }
+
+    /// Load every live segment for searching, along with the documents
+    /// buffered in memory.
+    pub fn searcher(&self) -> Result<MultiSearcher> {
+        let mut state = self.state.lock().unwrap();
+        let _reading = self.lock_segments(&mut state)?;
+        let mut searchers = state
+            .list
+            .paths(&self.dir)
+            .iter()
+            .map(Searcher::open)
+            .collect::<Result<Vec<_>>>()?;
+        if !state.buffer.docs.is_empty() {
+            let mut index = state.buffer.to_parsed();
+            let deleted = &state.buffer_deleted;
+            if !deleted.is_empty() {
+                for entry in index.map.values_mut() {
+                    deleted.purge(entry);
+                }
+                index.map.retain(|_, entry| !entry.is_empty());
+                index.docs.retain(|doc, _| !deleted.contains(doc));
+            }
+            searchers.push(Searcher::new(index, self.settings.clone())?);
+        }
+        Ok(MultiSearcher::new(searchers))
+    }
+
+    /// Read and decode the hits for `term`, an analyzed term, from every
+    /// live segment and the buffered documents, without loading whole
+    /// segments. Segments whose bloom filter says they don't have the term
+    /// are skipped without reading their table of contents.
+    ///
+    /// Returns `Ok(None)` if no live document contains the term.
+    pub fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        let mut state = self.state.lock().unwrap();
+        let _reading = self.lock_segments(&mut state)?;
+        let mut entry = DocEntry::new();
+        for path in state.list.paths(&self.dir) {
+            if BloomFilter::load(&path)?.is_some_and(|b| !b.might_contain(term))
+            {
+                debug!("Skipping {} for {:?}", path.display(), term);
+                continue;
+            }
+            if let Some(hits) = IndexFileSearcher::open(&path)?.lookup(term)? {
+                entry.extend(hits);
+            }
+        }
+        if let Some(mut hits) = state.buffer.lookup(term) {
+            state.buffer_deleted.purge(&mut hits);
+            entry.extend(hits);
+        }
+        Ok(Some(entry).filter(|e| !e.is_empty()))
+    }
+
+    /// Run `f` with the paths of the live segments, which aren't removed
+    /// until it returns.
+    pub(crate) fn with_segments<T>(
+        &self,
+        f: impl FnOnce(&[PathBuf]) -> Result<T>,
+    ) -> Result<T> {
+        let mut state = self.state.lock().unwrap();
+        let _reading = self.lock_segments(&mut state)?;
+        f(&state.list.paths(&self.dir))
+    }
+
+    /// Keep the segments from being removed until the returned lock is
+    /// dropped, while `state` is locked. Opened read-only, the list is
+    /// reloaded first, as another process may have changed it.
+    fn lock_segments(&self, state: &mut State) -> Result<Option<DirLock>> {
+        // No segment can be removed while the list is locked, by this
+        // process, or while the read lock is held, by a writer of another.
+        if self.writer.is_some() {
+            return Ok(None);
+        }
+        let reading = DirLock::read(&self.dir)?;
+        state.list = SegmentList::load(&self.dir)?;
+        Ok(Some(reading))
+    }
+
+    /// Index with `indexer` into a new segment, and return its path. The
+    /// output directory of `indexer` is replaced by one within the index
+    /// directory, which is removed afterwards.
+    pub fn build(&self, indexer: Indexer) -> Result<PathBuf> {
+        self.check_writable()?;
+        let staging = self.create_staging_dir()?;
+        let result = indexer
+            .output(&staging)
+            .shards(1)
+            .run()
+            .and_then(|file| self.add_file(&file));
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+
+    /// Write `index` to a new segment, and return its path.
+    pub fn add_index(&self, index: InMemoryIndex) -> Result<PathBuf> {
+        self.check_writable()?;
+        if index.is_empty() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no documents to add",
+            )));
+        }
+        let mut tmp_dir = TmpDir::new(&self.dir);
+        let file =
+            write_index_to_tmp_file(index, &mut tmp_dir, &self.settings)?;
+        self.add_file(&file)
+    }
+
+    /// Buffer the documents of `index`, which must have been analyzed with
+    /// the analyzer of the index, in memory, once they are in the log of the
+    /// buffer on disk. Searches see them at once, instead of the documents
+    /// indexed earlier from the same paths. Once the buffer is over budget,
+    /// it is written to a new segment, whose path is returned.
+    pub fn add(&self, index: InMemoryIndex) -> Result<Option<PathBuf>> {
+        self.check_writable()?;
+        let mut state = self.state.lock().unwrap();
+        let (update, mut flushed) = self.replacing(&mut state, index)?;
+        // Logged before the documents it replaces are deleted, which
+        // replaying it does again, so that a crash in between loses neither
+        // version.
+        state.log(&update)?;
+        self.apply(&mut state, update)?;
+        if state.buffer.memory_usage() > self.buffer_budget {
+            flushed = self.flush_locked(&mut state, &[])?;
+        }
+        Ok(flushed)
+    }
+
+    /// The update adding `index`, with the documents of segments it
+    /// replaces. Buffered documents it replaces, or deleted documents it
+    /// adds again, are written to a segment first, whose path is returned.
+    fn replacing(
+        &self,
+        state: &mut State,
+        index: InMemoryIndex,
+    ) -> Result<(Update, Option<PathBuf>)> {
+        let paths = replaced_paths(index.docs.iter().map(|(_, info)| info));
+        let replaces = |_: &Doc, info: &DocInfo| paths.contains(&*info.path);
+        let mut flushed = None;
+        if state.adds_deleted(&index) || !state.buffered(replaces).is_empty() {
+            flushed = self.flush_locked(state, &[])?;
+        }
+        let replaced = self.in_segments(state, replaces)?;
+        Ok((Update::Add { index, replaced }, flushed))
+    }
+
+    /// Write the documents buffered by `add` to a new segment, and return
+    /// its path, or `None` if there were none.
+    pub fn flush(&self) -> Result<Option<PathBuf>> {
+        let mut state = self.state.lock().unwrap();
+        self.flush_locked(&mut state, &[])
+    }
+
+    /// `flush`, with the state already locked, so that searches see the
+    /// buffered documents either in the buffer or in the new segment. The
+    /// segment is listed along with a new log of the buffer, which starts
+    /// with `pending`, updates being replayed that aren't in the buffer yet.
+    fn flush_locked(
+        &self,
+        state: &mut State,
+        pending: &[Update],
+    ) -> Result<Option<PathBuf>> {
+        let buffer = mem::take(&mut state.buffer);
+        let deleted = mem::take(&mut state.buffer_deleted);
+        if buffer.docs.iter().all(|(doc, _)| deleted.contains(doc)) {
+            return Ok(None);
+        }
+        let mut tmp_dir = TmpDir::new(&self.dir);
+        let file =
+            write_index_to_tmp_file(buffer, &mut tmp_dir, &self.settings)?;
+        if !deleted.is_empty() {
+            deleted.save(&file)?;
+        }
+        let bytes = fs::metadata(&file)?.len();
+        let wal = self.start_wal(state, pending)?;
+        let old = state.list.wal.replace(wal.name().to_string());
+        match self.list_file(state, &file, bytes) {
+            Ok(path) => {
+                if let Some(old) = old {
+                    // Otherwise removed on the next open.
+                    let _ = fs::remove_file(self.dir.join(old));
+                }
+                state.wal = Some(wal);
+                Ok(Some(path))
+            }
+            Err(e) => {
+                state.list.wal = old;
+                let _ = fs::remove_file(self.dir.join(wal.name()));
+                Err(e)
+            }
+        }
+    }
+
+    /// The key of the directory, if it is encrypted.
+    fn key(&self) -> Result<Option<Key>> {
+        self.settings.encrypted.then(Key::from_env).transpose()
+    }
+
+    /// A new log of the buffer, holding `updates`, not listed yet.
+    fn start_wal(&self, state: &mut State, updates: &[Update]) -> Result<Wal> {
+        let name = state.list.new_name(WAL_EXTENSION);
+        let mut wal = Wal::create(&self.dir, name, self.key()?)?;
+        for update in updates {
+            wal.append(update)?;
+        }
+        Ok(wal)
+    }
+
+    /// Move the index file `file`, with its tombstones, into the directory
+    /// as a new segment, replacing the documents indexed earlier from the
+    /// same paths, and return its new path. `file` must have been
+    /// built with the analyzer and document identity of the index, with
+    /// positions if the index has them, and be on the same file system.
+    pub fn add_file(&self, file: &Path) -> Result<PathBuf> {
+        self.check_writable()?;
+        let settings = IndexSettings::load(file)?;
+        if settings.analyzer != self.settings.analyzer {
+            return Err(Error::Incompatible(format!(
+                "{} was built with another analyzer than {}",
+                file.display(),
+                self.dir.display()
+            )));
+        }
+        if settings.omit_positions != self.settings.omit_positions {
+            return Err(Error::Incompatible(format!(
+                "{} and {} don't both store positions",
+                file.display(),
+                self.dir.display()
+            )));
+        }
+        if settings.byte_offsets != self.settings.byte_offsets {
+            return Err(Error::Incompatible(format!(
+                "{} and {} don't both store byte offsets",
+                file.display(),
+                self.dir.display()
+            )));
+        }
+        if settings.line_numbers != self.settings.line_numbers {
+            return Err(Error::Incompatible(format!(
+                "{} and {} don't both store line numbers",
+                file.display(),
+                self.dir.display()
+            )));
+        }
+        if settings.encrypted != self.settings.encrypted {
+            return Err(Error::Incompatible(format!(
+                "{} and {} aren't both encrypted",
+                file.display(),
+                self.dir.display()
+            )));
+        }
+        if settings.identity != self.settings.identity {
+            return Err(Error::Incompatible(format!(
+                "{} identifies documents by {}, {} by {}",
+                file.display(),
+                settings.identity.name(),
+                self.dir.display(),
+                self.settings.identity.name()
+            )));
+        }
+        let bytes = fs::metadata(file)?.len();
+        let mut state = self.state.lock().unwrap();
+        self.list_file(&mut state, file, bytes)
+    }
+
+    /// Move `file` into the directory and list it as the newest segment,
+    /// deleting the documents its live ones replace.
+    fn list_file(
+        &self,
+        state: &mut State,
+        file: &Path,
+        bytes: u64,
+    ) -> Result<PathBuf> {
+        let name = state.list.new_name("dat");
+        let path = self.dir.join(&name);
+        publish(file, &path)?;
+        let sidecar = Tombstones::sidecar_path(file);
+        if sidecar.is_file() {
+            fs::rename(sidecar, Tombstones::sidecar_path(&path))?;
+        }
+        let reader = IndexFileReader::open(&path)?;
+        let documents = reader.docs().len();
+        let tombstones = Tombstones::load(&path)?;
+        let paths = replaced_paths(
+            reader
+                .docs()
+                .iter()
+                .filter(|(doc, _)| !tombstones.contains(doc))
+                .map(|(_, info)| info),
+        );
+        let replaces = |_: &Doc, info: &DocInfo| paths.contains(&*info.path);
+        let (buffered, replaced) =
+            (state.buffered(replaces), self.in_segments(state, replaces)?);
+        state.list.segments.push(Segment {
+            name,
+            documents,
+            bytes,
+        });
+        state.list.generation += 1;
+        state.list.save(&self.dir)?;
+        info!("Added segment {} ({} documents)", path.display(), documents);
+        // Deleted once it is listed, so that a crash in between leaves both
+        // versions rather than neither.
+        self.delete_buffered(state, buffered)?;
+        let replaced = self.tombstone(state, &replaced)?;
+        if replaced > 0 {
+            debug!("Replaced {} documents indexed earlier", replaced);
+        }
+        Ok(path)
+    }
+
+    /// Delete `docs` from the segments that hold them. Returns the number of
+    /// documents that were deleted, leaving out those that already were.
+    pub fn delete(&self, docs: &[Doc]) -> Result<usize> {
+        self.check_writable()?;
+        let docs: HashSet<&Doc> = docs.iter().collect();
+        // Locked, so that `compact` sees every deletion made while it runs.
+        let mut state = self.state.lock().unwrap();
+        self.delete_where(&mut state, |doc, _| docs.contains(doc))
+    }
+
+    /// Delete the documents `matches` picks from the buffer and from the
+    /// listed segments. Returns the number of documents that were deleted,
+    /// leaving out those that already were.
+    fn delete_where(
+        &self,
+        state: &mut State,
+        matches: impl Fn(&Doc, &DocInfo) -> bool,
+    ) -> Result<usize> {
+        let buffered = state.buffered(&matches);
+        let in_segments = self.in_segments(state, &matches)?;
+        let mut deleted: HashSet<Doc> = buffered.iter().cloned().collect();
+        deleted.extend(in_segments.iter().flat_map(|(_, docs)| docs).cloned());
+        self.delete_buffered(state, buffered)?;
+        self.tombstone(state, &in_segments)?;
+        Ok(deleted.len())
+    }
+
+    /// Delete `docs` from the buffer, once the deletion is logged.
+    fn delete_buffered(&self, state: &mut State, docs: Vec<Doc>) -> Result<()> {
+        if !docs.is_empty() {
+            let update = Update::Delete(docs);
+            state.log(&update)?;
+            self.apply(state, update)?;
+        }
+        Ok(())
+    }
+
+    /// The live documents `matches` picks in each listed segment, by
+    /// segment name, leaving out segments with none.
+    fn in_segments(
+        &self,
+        state: &State,
+        matches: impl Fn(&Doc, &DocInfo) -> bool,
+    ) -> Result<Vec<(String, Vec<Doc>)>> {
+        let mut found = vec![];
+        for segment in &state.list.segments {
+            let path = self.dir.join(&segment.name);
+            let reader = IndexFileReader::open(&path)?;
+            let tombstones = Tombstones::load(&path)?;
+            let docs: Vec<Doc> = reader
+                .docs()
+                .iter()
+                .filter(|(doc, info)| {
+                    matches(doc, info) && !tombstones.contains(doc)
+                })
+                .map(|(doc, _)| doc.clone())
+                .collect();
+            if !docs.is_empty() {
+                found.push((segment.name.clone(), docs));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Add `docs`, by segment name, to the tombstones of the segments,
+    /// leaving out segments no longer listed, whose documents have been
+    /// merged since. Returns the number of documents that weren't deleted
+    /// yet.
+    fn tombstone(
+        &self,
+        state: &mut State,
+        docs: &[(String, Vec<Doc>)],
+    ) -> Result<usize> {
+        let mut deleted = 0;
+        for (name, docs) in docs {
+            if !state.list.segments.iter().any(|s| &s.name == name) {
+                continue;
+            }
+            let path = self.dir.join(name);
+            let mut tombstones = Tombstones::load(&path)?;
+            let before = deleted;
+            for doc in docs {
+                if tombstones.add(doc.clone()) {
+                    deleted += 1;
+                }
+            }
+            if deleted > before {
+                tombstones.save(&path)?;
+            }
+        }
+        if deleted > 0 {
+            state.list.generation += 1;
+            state.list.save(&self.dir)?;
+        }
+        Ok(deleted)
+    }
+
+    /// Apply `update` to the buffer, and delete the documents an addition
+    /// replaces from their segments.
+    fn apply(&self, state: &mut State, update: Update) -> Result<()> {
+        match update {
+            Update::Add { index, replaced } => {
+                state.buffer.merge(index);
+                self.tombstone(state, &replaced)?;
+            }
+            Update::Delete(docs) => {
+                for doc in docs {
+                    state.buffer_deleted.add(doc);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge segments as the merge policy says, until it is satisfied, and
+    /// return the number of merges. Merged segments leave their deleted
+    /// documents behind, and segments whose documents are all deleted are
+    /// simply removed.
+    ///
+    /// Segments can be added, searched and deleted from while merges run:
+    /// the segment list is only locked to pick the segments, and to replace
+    /// them with the merged one in the end.
+    pub fn compact(&self) -> Result<usize> {
+        self.compact_with(&self.policy)
+    }
+
+    /// Like `compact`, with another merge policy.
+    pub fn compact_with(&self, policy: &MergePolicy) -> Result<usize> {
+        self.check_writable()?;
+        let mut merges = 0;
+        while self.merge_step(policy)? {
+            merges += 1;
+        }
+        Ok(merges)
+    }
+
+    /// Run `compact` on another thread. Not on wasm32, which has no threads.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn compact_in_background(
+        self: &Arc<Self>,
+    ) -> JoinHandle<Result<usize>> {
+        let index_dir = Arc::clone(self);
+        thread::spawn(move || index_dir.compact())
+    }
+
+    /// Merge the segments `policy` picks. Returns `false` if it picked none.
+    fn merge_step(&self, policy: &MergePolicy) -> Result<bool> {
+        let (picked, deleted, staging) = {
+            let mut state = self.state.lock().unwrap();
+            let mut candidates = vec![];
+            for segment in &state.list.segments {
+                if !state.merging.contains(&segment.name) {
+                    let tombstones =
+                        Tombstones::load(self.dir.join(&segment.name))?;
+                    candidates.push((segment.clone(), tombstones));
+                }
+            }
+            let sizes: Vec<SegmentSize> = candidates
+                .iter()
+                .map(|(segment, tombstones)| SegmentSize {
+                    bytes: segment.bytes,
+                    documents: segment.documents,
+                    deleted: tombstones.len(),
+                })
+                .collect();
+            let picked = policy.pick(&sizes);
+            if picked.is_empty() {
+                return Ok(false);
+            }
+            // What was deleted before the merge, to tell what's deleted
+            // while it runs.
+            let (picked, deleted): (Vec<Segment>, Vec<Tombstones>) =
+                picked.into_iter().map(|i| candidates[i].clone()).unzip();
+            let staging = self.dir.join(state.list.new_name("tmp"));
+            state.list.save(&self.dir)?;
+            state.merging.extend(picked.iter().map(|s| s.name.clone()));
+            (picked, deleted, staging)
+        };
+
+        let result = self
+            .merge(&picked, &deleted, &staging)
+            .and_then(|merged| self.replace(&picked, &deleted, merged));
+        let _ = fs::remove_dir_all(&staging);
+        let mut state = self.state.lock().unwrap();
+        for segment in &picked {
+            state.merging.remove(&segment.name);
+        }
+        drop(state);
+        let merged = result?;
+
+        let _removing = DirLock::remove(&self.dir)?;
+        for segment in &picked {
+            let path = self.dir.join(&segment.name);
+            debug!("Removing segment {}", path.display());
+            fs::remove_file(&path)?;
+            let _ = fs::remove_file(Tombstones::sidecar_path(&path));
+        }
+        match merged {
+            Some(path) => info!(
+                "Merged {} segments into {}",
+                picked.len(),
+                path.display()
+            ),
+            None => info!("Removed {} emptied segments", picked.len()),
+        }
+        Ok(true)
+    }
+
+    /// Merge the documents of `segments` that aren't `deleted` into a file
+    /// in `staging`, if there are any.
+    fn merge(
+        &self,
+        segments: &[Segment],
+        deleted: &[Tombstones],
+        staging: &Path,
+    ) -> Result<Option<PathBuf>> {
+        let inputs: Vec<PathBuf> = segments
+            .iter()
+            .zip(deleted)
+            .filter(|(segment, deleted)| deleted.len() < segment.documents)
+            .map(|(segment, _)| self.dir.join(&segment.name))
+            .collect();
+        if inputs.is_empty() {
+            return Ok(None);
+        }
+        fs::create_dir_all(staging)?;
+        info!("Merging {} segments", inputs.len());
+        // Merges run in the background, on one thread.
+        merge_index_files_with(&inputs, staging, self.settings.clone(), 1)?;
+        Ok(Some(staging.join(MERGED_FILENAME)))
+    }
+
+    /// Make `merged`, the result of merging `picked` without the documents
+    /// `deleted` from them, a segment, and list it instead of them. Returns
+    /// its path.
+    fn replace(
+        &self,
+        picked: &[Segment],
+        deleted: &[Tombstones],
+        merged: Option<PathBuf>,
+    ) -> Result<Option<PathBuf>> {
+        let mut state = self.state.lock().unwrap();
+        let merged = match merged {
+            Some(file) => {
+                // Documents deleted during the merge may still be in the
+                // merged file.
+                let now: Vec<Tombstones> = picked
+                    .iter()
+                    .map(|s| Tombstones::load(self.dir.join(&s.name)))
+                    .collect::<Result<_>>()?;
+                let mut tombstones = Tombstones::default();
+                let reader = IndexFileReader::open(&file)?;
+                for (doc, _) in reader.docs() {
+                    if now.iter().zip(deleted).any(|(now, before)| {
+                        now.contains(doc) && !before.contains(doc)
+                    }) {
+                        tombstones.add(doc.clone());
+                    }
+                }
+                let segment = Segment {
+                    name: state.list.new_name("dat"),
+                    documents: reader.docs().len(),
+                    bytes: fs::metadata(&file)?.len(),
+                };
+                drop(reader);
+                let path = self.dir.join(&segment.name);
+                publish(&file, &path)?;
+                if !tombstones.is_empty() {
+                    tombstones.save(&path)?;
+                }
+                Some((segment, path))
+            }
+            None => None,
+        };
+
+        // The merged segment takes the place of the oldest one it replaces.
+        let names: HashSet<&str> =
+            picked.iter().map(|s| s.name.as_str()).collect();
+        let segments = &mut state.list.segments;
+        let position = segments
+            .iter()
+            .position(|s| names.contains(s.name.as_str()))
+            .unwrap_or(segments.len());
+        segments.retain(|s| !names.contains(s.name.as_str()));
+        if let Some((segment, _)) = &merged {
+            segments.insert(position, segment.clone());
+        }
+        state.list.generation += 1;
+        state.list.save(&self.dir)?;
+        Ok(merged.map(|(_, path)| path))
+    }
+
+    /// Fail unless the directory was opened for writing.
+    fn check_writable(&self) -> Result<()> {
+        match self.writer {
+            Some(_) => Ok(()),
+            None => Err(Error::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} was opened read-only", self.dir.display()),
+            ))),
+        }
+    }
+
+    /// A new, empty directory within the index directory, to build a segment
+    /// in.
+    fn create_staging_dir(&self) -> Result<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        let staging = self.dir.join(state.list.new_name("tmp"));
+        state.list.save(&self.dir)?;
+        fs::create_dir_all(&staging)?;
+        Ok(staging)
+    }
+}
+
+impl State {
+    /// True if `update` adds a buffered document deleted since, which must
+    /// stay deleted in the older copy only, and so takes flushing the buffer
+    /// first.
+    fn conflicts(&self, update: &Update) -> bool {
+        match update {
+            Update::Add { index, .. } => self.adds_deleted(index),
+            Update::Delete(_) => false,
+        }
+    }
+
+    /// True if `index` holds a buffered document deleted since.
+    fn adds_deleted(&self, index: &InMemoryIndex) -> bool {
+        index
+            .docs
+            .iter()
+            .any(|(doc, _)| self.buffer_deleted.contains(doc))
+    }
+
+    /// The live buffered documents `matches` picks.
+    fn buffered(&self, matches: impl Fn(&Doc, &DocInfo) -> bool) -> Vec<Doc> {
+        self.buffer
+            .docs
+            .iter()
+            .filter(|(doc, info)| {
+                matches(doc, info) && !self.buffer_deleted.contains(doc)
+            })
+            .map(|(doc, _)| doc.clone())
+            .collect()
+    }
+
+    /// Append `update` to the log of the buffer, if there is one.
+    fn log(&mut self, update: &Update) -> Result<()> {
+        if let Some(wal) = &mut self.wal {
+            wal.append(update)?;
+        }
+        Ok(())
+    }
+}
+
+/// The paths that documents of `docs` replace the documents indexed earlier
+/// from. Documents indexed from text, with no path, replace none.
+fn replaced_paths<'a>(
+    docs: impl Iterator<Item = &'a DocInfo>,
+) -> HashSet<&'a str> {
+    docs.flat_map(|info| iter::once(&info.path).chain(&info.duplicates))
+        .map(String::as_str)
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+impl SegmentList {
+    fn load(dir: &Path) -> Result<SegmentList> {
+        let path = dir.join(SEGMENTS_FILENAME);
+        let text = fs::read_to_string(&path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| Error::format(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Write the list, replacing the previous one only once the new one is
+    /// complete.
+    fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(SEGMENTS_FILENAME);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let text = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(&tmp, text)?;
+        publish(Path::new(&tmp), &path)?;
+        Ok(())
+    }
+
+    fn paths(&self, dir: &Path) -> Vec<PathBuf> {
+        self.segments.iter().map(|s| dir.join(&s.name)).collect()
+    }
+
+    /// A file name in the directory that was never used, with `extension`.
+    /// The list must be saved for the name to stay reserved.
+    fn new_name(&mut self, extension: &str) -> String {
+        let name = format!("seg{:06}.{}", self.next, extension);
+        self.next += 1;
+        name
+    }
+}
+
+impl Drop for IndexDir {
+    fn drop(&mut self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if let Err(e) = self.flush_locked(&mut state, &[]) {
+            warn!(
+                "Documents buffered for {} were left in its log: {}",
+                self.dir.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{doc, index_of};
+    use crate::search::SearchResults;
+    use std::slice;
+
+    /// The paths of the documents of `results`, sorted.
+    fn paths(index_dir: &IndexDir, results: &SearchResults) -> Vec<String> {
+        let searcher = index_dir.searcher().unwrap();
+        let mut paths: Vec<String> = results
+            .hits()
+            .iter()
+            .map(|hit| searcher.doc_info(&hit.doc).unwrap().path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn search(index_dir: &IndexDir, query: &str) -> Vec<String> {
+        let results = index_dir.searcher().unwrap().query(query).unwrap();
+        paths(index_dir, &results)
+    }
+
+    /// An index of `texts` with the documents numbered from `first` up, as
+    /// if read from `paths`.
+    fn index_at(first: u8, texts: &[&str], paths: &[&str]) -> InMemoryIndex {
+        let mut index = index_of(first, texts);
+        for ((_, info), path) in index.docs.iter_mut().zip(paths) {
+            info.path = path.to_string();
+        }
+        index
+    }
+
+    #[test]
+    fn reindexed_files_replace_their_earlier_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        let (a, b) = (docs.join("a.txt"), docs.join("b.txt"));
+        fs::write(&a, "the quick brown fox").unwrap();
+        fs::write(&b, "a quick dog").unwrap();
+        let out = dir.path().join("out");
+        let index_dir =
+            IndexDir::create(&out, IndexSettings::default()).unwrap();
+        index_dir.build(Indexer::new(&docs)).unwrap();
+
+        fs::write(&a, "the quick brown cat").unwrap();
+        index_dir.build(Indexer::new(&docs)).unwrap();
+        assert_eq!(index_dir.segments().len(), 2);
+        assert!(search(&index_dir, "fox").is_empty());
+        let (a, b) = (a.display().to_string(), b.display().to_string());
+        assert_eq!(search(&index_dir, "cat"), slice::from_ref(&a));
+        assert_eq!(search(&index_dir, "quick"), [a, b]);
+        // Both documents of the first segment were replaced.
+        assert_eq!(index_dir.compact().unwrap(), 1);
+        assert_eq!(index_dir.segments().len(), 1);
+    }
+
+    #[test]
+    fn buffered_documents_replace_earlier_ones_from_the_same_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        index_dir
+            .add_index(index_at(0, &["the cat", "a dog"], &["a", "b"]))
+            .unwrap();
+        index_dir.add(index_at(2, &["the bird"], &["a"])).unwrap();
+        assert!(search(&index_dir, "cat").is_empty());
+        assert_eq!(search(&index_dir, "the"), ["a"]);
+        // Added again, and the earlier buffered copy replaced in turn.
+        index_dir.add(index_at(3, &["the fish"], &["a"])).unwrap();
+        assert!(search(&index_dir, "bird").is_empty());
+        index_dir.flush().unwrap();
+        assert_eq!(search(&index_dir, "the"), ["a"]);
+        assert_eq!(search(&index_dir, "dog"), ["b"]);
+
+        // Documents without a path replace nothing.
+        index_dir.add_index(index_of(4, &["a cat"])).unwrap();
+        index_dir.add_index(index_of(5, &["a cow"])).unwrap();
+        let searcher = index_dir.searcher().unwrap();
+        assert_eq!(searcher.query("a").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn buffered_documents_are_searched_before_they_are_flushed() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        let files = || fs::read_dir(dir.path()).unwrap().count();
+        let before = files();
+        let added = index_dir.add(index_at(0, &["the cat"], &["a"])).unwrap();
+        assert_eq!(added, None);
+        assert_eq!(search(&index_dir, "cat"), ["a"]);
+        assert_eq!(index_dir.lookup("cat").unwrap().unwrap().len(), 1);
+        // Only the log of the buffer, there from the start, is written.
+        assert!(index_dir.segments().is_empty());
+        assert_eq!(files(), before);
+
+        let flushed = index_dir.flush().unwrap().unwrap();
+        assert_eq!(index_dir.segments(), [flushed]);
+        assert_eq!(search(&index_dir, "cat"), ["a"]);
+        assert_eq!(index_dir.flush().unwrap(), None);
+
+        // Over budget, the buffer is flushed by `add`.
+        let index_dir = index_dir.with_buffer_budget(0);
+        let added = index_dir.add(index_at(1, &["a dog"], &["b"])).unwrap();
+        assert_eq!(index_dir.segments().last(), added.as_ref());
+        assert_eq!(index_dir.segments().len(), 2);
+        assert_eq!(search(&index_dir, "dog"), ["b"]);
+    }
+
+    #[test]
+    fn replacements_logged_before_a_crash_are_replayed_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        index_dir
+            .add_index(index_at(0, &["the cat"], &["a"]))
+            .unwrap();
+        {
+            // What `add` logs, before it deletes the document replaced.
+            let mut state = index_dir.state.lock().unwrap();
+            let index = index_at(1, &["the dog"], &["a"]);
+            let (update, _) = index_dir.replacing(&mut state, index).unwrap();
+            state.log(&update).unwrap();
+        }
+        // What a crash would leave behind then.
+        let crashed = tempfile::tempdir().unwrap();
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, crashed.path().join(path.file_name().unwrap()))
+                .unwrap();
+        }
+        assert_eq!(search(&index_dir, "cat"), ["a"]);
+
+        let recovered = IndexDir::open(crashed.path()).unwrap();
+        assert!(search(&recovered, "cat").is_empty());
+        assert_eq!(search(&recovered, "the"), ["a"]);
+        recovered.flush().unwrap();
+        drop(recovered);
+        let reopened = IndexDir::open(crashed.path()).unwrap();
+        assert!(search(&reopened, "cat").is_empty());
+        assert_eq!(search(&reopened, "dog"), ["a"]);
+    }
+
+    #[test]
+    fn deleted_documents_are_hidden_and_compacted_away() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        index_dir
+            .add_index(index_of(0, &["the cat", "the dog"]))
+            .unwrap();
+        index_dir.add_index(index_of(2, &["the cow"])).unwrap();
+        let (merged, generation) = (
+            index_dir.segments(),
+            IndexDir::generation(dir.path()).unwrap(),
+        );
+
+        assert_eq!(index_dir.delete(&[doc(1), doc(2)]).unwrap(), 2);
+        assert_eq!(index_dir.delete(&[doc(1)]).unwrap(), 0);
+        assert!(IndexDir::generation(dir.path()).unwrap() > generation);
+        let the = index_dir.lookup("the").unwrap().unwrap();
+        assert_eq!(the.keys().collect::<Vec<_>>(), [&doc(0)]);
+        assert!(index_dir.lookup("cow").unwrap().is_none());
+
+        assert_eq!(index_dir.compact_with(&MergePolicy::Full).unwrap(), 1);
+        let segments = index_dir.segments();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(
+            IndexFileReader::open(&segments[0]).unwrap().docs().len(),
+            1
+        );
+        assert!(merged.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn read_only_directories_follow_the_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        index_dir.add_index(index_of(0, &["the cat"])).unwrap();
+
+        let reader = IndexDir::open_read_only(dir.path()).unwrap();
+        assert!(reader.is_read_only());
+        assert!(reader.add_index(index_of(1, &["a dog"])).is_err());
+        assert!(matches!(IndexDir::open(dir.path()), Err(Error::Locked(_))));
+        index_dir.add_index(index_of(1, &["the dog"])).unwrap();
+        let searcher = reader.searcher().unwrap();
+        assert_eq!(searcher.query("the").unwrap().len(), 2);
+        assert_eq!(reader.segments().len(), 2);
+    }
+
+    #[test]
+    fn directories_reopen_only_with_their_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = IndexSettings {
+            omit_positions: true,
+            ..IndexSettings::default()
+        };
+        let index_dir = IndexDir::create(dir.path(), settings.clone()).unwrap();
+        index_dir.add_index(index_of(0, &["the cat"])).unwrap();
+        drop(index_dir);
+
+        assert!(matches!(
+            IndexDir::create(dir.path(), IndexSettings::default()),
+            Err(Error::Incompatible(_))
+        ));
+        let index_dir = IndexDir::open(dir.path()).unwrap();
+        assert_eq!(index_dir.settings(), &settings);
+        let file = crate::fixtures::write_index(dir.path(), &["a dog"]);
+        assert!(matches!(
+            index_dir.add_file(&file),
+            Err(Error::Incompatible(_))
+        ));
+    }
+}
@@ -0,0 +1,386 @@
+//! Indexes split into shards by term.
+//!
+//! An index built with several shards (see `Indexer::shards`) is written as
+//! `index.0.dat`, `index.1.dat`, ... instead of `index.dat`, listed in
+//! `shards.json`. Every shard is a complete index file with the document
+//! table of the whole index, but only the terms whose hash, modulo the
+//! number of shards, is its number (see `shard_of`). The shards can be
+//! loaded on as many threads, or machines, and a term is only ever looked up
+//! in its own shard.
+//!
+//! `ShardedIndex::load` puts the shards back together into one index, and
+//! `Searcher::open` does so for a sharded index directory, so that queries
+//! combining terms of different shards work as on a single file.
+//!
+//! `shards.json` is written after the shards and renamed into place, so
+//! readers see either the previous index or the new one.
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::bloom::term_hash;
+use crate::error::{Error, Result};
+use crate::header::IndexSettings;
+use crate::index::{Doc, DocEntry, ParsedIndex};
+use crate::merge::MERGED_FILENAME;
+use crate::read::{IndexFileReader, IndexFileSearcher};
+use crate::search::Searcher;
+use crate::tmp::publish;
+use crate::tombstone::{compact, Tombstones};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Name of the shard list in a sharded index directory.
+pub const SHARDS_FILENAME: &str = "shards.json";
+
+/// The shard of `term` among `shards`.
+pub fn shard_of(term: &str, shards: usize) -> usize {
+    if shards <= 1 {
+        return 0;
+    }
+    (term_hash(term) % shards as u64) as usize
+}
+
+/// The index of which `shards` are the shards, each with its name. Fails if
+/// they don't all have the same documents.
+pub(crate) fn join<N: Display>(
+    mut shards: impl Iterator<Item = (ParsedIndex, N)>,
+) -> Result<ParsedIndex> {
+    let (mut index, _) = shards.next().expect("a shard");
+    for (shard, name) in shards {
+        if shard.docs != index.docs {
+            return Err(Error::format(format!(
+                "{} doesn't have the documents of the other shards",
+                name
+            )));
+        }
+        index.word_count += shard.word_count;
+        index.map.extend(shard.map);
+    }
+    Ok(index)
+}
+
+/// The contents of `shards.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ShardList {
+    /// File names of the shards, in the index directory, in order.
+    shards: Vec<String>,
+}
+
+/// A sharded index, open for searching.
+pub struct ShardedIndex {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+    settings: IndexSettings,
+}
+
+impl ShardedIndex {
+    /// True if `path` is a sharded index: a directory with `shards.json`,
+    /// or that file itself.
+    pub fn is_sharded<P: AsRef<Path>>(path: P) -> bool {
+        let path = path.as_ref();
+        path.join(SHARDS_FILENAME).is_file()
+            || (path.file_name() == Some(SHARDS_FILENAME.as_ref())
+                && path.is_file())
+    }
+
+    /// Open the sharded index `path`, its directory or its `shards.json`.
+    /// Only the header of the first shard is read.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ShardedIndex> {
+        let path = path.as_ref();
+        let dir = if path.is_dir() {
+            path.to_owned()
+        } else {
+            path.parent().unwrap_or(Path::new(".")).to_owned()
+        };
+        let list = ShardList::load(&dir)?;
+        if list.shards.is_empty() {
+            return Err(Error::format(format!(
+                "{} lists no shards",
+                dir.join(SHARDS_FILENAME).display()
+            )));
+        }
+        let files: Vec<PathBuf> =
+            list.shards.iter().map(|name| dir.join(name)).collect();
+        let settings = IndexSettings::load(&files[0])?;
+        Ok(ShardedIndex {
+            dir,
+            files,
+            settings,
+        })
+    }
+
+    /// The directory of the index.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The settings the index was built with.
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    /// Paths of the shards, in order.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Path of the shard that holds `term`, an analyzed term.
+    pub fn shard_of(&self, term: &str) -> &Path {
+        &self.files[shard_of(term, self.files.len())]
+    }
+
+    /// Load every shard, each on a thread of its own (but on wasm32), into
+    /// a single index. Fails if the shards were built with other settings
+    /// than the first one, or don't have the same documents.
+    pub fn load(&self) -> Result<ParsedIndex> {
+        let load = |file: &Path| -> Result<ParsedIndex> {
+            debug!("Loading shard {}", file.display());
+            if IndexSettings::load(file)? != self.settings {
+                return Err(Error::Incompatible(format!(
+                    "{} was built with other settings",
+                    file.display()
+                )));
+            }
+            IndexFileReader::get_index_from_file(file)
+        };
+        let shards = if cfg!(target_arch = "wasm32") {
+            self.files.iter().map(|file| load(file)).collect()
+        } else {
+            thread::scope(|scope| {
+                let workers: Vec<_> = self
+                    .files
+                    .iter()
+                    .map(|file| scope.spawn(move || load(file)))
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("shard loader panicked"))
+                    .collect::<Result<Vec<_>>>()
+            })
+        }?;
+
+        join(
+            shards
+                .into_iter()
+                .zip(self.files.iter().map(|f| f.display())),
+        )
+    }
+
+    /// Load the index for searching, like `Searcher::open`.
+    pub fn searcher(&self) -> Result<Searcher> {
+        Searcher::new(self.load()?, self.settings.clone())
+    }
+
+    /// Read and decode the hits for `term`, an analyzed term, from its shard
+    /// only, without loading it.
+    ///
+    /// Returns `Ok(None)` if no live document contains the term.
+    pub fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        IndexFileSearcher::open(self.shard_of(term))?.lookup(term)
+    }
+
+    /// Delete `docs` from every shard. Returns the number of documents that
+    /// were deleted, leaving out those that already were.
+    pub fn delete(&self, docs: &[Doc]) -> Result<usize> {
+        let docs: HashSet<&Doc> = docs.iter().collect();
+        // All shards have the same documents, and the same tombstones.
+        let reader = IndexFileReader::open(&self.files[0])?;
+        let tombstones = Tombstones::load(&self.files[0])?;
+        let deleted: Vec<&Doc> = reader
+            .docs()
+            .iter()
+            .map(|(doc, _)| doc)
+            .filter(|doc| docs.contains(doc) && !tombstones.contains(doc))
+            .collect();
+        if !deleted.is_empty() {
+            for file in &self.files {
+                let mut tombstones = Tombstones::load(file)?;
+                for doc in &deleted {
+                    tombstones.add((*doc).clone());
+                }
+                tombstones.save(file)?;
+            }
+        }
+        Ok(deleted.len())
+    }
+
+    /// Rewrite every shard without its deleted documents (see
+    /// `tombstone::compact`). Returns the number of documents that were
+    /// purged.
+    pub fn compact(&self) -> Result<usize> {
+        let mut purged = 0;
+        for file in &self.files {
+            purged = purged.max(compact(file)?);
+        }
+        Ok(purged)
+    }
+}
+
+impl ShardList {
+    fn load(dir: &Path) -> Result<ShardList> {
+        let path = dir.join(SHARDS_FILENAME);
+        let text = fs::read_to_string(&path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| Error::format(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Write the list, replacing the previous one only once the new one is
+    /// complete.
+    fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(SHARDS_FILENAME);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let text = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(&tmp, text)?;
+        publish(Path::new(&tmp), &path)?;
+        Ok(())
+    }
+}
+
+/// Rename the index files `files` to the shards of the index in `dir`, in
+/// order, and list them in `shards.json`. The files of the index that was
+/// there before, sharded or not, are removed.
+pub(crate) fn publish_shards(files: &[PathBuf], dir: &Path) -> Result<()> {
+    let old = match ShardList::load(dir) {
+        Ok(list) => list.shards,
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e),
+    };
+    let list = ShardList {
+        shards: (0..files.len())
+            .map(|i| format!("index.{}.dat", i))
+            .collect(),
+    };
+    for (file, name) in files.iter().zip(&list.shards) {
+        let shard = dir.join(name);
+        debug!("Renaming {} to {}", file.display(), shard.display());
+        // Tombstones of the previous index don't apply to the new one.
+        remove_if_exists(&Tombstones::sidecar_path(&shard))?;
+        publish(file, &shard)?;
+    }
+    list.save(dir)?;
+
+    for name in old.iter().filter(|name| !list.shards.contains(name)) {
+        remove_index_file(&dir.join(name))?;
+    }
+    remove_index_file(&dir.join(MERGED_FILENAME))
+}
+
+/// Remove the shards of the index in `dir` and their list, if it is
+/// sharded, once an unsharded `index.dat` replaced them.
+pub(crate) fn remove_shards(dir: &Path) -> Result<()> {
+    let list = match ShardList::load(dir) {
+        Ok(list) => list,
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(())
+        }
+        Err(e) => return Err(e),
+    };
+    remove_if_exists(&dir.join(SHARDS_FILENAME))?;
+    for name in &list.shards {
+        remove_index_file(&dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Remove the index file `file` and its tombstones, if they exist.
+fn remove_index_file(file: &Path) -> Result<()> {
+    remove_if_exists(file)?;
+    remove_if_exists(&Tombstones::sidecar_path(file))
+}
+
+fn remove_if_exists(file: &Path) -> Result<()> {
+    match fs::remove_file(file) {
+        Ok(()) => {
+            debug!("Removed {}", file.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::Indexer;
+
+    #[test]
+    fn terms_keep_their_shard() {
+        // Saved indexes are looked up by these, so they must never change.
+        let cases = [
+            ("cat", [0, 2, 2, 2]),
+            ("dog", [1, 1, 3, 7]),
+            ("the", [1, 0, 1, 5]),
+            ("title:cat", [0, 2, 0, 0]),
+            ("ünïcode", [1, 2, 3, 7]),
+        ];
+        for (term, expected) in cases {
+            let shards = [2, 3, 4, 8].map(|n| shard_of(term, n));
+            assert_eq!(shards, expected, "{}", term);
+            assert_eq!(shard_of(term, 1), 0);
+            assert_eq!(shard_of(term, 0), 0);
+        }
+    }
+
+    #[test]
+    fn sharded_indexes_hold_each_term_in_its_shard() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir(&docs).unwrap();
+        let texts = ["the cat sat", "the dog and the cat", "a bird", "ünïcode"];
+        for (i, text) in texts.iter().enumerate() {
+            fs::write(docs.join(format!("{i}.txt")), text).unwrap();
+        }
+        let build = |out: &str, shards| {
+            let out = dir.path().join(out);
+            fs::create_dir(&out).unwrap();
+            Indexer::new(&docs)
+                .shards(shards)
+                .output(&out)
+                .run()
+                .unwrap()
+        };
+        let whole = IndexFileReader::get_index_from_file(build("one", 1));
+        let whole = whole.unwrap();
+        let out = build("three", 3);
+        assert!(ShardedIndex::is_sharded(&out));
+        let index = ShardedIndex::open(&out).unwrap();
+        assert_eq!(index.files().len(), 3);
+        for (i, file) in index.files().iter().enumerate() {
+            let shard = IndexFileReader::get_index_from_file(file).unwrap();
+            assert_eq!(shard.docs, whole.docs);
+            for term in shard.map.keys() {
+                assert_eq!(shard_of(term, 3), i, "{}", term);
+                assert_eq!(index.shard_of(term), file);
+            }
+        }
+        let loaded = index.load().unwrap();
+        assert_eq!(loaded.map, whole.map);
+        for term in whole.map.keys() {
+            assert_eq!(
+                index.lookup(term).unwrap().as_ref(),
+                whole.map.get(term)
+            );
+        }
+        assert_eq!(index.lookup("none").unwrap(), None);
+
+        // Deletions reach every shard.
+        let cat: Vec<Doc> = whole.map["cat"].keys().cloned().collect();
+        assert_eq!(index.delete(&cat).unwrap(), 2);
+        assert_eq!(index.delete(&cat).unwrap(), 0);
+        assert_eq!(index.lookup("cat").unwrap(), None);
+        assert_eq!(index.lookup("the").unwrap(), None);
+        assert_eq!(index.compact().unwrap(), 2);
+        assert_eq!(index.load().unwrap().docs.len(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+//! Index files read in place from bytes in memory.
+//!
+//! `SliceReader` decodes an index file straight out of any byte slice: the
+//! mapping of `MmapIndexReader`, or the bytes of a `StaticIndex`, e.g. ones
+//! built into the program with `include_bytes!`. Hits are borrowed from the
+//! bytes, with no copying, unless they were compressed.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::ops::Deref;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::bloom::footer_start;
+use crate::dictionary::TermDictionary;
+use crate::error::{Error, Result};
+use crate::header::{read_header, IndexSettings};
+use crate::index::{is_twin_term, DocEntry, DocTable, ParsedIndex};
+use crate::read::{decode_doc_table, decode_hits, hits_limit, Entry};
+use crate::tombstone::Tombstones;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// An index file reader over the bytes `B` of the whole file.
+#[derive(Debug)]
+pub(crate) struct SliceReader<B> {
+    /// The bytes of the file.
+    bytes: B,
+    /// The table of contents, in file order (which is sorted by term).
+    entries: Vec<Entry>,
+    /// The document table, including deleted documents.
+    docs: DocTable,
+    /// Deleted documents, hidden from lookups.
+    tombstones: Tombstones,
+    /// The settings from the file header.
+    settings: IndexSettings,
+    /// The most bytes the hits of a term can take decompressed (see
+    /// `read::hits_limit`).
+    hits_limit: usize,
+}
+
+impl<B: Deref<Target = [u8]>> SliceReader<B> {
+    /// Load the table of contents of the index file `bytes`, with
+    /// `tombstones` deleted.
+    pub fn open(bytes: B, tombstones: Tombstones) -> Result<SliceReader<B>> {
+        let header = read_header(&mut &bytes[..])?;
+        let (table_contents_offset, doc_table_offset) =
+            (header.table_contents_offset, header.doc_table_offset);
+        header.check_len(bytes.len() as u64)?;
+
+        let docs = decode_doc_table(
+            &bytes[doc_table_offset as usize..table_contents_offset as usize],
+            header.settings.identity,
+        )?;
+        let table_end =
+            footer_start(&mut io::Cursor::new(&bytes[..]), &header.settings)?;
+        let table = bytes
+            .get(table_contents_offset as usize..table_end as usize)
+            .ok_or_else(|| {
+                Error::format("table of contents runs into the footer")
+            })?
+            .to_vec();
+        let entries = TermDictionary::load(table, doc_table_offset)?
+            .iter()
+            .collect();
+
+        let hits_limit = hits_limit(&docs, !header.settings.omit_positions);
+        Ok(SliceReader {
+            bytes,
+            entries,
+            docs,
+            tombstones,
+            settings: header.settings,
+            hits_limit,
+        })
+    }
+
+    /// The bytes of the file.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn bytes(&self) -> &B {
+        &self.bytes
+    }
+
+    /// The settings from the file header.
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    /// The document table of the file, by document id, including deleted
+    /// documents.
+    #[cfg(feature = "mmap")]
+    pub fn docs(&self) -> &DocTable {
+        &self.docs
+    }
+
+    /// Deleted documents, hidden from lookups.
+    #[cfg(feature = "mmap")]
+    pub fn tombstones(&self) -> &Tombstones {
+        &self.tombstones
+    }
+
+    /// The table of contents, sorted by term.
+    #[cfg(feature = "mmap")]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The table of contents entry for `term`, if the term is in the index.
+    pub fn entry(&self, term: &str) -> Option<&Entry> {
+        self.entries
+            .binary_search_by(|e| e.term.as_str().cmp(term))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// Decode the hits for `term`.
+    ///
+    /// Returns `Ok(None)` if the term does not occur in the index.
+    pub fn lookup(&self, term: &str) -> Result<Option<DocEntry>> {
+        match self.entry(term) {
+            Some(e) => self.decode(e),
+            None => Ok(None),
+        }
+    }
+
+    /// Decode the whole index, without the deleted documents.
+    pub fn load(&self) -> Result<ParsedIndex> {
+        let mut map = HashMap::new();
+        let mut word_count = 0;
+        for e in &self.entries {
+            if let Some(entry) = self.decode(e)? {
+                if !is_twin_term(&e.term) {
+                    word_count += 1;
+                }
+                map.insert(e.term.clone(), entry);
+            }
+        }
+        let docs = self
+            .docs
+            .iter()
+            .filter(|(doc, _)| !self.tombstones.contains(doc))
+            .cloned()
+            .collect();
+        Ok(ParsedIndex {
+            word_count,
+            map,
+            docs,
+            positions: !self.settings.omit_positions,
+        })
+    }
+
+    /// The live documents of the hits of `e`, if any.
+    fn decode(&self, e: &Entry) -> Result<Option<DocEntry>> {
+        let positions = !self.settings.omit_positions;
+        let mut entry =
+            decode_hits(&self.hits(e)?, e.doc_count, &self.docs, positions)?;
+        self.tombstones.purge(&mut entry);
+        Ok(Some(entry).filter(|e| !e.is_empty()))
+    }
+
+    /// The decompressed hits of an entry. With no codec, they are borrowed
+    /// straight from the bytes.
+    pub fn hits(&self, e: &Entry) -> Result<Cow<'_, [u8]>> {
+        self.settings
+            .codec
+            .decompress(self.data(e), self.hits_limit)
+    }
+
+    /// The raw bytes of an entry's data. Entries were bounds-checked when
+    /// the file was opened.
+    pub fn data(&self, e: &Entry) -> &[u8] {
+        &self.bytes[e.offset as usize..(e.offset + e.nbytes) as usize]
+    }
+}
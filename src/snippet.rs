@@ -0,0 +1,171 @@
+//! Snippets: short excerpts of a document around search hits.
+//!
+//! The index only stores word offsets. To show a hit in context, the
+//! document is read again and split with the same tokenizer that was used
+//! for indexing, which tells where each word starts and ends in the text.
+//! Hits close to each other are shown in the same excerpt.
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::Tokenizer;
+use crate::index::Offsets;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// How snippets are cut and highlighted.
+#[derive(Debug, Clone)]
+pub struct SnippetOptions {
+    /// Number of words to show on each side of a hit.
+    pub context: usize,
+    /// Inserted before each highlighted word.
+    pub open: String,
+    /// Inserted after each highlighted word.
+    pub close: String,
+    /// At most this many excerpts per document.
+    pub max_snippets: usize,
+}
+
+impl Default for SnippetOptions {
+    fn default() -> Self {
+        SnippetOptions {
+            context: 5,
+            open: "[".to_string(),
+            close: "]".to_string(),
+            max_snippets: 3,
+        }
+    }
+}
+
+/// Build excerpts of `text` around the words at `offsets`.
+///
+/// `tokenizer` must be the tokenizer the index was built with, otherwise the
+/// offsets point at the wrong words. Offsets that match no word are
+/// ignored. Whitespace inside an excerpt is collapsed to single spaces.
+pub fn snippets(
+    text: &str,
+    offsets: &Offsets,
+    tokenizer: &dyn Tokenizer,
+    options: &SnippetOptions,
+) -> Vec<String> {
+    let words = tokenizer.tokenize(text);
+    if words.is_empty() {
+        return vec![];
+    }
+    let hits: Vec<usize> = (0..words.len())
+        .filter(|&i| offsets.contains(&words[i].position))
+        .collect();
+
+    // Group hits into windows of words, merging windows that overlap.
+    let mut windows: Vec<(usize, usize, Vec<usize>)> = vec![];
+    for hit in hits {
+        let first = hit.saturating_sub(options.context);
+        let last = (hit + options.context).min(words.len() - 1);
+        match windows.last_mut() {
+            Some((_, end, group)) if first <= *end + 1 => {
+                *end = last;
+                group.push(hit);
+            }
+            _ => windows.push((first, last, vec![hit])),
+        }
+    }
+
+    windows
+        .into_iter()
+        .take(options.max_snippets)
+        .map(|(first, last, group)| {
+            let mut out = String::new();
+            if first > 0 {
+                out.push_str("... ");
+            }
+            let mut cursor = words[first].start;
+            for &hit in &group {
+                out.push_str(&text[cursor..words[hit].start]);
+                out.push_str(&options.open);
+                out.push_str(&text[words[hit].start..words[hit].end]);
+                out.push_str(&options.close);
+                cursor = words[hit].end;
+            }
+            out.push_str(&text[cursor..words[last].end]);
+            if last + 1 < words.len() {
+                out.push_str(" ...");
+            }
+            out.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::SimpleTokenizer;
+
+    fn cut(
+        text: &str,
+        offsets: &[u32],
+        context: usize,
+        max: usize,
+    ) -> Vec<String> {
+        let options = SnippetOptions {
+            context,
+            max_snippets: max,
+            ..SnippetOptions::default()
+        };
+        snippets(text, &offsets.to_vec(), &SimpleTokenizer, &options)
+    }
+
+    #[test]
+    fn hits_are_shown_in_windows_of_context() {
+        let text = "zero one two three four five six seven eight nine";
+        let cases: &[(&[u32], usize, usize, &[&str])] = &[
+            (&[0], 2, 3, &["[zero] one two ..."]),
+            (&[9], 2, 3, &["... seven eight [nine]"]),
+            (&[5], 1, 3, &["... four [five] six ..."]),
+            (&[4], 0, 3, &["... [four] ..."]),
+            // Windows that touch or overlap are merged.
+            (&[2, 5], 1, 3, &["... one [two] three four [five] six ..."]),
+            (
+                &[1, 7],
+                1,
+                3,
+                &["zero [one] two ...", "... six [seven] eight ..."],
+            ),
+            (
+                &[2, 8],
+                1,
+                3,
+                &["... one [two] three ...", "... seven [eight] nine"],
+            ),
+            (&[0, 4, 8], 0, 2, &["[zero] ...", "... [four] ..."]),
+            (
+                &[3],
+                20,
+                3,
+                &["zero one two [three] four five six seven eight nine"],
+            ),
+            // Offsets past the text match no word.
+            (&[42], 2, 3, &[]),
+        ];
+        for &(offsets, context, max, expected) in cases {
+            assert_eq!(
+                cut(text, offsets, context, max),
+                expected,
+                "{:?}",
+                offsets
+            );
+        }
+    }
+
+    #[test]
+    fn excerpts_keep_the_text_between_words() {
+        let text = "A  cat,\n\tsat (on)\nthe mat.";
+        let options = SnippetOptions {
+            context: 1,
+            open: "<b>".to_string(),
+            close: "</b>".to_string(),
+            max_snippets: 1,
+        };
+        let found = snippets(text, &vec![2], &SimpleTokenizer, &options);
+        assert_eq!(found, ["... cat, <b>sat</b> (on ..."]);
+        assert!(snippets("", &vec![0], &SimpleTokenizer, &options).is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+//! Statistics about an index file.
+//!
+//! `index_stats` walks an index file once, with the streaming entry iterator,
+//! and sums up what's in it: how many terms, documents and postings, how
+//! long documents are, which terms are the most frequent and how many bytes
+//! each section of the file takes. The most frequent terms show whether stop
+//! words or stemming would help, and the section sizes how well the codec
+//! does. Terms of the title and the path are ranked apart from those of the
+//! body, as every document has a path, whose words would otherwise crowd
+//! out those of the text.
+//!
+//! Tools that rank or analyze documents themselves need fewer numbers, but
+//! need them without reading the whole file: `IndexFileSearcher::term_stats`
+//! and `IndexFileSearcher::collection_stats` read them from the table of
+//! contents and the document table alone, and `Searcher` has the same
+//! methods for indexes loaded in memory.
+//!
+//! Terms holding byte offsets or line numbers (see the `analyzer` module)
+//! aren't words, and are left out of every statistic.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::{self, BufReader, Seek};
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::bloom::footer_start;
+use crate::crypt::IndexFile;
+use crate::description::description_start;
+use crate::error::Result;
+use crate::field::Field;
+use crate::header::{read_header, IndexSettings};
+use crate::index::{is_twin_term, DocEntry, DocInfo};
+use crate::read::IndexFileReader;
+use crate::score::CorpusStats;
+use crate::tombstone::Tombstones;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// What `index_stats` found. Deleted documents are left out of every count
+/// but `deleted_documents`.
+#[derive(Debug, Clone, Default)]
+pub struct IndexStats {
+    /// The settings from the file header.
+    pub settings: IndexSettings,
+    /// Number of documents.
+    pub documents: usize,
+    /// Number of documents deleted through the tombstones but still in the
+    /// file.
+    pub deleted_documents: usize,
+    /// Number of distinct terms, all fields included.
+    pub terms: usize,
+    /// Number of (term, document) pairs.
+    pub postings: u64,
+    /// Number of term occurrences, that is of offsets.
+    pub occurrences: u64,
+    /// Average document length, in words of the body.
+    pub avg_doc_len: f64,
+    /// Average length of each field other than the body, in words, in the
+    /// order of `Field::NAMED`, over the documents that have it.
+    pub avg_field_lens: [f64; Field::NAMED.len()],
+    /// The most frequent terms of the body, most frequent first.
+    pub top_terms: Vec<TermStats>,
+    /// The most frequent terms of each other field, in the order of
+    /// `Field::NAMED`, most frequent first.
+    pub top_field_terms: [Vec<TermStats>; Field::NAMED.len()],
+    /// Size of each section of the file.
+    pub sections: Sections,
+}
+
+/// How often a term is used.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TermStats {
+    pub term: String,
+    /// Number of documents that contain the term.
+    pub documents: usize,
+    /// Number of times the term occurs, in all documents.
+    pub occurrences: u64,
+}
+
+impl TermStats {
+    /// The statistics of `term` from its decoded `postings`.
+    pub(crate) fn of_postings(term: &str, postings: &DocEntry) -> TermStats {
+        TermStats {
+            term: term.to_string(),
+            documents: postings.len(),
+            occurrences: postings.values().map(|o| o.len() as u64).sum(),
+        }
+    }
+}
+
+impl Ord for TermStats {
+    /// More occurrences first, then alphabetical order.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .occurrences
+            .cmp(&self.occurrences)
+            .then_with(|| self.term.cmp(&other.term))
+    }
+}
+
+impl PartialOrd for TermStats {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Numbers about a whole index that don't take decoding its hits, for
+/// ranking documents or analyzing the corpus.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollectionStats {
+    /// Number of documents.
+    pub documents: usize,
+    /// Number of distinct terms, all fields included.
+    pub terms: usize,
+    /// Number of (term, document) pairs.
+    pub postings: u64,
+    /// Number of term occurrences, that is of offsets.
+    pub occurrences: u64,
+    /// Number of words of the bodies of all documents.
+    pub words: u64,
+    /// Average document length, in words of the body.
+    pub avg_doc_len: f64,
+    /// Average length of each field other than the body, in words, in the
+    /// order of `Field::NAMED`, over the documents that have it.
+    pub avg_field_lens: [f64; Field::NAMED.len()],
+}
+
+impl CollectionStats {
+    /// The statistics of an index of the documents of `corpus`, whose terms
+    /// add up to `postings` and `occurrences`.
+    pub(crate) fn new(
+        corpus: &CorpusStats,
+        terms: usize,
+        postings: u64,
+        occurrences: u64,
+    ) -> CollectionStats {
+        CollectionStats {
+            documents: corpus.doc_count,
+            terms,
+            postings,
+            occurrences,
+            words: corpus.doc_lens.values().map(|len| *len as u64).sum(),
+            avg_doc_len: corpus.avg_doc_len,
+            avg_field_lens: corpus.avg_field_lens,
+        }
+    }
+}
+
+/// Size of each section of an index file, in bytes. See `write` and
+/// `header` for the layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sections {
+    pub header: u64,
+    /// The compressed hits of all terms.
+    pub postings: u64,
+    pub doc_table: u64,
+    pub table_of_contents: u64,
+    /// The footer with the bloom filter of the terms, if there is one.
+    pub bloom_filter: u64,
+    /// The description of the file at its end.
+    pub description: u64,
+    /// The tombstone sidecar, if there is one.
+    pub tombstones: u64,
+}
+
+impl Sections {
+    /// Size of the index file and its sidecar.
+    pub fn total(&self) -> u64 {
+        self.header
+            + self.postings
+            + self.doc_table
+            + self.table_of_contents
+            + self.bloom_filter
+            + self.description
+            + self.tombstones
+    }
+}
+
+/// Read all of `index_file` and compute its statistics, keeping the `top`
+/// most frequent terms of each field.
+pub fn index_stats<P: AsRef<Path>>(
+    index_file: P,
+    top: usize,
+) -> Result<IndexStats> {
+    let index_file = index_file.as_ref();
+    let sections = sections(index_file)?;
+    let tombstones = Tombstones::load(index_file)?;
+    let mut reader = IndexFileReader::open(index_file)?;
+    let mut stats = IndexStats {
+        settings: reader.settings().clone(),
+        deleted_documents: reader
+            .docs()
+            .iter()
+            .filter(|(doc, _)| tombstones.contains(doc))
+            .count(),
+        sections,
+        ..IndexStats::default()
+    };
+    stats.documents = reader.docs().len() - stats.deleted_documents;
+    let live: Vec<&DocInfo> = reader
+        .docs()
+        .iter()
+        .filter(|(doc, _)| !tombstones.contains(doc))
+        .map(|(_, info)| info)
+        .collect();
+    if !live.is_empty() {
+        let total: u64 = live.iter().map(|info| info.token_count as u64).sum();
+        stats.avg_doc_len = total as f64 / live.len() as f64;
+    }
+    for (i, avg) in stats.avg_field_lens.iter_mut().enumerate() {
+        let lens: Vec<u64> = live
+            .iter()
+            .map(|info| info.field_lens[i] as u64)
+            .filter(|len| *len > 0)
+            .collect();
+        if !lens.is_empty() {
+            *avg = lens.iter().sum::<u64>() as f64 / lens.len() as f64;
+        }
+    }
+
+    // The `top` most frequent terms of each field so far, the body first.
+    // Less frequent terms order after more frequent ones, so the top of a
+    // heap is the one to go when a more frequent term comes.
+    let mut top_terms: Vec<BinaryHeap<TermStats>> = (0..=Field::NAMED.len())
+        .map(|_| BinaryHeap::with_capacity(top + 1))
+        .collect();
+    for entry in reader.entries() {
+        let (term, mut postings) = entry?;
+        tombstones.purge(&mut postings);
+        if postings.is_empty() || is_twin_term(&term) {
+            continue;
+        }
+        let term_stats = TermStats::of_postings(&term, &postings);
+        stats.terms += 1;
+        stats.postings += term_stats.documents as u64;
+        stats.occurrences += term_stats.occurrences;
+        if top > 0 {
+            let field =
+                Field::of_term(&term).named_index().map_or(0, |i| i + 1);
+            let heap = &mut top_terms[field];
+            heap.push(term_stats);
+            if heap.len() > top {
+                heap.pop();
+            }
+        }
+    }
+
+    let mut top_terms = top_terms.into_iter().map(BinaryHeap::into_sorted_vec);
+    stats.top_terms = top_terms.next().unwrap_or_default();
+    for (field_terms, terms) in stats.top_field_terms.iter_mut().zip(top_terms)
+    {
+        *field_terms = terms;
+    }
+    Ok(stats)
+}
+
+/// Measure the sections of `index_file` from the offsets in its header.
+fn sections(index_file: &Path) -> Result<Sections> {
+    let mut f = BufReader::new(IndexFile::open(index_file)?);
+    let header = read_header(&mut f)?;
+    let header_len = f.stream_position()?;
+    let file_len = f.get_ref().len();
+    let footer_start = footer_start(&mut f, &header.settings)?;
+    let description_start = description_start(&mut f)?;
+    let tombstones = match fs::metadata(Tombstones::sidecar_path(index_file)) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Sections {
+        header: header_len,
+        postings: header.doc_table_offset - header_len,
+        doc_table: header.table_contents_offset - header.doc_table_offset,
+        table_of_contents: footer_start
+            .saturating_sub(header.table_contents_offset),
+        bloom_filter: description_start - footer_start,
+        description: file_len - description_start,
+        tombstones,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::TextAnalyzer;
+    use crate::fixtures::{doc, write_index};
+    use crate::index::InMemoryIndex;
+    use crate::tmp::TmpDir;
+    use crate::write::write_index_to_tmp_file;
+
+    fn top(terms: &[TermStats]) -> Vec<(&str, usize, u64)> {
+        terms
+            .iter()
+            .map(|t| (t.term.as_str(), t.documents, t.occurrences))
+            .collect()
+    }
+
+    #[test]
+    fn counts_leave_deleted_documents_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["the cat and the dog", "the cat", "a bird"];
+        let file = write_index(dir.path(), &texts);
+        let stats = index_stats(&file, 2).unwrap();
+        assert_eq!((stats.documents, stats.deleted_documents), (3, 0));
+        assert_eq!((stats.terms, stats.postings, stats.occurrences), (6, 8, 9));
+        assert_eq!(stats.avg_doc_len, 3.0);
+        assert_eq!(top(&stats.top_terms), [("the", 2, 3), ("cat", 2, 2)]);
+        let len = fs::metadata(&file).unwrap().len();
+        assert_eq!(stats.sections.total(), len);
+
+        let mut tombstones = Tombstones::load(&file).unwrap();
+        tombstones.add(doc(0));
+        tombstones.save(&file).unwrap();
+        let stats = index_stats(&file, 3).unwrap();
+        assert_eq!((stats.documents, stats.deleted_documents), (2, 1));
+        assert_eq!((stats.terms, stats.postings, stats.occurrences), (4, 4, 4));
+        assert_eq!(stats.avg_doc_len, 2.0);
+        assert_eq!(
+            top(&stats.top_terms),
+            [("a", 1, 1), ("bird", 1, 1), ("cat", 1, 1)]
+        );
+        assert!(stats.sections.tombstones > 0);
+        assert_eq!(stats.sections.total(), len + stats.sections.tombstones);
+        assert!(index_stats(&file, 0).unwrap().top_terms.is_empty());
+    }
+
+    #[test]
+    fn terms_of_other_fields_are_ranked_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = InMemoryIndex::new();
+        for (n, text) in [(0, "a cat"), (1, "a dog")] {
+            let path = format!("src/pets/docs/{}.txt", n);
+            index.merge(InMemoryIndex::from_single_document_fields(
+                &doc(n).hash,
+                text.to_string(),
+                &[(Field::Title, "Pets".to_string()), (Field::Path, path)],
+                &TextAnalyzer::default(),
+            ));
+        }
+        let settings = IndexSettings::default();
+        let file = write_index_to_tmp_file(
+            index,
+            &mut TmpDir::new(dir.path()),
+            &settings,
+        )
+        .unwrap();
+        let stats = index_stats(&file, 2).unwrap();
+        assert_eq!(top(&stats.top_terms), [("a", 2, 2), ("cat", 1, 1)]);
+        let [title, path] = &stats.top_field_terms;
+        assert_eq!(top(title), [("title:pets", 2, 2)]);
+        assert_eq!(top(path), [("path:docs", 2, 2), ("path:pets", 2, 2)]);
+        // Every term counts, whatever its field.
+        assert_eq!(stats.terms, 3 + 1 + 6);
+    }
+}
@@ -0,0 +1,161 @@
+//! Built-in stop-word lists.
+//!
+//! These are short lists of the most frequent function words of each
+//! language. They are deliberately conservative: anything that could carry
+//! meaning on its own is left out. All words are lowercase, so the filter
+//! using them must run after `LowerCaser`.
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+const ENGLISH: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in",
+    "into", "is", "it", "no", "not", "of", "on", "or", "such", "that", "the",
+    "their", "then", "there", "these", "they", "this", "to", "was", "will",
+    "with",
+];
+
+const FRENCH: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en",
+    "et", "eux", "il", "je", "la", "le", "les", "leur", "lui", "ma", "mais",
+    "me", "mes", "moi", "mon", "ne", "nos", "notre", "nous", "on", "ou", "par",
+    "pas", "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sur", "ta",
+    "te", "tes", "toi", "ton", "tu", "un", "une", "vos", "votre", "vous",
+];
+
+const GERMAN: &[&str] = &[
+    "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis",
+    "das", "dass", "dem", "den", "der", "des", "die", "ein", "eine", "einem",
+    "einen", "einer", "eines", "er", "es", "für", "hat", "ich", "im", "in",
+    "ist", "mit", "nach", "nicht", "noch", "oder", "sich", "sie", "sind", "so",
+    "und", "von", "vor", "war", "wie", "wir", "zu", "zum", "zur",
+];
+
+const SPANISH: &[&str] = &[
+    "a", "al", "como", "con", "de", "del", "el", "en", "es", "esta", "este",
+    "la", "las", "lo", "los", "más", "mi", "no", "o", "para", "pero", "por",
+    "que", "se", "si", "sin", "su", "sus", "un", "una", "y", "ya",
+];
+
+const ITALIAN: &[&str] = &[
+    "a", "al", "alla", "che", "con", "da", "dei", "del", "della", "di", "e",
+    "è", "gli", "il", "in", "la", "le", "lo", "ma", "non", "per", "si", "su",
+    "un", "una", "uno",
+];
+
+const PORTUGUESE: &[&str] = &[
+    "a", "ao", "as", "com", "da", "das", "de", "do", "dos", "e", "em", "é",
+    "na", "nas", "no", "nos", "o", "os", "ou", "para", "por", "que", "se",
+    "um", "uma",
+];
+
+const DUTCH: &[&str] = &[
+    "aan", "al", "dat", "de", "die", "dit", "een", "en", "er", "het", "hij",
+    "in", "is", "je", "met", "niet", "of", "op", "te", "van", "voor", "was",
+    "wat", "ze", "zijn",
+];
+
+const RUSSIAN: &[&str] = &[
+    "а", "без", "в", "во", "да", "для", "до", "же", "за", "и", "из", "или",
+    "к", "как", "ли", "на", "не", "но", "о", "об", "от", "по", "с", "со", "то",
+    "у", "что",
+];
+
+/// The built-in stop words for `language` (e.g. "english"), or `None` if
+/// there is no list for it.
+pub fn for_language(language: &str) -> Option<&'static [&'static str]> {
+    match language.to_lowercase().as_str() {
+        "english" => Some(ENGLISH),
+        "french" => Some(FRENCH),
+        "german" => Some(GERMAN),
+        "spanish" => Some(SPANISH),
+        "italian" => Some(ITALIAN),
+        "portuguese" => Some(PORTUGUESE),
+        "dutch" => Some(DUTCH),
+        "russian" => Some(RUSSIAN),
+        _ => None,
+    }
+}
+
+/// Parse a custom stop-word file: one word per line, blank lines and lines
+/// starting with `#` are ignored. Words are lowercased.
+pub fn parse_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{Analyzer, AnalyzerConfig};
+
+    #[test]
+    fn sentences_lose_the_stop_words_of_their_language() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            ("english", "The cat is on the mat", &["cat", "mat"]),
+            (
+                "french",
+                "Le chat est sur la table",
+                &["chat", "est", "table"],
+            ),
+            ("german", "Die Katze ist auf dem Tisch", &["katze", "tisch"]),
+            (
+                "spanish",
+                "El gato está en la mesa",
+                &["gato", "está", "mesa"],
+            ),
+            (
+                "italian",
+                "Il gatto è sul tavolo",
+                &["gatto", "sul", "tavolo"],
+            ),
+            (
+                "portuguese",
+                "O gato está na mesa",
+                &["gato", "está", "mesa"],
+            ),
+            ("dutch", "De kat is op de mat", &["kat", "mat"]),
+            (
+                "russian",
+                "Кошка на столе и под столом",
+                &["кошка", "столе", "под", "столом"],
+            ),
+        ];
+        for &(language, text, expected) in cases {
+            let config = AnalyzerConfig {
+                stop_words: vec![language.to_string()],
+                ..Default::default()
+            };
+            let analyzer = config.build().unwrap();
+            let terms: Vec<String> =
+                analyzer.analyze(text).into_iter().map(|t| t.text).collect();
+            assert_eq!(terms, expected, "{}", language);
+        }
+    }
+
+    #[test]
+    fn lists_are_lowercase_and_found_by_any_case() {
+        for language in crate::language::LANGUAGES {
+            let Some(words) = for_language(language) else {
+                continue;
+            };
+            assert!(
+                words.iter().all(|w| *w == w.to_lowercase()),
+                "{}",
+                language
+            );
+            let upper = for_language(&language.to_uppercase());
+            assert_eq!(upper, Some(words));
+        }
+        assert_eq!(for_language("klingon"), None);
+    }
+
+    #[test]
+    fn custom_lists_skip_blank_lines_and_comments() {
+        let text = "# Project jargon\n\n  Foo \nBAR\n#baz\nqux";
+        assert_eq!(parse_list(text), ["foo", "bar", "qux"]);
+        assert!(parse_list("").is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+//! Helpers for testing code built on the crate against the index format.
+//!
+//! Needs the `testing` feature. `corpus` and `settings` are proptest
+//! strategies for documents and for the settings index files are written
+//! with. The `assert_*` functions write documents to index files, read them
+//! back, look their terms up and merge them, and panic, naming the first term
+//! that differs, unless the postings that come out are exactly those of the
+//! documents. Code that writes index files of its own, or reads them, can
+//! check itself the same way:
+//!
+//! ```text
+//! proptest! {
+//!     #[test]
+//!     fn my_files_read_back(texts in corpus(), settings in settings()) {
+//!         let dir = tempfile::tempdir().unwrap();
+//!         let file = assert_write_read(&texts, &settings, dir.path());
+//!         // ... read `file` with your own code and compare with
+//!         // `expected_postings(&texts, &settings)`.
+//!     }
+//! }
+//! ```
+
+use proptest::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer::AnalyzerConfig;
+use crate::bench::{index, write_segments};
+use crate::codec::Codec;
+use crate::header::IndexSettings;
+use crate::index::{Doc, DocEntry, DocInfo};
+use crate::merge::merge_index_files;
+use crate::read::{IndexFileReader, IndexFileSearcher};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Words `document` picks from besides random ones, so that terms repeat
+/// within and across documents, in several cases, with stop words and
+/// non-ASCII letters.
+const WORDS: [&str; 12] = [
+    "the", "The", "cat", "dog", "and", "of", "Rust", "rust", "über", "naïve",
+    "42", "x",
+];
+
+/// A word: one of `WORDS` or a random one.
+fn word() -> impl Strategy<Value = String> {
+    prop_oneof![
+        3 => prop::sample::select(&WORDS[..]).prop_map(str::to_string),
+        1 => "[a-zA-Z]{1,8}",
+    ]
+}
+
+/// A document of up to 40 words, separated by spaces, newlines or
+/// punctuation.
+pub fn document() -> impl Strategy<Value = String> {
+    let separator = prop::sample::select(&[" ", " ", "\n", ", ", ". "][..]);
+    prop::collection::vec((word(), separator), 0..40).prop_map(|words| {
+        words
+            .into_iter()
+            .fold(String::new(), |mut text, (word, sep)| {
+                text.push_str(&word);
+                text.push_str(sep);
+                text
+            })
+    })
+}
+
+/// From one to 8 documents.
+pub fn corpus() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(document(), 1..8)
+}
+
+/// Settings of an index file: any codec of this build, with or without a
+/// bloom filter and positions, analyzing with or without English stop
+/// words and the original case of words.
+pub fn settings() -> impl Strategy<Value = IndexSettings> {
+    let codecs: Vec<Codec> = [Codec::None, Codec::Zstd, Codec::Lz4]
+        .into_iter()
+        .filter(|codec| codec.is_available())
+        .collect();
+    (
+        prop::sample::select(codecs),
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+    )
+        .prop_map(
+            |(codec, bloom_filter, omit_positions, preserve_case, stop)| {
+                IndexSettings {
+                    codec,
+                    analyzer: AnalyzerConfig {
+                        stop_words: match stop {
+                            true => vec!["english".to_string()],
+                            false => vec![],
+                        },
+                        preserve_case,
+                        ..AnalyzerConfig::default()
+                    },
+                    bloom_filter,
+                    omit_positions,
+                    ..IndexSettings::default()
+                }
+            },
+        )
+}
+
+/// The postings an index file of `texts` written with `settings` must read
+/// back as, the hash of each document being its number in `texts` (see
+/// `bench::index`). Without positions, each document has offsets 0, 1, 2…
+/// standing for the number of times it has the term.
+pub fn expected_postings(
+    texts: &[String],
+    settings: &IndexSettings,
+) -> HashMap<String, DocEntry> {
+    let analyzer = settings.analyzer.build().unwrap();
+    let mut map = index(texts, &analyzer).to_parsed().map;
+    if settings.omit_positions {
+        for offsets in map.values_mut().flat_map(|entry| entry.values_mut()) {
+            *offsets = (0..offsets.len() as u32).collect();
+        }
+    }
+    map
+}
+
+/// The document table an index file of `texts` written with `settings`
+/// must read back as.
+fn expected_docs(
+    texts: &[String],
+    settings: &IndexSettings,
+) -> HashMap<Doc, DocInfo> {
+    let analyzer = settings.analyzer.build().unwrap();
+    index(texts, &analyzer).docs.into_iter().collect()
+}
+
+/// Panic unless `actual`, the postings read from `file`, are `expected`,
+/// naming the first term, in order, that differs.
+pub fn assert_same_postings(
+    actual: &HashMap<String, DocEntry>,
+    expected: &HashMap<String, DocEntry>,
+    file: &Path,
+) {
+    let terms: BTreeSet<&String> =
+        actual.keys().chain(expected.keys()).collect();
+    for term in terms {
+        assert_eq!(
+            actual.get(term),
+            expected.get(term),
+            "postings of {:?} read from {}",
+            term,
+            file.display()
+        );
+    }
+}
+
+/// Write `texts` to an index file in `dir` with `settings`, then panic
+/// unless reading it from beginning to end, and looking each of its terms
+/// up, gives back exactly the postings and documents of `texts`. Returns the
+/// file.
+pub fn assert_write_read(
+    texts: &[String],
+    settings: &IndexSettings,
+    dir: &Path,
+) -> PathBuf {
+    let file = write_segments(texts, 1, dir, settings).unwrap().remove(0);
+    assert_reads_back(&file, texts, settings);
+    file
+}
+
+/// Write `texts` in `segments` index files in `dir` with `settings`, merge
+/// them, then panic unless the merged file reads back like a single file of
+/// `texts` (see `assert_write_read`). The segments are removed; returns the
+/// merged file.
+pub fn assert_merge(
+    texts: &[String],
+    segments: usize,
+    settings: &IndexSettings,
+    dir: &Path,
+) -> PathBuf {
+    let files = write_segments(texts, segments, dir, settings).unwrap();
+    let merged = merge_index_files(&files, dir).unwrap();
+    for file in files {
+        fs::remove_file(file).unwrap();
+    }
+    assert_reads_back(&merged, texts, settings);
+    merged
+}
+
+/// `assert_write_read`, then `assert_merge`, leaving nothing in `dir`.
+pub fn assert_round_trip(
+    texts: &[String],
+    segments: usize,
+    settings: &IndexSettings,
+    dir: &Path,
+) {
+    fs::remove_file(assert_write_read(texts, settings, dir)).unwrap();
+    fs::remove_file(assert_merge(texts, segments, settings, dir)).unwrap();
+}
+
+/// Panic unless `file` reads back as an index of `texts` written with
+/// `settings`, from beginning to end and through lookups.
+fn assert_reads_back(file: &Path, texts: &[String], settings: &IndexSettings) {
+    assert_eq!(&IndexSettings::load(file).unwrap(), settings);
+    let expected = expected_postings(texts, settings);
+    let index = IndexFileReader::get_index_from_file(file).unwrap();
+    assert_same_postings(&index.map, &expected, file);
+    assert_eq!(index.docs, expected_docs(texts, settings));
+    assert_eq!(index.positions, !settings.omit_positions);
+
+    let mut searcher = IndexFileSearcher::open(file).unwrap();
+    let looked_up = expected
+        .keys()
+        .map(|term| {
+            let entry = searcher.lookup(term).unwrap().unwrap_or_default();
+            (term.clone(), entry)
+        })
+        .collect();
+    assert_same_postings(&looked_up, &expected, file);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn written_files_read_back(texts in corpus(), settings in settings()) {
+            let dir = tempfile::tempdir().unwrap();
+            assert_write_read(&texts, &settings, dir.path());
+        }
+
+        #[test]
+        fn merged_files_read_back(
+            texts in corpus(),
+            segments in 1..4usize,
+            settings in settings(),
+        ) {
+            let dir = tempfile::tempdir().unwrap();
+            let merged = assert_merge(&texts, segments, &settings, dir.path());
+            fs::remove_file(merged).unwrap();
+            prop_assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        }
+    }
+}
@@ -19,7 +19,9 @@ impl TmpDir {
     }
 
     pub fn create(&mut self) -> io::Result<(PathBuf, BufWriter<File>)> {
-        let mut retry = 1;
+        // Names already taken (for example by another `TmpDir` on the same
+        // directory, which may hold up to a merge fan-in worth of files) are
+        // skipped.
         loop {
             let filename = self
                 .dir
@@ -31,15 +33,64 @@ impl TmpDir {
                 .open(&filename)
             {
                 Ok(f) => return Ok((filename, BufWriter::new(f))),
-                Err(e) => {
-                    if retry < 50 && e.kind() == io::ErrorKind::AlreadyExists {
-                        // keep going
-                    } else {
-                        return Err(e);
-                    }
-                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
             }
-            retry += 1;
         }
     }
 }
+
+/// Move the complete file `file` to `dest`, replacing it atomically, so that
+/// `dest` is either the old file or the whole new one, also after a crash.
+/// The data is synced to disk before the rename, and the directory after it.
+pub fn publish(file: &Path, dest: &Path) -> io::Result<()> {
+    File::open(file)?.sync_all()?;
+    fs::rename(file, dest)?;
+    match dest.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+/// Make the renames in `dir` durable.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Windows can't open a directory as a file, and doesn't need to.
+    if cfg!(windows) {
+        return Ok(());
+    }
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn published_files_replace_their_destination_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("index.dat");
+        fs::write(&dest, "old").unwrap();
+        // A name taken by someone else is skipped.
+        fs::write(dir.path().join("tmp00000001.dat"), "theirs").unwrap();
+        let mut tmp_dir = TmpDir::new(dir.path());
+        let (file, mut out) = tmp_dir.create().unwrap();
+        assert_eq!(file, dir.path().join("tmp00000002.dat"));
+        out.write_all(b"the whole new file").unwrap();
+        drop(out);
+
+        publish(&file, &dest).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "the whole new file");
+        assert!(!file.exists());
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["index.dat", "tmp00000001.dat"]);
+
+        // A file that isn't there leaves the destination alone.
+        assert!(publish(&file, &dest).is_err());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "the whole new file");
+    }
+}
@@ -0,0 +1,217 @@
+//! Deleted documents.
+//!
+//! Index files are immutable, so removing a document doesn't touch the index
+//! itself. Instead the document's hash is added to a tombstone list stored
+//! next to the index, in a sidecar file named after it (`index.dat` gets
+//! `index.dat.tombstones`). Readers load the sidecar and hide tombstoned
+//! documents from lookups. `compact` rewrites the index without them and
+//! removes the sidecar.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+use crate::header::read_any_header;
+use crate::index::{Doc, DocEntry};
+use crate::read::{HitRemapper, IndexFileReader};
+use crate::tmp::publish;
+use crate::write::IndexFileWriter;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// The set of deleted documents of one index file.
+#[derive(Debug, Default, Clone)]
+pub struct Tombstones {
+    docs: HashSet<Doc>,
+}
+
+impl Tombstones {
+    /// Path of the sidecar file holding the tombstones for `index_file`.
+    pub fn sidecar_path<P: AsRef<Path>>(index_file: P) -> PathBuf {
+        let mut path = index_file.as_ref().as_os_str().to_owned();
+        path.push(".tombstones");
+        PathBuf::from(path)
+    }
+
+    /// Load the tombstones for `index_file`. A missing sidecar simply means
+    /// that no document was deleted.
+    ///
+    /// The sidecar holds the deleted documents' ids back to back, so the
+    /// header of `index_file` is read for their length.
+    pub fn load<P: AsRef<Path>>(index_file: P) -> Result<Tombstones> {
+        let index_file = index_file.as_ref();
+        let mut bytes = vec![];
+        match File::open(Self::sidecar_path(index_file)) {
+            Ok(mut f) => f.read_to_end(&mut bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Tombstones::default())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.is_empty() {
+            return Ok(Tombstones::default());
+        }
+        let mut f = BufReader::new(File::open(index_file)?);
+        let (_, header) = read_any_header(&mut f)?;
+        Tombstones::from_bytes(&bytes, header.settings.identity.hash_len())
+    }
+
+    /// The tombstones of a sidecar holding `bytes`, of an index file whose
+    /// ids are `hash_len` bytes long.
+    pub(crate) fn from_bytes(
+        bytes: &[u8],
+        hash_len: usize,
+    ) -> Result<Tombstones> {
+        if !bytes.len().is_multiple_of(hash_len) {
+            return Err(Error::format("tombstone file is truncated"));
+        }
+        Ok(Tombstones {
+            docs: bytes.chunks(hash_len).map(Doc::new).collect(),
+        })
+    }
+
+    /// Write the tombstones for `index_file`, replacing any previous sidecar.
+    pub fn save<P: AsRef<Path>>(&self, index_file: P) -> io::Result<()> {
+        let mut f =
+            BufWriter::new(File::create(Self::sidecar_path(index_file))?);
+        for doc in &self.docs {
+            f.write_all(&doc.hash)?;
+        }
+        f.flush()
+    }
+
+    /// Mark a document as deleted. Returns `false` if it already was.
+    pub fn add(&mut self, doc: Doc) -> bool {
+        self.docs.insert(doc)
+    }
+
+    /// True if `doc` has been deleted.
+    pub fn contains(&self, doc: &Doc) -> bool {
+        self.docs.contains(doc)
+    }
+
+    /// True if no document has been deleted.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Number of deleted documents.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Remove deleted documents from a decoded entry.
+    pub fn purge(&self, entry: &mut DocEntry) {
+        if !self.is_empty() {
+            entry.retain(|doc, _| !self.contains(doc));
+        }
+    }
+}
+
+/// Rewrite `index_file` without the documents listed in its tombstones, then
+/// remove the sidecar. Terms that no longer occur in any document are
+/// dropped.
+///
+/// Returns the number of documents that were purged.
+pub fn compact<P: AsRef<Path>>(index_file: P) -> Result<usize> {
+    let index_file = index_file.as_ref();
+    let tombstones = Tombstones::load(index_file)?;
+    if tombstones.is_empty() {
+        return Ok(0);
+    }
+
+    let mut compacted = index_file.as_os_str().to_owned();
+    compacted.push(".compact");
+    let compacted = PathBuf::from(compacted);
+
+    {
+        let mut reader = IndexFileReader::open(index_file)?;
+        let out = BufWriter::new(File::create(&compacted)?);
+        let mut writer = IndexFileWriter::new(out, reader.settings())?;
+
+        // Deleted documents leave the document table, and the remaining
+        // ones are renumbered to fill the gaps.
+        let mut new_ids = Vec::with_capacity(reader.docs().len());
+        let mut next_id = 0;
+        for (doc, info) in reader.docs() {
+            if tombstones.contains(doc) {
+                new_ids.push(None);
+            } else {
+                writer.write_doc_entry(doc, info)?;
+                new_ids.push(Some(next_id));
+                next_id += 1;
+            }
+        }
+        while let Some((entry, hits_raw)) = reader.take_entry()? {
+            let (hits, doc_count) = HitRemapper::default().remap(
+                &hits_raw,
+                entry.doc_count,
+                !reader.settings().omit_positions,
+                |id| new_ids.get(id as usize).copied().flatten(),
+            )?;
+            if doc_count == 0 {
+                continue;
+            }
+            writer.write_term(entry.term, doc_count, &hits)?;
+        }
+        writer.finish()?;
+    }
+
+    publish(&compacted, index_file)?;
+    fs::remove_file(Tombstones::sidecar_path(index_file))?;
+    Ok(tombstones.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_index;
+    use crate::HASH_LENGTH;
+
+    #[test]
+    fn tombstones_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &["a cat"]);
+        assert!(Tombstones::load(&file).unwrap().is_empty());
+
+        let mut tombstones = Tombstones::default();
+        assert!(tombstones.add(Doc::new(&[1; HASH_LENGTH])));
+        assert!(tombstones.add(Doc::new(&[2; HASH_LENGTH])));
+        assert!(!tombstones.add(Doc::new(&[1; HASH_LENGTH])));
+        tombstones.save(&file).unwrap();
+        let loaded = Tombstones::load(&file).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.contains(&Doc::new(&[2; HASH_LENGTH])));
+        assert!(!loaded.contains(&Doc::new(&[3; HASH_LENGTH])));
+
+        fs::write(Tombstones::sidecar_path(&file), [1; HASH_LENGTH + 1])
+            .unwrap();
+        assert!(Tombstones::load(&file).is_err());
+    }
+
+    #[test]
+    fn compact_drops_deleted_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let file =
+            write_index(dir.path(), &["a cat and a dog", "a bird", "a dog"]);
+        assert_eq!(compact(&file).unwrap(), 0);
+
+        let mut tombstones = Tombstones::default();
+        tombstones.add(Doc::new(&[1; HASH_LENGTH]));
+        tombstones.save(&file).unwrap();
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert!(!index.map.contains_key("bird"));
+
+        assert_eq!(compact(&file).unwrap(), 1);
+        assert!(!Tombstones::sidecar_path(&file).exists());
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert!(!index.map.contains_key("bird"));
+        assert_eq!(index.map["a"].len(), 2);
+        assert_eq!(index.map["dog"].len(), 2);
+        assert_eq!(index.map["cat"].len(), 1);
+    }
+}
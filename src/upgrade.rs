@@ -0,0 +1,631 @@
+//! Rewriting index files of older format versions in the current one.
+//!
+//! Readers only accept files of the current version (see `header`), so an
+//! index built before an upgrade of the crate would have to be built again.
+//! `upgrade` reads a file of any version since 1, the first with a header,
+//! and writes it again in the current format, without the original
+//! documents.
+//!
+//! The hits of terms have been encoded the same way since version 1, so
+//! they are only decompressed, checked and compressed again; the writer
+//! works out the skip points, block maxima and numbers of occurrences of
+//! terms that older versions lack.
+//! What older versions didn't record is made up: the length of each field
+//! (before version 5) is counted from the hits of the field's terms, the
+//! size of each file (before version 7) is left at 0, documents have no
+//! duplicates (before version 9) and none was truncated (before version
+//! 12). Documents deleted through the tombstones
+//! of the old file are left out, like `compact` does.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::analyzer;
+use crate::bloom::{footer_start, footer_start_before};
+use crate::crypt::IndexFile;
+use crate::error::{Error, Result};
+use crate::field::Field;
+use crate::header::{read_any_header, IndexSettings};
+use crate::index::{Doc, DocInfo, DocTable};
+use crate::read::{
+    decode_doc_table, hits_limit, read_bytes, skip_offsets, HitRemapper,
+};
+use crate::tmp::publish;
+use crate::tombstone::Tombstones;
+use crate::varint;
+use crate::write::IndexFileWriter;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Number of entries in a block of the front-coded term dictionary, which
+/// hasn't changed since version 4.
+const DICTIONARY_BLOCK_LEN: u64 = 16;
+
+/// An entry of the table of contents of a file of any version.
+#[derive(Debug)]
+struct Term {
+    term: String,
+    doc_count: u32,
+    offset: u64,
+    nbytes: u64,
+}
+
+/// Rewrite `old_file`, an index file of any format version, as `new_file`
+/// in the current one, replacing `new_file` atomically if it exists. They
+/// may be the same file. Returns the version of `old_file`.
+pub fn upgrade<P: AsRef<Path>, Q: AsRef<Path>>(
+    old_file: P,
+    new_file: Q,
+) -> Result<u32> {
+    let (old_file, new_file) = (old_file.as_ref(), new_file.as_ref());
+    let mut f = BufReader::new(IndexFile::open(old_file)?);
+    let (version, header) = read_any_header(&mut f)?;
+    let settings = header.settings;
+    settings.codec.check_available()?;
+
+    let contents_end = if version >= 8 {
+        footer_start(&mut f, &settings)?
+    } else {
+        // Files didn't end with a description yet.
+        let end = f.seek(SeekFrom::End(0))?;
+        footer_start_before(&mut f, &settings, end)?
+    };
+    let data_end = header.doc_table_offset;
+    let doc_table =
+        read_section(&mut f, data_end, header.table_contents_offset)?;
+    let mut docs = decode_docs(&doc_table, version, &settings)?;
+    let contents =
+        read_section(&mut f, header.table_contents_offset, contents_end)?;
+    let terms = decode_terms(&contents, version)?;
+    let out_of_bounds = |t: &&Term| {
+        t.offset
+            .checked_add(t.nbytes)
+            .is_none_or(|end| end > data_end)
+    };
+    if let Some(term) = terms.iter().find(out_of_bounds) {
+        return Err(Error::format(format!(
+            "data for term {:?} is out of bounds",
+            term.term
+        )));
+    }
+    if version < 5 {
+        count_field_lens(&mut f, &settings, &terms, &mut docs)?;
+    }
+    let limit = hits_limit(&docs, !settings.omit_positions);
+    let tombstones = Tombstones::load(old_file)?;
+
+    let mut upgraded = new_file.as_os_str().to_owned();
+    upgraded.push(".upgrade");
+    let upgraded = PathBuf::from(upgraded);
+    {
+        let out = BufWriter::new(File::create(&upgraded)?);
+        let mut writer = IndexFileWriter::new(out, &settings)?;
+        let mut new_ids = Vec::with_capacity(docs.len());
+        let mut next_id = 0;
+        for (doc, info) in &docs {
+            if tombstones.contains(doc) {
+                new_ids.push(None);
+            } else {
+                writer.write_doc_entry(doc, info)?;
+                new_ids.push(Some(next_id));
+                next_id += 1;
+            }
+        }
+        for term in terms {
+            let hits = read_hits(&mut f, &settings, &term, limit)?;
+            let (hits, doc_count) = HitRemapper::default().remap(
+                &hits,
+                term.doc_count,
+                !settings.omit_positions,
+                |id| new_ids.get(id as usize).copied().flatten(),
+            )?;
+            if doc_count > 0 {
+                writer.write_term(term.term, doc_count, &hits)?;
+            }
+        }
+        writer.finish()?;
+    }
+
+    let in_place = same_file(old_file, new_file);
+    publish(&upgraded, new_file)?;
+    if in_place && !tombstones.is_empty() {
+        fs::remove_file(Tombstones::sidecar_path(old_file))?;
+    }
+    Ok(version)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Read the bytes of `f` from `start` to `end`.
+fn read_section<R: Read + Seek>(
+    f: &mut R,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>> {
+    let len = end
+        .checked_sub(start)
+        .ok_or_else(|| Error::format("header offsets are out of range"))?;
+    f.seek(SeekFrom::Start(start))?;
+    read_bytes(f, len, "index file")
+}
+
+/// The decompressed hits of `term`, which can't take more than `limit`
+/// bytes.
+fn read_hits<R: Read + Seek>(
+    f: &mut R,
+    settings: &IndexSettings,
+    term: &Term,
+    limit: usize,
+) -> Result<Vec<u8>> {
+    let hits = read_section(f, term.offset, term.offset + term.nbytes)?;
+    Ok(settings.codec.decompress(&hits, limit)?.into_owned())
+}
+
+/// Decode the document table of a file of format `version`.
+///
+/// Entries have always started with the document hash and its number of
+/// words (u32). Version 5 added the number of words of each of the
+/// `Field::NAMED` (u32 each), then comes the modification time (u64), since
+/// version 7 the size of the file (u64), the path (u32 length, then UTF-8
+/// bytes), since version 2 the language (u8 length, then UTF-8 bytes),
+/// since version 9 the paths of the document's duplicates (u32 count, then
+/// each path as above) and since version 12 the document's flags. Hashes
+/// were SHA-256 hashes before version 10, see `header`.
+fn decode_docs(
+    mut raw: &[u8],
+    version: u32,
+    settings: &IndexSettings,
+) -> Result<DocTable> {
+    if version >= 12 {
+        return decode_doc_table(raw, settings.identity);
+    }
+    let truncated = Error::truncated("document table");
+    let mut docs = DocTable::new();
+    while !raw.is_empty() {
+        let mut hash = vec![0; settings.identity.hash_len()];
+        raw.read_exact(&mut hash).map_err(&truncated)?;
+        let token_count = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+        let mut field_lens = [0; Field::NAMED.len()];
+        if version >= 5 {
+            for len in &mut field_lens {
+                *len = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+            }
+        }
+        let mtime = raw.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let size = if version >= 7 {
+            raw.read_u64::<LittleEndian>().map_err(&truncated)?
+        } else {
+            0
+        };
+        let path_len = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+        let path = read_string(&mut raw, path_len as usize)?;
+        let language = if version >= 2 {
+            let len = raw.read_u8().map_err(&truncated)?;
+            read_string(&mut raw, len as usize)?
+        } else {
+            String::new()
+        };
+        let mut duplicates = vec![];
+        if version >= 9 {
+            let count = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+            for _ in 0..count {
+                let len = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+                duplicates.push(read_string(&mut raw, len as usize)?);
+            }
+        }
+        let info = DocInfo {
+            path,
+            token_count,
+            field_lens,
+            mtime,
+            size,
+            language,
+            duplicates,
+            truncated: false,
+        };
+        docs.push((Doc::new(&hash), info));
+    }
+    Ok(docs)
+}
+
+fn read_string(raw: &mut &[u8], len: usize) -> Result<String> {
+    if len > raw.len() {
+        return Err(Error::format("document table is truncated"));
+    }
+    let (bytes, rest) = raw.split_at(len);
+    *raw = rest;
+    Ok(String::from_utf8(bytes.to_vec())?)
+}
+
+/// Decode the table of contents of a file of format `version`.
+fn decode_terms(raw: &[u8], version: u32) -> Result<Vec<Term>> {
+    match version {
+        1..=3 => decode_fixed_terms(raw, version >= 3),
+        _ => decode_front_coded_terms(raw, version >= 6, version >= 11),
+    }
+}
+
+/// Decode a table of contents of fixed-width entries, before version 4:
+/// the offset and the length of the term's data (u64 each), its number of
+/// documents (u32), the term (u32 length, then UTF-8 bytes) and, since
+/// version 3, its skip points (u32 count, then two u32 each).
+fn decode_fixed_terms(mut raw: &[u8], skips: bool) -> Result<Vec<Term>> {
+    let truncated = Error::truncated("table of contents");
+    let mut terms = vec![];
+    while !raw.is_empty() {
+        let offset = raw.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let nbytes = raw.read_u64::<LittleEndian>().map_err(&truncated)?;
+        let doc_count = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+        let len = raw.read_u32::<LittleEndian>().map_err(&truncated)? as u64;
+        let mut term = vec![];
+        (&mut raw).take(len).read_to_end(&mut term)?;
+        if term.len() as u64 != len {
+            return Err(Error::format("table of contents is truncated"));
+        }
+        if skips {
+            let count = raw.read_u32::<LittleEndian>().map_err(&truncated)?;
+            let len = count as usize * 8;
+            raw = raw.get(len..).ok_or_else(|| {
+                Error::format("table of contents is truncated")
+            })?;
+        }
+        terms.push(Term {
+            term: String::from_utf8(term)?,
+            doc_count,
+            offset,
+            nbytes,
+        });
+    }
+    Ok(terms)
+}
+
+/// Decode a front-coded table of contents, since version 4, whose entries
+/// have had block maxima since version 6 and the number of occurrences of
+/// their term since version 11. See `dictionary` for the layout.
+fn decode_front_coded_terms(
+    raw: &[u8],
+    maxima: bool,
+    occurrences: bool,
+) -> Result<Vec<Term>> {
+    let truncated = || Error::format("table of contents is truncated");
+    let mut trailer = raw.get(raw.len().saturating_sub(16)..).unwrap_or(&[]);
+    let count = trailer
+        .read_u64::<LittleEndian>()
+        .map_err(|_| truncated())?;
+    let blocks = trailer
+        .read_u64::<LittleEndian>()
+        .map_err(|_| truncated())?;
+    let entries_len = blocks
+        .checked_mul(8)
+        .and_then(|n| (raw.len() as u64).checked_sub(16 + n))
+        .ok_or_else(truncated)?;
+    let mut rest = &raw[..entries_len as usize];
+
+    let mut terms = vec![];
+    let mut previous: Vec<u8> = vec![];
+    let mut previous_end = 0u64;
+    for i in 0..count {
+        if i % DICTIONARY_BLOCK_LEN == 0 {
+            previous.clear();
+            previous_end = 0;
+        }
+        let shared = number(&mut rest)? as usize;
+        if shared > previous.len() {
+            return Err(Error::format(
+                "term shares more than the previous term",
+            ));
+        }
+        previous.truncate(shared);
+        let suffix_len = number(&mut rest)? as usize;
+        let suffix = rest.get(..suffix_len).ok_or_else(truncated)?;
+        previous.extend_from_slice(suffix);
+        rest = &rest[suffix_len..];
+        let offset_gap = number(&mut rest)?;
+        let offset =
+            previous_end.checked_add(offset_gap).ok_or_else(truncated)?;
+        let nbytes = number(&mut rest)?;
+        let doc_count =
+            u32::try_from(number(&mut rest)?).map_err(|_| truncated())?;
+        // Occurrences, skip points and block maxima are worked out again
+        // when writing.
+        if occurrences {
+            number(&mut rest)?;
+        }
+        let skips = number(&mut rest)?;
+        let numbers = if maxima {
+            skips * 2 + (skips + 1) * 2
+        } else {
+            skips * 2
+        };
+        for _ in 0..numbers {
+            number(&mut rest)?;
+        }
+        previous_end = offset.checked_add(nbytes).ok_or_else(truncated)?;
+        terms.push(Term {
+            term: String::from_utf8(previous.clone())?,
+            doc_count,
+            offset,
+            nbytes,
+        });
+    }
+    Ok(terms)
+}
+
+/// Read a number of an entry of a front-coded table of contents.
+fn number(rest: &mut &[u8]) -> Result<u64> {
+    varint::read_u64_from(rest)
+        .map_err(|_| Error::format("table of contents is truncated"))
+}
+
+/// Count the words of each field of `docs` from the hits of the field's
+/// terms, for files from before version 5, which didn't record them.
+fn count_field_lens<R: Read + Seek>(
+    f: &mut R,
+    settings: &IndexSettings,
+    terms: &[Term],
+    docs: &mut DocTable,
+) -> Result<()> {
+    for term in terms {
+        let (field, word) = Field::split(&term.term);
+        let Some(i) = field.named_index() else {
+            continue;
+        };
+        if analyzer::is_exact(word) {
+            continue;
+        }
+        // The lengths of fields, which bound their hits, are still unknown.
+        let hits = read_hits(f, settings, term, usize::MAX)?;
+        let mut reader = &hits[..];
+        let mut id = 0u32;
+        for _ in 0..term.doc_count {
+            id = id.wrapping_add(varint::read_u32(&mut reader)?);
+            let count = skip_offsets(&mut reader, !settings.omit_positions)?;
+            let (_, info) = docs.get_mut(id as usize).ok_or_else(|| {
+                Error::format(format!(
+                    "hit refers to unknown document id {}",
+                    id
+                ))
+            })?;
+            info.field_lens[i] += count;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::io::{self, Write};
+
+    use crate::fixtures::{write_index, write_index_with};
+    use crate::header::{OFFSETS_POSITION, VERSION};
+    use crate::read::{IndexFileReader, IndexFileSearcher};
+    use crate::HASH_LENGTH;
+
+    const TEXTS: [&str; 3] = ["the cat sat", "the dog and the cat", "a bird"];
+
+    /// `file` rewritten in format version 1: the same header and hits,
+    /// with the document table and table of contents of the time.
+    fn version_1_of(file: &Path) -> Vec<u8> {
+        let searcher = IndexFileSearcher::open(file).unwrap();
+        let index = IndexFileReader::get_index_from_file(file).unwrap();
+        let mut bytes = fs::read(file).unwrap();
+        let mut f = &bytes[OFFSETS_POSITION as usize..];
+        f.read_u64::<LittleEndian>().unwrap();
+        let doc_table_offset = f.read_u64::<LittleEndian>().unwrap();
+        bytes.truncate(doc_table_offset as usize);
+        bytes[8..12].copy_from_slice(&1u32.to_le_bytes());
+
+        for (doc, info) in searcher.docs() {
+            bytes.extend_from_slice(&doc.hash);
+            bytes.write_u32::<LittleEndian>(info.token_count).unwrap();
+            bytes.write_u64::<LittleEndian>(info.mtime).unwrap();
+            bytes
+                .write_u32::<LittleEndian>(info.path.len() as u32)
+                .unwrap();
+            bytes.write_all(info.path.as_bytes()).unwrap();
+        }
+        let contents_offset = bytes.len() as u64;
+        let mut terms: Vec<_> = index.map.keys().collect();
+        terms.sort();
+        for term in terms {
+            let entry = searcher.entry(term).unwrap();
+            bytes.write_u64::<LittleEndian>(entry.offset).unwrap();
+            bytes.write_u64::<LittleEndian>(entry.nbytes).unwrap();
+            bytes.write_u32::<LittleEndian>(entry.doc_count).unwrap();
+            bytes.write_u32::<LittleEndian>(term.len() as u32).unwrap();
+            bytes.write_all(term.as_bytes()).unwrap();
+        }
+        let at = OFFSETS_POSITION as usize;
+        bytes[at..at + 8].copy_from_slice(&contents_offset.to_le_bytes());
+        bytes
+    }
+
+    /// The table of contents of `file` as it was written before version
+    /// 11, without the number of occurrences of each term. Terms are
+    /// written whole, sharing no prefix with the previous one.
+    fn contents_before_11(file: &Path) -> Vec<u8> {
+        let searcher = IndexFileSearcher::open(file).unwrap();
+        let index = IndexFileReader::get_index_from_file(file).unwrap();
+        let mut terms: Vec<_> = index.map.keys().collect();
+        terms.sort();
+        let mut contents = vec![];
+        let mut blocks = vec![];
+        let mut previous_end = 0;
+        for (i, term) in terms.iter().enumerate() {
+            if (i as u64).is_multiple_of(DICTIONARY_BLOCK_LEN) {
+                blocks.push(contents.len() as u64);
+                previous_end = 0;
+            }
+            let e = searcher.entry(term).unwrap();
+            assert!(e.skips.is_empty());
+            for n in [0, term.len() as u64] {
+                varint::write_u64(&mut contents, n);
+            }
+            contents.extend_from_slice(term.as_bytes());
+            let (max_tf, min_len) = (e.blocks[0].max_tf, e.blocks[0].min_len);
+            for n in [
+                e.offset - previous_end,
+                e.nbytes,
+                e.doc_count as u64,
+                0,
+                max_tf as u64,
+                min_len as u64,
+            ] {
+                varint::write_u64(&mut contents, n);
+            }
+            previous_end = e.offset + e.nbytes;
+        }
+        for n in blocks
+            .iter()
+            .chain([&(terms.len() as u64), &(blocks.len() as u64)])
+        {
+            contents.write_u64::<LittleEndian>(*n).unwrap();
+        }
+        contents
+    }
+
+    /// `file` rewritten in format version 8, whose document table had no
+    /// duplicates yet.
+    fn version_8_of(file: &Path) -> Vec<u8> {
+        let searcher = IndexFileSearcher::open(file).unwrap();
+        let bytes = fs::read(file).unwrap();
+        let mut f = &bytes[OFFSETS_POSITION as usize..];
+        f.read_u64::<LittleEndian>().unwrap();
+        let doc_table_offset = f.read_u64::<LittleEndian>().unwrap();
+        let settings = searcher.settings();
+        let contents_end =
+            footer_start(&mut io::Cursor::new(&bytes), settings).unwrap();
+        let mut old = bytes[..doc_table_offset as usize].to_vec();
+        old[8..12].copy_from_slice(&8u32.to_le_bytes());
+
+        for (doc, info) in searcher.docs() {
+            old.extend_from_slice(&doc.hash);
+            old.write_u32::<LittleEndian>(info.token_count).unwrap();
+            for len in info.field_lens {
+                old.write_u32::<LittleEndian>(len).unwrap();
+            }
+            old.write_u64::<LittleEndian>(info.mtime).unwrap();
+            old.write_u64::<LittleEndian>(info.size).unwrap();
+            old.write_u32::<LittleEndian>(info.path.len() as u32)
+                .unwrap();
+            old.write_all(info.path.as_bytes()).unwrap();
+            old.write_u8(info.language.len() as u8).unwrap();
+            old.write_all(info.language.as_bytes()).unwrap();
+        }
+        let at = OFFSETS_POSITION as usize;
+        let new_contents_offset = old.len() as u64;
+        old[at..at + 8].copy_from_slice(&new_contents_offset.to_le_bytes());
+        old.extend(contents_before_11(file));
+        old.extend_from_slice(&bytes[contents_end as usize..]);
+        old
+    }
+
+    #[test]
+    fn current_files_upgrade_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        for settings in [
+            IndexSettings::default(),
+            IndexSettings {
+                omit_positions: true,
+                bloom_filter: true,
+                ..IndexSettings::default()
+            },
+        ] {
+            let file = write_index_with(dir.path(), &TEXTS, &settings);
+            let expected = IndexFileReader::get_index_from_file(&file).unwrap();
+            let upgraded = dir.path().join("upgraded.dat");
+            assert_eq!(upgrade(&file, &upgraded).unwrap(), VERSION);
+            assert_eq!(IndexSettings::load(&upgraded).unwrap(), settings);
+            let index =
+                IndexFileReader::get_index_from_file(&upgraded).unwrap();
+            assert_eq!(index.map, expected.map);
+            assert_eq!(index.docs, expected.docs);
+
+            // In place, too.
+            upgrade(&upgraded, &upgraded).unwrap();
+            let index =
+                IndexFileReader::get_index_from_file(&upgraded).unwrap();
+            assert_eq!(index.map, expected.map);
+        }
+    }
+
+    #[test]
+    fn version_1_files_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &TEXTS);
+        let expected = IndexFileReader::get_index_from_file(&file).unwrap();
+        let old = dir.path().join("old.dat");
+        fs::write(&old, version_1_of(&file)).unwrap();
+        assert!(IndexFileSearcher::open(&old).is_err());
+
+        assert_eq!(upgrade(&old, &old).unwrap(), 1);
+        let index = IndexFileReader::get_index_from_file(&old).unwrap();
+        assert_eq!(index.map, expected.map);
+        assert_eq!(index.docs, expected.docs);
+    }
+
+    #[test]
+    fn version_8_files_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = IndexSettings {
+            bloom_filter: true,
+            ..IndexSettings::default()
+        };
+        let file = write_index_with(dir.path(), &TEXTS, &settings);
+        let expected = IndexFileReader::get_index_from_file(&file).unwrap();
+        let old = dir.path().join("old.dat");
+        fs::write(&old, version_8_of(&file)).unwrap();
+        assert!(IndexFileSearcher::open(&old).is_err());
+
+        assert_eq!(upgrade(&old, &old).unwrap(), 8);
+        let index = IndexFileReader::get_index_from_file(&old).unwrap();
+        assert_eq!(index.map, expected.map);
+        assert_eq!(index.docs, expected.docs);
+        // Occurrences are counted again.
+        let searcher = IndexFileSearcher::open(&old).unwrap();
+        assert_eq!(searcher.entry("the").unwrap().occurrences, 3);
+    }
+
+    #[test]
+    fn deleted_documents_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &TEXTS);
+        let deleted = Doc::new(&[2; HASH_LENGTH]);
+        let mut tombstones = Tombstones::default();
+        tombstones.add(deleted.clone());
+        tombstones.save(&file).unwrap();
+
+        upgrade(&file, &file).unwrap();
+        assert!(!Tombstones::sidecar_path(&file).exists());
+        let index = IndexFileReader::get_index_from_file(&file).unwrap();
+        assert_eq!(index.docs.len(), 2);
+        assert!(!index.docs.contains_key(&deleted));
+        assert!(!index.map.contains_key("bird"));
+        assert_eq!(index.map["cat"].len(), 2);
+    }
+
+    #[test]
+    fn damaged_files_are_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_index(dir.path(), &TEXTS);
+        let old = version_1_of(&file);
+        let damaged = dir.path().join("damaged.dat");
+        let upgraded = dir.path().join("upgraded.dat");
+        for len in [0, 10, 40, old.len() - 1] {
+            fs::write(&damaged, &old[..len]).unwrap();
+            assert!(upgrade(&damaged, &upgraded).is_err(), "{} bytes", len);
+        }
+        assert!(!upgraded.exists());
+    }
+}
@@ -0,0 +1,134 @@
+//! Variable-length integers (unsigned LEB128).
+//!
+//! Postings are mostly small numbers: document ids, offset counts and word
+//! offsets. LEB128 stores 7 bits per byte, using the high bit to mark that
+//! more bytes follow, so values below 128 take a single byte instead of
+//! four.
+
+use std::io::{self, Read};
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::error::{Error, Result};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Append `value` to `out`.
+pub(crate) fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read a value from the front of `input`, advancing past it.
+pub(crate) fn read_u32(input: &mut &[u8]) -> Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = input
+            .split_first()
+            .ok_or_else(|| Error::format("varint is truncated"))?;
+        *input = rest;
+        let bits = (byte & 0x7f) as u32;
+        if shift == 28 && bits > 0x0f {
+            break;
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::format("varint does not fit in 32 bits"))
+}
+
+/// Append `value` to `out`.
+pub(crate) fn write_u64(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read a value from `input`. Fails with `UnexpectedEof` if `input` ends
+/// first.
+pub(crate) fn read_u64_from<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..70).step_by(7) {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        let bits = (byte[0] & 0x7f) as u64;
+        if shift == 63 && bits > 1 {
+            break;
+        }
+        value |= bits << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint does not fit in 64 bits",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_read_back() {
+        let values =
+            [0, 1, 127, 128, 300, 16_383, 16_384, u32::MAX - 1, u32::MAX];
+        let mut out = vec![];
+        for &value in &values {
+            write_u32(&mut out, value);
+        }
+        // One byte for each 7 bits.
+        assert_eq!(out.len(), 1 + 1 + 1 + 2 + 2 + 2 + 3 + 5 + 5);
+        let mut input = &out[..];
+        for &value in &values {
+            assert_eq!(read_u32(&mut input).unwrap(), value);
+        }
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn truncated_values_are_an_error() {
+        let mut out = vec![];
+        write_u32(&mut out, 300);
+        let e = read_u32(&mut &out[..1]).unwrap_err();
+        assert!(e.to_string().contains("truncated"), "{e}");
+        assert!(read_u32(&mut &[][..]).is_err());
+    }
+
+    #[test]
+    fn wide_values_read_back() {
+        let values = [0, 127, 128, u32::MAX as u64 + 1, u64::MAX];
+        let mut out = vec![];
+        for &value in &values {
+            write_u64(&mut out, value);
+        }
+        assert_eq!(out.len(), 1 + 1 + 2 + 5 + 10);
+        let mut input = &out[..];
+        for &value in &values {
+            assert_eq!(read_u64_from(&mut input).unwrap(), value);
+        }
+        let e = read_u64_from(&mut input).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let mut too_wide = vec![0xff; 9];
+        too_wide.push(0x02);
+        let e = read_u64_from(&mut &too_wide[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn values_over_32_bits_are_an_error() {
+        let e = read_u32(&mut &[0xff, 0xff, 0xff, 0xff, 0x1f][..]).unwrap_err();
+        assert!(matches!(e, Error::Format(_)));
+        let e = read_u32(&mut &[0x80, 0x80, 0x80, 0x80, 0x80, 0x01][..])
+            .unwrap_err();
+        assert!(matches!(e, Error::Format(_)));
+    }
+}
@@ -0,0 +1,202 @@
+//! Checking an index file for corruption.
+//!
+//! Opening an index only reads its header, table of contents and document
+//! table; a damaged posting list goes unnoticed until a search reads it.
+//! `verify` reads the whole file and checks every invariant the writer
+//! guarantees, like the hits of every term being sorted by document, so a
+//! bad file can be found (and rebuilt) before it is used.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::bloom::BloomFilter;
+use crate::description::{Counts, FileDescription};
+use crate::error::Result;
+use crate::index::DocEntry;
+use crate::read::{check_doc_order, decode_hits, Entry, IndexFileReader};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// What `verify` found.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Number of terms read.
+    pub terms: usize,
+    /// Number of (term, document) pairs read.
+    pub postings: u64,
+    /// Number of entries in the document table.
+    pub documents: usize,
+    /// Everything that is wrong with the file, in the order it was found.
+    pub problems: Vec<String>,
+}
+
+impl Report {
+    /// True if no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Read all of `index_file` and check it. Fails only if the file can't be
+/// opened at all: problems found past the header are listed in the report.
+/// Reading stops at the first posting list that can't be decoded.
+pub fn verify<P: AsRef<Path>>(index_file: P) -> Result<Report> {
+    let index_file = index_file.as_ref();
+    let mut reader = IndexFileReader::open(index_file)?;
+    let mut report = Report {
+        documents: reader.docs().len(),
+        ..Report::default()
+    };
+    let bloom = BloomFilter::load(index_file).unwrap_or_else(|e| {
+        report.problems.push(e.to_string());
+        None
+    });
+    let description = FileDescription::load(index_file)
+        .inspect_err(|e| report.problems.push(e.to_string()))
+        .ok();
+
+    let mut seen = HashSet::new();
+    for (doc, info) in reader.docs() {
+        if !seen.insert(doc.clone()) {
+            report.problems.push(format!(
+                "document {} ({}) is in the document table twice",
+                doc.to_hex(),
+                info.path
+            ));
+        }
+    }
+
+    let positions = !reader.settings().omit_positions;
+    let mut previous: Option<String> = None;
+    let mut complete = true;
+    loop {
+        let (entry, hits, postings) = match next_entry(&mut reader, positions) {
+            Ok(Some(next)) => next,
+            Ok(None) => break,
+            Err(e) => {
+                report.problems.push(e.to_string());
+                complete = false;
+                break;
+            }
+        };
+        let term = entry.term;
+        if previous.as_ref().is_some_and(|p| *p >= term) {
+            report
+                .problems
+                .push(format!("term {:?} is out of order", term));
+        }
+        if bloom.as_ref().is_some_and(|b| !b.might_contain(&term)) {
+            report.problems.push(format!(
+                "term {:?} is missing from the bloom filter",
+                term
+            ));
+        }
+        if let Err(e) = check_doc_order(&hits, entry.doc_count, positions) {
+            report.problems.push(format!("term {:?}: {}", term, e));
+        }
+        report.terms += 1;
+        for (doc, offsets) in &postings {
+            report.postings += 1;
+            if offsets.is_empty() {
+                report.problems.push(format!(
+                    "term {:?} has no offsets in document {}",
+                    term,
+                    doc.to_hex()
+                ));
+            }
+            if offsets.windows(2).any(|w| w[0] >= w[1]) {
+                report.problems.push(format!(
+                    "offsets of term {:?} in document {} are out of order",
+                    term,
+                    doc.to_hex()
+                ));
+            }
+        }
+        let occurrences: u64 = postings.values().map(|o| o.len() as u64).sum();
+        if occurrences != entry.occurrences {
+            report.problems.push(format!(
+                "the table of contents counts {} occurrences of term {:?}, \
+                 but its hits hold {}",
+                entry.occurrences, term, occurrences
+            ));
+        }
+        previous = Some(term);
+    }
+
+    // Counts can only be compared once everything has been read.
+    if let Some(description) = description.filter(|_| complete) {
+        let counts = Counts {
+            documents: report.documents as u64,
+            terms: report.terms as u64,
+            postings: report.postings,
+            words: reader
+                .docs()
+                .iter()
+                .map(|(_, d)| d.token_count as u64)
+                .sum(),
+        };
+        if description.counts != counts {
+            report.problems.push(format!(
+                "the description counts {:?}, but the file holds {:?}",
+                description.counts, counts
+            ));
+        }
+    }
+    Ok(report)
+}
+
+/// The next entry of `reader`, with its decompressed hits, and the hits
+/// decoded.
+fn next_entry(
+    reader: &mut IndexFileReader,
+    positions: bool,
+) -> Result<Option<(Entry, Vec<u8>, DocEntry)>> {
+    let Some((entry, hits)) = reader.take_entry()? else {
+        return Ok(None);
+    };
+    let postings =
+        decode_hits(&hits, entry.doc_count, reader.docs(), positions)?;
+    Ok(Some((entry, hits, postings)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_index;
+    use std::fs;
+
+    #[test]
+    fn damaged_hits_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let file =
+            write_index(dir.path(), &["the cat sat", "the dog", "a cat"]);
+        let report = verify(&file).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(
+            (report.terms, report.postings, report.documents),
+            (5, 7, 3)
+        );
+
+        // The hits of the last term, overwritten with bytes that can't be
+        // decoded.
+        let mut reader = IndexFileReader::open(&file).unwrap();
+        let mut last = None;
+        while let Some((entry, _)) = reader.take_entry().unwrap() {
+            last = Some(entry);
+        }
+        let last = last.unwrap();
+        let mut bytes = fs::read(&file).unwrap();
+        let start = last.offset as usize;
+        bytes[start..start + last.nbytes as usize].fill(0xff);
+        fs::write(&file, &bytes).unwrap();
+        let report = verify(&file).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.terms, 4);
+
+        // Files that can't be opened at all are an error.
+        fs::write(&file, &bytes[..20]).unwrap();
+        assert!(verify(&file).is_err());
+    }
+}
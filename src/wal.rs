@@ -0,0 +1,460 @@
+//! The write-ahead log of an index directory.
+//!
+//! Documents added with `IndexDir::add` are only buffered in memory until
+//! the buffer becomes a segment, and so are the deletions of buffered
+//! documents. So that a crash in between doesn't lose them, each such update
+//! is first appended to the log, and synced to disk, before `add` or
+//! `delete` returns. The log is named in `segments.json`; writing a segment
+//! of the buffer starts a new, empty one, named in the same update of the
+//! list that adds the segment, so every update is either in a listed segment
+//! or in the log, never both. An addition also names the documents of
+//! segments that it replaces, which replaying it deletes again, in case a
+//! crash came before they were.
+//!
+//! The log is in JSON Lines, one update per line. Opening the directory for
+//! writing replays it, before any search, into a new log. A last line cut
+//! short by a crash was never acknowledged, and is left out; one cut short by
+//! a failed write is cut off again before the next, so that no torn line is
+//! ever followed by another. In encrypted directories, each line is instead
+//! the hex of its update, encrypted (see `Key::seal`).
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// ───── Current Crate Imports ────────────────────────────────────────────── //
+
+use crate::crypt::Key;
+use crate::error::{Error, Result};
+use crate::field::Field;
+use crate::index::{Doc, DocInfo, Hit, InMemoryIndex};
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Extension of the names of logs in an index directory.
+pub(crate) const WAL_EXTENSION: &str = "wal";
+
+/// An update of the buffer of an index directory.
+pub(crate) enum Update {
+    /// Documents added with `IndexDir::add`, and the documents of segments
+    /// they replace, by segment name.
+    Add {
+        index: InMemoryIndex,
+        replaced: Vec<(String, Vec<Doc>)>,
+    },
+    /// Buffered documents deleted with `IndexDir::delete`.
+    Delete(Vec<Doc>),
+}
+
+/// A line of a log.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Add {
+        word_count: usize,
+        docs: Vec<Document>,
+        /// The hits of each term, each as the u32s it is made of: the
+        /// document id, the number of offsets, then the offsets.
+        terms: HashMap<String, Vec<Vec<u32>>>,
+        /// The documents replaced, by segment name.
+        #[serde(default)]
+        replaced: Vec<(String, Vec<String>)>,
+    },
+    Delete {
+        docs: Vec<String>,
+    },
+}
+
+/// A document of the document table of an `Add` record.
+#[derive(Serialize, Deserialize)]
+struct Document {
+    doc: String,
+    path: String,
+    token_count: u32,
+    field_lens: [u32; Field::NAMED.len()],
+    mtime: u64,
+    size: u64,
+    language: String,
+    duplicates: Vec<String>,
+    truncated: bool,
+}
+
+/// A log, open for appending.
+pub(crate) struct Wal {
+    name: String,
+    file: File,
+    /// The length of the log up to its last whole update.
+    len: u64,
+    /// The key updates are encrypted with, if any.
+    key: Option<Key>,
+}
+
+impl Wal {
+    /// Start an empty log named `name` in `dir`, encrypted with `key` if
+    /// any.
+    pub fn create(
+        dir: &Path,
+        name: String,
+        key: Option<Key>,
+    ) -> io::Result<Wal> {
+        let file = File::create(dir.join(&name))?;
+        file.sync_all()?;
+        Ok(Wal {
+            name,
+            file,
+            len: 0,
+            key,
+        })
+    }
+
+    /// The name of the log in its directory.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Append `update`, and return once it is on disk.
+    pub fn append(&mut self, update: &Update) -> io::Result<()> {
+        let record = match update {
+            Update::Add { index, replaced } => Record::Add {
+                word_count: index.word_count,
+                docs: index
+                    .docs
+                    .iter()
+                    .map(|(doc, info)| Document {
+                        doc: doc.to_hex(),
+                        path: info.path.clone(),
+                        token_count: info.token_count,
+                        field_lens: info.field_lens,
+                        mtime: info.mtime,
+                        size: info.size,
+                        language: info.language.clone(),
+                        duplicates: info.duplicates.clone(),
+                        truncated: info.truncated,
+                    })
+                    .collect(),
+                terms: index
+                    .map
+                    .iter()
+                    .map(|(term, hits)| {
+                        (term.clone(), hits.iter().map(to_u32s).collect())
+                    })
+                    .collect(),
+                replaced: replaced
+                    .iter()
+                    .map(|(segment, docs)| {
+                        (
+                            segment.clone(),
+                            docs.iter().map(Doc::to_hex).collect(),
+                        )
+                    })
+                    .collect(),
+            },
+            Update::Delete(docs) => Record::Delete {
+                docs: docs.iter().map(Doc::to_hex).collect(),
+            },
+        };
+        let mut line = serde_json::to_vec(&record).map_err(io::Error::other)?;
+        if let Some(key) = &self.key {
+            line = to_hex(&key.seal(&line)?).into_bytes();
+        }
+        line.push(b'\n');
+        // One write, so that a crash cuts the last line short at worst.
+        let written = self
+            .file
+            .write_all(&line)
+            .and_then(|()| self.file.sync_data());
+        match written {
+            Ok(()) => {
+                self.len += line.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                self.rewind()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Cut off whatever a failed append left after the last whole update.
+    fn rewind(&mut self) -> io::Result<()> {
+        self.file.set_len(self.len)?;
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.sync_data()
+    }
+
+    /// The updates of the log at `path`, in order, decrypted with `key` if
+    /// any.
+    pub fn read(path: &Path, key: Option<&Key>) -> Result<Vec<Update>> {
+        let text = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let lines: Vec<&[u8]> = text.split(|&b| b == b'\n').collect();
+        let mut updates = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let line = match key {
+                Some(key) => from_hex(line)
+                    .and_then(|sealed| key.open(&sealed))
+                    .map(Cow::Owned)
+                    .ok_or_else(|| "can't decrypt it".to_string()),
+                None => Ok(Cow::Borrowed(*line)),
+            };
+            let update = line
+                .and_then(|line| {
+                    serde_json::from_slice::<Record>(&line)
+                        .map_err(|e| e.to_string())
+                })
+                .and_then(to_update);
+            match update {
+                Ok(update) => updates.push(update),
+                // Cut short as it was written.
+                Err(_) if i + 1 == lines.len() => break,
+                Err(e) => {
+                    return Err(Error::format(format!(
+                        "{}, line {}: {}",
+                        path.display(),
+                        i + 1,
+                        e
+                    )))
+                }
+            }
+        }
+        Ok(updates)
+    }
+}
+
+fn to_update(record: Record) -> std::result::Result<Update, String> {
+    let parse = |hex: &str| {
+        Doc::from_hex(hex).ok_or_else(|| format!("bad document id {}", hex))
+    };
+    match record {
+        Record::Add {
+            word_count,
+            docs,
+            terms,
+            replaced,
+        } => {
+            let docs = docs
+                .into_iter()
+                .map(|d| {
+                    let info = DocInfo {
+                        path: d.path,
+                        token_count: d.token_count,
+                        field_lens: d.field_lens,
+                        mtime: d.mtime,
+                        size: d.size,
+                        language: d.language,
+                        duplicates: d.duplicates,
+                        truncated: d.truncated,
+                    };
+                    Ok((parse(&d.doc)?, info))
+                })
+                .collect::<std::result::Result<Vec<_>, String>>()?;
+            let map = terms
+                .into_iter()
+                .map(|(term, hits)| {
+                    let hits = hits
+                        .iter()
+                        .map(|u32s| to_hit(u32s, docs.len()))
+                        .collect::<std::result::Result<_, _>>()?;
+                    Ok((term, hits))
+                })
+                .collect::<std::result::Result<_, String>>()?;
+            let replaced = replaced
+                .into_iter()
+                .map(|(segment, docs)| {
+                    let docs = docs
+                        .iter()
+                        .map(|hex| parse(hex))
+                        .collect::<std::result::Result<_, _>>()?;
+                    Ok((segment, docs))
+                })
+                .collect::<std::result::Result<_, String>>()?;
+            Ok(Update::Add {
+                index: InMemoryIndex::from_parts(word_count, map, docs),
+                replaced,
+            })
+        }
+        Record::Delete { docs } => Ok(Update::Delete(
+            docs.iter()
+                .map(|hex| parse(hex))
+                .collect::<std::result::Result<_, _>>()?,
+        )),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks_exact(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+fn to_u32s(hit: &Hit) -> Vec<u32> {
+    hit.chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// The hit made of `u32s`, of one of `docs` documents.
+fn to_hit(u32s: &[u32], docs: usize) -> std::result::Result<Hit, String> {
+    match u32s {
+        [id, count, offsets @ ..]
+            if (*id as usize) < docs && *count as usize == offsets.len() =>
+        {
+            Ok(u32s.iter().flat_map(|n| n.to_le_bytes()).collect())
+        }
+        _ => Err(format!("bad hit {:?}", u32s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::TextAnalyzer;
+    use crate::header::IndexSettings;
+    use crate::segments::IndexDir;
+    use std::slice;
+
+    #[test]
+    fn updates_read_back_without_a_torn_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = InMemoryIndex::new();
+        index.add_document_fields(
+            b"a",
+            "the cat sat".to_string(),
+            &[(Field::Title, "Cats".to_string())],
+            &TextAnalyzer::default(),
+        );
+        let (expected, memory) = (index.to_parsed(), index.memory_usage());
+        let mut wal =
+            Wal::create(dir.path(), "seg000001.wal".into(), None).unwrap();
+        let replaced =
+            vec![("seg000002.dat".to_string(), vec![Doc::new(b"b")])];
+        wal.append(&Update::Add { index, replaced }).unwrap();
+        wal.append(&Update::Delete(vec![Doc::new(b"a")])).unwrap();
+        let path = dir.path().join(wal.name());
+        // A crash in the middle of the next append.
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"delete\":{\"docs\":[\"6")
+            .unwrap();
+
+        let updates = Wal::read(&path, None).unwrap();
+        assert_eq!(updates.len(), 2);
+        let Update::Add { index, replaced } = &updates[0] else {
+            panic!("expected an addition");
+        };
+        assert_eq!(replaced[0].0, "seg000002.dat");
+        assert_eq!(replaced[0].1, [Doc::new(b"b")]);
+        let parsed = index.to_parsed();
+        assert_eq!(parsed.map, expected.map);
+        assert_eq!(parsed.docs, expected.docs);
+        assert_eq!(index.memory_usage(), memory);
+        assert!(matches!(&updates[1], Update::Delete(d) if d.len() == 1));
+    }
+
+    #[test]
+    fn failed_appends_leave_no_torn_line_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wal =
+            Wal::create(dir.path(), "seg000001.wal".into(), None).unwrap();
+        wal.append(&Update::Delete(vec![Doc::new(b"a")])).unwrap();
+        // What a write that failed halfway leaves behind.
+        wal.file.write_all(b"{\"delete\":{\"docs\":[\"6").unwrap();
+        let path = dir.path().join(wal.name());
+        let torn = fs::read(&path).unwrap();
+        wal.rewind().unwrap();
+        wal.append(&Update::Delete(vec![Doc::new(b"b")])).unwrap();
+
+        let updates = Wal::read(&path, None).unwrap();
+        let deleted: Vec<_> = updates
+            .iter()
+            .map(|update| match update {
+                Update::Delete(docs) => docs[0].clone(),
+                Update::Add { .. } => panic!("expected a deletion"),
+            })
+            .collect();
+        assert_eq!(deleted, [Doc::new(b"a"), Doc::new(b"b")]);
+        // Left in the middle, the torn line would be taken for damage.
+        let mut torn = torn;
+        torn.extend(b"\n{\"delete\":{\"docs\":[]}}\n");
+        fs::write(&path, torn).unwrap();
+        assert!(Wal::read(&path, None).is_err());
+    }
+
+    #[test]
+    fn encrypted_updates_read_back_with_the_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = Key::new([9; 32]);
+        let name = "seg000001.wal".to_string();
+        let mut wal = Wal::create(dir.path(), name, Some(key.clone())).unwrap();
+        wal.append(&Update::Delete(vec![Doc::new(b"a")])).unwrap();
+        wal.append(&Update::Delete(vec![Doc::new(b"b")])).unwrap();
+        let path = dir.path().join(wal.name());
+        assert!(!fs::read_to_string(&path).unwrap().contains("delete"));
+
+        let updates = Wal::read(&path, Some(&key)).unwrap();
+        assert_eq!(updates.len(), 2);
+        assert!(Wal::read(&path, Some(&Key::new([8; 32]))).is_err());
+    }
+
+    #[test]
+    fn buffered_updates_survive_a_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir =
+            IndexDir::create(dir.path(), IndexSettings::default()).unwrap();
+        let analyzer = index_dir.settings().analyzer.build().unwrap();
+        let (a, b) = (Doc::new(&[1; 32]), Doc::new(&[2; 32]));
+        for (doc, text) in [(&a, "the cat sat"), (&b, "the cat ran")] {
+            let mut index = InMemoryIndex::new();
+            index.add_document_with(&doc.hash, text.to_string(), &analyzer);
+            index_dir.add(index).unwrap();
+        }
+        index_dir.delete(slice::from_ref(&b)).unwrap();
+        // What a crash would leave behind, before anything was flushed.
+        let crashed = tempfile::tempdir().unwrap();
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, crashed.path().join(path.file_name().unwrap()))
+                .unwrap();
+        }
+
+        let recovered = IndexDir::open(crashed.path()).unwrap();
+        assert!(recovered.segments().is_empty());
+        let cat = recovered.lookup("cat").unwrap().unwrap();
+        assert_eq!(cat.keys().collect::<Vec<_>>(), [&a]);
+        let path = recovered.flush().unwrap().unwrap();
+        let searcher = recovered.searcher().unwrap();
+        assert!(searcher.doc_info(&a).is_some());
+        assert!(searcher.doc_info(&b).is_none());
+        // The log was started again with the segment.
+        drop(recovered);
+        let wals: Vec<_> = fs::read_dir(crashed.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "wal"))
+            .collect();
+        assert_eq!(wals.len(), 1);
+        assert_eq!(fs::metadata(&wals[0]).unwrap().len(), 0);
+        assert!(path.is_file());
+    }
+}
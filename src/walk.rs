@@ -0,0 +1,218 @@
+//! Finding the files to index.
+//!
+//! Directories are walked recursively. Which files are picked up can be
+//! narrowed with include and exclude glob patterns, matched against the path
+//! of each file relative to the directory being walked (`*` also matches
+//! `/`, so `*.md` picks Markdown files at any depth). Optionally, the rules
+//! of `.gitignore` files are honored too. Hidden files and directories are
+//! always skipped, unless named explicitly. Directories can't be walked on
+//! wasm32, which has no file system.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+#[cfg(not(target_arch = "wasm32"))]
+use ignore::WalkBuilder;
+use log::trace;
+
+// ───── Body ─────────────────────────────────────────────────────────────── //
+
+/// Which files under the given paths to index.
+#[derive(Debug, Clone)]
+pub struct FileFilter {
+    /// Files must match one of these, if there are any.
+    include: Option<GlobSet>,
+    /// Files and directories matching any of these are skipped.
+    exclude: GlobSet,
+    /// Honor `.gitignore` files.
+    git_ignore: bool,
+}
+
+impl Default for FileFilter {
+    fn default() -> Self {
+        FileFilter {
+            include: None,
+            exclude: GlobSet::empty(),
+            git_ignore: false,
+        }
+    }
+}
+
+impl FileFilter {
+    /// A filter keeping files that match any of `include` (or all files, if
+    /// it is empty) and none of `exclude`.
+    pub fn new<S: AsRef<str>>(
+        include: &[S],
+        exclude: &[S],
+    ) -> io::Result<FileFilter> {
+        let include = match include {
+            [] => None,
+            patterns => Some(glob_set(patterns)?),
+        };
+        Ok(FileFilter {
+            include,
+            exclude: glob_set(exclude)?,
+            git_ignore: false,
+        })
+    }
+
+    /// Also skip files ignored by `.gitignore` files, as git would.
+    pub fn git_ignore(mut self, yes: bool) -> FileFilter {
+        self.git_ignore = yes;
+        self
+    }
+
+    /// True if a file at `relative`, a path relative to the directory being
+    /// indexed, passes the include and exclude patterns. `.gitignore` files
+    /// are not consulted.
+    pub fn is_match(&self, relative: &Path) -> bool {
+        !self.exclude.is_match(relative)
+            && self.include.as_ref().is_none_or(|i| i.is_match(relative))
+    }
+
+    /// Given some paths, generate the complete list of files to index. We
+    /// check on disk whether the path is the name of a file or a directory;
+    /// files are taken as they are, and directories are walked. Relative
+    /// paths are fine.
+    ///
+    /// It's an error if any of the `paths` is not a valid path to an
+    /// existing file or directory.
+    pub fn expand<P: AsRef<Path>>(
+        &self,
+        paths: &[P],
+    ) -> io::Result<Vec<PathBuf>> {
+        let mut filenames = vec![];
+        for path in paths {
+            let path = path.as_ref();
+            if path.metadata()?.is_file() {
+                trace!("Got a file: {}", path.display());
+                filenames.push(path.to_path_buf());
+            } else {
+                self.walk(path, &mut filenames)?;
+            }
+        }
+        Ok(filenames)
+    }
+
+    /// Add the files under the directory `path` that pass the filter to
+    /// `filenames`, in order.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn walk(
+        &self,
+        path: &Path,
+        filenames: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+        let root = path.to_path_buf();
+        let exclude = self.exclude.clone();
+        let walk = WalkBuilder::new(path)
+            .standard_filters(false)
+            .hidden(true)
+            .git_ignore(self.git_ignore)
+            .git_exclude(self.git_ignore)
+            .parents(self.git_ignore)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            // Prune excluded directories instead of walking them.
+            .filter_entry(move |entry| {
+                !exclude.is_match(relative_to(&root, entry.path()))
+            })
+            .build();
+        for entry in walk {
+            let entry = entry.map_err(io::Error::other)?;
+            let is_file = entry.file_type().is_some_and(|t| t.is_file());
+            if is_file && self.is_match(relative_to(path, entry.path())) {
+                filenames.push(entry.into_path());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn walk(&self, path: &Path, _: &mut Vec<PathBuf>) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can't walk {} on wasm32", path.display()),
+        ))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn relative_to<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+fn glob_set<S: AsRef<str>>(patterns: &[S]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern.as_ref())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn walks_keep_the_files_that_pass_the_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for file in [
+            "a.md",
+            "b.txt",
+            "x.log",
+            "sub/c.md",
+            "target/d.md",
+            ".hidden/e.md",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "text").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+
+        // Include and exclude patterns, whether to honor `.gitignore`, and
+        // the files found.
+        type Case = (&'static [&'static str], &'static [&'static str], bool);
+        let cases: [(Case, &[&str]); 5] = [
+            (
+                (&[], &[], false),
+                &["a.md", "b.txt", "sub/c.md", "target/d.md", "x.log"],
+            ),
+            ((&[], &[], true), &["a.md", "b.txt", "sub/c.md"]),
+            (
+                (&["*.md"], &[], false),
+                &["a.md", "sub/c.md", "target/d.md"],
+            ),
+            ((&["*.md"], &["target"], false), &["a.md", "sub/c.md"]),
+            ((&[], &["sub", "*.txt"], true), &["a.md"]),
+        ];
+        for ((include, exclude, git_ignore), expected) in cases {
+            let filter = FileFilter::new(include, exclude)
+                .unwrap()
+                .git_ignore(git_ignore);
+            let found: Vec<PathBuf> = filter
+                .expand(&[root])
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+                .collect();
+            let expected: Vec<PathBuf> =
+                expected.iter().map(PathBuf::from).collect();
+            assert_eq!(found, expected, "{:?} {:?}", include, exclude);
+        }
+
+        // Files named explicitly are taken as they are.
+        let hidden = root.join(".hidden/e.md");
+        let filter = FileFilter::new(&["*.txt"], &[]).unwrap();
+        assert_eq!(filter.expand(&[&hidden]).unwrap(), [hidden]);
+        assert!(filter.expand(&[root.join("none")]).is_err());
+        assert!(FileFilter::new(&["a{"], &[]).is_err());
+    }
+}
@@ -1,107 +1,622 @@
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::{self, BufWriter, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use byteorder::{LittleEndian, WriteBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::debug;
 
 // ───── Current Crate Imports ────────────────────────────────────────────── //
 
-use crate::index::{Hit, InMemoryIndex};
+use crate::bloom::{term_hash, BloomFilter};
+use crate::codec::Codec;
+use crate::crypt::{Key, Sealer};
+use crate::description::{Counts, FileDescription, Section};
+use crate::dictionary::DictionaryWriter;
+use crate::error::Result;
+use crate::field::Field;
+use crate::header::{write_header, IndexSettings, OFFSETS_POSITION};
+use crate::index::{sort_by_doc, Doc, DocInfo, Hit, InMemoryIndex, MAX_DOCS};
+use crate::read::{
+    check_doc_order, skip_offsets, BlockMax, Entry, Skip, DOC_TRUNCATED,
+};
 use crate::tmp::TmpDir;
+use crate::varint;
 
 // ───── Body ─────────────────────────────────────────────────────────────── //
 
+/// Number of documents between two skip points in the hits of a term. Part
+/// of the file format: readers count documents by it when they skip.
+pub(crate) const SKIP_INTERVAL: u32 = 128;
+
 /// Writer for saving an index to a binary file.
 ///
-/// The first 8 bytes of the index file contain the offset of the table of
-/// contents, in bytes. Then come the main entries, all stored back-to-back
-/// with no particular metadata.
-pub struct IndexFileWriter {
+/// The index file starts with a header (see `header`), which among other
+/// things holds the offsets of the table of contents and of the document
+/// table, in bytes. Then come the main entries, all stored back-to-back with
+/// no particular metadata, followed by the document table, the table of
+/// contents, the bloom filter if there is one (see `bloom`) and a
+/// description of the whole file (see `description`).
+///
+/// In the main entries, every number of a hit (document id, offsets count
+/// and offsets) is stored as a variable-length integer, see `varint`. Ids
+/// and offsets only ever increase, so each is stored as the difference from
+/// the one before it, which keeps the numbers small. The entry of each term
+/// is then compressed as a whole with the codec. In files written with
+/// `IndexSettings::omit_positions`, hits stop after the offsets count.
+///
+/// Terms of fields other than the body (see `field`) are entries like any
+/// other, under their prefixed name, so their hits carry the field with
+/// them.
+///
+/// The table of contents is front-coded, see `dictionary`. The entry of a
+/// term lists where every `SKIP_INTERVAL`th document starts in its hits, so
+/// that queries can jump over the documents they don't need (see `Skip`),
+/// and what bounds the score of the documents between two such points (see
+/// `BlockMax`), which takes the lengths of the documents: they must all be
+/// added before the terms.
+///
+/// The entries of ranges of terms can also be written by several threads at
+/// once, each to a file of its own (see `range_writer`), and copied in
+/// afterwards.
+///
+/// In files written with `IndexSettings::encrypted`, everything after the
+/// header is encrypted as it is written (see `crypt`); offsets are those of
+/// the decrypted file.
+///
+/// Files are written to a `BufWriter<File>`, unless written to memory (see
+/// `write_index_to_vec`).
+pub struct IndexFileWriter<W = BufWriter<File>> {
     /// The number of bytes written so far.
     offset: u64,
-    /// The open file we're writing to.
-    writer: BufWriter<File>,
+    /// The open file we're writing to, after the header.
+    writer: Body<W>,
     /// The table of contents for this file.
-    contents_buf: Vec<u8>,
+    contents: DictionaryWriter,
+    /// The document table for this file.
+    docs_buf: Vec<u8>,
+    /// The documents added so far, by id, without their path and language:
+    /// only their lengths are needed.
+    doc_lens: Vec<DocInfo>,
+    /// The codec term entries are compressed with.
+    codec: Codec,
+    /// Hashes of the terms written, if the file gets a bloom filter.
+    term_hashes: Option<Vec<u64>>,
+    /// Whether hits have offsets.
+    positions: bool,
+    /// How the file is written, for its description.
+    settings: IndexSettings,
+    /// The header, as written but for its offsets.
+    header: Vec<u8>,
+    /// Length of the header.
+    header_len: u64,
+    /// What the file holds so far, for its description.
+    counts: Counts,
 }
 
-impl IndexFileWriter {
-    pub fn new(mut f: BufWriter<File>) -> io::Result<IndexFileWriter> {
-        const HEADER_SIZE: u64 = 8;
-        f.write_u64::<LittleEndian>(0)?;
+impl<W: Write + Seek> IndexFileWriter<W> {
+    pub fn new(
+        mut f: W,
+        settings: &IndexSettings,
+    ) -> io::Result<IndexFileWriter<W>> {
+        settings.codec.check_available()?;
+        let mut header = vec![];
+        let header_len = write_header(&mut header, settings)?;
+        f.write_all(&header)?;
+        let writer = match settings.encrypted {
+            true => {
+                let sealer = Sealer::new(f, &Key::from_env()?)?;
+                Body::Encrypted(Box::new(sealer))
+            }
+            false => Body::Plain(f),
+        };
         Ok(IndexFileWriter {
-            offset: HEADER_SIZE,
-            writer: f,
-            contents_buf: vec![],
+            offset: header_len,
+            writer,
+            contents: DictionaryWriter::default(),
+            docs_buf: vec![],
+            doc_lens: vec![],
+            codec: settings.codec,
+            term_hashes: settings.bloom_filter.then(Vec::new),
+            positions: !settings.omit_positions,
+            settings: settings.clone(),
+            header,
+            header_len,
+            counts: Counts::default(),
         })
     }
 
-    pub fn write_data(&mut self, buf: &[u8]) -> io::Result<()> {
+    fn write_data(&mut self, buf: &[u8]) -> io::Result<()> {
         self.writer.write_all(buf)?;
         self.offset += buf.len() as u64;
         Ok(())
     }
 
-    pub fn write_contents_entry(
+    /// Write the entry of one term: its encoded hits, as produced by
+    /// `encode_hits` with the settings of the file, and their number.
+    pub fn write_term(
         &mut self,
         term: String,
         doc_count: u32,
+        hits: &[u8],
+    ) -> io::Result<()> {
+        let start = self.offset;
+        let (data, entry) = self.encode_term(term, doc_count, hits, start)?;
+        self.write_data(&data)?;
+        self.add_entry(&entry);
+        Ok(())
+    }
+
+    /// Compress the hits of a term, to be written at `offset`, and make its
+    /// table of contents entry.
+    fn encode_term<'h>(
+        &self,
+        term: String,
+        doc_count: u32,
+        hits: &'h [u8],
         offset: u64,
-        nbytes: u64,
-    ) {
-        self.contents_buf.write_u64::<LittleEndian>(offset).unwrap();
-        self.contents_buf.write_u64::<LittleEndian>(nbytes).unwrap();
-        self.contents_buf
-            .write_u32::<LittleEndian>(doc_count)
+    ) -> io::Result<(Cow<'h, [u8]>, Entry)> {
+        let field = Field::of_term(&term);
+        let len = |id: u32| {
+            // Documents that weren't added count as empty, which only makes
+            // the bounds higher.
+            self.doc_lens
+                .get(id as usize)
+                .map_or(0, |info| info.len_of(field))
+        };
+        debug_assert!(
+            check_doc_order(hits, doc_count, self.positions).is_ok(),
+            "hits of {:?} are out of document order",
+            term
+        );
+        let (skips, blocks, occurrences) =
+            skip_points(hits, doc_count, self.positions, len)?;
+        let data = self.codec.compress(hits)?;
+        let entry = Entry {
+            term,
+            doc_count,
+            occurrences,
+            offset,
+            nbytes: data.len() as u64,
+            skips,
+            blocks,
+        };
+        Ok((data, entry))
+    }
+
+    fn add_entry(&mut self, e: &Entry) {
+        if let Some(hashes) = &mut self.term_hashes {
+            hashes.push(term_hash(&e.term));
+        }
+        self.counts.terms += 1;
+        self.counts.postings += e.doc_count as u64;
+        self.contents.add(e);
+    }
+
+    /// Add a document to the document table. Fails with `InvalidData` if
+    /// the table already has `MAX_DOCS` documents, or if `info` doesn't fit
+    /// in it: a language of more than 255 bytes, or a path, or a number of
+    /// duplicates, that doesn't fit in a u32.
+    pub fn write_doc_entry(
+        &mut self,
+        doc: &Doc,
+        info: &DocInfo,
+    ) -> io::Result<()> {
+        debug_assert_eq!(doc.hash.len(), self.settings.identity.hash_len());
+        if self.counts.documents >= MAX_DOCS as u64 {
+            return Err(too_large(format!("more than {} documents", MAX_DOCS)));
+        }
+        if info.language.len() > u8::MAX as usize {
+            return Err(too_large(format!("language {:?}", info.language)));
+        }
+        let too_long = |len: usize| u32::try_from(len).is_err();
+        if too_long(info.path.len())
+            || too_long(info.duplicates.len())
+            || info.duplicates.iter().any(|path| too_long(path.len()))
+        {
+            return Err(too_large(format!("paths of {}", doc.to_hex())));
+        }
+        self.counts.documents += 1;
+        self.counts.words += info.token_count as u64;
+        self.doc_lens.push(DocInfo {
+            path: String::new(),
+            language: String::new(),
+            duplicates: vec![],
+            ..*info
+        });
+        self.docs_buf.extend(&doc.hash);
+        self.docs_buf
+            .write_u32::<LittleEndian>(info.token_count)
             .unwrap();
-        let bytes = term.bytes();
-        self.contents_buf
+        for len in info.field_lens {
+            self.docs_buf.write_u32::<LittleEndian>(len).unwrap();
+        }
+        self.docs_buf.write_u64::<LittleEndian>(info.mtime).unwrap();
+        self.docs_buf.write_u64::<LittleEndian>(info.size).unwrap();
+        let bytes = info.path.bytes();
+        self.docs_buf
             .write_u32::<LittleEndian>(bytes.len() as u32)
             .unwrap();
-        self.contents_buf.extend(bytes);
+        self.docs_buf.extend(bytes);
+        let language = info.language.bytes();
+        self.docs_buf.write_u8(language.len() as u8).unwrap();
+        self.docs_buf.extend(language);
+        self.docs_buf
+            .write_u32::<LittleEndian>(info.duplicates.len() as u32)
+            .unwrap();
+        for path in &info.duplicates {
+            self.docs_buf
+                .write_u32::<LittleEndian>(path.len() as u32)
+                .unwrap();
+            self.docs_buf.extend(path.bytes());
+        }
+        let flags = if info.truncated { DOC_TRUNCATED } else { 0 };
+        self.docs_buf.write_u8(flags).unwrap();
+        Ok(())
     }
 
     /// Finish writing the index file and close it
-    pub fn finish(mut self) -> io::Result<()> {
-        let table_contents_start = self.offset;
-        self.writer.write_all(&self.contents_buf)?;
-        println!(
-            "{} bytes data, {}, bytes total",
-            table_contents_start,
-            table_contents_start + self.contents_buf.len() as u64
+    pub fn finish(self) -> io::Result<()> {
+        self.into_inner().map(drop)
+    }
+
+    /// Finish writing the index file, and return what it was written to.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        let doc_table_start = self.offset;
+        self.writer.write_all(&self.docs_buf)?;
+        let table_contents_start = doc_table_start + self.docs_buf.len() as u64;
+        let contents = self.contents.finish();
+        self.writer.write_all(&contents)?;
+        let footer_start = table_contents_start + contents.len() as u64;
+        let mut sections = vec![
+            section("header", 0, self.header_len),
+            section("postings", self.header_len, doc_table_start),
+            section("document_table", doc_table_start, table_contents_start),
+            section("table_of_contents", table_contents_start, footer_start),
+        ];
+        let mut end = footer_start;
+        if let Some(hashes) = &self.term_hashes {
+            let filter = BloomFilter::from_hashes(hashes).encode();
+            self.writer.write_all(&filter)?;
+            end += filter.len() as u64;
+            sections.push(section("bloom_filter", footer_start, end));
+        }
+        let description =
+            FileDescription::new(&self.settings, sections, self.counts)
+                .encode();
+        self.writer.write_all(&description)?;
+        debug!(
+            "{} bytes data, {} bytes total",
+            doc_table_start,
+            end + description.len() as u64
         );
-        self.writer.seek(SeekFrom::Start(0))?;
-        self.writer
-            .write_u64::<LittleEndian>(table_contents_start)?;
+        let offsets = &mut self.header[OFFSETS_POSITION as usize..];
+        offsets[..8].copy_from_slice(&table_contents_start.to_le_bytes());
+        offsets[8..16].copy_from_slice(&doc_table_start.to_le_bytes());
+        let mut f = match self.writer {
+            Body::Plain(f) => f,
+            Body::Encrypted(sealer) => sealer.finish(&self.header)?,
+        };
+        f.seek(SeekFrom::Start(OFFSETS_POSITION))?;
+        f.write_u64::<LittleEndian>(table_contents_start)?;
+        f.write_u64::<LittleEndian>(doc_table_start)?;
+        Ok(f)
+    }
+}
+
+impl IndexFileWriter {
+    /// A writer for the terms of a range, to `f`, that can run alongside
+    /// the writers of other ranges. The documents must all have been added.
+    pub(crate) fn range_writer(&self, f: BufWriter<File>) -> RangeWriter<'_> {
+        RangeWriter {
+            index: self,
+            writer: f,
+            offset: 0,
+            entries: vec![],
+        }
+    }
+
+    /// Copy the terms of `range`, written to `file`, after those written so
+    /// far. Ranges must be appended in the order of their terms.
+    pub(crate) fn append_range(
+        &mut self,
+        range: WrittenRange,
+        file: &Path,
+    ) -> io::Result<()> {
+        let start = self.offset;
+        let copied = io::copy(&mut File::open(file)?, &mut self.writer)?;
+        if copied != range.len {
+            return Err(io::Error::other("range file has the wrong length"));
+        }
+        self.offset += copied;
+        for mut e in range.entries {
+            e.offset += start;
+            self.add_entry(&e);
+        }
         Ok(())
     }
 }
 
+/// What an `IndexFileWriter` writes the file after its header to.
+enum Body<W> {
+    Plain(W),
+    Encrypted(Box<Sealer<W>>),
+}
+
+impl<W: Write> Write for Body<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Body::Plain(f) => f.write(buf),
+            Body::Encrypted(sealer) => sealer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Body::Plain(f) => f.flush(),
+            Body::Encrypted(sealer) => sealer.flush(),
+        }
+    }
+}
+
+/// Writes the terms of a range of an index file to a file of their own, see
+/// `IndexFileWriter::range_writer`. The ranges of an index can be written by
+/// several threads at once, and then put together in order with
+/// `IndexFileWriter::append_range`.
+pub(crate) struct RangeWriter<'a> {
+    index: &'a IndexFileWriter,
+    writer: BufWriter<File>,
+    /// The number of bytes written so far.
+    offset: u64,
+    entries: Vec<Entry>,
+}
+
+/// The terms written by a `RangeWriter`.
+pub(crate) struct WrittenRange {
+    /// Length of the range's file.
+    len: u64,
+    /// The table of contents entries of the terms, with offsets from the
+    /// start of the range's file.
+    entries: Vec<Entry>,
+}
+
+impl RangeWriter<'_> {
+    /// Write the entry of one term, like `IndexFileWriter::write_term`.
+    pub(crate) fn write_term(
+        &mut self,
+        term: String,
+        doc_count: u32,
+        hits: &[u8],
+    ) -> io::Result<()> {
+        let (data, entry) =
+            self.index.encode_term(term, doc_count, hits, self.offset)?;
+        self.writer.write_all(&data)?;
+        self.offset += data.len() as u64;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Flush the range's file.
+    pub(crate) fn finish(mut self) -> io::Result<WrittenRange> {
+        self.writer.flush()?;
+        Ok(WrittenRange {
+            len: self.offset,
+            entries: self.entries,
+        })
+    }
+}
+
+fn section(name: &str, start: u64, end: u64) -> Section {
+    Section {
+        name: name.to_string(),
+        offset: start,
+        length: end - start,
+    }
+}
+
 pub fn write_index_to_tmp_file(
     index: InMemoryIndex,
     tmp_dir: &mut TmpDir,
+    settings: &IndexSettings,
 ) -> io::Result<PathBuf> {
     let (filename, f) = tmp_dir.create()?;
-    let mut writer = IndexFileWriter::new(f)?;
+    write_index(index, IndexFileWriter::new(f, settings)?, settings)?;
+    debug!("Wrote segment {}", filename.display());
+    Ok(filename)
+}
+
+/// The bytes of an index file of `index`, written in memory, e.g. to be
+/// searched with `StaticIndex::from_vec` where there are no files.
+pub fn write_index_to_vec(
+    index: InMemoryIndex,
+    settings: &IndexSettings,
+) -> io::Result<Vec<u8>> {
+    let writer = IndexFileWriter::new(io::Cursor::new(vec![]), settings)?;
+    Ok(write_index(index, writer, settings)?.into_inner())
+}
 
+/// Write `index` with `writer`, which writes with `settings`, and return
+/// what it was written to.
+fn write_index<W: Write + Seek>(
+    index: InMemoryIndex,
+    mut writer: IndexFileWriter<W>,
+    settings: &IndexSettings,
+) -> io::Result<W> {
     // The merge algorighm requires the entries within each file to be
     // sorted by term. Sort before writing anything.
     let mut index_as_vec: Vec<(String, Vec<Hit>)> =
         index.map.into_iter().collect();
-    index_as_vec.sort_by(|&(ref a, _), &(ref b, _)| a.cmp(b));
+    index_as_vec.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (doc, info) in &index.docs {
+        writer.write_doc_entry(doc, info)?;
+    }
 
-    for (term, hits) in index_as_vec {
+    for (term, mut hits) in index_as_vec {
+        sort_by_doc(&mut hits);
         let doc_count = hits.len() as u32;
-        let start = writer.offset;
-        for buffer in hits {
-            writer.write_data(&buffer)?;
+        let hits = encode_hits(&hits, !settings.omit_positions)?;
+        writer.write_term(term, doc_count, &hits)?;
+    }
+
+    writer.into_inner()
+}
+
+/// The error of a document table that can't hold `what`.
+fn too_large(what: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("too large for the document table: {}", what),
+    )
+}
+
+/// Convert the `Hit`s of one term from their fixed-width in-memory form to
+/// the delta-encoded, variable-length form used in files, leaving the
+/// offsets out unless `positions` is set.
+pub(crate) fn encode_hits(
+    hits: &[Hit],
+    positions: bool,
+) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    let mut last_id = 0u32;
+    for hit in hits {
+        let mut reader = &hit[..];
+        let id = reader.read_u32::<LittleEndian>()?;
+        varint::write_u32(&mut buf, id.wrapping_sub(last_id));
+        last_id = id;
+        let offsets_count = reader.read_u32::<LittleEndian>()?;
+        varint::write_u32(&mut buf, offsets_count);
+        if !positions {
+            continue;
+        }
+        let mut last_offset = 0u32;
+        for _ in 0..offsets_count {
+            let offset = reader.read_u32::<LittleEndian>()?;
+            varint::write_u32(&mut buf, offset.wrapping_sub(last_offset));
+            last_offset = offset;
         }
-        let stop = writer.offset;
-        writer.write_contents_entry(term, doc_count, start, stop - start);
     }
+    Ok(buf)
+}
 
-    writer.finish()?;
-    println!("Wrote file {:?}", filename);
-    Ok(filename)
+/// The skip points of the encoded hits of a term: one before every
+/// `SKIP_INTERVAL`th document, none for terms in fewer documents. Also
+/// returns the maxima of the blocks the points split the hits into, given
+/// the length of the term's field in each document, by id, and the number
+/// of occurrences of the term in all the documents.
+fn skip_points(
+    hits: &[u8],
+    doc_count: u32,
+    positions: bool,
+    len: impl Fn(u32) -> u32,
+) -> Result<(Vec<Skip>, Vec<BlockMax>, u64)> {
+    let mut skips = vec![];
+    let mut blocks = vec![];
+    let mut occurrences = 0;
+    let mut block: Option<BlockMax> = None;
+    let mut reader = hits;
+    let mut id = 0u32;
+    // Points past 4 GiB can't be recorded; reading there just takes longer.
+    let mut recording = doc_count > SKIP_INTERVAL;
+    for i in 0..doc_count {
+        if recording && i > 0 && i % SKIP_INTERVAL == 0 {
+            match u32::try_from(hits.len() - reader.len()) {
+                Ok(offset) => {
+                    skips.push(Skip {
+                        last_id: id,
+                        offset,
+                    });
+                    blocks.extend(block.take());
+                }
+                Err(_) => recording = false,
+            }
+        }
+        id = id.wrapping_add(varint::read_u32(&mut reader)?);
+        let tf = skip_offsets(&mut reader, positions)?;
+        occurrences += tf as u64;
+        let len = len(id);
+        block = Some(match block {
+            Some(b) => BlockMax {
+                max_tf: b.max_tf.max(tf),
+                min_len: b.min_len.min(len),
+            },
+            None => BlockMax {
+                max_tf: tf,
+                min_len: len,
+            },
+        });
+    }
+    blocks.push(block.unwrap_or_default());
+    Ok((skips, blocks, occurrences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hit of the document `id`, with `tf` occurrences.
+    fn hit(id: u32, tf: u32) -> Hit {
+        let mut hit = vec![];
+        hit.write_u32::<LittleEndian>(id).unwrap();
+        hit.write_u32::<LittleEndian>(tf).unwrap();
+        for offset in 0..tf {
+            hit.write_u32::<LittleEndian>(offset * 2).unwrap();
+        }
+        hit
+    }
+
+    #[test]
+    fn document_tables_refuse_what_they_cant_hold() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = BufWriter::new(File::create(dir.path().join("f")).unwrap());
+        let mut writer =
+            IndexFileWriter::new(out, &IndexSettings::default()).unwrap();
+        let doc = Doc::new(&[1; 32]);
+        let mut index = InMemoryIndex::new();
+        index.add_document(&doc.hash, "the cat".to_string());
+        let mut info = index.docs[0].1.clone();
+        info.language = "x".repeat(256);
+        let e = writer.write_doc_entry(&doc, &info).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        info.language = "x".repeat(255);
+        writer.write_doc_entry(&doc, &info).unwrap();
+
+        writer.counts.documents = MAX_DOCS as u64;
+        assert!(writer.write_doc_entry(&doc, &info).is_err());
+    }
+
+    #[test]
+    fn blocks_record_their_maxima() {
+        let hits: Vec<_> = (0..300).map(|id| hit(id * 2, 1 + id % 7)).collect();
+        for positions in [true, false] {
+            let encoded = encode_hits(&hits, positions).unwrap();
+            let (skips, blocks, occurrences) =
+                skip_points(&encoded, 300, positions, |id| 1000 - id).unwrap();
+            assert_eq!(occurrences, (0..300).map(|id| 1 + id % 7).sum::<u64>());
+            let last_ids: Vec<_> = skips.iter().map(|s| s.last_id).collect();
+            assert_eq!(last_ids, [254, 510]);
+            let maxima: Vec<_> =
+                blocks.iter().map(|b| (b.max_tf, b.min_len)).collect();
+            assert_eq!(maxima, [(7, 746), (7, 490), (7, 402)]);
+        }
+    }
+
+    #[test]
+    fn short_lists_have_one_block() {
+        let hits = [hit(3, 2), hit(9, 5)];
+        let encoded = encode_hits(&hits, true).unwrap();
+        let (skips, blocks, occurrences) =
+            skip_points(&encoded, 2, true, |id| id).unwrap();
+        assert!(skips.is_empty());
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(occurrences, 7);
+        assert_eq!((blocks[0].max_tf, blocks[0].min_len), (5, 3));
+
+        let (skips, blocks, _) = skip_points(&[], 0, true, |id| id).unwrap();
+        assert!(skips.is_empty());
+        assert_eq!((blocks[0].max_tf, blocks[0].min_len), (0, 0));
+        // Hits that end early are an error.
+        assert!(skip_points(&encoded[..3], 2, true, |id| id).is_err());
+    }
 }